@@ -0,0 +1,329 @@
+//! Embedding API for zerobrew.
+//!
+//! [`Zerobrew`] wraps the installer, store, cellar and linker behind a small
+//! surface meant for programs that drive installs themselves, such as editor
+//! plugins or provisioning tools. The types in `zb_io` remain available for
+//! anything not covered here, but may change between releases; this crate
+//! is the interface we keep stable.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), zb_api::Error> {
+//! use zb_api::{InstallProgress, Zerobrew};
+//!
+//! let mut zb = Zerobrew::builder()
+//!     .root("/opt/zerobrew")
+//!     .prefix("/opt/zerobrew/prefix")
+//!     .build()?;
+//!
+//! let mut events = zb.subscribe();
+//! tokio::spawn(async move {
+//!     while let Ok(event) = events.recv().await {
+//!         if let InstallProgress::InstallCompleted { name } = event {
+//!             println!("installed {name}");
+//!         }
+//!     }
+//! });
+//!
+//! zb.install(&["jq"]).await?;
+//! for keg in zb.list()? {
+//!     println!("{} {}", keg.name, keg.version);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+use zb_io::{Installer, ProgressCallback, create_installer};
+
+pub use zb_core::{BottleStrictness, Error, Formula, InstallMethod};
+pub use zb_io::{
+    ExecuteResult, ExtractPolicy, InstallPlan, InstallProgress, InstallReceipt, InstalledKeg,
+    OutdatedPackage, PlanSizes, RetryOn, RetryPolicy,
+};
+
+const DEFAULT_CONCURRENCY: usize = 20;
+const PROGRESS_CHANNEL_CAPACITY: usize = 1024;
+
+/// Configures and opens a [`Zerobrew`] instance.
+#[derive(Debug, Clone, Default)]
+pub struct ZerobrewBuilder {
+    root: Option<PathBuf>,
+    prefix: Option<PathBuf>,
+    concurrency: Option<usize>,
+    max_cache_size: Option<u64>,
+    extract_policy: Option<ExtractPolicy>,
+    retry_policy: Option<RetryPolicy>,
+    bottle_strictness: Option<BottleStrictness>,
+    link: Option<bool>,
+}
+
+impl ZerobrewBuilder {
+    /// Directory holding the store, download cache and database. Required.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Directory kegs are linked into. Defaults to the root on macOS and
+    /// `<root>/prefix` elsewhere, matching `zb`.
+    pub fn prefix(mut self, prefix: impl Into<PathBuf>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Number of parallel downloads. Defaults to 20.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Trim the download cache to this many bytes after each install.
+    pub fn max_cache_size(mut self, bytes: u64) -> Self {
+        self.max_cache_size = Some(bytes);
+        self
+    }
+
+    pub fn extract_policy(mut self, policy: ExtractPolicy) -> Self {
+        self.extract_policy = Some(policy);
+        self
+    }
+
+    /// Backoff for transient network failures. Defaults to three attempts.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Whether bottles built for older OS releases may be used when the
+    /// running one has none. Defaults to [`BottleStrictness::Compatible`].
+    pub fn bottle_strictness(mut self, strictness: BottleStrictness) -> Self {
+        self.bottle_strictness = Some(strictness);
+        self
+    }
+
+    /// Whether installed kegs are linked into the prefix. Defaults to true.
+    pub fn link(mut self, link: bool) -> Self {
+        self.link = Some(link);
+        self
+    }
+
+    /// Create any missing directories and open the database.
+    pub fn build(self) -> Result<Zerobrew, Error> {
+        let root = self.root.ok_or_else(|| Error::InvalidArgument {
+            message: "zerobrew root directory is required".to_string(),
+        })?;
+        let prefix = self.prefix.unwrap_or_else(|| {
+            if cfg!(target_os = "macos") {
+                root.clone()
+            } else {
+                root.join("prefix")
+            }
+        });
+        let concurrency = self.concurrency.unwrap_or(DEFAULT_CONCURRENCY);
+        if concurrency == 0 {
+            return Err(Error::InvalidArgument {
+                message: "concurrency must be at least 1".to_string(),
+            });
+        }
+
+        let mut installer =
+            create_installer(&root, &prefix, concurrency)?.with_max_cache_size(self.max_cache_size);
+        if let Some(policy) = self.extract_policy {
+            installer = installer.with_extract_policy(policy);
+        }
+        if let Some(policy) = self.retry_policy {
+            installer = installer.with_retry_policy(policy);
+        }
+        if let Some(strictness) = self.bottle_strictness {
+            installer = installer.with_bottle_strictness(strictness);
+        }
+
+        let (progress, _) = broadcast::channel(PROGRESS_CHANNEL_CAPACITY);
+        Ok(Zerobrew {
+            installer,
+            progress,
+            root,
+            prefix,
+            link: self.link.unwrap_or(true),
+        })
+    }
+}
+
+/// A zerobrew installation rooted at one directory.
+///
+/// Methods that change the installation take the cross-process install lock,
+/// so several `Zerobrew` handles (or a concurrent `zb` process) on the same
+/// root are safe, though they serialize.
+pub struct Zerobrew {
+    installer: Installer,
+    progress: broadcast::Sender<InstallProgress>,
+    root: PathBuf,
+    prefix: PathBuf,
+    link: bool,
+}
+
+impl Zerobrew {
+    pub fn builder() -> ZerobrewBuilder {
+        ZerobrewBuilder::default()
+    }
+
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    pub fn prefix(&self) -> &PathBuf {
+        &self.prefix
+    }
+
+    /// Receive progress events for every subsequent install and upgrade.
+    ///
+    /// A receiver that falls more than 1024 events behind skips the oldest
+    /// ones and sees `RecvError::Lagged`.
+    pub fn subscribe(&self) -> broadcast::Receiver<InstallProgress> {
+        self.progress.subscribe()
+    }
+
+    /// Resolve `names` and their dependencies into an install plan without
+    /// installing anything.
+    pub async fn resolve<S: AsRef<str>>(&self, names: &[S]) -> Result<InstallPlan, Error> {
+        self.installer.plan(&owned(names)).await
+    }
+
+    /// Install `names` with their dependencies.
+    pub async fn install<S: AsRef<str>>(&mut self, names: &[S]) -> Result<ExecuteResult, Error> {
+        let plan = self.resolve(names).await?;
+        self.install_plan(plan).await
+    }
+
+    /// Install a plan previously returned by [`Zerobrew::resolve`].
+    pub async fn install_plan(&mut self, plan: InstallPlan) -> Result<ExecuteResult, Error> {
+        let progress = self.progress_callback();
+        self.installer
+            .execute_with_progress(plan, self.link, Some(progress))
+            .await
+    }
+
+    pub async fn upgrade(&mut self, name: &str) -> Result<(), Error> {
+        let progress = self.progress_callback();
+        self.installer
+            .upgrade(name, false, self.link, Some(progress))
+            .await
+    }
+
+    /// Switch `name` back to the version its last upgrade replaced and
+    /// return that version.
+    pub fn rollback(&mut self, name: &str) -> Result<String, Error> {
+        self.installer.rollback(name)
+    }
+
+    pub async fn outdated(&self) -> Result<Vec<OutdatedPackage>, Error> {
+        let mut outdated = Vec::new();
+        for keg in self.installer.list_installed()? {
+            if let Some(package) = self.installer.is_outdated(&keg.name).await? {
+                outdated.push(package);
+            }
+        }
+        Ok(outdated)
+    }
+
+    pub fn uninstall(&mut self, name: &str) -> Result<(), Error> {
+        self.installer.uninstall(name)
+    }
+
+    pub fn list(&self) -> Result<Vec<InstalledKeg>, Error> {
+        self.installer.list_installed()
+    }
+
+    pub fn get(&self, name: &str) -> Option<InstalledKeg> {
+        self.installer.get_installed(name)
+    }
+
+    pub fn receipt(&self, name: &str) -> Result<Option<InstallReceipt>, Error> {
+        self.installer.read_receipt(name)
+    }
+
+    /// The underlying installer, for operations this facade does not cover.
+    /// Its API is not covered by the stability guarantee of this crate.
+    pub fn installer_mut(&mut self) -> &mut Installer {
+        &mut self.installer
+    }
+
+    fn progress_callback(&self) -> Arc<ProgressCallback> {
+        let sender = self.progress.clone();
+        Arc::new(Box::new(move |event| {
+            // No subscribers is not an error.
+            let _ = sender.send(event);
+        }))
+    }
+}
+
+fn owned<S: AsRef<str>>(names: &[S]) -> Vec<String> {
+    names.iter().map(|n| n.as_ref().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn build_requires_root() {
+        let err = Zerobrew::builder().build().err().unwrap();
+        assert!(matches!(err, Error::InvalidArgument { .. }));
+    }
+
+    #[test]
+    fn build_rejects_zero_concurrency() {
+        let tmp = TempDir::new().unwrap();
+        let err = Zerobrew::builder()
+            .root(tmp.path())
+            .concurrency(0)
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidArgument { .. }));
+    }
+
+    #[test]
+    fn build_initializes_an_empty_root() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("prefix");
+
+        let mut zb = Zerobrew::builder()
+            .root(&root)
+            .prefix(&prefix)
+            .build()
+            .unwrap();
+
+        assert_eq!(zb.root(), &root);
+        assert_eq!(zb.prefix(), &prefix);
+        assert!(root.join("db/zb.sqlite3").exists());
+        assert!(zb.list().unwrap().is_empty());
+        assert!(zb.get("jq").is_none());
+        assert!(matches!(
+            zb.uninstall("jq").unwrap_err(),
+            Error::NotInstalled { .. }
+        ));
+    }
+
+    #[test]
+    fn subscribers_receive_progress_events() {
+        let tmp = TempDir::new().unwrap();
+        let zb = Zerobrew::builder().root(tmp.path()).build().unwrap();
+
+        let mut events = zb.subscribe();
+        (zb.progress_callback())(InstallProgress::InstallCompleted {
+            name: "jq".to_string(),
+        });
+
+        match events.try_recv().unwrap() {
+            InstallProgress::InstallCompleted { name } => assert_eq!(name, "jq"),
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Extraction of a large bottle with one writer against the writer pool.
+
+use std::time::Duration;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use tempfile::TempDir;
+use zb_bench::synthetic_bottle;
+use zb_io::extraction::{ExtractPolicy, extract_archive_with_policy};
+
+fn extract(c: &mut Criterion) {
+    let fixtures = TempDir::new().unwrap();
+    let archive = fixtures.path().join("toolchain.tar.gz");
+    std::fs::write(&archive, synthetic_bottle(20_000)).unwrap();
+
+    let mut group = c.benchmark_group("extract_large_bottle");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(30));
+
+    let mut counts = vec![1];
+    if ExtractPolicy::default_writers() > 1 {
+        counts.push(ExtractPolicy::default_writers());
+    }
+    for writers in counts {
+        let policy = ExtractPolicy {
+            writers,
+            ..ExtractPolicy::large_bottle()
+        };
+        group.bench_function(format!("{writers}_writers"), |b| {
+            b.iter_batched(
+                || TempDir::new_in(fixtures.path()).unwrap(),
+                |dest| {
+                    extract_archive_with_policy(&archive, dest.path(), &policy).unwrap();
+                    dest
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, extract);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+//! Fixtures for zerobrew's benchmarks. Run them with `cargo bench -p zb_bench`.
+
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+/// A gzipped bottle shaped like a large toolchain keg: `files` small files
+/// spread over a few hundred directories, a handful of multi-megabyte
+/// archives, and a symlink per directory.
+pub fn synthetic_bottle(files: usize) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    for i in 0..files {
+        // Sizes between 1 and 64 KiB, like headers and man pages.
+        let size = 1024 + (i * 7919) % (63 * 1024);
+        let content: Vec<u8> = (0..size).map(|j| ((i + j) % 251) as u8).collect();
+        let path = format!("toolchain/1.0/lib/{}/file-{i}.h", i % 300);
+        append(&mut builder, &path, 0o644, &content);
+    }
+    for i in 0..4 {
+        let content = vec![i as u8; 8 << 20];
+        let path = format!("toolchain/1.0/libexec/archive-{i}.a");
+        append(&mut builder, &path, 0o644, &content);
+    }
+    for dir in 0..files.min(300) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        builder
+            .append_link(
+                &mut header,
+                format!("toolchain/1.0/lib/{dir}/current.h"),
+                format!("file-{dir}.h"),
+            )
+            .expect("append symlink");
+    }
+
+    let tar = builder.into_inner().expect("finish tar");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&tar).expect("compress bottle");
+    encoder.finish().expect("compress bottle")
+}
+
+fn append(builder: &mut tar::Builder<Vec<u8>>, path: &str, mode: u32, content: &[u8]) {
+    let mut header = tar::Header::new_gnu();
+    header.set_path(path).expect("valid path");
+    header.set_size(content.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(1_700_000_000);
+    header.set_cksum();
+    builder.append(&header, content).expect("append file");
+}
@@ -1,77 +1,457 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use console::style;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use zb_cli::{
     cli::{Cli, Commands},
-    commands,
+    commands::{self, query::Query},
     init::ensure_init,
     logging,
-    ui::Ui,
+    ui::{StdUi, Ui},
     utils::{get_prefix_path, get_root_path},
 };
-use zb_io::create_installer;
+use zb_core::{
+    ApiEndpoints, BottleStrictness, ConcurrencyLimits, Context, DependencyClasses, LogLevel,
+    LoggerHandle, Paths,
+};
+use zb_io::{
+    BandwidthLimit, LinkStrategy, RetryPolicy, StorageLayout, Transcript,
+    create_installer_with_layout,
+};
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
-    logging::init(cli.verbose, cli.quiet);
+    let context = cli_context(&cli);
+    let log_file = logging::init(&context.logger);
+
+    let result = if installs_packages(&cli) {
+        // Dropping `run` on Ctrl-C unwinds its cleanup guards: half-unpacked
+        // bottles are deleted and an unfinished keg keeps its incomplete
+        // marker, which the next install clears.
+        let finished = tokio::select! {
+            result = run(cli) => Some(result),
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        finished.unwrap_or_else(|| {
+            eprintln!(
+                "{} interrupted; partially installed packages are cleaned up on the next install",
+                style("warning:").yellow().bold()
+            );
+            std::process::exit(130);
+        })
+    } else {
+        run(cli).await
+    };
 
-    if let Err(e) = run(cli).await {
+    if let Err(e) = result {
         eprintln!("{} {}", style("error:").red().bold(), e);
+        if let Some(path) = log_file {
+            eprintln!(
+                "{} details were logged to {}",
+                style("note:").dim(),
+                path.display()
+            );
+        }
         std::process::exit(1);
     }
 }
 
-async fn run(cli: Cli) -> Result<(), zb_core::Error> {
+/// Whether `cli` runs a command that materializes kegs, and so should stop
+/// cleanly rather than die outright on Ctrl-C.
+fn installs_packages(cli: &Cli) -> bool {
+    matches!(
+        cli.command,
+        Some(
+            Commands::Install { .. }
+                | Commands::Bundle { .. }
+                | Commands::Import { .. }
+                | Commands::Migrate { .. }
+                | Commands::Upgrade { .. }
+        )
+    )
+}
+
+fn cli_context(cli: &Cli) -> Context {
+    let paths = Paths::from_root(get_root_path(cli.root.clone()));
+    // Never create the root just to log into it; `zb init` owns that.
+    let log_file = paths.root.is_dir().then(|| paths.log_file());
+    Context {
+        paths,
+        concurrency: concurrency_limits(cli),
+        logger: LoggerHandle {
+            level: LogLevel::from_verbosity(cli.verbose, cli.quiet),
+            log_file,
+        },
+        endpoints: api_endpoints(cli),
+    }
+}
+
+/// Limits tuned for this machine, with the ones given on the command line.
+fn concurrency_limits(cli: &Cli) -> ConcurrencyLimits {
+    let detected = ConcurrencyLimits::detect();
+    ConcurrencyLimits {
+        download: cli.concurrency.unwrap_or(detected.download),
+        unpack: cli.unpack_concurrency.unwrap_or(detected.unpack),
+        relocate: cli.relocate_concurrency.unwrap_or(detected.relocate),
+        link: cli.link_concurrency.unwrap_or(detected.link),
+    }
+}
+
+fn api_endpoints(cli: &Cli) -> ApiEndpoints {
+    ApiEndpoints {
+        api_url: cli.api_url.clone(),
+        tap_base_url: cli.tap_base_url.clone(),
+    }
+}
+
+async fn run(mut cli: Cli) -> Result<(), zb_core::Error> {
     let mut ui = Ui::new();
 
-    if let Commands::Completion { shell } = cli.command {
+    let Some(command) = cli.command.take() else {
+        // clap shows help for a bare `zb` itself, unless a `ZEROBREW_*`
+        // variable filled in one of the flags.
+        if cli.prefix.is_none() && cli.cellar.is_none() && cli.env.is_none() {
+            return Cli::command()
+                .print_help()
+                .map_err(|e| zb_core::Error::FileError {
+                    message: format!("failed to write CLI output: {e}"),
+                });
+        }
+        return run_query(cli, &mut ui);
+    };
+    if cli.cellar.is_some() || cli.env.is_some() {
+        return Err(zb_core::Error::InvalidArgument {
+            message: "--cellar and --env print paths and cannot be combined with a command"
+                .to_string(),
+        });
+    }
+
+    if let Commands::Completion { shell } = command {
         return commands::completion::execute(shell);
     }
+    if let Commands::Create {
+        url,
+        name,
+        formula_version,
+        output,
+        force,
+    } = command
+    {
+        return commands::create::execute(&url, name, formula_version, output, force, &mut ui)
+            .await;
+    }
+    if let Commands::Which {
+        hook: Some(shell), ..
+    } = command
+    {
+        return commands::which::print_hook(shell, &mut ui);
+    }
 
-    let root = get_root_path(cli.root);
-    let prefix = get_prefix_path(cli.prefix, &root);
+    let layout_configured = cli.root.is_some()
+        || matches!(cli.prefix, Some(Some(_)))
+        || std::env::var_os("ZEROBREW_ROOT").is_some()
+        || std::env::var_os("ZEROBREW_PREFIX").is_some();
+    let root = get_root_path(cli.root.take());
+    let prefix = get_prefix_path(cli.prefix.take().flatten(), &root);
 
-    if let Commands::Init { no_modify_path } = cli.command {
-        return commands::init::execute(&root, &prefix, no_modify_path, &mut ui);
+    if let Commands::Init {
+        no_modify_path,
+        no_sudo,
+    } = command
+    {
+        return commands::init::execute(
+            &root,
+            &prefix,
+            layout_configured,
+            no_modify_path,
+            no_sudo,
+            &mut ui,
+        );
+    }
+    if let Commands::SelfManage { command } = command {
+        let layout = storage_layout(&cli, &root, &prefix);
+        return commands::self_manage::execute(&layout, command, &mut ui).await;
     }
 
-    if !matches!(cli.command, Commands::Reset { .. }) {
+    if !matches!(command, Commands::Reset { .. }) {
         ensure_init(&root, &prefix, cli.auto_init, &mut ui)?;
     }
 
-    let mut installer = create_installer(&root, &prefix, cli.concurrency)?;
+    let layout = storage_layout(&cli, &root, &prefix);
+    let limits = concurrency_limits(&cli);
+    let mut installer = create_installer_with_layout(&layout, limits.download)?
+        .with_concurrency_limits(&limits)
+        .with_api_endpoints(&api_endpoints(&cli))?
+        .with_max_cache_size(cli.max_cache_size)
+        .with_retry_policy(RetryPolicy::default().with_max_attempts(cli.retries))
+        .with_bandwidth_limit(BandwidthLimit::new(cli.limit_rate, cli.max_bandwidth))
+        .with_keep_generations(cli.keep_generations)
+        .with_store_verification(cli.verify_store)
+        .with_link_mode(cli.link_mode)
+        .with_hooks_dir(cli.hooks_dir.clone().unwrap_or_else(|| root.join("hooks")));
+    if cli.strict_bottles {
+        installer = installer.with_bottle_strictness(BottleStrictness::Exact);
+    }
+    if cli.record_stats {
+        installer = installer.with_stats(true);
+    }
+
+    if !matches!(
+        command,
+        Commands::Doctor { .. } | Commands::Relink { .. } | Commands::Reset { .. }
+    ) {
+        warn_link_drift(&installer, &mut ui);
+    }
 
-    match cli.command {
+    match command {
         Commands::Init { .. } => unreachable!(),
         Commands::Completion { .. } => unreachable!(),
+        Commands::Create { .. } => unreachable!(),
+        Commands::SelfManage { .. } => unreachable!(),
         Commands::Install {
             formulas,
+            bottle,
+            name,
+            bottle_version,
             no_link,
+            overwrite,
             build_from_source,
+            include_build,
+            include_optional,
+            head,
+            variant,
+            dry_run,
+            arch,
+            explain,
+            why,
+            paranoid,
+            timings,
+            force,
+            transcript,
+            lockfile,
+            from_lockfile,
         } => {
-            commands::install::execute(
-                &mut installer,
-                formulas,
+            if paranoid {
+                installer = installer.with_paranoid(true);
+            }
+            let phase_timings = Arc::new(Mutex::new(Vec::new()));
+            if timings {
+                let sink = phase_timings.clone();
+                installer = installer.with_phase_hook(Arc::new(move |timing| {
+                    sink.lock().unwrap().push(timing);
+                }));
+            }
+            if head {
+                installer = installer.with_head(true);
+            }
+            if let Some(variant) = variant {
+                installer = installer.with_bottle_variant(variant);
+            }
+            if include_build || include_optional {
+                installer = installer.with_dependency_classes(DependencyClasses {
+                    build: include_build,
+                    optional: include_optional,
+                    ..DependencyClasses::default()
+                });
+            }
+            if let Some(path) = &transcript {
+                installer = installer.with_transcript(Arc::new(Transcript::create(path)?));
+            }
+            if overwrite {
+                installer = installer.with_link_strategy(LinkStrategy::Overwrite);
+            }
+            if let Some(arch) = arch {
+                commands::install::check_arch_supported(arch)?;
+                installer = installer.with_arch(arch);
+            }
+            let local_bottle = bottle.map(|path| {
+                (
+                    path,
+                    name.unwrap_or_default(),
+                    bottle_version.unwrap_or_default(),
+                )
+            });
+            let (formulas, lock) = match (lockfile, from_lockfile) {
+                (_, Some(path)) => {
+                    let lockfile = zb_io::Lockfile::read(&path)?;
+                    let formulas = lockfile.formulas.clone();
+                    (formulas, Some(commands::install::Lock::Enforce(lockfile)))
+                }
+                (Some(path), None) => (formulas, Some(commands::install::Lock::Write(path))),
+                (None, None) => (formulas, None),
+            };
+            let formulas =
+                commands::install::load_local_formulas(&mut installer, formulas, local_bottle)?;
+            if explain || why.is_some() {
+                return commands::install::explain(
+                    &installer,
+                    formulas,
+                    build_from_source,
+                    why,
+                    &mut ui,
+                )
+                .await;
+            }
+            let options = commands::install::InstallOptions {
                 no_link,
                 build_from_source,
-                &mut ui,
-            )
-            .await
+                dry_run,
+                force,
+                lock,
+            };
+            let result =
+                commands::install::execute(&mut installer, formulas, options, &mut ui).await;
+            if timings {
+                commands::install::print_phase_timings(&phase_timings.lock().unwrap(), &mut ui)?;
+            }
+            result
+        }
+        Commands::Fetch {
+            formulas,
+            bottle_tag,
+        } => {
+            if let Some(tag) = &bottle_tag {
+                installer = installer.with_bottle_tag(tag);
+            }
+            commands::fetch::execute(&installer, formulas, bottle_tag.as_deref(), &mut ui).await
         }
         Commands::Bundle { command } => {
             commands::bundle::execute(&mut installer, command, &mut ui).await
         }
-        Commands::Uninstall { formulas, all } => {
-            commands::uninstall::execute(&mut installer, formulas, all, &mut ui)
+        Commands::Export { format, output } => {
+            commands::export::execute(&installer, &root, format, output.as_deref())
+        }
+        Commands::ExportImage {
+            formula,
+            output,
+            format,
+        } => commands::export_image::execute(&installer, &formula, &output, format, &mut ui),
+        Commands::Import {
+            source,
+            format,
+            no_link,
+            cleanup,
+            dry_run,
+        } => {
+            let options = commands::import::ImportOptions {
+                no_link,
+                cleanup,
+                dry_run,
+            };
+            commands::import::execute(&mut installer, &root, &source, format, options, &mut ui)
+                .await
+        }
+        Commands::Uninstall { formulas, all, tag } => {
+            commands::uninstall::execute(&mut installer, formulas, all, tag, &mut ui)
         }
         Commands::Migrate { yes, force } => {
-            commands::migrate::execute(&mut installer, yes, force, &mut ui).await
+            commands::migrate::execute(&mut installer, &root, yes, force, &mut ui).await
         }
         Commands::Doctor { repair } => commands::doctor::execute(&mut installer, repair, &mut ui),
-        Commands::List => commands::list::execute(&mut installer),
-        Commands::Info { formula } => commands::info::execute(&mut installer, formula),
-        Commands::Gc => commands::gc::execute(&mut installer),
-        Commands::Update => commands::update::execute(&mut installer),
+        Commands::Stats { json, clear } => {
+            commands::stats::execute(&installer, json, clear, &mut ui)
+        }
+        Commands::Replay { file } => commands::replay::execute(&installer, &file, &mut ui),
+        Commands::AuditRelocation { formula } => {
+            commands::audit_relocation::execute(&mut installer, formula, &mut ui)
+        }
+        Commands::List {
+            json,
+            files: Some(formula),
+            ..
+        } => commands::list::execute_files(&mut installer, formula, json),
+        Commands::List {
+            size,
+            deps_of,
+            json,
+            sort,
+            reverse,
+            files: None,
+            tag,
+        } => commands::list::execute(&mut installer, size, deps_of, json, sort, reverse, tag),
+        Commands::Du {
+            sort,
+            reverse,
+            json,
+            recalculate,
+        } => commands::du::execute(&installer, sort, reverse, json, recalculate),
+        Commands::Notes {
+            formula,
+            set,
+            clear,
+            tags,
+            untags,
+            json,
+        } => commands::notes::execute(
+            &mut installer,
+            formula,
+            commands::notes::NotesEdit {
+                set,
+                clear,
+                tags,
+                untags,
+            },
+            json,
+            &mut ui,
+        ),
+        Commands::Owns { path } => commands::owns::execute(&installer, &path),
+        Commands::Search {
+            query,
+            desc,
+            popular,
+            period,
+        } => commands::search::execute(&installer, query, desc, popular, period).await,
+        Commands::Licenses { json } => commands::licenses::execute(&installer, json).await,
+        Commands::Audit { json, offline } => {
+            commands::audit::execute(&installer, json, offline).await
+        }
+        Commands::Deps {
+            formulas,
+            dot,
+            mermaid,
+        } => {
+            let format = if dot {
+                commands::deps::GraphFormat::Dot
+            } else if mermaid {
+                commands::deps::GraphFormat::Mermaid
+            } else {
+                commands::deps::GraphFormat::Tree
+            };
+            commands::deps::execute(&installer, formulas, format, &mut ui).await
+        }
+        Commands::Info {
+            formula,
+            receipt,
+            bottles,
+        } => commands::info::execute(&mut installer, formula, receipt, bottles).await,
+        Commands::Gc {
+            dedupe,
+            keep_days,
+            max_age,
+            keep_versions,
+        } => {
+            installer =
+                installer.with_generation_retention(Duration::from_secs(keep_days * 24 * 60 * 60));
+            let options = zb_io::GcOptions {
+                max_age: (max_age > 0).then(|| Duration::from_secs(max_age * 24 * 60 * 60)),
+                keep_versions,
+            };
+            commands::gc::execute(&mut installer, &options, dedupe)
+        }
+        Commands::Cleanup { max_age, dry_run } => {
+            commands::cleanup::execute(&mut installer, max_age, cli.max_cache_size, dry_run)
+        }
+        Commands::Env { formulas } => commands::env::execute(&installer, formulas, &mut ui),
+        Commands::Exec { formulas, command } => {
+            commands::exec::execute(&installer, formulas, command)
+        }
+        Commands::Shell { formulas } => commands::exec::shell(&installer, formulas),
+        Commands::Which {
+            command, suggest, ..
+        } => commands::which::execute(&installer, command.unwrap_or_default(), suggest, &mut ui),
+        Commands::Update => commands::update::execute(&mut installer).await,
         Commands::Outdated { json } => {
             commands::outdated::execute(&mut installer, cli.quiet, cli.verbose > 0, json).await
         }
@@ -90,8 +470,102 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
             .await
         }
         Commands::Reset { yes } => commands::reset::execute(&root, &prefix, yes, &mut ui),
+        Commands::Link {
+            formula,
+            overwrite,
+            keep_both,
+            mode,
+        } => commands::link::execute(&mut installer, formula, overwrite, keep_both, mode, &mut ui),
+        Commands::Relink { formula, force } => {
+            commands::relink::execute(&mut installer, formula, force, &mut ui)
+        }
+        Commands::Profile { command } => {
+            commands::profile::execute(&mut installer, command, &mut ui)
+        }
+        Commands::History {
+            command,
+            json,
+            limit,
+        } => commands::history::execute(&mut installer, command, json, limit, &mut ui),
+        Commands::Rollback { formula } => {
+            commands::rollback::execute(&mut installer, formula, &mut ui)
+        }
+        Commands::Services { command } => {
+            commands::services::execute(&mut installer, &root, command, &mut ui).await
+        }
+        Commands::Compat { command } => commands::compat::execute(&installer, command, &mut ui),
+        Commands::Store { command } => commands::store::execute(&installer, command, &mut ui),
+        Commands::Shim { command } => commands::shim::execute(&root, &prefix, command, &mut ui),
+        Commands::Daemon { socket } => {
+            let socket = socket.unwrap_or_else(|| root.join("zb.sock"));
+            commands::daemon::execute(installer, &socket, &mut ui).await
+        }
         Commands::Run { formula, args } => {
             commands::run::execute(&mut installer, formula, args).await
         }
     }
 }
+
+/// Recorded links checked before each command; `zb doctor` checks them all.
+const LINK_DRIFT_SAMPLE: usize = 32;
+
+/// Warn when links zerobrew placed in the prefix were removed or changed by
+/// hand, so uninstalls and upgrades do not trip over them later.
+fn warn_link_drift(installer: &zb_io::Installer, ui: &mut StdUi) {
+    let Ok(drift) = installer.sample_link_drift(LINK_DRIFT_SAMPLE) else {
+        return;
+    };
+    let Some(first) = drift.first() else {
+        return;
+    };
+    let message = if drift.len() == 1 {
+        format!(
+            "{} was changed outside zerobrew; run `zb relink {}` to restore it",
+            first.link.display(),
+            first.name
+        )
+    } else {
+        format!(
+            "{} links in the prefix were changed outside zerobrew; run `zb doctor` for details",
+            drift.len()
+        )
+    };
+    let _ = ui.warn(message);
+}
+
+/// The default layout under `root` and `prefix`, with any locations moved
+/// by flags or `ZEROBREW_*_DIR` variables.
+fn storage_layout(cli: &Cli, root: &Path, prefix: &Path) -> StorageLayout {
+    let mut layout = StorageLayout::new(root, prefix);
+    if let Some(dir) = &cli.cache_dir {
+        layout = layout.with_cache_dir(dir.clone());
+    }
+    if let Some(dir) = &cli.store_dir {
+        layout = layout.with_store_dir(dir.clone());
+    }
+    if let Some(dir) = &cli.cellar_dir {
+        layout = layout.with_cellar_dir(dir.clone());
+    }
+    if let Some(path) = &cli.db_path {
+        layout = layout.with_db_path(path.clone());
+    }
+    layout
+}
+
+/// `zb --prefix [formula]`, `zb --cellar [formula]` and `zb --env formula`.
+fn run_query(mut cli: Cli, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    let (query, prefix) = Query::from_flags(cli.prefix.take(), cli.cellar.take(), cli.env.take())?;
+    let root = get_root_path(cli.root.take());
+    let prefix = get_prefix_path(prefix, &root);
+    if let Some(path) = query.answer_without_installer(&prefix) {
+        return commands::query::print_path(&path, ui);
+    }
+
+    ensure_init(&root, &prefix, cli.auto_init, ui)?;
+    let installer = create_installer_with_layout(
+        &storage_layout(&cli, &root, &prefix),
+        concurrency_limits(&cli).download,
+    )?
+    .with_api_endpoints(&api_endpoints(&cli))?;
+    commands::query::execute(&installer, query, ui)
+}
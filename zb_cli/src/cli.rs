@@ -1,27 +1,224 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use zb_core::RestartPolicy;
+use zb_io::{AnalyticsPeriod, LinkMode, StoreVerification};
 
 #[derive(Parser)]
 #[command(name = "zb")]
 #[command(about = "Zerobrew - A fast Homebrew-compatible package installer")]
 #[command(version)]
+#[command(arg_required_else_help = true)]
 pub struct Cli {
     #[arg(long, env = "ZEROBREW_ROOT", help = "Path to zerobrew data directory")]
     pub root: Option<PathBuf>,
 
     #[arg(
         long,
-        help = "Path to Homebrew-style prefix (overrides ZEROBREW_PREFIX)"
+        value_name = "PATH",
+        num_args = 0..=1,
+        help = "Path to Homebrew-style prefix for a command (overrides ZEROBREW_PREFIX); on its own, print the prefix or a formula's opt path"
     )]
-    pub prefix: Option<PathBuf>,
+    pub prefix: Option<Option<PathBuf>>,
 
     #[arg(
         long,
-        default_value = "20",
+        value_name = "FORMULA",
+        num_args = 0..=1,
+        help = "Print the cellar, or the directory holding a formula's kegs"
+    )]
+    pub cellar: Option<Option<String>>,
+
+    #[arg(
+        long,
+        value_name = "FORMULA",
+        conflicts_with = "cellar",
+        help = "Print CPPFLAGS, LDFLAGS and PKG_CONFIG_PATH exports for building against a formula"
+    )]
+    pub env: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_CACHE_DIR",
+        value_name = "DIR",
+        help = "Keep downloaded bottles and API responses here instead of under the root"
+    )]
+    pub cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_STORE_DIR",
+        value_name = "DIR",
+        help = "Keep unpacked bottles here instead of under the root"
+    )]
+    pub store_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_CELLAR_DIR",
+        value_name = "DIR",
+        help = "Keep installed kegs here, linked from the prefix's Cellar"
+    )]
+    pub cellar_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_DB_PATH",
+        value_name = "FILE",
+        help = "Use this database file instead of the one under the root"
+    )]
+    pub db_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        visible_alias = "download-concurrency",
+        value_name = "N",
+        value_parser = parse_concurrency,
+        help = "Number of concurrent downloads [default: up to 20, fewer on low memory]"
+    )]
+    pub concurrency: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "N",
         value_parser = parse_concurrency,
-        help = "Number of concurrent download threads"
+        help = "Bottles unpacked while downloading [default: CPU count, 2 to 8]"
+    )]
+    pub unpack_concurrency: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = parse_concurrency,
+        help = "Threads patching binaries while relocating a keg [default: CPU count]"
+    )]
+    pub relocate_concurrency: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = parse_concurrency,
+        help = "Threads linking a keg's directories [default: CPU count, up to 6]"
+    )]
+    pub link_concurrency: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_MAX_CACHE_SIZE",
+        value_name = "SIZE",
+        value_parser = parse_byte_size,
+        help = "Evict least-recently-used bottles once the download cache exceeds this size (e.g. 5G)"
+    )]
+    pub max_cache_size: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_LIMIT_RATE",
+        value_name = "RATE",
+        value_parser = parse_rate,
+        help = "Download each bottle at most this many bytes per second (e.g. 5M)"
+    )]
+    pub limit_rate: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_MAX_BANDWIDTH",
+        value_name = "RATE",
+        value_parser = parse_rate,
+        help = "Cap all downloads together at this many bytes per second (e.g. 20M)"
+    )]
+    pub max_bandwidth: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_API_URL",
+        value_name = "URL",
+        help = "Fetch formula metadata from this mirror of https://formulae.brew.sh/api/formula"
+    )]
+    pub api_url: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZB_TAP_BASE_URL",
+        value_name = "URL",
+        help = "Fetch formula sources from this mirror of https://raw.githubusercontent.com"
+    )]
+    pub tap_base_url: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_RETRIES",
+        default_value = "3",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Attempts per network request before giving up on transient failures"
+    )]
+    pub retries: u32,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_STRICT_BOTTLES",
+        help = "Only use bottles built for this exact OS release, never older ones"
+    )]
+    pub strict_bottles: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_VERIFY_STORE",
+        default_value = "off",
+        value_name = "MODE",
+        value_parser = parse_store_verification,
+        help = "Check unpacked bottles for on-disk changes before installing from them: off, sampled or full"
+    )]
+    pub verify_store: StoreVerification,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_LINK_MODE",
+        default_value = "symlink",
+        value_name = "MODE",
+        value_parser = parse_link_mode,
+        help = "How executables are linked into the prefix: symlink, hardlink or wrapper (a script that runs the keg's copy); `zb link --mode` sets it per formula"
+    )]
+    pub link_mode: LinkMode,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_KEEP_GENERATIONS",
+        default_value = "1",
+        value_name = "N",
+        help = "Previous versions of each package to keep after upgrading, for rollback"
+    )]
+    pub keep_generations: usize,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_RECORD_STATS",
+        help = "Record install timings locally for `zb stats` (nothing is sent anywhere)"
+    )]
+    pub record_stats: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ZEROBREW_HOOKS_DIR",
+        value_name = "DIR",
+        help = "Run the pre-plan, pre-install, post-install and post-execute executables here around installs [default: <root>/hooks]"
     )]
-    pub concurrency: usize,
+    pub hooks_dir: Option<PathBuf>,
 
     #[arg(
         long = "auto-init",
@@ -44,7 +241,7 @@ pub struct Cli {
     pub quiet: bool,
 
     #[command(subcommand)]
-    pub command: Commands,
+    pub command: Option<Commands>,
 }
 
 fn parse_concurrency(value: &str) -> Result<usize, String> {
@@ -57,15 +254,99 @@ fn parse_concurrency(value: &str) -> Result<usize, String> {
     Ok(parsed)
 }
 
+fn parse_arch(value: &str) -> Result<&'static str, String> {
+    zb_core::parse_arch(value)
+        .ok_or_else(|| format!("unknown architecture '{}': expected x86_64 or arm64", value))
+}
+
+fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (digits, suffix) = trimmed.split_at(split);
+    let number = digits
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size '{}': expected e.g. 500M or 5G", value))?;
+    let multiplier: u64 = match suffix.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        other => return Err(format!("invalid size unit '{}'", other)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", value))
+}
+
+/// A transfer rate in bytes per second, written like a size (`5M` is
+/// 5 MiB/s).
+fn parse_rate(value: &str) -> Result<u64, String> {
+    let rate = parse_byte_size(value.trim().trim_end_matches("/s"))?;
+    if rate == 0 {
+        return Err("rate must be at least 1 byte per second".to_string());
+    }
+    Ok(rate)
+}
+
+fn parse_restart_policy(value: &str) -> Result<RestartPolicy, String> {
+    RestartPolicy::parse(value).map_err(|e| e.to_string())
+}
+
+fn parse_store_verification(value: &str) -> Result<StoreVerification, String> {
+    StoreVerification::parse(value).map_err(|e| e.to_string())
+}
+
+fn parse_link_mode(value: &str) -> Result<LinkMode, String> {
+    value.parse().map_err(|e: zb_core::Error| e.to_string())
+}
+
+fn parse_analytics_period(value: &str) -> Result<AnalyticsPeriod, String> {
+    value.parse().map_err(|e: zb_core::Error| e.to_string())
+}
+
+fn parse_env_var(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, val)) if !key.is_empty() => Ok((key.to_string(), val.to_string())),
+        _ => Err(format!("expected KEY=VAL, got '{value}'")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Cli;
     use clap::Parser;
+    use std::path::PathBuf;
+    use zb_io::LinkMode;
 
     #[test]
     fn accepts_positive_concurrency() {
         let cli = Cli::try_parse_from(["zb", "--concurrency", "4", "list"]).unwrap();
-        assert_eq!(cli.concurrency, 4);
+        assert_eq!(cli.concurrency, Some(4));
+    }
+
+    #[test]
+    fn accepts_concurrency_per_operation() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "--download-concurrency",
+            "8",
+            "--unpack-concurrency",
+            "2",
+            "--relocate-concurrency",
+            "3",
+            "--link-concurrency",
+            "1",
+            "list",
+        ])
+        .unwrap();
+        assert_eq!(cli.concurrency, Some(8));
+        assert_eq!(cli.unpack_concurrency, Some(2));
+        assert_eq!(cli.relocate_concurrency, Some(3));
+        assert_eq!(cli.link_concurrency, Some(1));
+        assert!(Cli::try_parse_from(["zb", "--link-concurrency", "0", "list"]).is_err());
     }
 
     #[test]
@@ -76,6 +357,1143 @@ mod tests {
         assert!(err.contains("at least 1"));
     }
 
+    #[test]
+    fn install_arch_accepts_aliases() {
+        let cli = Cli::try_parse_from(["zb", "install", "--arch", "intel", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { arch, .. } => assert_eq!(arch, Some("x86_64")),
+            _ => panic!("expected install command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "install", "--arch", "ppc", "jq"]).is_err());
+    }
+
+    #[test]
+    fn install_explain_and_why_conflict() {
+        let cli = Cli::try_parse_from(["zb", "install", "ffmpeg", "--why", "x264"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { why, explain, .. } => {
+                assert_eq!(why.as_deref(), Some("x264"));
+                assert!(!explain);
+            }
+            _ => panic!("expected install command"),
+        }
+        assert!(
+            Cli::try_parse_from(["zb", "install", "ffmpeg", "--explain", "--why", "x264"]).is_err()
+        );
+    }
+
+    #[test]
+    fn install_local_bottle_needs_name_and_version() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "install",
+            "--bottle",
+            "./foo-1.0.arm64_sonoma.bottle.tar.gz",
+            "--name",
+            "foo",
+            "--version",
+            "1.0",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install {
+                formulas,
+                bottle,
+                name,
+                bottle_version,
+                ..
+            } => {
+                assert!(formulas.is_empty());
+                assert_eq!(
+                    bottle,
+                    Some(PathBuf::from("./foo-1.0.arm64_sonoma.bottle.tar.gz"))
+                );
+                assert_eq!(name.as_deref(), Some("foo"));
+                assert_eq!(bottle_version.as_deref(), Some("1.0"));
+            }
+            _ => panic!("expected install command"),
+        }
+
+        assert!(Cli::try_parse_from(["zb", "install", "--bottle", "foo.tar.gz"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "install", "--name", "foo", "jq"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "install"]).is_err());
+    }
+
+    #[test]
+    fn install_dependency_class_flags() {
+        let cli = Cli::try_parse_from(["zb", "install", "--include-build", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install {
+                include_build,
+                include_optional,
+                ..
+            } => {
+                assert!(include_build);
+                assert!(!include_optional);
+            }
+            _ => panic!("expected install command"),
+        }
+        assert!(
+            Cli::try_parse_from([
+                "zb",
+                "install",
+                "--bottle",
+                "jq.tar.gz",
+                "--name",
+                "jq",
+                "--version",
+                "1.7",
+                "--include-optional",
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn install_lockfile_flags() {
+        let cli = Cli::try_parse_from(["zb", "install", "--lockfile", "zb.lock", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { lockfile, .. } => {
+                assert_eq!(lockfile, Some(PathBuf::from("zb.lock")));
+            }
+            _ => panic!("expected install command"),
+        }
+        let cli = Cli::try_parse_from(["zb", "install", "--from-lockfile", "zb.lock"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install {
+                formulas,
+                from_lockfile,
+                ..
+            } => {
+                assert!(formulas.is_empty());
+                assert_eq!(from_lockfile, Some(PathBuf::from("zb.lock")));
+            }
+            _ => panic!("expected install command"),
+        }
+        assert!(
+            Cli::try_parse_from(["zb", "install", "--from-lockfile", "zb.lock", "jq"]).is_err()
+        );
+        assert!(
+            Cli::try_parse_from([
+                "zb",
+                "install",
+                "--from-lockfile",
+                "a.lock",
+                "--lockfile",
+                "b.lock"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn install_dry_run_flag() {
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--dry-run"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { dry_run, .. } => assert!(dry_run),
+            _ => panic!("expected install command"),
+        }
+    }
+
+    #[test]
+    fn install_variant_flag() {
+        let cli = Cli::try_parse_from(["zb", "install", "--variant", "no-gui", "acme/tap/viewer"])
+            .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { variant, .. } => {
+                assert_eq!(variant.as_deref(), Some("no-gui"))
+            }
+            _ => panic!("expected install command"),
+        }
+        assert!(
+            Cli::try_parse_from(["zb", "install", "--variant", "no-gui", "-s", "viewer"]).is_err()
+        );
+    }
+
+    #[test]
+    fn install_head_flag() {
+        let cli = Cli::try_parse_from(["zb", "install", "--HEAD", "org/tap/tool"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { head, .. } => assert!(head),
+            _ => panic!("expected install command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "install", "--head", "tool"]).is_err());
+        assert!(
+            Cli::try_parse_from(["zb", "install", "--HEAD", "--arch", "x86_64", "tool"]).is_err()
+        );
+    }
+
+    #[test]
+    fn install_force_flag() {
+        let cli = Cli::try_parse_from(["zb", "install", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { force, .. } => assert!(!force),
+            _ => panic!("expected install command"),
+        }
+        let cli = Cli::try_parse_from(["zb", "install", "--force", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { force, .. } => assert!(force),
+            _ => panic!("expected install command"),
+        }
+    }
+
+    #[test]
+    fn install_transcript_and_replay() {
+        let cli =
+            Cli::try_parse_from(["zb", "install", "--transcript", "out.jsonl", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { transcript, .. } => {
+                assert_eq!(transcript, Some(PathBuf::from("out.jsonl")))
+            }
+            _ => panic!("expected install command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "replay", "out.jsonl"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Replay { file } => assert_eq!(file, PathBuf::from("out.jsonl")),
+            _ => panic!("expected replay command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "replay"]).is_err());
+    }
+
+    #[test]
+    fn install_timings_flag() {
+        let cli = Cli::try_parse_from(["zb", "install", "--timings", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { timings, .. } => assert!(timings),
+            _ => panic!("expected install command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "install", "--timings", "--dry-run", "jq"]).is_err());
+    }
+
+    #[test]
+    fn install_paranoid_flag() {
+        let cli = Cli::try_parse_from(["zb", "install", "--paranoid", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Install { paranoid, .. } => assert!(paranoid),
+            _ => panic!("expected install command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "audit-relocation", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::AuditRelocation { formula } => assert_eq!(formula, "jq"),
+            _ => panic!("expected audit-relocation command"),
+        }
+    }
+
+    #[test]
+    fn shim_requires_a_subcommand() {
+        let cli = Cli::try_parse_from(["zb", "shim", "install", "--force"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Shim {
+                command: super::ShimCommands::Install { force },
+            } => assert!(force),
+            _ => panic!("expected shim install command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "shim"]).is_err());
+    }
+
+    #[test]
+    fn init_accepts_no_sudo() {
+        let cli = Cli::try_parse_from(["zb", "init", "--no-sudo"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Init {
+                no_modify_path: false,
+                no_sudo: true,
+            })
+        ));
+    }
+
+    #[test]
+    fn self_takes_update_or_uninstall() {
+        let cli = Cli::try_parse_from(["zb", "self", "uninstall", "-y"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::SelfManage {
+                command: super::SelfCommands::Uninstall { yes },
+            } => assert!(yes),
+            _ => panic!("expected self uninstall command"),
+        }
+        let cli = Cli::try_parse_from(["zb", "self", "update", "--check"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::SelfManage {
+                command: super::SelfCommands::Update {
+                    check: true,
+                    skip_attestation: false
+                },
+            })
+        ));
+        assert!(Cli::try_parse_from(["zb", "self"]).is_err());
+    }
+
+    #[test]
+    fn prefix_is_a_query_without_a_command() {
+        let cli = Cli::try_parse_from(["zb", "--prefix"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.prefix, Some(None));
+
+        let cli = Cli::try_parse_from(["zb", "--prefix", "openssl@3"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.prefix, Some(Some(PathBuf::from("openssl@3"))));
+
+        let cli = Cli::try_parse_from(["zb", "--prefix", "/custom", "list"]).unwrap();
+        assert!(matches!(cli.command, Some(super::Commands::List { .. })));
+        assert_eq!(cli.prefix, Some(Some(PathBuf::from("/custom"))));
+
+        let cli = Cli::try_parse_from(["zb", "--cellar"]).unwrap();
+        assert_eq!(cli.cellar, Some(None));
+        assert!(Cli::try_parse_from(["zb", "--cellar", "--env", "readline"]).is_err());
+    }
+
+    #[test]
+    fn bare_zb_prints_help() {
+        let err = match Cli::try_parse_from(["zb"]) {
+            Ok(_) => panic!("expected help"),
+            Err(err) => err,
+        };
+        assert_eq!(
+            err.kind(),
+            clap::error::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+        );
+    }
+
+    #[test]
+    fn prefix_path_without_a_command_is_refused() {
+        use crate::commands::query::Query;
+
+        let cli = Cli::try_parse_from(["zb", "--prefix", "/some/path"]).unwrap();
+        assert!(cli.command.is_none());
+        let err = Query::from_flags(cli.prefix, cli.cellar, cli.env).unwrap_err();
+        assert!(err.to_string().contains("needs a command"), "{err}");
+
+        let cli = Cli::try_parse_from(["zb", "--prefix", "acme/tap/tool"]).unwrap();
+        assert_eq!(
+            Query::from_flags(cli.prefix, cli.cellar, cli.env).unwrap(),
+            (Query::Prefix(Some("acme/tap/tool".to_string())), None)
+        );
+    }
+
+    #[test]
+    fn storage_locations_are_global_flags() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "install",
+            "jq",
+            "--cache-dir",
+            "/scratch/zb-cache",
+            "--store-dir",
+            "/persist/store",
+        ])
+        .unwrap();
+        assert_eq!(cli.cache_dir, Some(PathBuf::from("/scratch/zb-cache")));
+        assert_eq!(cli.store_dir, Some(PathBuf::from("/persist/store")));
+        assert_eq!(cli.cellar_dir, None);
+        assert_eq!(cli.db_path, None);
+    }
+
+    #[test]
+    fn env_accepts_repeated_formulas() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "env",
+            "--formula",
+            "openssl@3",
+            "--formula",
+            "readline",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Env { formulas } => assert_eq!(formulas, ["openssl@3", "readline"]),
+            _ => panic!("expected env command"),
+        }
+    }
+
+    #[test]
+    fn history_lists_or_undoes() {
+        let cli = Cli::try_parse_from(["zb", "history", "--json", "-n", "5"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::History {
+                command,
+                json,
+                limit,
+            } => {
+                assert!(command.is_none());
+                assert!(json);
+                assert_eq!(limit, 5);
+            }
+            _ => panic!("expected history command"),
+        }
+        let cli = Cli::try_parse_from(["zb", "history", "undo", "12"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::History {
+                command: Some(super::HistoryCommands::Undo { id }),
+                ..
+            } => assert_eq!(id, 12),
+            _ => panic!("expected history undo"),
+        }
+    }
+
+    #[test]
+    fn exec_takes_the_command_after_double_dash() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "exec",
+            "--with",
+            "openssl@3",
+            "--",
+            "make",
+            "-j4",
+            "--with",
+            "x",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Exec { formulas, command } => {
+                assert_eq!(formulas, ["openssl@3"]);
+                assert_eq!(command, ["make", "-j4", "--with", "x"]);
+            }
+            _ => panic!("expected exec command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "exec", "--with", "openssl@3"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "shell", "--with", "openssl@3"]).is_ok());
+    }
+
+    #[test]
+    fn which_takes_a_command_or_a_hook() {
+        let cli = Cli::try_parse_from(["zb", "which", "--suggest", "jq"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Which {
+                command,
+                suggest,
+                hook,
+            } => {
+                assert_eq!(command.as_deref(), Some("jq"));
+                assert!(suggest);
+                assert!(hook.is_none());
+            }
+            _ => panic!("expected which command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "which", "--hook", "zsh"]).unwrap();
+        assert!(matches!(
+            cli.command.unwrap(),
+            super::Commands::Which {
+                command: None,
+                hook: Some(clap_complete::shells::Shell::Zsh),
+                ..
+            }
+        ));
+
+        assert!(Cli::try_parse_from(["zb", "which"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "which", "--hook", "zsh", "jq"]).is_err());
+    }
+
+    #[test]
+    fn link_strategies_conflict() {
+        let cli = Cli::try_parse_from(["zb", "link", "python@3.11", "--keep-both"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Link {
+                formula,
+                overwrite,
+                keep_both,
+                mode,
+            } => {
+                assert_eq!(formula, "python@3.11");
+                assert!(!overwrite);
+                assert!(keep_both);
+                assert_eq!(mode, None);
+            }
+            _ => panic!("expected link command"),
+        }
+        assert_eq!(cli.link_mode, LinkMode::Symlink);
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "--link-mode",
+            "hardlink",
+            "link",
+            "jq",
+            "--mode",
+            "wrapper",
+        ])
+        .unwrap();
+        assert_eq!(cli.link_mode, LinkMode::Hardlink);
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Link {
+                mode: Some(LinkMode::Wrapper),
+                ..
+            })
+        ));
+        assert!(Cli::try_parse_from(["zb", "link", "jq", "--mode", "copy"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "link", "jq", "--overwrite", "--keep-both"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "install", "jq", "--overwrite", "--no-link"]).is_err());
+    }
+
+    #[test]
+    fn relink_takes_a_formula() {
+        let cli = Cli::try_parse_from(["zb", "relink", "jq", "--force"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Relink { ref formula, force: true }) if formula == "jq"
+        ));
+        assert!(Cli::try_parse_from(["zb", "relink"]).is_err());
+    }
+
+    #[test]
+    fn import_reads_stdin_with_a_format() {
+        let cli = Cli::try_parse_from(["zb", "import", "--format", "list", "-"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Import {
+                source,
+                format,
+                no_link,
+                cleanup,
+                dry_run,
+            } => {
+                assert_eq!(source, PathBuf::from("-"));
+                assert_eq!(format, super::ImportFormat::List);
+                assert!(!no_link);
+                assert!(!cleanup);
+                assert!(!dry_run);
+            }
+            _ => panic!("expected import command"),
+        }
+        let cli =
+            Cli::try_parse_from(["zb", "import", "Brewfile", "--cleanup", "--dry-run"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Import {
+                cleanup: true,
+                dry_run: true,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn export_defaults_to_a_brewfile_on_stdout() {
+        let cli = Cli::try_parse_from(["zb", "export"]).unwrap();
+        assert!(matches!(
+            cli.command.unwrap(),
+            super::Commands::Export {
+                format: super::ExportFormat::Brewfile,
+                output: None,
+            }
+        ));
+        let cli =
+            Cli::try_parse_from(["zb", "export", "--format", "toml", "-o", "zb.toml"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Export { format, output } => {
+                assert_eq!(format, super::ExportFormat::Toml);
+                assert_eq!(output, Some(PathBuf::from("zb.toml")));
+            }
+            _ => panic!("expected export command"),
+        }
+    }
+
+    #[test]
+    fn export_image_requires_an_output() {
+        assert!(Cli::try_parse_from(["zb", "export-image", "jq"]).is_err());
+        let cli = Cli::try_parse_from([
+            "zb",
+            "export-image",
+            "jq",
+            "-o",
+            "jq.tar.gz",
+            "--format",
+            "layer",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::ExportImage {
+                formula,
+                output,
+                format,
+            } => {
+                assert_eq!(formula, "jq");
+                assert_eq!(output, PathBuf::from("jq.tar.gz"));
+                assert_eq!(format, super::ImageFormat::Layer);
+            }
+            _ => panic!("expected export-image command"),
+        }
+    }
+
+    #[test]
+    fn store_info_takes_a_key() {
+        let cli = Cli::try_parse_from(["zb", "store", "info", "abc123", "--json"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Store {
+                command: Some(super::StoreCommands::Info { key, json }),
+            } => {
+                assert_eq!(key, "abc123");
+                assert!(json);
+            }
+            _ => panic!("expected store info command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "store", "info"]).is_err());
+    }
+
+    #[test]
+    fn compat_enable_takes_an_optional_path() {
+        let cli = Cli::try_parse_from(["zb", "compat", "enable", "/opt/homebrew"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Compat {
+                command: Some(super::CompatCommands::Enable { path }),
+            } => assert_eq!(path, Some(PathBuf::from("/opt/homebrew"))),
+            _ => panic!("expected compat enable command"),
+        }
+        let cli = Cli::try_parse_from(["zb", "compat", "disable"]).unwrap();
+        assert!(matches!(
+            cli.command.unwrap(),
+            super::Commands::Compat {
+                command: Some(super::CompatCommands::Disable { path: None })
+            }
+        ));
+    }
+
+    #[test]
+    fn profile_create_takes_formulas() {
+        let cli = Cli::try_parse_from(["zb", "profile", "create", "work", "jq", "git"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Profile {
+                command: Some(super::ProfileCommands::Create { name, formulas }),
+            } => {
+                assert_eq!(name, "work");
+                assert_eq!(formulas, ["jq", "git"]);
+            }
+            _ => panic!("expected profile create command"),
+        }
+        let cli = Cli::try_parse_from(["zb", "profile"]).unwrap();
+        assert!(matches!(
+            cli.command.unwrap(),
+            super::Commands::Profile { command: None }
+        ));
+    }
+
+    #[test]
+    fn services_schedule_takes_one_trigger() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "services",
+            "schedule",
+            "backup",
+            "--cron",
+            "0 3 * * *",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Services {
+                command:
+                    Some(super::ServicesCommands::Schedule {
+                        formula,
+                        cron,
+                        interval,
+                        clear,
+                    }),
+            } => {
+                assert_eq!(formula, "backup");
+                assert_eq!(cron.as_deref(), Some("0 3 * * *"));
+                assert_eq!(interval, None);
+                assert!(!clear);
+            }
+            _ => panic!("expected services schedule command"),
+        }
+        assert!(
+            Cli::try_parse_from([
+                "zb",
+                "services",
+                "schedule",
+                "backup",
+                "--cron",
+                "0 3 * * *",
+                "--interval",
+                "60"
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["zb", "services", "schedule", "backup", "--interval", "0"])
+                .is_err()
+        );
+        assert!(Cli::try_parse_from(["zb", "services", "schedule", "backup"]).is_err());
+    }
+
+    #[test]
+    fn services_edit_collects_overrides() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "services",
+            "edit",
+            "redis",
+            "--env",
+            "PORT=6380",
+            "--arg",
+            "--port=6380",
+            "--env",
+            "OPTS=a=b",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Services {
+                command: Some(super::ServicesCommands::Edit { env, arg, .. }),
+            } => {
+                assert_eq!(
+                    env,
+                    [
+                        ("PORT".to_string(), "6380".to_string()),
+                        ("OPTS".to_string(), "a=b".to_string())
+                    ]
+                );
+                assert_eq!(arg, ["--port=6380"]);
+            }
+            _ => panic!("expected services edit command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "services", "edit", "redis", "--env", "PORT"]).is_err());
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "services",
+            "edit",
+            "redis",
+            "--restart",
+            "on-failure",
+            "--health-port",
+            "6379",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Services {
+                command:
+                    Some(super::ServicesCommands::Edit {
+                        restart,
+                        health_port,
+                        ..
+                    }),
+            } => {
+                assert_eq!(restart, Some(zb_core::RestartPolicy::OnFailure));
+                assert_eq!(health_port, Some(6379));
+            }
+            _ => panic!("expected services edit command"),
+        }
+        assert!(
+            Cli::try_parse_from(["zb", "services", "edit", "redis", "--restart", "sometimes"])
+                .is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["zb", "services", "edit", "redis", "--reset", "--arg", "x"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_max_cache_size_units() {
+        let cli = Cli::try_parse_from(["zb", "--max-cache-size", "5G", "list"]).unwrap();
+        assert_eq!(cli.max_cache_size, Some(5 << 30));
+
+        let cli = Cli::try_parse_from(["zb", "cleanup", "--max-cache-size", "512mb"]).unwrap();
+        assert_eq!(cli.max_cache_size, Some(512 << 20));
+    }
+
+    #[test]
+    fn parses_bandwidth_limits() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "--limit-rate",
+            "5M",
+            "install",
+            "jq",
+            "--max-bandwidth",
+            "20M/s",
+        ])
+        .unwrap();
+        assert_eq!(cli.limit_rate, Some(5 << 20));
+        assert_eq!(cli.max_bandwidth, Some(20 << 20));
+        assert!(Cli::try_parse_from(["zb", "--limit-rate", "0", "list"]).is_err());
+    }
+
+    #[test]
+    fn create_takes_name_version_and_output_overrides() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "create",
+            "https://example.com/tool.tar.gz",
+            "--name",
+            "tool",
+            "--version",
+            "1.0",
+            "-o",
+            "-",
+        ])
+        .unwrap();
+        let Some(super::Commands::Create {
+            url,
+            name,
+            formula_version,
+            output,
+            force,
+        }) = cli.command
+        else {
+            panic!("expected create");
+        };
+        assert_eq!(url, "https://example.com/tool.tar.gz");
+        assert_eq!(name.as_deref(), Some("tool"));
+        assert_eq!(formula_version.as_deref(), Some("1.0"));
+        assert_eq!(output, Some(PathBuf::from("-")));
+        assert!(!force);
+    }
+
+    #[test]
+    fn rejects_unknown_cache_size_unit() {
+        let result = Cli::try_parse_from(["zb", "--max-cache-size", "5X", "list"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn retries_defaults_to_three_and_rejects_zero() {
+        let cli = Cli::try_parse_from(["zb", "list"]).unwrap();
+        assert_eq!(cli.retries, 3);
+
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--retries", "5"]).unwrap();
+        assert_eq!(cli.retries, 5);
+
+        assert!(Cli::try_parse_from(["zb", "--retries", "0", "list"]).is_err());
+    }
+
+    #[test]
+    fn api_mirrors_apply_to_every_command() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "outdated",
+            "--api-url",
+            "https://mirror.internal/api/formula",
+            "--tap-base-url",
+            "https://raw.mirror.internal",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.api_url.as_deref(),
+            Some("https://mirror.internal/api/formula")
+        );
+        assert_eq!(
+            cli.tap_base_url.as_deref(),
+            Some("https://raw.mirror.internal")
+        );
+    }
+
+    #[test]
+    fn strict_bottles_is_off_by_default() {
+        let cli = Cli::try_parse_from(["zb", "list"]).unwrap();
+        assert!(!cli.strict_bottles);
+
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--strict-bottles"]).unwrap();
+        assert!(cli.strict_bottles);
+    }
+
+    #[test]
+    fn keeps_one_generation_by_default() {
+        let cli = Cli::try_parse_from(["zb", "rollback", "jq"]).unwrap();
+        assert_eq!(cli.keep_generations, 1);
+
+        let cli = Cli::try_parse_from(["zb", "upgrade", "--keep-generations", "0"]).unwrap();
+        assert_eq!(cli.keep_generations, 0);
+
+        let cli = Cli::try_parse_from(["zb", "gc", "--keep-days", "7"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Gc { keep_days, .. } => assert_eq!(keep_days, 7),
+            _ => panic!("expected gc command"),
+        }
+    }
+
+    #[test]
+    fn gc_keeps_unused_store_entries_for_a_week() {
+        let cli = Cli::try_parse_from(["zb", "gc"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Gc {
+                max_age,
+                keep_versions,
+                ..
+            } => {
+                assert_eq!(max_age, 7);
+                assert_eq!(keep_versions, 0);
+            }
+            _ => panic!("expected gc command"),
+        }
+
+        let cli =
+            Cli::try_parse_from(["zb", "gc", "--max-age", "0", "--keep-versions", "2"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Gc {
+                max_age,
+                keep_versions,
+                ..
+            } => {
+                assert_eq!(max_age, 0);
+                assert_eq!(keep_versions, 2);
+            }
+            _ => panic!("expected gc command"),
+        }
+    }
+
+    #[test]
+    fn cleanup_defaults_to_thirty_day_max_age() {
+        let cli = Cli::try_parse_from(["zb", "cleanup", "--dry-run"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Cleanup { max_age, dry_run } => {
+                assert_eq!(max_age, 30);
+                assert!(dry_run);
+            }
+            _ => panic!("expected cleanup command"),
+        }
+    }
+
+    #[test]
+    fn list_accepts_sort_and_filters() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "list",
+            "--size",
+            "--sort",
+            "size",
+            "-r",
+            "--deps-of",
+            "wget",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::List {
+                size,
+                deps_of,
+                json,
+                sort,
+                reverse,
+                files,
+                tag,
+            } => {
+                assert!(size);
+                assert!(files.is_none());
+                assert!(tag.is_none());
+                assert_eq!(deps_of.as_deref(), Some("wget"));
+                assert!(!json);
+                assert_eq!(sort, super::ListSort::Size);
+                assert!(reverse);
+            }
+            _ => panic!("expected list command"),
+        }
+    }
+
+    #[test]
+    fn du_sorts_and_recalculates() {
+        let cli = Cli::try_parse_from(["zb", "du", "--sort", "size", "--recalculate"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Du {
+                sort,
+                reverse,
+                json,
+                recalculate,
+            } => {
+                assert_eq!(sort, super::ListSort::Size);
+                assert!(!reverse);
+                assert!(!json);
+                assert!(recalculate);
+            }
+            _ => panic!("expected du command"),
+        }
+    }
+
+    #[test]
+    fn notes_edits_the_note_and_tags_of_a_package() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "notes",
+            "jq",
+            "--set",
+            "needed for project X",
+            "--tag",
+            "work",
+            "--tag",
+            "ci",
+            "--untag",
+            "old",
+        ])
+        .unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Notes {
+                formula,
+                set,
+                clear,
+                tags,
+                untags,
+                json,
+            } => {
+                assert_eq!(formula, "jq");
+                assert_eq!(set.as_deref(), Some("needed for project X"));
+                assert!(!clear);
+                assert_eq!(tags, ["work", "ci"]);
+                assert_eq!(untags, ["old"]);
+                assert!(!json);
+            }
+            _ => panic!("expected notes command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "notes", "jq", "--set", "x", "--clear"]).is_err());
+
+        let cli = Cli::try_parse_from(["zb", "list", "--tag", "work"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::List { tag: Some(ref tag), .. }) if tag == "work"
+        ));
+        let cli = Cli::try_parse_from(["zb", "uninstall", "--tag", "work"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Uninstall { tag: Some(ref tag), ref formulas, all: false })
+                if tag == "work" && formulas.is_empty()
+        ));
+        assert!(Cli::try_parse_from(["zb", "uninstall", "jq", "--tag", "work"]).is_err());
+    }
+
+    #[test]
+    fn list_files_takes_a_formula() {
+        let cli = Cli::try_parse_from(["zb", "list", "--files", "jq", "--json"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::List { files, json, .. } => {
+                assert_eq!(files.as_deref(), Some("jq"));
+                assert!(json);
+            }
+            _ => panic!("expected list command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "list", "--files", "jq", "--size"]).is_err());
+
+        let cli = Cli::try_parse_from(["zb", "owns", "/opt/zerobrew/prefix/bin/jq"]).unwrap();
+        assert!(matches!(cli.command, Some(super::Commands::Owns { .. })));
+    }
+
+    #[test]
+    fn store_verification_is_off_by_default() {
+        let cli = Cli::try_parse_from(["zb", "install", "jq"]).unwrap();
+        assert_eq!(cli.verify_store, zb_io::StoreVerification::Off);
+
+        let cli =
+            Cli::try_parse_from(["zb", "--verify-store", "sampled", "install", "jq"]).unwrap();
+        assert_eq!(cli.verify_store, zb_io::StoreVerification::Sampled);
+
+        assert!(Cli::try_parse_from(["zb", "--verify-store", "paranoid", "list"]).is_err());
+    }
+
+    #[test]
+    fn deps_takes_one_graph_format() {
+        let cli = Cli::try_parse_from(["zb", "deps", "--dot", "wget"]).unwrap();
+        match cli.command {
+            Some(super::Commands::Deps {
+                formulas,
+                dot,
+                mermaid,
+            }) => {
+                assert_eq!(formulas, vec!["wget"]);
+                assert!(dot && !mermaid);
+            }
+            _ => panic!("expected deps command"),
+        }
+
+        assert!(Cli::try_parse_from(["zb", "deps"]).is_ok());
+        assert!(Cli::try_parse_from(["zb", "deps", "--dot", "--mermaid"]).is_err());
+    }
+
+    #[test]
+    fn search_matches_descriptions_on_request() {
+        let cli = Cli::try_parse_from(["zb", "search", "--desc", "json"]).unwrap();
+        match cli.command {
+            Some(super::Commands::Search { query, desc, .. }) => {
+                assert_eq!(query.as_deref(), Some("json"));
+                assert!(desc);
+            }
+            _ => panic!("expected search command"),
+        }
+
+        assert!(Cli::try_parse_from(["zb", "search"]).is_err());
+        let cli = Cli::try_parse_from(["zb", "licenses", "--json"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Licenses { json: true })
+        ));
+    }
+
+    #[test]
+    fn search_lists_popular_formulas() {
+        let cli = Cli::try_parse_from(["zb", "search", "--popular"]).unwrap();
+        match cli.command {
+            Some(super::Commands::Search {
+                query,
+                popular,
+                period,
+                ..
+            }) => {
+                assert_eq!(query, None);
+                assert_eq!(popular, Some(20));
+                assert_eq!(period, zb_io::AnalyticsPeriod::Days30);
+            }
+            _ => panic!("expected search command"),
+        }
+
+        let cli =
+            Cli::try_parse_from(["zb", "search", "--popular", "5", "--period", "365d"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Search {
+                popular: Some(5),
+                period: zb_io::AnalyticsPeriod::Days365,
+                ..
+            })
+        ));
+        assert!(Cli::try_parse_from(["zb", "search", "jq", "--popular"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "search", "--popular", "--period", "7d"]).is_err());
+    }
+
+    #[test]
+    fn audit_can_run_offline() {
+        let cli = Cli::try_parse_from(["zb", "audit", "--offline", "--json"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Audit {
+                json: true,
+                offline: true
+            })
+        ));
+    }
+
+    #[test]
+    fn stats_are_recorded_on_request() {
+        let cli = Cli::try_parse_from(["zb", "--record-stats", "install", "jq"]).unwrap();
+        assert!(cli.record_stats);
+
+        let cli = Cli::try_parse_from(["zb", "stats", "--json"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Stats {
+                json: true,
+                clear: false
+            })
+        ));
+        assert!(Cli::try_parse_from(["zb", "stats", "--json", "--clear"]).is_err());
+    }
+
+    #[test]
+    fn daemon_takes_an_optional_socket() {
+        let cli = Cli::try_parse_from(["zb", "daemon"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(super::Commands::Daemon { socket: None })
+        ));
+
+        let cli = Cli::try_parse_from(["zb", "daemon", "--socket", "/tmp/zb.sock"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Daemon { socket } => {
+                assert_eq!(socket, Some(PathBuf::from("/tmp/zb.sock")))
+            }
+            _ => panic!("expected daemon command"),
+        }
+    }
+
     #[test]
     fn accepts_verbose_levels() {
         let cli = Cli::try_parse_from(["zb", "-vv", "list"]).unwrap();
@@ -101,6 +1519,28 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn fetch_accepts_bottle_tag() {
+        let cli =
+            Cli::try_parse_from(["zb", "fetch", "jq", "--bottle-tag", "arm64_sonoma"]).unwrap();
+        match cli.command.unwrap() {
+            super::Commands::Fetch {
+                formulas,
+                bottle_tag,
+            } => {
+                assert_eq!(formulas, vec!["jq"]);
+                assert_eq!(bottle_tag.as_deref(), Some("arm64_sonoma"));
+            }
+            _ => panic!("expected fetch command"),
+        }
+    }
+
+    #[test]
+    fn info_bottles_and_receipt_conflict() {
+        let result = Cli::try_parse_from(["zb", "info", "jq", "--bottles", "--receipt"]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn outdated_verbose_and_json_conflict() {
         let result = Cli::try_parse_from(["zb", "outdated", "--verbose", "--json"]);
@@ -112,24 +1552,228 @@ mod tests {
 pub enum Commands {
     /// Install formulas and casks
     Install {
-        #[arg(required = true, num_args = 1..)]
+        #[arg(
+            required_unless_present_any = ["bottle", "from_lockfile"],
+            num_args = 1..,
+            help = "Formula names, or paths to .rb formula files"
+        )]
         formulas: Vec<String>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            requires_all = ["name", "bottle_version"],
+            conflicts_with_all = ["build_from_source", "head", "arch"],
+            help = "Install a local bottle tarball without any network access"
+        )]
+        bottle: Option<PathBuf>,
+        #[arg(
+            long,
+            requires = "bottle",
+            help = "Formula name to install --bottle as"
+        )]
+        name: Option<String>,
+        #[arg(
+            long = "version",
+            value_name = "VERSION",
+            requires = "bottle",
+            help = "Version to install --bottle as"
+        )]
+        bottle_version: Option<String>,
         #[arg(long, help = "Do not create symlinks after installation")]
         no_link: bool,
+        #[arg(
+            long,
+            conflicts_with = "no_link",
+            help = "Replace conflicting links that belong to other zerobrew packages"
+        )]
+        overwrite: bool,
         #[arg(long, short = 's', help = "Build from source instead of using bottles")]
         build_from_source: bool,
+        #[arg(
+            long,
+            conflicts_with = "bottle",
+            help = "Also install build dependencies, which bottles do not need"
+        )]
+        include_build: bool,
+        #[arg(
+            long,
+            conflicts_with = "bottle",
+            help = "Also install optional dependencies"
+        )]
+        include_optional: bool,
+        #[arg(
+            long = "HEAD",
+            conflicts_with = "arch",
+            help = "Build the latest commit of the formula's head repository"
+        )]
+        head: bool,
+        #[arg(
+            long,
+            value_name = "NAME",
+            conflicts_with_all = ["bottle", "build_from_source", "head", "from_lockfile"],
+            help = "Install the tap's alternative bottles named NAME (e.g. no-gui); upgrades keep them"
+        )]
+        variant: Option<String>,
+        #[arg(
+            long,
+            help = "Show what would be downloaded and installed, without installing"
+        )]
+        dry_run: bool,
+        #[arg(
+            long,
+            value_name = "ARCH",
+            value_parser = parse_arch,
+            help = "Install bottles for another CPU architecture (x86_64 under Rosetta 2)"
+        )]
+        arch: Option<&'static str>,
+        #[arg(
+            long,
+            help = "Show the dependency tree that would be installed, without installing"
+        )]
+        explain: bool,
+        #[arg(
+            long,
+            value_name = "FORMULA",
+            conflicts_with = "explain",
+            help = "Show which requested formula pulls in FORMULA, without installing"
+        )]
+        why: Option<String>,
+        #[arg(
+            long,
+            help = "Audit relocated bottles and fail if they reference missing libraries"
+        )]
+        paranoid: bool,
+        #[arg(
+            long,
+            conflicts_with = "dry_run",
+            help = "Print how long each install phase took per formula"
+        )]
+        timings: bool,
+        #[arg(
+            long,
+            help = "Resolve and reinstall even when the formulas are already installed"
+        )]
+        force: bool,
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Record API requests, bottles, relocations and links to FILE as JSON lines"
+        )]
+        transcript: Option<PathBuf>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["bottle", "from_lockfile"],
+            help = "Write the resolved packages, versions and digests to FILE"
+        )]
+        lockfile: Option<PathBuf>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["formulas", "bottle", "head", "build_from_source"],
+            help = "Install exactly what FILE pinned, failing if upstream has moved on"
+        )]
+        from_lockfile: Option<PathBuf>,
+    },
+    /// Write a tap formula for a source tarball or release binary URL
+    Create {
+        #[arg(help = "URL of the source archive or prebuilt binary")]
+        url: String,
+        #[arg(long, help = "Formula name, instead of the one the URL suggests")]
+        name: Option<String>,
+        #[arg(
+            long = "version",
+            value_name = "VERSION",
+            help = "Formula version, instead of the one the URL suggests"
+        )]
+        formula_version: Option<String>,
+        #[arg(
+            long,
+            short = 'o',
+            value_name = "FILE",
+            help = "Where to write the formula (default: ./<name>.rb, - for stdout)"
+        )]
+        output: Option<PathBuf>,
+        #[arg(long, help = "Overwrite an existing formula file")]
+        force: bool,
+    },
+    /// Download bottles into the cache without installing them
+    Fetch {
+        #[arg(required = true, num_args = 1..)]
+        formulas: Vec<String>,
+        #[arg(
+            long,
+            value_name = "TAG",
+            help = "Fetch bottles for another platform (e.g. arm64_sonoma, x86_64_linux)"
+        )]
+        bottle_tag: Option<String>,
+    },
+    /// Install or dump from a Brewfile
+    Bundle {
+        #[command(subcommand)]
+        command: Option<BundleCommands>,
+    },
+    /// Install packages from a Brewfile or a plain package list
+    Import {
+        #[arg(
+            value_name = "FILE",
+            help = "Brewfile or package list to read, or - for stdin"
+        )]
+        source: PathBuf,
+        #[arg(long, value_enum, default_value_t = ImportFormat::Auto, help = "Format of the file")]
+        format: ImportFormat,
+        #[arg(long, help = "Do not create symlinks after installation")]
+        no_link: bool,
+        #[arg(
+            long,
+            help = "Then uninstall packages that are not listed and not needed by ones that are"
+        )]
+        cleanup: bool,
+        #[arg(
+            long,
+            help = "Show what would be installed and cleaned up without doing it"
+        )]
+        dry_run: bool,
     },
-    /// Install or dump from a Brewfile
-    Bundle {
-        #[command(subcommand)]
-        command: Option<BundleCommands>,
+    /// Describe installed packages as a Brewfile, JSON or TOML
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Brewfile, help = "Output format")]
+        format: ExportFormat,
+        #[arg(
+            long,
+            short = 'o',
+            value_name = "FILE",
+            help = "Write to FILE instead of stdout"
+        )]
+        output: Option<PathBuf>,
+    },
+    /// Assemble a formula and its runtime dependencies into a container image root or layer
+    ExportImage {
+        #[arg(help = "Installed formula to export")]
+        formula: String,
+        #[arg(
+            long,
+            short = 'o',
+            value_name = "PATH",
+            help = "Directory or layer file to write"
+        )]
+        output: PathBuf,
+        #[arg(long, value_enum, default_value_t = ImageFormat::Dir, help = "Output format")]
+        format: ImageFormat,
     },
     /// Uninstall formulas and casks
     Uninstall {
-        #[arg(required_unless_present = "all", num_args = 1..)]
+        #[arg(required_unless_present_any = ["all", "tag"], num_args = 1..)]
         formulas: Vec<String>,
         #[arg(long, help = "Uninstall all installed packages")]
         all: bool,
+        #[arg(
+            long,
+            value_name = "TAG",
+            conflicts_with_all = ["formulas", "all"],
+            help = "Uninstall the packages tagged TAG"
+        )]
+        tag: Option<String>,
     },
     /// Migrate packages from Homebrew
     Migrate {
@@ -139,19 +1783,196 @@ pub enum Commands {
         force: bool,
     },
     /// List installed packages
-    List,
+    List {
+        #[arg(long, help = "Show the disk usage of each package")]
+        size: bool,
+        #[arg(
+            long,
+            value_name = "FORMULA",
+            help = "Only list installed dependencies of FORMULA"
+        )]
+        deps_of: Option<String>,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[arg(long, value_enum, default_value_t = ListSort::Name, help = "Sort order")]
+        sort: ListSort,
+        #[arg(long, short = 'r', help = "Reverse the sort order")]
+        reverse: bool,
+        #[arg(
+            long,
+            value_name = "FORMULA",
+            conflicts_with_all = ["size", "deps_of", "sort", "reverse"],
+            help = "List the files FORMULA installed"
+        )]
+        files: Option<String>,
+        #[arg(
+            long,
+            value_name = "TAG",
+            conflicts_with_all = ["deps_of", "files"],
+            help = "Only list packages tagged TAG"
+        )]
+        tag: Option<String>,
+    },
+    /// Show the disk usage of kegs, the store and the download cache
+    Du {
+        #[arg(long, value_enum, default_value_t = ListSort::Name, help = "Sort order of the kegs")]
+        sort: ListSort,
+        #[arg(long, short = 'r', help = "Reverse the sort order")]
+        reverse: bool,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[arg(long, help = "Measure every keg again instead of using cached sizes")]
+        recalculate: bool,
+    },
+    /// Show or edit why a package is installed: a free-text note and tags
+    Notes {
+        #[arg(help = "Name of the installed package")]
+        formula: String,
+        #[arg(
+            long,
+            value_name = "TEXT",
+            conflicts_with = "clear",
+            help = "Replace the note"
+        )]
+        set: Option<String>,
+        #[arg(long, help = "Remove the note")]
+        clear: bool,
+        #[arg(long = "tag", value_name = "TAG", help = "Add a tag (repeatable)")]
+        tags: Vec<String>,
+        #[arg(long = "untag", value_name = "TAG", help = "Remove a tag (repeatable)")]
+        untags: Vec<String>,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+    },
+    /// Show which installed package a file belongs to
+    Owns {
+        #[arg(help = "Path to a file in the prefix or cellar")]
+        path: PathBuf,
+    },
+    /// Search formulas by name, or also by description with --desc
+    Search {
+        #[arg(
+            required_unless_present = "popular",
+            help = "Text to look for, ignoring case"
+        )]
+        query: Option<String>,
+        #[arg(long, help = "Also match and show descriptions")]
+        desc: bool,
+        #[arg(
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            default_missing_value = "20",
+            conflicts_with = "query",
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "List the N most installed formulas instead (default 20)"
+        )]
+        popular: Option<u64>,
+        #[arg(
+            long,
+            default_value = "30d",
+            value_parser = parse_analytics_period,
+            help = "Install counts to rank and annotate by: 30d, 90d or 365d"
+        )]
+        period: AnalyticsPeriod,
+    },
+    /// List the license of every installed package
+    Licenses {
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+    },
+    /// Check installed packages against the OSV vulnerability database
+    Audit {
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[arg(
+            long,
+            help = "Check against the records fetched by the last online audit"
+        )]
+        offline: bool,
+    },
+    /// Show the dependency graph of installed or prospective packages
+    Deps {
+        #[arg(help = "Formulas to graph (default: every installed package)")]
+        formulas: Vec<String>,
+        #[arg(long, conflicts_with = "mermaid", help = "Output Graphviz DOT")]
+        dot: bool,
+        #[arg(long, help = "Output a Mermaid flowchart")]
+        mermaid: bool,
+    },
     /// Show information about an installed package
     Info {
         #[arg(help = "Name of the installed package")]
         formula: String,
+        #[arg(long, help = "Print the keg's install receipt as JSON")]
+        receipt: bool,
+        #[arg(
+            long,
+            conflicts_with = "receipt",
+            help = "List the bottle tags the formula publishes"
+        )]
+        bottles: bool,
     },
     /// Run diagnostics and optionally repair issues
     Doctor {
         #[arg(long, help = "Automatically repair detected issues")]
         repair: bool,
     },
-    /// Remove unreferenced store entries
-    Gc,
+    /// Show install timings, cache hit rate and download throughput
+    Stats {
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[arg(long, conflicts_with = "json", help = "Forget the recorded installs")]
+        clear: bool,
+    },
+    /// Check a transcript from `install --transcript` against this system
+    Replay {
+        #[arg(help = "Transcript file to check")]
+        file: PathBuf,
+    },
+    /// Check an installed package for references to missing libraries and data
+    AuditRelocation {
+        #[arg(help = "Name of the installed package")]
+        formula: String,
+    },
+    /// Remove unreferenced store entries and expired previous versions
+    Gc {
+        #[arg(long, help = "Also hardlink identical files across store entries")]
+        dedupe: bool,
+        #[arg(
+            long,
+            value_name = "DAYS",
+            default_value = "30",
+            help = "Remove previous versions kept for rollback once replaced this many days ago"
+        )]
+        keep_days: u64,
+        #[arg(
+            long,
+            value_name = "DAYS",
+            default_value = "7",
+            help = "Keep store entries no package uses until unused this many days, for reinstalls"
+        )]
+        max_age: u64,
+        #[arg(
+            long,
+            value_name = "N",
+            default_value = "0",
+            help = "Keep the store entries of the last N versions of each formula however old"
+        )]
+        keep_versions: usize,
+    },
+    /// Evict old downloads and cached API responses
+    Cleanup {
+        #[arg(
+            long,
+            value_name = "DAYS",
+            default_value = "30",
+            help = "Remove unused downloads and API responses older than this many days"
+        )]
+        max_age: u64,
+        #[arg(long, help = "Show what would be removed without deleting anything")]
+        dry_run: bool,
+    },
     /// Reset zerobrew data directories
     Reset {
         #[arg(long, short = 'y', help = "Skip confirmation prompts")]
@@ -161,6 +1982,11 @@ pub enum Commands {
     Init {
         #[arg(long, help = "Do not modify shell configuration files")]
         no_modify_path: bool,
+        #[arg(
+            long,
+            help = "Never use sudo; fall back to ~/.zerobrew when the default root is not writable"
+        )]
+        no_sudo: bool,
     },
     /// Generate shell completions
     Completion {
@@ -177,6 +2003,59 @@ pub enum Commands {
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
     },
+    /// Run a command with PATH, MANPATH and library paths from the prefix and chosen formulas
+    Exec {
+        #[arg(
+            long = "with",
+            value_name = "FORMULA",
+            help = "Include an installed formula, such as a keg-only openssl@3 (repeatable)"
+        )]
+        formulas: Vec<String>,
+        #[arg(
+            required = true,
+            last = true,
+            value_name = "COMMAND",
+            help = "Command and arguments to run, after --"
+        )]
+        command: Vec<String>,
+    },
+    /// Start $SHELL with the environment of `zb exec`
+    Shell {
+        #[arg(
+            long = "with",
+            value_name = "FORMULA",
+            help = "Include an installed formula, such as a keg-only openssl@3 (repeatable)"
+        )]
+        formulas: Vec<String>,
+    },
+    /// Print compiler and pkg-config exports for building against installed formulas
+    Env {
+        #[arg(
+            long = "formula",
+            value_name = "FORMULA",
+            help = "Include an installed formula, such as a keg-only openssl@3 (repeatable)"
+        )]
+        formulas: Vec<String>,
+    },
+    /// Show which formula provides a command
+    Which {
+        #[arg(required_unless_present = "hook", help = "Name of the command")]
+        command: Option<String>,
+        #[arg(
+            long,
+            requires = "command",
+            help = "Print a command-not-found message suggesting a formula to install"
+        )]
+        suggest: bool,
+        #[arg(
+            long,
+            value_enum,
+            value_name = "SHELL",
+            conflicts_with_all = ["command", "suggest"],
+            help = "Print a command-not-found hook for bash, zsh or fish"
+        )]
+        hook: Option<clap_complete::shells::Shell>,
+    },
     /// Refresh cached formula metadata
     Update,
     /// List installed packages with newer versions available
@@ -193,6 +2072,130 @@ pub enum Commands {
         #[arg(long, help = "Do not create symlinks after installation")]
         no_link: bool,
     },
+    /// Link an installed package into the prefix
+    Link {
+        #[arg(help = "Name of the installed package")]
+        formula: String,
+        #[arg(
+            long,
+            help = "Replace conflicting links that belong to other zerobrew packages"
+        )]
+        overwrite: bool,
+        #[arg(
+            long,
+            conflicts_with = "overwrite",
+            help = "Link conflicting files of a versioned formula (name@X) with an -X suffix"
+        )]
+        keep_both: bool,
+        #[arg(
+            long,
+            value_name = "MODE",
+            value_parser = parse_link_mode,
+            help = "Link its executables as symlink, hardlink or wrapper from now on, including after upgrades"
+        )]
+        mode: Option<LinkMode>,
+    },
+    /// Manage named link trees over the installed kegs
+    Profile {
+        #[command(subcommand)]
+        command: Option<ProfileCommands>,
+    },
+    /// Restore a package's links in the prefix to what zerobrew recorded
+    Relink {
+        #[arg(help = "Name of the installed package")]
+        formula: String,
+        #[arg(long, help = "Also replace files put where its links were")]
+        force: bool,
+    },
+    /// List recorded installs, uninstalls, upgrades and gc runs, newest first
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommands>,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+        #[arg(
+            long,
+            short = 'n',
+            default_value_t = 20,
+            help = "How many operations to show"
+        )]
+        limit: usize,
+    },
+    /// Switch a package back to the version its last upgrade replaced
+    Rollback {
+        #[arg(help = "Name of the installed package")]
+        formula: String,
+    },
+    /// Manage background services of installed formulas
+    Services {
+        #[command(subcommand)]
+        command: Option<ServicesCommands>,
+    },
+    /// Manage a `brew` executable in the prefix that runs zb
+    Shim {
+        #[command(subcommand)]
+        command: ShimCommands,
+    },
+    /// Mirror Homebrew's Cellar and opt layout for tools that hardcode it
+    Compat {
+        #[command(subcommand)]
+        command: Option<CompatCommands>,
+    },
+    /// Inspect the content-addressable store of unpacked bottles
+    Store {
+        #[command(subcommand)]
+        command: Option<StoreCommands>,
+    },
+    /// Serve install, uninstall and queries over a local JSON-RPC socket
+    Daemon {
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Socket to listen on (default: <root>/zb.sock)"
+        )]
+        socket: Option<PathBuf>,
+    },
+    /// Update or uninstall zerobrew itself
+    #[command(name = "self")]
+    SelfManage {
+        #[command(subcommand)]
+        command: SelfCommands,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ImportFormat {
+    /// Brewfile if it has brew, cask or tap lines, otherwise a list
+    Auto,
+    /// `brew "jq"` and `cask "docker"` lines
+    Brewfile,
+    /// One package per line, as `brew leaves` prints
+    List,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// `brew "jq"` lines, as `zb bundle` and `zb import` read
+    Brewfile,
+    /// Every package with its version, tap, link and service state
+    Json,
+    /// The same as json, as a TOML array of tables
+    Toml,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ImageFormat {
+    /// A root filesystem directory, to `COPY` into an image
+    Dir,
+    /// A gzipped OCI image layer, to `ADD` into an image
+    Layer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    Name,
+    Size,
+    Installed,
 }
 
 #[derive(Subcommand)]
@@ -224,3 +2227,239 @@ pub enum BundleCommands {
         force: bool,
     },
 }
+
+#[derive(Subcommand)]
+pub enum CompatCommands {
+    /// List the directories kept in Homebrew's layout
+    List,
+    /// Keep Cellar/<name>/<version> and opt/<name> links to zerobrew kegs in a directory
+    Enable {
+        #[arg(
+            value_name = "PATH",
+            help = "Directory to populate (default: Homebrew's prefix for this platform)"
+        )]
+        path: Option<PathBuf>,
+    },
+    /// Remove zerobrew's links from a directory and stop updating it
+    Disable {
+        #[arg(
+            value_name = "PATH",
+            help = "Directory to clean (default: Homebrew's prefix for this platform)"
+        )]
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StoreCommands {
+    /// List store entries with their reference counts and kegs
+    Ls {
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+    },
+    /// Show one store entry: its path, references and the kegs using it
+    Info {
+        #[arg(
+            value_name = "SHA",
+            help = "Key of the entry, or a unique prefix of it"
+        )]
+        key: String,
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ShimCommands {
+    /// Create `brew` in the prefix, running zb for the commands it supports
+    /// and Homebrew, if installed, for the rest
+    Install {
+        #[arg(long, help = "Replace a `brew` in the prefix that is not a shim")]
+        force: bool,
+    },
+    /// Remove the `brew` shim from the prefix
+    Uninstall,
+}
+
+#[derive(Subcommand)]
+pub enum SelfCommands {
+    /// Replace zb (and zbx) with the latest release when it is newer, after
+    /// checking it against the release's checksums and build provenance
+    Update {
+        #[arg(long, help = "Only report whether a newer release is available")]
+        check: bool,
+        #[arg(
+            long,
+            help = "Trust the release checksums alone, without verifying build provenance with the GitHub CLI"
+        )]
+        skip_attestation: bool,
+    },
+    /// Remove the root, the prefix, the zb binaries and the shell
+    /// configuration `zb init` added
+    Uninstall {
+        #[arg(long, short = 'y', help = "Skip confirmation prompts")]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommands {
+    /// Reverse an install, upgrade or rollback from the history
+    Undo {
+        #[arg(help = "Operation id, as shown by `zb history`")]
+        id: i64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommands {
+    /// List profiles with the number of kegs in each, marking the current one
+    List,
+    /// Create a profile from installed formulas
+    Create {
+        #[arg(help = "Name of the new profile")]
+        name: String,
+        #[arg(help = "Installed formulas to link into it (default: everything linked now)")]
+        formulas: Vec<String>,
+    },
+    /// Point prefix/profiles/current at a profile
+    Switch {
+        #[arg(help = "Name of the profile, or default for the prefix")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ServicesCommands {
+    /// List services with their state and next scheduled run
+    List,
+    /// Start a formula's service and run it at login
+    Start {
+        #[arg(help = "Name of the installed formula")]
+        formula: String,
+    },
+    /// Stop a formula's service
+    Stop {
+        #[arg(help = "Name of the installed formula")]
+        formula: String,
+    },
+    /// Run a formula's service in the foreground, outside the service manager
+    Run {
+        #[arg(help = "Name of the installed formula")]
+        formula: String,
+    },
+    /// Show a service's state, schedule and overrides
+    Status {
+        #[arg(help = "Name of the installed formula")]
+        formula: String,
+    },
+    /// Change the environment, arguments or working directory of a service
+    #[command(group(
+        clap::ArgGroup::new("change")
+            .required(true)
+            .multiple(true)
+            .args([
+                "env",
+                "unset_env",
+                "arg",
+                "workdir",
+                "restart",
+                "health_port",
+                "health_cmd",
+                "no_health_check",
+                "reset",
+            ])
+    ))]
+    Edit {
+        #[arg(help = "Name of the installed formula")]
+        formula: String,
+        #[arg(
+            long,
+            value_name = "KEY=VAL",
+            value_parser = parse_env_var,
+            help = "Set an environment variable (repeatable)"
+        )]
+        env: Vec<(String, String)>,
+        #[arg(
+            long,
+            value_name = "KEY",
+            help = "Remove an environment variable set with --env (repeatable)"
+        )]
+        unset_env: Vec<String>,
+        #[arg(
+            long,
+            value_name = "ARG",
+            allow_hyphen_values = true,
+            help = "Append an argument to the command, replacing earlier --arg values (repeatable)"
+        )]
+        arg: Vec<String>,
+        #[arg(long, value_name = "PATH", help = "Run the service in this directory")]
+        workdir: Option<PathBuf>,
+        #[arg(
+            long,
+            value_name = "POLICY",
+            value_parser = parse_restart_policy,
+            help = "When to restart the service: always, on-failure or never"
+        )]
+        restart: Option<RestartPolicy>,
+        #[arg(
+            long,
+            value_name = "PORT",
+            conflicts_with = "health_cmd",
+            help = "Consider the service healthy once this local TCP port accepts connections"
+        )]
+        health_port: Option<u16>,
+        #[arg(
+            long,
+            value_name = "COMMAND",
+            help = "Consider the service healthy while this shell command succeeds"
+        )]
+        health_cmd: Option<String>,
+        #[arg(
+            long,
+            conflicts_with_all = ["health_port", "health_cmd"],
+            help = "Remove the health check"
+        )]
+        no_health_check: bool,
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "env",
+                "unset_env",
+                "arg",
+                "workdir",
+                "restart",
+                "health_port",
+                "health_cmd",
+                "no_health_check",
+            ],
+            help = "Drop all overrides"
+        )]
+        reset: bool,
+    },
+    /// Run a formula's service on a schedule instead of keeping it running
+    #[command(group(
+        clap::ArgGroup::new("trigger")
+            .required(true)
+            .args(["cron", "interval", "clear"])
+    ))]
+    Schedule {
+        #[arg(help = "Name of the installed formula")]
+        formula: String,
+        #[arg(
+            long,
+            value_name = "EXPR",
+            help = "Five-field cron expression, e.g. \"0 3 * * *\""
+        )]
+        cron: Option<String>,
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Run every this many seconds"
+        )]
+        interval: Option<u64>,
+        #[arg(long, help = "Go back to the schedule the formula declares")]
+        clear: bool,
+    },
+}
@@ -0,0 +1,109 @@
+use console::style;
+use zb_io::{OsvClient, PackageAudit};
+
+pub async fn execute(
+    installer: &zb_io::Installer,
+    json: bool,
+    offline: bool,
+) -> Result<(), zb_core::Error> {
+    let feed = (!offline).then(OsvClient::new);
+    let audits = installer.audit_vulnerabilities(feed.as_ref()).await?;
+
+    if json {
+        let json_output: Vec<serde_json::Value> = audits.iter().map(audit_json).collect();
+        println!("{}", serde_json::to_string_pretty(&json_output).unwrap());
+    } else {
+        print_report(&audits, offline);
+    }
+
+    let vulnerable: Vec<String> = audits
+        .iter()
+        .filter(|audit| !audit.advisories.is_empty())
+        .map(|audit| audit.keg.name.clone())
+        .collect();
+    if vulnerable.is_empty() {
+        Ok(())
+    } else {
+        Err(zb_core::Error::Vulnerable { names: vulnerable })
+    }
+}
+
+fn audit_json(audit: &PackageAudit) -> serde_json::Value {
+    let vulnerabilities: Vec<serde_json::Value> = audit
+        .advisories
+        .iter()
+        .map(|advisory| {
+            serde_json::json!({
+                "id": advisory.id,
+                "aliases": advisory.aliases,
+                "summary": advisory.summary,
+                "fixed": advisory.fixed,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "name": audit.keg.name,
+        "version": audit.keg.version,
+        "license": audit.license,
+        "checked_at": audit.checked_at,
+        "vulnerabilities": vulnerabilities,
+    })
+}
+
+fn print_report(audits: &[PackageAudit], offline: bool) {
+    if audits.is_empty() {
+        println!("No formulas installed.");
+        return;
+    }
+
+    for audit in audits.iter().filter(|audit| !audit.advisories.is_empty()) {
+        let license = audit.license.as_deref().unwrap_or("unknown license");
+        println!(
+            "{} {} {}",
+            style(&audit.keg.name).bold(),
+            audit.keg.version,
+            style(format!("({license})")).dim()
+        );
+        for advisory in &audit.advisories {
+            let mut line = format!("    {} {}", style("✗").red(), advisory.id);
+            if !advisory.aliases.is_empty() {
+                line.push_str(&format!(" ({})", advisory.aliases.join(", ")));
+            }
+            if let Some(summary) = &advisory.summary {
+                line.push_str(&format!(": {summary}"));
+            }
+            if let Some(fixed) = &advisory.fixed {
+                line.push_str(&format!(
+                    " {}",
+                    style(format!("[fixed in {fixed}]")).green()
+                ));
+            }
+            println!("{line}");
+        }
+    }
+
+    let unchecked: Vec<&str> = audits
+        .iter()
+        .filter(|audit| audit.checked_at.is_none())
+        .map(|audit| audit.keg.name.as_str())
+        .collect();
+    if !unchecked.is_empty() {
+        println!(
+            "{} No vulnerability records for: {}",
+            style("Note:").yellow().bold(),
+            unchecked.join(", ")
+        );
+        if offline {
+            println!("      Run `zb audit` while online to fetch them.");
+        }
+    }
+
+    let checked = audits.len() - unchecked.len();
+    if audits.iter().all(|audit| audit.advisories.is_empty()) {
+        println!(
+            "{} No known vulnerabilities in {checked} {}",
+            style("✓").green(),
+            if checked == 1 { "package" } else { "packages" }
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+use console::style;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(&formula)?;
+    ui.heading(format!("Auditing {}...", style(&name).bold()))
+        .map_err(ui_error)?;
+
+    let audit = installer.audit_relocation(&name)?;
+    for missing in &audit.missing_data {
+        let mut message = format!(
+            "{}: data path {} does not exist",
+            missing.file.display(),
+            missing.reference
+        );
+        if let Some(suggestion) = &missing.suggestion {
+            message.push_str(&format!(" ({suggestion})"));
+        }
+        ui.warn(message).map_err(ui_error)?;
+    }
+    for missing in &audit.missing_interpreters {
+        let mut message = format!(
+            "{}: interpreter {} does not exist",
+            missing.file.display(),
+            missing.reference
+        );
+        if let Some(suggestion) = &missing.suggestion {
+            message.push_str(&format!(" ({suggestion})"));
+        }
+        ui.warn(message).map_err(ui_error)?;
+    }
+    audit.ensure_clean(&name)?;
+
+    ui.println(format!(
+        "    {} No dangling references in {} {}",
+        style("✓").green(),
+        audit.files_scanned,
+        if audit.files_scanned == 1 {
+            "binary"
+        } else {
+            "binaries"
+        }
+    ))
+    .map_err(ui_error)?;
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
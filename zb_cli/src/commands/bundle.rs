@@ -29,17 +29,30 @@ async fn install_from_file(
     no_link: bool,
     ui: &mut StdUi,
 ) -> Result<(), zb_core::Error> {
-    let formulas = load_manifest(manifest_path)?;
+    let entries = load_manifest(manifest_path)?;
     println!(
         "{} Installing {} formulas from {}...",
         style("==>").cyan().bold(),
-        style(formulas.len()).green().bold(),
+        style(entries.len()).green().bold(),
         manifest_path.display()
     );
 
     let start = Instant::now();
-    for formula in formulas {
-        install::execute(installer, vec![formula], no_link, false, ui).await?;
+    for entry in entries {
+        if !entry.args.is_empty() {
+            ui.warn(format!(
+                "ignoring args for {} ({}): bottles are prebuilt",
+                entry.name,
+                entry.args.join(", ")
+            ))
+            .map_err(ui_error)?;
+        }
+        let no_link = no_link || !entry.link;
+        let options = install::InstallOptions {
+            no_link,
+            ..Default::default()
+        };
+        install::execute(installer, vec![entry.name], options, ui).await?;
     }
 
     println!(
@@ -84,67 +97,279 @@ fn dump_to_file(
     Ok(())
 }
 
-fn load_manifest(path: &Path) -> Result<Vec<String>, zb_core::Error> {
-    let contents = std::fs::read_to_string(path).map_err(|e| zb_core::Error::FileError {
+fn load_manifest(path: &Path) -> Result<Vec<BrewfileEntry>, zb_core::Error> {
+    let contents = std::fs::read_to_string(path).map_err(|e| zb_core::Error::ImportError {
         message: format!("failed to read manifest {}: {}", path.display(), e),
     })?;
 
-    let mut formulas = Vec::new();
+    let entries = parse_brewfile(&contents);
+    if entries.is_empty() {
+        return Err(zb_core::Error::ImportError {
+            message: format!("manifest {} did not contain any formulas", path.display()),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// A package a Brewfile asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct BrewfileEntry {
+    /// Formula name, or `cask:<token>` for a cask.
+    pub name: String,
+    /// Build arguments from `args: [...]`, which bottles cannot honor.
+    pub args: Vec<String>,
+    /// False for `link: false`.
+    pub link: bool,
+}
+
+impl BrewfileEntry {
+    pub(super) fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            args: Vec::new(),
+            link: true,
+        }
+    }
+}
+
+/// A branch of an `if`/`unless` block.
+struct Branch {
+    active: bool,
+    /// Whether any branch of the block so far was taken.
+    taken: bool,
+}
+
+/// The packages of a Brewfile that apply to this machine, in order and
+/// without duplicates. Bare names are accepted as well, so plain lists
+/// keep working. Conditions are evaluated both as blocks and as trailing
+/// `if`/`unless` modifiers; ones that cannot be evaluated here hold.
+pub(super) fn parse_brewfile(contents: &str) -> Vec<BrewfileEntry> {
+    let mut entries = Vec::new();
     let mut seen: HashSet<String> = HashSet::new();
+    let mut blocks: Vec<Branch> = Vec::new();
 
     for line in contents.lines() {
-        // Handle inline comments by splitting on '#' and taking the first part
-        let entry = line.split('#').next().unwrap_or("").trim();
-        if entry.is_empty() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(condition) = line.strip_prefix("if ") {
+            let active = condition_holds(condition);
+            blocks.push(Branch {
+                active,
+                taken: active,
+            });
+            continue;
+        }
+        if let Some(condition) = line.strip_prefix("unless ") {
+            let active = !condition_holds(condition);
+            blocks.push(Branch {
+                active,
+                taken: active,
+            });
+            continue;
+        }
+        if let Some(condition) = line.strip_prefix("elsif ") {
+            if let Some(branch) = blocks.last_mut() {
+                branch.active = !branch.taken && condition_holds(condition);
+                branch.taken |= branch.active;
+            }
+            continue;
+        }
+        if line == "else" {
+            if let Some(branch) = blocks.last_mut() {
+                branch.active = !branch.taken;
+                branch.taken = true;
+            }
+            continue;
+        }
+        if line == "end" {
+            blocks.pop();
+            continue;
+        }
+        if !blocks.iter().all(|branch| branch.active) {
             continue;
         }
 
-        if let Some(parsed) = parse_brewfile_entry(entry)
-            && seen.insert(parsed.clone())
+        let (statement, applies) = split_modifier(line);
+        if !applies {
+            continue;
+        }
+        if let Some(entry) = parse_brewfile_entry(statement)
+            && seen.insert(entry.name.clone())
         {
-            formulas.push(parsed);
+            entries.push(entry);
         }
     }
 
-    if formulas.is_empty() {
-        return Err(zb_core::Error::FileError {
-            message: format!("manifest {} did not contain any formulas", path.display()),
-        });
+    entries
+}
+
+/// The package a single Brewfile statement asks for. Directives for things
+/// other than formulas and casks (taps, Mac App Store apps, editor
+/// extensions and the like) ask for none.
+pub(super) fn parse_brewfile_entry(line: &str) -> Option<BrewfileEntry> {
+    let (directive, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim_start();
+    match directive {
+        "brew" | "cask" => {
+            let (name, options) = parse_quoted(rest)?;
+            let mut entry = BrewfileEntry::new(if directive == "cask" {
+                format!("cask:{name}")
+            } else {
+                name.to_string()
+            });
+            for option in split_top_level(
+                options.trim_start_matches(|c: char| c == ',' || c.is_whitespace()),
+                ',',
+            ) {
+                let Some((key, value)) = option.split_once("=>").or_else(|| option.split_once(':'))
+                else {
+                    continue;
+                };
+                match key.trim().trim_start_matches(':') {
+                    "args" => entry.args = quoted_strings(value),
+                    "link" => entry.link = value.trim() != "false",
+                    _ => {}
+                }
+            }
+            Some(entry)
+        }
+        _ if rest.is_empty() && !line.contains(['"', '\'', '(']) => Some(BrewfileEntry::new(line)),
+        _ => None,
     }
+}
 
-    Ok(formulas)
+/// `line` without a trailing comment, leaving `#` inside strings alone.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+    }
+    line
 }
 
-fn parse_brewfile_entry(line: &str) -> Option<String> {
-    if line.starts_with("tap ") {
+/// The first quoted string of `text` and what follows it.
+fn parse_quoted(text: &str) -> Option<(&str, &str)> {
+    let quote = text.chars().next()?;
+    if quote != '"' && quote != '\'' {
         return None;
     }
+    let tail = &text[1..];
+    let end = tail.find(quote)?;
+    Some((&tail[..end], &tail[end + 1..]))
+}
 
-    if let Some(token) = parse_quoted_directive(line, "cask") {
-        return Some(format!("cask:{token}"));
+/// Every quoted string in `text`, e.g. the items of `["a", "b"]`.
+fn quoted_strings(text: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['"', '\'']) {
+        let Some((string, tail)) = parse_quoted(&rest[start..]) else {
+            break;
+        };
+        strings.push(string.to_string());
+        rest = tail;
     }
+    strings
+}
 
-    if let Some(formula) = parse_quoted_directive(line, "brew") {
-        return Some(formula.to_string());
+/// Split `text` on `separator` outside strings, brackets and braces.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '{' | '(') => depth += 1,
+            (None, ']' | '}' | ')') => depth = depth.saturating_sub(1),
+            (None, _) if c == separator && depth == 0 => {
+                parts.push(text[start..index].trim());
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
     }
-
-    Some(line.to_string())
+    parts.push(text[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
 }
 
-fn parse_quoted_directive<'a>(line: &'a str, directive: &str) -> Option<&'a str> {
-    if !line.starts_with(directive) {
-        return None;
+/// A statement without its trailing `if`/`unless` modifier, and whether the
+/// modifier lets it apply.
+fn split_modifier(line: &str) -> (&str, bool) {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, ' ') => {
+                let rest = &line[index + 1..];
+                if let Some(condition) = rest.strip_prefix("if ") {
+                    return (line[..index].trim_end(), condition_holds(condition));
+                }
+                if let Some(condition) = rest.strip_prefix("unless ") {
+                    return (line[..index].trim_end(), !condition_holds(condition));
+                }
+            }
+            _ => {}
+        }
     }
+    (line, true)
+}
 
-    let rest = line[directive.len()..].trim_start();
-    let quote = rest.chars().next()?;
-    if quote != '"' && quote != '\'' {
-        return None;
+/// Evaluate a Brewfile condition: `OS.mac?`, `OS.linux?`,
+/// `Hardware::CPU.arm?`, `Hardware::CPU.intel?` and `ENV["NAME"]`, negated
+/// with `!` and combined with `&&` and `||`. Anything else holds, so an
+/// entry is never dropped for a condition zerobrew does not understand.
+fn condition_holds(condition: &str) -> bool {
+    let condition = condition.trim().trim_end_matches(" then");
+    condition.split("||").any(|any| {
+        any.split("&&").all(|term| {
+            let term = term.trim().trim_start_matches('(').trim_end_matches(')');
+            match term.strip_prefix('!') {
+                Some(negated) => !term_holds(negated.trim()),
+                None => term_holds(term),
+            }
+        })
+    })
+}
+
+fn term_holds(term: &str) -> bool {
+    match term {
+        "OS.mac?" => cfg!(target_os = "macos"),
+        "OS.linux?" => cfg!(target_os = "linux"),
+        "Hardware::CPU.arm?" => cfg!(target_arch = "aarch64"),
+        "Hardware::CPU.intel?" => cfg!(target_arch = "x86_64"),
+        "true" => true,
+        "false" => false,
+        _ => match term
+            .strip_prefix("ENV[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .and_then(|key| parse_quoted(key.trim()))
+        {
+            Some((name, _)) => std::env::var_os(name).is_some_and(|value| !value.is_empty()),
+            None => true,
+        },
     }
+}
 
-    let tail = &rest[1..];
-    let end = tail.find(quote)?;
-    Some(&tail[..end])
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
 }
 
 #[cfg(test)]
@@ -152,6 +377,10 @@ mod tests {
     use super::*;
     use std::io::Write;
 
+    fn names(entries: Vec<BrewfileEntry>) -> Vec<String> {
+        entries.into_iter().map(|entry| entry.name).collect()
+    }
+
     #[test]
     fn load_manifest_parses_entries_ignoring_whitespace_and_comments() {
         let mut file = tempfile::NamedTempFile::new().unwrap();
@@ -161,7 +390,7 @@ mod tests {
         )
         .unwrap();
 
-        let entries = load_manifest(file.path()).unwrap();
+        let entries = names(load_manifest(file.path()).unwrap());
         assert_eq!(entries, vec!["jq", "wget", "git"]);
     }
 
@@ -174,7 +403,7 @@ mod tests {
         )
         .unwrap();
 
-        let entries = load_manifest(file.path()).unwrap();
+        let entries = names(load_manifest(file.path()).unwrap());
         assert_eq!(entries, vec!["jq", "wget", "git"]);
     }
 
@@ -185,10 +414,10 @@ mod tests {
 
         let err = load_manifest(file.path()).unwrap_err();
         match err {
-            zb_core::Error::FileError { message } => {
+            zb_core::Error::ImportError { message } => {
                 assert!(message.contains("did not contain any formulas"))
             }
-            other => panic!("expected file error, got {other:?}"),
+            other => panic!("expected import error, got {other:?}"),
         }
     }
 
@@ -199,10 +428,10 @@ mod tests {
 
         let err = load_manifest(&missing).unwrap_err();
         match err {
-            zb_core::Error::FileError { message } => {
+            zb_core::Error::ImportError { message } => {
                 assert!(message.contains("failed to read manifest"))
             }
-            other => panic!("expected file error, got {other:?}"),
+            other => panic!("expected import error, got {other:?}"),
         }
     }
 
@@ -215,16 +444,19 @@ mod tests {
         )
         .unwrap();
 
-        let entries = load_manifest(file.path()).unwrap();
+        let entries = names(load_manifest(file.path()).unwrap());
         assert_eq!(entries, vec!["wget", "cask:docker-desktop"]);
     }
 
     #[test]
     fn parse_brewfile_entry_handles_brew_directive() {
-        assert_eq!(parse_brewfile_entry("brew \"jq\""), Some("jq".to_string()));
+        assert_eq!(
+            parse_brewfile_entry("brew \"jq\""),
+            Some(BrewfileEntry::new("jq"))
+        );
         assert_eq!(
             parse_brewfile_entry("brew 'wget'"),
-            Some("wget".to_string())
+            Some(BrewfileEntry::new("wget"))
         );
     }
 
@@ -232,7 +464,7 @@ mod tests {
     fn parse_brewfile_entry_handles_cask_directive() {
         assert_eq!(
             parse_brewfile_entry("cask \"docker\""),
-            Some("cask:docker".to_string())
+            Some(BrewfileEntry::new("cask:docker"))
         );
     }
 
@@ -240,4 +472,59 @@ mod tests {
     fn parse_brewfile_entry_skips_tap_directive() {
         assert_eq!(parse_brewfile_entry("tap \"homebrew/core\""), None);
     }
+
+    #[test]
+    fn parse_brewfile_entry_reads_options() {
+        assert_eq!(
+            parse_brewfile_entry(
+                "brew \"vim\", args: [\"with-lua\", \"HEAD\"], link: false, restart_service: true"
+            ),
+            Some(BrewfileEntry {
+                name: "vim".into(),
+                args: vec!["with-lua".into(), "HEAD".into()],
+                link: false,
+            })
+        );
+        assert_eq!(
+            parse_brewfile_entry("brew \"mysql@8.0\", :link => true"),
+            Some(BrewfileEntry::new("mysql@8.0"))
+        );
+        assert_eq!(parse_brewfile_entry("mas \"Xcode\", id: 497799835"), None);
+        assert_eq!(
+            parse_brewfile_entry("vscode \"rust-lang.rust-analyzer\""),
+            None
+        );
+        assert_eq!(parse_brewfile_entry("cask_args appdir: \"~/Apps\""), None);
+    }
+
+    #[test]
+    fn parse_brewfile_applies_conditionals() {
+        let contents = r##"
+            brew "git" # always
+            brew "mas" if OS.mac?
+            brew "xclip" if OS.linux?
+            cask "iterm2" unless OS.linux?
+            if OS.mac?
+              brew "coreutils"
+            elsif OS.linux?
+              brew "strace"
+            else
+              brew "never"
+            end
+            unless ENV["ZB_TEST_UNSET_VARIABLE"]
+              brew "tool", args: ["#not-a-comment"]
+            end
+            brew "git"
+        "##;
+        let mut expected = vec!["git"];
+        if cfg!(target_os = "macos") {
+            expected.extend(["mas", "cask:iterm2", "coreutils"]);
+        } else {
+            expected.extend(["xclip", "strace"]);
+        }
+        expected.push("tool");
+        let entries = parse_brewfile(contents);
+        assert_eq!(names(entries.clone()), expected);
+        assert_eq!(entries.last().unwrap().args, ["#not-a-comment"]);
+    }
 }
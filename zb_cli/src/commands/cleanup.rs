@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use console::style;
+use indicatif::HumanBytes;
+use zb_io::CleanupOptions;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    max_age_days: u64,
+    max_cache_size: Option<u64>,
+    dry_run: bool,
+) -> Result<(), zb_core::Error> {
+    println!(
+        "{} {}cache cleanup...",
+        style("==>").cyan().bold(),
+        if dry_run { "Simulating " } else { "Running " }
+    );
+
+    let options = CleanupOptions {
+        max_age: Some(Duration::from_secs(max_age_days.saturating_mul(86_400))),
+        max_cache_size,
+        dry_run,
+    };
+    let summary = installer.cleanup(&options)?;
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+
+    for blob in &summary.removed_blobs {
+        println!(
+            "    {} {} {} ({})",
+            style("✓").green(),
+            verb,
+            &blob.sha256[..blob.sha256.len().min(12)],
+            HumanBytes(blob.size)
+        );
+    }
+
+    if summary.removed_blobs.is_empty() && summary.pruned_api_entries == 0 {
+        println!("Nothing to clean up.");
+    } else {
+        println!(
+            "{} {} {} downloads ({}) and {} cached API {}",
+            style("==>").cyan().bold(),
+            verb,
+            style(summary.removed_blobs.len()).green().bold(),
+            HumanBytes(summary.freed_bytes()),
+            style(summary.pruned_api_entries).green().bold(),
+            if summary.pruned_api_entries == 1 {
+                "response"
+            } else {
+                "responses"
+            }
+        );
+    }
+
+    println!(
+        "{}",
+        style(format!(
+            "Download cache: {} -> {}",
+            HumanBytes(summary.cache_size_before),
+            HumanBytes(summary.cache_size_after)
+        ))
+        .dim()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use console::style;
+use std::path::PathBuf;
+
+use crate::cli::CompatCommands;
+use crate::ui::StdUi;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    command: Option<CompatCommands>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    match command.unwrap_or(CompatCommands::List) {
+        CompatCommands::List => {
+            let roots = installer.compat_roots()?;
+            if roots.is_empty() {
+                ui.info("No directories are kept in Homebrew's layout.")
+                    .map_err(ui_error)?;
+            }
+            for root in roots {
+                ui.bullet(root.display()).map_err(ui_error)?;
+            }
+            Ok(())
+        }
+        CompatCommands::Enable { path } => {
+            let root = path.unwrap_or_else(homebrew_prefix);
+            let sync = installer.enable_compat_root(&root)?;
+            ui.heading(format!(
+                "Mirroring Homebrew's layout at {}",
+                style(root.display()).bold()
+            ))
+            .map_err(ui_error)?;
+            ui.bullet(format!(
+                "{} {} created",
+                sync.linked,
+                if sync.linked == 1 { "link" } else { "links" }
+            ))
+            .map_err(ui_error)?;
+            for skipped in &sync.skipped {
+                ui.warn(format!(
+                    "left {} alone: it is not zerobrew's",
+                    skipped.display()
+                ))
+                .map_err(ui_error)?;
+            }
+            Ok(())
+        }
+        CompatCommands::Disable { path } => {
+            let root = path.unwrap_or_else(homebrew_prefix);
+            let removed = installer.disable_compat_root(&root)?;
+            ui.heading(format!(
+                "Removed {removed} {} from {}",
+                if removed == 1 { "link" } else { "links" },
+                style(root.display()).bold()
+            ))
+            .map_err(ui_error)?;
+            Ok(())
+        }
+    }
+}
+
+/// Where Homebrew installs by default on this platform.
+fn homebrew_prefix() -> PathBuf {
+    if cfg!(target_os = "linux") {
+        PathBuf::from("/home/linuxbrew/.linuxbrew")
+    } else if cfg!(target_arch = "aarch64") {
+        PathBuf::from("/opt/homebrew")
+    } else {
+        PathBuf::from("/usr/local")
+    }
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
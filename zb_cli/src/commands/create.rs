@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+use console::style;
+use zb_io::{ArtifactKind, FormulaTemplate, SourceBuild};
+
+use crate::ui::StdUi;
+
+pub async fn execute(
+    url: &str,
+    name: Option<String>,
+    version: Option<String>,
+    output: Option<PathBuf>,
+    force: bool,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let to_stdout = output.as_deref() == Some(Path::new("-"));
+    if !to_stdout {
+        ui.heading(format!("Downloading {}...", style(url).bold()))
+            .map_err(ui_error)?;
+    }
+    let template = FormulaTemplate::from_url(url, name, version).await?;
+    let formula = template.render();
+    if to_stdout {
+        return ui.println(formula.trim_end()).map_err(ui_error);
+    }
+
+    let path = output.unwrap_or_else(|| PathBuf::from(format!("{}.rb", template.name)));
+    if path.exists() && !force {
+        return Err(zb_core::Error::InvalidArgument {
+            message: format!(
+                "{} already exists; pass --force to overwrite it",
+                path.display()
+            ),
+        });
+    }
+    std::fs::write(&path, formula).map_err(zb_core::Error::file("failed to write formula"))?;
+
+    ui.heading(format!(
+        "Wrote {} {} to {}",
+        style(&template.name).green().bold(),
+        style(&template.version).dim(),
+        style(path.display()).bold()
+    ))
+    .map_err(ui_error)?;
+    ui.bullet(format!("sha256 {}", template.sha256))
+        .map_err(ui_error)?;
+    ui.bullet(match &template.kind {
+        ArtifactKind::Binary(binaries) => format!(
+            "installs prebuilt {}",
+            binaries
+                .iter()
+                .map(|(_, target)| target.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        ArtifactKind::Source(SourceBuild::Unknown) => {
+            "builds from source; no build system recognized, so fill in install".to_string()
+        }
+        ArtifactKind::Source(build) => format!("builds from source with {}", build.label()),
+    })
+    .map_err(ui_error)?;
+    ui.info(format!(
+        "Fill in desc, then try it with `zb install {}`.",
+        path.display()
+    ))
+    .map_err(ui_error)?;
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
@@ -0,0 +1,478 @@
+//! `zb daemon`: the installer behind a Unix socket, for GUI frontends and
+//! editors that would otherwise run `zb` and reopen the database per call.
+//!
+//! The protocol is JSON-RPC 2.0, one message per line. Methods:
+//!
+//! - `list`: installed kegs, as `zb list --json` prints them.
+//! - `plan` `{"formulas": [...]}`: what installing them would do.
+//! - `install` `{"formulas": [...], "link": true}`: install with dependencies.
+//! - `uninstall` `{"formulas": [...]}`.
+//! - `store.list`: entries of the content-addressable store, with their
+//!   reference counts and the kegs using them, as `zb store ls --json`
+//!   prints them.
+//! - `store.info` `{"key": "..."}`: one store entry, by key or unique prefix.
+//! - `subscribe`: send `progress` notifications for every later install on
+//!   this connection until it closes.
+
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{RwLock, broadcast, mpsc};
+use tokio::task::{LocalSet, spawn_local};
+use zb_io::{InstallPlan, InstallProgress, Installer, ProgressCallback};
+
+use crate::commands::list::keg_json;
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+
+/// Progress events a subscriber may fall behind by before it misses some.
+const PROGRESS_CAPACITY: usize = 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The installer refused or failed the operation; the message says why.
+const INSTALLER_ERROR: i64 = -32000;
+
+/// State shared by all connections. Queries share the installer, while
+/// install and uninstall hold it exclusively, so mutations run one at a time
+/// and queries never see one half done.
+struct Daemon {
+    installer: RwLock<Installer>,
+    progress: broadcast::Sender<InstallProgress>,
+}
+
+impl Daemon {
+    fn new(installer: Installer) -> Self {
+        let (progress, _) = broadcast::channel(PROGRESS_CAPACITY);
+        Self {
+            installer: RwLock::new(installer),
+            progress,
+        }
+    }
+
+    fn progress_callback(&self) -> Arc<ProgressCallback> {
+        let sender = self.progress.clone();
+        Arc::new(Box::new(move |event| {
+            // No subscribers is not an error.
+            let _ = sender.send(event);
+        }))
+    }
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<zb_core::Error> for RpcError {
+    fn from(err: zb_core::Error) -> Self {
+        Self::new(INSTALLER_ERROR, err.to_string())
+    }
+}
+
+pub async fn execute(
+    installer: Installer,
+    socket: &Path,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let listener = bind(socket).await?;
+    ui.heading(format!("Listening on {}", socket.display()))
+        .map_err(ui_error)?;
+    ui.note("Press Ctrl-C to stop.").map_err(ui_error)?;
+
+    let daemon = Rc::new(Daemon::new(installer));
+    // Installer futures are not Send, so connections share one thread.
+    let result = LocalSet::new()
+        .run_until(async {
+            tokio::select! {
+                result = serve(daemon, listener) => result,
+                _ = tokio::signal::ctrl_c() => Ok(()),
+            }
+        })
+        .await;
+    let _ = fs::remove_file(socket);
+    result
+}
+
+/// Listen on `socket`, replacing one left behind by a daemon that did not
+/// exit cleanly but refusing to take over one that still answers.
+async fn bind(socket: &Path) -> Result<UnixListener, zb_core::Error> {
+    if socket.exists() {
+        if UnixStream::connect(socket).await.is_ok() {
+            return Err(zb_core::Error::InvalidArgument {
+                message: format!("a daemon is already listening on {}", socket.display()),
+            });
+        }
+        fs::remove_file(socket).map_err(zb_core::Error::file("failed to remove stale socket"))?;
+    }
+    let dir = socket.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).map_err(zb_core::Error::file("failed to create socket directory"))?;
+
+    // Bind inside a directory only we can enter and move the socket into
+    // place once it is restricted, so no one can connect in between.
+    let staging = dir.join(format!(".zbd-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .map_err(zb_core::Error::file(
+            "failed to create socket staging directory",
+        ))?;
+    let staged = staging.join("s");
+    let bound = UnixListener::bind(&staged)
+        .map_err(zb_core::Error::file("failed to bind socket"))
+        .and_then(|listener| {
+            fs::set_permissions(&staged, fs::Permissions::from_mode(0o600)).map_err(
+                zb_core::Error::file("failed to restrict socket permissions"),
+            )?;
+            fs::rename(&staged, socket)
+                .map_err(zb_core::Error::file("failed to move socket into place"))?;
+            Ok(listener)
+        });
+    let _ = fs::remove_dir_all(&staging);
+    bound
+}
+
+async fn serve(daemon: Rc<Daemon>, listener: UnixListener) -> Result<(), zb_core::Error> {
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .map_err(zb_core::Error::file("failed to accept connection"))?;
+        spawn_local(handle_connection(daemon.clone(), stream));
+    }
+}
+
+/// Answer requests from one client. Each request runs on its own task, so
+/// a query can be answered while an install from the same client runs.
+async fn handle_connection(daemon: Rc<Daemon>, stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+    let (outgoing, mut queue) = mpsc::unbounded_channel::<Value>();
+    let writer_task = spawn_local(async move {
+        while let Some(message) = queue.recv().await {
+            let line = format!("{message}\n");
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let daemon = daemon.clone();
+        let outgoing = outgoing.clone();
+        spawn_local(async move {
+            if let Some(response) = handle_line(&daemon, &line, &outgoing).await {
+                let _ = outgoing.send(response);
+            }
+        });
+    }
+
+    // Dropping the queue ends this client's subscriptions too.
+    writer_task.abort();
+}
+
+/// The response to one request line, or `None` for a notification, which
+/// JSON-RPC never answers.
+async fn handle_line(
+    daemon: &Daemon,
+    line: &str,
+    outgoing: &mpsc::UnboundedSender<Value>,
+) -> Option<Value> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            return Some(error_response(
+                Value::Null,
+                RpcError::new(PARSE_ERROR, format!("invalid JSON: {e}")),
+            ));
+        }
+    };
+    let id = request.get("id").cloned();
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return Some(error_response(
+            id.unwrap_or(Value::Null),
+            RpcError::new(INVALID_REQUEST, "request has no method"),
+        ));
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    let result = dispatch(daemon, method, &params, outgoing).await;
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => error_response(id, err),
+    })
+}
+
+async fn dispatch(
+    daemon: &Daemon,
+    method: &str,
+    params: &Value,
+    outgoing: &mpsc::UnboundedSender<Value>,
+) -> Result<Value, RpcError> {
+    match method {
+        "list" => {
+            let installer = daemon.installer.read().await;
+            let kegs = installer.list_installed()?;
+            Ok(kegs.iter().map(keg_json).collect())
+        }
+        "plan" => {
+            let names = formulas(params)?;
+            let installer = daemon.installer.read().await;
+            Ok(plan_json(&installer.plan(&names).await?))
+        }
+        "install" => {
+            let names = formulas(params)?;
+            let link = params.get("link").and_then(Value::as_bool).unwrap_or(true);
+            let mut installer = daemon.installer.write().await;
+            let plan = installer.plan(&names).await?;
+            let result = installer
+                .execute_with_progress(plan, link, Some(daemon.progress_callback()))
+                .await?;
+            Ok(json!({ "installed": result.installed }))
+        }
+        "uninstall" => {
+            let names = formulas(params)?;
+            let mut installer = daemon.installer.write().await;
+            for name in &names {
+                installer.uninstall(name)?;
+            }
+            Ok(json!({ "uninstalled": names }))
+        }
+        "store.list" => {
+            let installer = daemon.installer.read().await;
+            Ok(json!(installer.store_entries()?))
+        }
+        "store.info" => {
+            let key = params
+                .get("key")
+                .and_then(Value::as_str)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected a `key` string"))?;
+            let installer = daemon.installer.read().await;
+            Ok(json!(installer.store_entry(key)?))
+        }
+        "subscribe" => {
+            let mut events = daemon.progress.subscribe();
+            let outgoing = outgoing.clone();
+            spawn_local(async move {
+                loop {
+                    let event = tokio::select! {
+                        event = events.recv() => event,
+                        _ = outgoing.closed() => break,
+                    };
+                    match event {
+                        Ok(event) => {
+                            let notification = json!({
+                                "jsonrpc": "2.0",
+                                "method": "progress",
+                                "params": event,
+                            });
+                            if outgoing.send(notification).is_err() {
+                                break;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+            Ok(Value::Bool(true))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method '{method}'"),
+        )),
+    }
+}
+
+/// The `formulas` parameter: a non-empty list of formula names.
+fn formulas(params: &Value) -> Result<Vec<String>, RpcError> {
+    let invalid = || RpcError::new(INVALID_PARAMS, "expected a non-empty `formulas` list");
+    let names = params
+        .get("formulas")
+        .and_then(Value::as_array)
+        .filter(|names| !names.is_empty())
+        .ok_or_else(invalid)?;
+    names
+        .iter()
+        .map(|name| {
+            let name = name.as_str().ok_or_else(invalid)?;
+            normalize_formula_name(name).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+        })
+        .collect()
+}
+
+fn plan_json(plan: &InstallPlan) -> Value {
+    let sizes = plan.sizes();
+    let items: Vec<Value> = plan
+        .items
+        .iter()
+        .map(|item| {
+            json!({
+                "name": item.install_name,
+                "version": item.formula.effective_version(),
+                "method": item.method.kind(),
+                "on_request": item.installed_on_request,
+                "cached": item.cached,
+                "download_size": item.download_size,
+                "install_size": item.install_size,
+            })
+        })
+        .collect();
+    json!({
+        "items": items,
+        "download_bytes": sizes.download_bytes,
+        "install_bytes": sizes.install_bytes,
+    })
+}
+
+fn error_response(id: Value, err: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": err.code, "message": err.message },
+    })
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::io::Lines;
+    use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+
+    use super::*;
+
+    async fn call(
+        writer: &mut OwnedWriteHalf,
+        lines: &mut Lines<BufReader<OwnedReadHalf>>,
+        request: &str,
+    ) -> Value {
+        writer
+            .write_all(format!("{request}\n").as_bytes())
+            .await
+            .unwrap();
+        serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn socket_is_private_from_the_start() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("run/zb.sock");
+        let _listener = bind(&socket).await.unwrap();
+
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let leftovers: Vec<_> = fs::read_dir(tmp.path().join("run")).unwrap().collect();
+        assert_eq!(leftovers.len(), 1, "staging directory left behind");
+        UnixStream::connect(&socket).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn answers_json_rpc_over_the_socket() {
+        let tmp = TempDir::new().unwrap();
+        let installer =
+            zb_io::create_installer(&tmp.path().join("root"), &tmp.path().join("prefix"), 4)
+                .unwrap();
+        let socket = tmp.path().join("zb.sock");
+        let listener = bind(&socket).await.unwrap();
+        let daemon = Rc::new(Daemon::new(installer));
+
+        LocalSet::new()
+            .run_until(async {
+                spawn_local(serve(daemon.clone(), listener));
+                let (reader, mut writer) = UnixStream::connect(&socket).await.unwrap().into_split();
+                let mut lines = BufReader::new(reader).lines();
+
+                let response = call(
+                    &mut writer,
+                    &mut lines,
+                    r#"{"jsonrpc":"2.0","id":1,"method":"list"}"#,
+                )
+                .await;
+                assert_eq!(response["id"], 1);
+                assert_eq!(response["result"], json!([]));
+
+                let response = call(
+                    &mut writer,
+                    &mut lines,
+                    r#"{"jsonrpc":"2.0","id":1,"method":"store.list"}"#,
+                )
+                .await;
+                assert_eq!(response["result"], json!([]));
+
+                let response = call(
+                    &mut writer,
+                    &mut lines,
+                    r#"{"jsonrpc":"2.0","id":2,"method":"frobnicate"}"#,
+                )
+                .await;
+                assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+                let response = call(&mut writer, &mut lines, "not json").await;
+                assert_eq!(response["id"], Value::Null);
+                assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+                let response = call(
+                    &mut writer,
+                    &mut lines,
+                    r#"{"jsonrpc":"2.0","id":3,"method":"uninstall","params":{"formulas":[]}}"#,
+                )
+                .await;
+                assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+                let response = call(
+                    &mut writer,
+                    &mut lines,
+                    r#"{"jsonrpc":"2.0","id":4,"method":"uninstall","params":{"formulas":["jq"]}}"#,
+                )
+                .await;
+                assert_eq!(response["error"]["code"], INSTALLER_ERROR);
+
+                let response = call(
+                    &mut writer,
+                    &mut lines,
+                    r#"{"jsonrpc":"2.0","id":5,"method":"subscribe"}"#,
+                )
+                .await;
+                assert_eq!(response["result"], true);
+                (daemon.progress_callback())(InstallProgress::InstallCompleted {
+                    name: "jq".to_string(),
+                });
+                let notification: Value =
+                    serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+                assert_eq!(notification["method"], "progress");
+                assert_eq!(notification["params"]["event"], "install_completed");
+                assert_eq!(notification["params"]["name"], "jq");
+
+                assert!(bind(&socket).await.is_err());
+            })
+            .await;
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use console::style;
+
+use crate::commands::install::render_tree;
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Tree,
+    Dot,
+    Mermaid,
+}
+
+/// Print the dependency graph of `formulas`. Installed packages are graphed
+/// from the dependencies recorded when they were installed, so this works
+/// offline; anything else is resolved as if it were about to be installed.
+pub async fn execute(
+    installer: &zb_io::Installer,
+    formulas: Vec<String>,
+    format: GraphFormat,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let names = formulas
+        .iter()
+        .map(|formula| normalize_formula_name(formula))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (graph, versions): (_, HashMap<String, String>) = if names
+        .iter()
+        .all(|name| installer.get_installed(name).is_some())
+    {
+        let versions = installer
+            .list_installed()?
+            .into_iter()
+            .map(|keg| (keg.name, keg.version))
+            .collect();
+        (installer.installed_dependency_graph(&names)?, versions)
+    } else {
+        let plan = installer.plan_with_options(&names, false).await?;
+        let versions = plan
+            .items
+            .iter()
+            .map(|item| {
+                (
+                    item.install_name.clone(),
+                    item.formula.versions.stable.clone(),
+                )
+            })
+            .collect();
+        (plan.graph, versions)
+    };
+
+    match format {
+        GraphFormat::Dot => ui.println(graph.to_dot().trim_end()).map_err(ui_error),
+        GraphFormat::Mermaid => ui.println(graph.to_mermaid().trim_end()).map_err(ui_error),
+        GraphFormat::Tree => {
+            for line in render_tree(&graph, |name| match versions.get(name) {
+                Some(version) => format!("{name} {}", style(version).dim()),
+                None => name.to_string(),
+            }) {
+                ui.println(line).map_err(ui_error)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
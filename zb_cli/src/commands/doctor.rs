@@ -1,4 +1,5 @@
 use console::style;
+use zb_io::DriftKind;
 
 use crate::ui::StdUi;
 
@@ -10,6 +11,7 @@ pub fn execute(
     ui.heading("Running diagnostics...").map_err(ui_error)?;
 
     let report = installer.doctor()?;
+    report_shadowed_commands(&report.shadowed_commands, ui)?;
 
     if report.is_healthy() {
         ui.println(format!("    {} No issues found", style("✓").green()))
@@ -65,6 +67,20 @@ pub fn execute(
             .map_err(ui_error)?;
     }
 
+    for drift in &report.drifted_links {
+        let what = match drift.kind {
+            DriftKind::Missing => "removed",
+            DriftKind::Retargeted => "points elsewhere",
+            DriftKind::Replaced => "replaced by another file",
+        };
+        ui.warn(format!(
+            "Link of {} changed: {} ({what})",
+            drift.name,
+            drift.link.display()
+        ))
+        .map_err(ui_error)?;
+    }
+
     if report.stale_keg_file_records > 0 {
         ui.warn(format!(
             "{} stale keg_files records (referencing uninstalled kegs)",
@@ -73,12 +89,41 @@ pub fn execute(
         .map_err(ui_error)?;
     }
 
+    if !report.quarantined_blobs.is_empty() {
+        ui.warn(format!(
+            "{} quarantined {} in the download cache (failed to unpack)",
+            report.quarantined_blobs.len(),
+            pluralize("blob", report.quarantined_blobs.len())
+        ))
+        .map_err(ui_error)?;
+    }
+
+    for download in &report.corrupt_downloads {
+        ui.warn(format!(
+            "Corrupt downloads from {} ({} times); the mirror or network may be serving bad data",
+            download.url, download.failures
+        ))
+        .map_err(ui_error)?;
+    }
+
+    if let Some(newer) = &report.newer_database {
+        ui.warn(format!(
+            "Database schema version {} is newer than this zb supports ({}); upgrade zerobrew",
+            newer.schema_version, newer.supported_version
+        ))
+        .map_err(ui_error)?;
+    }
+
     let issue_count = report.orphaned_cellar_kegs.len()
         + report.missing_cellar_kegs.len()
         + report.orphaned_store_entries.len()
         + report.stale_store_refs.len()
         + report.broken_symlinks.len()
-        + usize::from(report.stale_keg_file_records > 0);
+        + report.drifted_links.len()
+        + usize::from(report.stale_keg_file_records > 0)
+        + usize::from(!report.quarantined_blobs.is_empty())
+        + report.corrupt_downloads.len()
+        + usize::from(report.newer_database.is_some());
 
     ui.blank_line().map_err(ui_error)?;
     ui.heading(format!(
@@ -142,6 +187,28 @@ pub fn execute(
         ))
         .map_err(ui_error)?;
     }
+    if summary.restored_links > 0 {
+        ui.bullet(format!(
+            "Restored {} {}",
+            summary.restored_links,
+            pluralize("link", summary.restored_links)
+        ))
+        .map_err(ui_error)?;
+    }
+    let mut replaced: Vec<&str> = report
+        .drifted_links
+        .iter()
+        .filter(|d| !d.is_restorable())
+        .map(|d| d.name.as_str())
+        .collect();
+    replaced.dedup();
+    for name in replaced {
+        ui.bullet(format!(
+            "Left files replacing links of {name} alone; run {} to put the links back",
+            style(format!("zb relink {name} --force")).bold()
+        ))
+        .map_err(ui_error)?;
+    }
     if summary.pruned_keg_file_records > 0 {
         ui.bullet(format!(
             "Pruned {} stale keg_files {}",
@@ -151,6 +218,23 @@ pub fn execute(
         .map_err(ui_error)?;
     }
 
+    if summary.removed_quarantined_blobs > 0 {
+        ui.bullet(format!(
+            "Removed {} quarantined {}",
+            summary.removed_quarantined_blobs,
+            pluralize("blob", summary.removed_quarantined_blobs)
+        ))
+        .map_err(ui_error)?;
+    }
+    if summary.cleared_corrupt_downloads > 0 {
+        ui.bullet(format!(
+            "Forgot {} corrupt {}",
+            summary.cleared_corrupt_downloads,
+            pluralize("download", summary.cleared_corrupt_downloads)
+        ))
+        .map_err(ui_error)?;
+    }
+
     ui.blank_line().map_err(ui_error)?;
     ui.println(format!(
         "    {} Applied {} {}",
@@ -163,6 +247,40 @@ pub fn execute(
     Ok(())
 }
 
+/// Note which copy of each command runs, zerobrew's or another one on PATH.
+pub fn report_shadowed_commands(
+    shadowed: &[zb_io::ShadowedCommand],
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    for command in shadowed {
+        let other = match command.other_source() {
+            Some(source) => format!("{} ({source})", command.other_path.display()),
+            None => command.other_path.display().to_string(),
+        };
+        let message = if command.prefix_wins {
+            format!(
+                "{} shadows {other}; {} runs",
+                style(&command.command).bold(),
+                command.winner().display()
+            )
+        } else {
+            format!(
+                "{} is shadowed by {other}, which runs instead of {}; \
+                 move {} earlier on PATH to prefer zerobrew's",
+                style(&command.command).bold(),
+                command.prefix_path.display(),
+                command
+                    .prefix_path
+                    .parent()
+                    .unwrap_or(&command.prefix_path)
+                    .display()
+            )
+        };
+        ui.note(message).map_err(ui_error)?;
+    }
+    Ok(())
+}
+
 fn pluralize(word: &str, count: usize) -> &str {
     if count == 1 {
         word
@@ -173,6 +291,9 @@ fn pluralize(word: &str, count: usize) -> &str {
             "ref" => "refs",
             "entry" => "entries",
             "symlink" => "symlinks",
+            "link" => "links",
+            "blob" => "blobs",
+            "download" => "downloads",
             "fix" => "fixes",
             "issue" => "issues",
             _ => word,
@@ -181,7 +302,7 @@ fn pluralize(word: &str, count: usize) -> &str {
 }
 
 fn ui_error(err: std::io::Error) -> zb_core::Error {
-    zb_core::Error::StoreCorruption {
+    zb_core::Error::FileError {
         message: format!("failed to write CLI output: {err}"),
     }
 }
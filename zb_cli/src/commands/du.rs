@@ -0,0 +1,81 @@
+use console::style;
+use indicatif::HumanBytes;
+
+use crate::cli::ListSort;
+use crate::commands::list::sort_kegs;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    sort: ListSort,
+    reverse: bool,
+    json: bool,
+    recalculate: bool,
+) -> Result<(), zb_core::Error> {
+    let mut usage = installer.disk_usage(recalculate)?;
+    sort_kegs(&mut usage.kegs, sort, reverse);
+
+    if json {
+        let kegs: Vec<serde_json::Value> = usage
+            .kegs
+            .iter()
+            .map(|keg| {
+                serde_json::json!({
+                    "name": keg.name,
+                    "version": keg.version,
+                    "size_bytes": keg.size_bytes,
+                    "shared_bytes": keg.shared_bytes,
+                })
+            })
+            .collect();
+        let output = serde_json::json!({
+            "kegs": kegs,
+            "keg_bytes": usage.keg_bytes(),
+            "store_bytes": usage.store_bytes,
+            "cache_bytes": usage.cache_bytes,
+            "shared_bytes": usage.shared_bytes,
+            "total_bytes": usage.total_bytes(),
+        });
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+        return Ok(());
+    }
+
+    let name_width = usage.kegs.iter().map(|k| k.name.len()).max().unwrap_or(0);
+    for keg in &usage.kegs {
+        let size = keg
+            .size_bytes
+            .map(|s| HumanBytes(s).to_string())
+            .unwrap_or_else(|| "?".to_string());
+        println!(
+            "{:<name_width$}  {:>10}  {}",
+            style(&keg.name).bold(),
+            size,
+            style(&keg.version).dim()
+        );
+    }
+    if !usage.kegs.is_empty() {
+        println!();
+    }
+
+    let rows = [
+        (
+            format!("Kegs ({})", usage.kegs.len()),
+            HumanBytes(usage.keg_bytes()),
+        ),
+        ("Store".to_string(), HumanBytes(usage.store_bytes)),
+        ("Download cache".to_string(), HumanBytes(usage.cache_bytes)),
+        (
+            "Shared by hardlinks and clones".to_string(),
+            HumanBytes(usage.shared_bytes),
+        ),
+    ];
+    let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    for (label, bytes) in rows {
+        println!("{label:<label_width$}  {:>10}", bytes.to_string());
+    }
+    println!(
+        "{} {} on disk",
+        style("==>").cyan().bold(),
+        HumanBytes(usage.total_bytes())
+    );
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use crate::commands::query::print_exports;
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    formulas: Vec<String>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let names = formulas
+        .iter()
+        .map(|formula| normalize_formula_name(formula))
+        .collect::<Result<Vec<_>, _>>()?;
+    print_exports(&installer.build_env(&names)?, ui)
+}
@@ -0,0 +1,51 @@
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use crate::utils::normalize_formula_name;
+
+/// Replace this process with `command`, run in the environment of the
+/// installed `formulas` and the prefix.
+pub fn execute(
+    installer: &zb_io::Installer,
+    formulas: Vec<String>,
+    command: Vec<String>,
+) -> Result<(), zb_core::Error> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(zb_core::Error::InvalidArgument {
+            message: "no command given; usage: zb exec [--with FORMULA] -- <command>".to_string(),
+        });
+    };
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    exec_in_env(installer, formulas, cmd, program)
+}
+
+/// Replace this process with `$SHELL`, run in the environment of the
+/// installed `formulas` and the prefix.
+pub fn shell(installer: &zb_io::Installer, formulas: Vec<String>) -> Result<(), zb_core::Error> {
+    let shell = std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "/bin/sh".to_string());
+    let cmd = Command::new(&shell);
+    exec_in_env(installer, formulas, cmd, &shell)
+}
+
+fn exec_in_env(
+    installer: &zb_io::Installer,
+    formulas: Vec<String>,
+    mut cmd: Command,
+    program: &str,
+) -> Result<(), zb_core::Error> {
+    let names = formulas
+        .iter()
+        .map(|formula| normalize_formula_name(formula))
+        .collect::<Result<Vec<_>, _>>()?;
+    cmd.envs(installer.exec_env(&names)?);
+
+    let err = cmd.exec();
+
+    Err(zb_core::Error::ExecutionError {
+        message: format!("failed to execute '{program}': {err}"),
+    })
+}
@@ -0,0 +1,117 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+use zb_io::{Database, ExportedPackage, ServiceManager};
+
+use crate::cli::ExportFormat;
+
+/// The document written for `--format json` and `--format toml`.
+#[derive(Serialize)]
+struct Export<'a> {
+    packages: &'a [ExportedPackage],
+}
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    root: &Path,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> Result<(), zb_core::Error> {
+    let services = ServiceManager::new(Database::open(&root.join("db/zb.sqlite3"))?)?.list()?;
+    let packages = installer.export_packages(&services)?;
+    let rendered = render(&packages, format)?;
+    match output {
+        Some(path) => std::fs::write(path, rendered).map_err(|e| zb_core::Error::FileError {
+            message: format!("failed to write {}: {e}", path.display()),
+        }),
+        None => std::io::stdout()
+            .write_all(rendered.as_bytes())
+            .map_err(|e| zb_core::Error::FileError {
+                message: format!("failed to write CLI output: {e}"),
+            }),
+    }
+}
+
+fn render(packages: &[ExportedPackage], format: ExportFormat) -> Result<String, zb_core::Error> {
+    let document = Export { packages };
+    match format {
+        ExportFormat::Brewfile => Ok(brewfile(packages)),
+        ExportFormat::Json => serde_json::to_string_pretty(&document)
+            .map(|json| json + "\n")
+            .map_err(zb_core::Error::file("failed to encode JSON export")),
+        ExportFormat::Toml => {
+            toml::to_string(&document).map_err(zb_core::Error::file("failed to encode TOML export"))
+        }
+    }
+}
+
+/// `tap` lines for every tap beyond homebrew/core, then a `brew` line per
+/// package.
+fn brewfile(packages: &[ExportedPackage]) -> String {
+    let taps: BTreeSet<&str> = packages
+        .iter()
+        .map(|p| p.tap.as_str())
+        .filter(|tap| *tap != "homebrew/core")
+        .collect();
+    let mut content = String::new();
+    for tap in taps {
+        content.push_str(&format!("tap \"{tap}\"\n"));
+    }
+    for package in packages {
+        content.push_str(&format!("brew \"{}\"\n", package.name));
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packages() -> Vec<ExportedPackage> {
+        vec![
+            ExportedPackage {
+                name: "jq".into(),
+                version: "1.7.1".into(),
+                tap: "homebrew/core".into(),
+                linked: true,
+                installed_on_request: true,
+                arch: None,
+                service: None,
+            },
+            ExportedPackage {
+                name: "acme/tools/widget".into(),
+                version: "2.0".into(),
+                tap: "acme/tools".into(),
+                linked: false,
+                installed_on_request: false,
+                arch: Some("x86_64".into()),
+                service: Some("running".into()),
+            },
+        ]
+    }
+
+    #[test]
+    fn machine_formats_carry_every_field() {
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&packages(), ExportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["packages"][0]["name"], "jq");
+        assert_eq!(json["packages"][0]["linked"], true);
+        assert!(json["packages"][0].get("service").is_none());
+        assert_eq!(json["packages"][1]["service"], "running");
+        assert_eq!(json["packages"][1]["arch"], "x86_64");
+
+        let toml = render(&packages(), ExportFormat::Toml).unwrap();
+        assert!(toml.contains("[[packages]]"), "{toml}");
+        assert!(toml.contains("tap = \"acme/tools\""), "{toml}");
+        assert!(toml.contains("installed_on_request = false"), "{toml}");
+    }
+
+    #[test]
+    fn brewfiles_tap_before_brewing() {
+        assert_eq!(
+            render(&packages(), ExportFormat::Brewfile).unwrap(),
+            "tap \"acme/tools\"\nbrew \"jq\"\nbrew \"acme/tools/widget\"\n"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use zb_io::ImageLayout;
+
+use crate::cli::ImageFormat;
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    formula: &str,
+    output: &Path,
+    format: ImageFormat,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(formula)?;
+    let layout = match format {
+        ImageFormat::Dir => ImageLayout::Directory,
+        ImageFormat::Layer => ImageLayout::Layer,
+    };
+    let image = installer.export_image(&name, output, layout)?;
+
+    ui.heading(format!("Exported {name} to {}", output.display()))
+        .map_err(ui_error)?;
+    for keg in &image.kegs {
+        ui.bullet(format!("{} {}", keg.name, keg.version))
+            .map_err(ui_error)?;
+    }
+    if let Some(digest) = &image.digest {
+        ui.note(format!("Layer digest: {digest}"))
+            .map_err(ui_error)?;
+    }
+
+    ui.blank_line().map_err(ui_error)?;
+    ui.println("To use it in a Dockerfile:").map_err(ui_error)?;
+    for line in dockerfile_lines(output, format, &image.env) {
+        ui.println(format!("    {line}")).map_err(ui_error)?;
+    }
+    ui.println(format!(
+        "or source {} inside the container.",
+        image.env_script.display()
+    ))
+    .map_err(ui_error)
+}
+
+/// Lines adding the export to an image and setting its environment.
+/// Docker unpacks a local tarball given to `ADD`.
+fn dockerfile_lines(output: &Path, format: ImageFormat, env: &[(&str, String)]) -> Vec<String> {
+    let mut lines = vec![match format {
+        ImageFormat::Dir => format!("COPY {}/ /", output.display()),
+        ImageFormat::Layer => format!("ADD {} /", output.display()),
+    }];
+    lines.extend(
+        env.iter()
+            .map(|(var, value)| format!("ENV {var}=\"{value}\"")),
+    );
+    lines
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dockerfile_lines_add_the_export_and_its_environment() {
+        let env = [(
+            "PATH",
+            "/opt/zerobrew/prefix/opt/jq/bin:/usr/bin".to_string(),
+        )];
+        assert_eq!(
+            dockerfile_lines(Path::new("jq.tar.gz"), ImageFormat::Layer, &env),
+            [
+                "ADD jq.tar.gz /",
+                "ENV PATH=\"/opt/zerobrew/prefix/opt/jq/bin:/usr/bin\"",
+            ]
+        );
+        assert_eq!(
+            dockerfile_lines(Path::new("rootfs"), ImageFormat::Dir, &[]),
+            ["COPY rootfs/ /"]
+        );
+    }
+}
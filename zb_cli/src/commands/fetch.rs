@@ -0,0 +1,75 @@
+use console::style;
+use std::time::Instant;
+
+use crate::ui::StdUi;
+use crate::utils::{normalize_formula_name, suggest_missing_formula_matches};
+
+pub async fn execute(
+    installer: &zb_io::Installer,
+    formulas: Vec<String>,
+    bottle_tag: Option<&str>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let start = Instant::now();
+    let names = formulas
+        .iter()
+        .map(|f| normalize_formula_name(f))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(cask) = names.iter().find(|n| n.starts_with("cask:")) {
+        return Err(zb_core::Error::InvalidArgument {
+            message: format!("'{cask}' is a cask; only formula bottles can be fetched"),
+        });
+    }
+
+    let plan = match installer.plan(&names).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            suggest_missing_formula_matches(installer, &e).await;
+            return Err(e);
+        }
+    };
+
+    let target = bottle_tag.map_or_else(String::new, |tag| format!(" for {}", style(tag).bold()));
+    ui.heading(format!("Fetching {} bottles{target}...", plan.items.len()))
+        .map_err(ui_error)?;
+
+    let blobs = installer.fetch(&plan, None).await?;
+    for item in &plan.items {
+        match &item.method {
+            zb_core::InstallMethod::Bottle(bottle) => ui.bullet(format!(
+                "{} {} {}",
+                style(&item.formula.name).green(),
+                style(&item.formula.versions.stable).dim(),
+                style(&bottle.tag).dim()
+            )),
+            zb_core::InstallMethod::Binary(binary) => ui.bullet(format!(
+                "{} {} {}",
+                style(&item.formula.name).green(),
+                style(&item.formula.versions.stable).dim(),
+                style(format!("{} binary", binary.platform)).dim()
+            )),
+            zb_core::InstallMethod::Source(_) => ui.bullet(format!(
+                "{} {}",
+                style(&item.formula.name).yellow(),
+                style("(no bottle, skipped)").dim()
+            )),
+        }
+        .map_err(ui_error)?;
+    }
+
+    ui.blank_line().map_err(ui_error)?;
+    ui.heading(format!(
+        "Fetched {} bottles in {:.2}s",
+        style(blobs.len()).green().bold(),
+        start.elapsed().as_secs_f64()
+    ))
+    .map_err(ui_error)?;
+
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
@@ -1,24 +1,61 @@
 use console::style;
+use indicatif::HumanBytes;
 
-pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    options: &zb_io::GcOptions,
+    dedupe: bool,
+) -> Result<(), zb_core::Error> {
     println!(
         "{} Running garbage collection...",
         style("==>").cyan().bold()
     );
-    let removed = installer.gc()?;
+    let summary = installer.gc(options)?;
 
-    if removed.is_empty() {
+    if summary.removed.is_empty() {
         println!("No unreferenced store entries to remove.");
     } else {
-        for key in &removed {
-            println!("    {} Removed {}", style("✓").green(), &key[..12]);
+        for key in &summary.removed {
+            println!(
+                "    {} Removed {}",
+                style("✓").green(),
+                &key[..key.len().min(12)]
+            );
         }
         println!(
-            "{} Removed {} store entries",
+            "{} Removed {} store entries, reclaimed {}",
             style("==>").cyan().bold(),
-            style(removed.len()).green().bold()
+            style(summary.removed.len()).green().bold(),
+            style(HumanBytes(summary.reclaimed_bytes)).green().bold()
         );
     }
+    if summary.kept > 0 {
+        println!(
+            "Kept {} unused store entries for reinstalls (see --max-age and --keep-versions).",
+            summary.kept
+        );
+    }
+
+    if dedupe {
+        println!(
+            "{} Deduplicating store entries...",
+            style("==>").cyan().bold()
+        );
+        let summary = installer.dedupe_store()?;
+        if summary.files_linked == 0 {
+            println!(
+                "No duplicate files found across {} scanned.",
+                summary.files_scanned
+            );
+        } else {
+            println!(
+                "{} Linked {} duplicate files, saved {}",
+                style("==>").cyan().bold(),
+                style(summary.files_linked).green().bold(),
+                style(HumanBytes(summary.bytes_saved)).green().bold()
+            );
+        }
+    }
 
     Ok(())
 }
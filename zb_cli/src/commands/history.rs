@@ -0,0 +1,106 @@
+use chrono::{DateTime, Local};
+use console::style;
+use zb_io::{OperationRecord, PackageChange};
+
+use crate::cli::HistoryCommands;
+use crate::ui::StdUi;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    command: Option<HistoryCommands>,
+    json: bool,
+    limit: usize,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    if let Some(HistoryCommands::Undo { id }) = command {
+        let changes = installer.undo(id)?;
+        ui.heading(format!("Undid operation #{id}"))
+            .map_err(ui_error)?;
+        for change in &changes {
+            ui.bullet(describe_change(change)).map_err(ui_error)?;
+        }
+        return Ok(());
+    }
+
+    let operations = installer.history(limit)?;
+    if json {
+        let output =
+            serde_json::to_string_pretty(&operations).map_err(|e| zb_core::Error::FileError {
+                message: format!("failed to encode history: {e}"),
+            })?;
+        return ui.println(output).map_err(ui_error);
+    }
+
+    if operations.is_empty() {
+        return ui.println("No operations recorded.").map_err(ui_error);
+    }
+    for operation in &operations {
+        ui.println(render(operation)).map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn render(operation: &OperationRecord) -> String {
+    let when = DateTime::from_timestamp(operation.recorded_at, 0)
+        .map(|dt| {
+            dt.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "unknown time".to_string());
+    let mut line = format!(
+        "{:>5}  {}  {}",
+        format!("#{}", operation.id),
+        style(when).dim(),
+        style(format!("{:<9}", operation.kind)).bold()
+    );
+    let summary: Vec<String> = operation.changes.iter().map(describe_change).collect();
+    if !summary.is_empty() {
+        line.push_str(&format!("  {}", summary.join(", ")));
+    }
+    if let Some(detail) = &operation.detail {
+        line.push_str(&format!("  {}", style(detail).dim()));
+    }
+    if let Some(error) = &operation.error {
+        line.push_str(&format!("  {}", style(format!("failed: {error}")).red()));
+    }
+    line
+}
+
+fn describe_change(change: &PackageChange) -> String {
+    match (&change.from, &change.to) {
+        (None, Some(to)) => format!("+{} {to}", change.name),
+        (Some(from), None) => format!("-{} {from}", change.name),
+        (Some(from), Some(to)) => format!("{} {from} -> {to}", change.name),
+        (None, None) => change.name.clone(),
+    }
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(from: Option<&str>, to: Option<&str>) -> PackageChange {
+        PackageChange {
+            name: "jq".to_string(),
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn describes_each_kind_of_change() {
+        assert_eq!(describe_change(&change(None, Some("1.7"))), "+jq 1.7");
+        assert_eq!(describe_change(&change(Some("1.7"), None)), "-jq 1.7");
+        assert_eq!(
+            describe_change(&change(Some("1.6"), Some("1.7"))),
+            "jq 1.6 -> 1.7"
+        );
+    }
+}
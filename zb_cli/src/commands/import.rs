@@ -0,0 +1,227 @@
+use console::style;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+
+use zb_io::{Database, ServiceManager};
+
+use super::bundle::{BrewfileEntry, parse_brewfile};
+use super::install;
+use crate::cli::ImportFormat;
+use crate::ui::StdUi;
+
+/// Brewfile directives that mark a file as a Brewfile rather than a list.
+const BREWFILE_DIRECTIVES: [&str; 3] = ["brew ", "cask ", "tap "];
+
+pub struct ImportOptions {
+    pub no_link: bool,
+    /// Uninstall packages the file does not list or need afterwards.
+    pub cleanup: bool,
+    pub dry_run: bool,
+}
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    root: &Path,
+    source: &Path,
+    format: ImportFormat,
+    options: ImportOptions,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let (name, contents) = read_source(source)?;
+    let format = match format {
+        ImportFormat::Auto => detect_format(source, &contents),
+        format => format,
+    };
+    let entries = parse_packages(&contents, format);
+    if entries.is_empty() {
+        return Err(zb_core::Error::ImportError {
+            message: format!("{name} did not contain any formulas"),
+        });
+    }
+
+    ui.heading(format!(
+        "Importing {} {} from {name}...",
+        style(entries.len()).green().bold(),
+        if entries.len() == 1 {
+            "package"
+        } else {
+            "packages"
+        }
+    ))
+    .map_err(ui_error)?;
+
+    let listed: Vec<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+    let (linked, unlinked): (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| entry.link);
+    for (entries, no_link) in [(linked, options.no_link), (unlinked, true)] {
+        if !entries.is_empty() {
+            let formulas = entries.into_iter().map(|entry| entry.name).collect();
+            let install_options = install::InstallOptions {
+                no_link,
+                dry_run: options.dry_run,
+                ..Default::default()
+            };
+            install::execute(installer, formulas, install_options, ui).await?;
+        }
+    }
+
+    if options.cleanup {
+        cleanup(installer, root, &name, &listed, options.dry_run, ui)?;
+    }
+    Ok(())
+}
+
+/// Uninstall every package that is neither in `listed` nor needed by one
+/// that is, stopping its service first, like `brew bundle --cleanup`.
+fn cleanup(
+    installer: &mut zb_io::Installer,
+    root: &Path,
+    source: &str,
+    listed: &[String],
+    dry_run: bool,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let unneeded = installer.unneeded_kegs(listed)?;
+    if unneeded.is_empty() {
+        ui.info(format!("Nothing to clean up; every package is in {source}"))
+            .map_err(ui_error)?;
+        return Ok(());
+    }
+
+    let services = ServiceManager::new(Database::open(&root.join("db/zb.sqlite3"))?)?;
+    let count = format!(
+        "{} {}",
+        style(unneeded.len()).yellow().bold(),
+        if unneeded.len() == 1 {
+            "package"
+        } else {
+            "packages"
+        }
+    );
+    if dry_run {
+        ui.heading(format!("Would uninstall {count} not in {source}:"))
+            .map_err(ui_error)?;
+    } else {
+        ui.heading(format!("Uninstalling {count} not in {source}..."))
+            .map_err(ui_error)?;
+    }
+
+    for keg in &unneeded {
+        let has_service = services.file(&keg.name).is_some();
+        let note = if has_service { " and its service" } else { "" };
+        ui.bullet(format!(
+            "{} {}{note}",
+            style(&keg.name).bold(),
+            style(&keg.version).dim()
+        ))
+        .map_err(ui_error)?;
+        if dry_run {
+            continue;
+        }
+        if has_service {
+            services.stop(&keg.name)?;
+        }
+        installer.uninstall(&keg.name)?;
+    }
+    Ok(())
+}
+
+/// The contents of `source`, read from stdin when it is `-`, with a name to
+/// report it by.
+fn read_source(source: &Path) -> Result<(String, String), zb_core::Error> {
+    let mut contents = String::new();
+    if source == Path::new("-") {
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .map_err(|e| zb_core::Error::ImportError {
+                message: format!("failed to read stdin: {e}"),
+            })?;
+        return Ok(("stdin".to_string(), contents));
+    }
+    contents = std::fs::read_to_string(source).map_err(|e| zb_core::Error::ImportError {
+        message: format!("failed to read {}: {e}", source.display()),
+    })?;
+    Ok((source.display().to_string(), contents))
+}
+
+/// A file named like a Brewfile, or with any brew, cask or tap line, is a
+/// Brewfile; anything else is a package list.
+fn detect_format(source: &Path, contents: &str) -> ImportFormat {
+    let named_brewfile = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name == "Brewfile" || name.ends_with(".Brewfile"));
+    let has_directives = entries(contents).any(|entry| {
+        BREWFILE_DIRECTIVES
+            .iter()
+            .any(|directive| entry.starts_with(directive))
+    });
+    if named_brewfile || has_directives {
+        ImportFormat::Brewfile
+    } else {
+        ImportFormat::List
+    }
+}
+
+/// The packages named in `contents`, in order and without duplicates. A
+/// list line names its package first, so `brew list --versions` output
+/// imports as well as `brew leaves`.
+fn parse_packages(contents: &str, format: ImportFormat) -> Vec<BrewfileEntry> {
+    match format {
+        ImportFormat::List => {
+            let mut seen = HashSet::new();
+            entries(contents)
+                .filter_map(|entry| entry.split_whitespace().next())
+                .filter(|package| seen.insert(*package))
+                .map(BrewfileEntry::new)
+                .collect()
+        }
+        ImportFormat::Brewfile | ImportFormat::Auto => parse_brewfile(contents),
+    }
+}
+
+/// Non-empty lines with comments stripped.
+fn entries(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|entry| !entry.is_empty())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_take_the_first_word_of_each_line() {
+        let contents = "# brew list --versions\njq 1.7.1\nwget\n\ngit 2.45.0 2.44.0\njq\n";
+        assert_eq!(detect_format(Path::new("-"), contents), ImportFormat::List);
+        assert_eq!(
+            parse_packages(contents, ImportFormat::List),
+            ["jq", "wget", "git"].map(BrewfileEntry::new)
+        );
+    }
+
+    #[test]
+    fn brewfiles_are_detected_by_name_or_directives() {
+        let contents = "tap \"homebrew/cask\"\nbrew \"wget\"\ncask \"docker\"\n";
+        assert_eq!(
+            detect_format(Path::new("packages.txt"), contents),
+            ImportFormat::Brewfile
+        );
+        assert_eq!(
+            parse_packages(contents, ImportFormat::Brewfile),
+            ["wget", "cask:docker"].map(BrewfileEntry::new)
+        );
+        assert_eq!(
+            detect_format(Path::new("dotfiles/Brewfile"), "jq\n"),
+            ImportFormat::Brewfile
+        );
+    }
+}
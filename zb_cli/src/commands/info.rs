@@ -1,12 +1,64 @@
 use chrono::{DateTime, Local};
 use console::style;
 
-pub fn execute(installer: &mut zb_io::Installer, formula: String) -> Result<(), zb_core::Error> {
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    receipt: bool,
+    bottles: bool,
+) -> Result<(), zb_core::Error> {
+    if bottles {
+        return print_bottles(installer, &formula).await;
+    }
+
     if let Some(keg) = installer.get_installed(&formula) {
+        let install_receipt = installer.read_receipt(&formula)?;
+
+        if receipt {
+            match install_receipt {
+                Some(install_receipt) => {
+                    let json = serde_json::to_string_pretty(&install_receipt).map_err(|e| {
+                        zb_core::Error::FileError {
+                            message: format!("failed to encode receipt: {e}"),
+                        }
+                    })?;
+                    println!("{json}");
+                }
+                None => println!("No install receipt recorded for '{}'.", formula),
+            }
+            return Ok(());
+        }
+
         print_field("Name:", style(&keg.name).bold());
         print_field("Version:", &keg.version);
+        if let Some(formula) = installer.stored_formula(&formula) {
+            if let Some(desc) = formula.desc {
+                print_field("Desc:", desc);
+            }
+            if let Some(homepage) = formula.homepage {
+                print_field("Homepage:", homepage);
+            }
+            if let Some(license) = formula.license {
+                print_field("License:", license);
+            }
+        }
+        print_field(
+            "Arch:",
+            keg.arch.as_deref().unwrap_or(std::env::consts::ARCH),
+        );
         print_field("Store key:", &keg.store_key[..12]);
         print_field("Installed:", format_timestamp(keg.installed_at));
+        if let Some(install_receipt) = install_receipt {
+            let reason = if install_receipt.installed_on_request {
+                "on request"
+            } else {
+                "as dependency"
+            };
+            print_field("Reason:", reason);
+            if let Some(tap) = install_receipt.source.tap {
+                print_field("Tap:", tap);
+            }
+        }
     } else {
         println!("Formula '{}' is not installed.", formula);
     }
@@ -14,6 +66,26 @@ pub fn execute(installer: &mut zb_io::Installer, formula: String) -> Result<(),
     Ok(())
 }
 
+/// List published bottle tags, marking the one this machine would install.
+async fn print_bottles(installer: &zb_io::Installer, name: &str) -> Result<(), zb_core::Error> {
+    let formula = installer.get_formula(name).await?;
+    let host_tag = zb_core::select_bottle(&formula).ok().map(|b| b.tag);
+    let tags = zb_core::bottle_tags(&formula);
+
+    if tags.is_empty() {
+        println!("No bottles published for '{}'.", formula.name);
+        return Ok(());
+    }
+    for tag in tags {
+        if host_tag.as_deref() == Some(tag) {
+            println!("{} {}", style(tag).green(), style("(this machine)").dim());
+        } else {
+            println!("{tag}");
+        }
+    }
+    Ok(())
+}
+
 fn print_field(label: &str, value: impl std::fmt::Display) {
     println!("{:<10}  {}", style(label).dim(), value);
 }
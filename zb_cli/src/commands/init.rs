@@ -1,15 +1,71 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::init::{InitError, run_init};
+use crate::init::{needs_sudo, run_init};
 use crate::ui::StdUi;
+use crate::utils::{default_prefix, user_local_root};
 
+/// With `no_sudo`, a default root and prefix that would need sudo are
+/// swapped for the user-local layout under `~/.zerobrew`. Paths the user
+/// configured are kept, and init fails instead of escalating.
 pub fn execute(
     root: &Path,
     prefix: &Path,
+    configured: bool,
     no_modify_path: bool,
+    no_sudo: bool,
     ui: &mut StdUi,
 ) -> Result<(), zb_core::Error> {
-    run_init(root, prefix, no_modify_path, ui).map_err(|e| match e {
-        InitError::Message(msg) => zb_core::Error::StoreCorruption { message: msg },
-    })
+    let (root, prefix) = match user_local_layout(root, prefix, configured, no_sudo) {
+        Some((local_root, local_prefix)) => {
+            ui.info(format!(
+                "{} is not writable; using {} instead",
+                root.display(),
+                local_root.display()
+            ))
+            .map_err(ui_error)?;
+            (local_root, local_prefix)
+        }
+        None => (root.to_path_buf(), prefix.to_path_buf()),
+    };
+    Ok(run_init(&root, &prefix, no_modify_path, no_sudo, ui)?)
+}
+
+fn user_local_layout(
+    root: &Path,
+    prefix: &Path,
+    configured: bool,
+    no_sudo: bool,
+) -> Option<(PathBuf, PathBuf)> {
+    if !no_sudo || configured || !needs_sudo(root, prefix) {
+        return None;
+    }
+    let local_root = user_local_root()?;
+    let local_prefix = default_prefix(&local_root);
+    Some((local_root, local_prefix))
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_local_layout_only_replaces_unwritable_defaults() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let writable = tmp.path().join("zerobrew");
+        let unwritable = Path::new("/proc/zerobrew");
+
+        assert!(user_local_layout(&writable, &writable, false, true).is_none());
+        assert!(user_local_layout(unwritable, unwritable, false, false).is_none());
+        assert!(user_local_layout(unwritable, unwritable, true, true).is_none());
+
+        let (root, prefix) = user_local_layout(unwritable, unwritable, false, true).unwrap();
+        assert!(root.ends_with(".zerobrew"));
+        assert_eq!(prefix, default_prefix(&root));
+    }
 }
@@ -1,20 +1,67 @@
 use console::style;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
-use zb_io::{InstallProgress, ProgressCallback};
+use indicatif::HumanBytes;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use zb_core::DependencyGraph;
+use zb_io::progress_channel;
 
-use crate::ui::StdUi;
+use crate::progress::ProgressRenderer;
+use crate::ui::{PromptDefault, StdUi};
 use crate::utils::{normalize_formula_name, suggest_homebrew, suggest_missing_formula_matches};
 
+/// Load `.rb` paths among `formulas`, and the `--bottle` tarball with its
+/// name and version, into the installer, returning the names to install.
+pub fn load_local_formulas(
+    installer: &mut zb_io::Installer,
+    formulas: Vec<String>,
+    bottle: Option<(PathBuf, String, String)>,
+) -> Result<Vec<String>, zb_core::Error> {
+    let mut names = Vec::with_capacity(formulas.len() + 1);
+    for formula in formulas {
+        if formula.ends_with(".rb") {
+            names.push(installer.load_formula_file(Path::new(&formula))?);
+        } else {
+            names.push(formula);
+        }
+    }
+    if let Some((path, name, version)) = bottle {
+        names.push(installer.load_bottle_file(&path, &name, &version)?);
+    }
+    Ok(names)
+}
+
+#[derive(Default)]
+pub struct InstallOptions {
+    pub no_link: bool,
+    pub build_from_source: bool,
+    pub dry_run: bool,
+    /// Resolve and reinstall even when everything is already installed.
+    pub force: bool,
+    pub lock: Option<Lock>,
+}
+
+/// What an install does with a lockfile.
+pub enum Lock {
+    /// Write the resolved packages to this file.
+    Write(PathBuf),
+    /// Install exactly the packages this lockfile pinned, or nothing.
+    Enforce(zb_io::Lockfile),
+}
+
 pub async fn execute(
     installer: &mut zb_io::Installer,
     formulas: Vec<String>,
-    no_link: bool,
-    build_from_source: bool,
+    options: InstallOptions,
     ui: &mut StdUi,
 ) -> Result<(), zb_core::Error> {
+    let InstallOptions {
+        no_link,
+        build_from_source,
+        dry_run,
+        force,
+        lock,
+    } = options;
     let start = Instant::now();
     ui.heading(format!(
         "Installing {}...",
@@ -40,6 +87,42 @@ pub async fn execute(
         }
     }
 
+    if let Some(Lock::Enforce(lockfile)) = &lock
+        && !force
+        && !dry_run
+        && installer.satisfies_lockfile(lockfile)
+    {
+        ui.info(format!(
+            "All {} locked packages are installed",
+            style(lockfile.packages.len()).bold()
+        ))
+        .map_err(ui_error)?;
+        return Ok(());
+    }
+
+    // Everything already installed: answer from the database without
+    // resolving anything over the network. Writing a lockfile needs the
+    // resolved plan, and enforcing one was checked above.
+    if !force
+        && !dry_run
+        && lock.is_none()
+        && !normalized_names.is_empty()
+        && let Some(kegs) = installer.installed_closure(&normalized_names)?
+    {
+        for keg in kegs {
+            ui.info(format!(
+                "{} is already installed, v{} (use `zb upgrade` to check for updates)",
+                style(&keg.name).bold(),
+                keg.version
+            ))
+            .map_err(ui_error)?;
+        }
+        if cask_names.is_empty() {
+            return Ok(());
+        }
+        normalized_names.clear();
+    }
+
     let mut installed_count = 0usize;
 
     if !normalized_names.is_empty() {
@@ -60,7 +143,39 @@ pub async fn execute(
             }
         };
 
-        installed_count += execute_formula_plan(installer, &formulas, plan, no_link, ui).await?;
+        match &lock {
+            Some(Lock::Write(path)) => {
+                zb_io::Lockfile::from_plan(&normalized_names, &plan).write(path)?;
+                ui.info(format!(
+                    "Locked {} packages in {}",
+                    plan.items.len(),
+                    path.display()
+                ))
+                .map_err(ui_error)?;
+            }
+            Some(Lock::Enforce(lockfile)) => lockfile.verify(&plan)?,
+            None => {}
+        }
+
+        if dry_run {
+            print_plan(&plan, ui)?;
+        } else {
+            installed_count +=
+                execute_formula_plan(installer, &formulas, plan, no_link, ui).await?;
+        }
+    }
+
+    if dry_run {
+        for cask in &cask_names {
+            ui.bullet(format!(
+                "{} (cask, size not estimated)",
+                style(cask).green()
+            ))
+            .map_err(ui_error)?;
+        }
+        ui.note("Dry run: nothing was installed.")
+            .map_err(ui_error)?;
+        return Ok(());
     }
 
     if !cask_names.is_empty() {
@@ -85,13 +200,136 @@ pub async fn execute(
     Ok(())
 }
 
-pub async fn execute_formula_plan(
-    installer: &mut zb_io::Installer,
-    requested_formulas: &[String],
-    plan: zb_io::InstallPlan,
-    no_link: bool,
+/// Print why each formula in the plan for `formulas` would be installed,
+/// or with `why`, the path from a requested formula to that one.
+pub async fn explain(
+    installer: &zb_io::Installer,
+    formulas: Vec<String>,
+    build_from_source: bool,
+    why: Option<String>,
     ui: &mut StdUi,
-) -> Result<usize, zb_core::Error> {
+) -> Result<(), zb_core::Error> {
+    let mut names = Vec::with_capacity(formulas.len());
+    for formula in &formulas {
+        let name = normalize_formula_name(formula)?;
+        if !name.starts_with("cask:") {
+            names.push(name);
+        }
+    }
+
+    let plan = installer
+        .plan_with_options(&names, build_from_source)
+        .await?;
+    let versions: HashMap<&str, &str> = plan
+        .items
+        .iter()
+        .map(|item| {
+            (
+                item.install_name.as_str(),
+                item.formula.versions.stable.as_str(),
+            )
+        })
+        .collect();
+
+    if let Some(target) = why {
+        let target = normalize_formula_name(&target)?;
+        let chain = plan
+            .graph
+            .chain(&target)
+            .ok_or_else(|| zb_core::Error::InvalidArgument {
+                message: format!("{target} is not a dependency of {}", names.join(", ")),
+            })?;
+        ui.println(chain.join(" → ")).map_err(ui_error)?;
+        return Ok(());
+    }
+
+    ui.heading(format!(
+        "Dependency tree ({} packages)",
+        style(plan.items.len()).bold()
+    ))
+    .map_err(ui_error)?;
+    for line in render_tree(&plan.graph, |name| match versions.get(name) {
+        Some(version) => format!("{name} {}", style(version).dim()),
+        None => name.to_string(),
+    }) {
+        ui.println(line).map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+/// Draw `graph` as a tree below each requested formula. A formula reached
+/// again through another path is listed but not expanded a second time.
+pub fn render_tree(graph: &DependencyGraph, label: impl Fn(&str) -> String) -> Vec<String> {
+    fn walk(
+        graph: &DependencyGraph,
+        label: &dyn Fn(&str) -> String,
+        name: &str,
+        indent: &str,
+        expanded: &mut HashSet<String>,
+        lines: &mut Vec<String>,
+    ) {
+        let deps = graph.dependencies(name);
+        for (i, dep) in deps.iter().enumerate() {
+            let last = i + 1 == deps.len();
+            let (branch, next_indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            if expanded.insert(dep.clone()) {
+                lines.push(format!("{indent}{branch}{}", label(dep)));
+                walk(
+                    graph,
+                    label,
+                    dep,
+                    &format!("{indent}{next_indent}"),
+                    expanded,
+                    lines,
+                );
+            } else if graph.dependencies(dep).is_empty() {
+                lines.push(format!("{indent}{branch}{}", label(dep)));
+            } else {
+                lines.push(format!("{indent}{branch}{} (see above)", label(dep)));
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut expanded = HashSet::new();
+    for root in graph.roots() {
+        if !graph.contains(root) || !expanded.insert(root.clone()) {
+            continue;
+        }
+        lines.push(label(root));
+        walk(graph, &label, root, "", &mut expanded, &mut lines);
+    }
+    lines
+}
+
+/// Foreign-arch bottles only run where the OS can translate them, which
+/// today means Intel bottles under Rosetta 2 on Apple Silicon.
+pub fn check_arch_supported(arch: &str) -> Result<(), zb_core::Error> {
+    if arch == std::env::consts::ARCH {
+        return Ok(());
+    }
+    if !(cfg!(all(target_os = "macos", target_arch = "aarch64")) && arch == "x86_64") {
+        return Err(zb_core::Error::InvalidArgument {
+            message: format!(
+                "--arch {arch} is not supported on this machine; only x86_64 on Apple Silicon (via Rosetta 2) is"
+            ),
+        });
+    }
+    if !std::path::Path::new("/Library/Apple/usr/share/rosetta/rosetta").exists() {
+        return Err(zb_core::Error::InvalidArgument {
+            message:
+                "x86_64 bottles need Rosetta 2; install it with `softwareupdate --install-rosetta`"
+                    .to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn print_plan(plan: &zb_io::InstallPlan, ui: &mut StdUi) -> Result<(), zb_core::Error> {
     ui.heading(format!(
         "Resolving dependencies ({} packages)...",
         plan.items.len()
@@ -105,145 +343,149 @@ pub async fn execute_formula_plan(
         ))
         .map_err(ui_error)?;
     }
+    ui.println(size_summary(&plan.sizes())).map_err(ui_error)?;
+    Ok(())
+}
 
-    let multi = MultiProgress::new();
-    let bars: Arc<Mutex<HashMap<String, ProgressBar>>> = Arc::new(Mutex::new(HashMap::new()));
+/// One line such as "Will download 182 MiB (34 MiB cached), install size ~640 MiB".
+pub fn size_summary(sizes: &zb_io::PlanSizes) -> String {
+    let mut line = format!("Will download {}", HumanBytes(sizes.download_bytes));
+    if sizes.cached_bytes > 0 {
+        line.push_str(&format!(" ({} cached)", HumanBytes(sizes.cached_bytes)));
+    }
+    line.push_str(&format!(
+        ", install size ~{}",
+        HumanBytes(sizes.install_bytes)
+    ));
+    if sizes.unknown > 0 {
+        line.push_str(&format!(
+            " (size unknown for {} package{})",
+            sizes.unknown,
+            if sizes.unknown == 1 { "" } else { "s" }
+        ));
+    }
+    line
+}
 
-    let download_style = ProgressStyle::default_bar()
-        .template("    {prefix:<16} {bar:25.cyan/dim} {bytes:>10}/{total_bytes:<10} {eta:>6}")
-        .unwrap()
-        .progress_chars("━━╸");
+pub fn print_phase_timings(
+    timings: &[zb_io::PhaseTiming],
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    if timings.is_empty() {
+        return Ok(());
+    }
+    ui.heading("Phase timings").map_err(ui_error)?;
+    for line in render_phase_timings(timings) {
+        ui.println(line).map_err(ui_error)?;
+    }
+    Ok(())
+}
 
-    let spinner_style = ProgressStyle::default_spinner()
-        .template("    {prefix:<16} {spinner:.cyan} {msg}")
-        .unwrap()
-        .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏");
+/// A table of seconds per phase, one row per formula in the order they
+/// were reported, leaving out phases no formula went through.
+fn render_phase_timings(timings: &[zb_io::PhaseTiming]) -> Vec<String> {
+    let mut rows: Vec<(&str, HashMap<zb_io::InstallPhase, Duration>)> = Vec::new();
+    for timing in timings {
+        let index = match rows.iter().position(|(name, _)| *name == timing.formula) {
+            Some(index) => index,
+            None => {
+                rows.push((&timing.formula, HashMap::new()));
+                rows.len() - 1
+            }
+        };
+        *rows[index].1.entry(timing.phase).or_default() += timing.duration;
+    }
+    let phases: Vec<zb_io::InstallPhase> = zb_io::InstallPhase::ALL
+        .into_iter()
+        .filter(|phase| rows.iter().any(|(_, row)| row.contains_key(phase)))
+        .collect();
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("formula".len());
 
-    let done_style = ProgressStyle::default_spinner()
-        .template("    {prefix:<16} {msg}")
-        .unwrap();
+    let mut header = format!("{:<width$}", "formula");
+    for phase in &phases {
+        header.push_str(&format!(" {:>11}", phase.as_str()));
+    }
+    let mut lines = vec![header];
+    for (name, row) in &rows {
+        let mut line = format!("{name:<width$}");
+        for phase in &phases {
+            match row.get(phase) {
+                Some(duration) => line.push_str(&format!(" {:>10.3}s", duration.as_secs_f64())),
+                None => line.push_str(&format!(" {:>11}", "-")),
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+pub async fn execute_formula_plan(
+    installer: &mut zb_io::Installer,
+    requested_formulas: &[String],
+    plan: zb_io::InstallPlan,
+    no_link: bool,
+    ui: &mut StdUi,
+) -> Result<usize, zb_core::Error> {
+    print_plan(&plan, ui)?;
 
     ui.heading("Downloading and installing formulas...")
         .map_err(ui_error)?;
 
-    let bars_clone = bars.clone();
-    let multi_clone = multi.clone();
-    let download_style_clone = download_style.clone();
-    let spinner_style_clone = spinner_style.clone();
-    let done_style_clone = done_style.clone();
-
-    let progress_callback: Arc<ProgressCallback> = Arc::new(Box::new(move |event| {
-        let mut bars = bars_clone.lock().unwrap();
-        match event {
-            InstallProgress::DownloadStarted { name, total_bytes } => {
-                let pb = if let Some(total) = total_bytes {
-                    let pb = multi_clone.add(ProgressBar::new(total));
-                    pb.set_style(download_style_clone.clone());
-                    pb
-                } else {
-                    let pb = multi_clone.add(ProgressBar::new_spinner());
-                    pb.set_style(spinner_style_clone.clone());
-                    pb.set_message("downloading...");
-                    pb.enable_steady_tick(std::time::Duration::from_millis(80));
-                    pb
-                };
-                pb.set_prefix(name.clone());
-                bars.insert(name, pb);
-            }
-            InstallProgress::DownloadProgress {
-                name,
-                downloaded,
-                total_bytes,
-            } => {
-                if let Some(pb) = bars.get(&name)
-                    && total_bytes.is_some()
-                {
-                    pb.set_position(downloaded);
-                }
-            }
-            InstallProgress::DownloadCompleted { name, total_bytes } => {
-                if let Some(pb) = bars.get(&name) {
-                    if total_bytes > 0 {
-                        pb.set_position(total_bytes);
-                    }
-                    pb.set_style(spinner_style_clone.clone());
-                    pb.set_message("unpacking...");
-                    pb.enable_steady_tick(std::time::Duration::from_millis(80));
-                }
-            }
-            InstallProgress::UnpackStarted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message("unpacking...");
-                }
-            }
-            InstallProgress::UnpackCompleted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message("unpacked");
-                }
-            }
-            InstallProgress::LinkStarted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message("linking...");
-                }
-            }
-            InstallProgress::LinkCompleted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message("linked");
-                }
-            }
-            InstallProgress::LinkSkipped { name, reason } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message(format!("keg-only ({})", reason));
-                }
-            }
-            InstallProgress::InstallCompleted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_style(done_style_clone.clone());
-                    pb.set_message(format!("{} installed", style("✓").green()));
-                    pb.finish();
-                }
-            }
-        }
-    }));
-
-    let result_val = installer
-        .execute_with_progress(plan, !no_link, Some(progress_callback))
-        .await;
+    let planned = plan.items.len();
+    let names: Vec<String> = plan
+        .items
+        .iter()
+        .map(|item| item.install_name.clone())
+        .collect();
+    let (events, receiver) = progress_channel();
+    let renderer = tokio::spawn(ProgressRenderer::new("installed").run(receiver));
+    let result_val = installer.execute_streaming(plan, !no_link, events).await;
+    let _ = renderer.await;
 
-    {
-        let bars = bars.lock().unwrap();
-        for (_, pb) in bars.iter() {
-            if !pb.is_finished() {
-                pb.finish();
+    match result_val {
+        Ok(result) => {
+            if !no_link {
+                let path = std::env::var_os("PATH").unwrap_or_default();
+                let shadowed = installer.shadowed_commands_of(&names, &path)?;
+                crate::commands::doctor::report_shadowed_commands(&shadowed, ui)?;
             }
+            Ok(result.installed)
         }
-    }
-
-    match result_val {
-        Ok(result) => Ok(result.installed),
-        Err(ref e @ zb_core::Error::LinkConflict { ref conflicts }) => {
+        Err(
+            ref e @ zb_core::Error::LinkConflict {
+                ref formula,
+                ref conflicts,
+            },
+        ) => {
             ui.blank_line().map_err(ui_error)?;
             ui.error("The link step did not complete successfully.")
                 .map_err(ui_error)?;
             ui.println("The formula was installed, but is not symlinked into the prefix.")
                 .map_err(ui_error)?;
             ui.blank_line().map_err(ui_error)?;
-            ui.println("Possible conflicting files:")
-                .map_err(ui_error)?;
-            for c in conflicts {
-                if let Some(ref owner) = c.owned_by {
-                    ui.println(format!(
-                        "  {} (symlink belonging to {})",
-                        c.path.display(),
-                        style(owner).yellow()
-                    ))
+            let Some(name) = formula else {
+                return Err(e.clone());
+            };
+            crate::commands::link::report_conflicts(name, conflicts, ui)?;
+
+            let interactive = std::io::IsTerminal::is_terminal(&std::io::stdin())
+                && std::io::IsTerminal::is_terminal(&std::io::stdout());
+            if interactive
+                && conflicts.iter().all(|c| c.owned_by.is_some())
+                && ui
+                    .prompt_yes_no("Overwrite them now? [y/N]", PromptDefault::No)
+                    .map_err(ui_error)?
+            {
+                installer.link(name, zb_io::LinkStrategy::Overwrite, None)?;
+                ui.heading(format!("Linked {}", style(name).bold()))
                     .map_err(ui_error)?;
-                } else {
-                    ui.println(format!("  {}", c.path.display()))
-                        .map_err(ui_error)?;
-                }
+                return Ok(planned);
             }
-            ui.blank_line().map_err(ui_error)?;
             Err(e.clone())
         }
         Err(e) => {
@@ -264,3 +506,100 @@ fn ui_error(err: std::io::Error) -> zb_core::Error {
         message: format!("failed to write CLI output: {err}"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use zb_core::{Formula, Platform, dependency_graph_for};
+
+    use super::render_tree;
+
+    fn formula(name: &str, deps: &[&str]) -> Formula {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "versions": { "stable": "1.0" },
+            "dependencies": deps,
+            "bottle": { "stable": { "files": {} } }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn size_summary_mentions_cache_and_unknowns() {
+        let sizes = zb_io::PlanSizes {
+            download_bytes: 182 << 20,
+            cached_bytes: 34 << 20,
+            install_bytes: 640 << 20,
+            unknown: 0,
+        };
+        assert_eq!(
+            super::size_summary(&sizes),
+            "Will download 182.00 MiB (34.00 MiB cached), install size ~640.00 MiB"
+        );
+
+        let sizes = zb_io::PlanSizes {
+            download_bytes: 1 << 20,
+            unknown: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            super::size_summary(&sizes),
+            "Will download 1.00 MiB, install size ~0 B (size unknown for 1 package)"
+        );
+    }
+
+    #[test]
+    fn phase_timings_render_one_row_per_formula() {
+        use std::time::Duration;
+        use zb_io::{InstallPhase, PhaseTiming};
+
+        let timing = |formula: &str, phase, ms| PhaseTiming {
+            formula: formula.to_string(),
+            phase,
+            duration: Duration::from_millis(ms),
+        };
+        let lines = super::render_phase_timings(&[
+            timing("jq", InstallPhase::Resolve, 120),
+            timing("oniguruma", InstallPhase::Download, 300),
+            timing("jq", InstallPhase::Download, 250),
+            timing("jq", InstallPhase::Link, 5),
+        ]);
+
+        assert_eq!(
+            lines,
+            [
+                "formula       resolve    download        link",
+                "jq             0.120s      0.250s      0.005s",
+                "oniguruma           -      0.300s           -",
+            ]
+        );
+    }
+
+    #[test]
+    fn tree_expands_shared_dependencies_once() {
+        let formulas: BTreeMap<String, Formula> = [
+            formula("app", &["lib", "tool"]),
+            formula("lib", &["zlib"]),
+            formula("tool", &["lib"]),
+            formula("zlib", &[]),
+        ]
+        .into_iter()
+        .map(|f| (f.name.clone(), f))
+        .collect();
+        let graph = dependency_graph_for(&["app".to_string()], &formulas, Platform::host());
+
+        let lines = render_tree(&graph, str::to_string);
+
+        assert_eq!(
+            lines,
+            [
+                "app",
+                "├── lib",
+                "│   └── zlib",
+                "└── tool",
+                "    └── lib (see above)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use console::style;
+
+pub async fn execute(installer: &zb_io::Installer, json: bool) -> Result<(), zb_core::Error> {
+    let licenses = installer.installed_licenses().await?;
+
+    if json {
+        let json_output: Vec<serde_json::Value> = licenses
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "name": entry.keg.name,
+                    "version": entry.keg.version,
+                    "license": entry.license,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&json_output).unwrap());
+        return Ok(());
+    }
+
+    if licenses.is_empty() {
+        println!("No formulas installed.");
+        return Ok(());
+    }
+
+    let width = licenses
+        .iter()
+        .map(|entry| entry.keg.name.len() + entry.keg.version.len() + 1)
+        .max()
+        .unwrap_or(0);
+    for entry in &licenses {
+        let package = format!("{} {}", entry.keg.name, entry.keg.version);
+        match &entry.license {
+            Some(license) => println!("{package:<width$}  {license}"),
+            None => println!("{package:<width$}  {}", style("unknown").dim()),
+        }
+    }
+    Ok(())
+}
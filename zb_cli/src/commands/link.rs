@@ -0,0 +1,80 @@
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+use console::style;
+use zb_core::ConflictedLink;
+use zb_io::{LinkMode, LinkStrategy};
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    overwrite: bool,
+    keep_both: bool,
+    mode: Option<LinkMode>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(&formula)?;
+    let strategy = if overwrite {
+        LinkStrategy::Overwrite
+    } else if keep_both {
+        LinkStrategy::KeepBoth
+    } else {
+        LinkStrategy::Refuse
+    };
+
+    match installer.link(&name, strategy, mode) {
+        Ok(count) => {
+            ui.heading(format!("Linked {} ({count} files)", style(&name).bold()))
+                .map_err(ui_error)?;
+            Ok(())
+        }
+        Err(ref e @ zb_core::Error::LinkConflict { ref conflicts, .. }) => {
+            report_conflicts(&name, conflicts, ui)?;
+            Err(e.clone())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// List the files that kept `name` from being linked, who owns them, and
+/// how to link anyway.
+pub fn report_conflicts(
+    name: &str,
+    conflicts: &[ConflictedLink],
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    ui.println("Conflicting files:").map_err(ui_error)?;
+    for c in conflicts {
+        if let Some(ref owner) = c.owned_by {
+            ui.println(format!(
+                "  {} (symlink belonging to {})",
+                c.path.display(),
+                style(owner).yellow()
+            ))
+            .map_err(ui_error)?;
+        } else {
+            ui.println(format!("  {}", c.path.display()))
+                .map_err(ui_error)?;
+        }
+    }
+    ui.blank_line().map_err(ui_error)?;
+
+    if conflicts.iter().all(|c| c.owned_by.is_some()) {
+        ui.println(format!(
+            "To replace links owned by other packages: zb link {name} --overwrite"
+        ))
+        .map_err(ui_error)?;
+    }
+    if let Some((_, version)) = name.split_once('@') {
+        ui.println(format!(
+            "To link the conflicting files with a -{version} suffix: zb link {name} --keep-both"
+        ))
+        .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
@@ -1,15 +1,184 @@
 use console::style;
+use indicatif::HumanBytes;
+use zb_io::InstalledKeg;
 
-pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
-    let installed = installer.list_installed()?;
+use crate::cli::ListSort;
+use crate::utils::normalize_formula_name;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    size: bool,
+    deps_of: Option<String>,
+    json: bool,
+    sort: ListSort,
+    reverse: bool,
+    tag: Option<String>,
+) -> Result<(), zb_core::Error> {
+    let show_size = size || json || sort == ListSort::Size;
+    if show_size {
+        installer.backfill_keg_sizes()?;
+    }
+
+    let mut installed = match (&deps_of, &tag) {
+        (Some(name), _) => installer.installed_dependencies_of(name)?,
+        (None, Some(tag)) => installer.installed_with_tag(tag)?,
+        (None, None) => installer.list_installed()?,
+    };
+    sort_kegs(&mut installed, sort, reverse);
+
+    if json {
+        let json_output: Vec<serde_json::Value> = installed.iter().map(keg_json).collect();
+        println!("{}", serde_json::to_string_pretty(&json_output).unwrap());
+        return Ok(());
+    }
 
     if installed.is_empty() {
-        println!("No formulas installed.");
-    } else {
-        for keg in installed {
-            println!("{} {}", style(&keg.name).bold(), style(&keg.version).dim());
+        match (deps_of, tag) {
+            (Some(name), _) => println!("No installed dependencies of {name}."),
+            (None, Some(tag)) => println!("No installed packages tagged {tag}."),
+            (None, None) => println!("No formulas installed."),
         }
+        return Ok(());
+    }
+
+    let name_width = installed.iter().map(|k| k.name.len()).max().unwrap_or(0);
+    for keg in &installed {
+        if show_size {
+            let size = keg
+                .size_bytes
+                .map(|s| HumanBytes(s).to_string())
+                .unwrap_or_else(|| "?".to_string());
+            println!(
+                "{:<name_width$}  {:>10}  {}{}",
+                style(&keg.name).bold(),
+                size,
+                style(&keg.version).dim(),
+                arch_suffix(keg)
+            );
+        } else {
+            println!(
+                "{} {}{}",
+                style(&keg.name).bold(),
+                style(&keg.version).dim(),
+                arch_suffix(keg)
+            );
+        }
+    }
+
+    if show_size {
+        let total: u64 = installed.iter().filter_map(|k| k.size_bytes).sum();
+        println!(
+            "{} {} packages, {}",
+            style("==>").cyan().bold(),
+            installed.len(),
+            HumanBytes(total)
+        );
     }
 
     Ok(())
 }
+
+/// Print the files the installed keg of `formula` contains, as absolute
+/// paths.
+pub fn execute_files(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(&formula)?;
+    let entries = installer.list_files(&name)?;
+    let keg = installer
+        .get_installed(&name)
+        .ok_or(zb_core::Error::NotInstalled { name: name.clone() })?;
+    let keg_path = installer.installed_keg_path(&keg);
+
+    if json {
+        let json_output: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "path": keg_path.join(&entry.path),
+                    "size_bytes": entry.size_bytes,
+                    "sha256": entry.sha256,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&json_output).unwrap());
+        return Ok(());
+    }
+
+    for entry in &entries {
+        println!("{}", keg_path.join(&entry.path).display());
+    }
+    Ok(())
+}
+
+/// How `list --json` and the daemon describe an installed keg.
+pub(crate) fn keg_json(keg: &InstalledKeg) -> serde_json::Value {
+    serde_json::json!({
+        "name": keg.name,
+        "version": keg.version,
+        "installed_at": keg.installed_at,
+        "size_bytes": keg.size_bytes,
+        "arch": keg.arch,
+    })
+}
+
+/// Marks kegs installed for a foreign arch, e.g. " (x86_64)".
+fn arch_suffix(keg: &InstalledKeg) -> String {
+    keg.arch
+        .as_deref()
+        .map(|arch| format!(" {}", style(format!("({arch})")).yellow()))
+        .unwrap_or_default()
+}
+
+pub(crate) fn sort_kegs(kegs: &mut [InstalledKeg], sort: ListSort, reverse: bool) {
+    match sort {
+        ListSort::Name => kegs.sort_by(|a, b| a.name.cmp(&b.name)),
+        // Largest first reads more naturally for disk usage
+        ListSort::Size => kegs.sort_by_key(|k| std::cmp::Reverse(k.size_bytes)),
+        ListSort::Installed => kegs.sort_by_key(|k| k.installed_at),
+    }
+    if reverse {
+        kegs.reverse();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keg(name: &str, size: Option<u64>, installed_at: i64) -> InstalledKeg {
+        InstalledKeg {
+            name: name.to_string(),
+            version: "1.0".to_string(),
+            store_key: String::new(),
+            installed_at,
+            size_bytes: size,
+            arch: None,
+            shared_bytes: None,
+        }
+    }
+
+    fn names(kegs: &[InstalledKeg]) -> Vec<&str> {
+        kegs.iter().map(|k| k.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sorts_by_size_descending() {
+        let mut kegs = vec![
+            keg("a", Some(1), 3),
+            keg("b", Some(5), 2),
+            keg("c", None, 1),
+        ];
+        sort_kegs(&mut kegs, ListSort::Size, false);
+        assert_eq!(names(&kegs), vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn sorts_by_install_time_and_reverses() {
+        let mut kegs = vec![keg("a", None, 3), keg("b", None, 1), keg("c", None, 2)];
+        sort_kegs(&mut kegs, ListSort::Installed, true);
+        assert_eq!(names(&kegs), vec!["a", "c", "b"]);
+    }
+}
@@ -1,9 +1,12 @@
 use crate::ui::{PromptDefault, StdUi};
 use console::style;
+use std::path::Path;
 use std::process::Command;
+use zb_io::{Database, ServiceManager};
 
 pub async fn execute(
     installer: &mut zb_io::Installer,
+    root: &Path,
     yes: bool,
     force: bool,
     ui: &mut StdUi,
@@ -140,6 +143,8 @@ pub async fn execute(
         return Ok(());
     }
 
+    adopt_services(installer, root, &successfully_installed, yes, ui).await?;
+
     ui.blank_line().map_err(ui_error)?;
     if !yes
         && !ui
@@ -233,6 +238,78 @@ pub async fn execute(
     Ok(())
 }
 
+/// Move the services Homebrew runs for migrated formulas over to zerobrew,
+/// so the two never run the same service at once.
+async fn adopt_services(
+    installer: &zb_io::Installer,
+    root: &Path,
+    migrated: &[String],
+    yes: bool,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let services = ServiceManager::new(Database::open(&root.join("db/zb.sqlite3"))?)?;
+    let running: Vec<(&String, String)> = migrated
+        .iter()
+        .filter_map(|name| services.homebrew_job(name).map(|label| (name, label)))
+        .collect();
+    if running.is_empty() {
+        return Ok(());
+    }
+
+    ui.blank_line().map_err(ui_error)?;
+    ui.note("Homebrew runs services for migrated formulas:")
+        .map_err(ui_error)?;
+    for (name, label) in &running {
+        ui.bullet(format!("{name} ({label})")).map_err(ui_error)?;
+    }
+    if !yes
+        && !ui
+            .prompt_yes_no(
+                "Stop them in Homebrew and start them with zerobrew? [y/N]",
+                PromptDefault::No,
+            )
+            .map_err(ui_error)?
+    {
+        ui.println("Left Homebrew's services running.")
+            .map_err(ui_error)?;
+        return Ok(());
+    }
+
+    let mut adopted = 0;
+    for (name, _) in &running {
+        ui.step_start(format!("moving the {name} service"))
+            .map_err(ui_error)?;
+        let result = async {
+            zb_io::stop_homebrew_service(name)?;
+            let definition = installer.service_definition(name).await?;
+            services.start(name, &definition)
+        }
+        .await;
+        match result {
+            Ok(_) => {
+                ui.step_ok().map_err(ui_error)?;
+                adopted += 1;
+            }
+            Err(e) => {
+                ui.step_fail().map_err(ui_error)?;
+                ui.error(e).map_err(ui_error)?;
+            }
+        }
+    }
+
+    ui.heading(format!(
+        "Moved {} of {} service(s) to zerobrew",
+        style(adopted).green().bold(),
+        running.len()
+    ))
+    .map_err(ui_error)?;
+    if adopted < running.len() {
+        ui.println("Start the rest with `zb services start <formula>` once fixed.")
+            .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
 // FIXME: Abstract this return type to a more structured type (e.g., a struct)
 fn check_install_status(
     installer: &zb_io::Installer,
@@ -241,12 +318,11 @@ fn check_install_status(
     let mut successfully_installed = Vec::new();
     let mut failed_installed = Vec::new();
 
-    let installed_kegs =
-        installer
-            .list_installed()
-            .map_err(|e| zb_core::Error::StoreCorruption {
-                message: format!("Failed to verify installation status: {}", e),
-            })?;
+    let installed_kegs = installer
+        .list_installed()
+        .map_err(zb_core::Error::migration(
+            "failed to verify installation status",
+        ))?;
 
     let installed_names: std::collections::HashSet<String> =
         installed_kegs.into_iter().map(|k| k.name).collect();
@@ -262,7 +338,7 @@ fn check_install_status(
 }
 
 fn ui_error(err: std::io::Error) -> zb_core::Error {
-    zb_core::Error::StoreCorruption {
+    zb_core::Error::FileError {
         message: format!("failed to write CLI output: {err}"),
     }
 }
@@ -1,15 +1,46 @@
+pub mod audit;
+pub mod audit_relocation;
 pub mod bundle;
+pub mod cleanup;
+pub mod compat;
 pub mod completion;
+pub mod create;
+pub mod daemon;
+pub mod deps;
 pub mod doctor;
+pub mod du;
+pub mod env;
+pub mod exec;
+pub mod export;
+pub mod export_image;
+pub mod fetch;
 pub mod gc;
+pub mod history;
+pub mod import;
 pub mod info;
 pub mod init;
 pub mod install;
+pub mod licenses;
+pub mod link;
 pub mod list;
 pub mod migrate;
+pub mod notes;
 pub mod outdated;
+pub mod owns;
+pub mod profile;
+pub mod query;
+pub mod relink;
+pub mod replay;
 pub mod reset;
+pub mod rollback;
 pub mod run;
+pub mod search;
+pub mod self_manage;
+pub mod services;
+pub mod shim;
+pub mod stats;
+pub mod store;
 pub mod uninstall;
 pub mod update;
 pub mod upgrade;
+pub mod which;
@@ -0,0 +1,56 @@
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+use console::style;
+
+pub struct NotesEdit {
+    pub set: Option<String>,
+    pub clear: bool,
+    pub tags: Vec<String>,
+    pub untags: Vec<String>,
+}
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    edit: NotesEdit,
+    json: bool,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(&formula)?;
+    if edit.set.is_some() || edit.clear {
+        installer.set_package_note(&name, edit.set.as_deref())?;
+    }
+    if !edit.tags.is_empty() || !edit.untags.is_empty() {
+        installer.tag_package(&name, &edit.tags, &edit.untags)?;
+    }
+
+    let notes = installer.package_notes(&name)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&notes).unwrap());
+        return Ok(());
+    }
+
+    if notes.note.is_none() && notes.tags.is_empty() {
+        ui.info(format!(
+            "No notes for {}; add one with `zb notes {name} --set TEXT`.",
+            style(&name).bold()
+        ))
+        .map_err(ui_error)?;
+        return Ok(());
+    }
+    ui.heading(style(&name).bold()).map_err(ui_error)?;
+    if let Some(note) = &notes.note {
+        ui.println(note).map_err(ui_error)?;
+    }
+    if !notes.tags.is_empty() {
+        ui.bullet(format!("tags: {}", notes.tags.join(", ")))
+            .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
@@ -0,0 +1,20 @@
+use std::path::Path;
+
+use console::style;
+
+pub fn execute(installer: &zb_io::Installer, path: &Path) -> Result<(), zb_core::Error> {
+    match installer.owns(path)? {
+        Some(owner) => {
+            println!(
+                "{} {} {}",
+                style(&owner.name).bold(),
+                style(&owner.version).dim(),
+                owner.file.display()
+            );
+            Ok(())
+        }
+        None => Err(zb_core::Error::InvalidArgument {
+            message: format!("{} does not belong to an installed formula", path.display()),
+        }),
+    }
+}
@@ -0,0 +1,71 @@
+use console::style;
+
+use crate::cli::ProfileCommands;
+use crate::ui::StdUi;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    command: Option<ProfileCommands>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    match command.unwrap_or(ProfileCommands::List) {
+        ProfileCommands::List => {
+            for profile in installer.list_profiles()? {
+                let marker = if profile.current {
+                    style("*").green().to_string()
+                } else {
+                    " ".to_string()
+                };
+                ui.println(format!(
+                    "{marker} {} {}",
+                    style(&profile.name).bold(),
+                    style(format!(
+                        "({} {})",
+                        profile.kegs.len(),
+                        if profile.kegs.len() == 1 {
+                            "keg"
+                        } else {
+                            "kegs"
+                        }
+                    ))
+                    .dim()
+                ))
+                .map_err(ui_error)?;
+            }
+            Ok(())
+        }
+        ProfileCommands::Create { name, formulas } => {
+            let profile = installer.create_profile(&name, &formulas)?;
+            ui.heading(format!(
+                "Created profile {} with {} {}",
+                style(&profile.name).bold(),
+                profile.kegs.len(),
+                if profile.kegs.len() == 1 {
+                    "keg"
+                } else {
+                    "kegs"
+                }
+            ))
+            .map_err(ui_error)?;
+            ui.bullet(profile.path.display()).map_err(ui_error)?;
+            Ok(())
+        }
+        ProfileCommands::Switch { name } => {
+            installer.switch_profile(&name)?;
+            ui.heading(format!("Switched to profile {}", style(&name).bold()))
+                .map_err(ui_error)?;
+            ui.note(format!(
+                "Put {} on PATH to follow profile switches.",
+                installer.current_profile_link().join("bin").display()
+            ))
+            .map_err(ui_error)?;
+            Ok(())
+        }
+    }
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+
+/// A path lookup made with `--prefix`, `--cellar` or `--env` and no
+/// command, as scripts ask `brew --prefix openssl@3`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Query {
+    Prefix(Option<String>),
+    Cellar(Option<String>),
+    Env(String),
+}
+
+impl Query {
+    /// The query the top-level flags ask for, and the prefix override left
+    /// once `--prefix` is read as a query rather than a path.
+    pub fn from_flags(
+        prefix: Option<Option<PathBuf>>,
+        cellar: Option<Option<String>>,
+        env: Option<String>,
+    ) -> Result<(Self, Option<PathBuf>), zb_core::Error> {
+        if let Some(formula) = env {
+            return Ok((Query::Env(formula), prefix.flatten()));
+        }
+        if let Some(formula) = cellar {
+            return Ok((Query::Cellar(formula), prefix.flatten()));
+        }
+        match prefix {
+            // Formula names never start like a path, so such a value was
+            // meant to set the prefix for a command that is missing.
+            Some(Some(path)) if looks_like_path(&path) => Err(zb_core::Error::InvalidArgument {
+                message: format!(
+                    "--prefix {} needs a command; to print a formula's prefix, name the formula (zb --prefix openssl@3)",
+                    path.display()
+                ),
+            }),
+            Some(formula) => Ok((
+                Query::Prefix(formula.map(|f| f.to_string_lossy().into_owned())),
+                None,
+            )),
+            None => Err(zb_core::Error::InvalidArgument {
+                message: "no command given (see zb --help)".to_string(),
+            }),
+        }
+    }
+
+    /// The answer when it does not depend on what is installed.
+    pub fn answer_without_installer(&self, prefix: &Path) -> Option<PathBuf> {
+        match self {
+            Query::Prefix(None) => Some(prefix.to_path_buf()),
+            Query::Cellar(None) => Some(prefix.join("Cellar")),
+            _ => None,
+        }
+    }
+}
+
+fn looks_like_path(value: &Path) -> bool {
+    value.is_absolute()
+        || value.starts_with(".")
+        || value.starts_with("..")
+        || value.starts_with("~")
+}
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    query: Query,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    match query {
+        Query::Prefix(None) => print_path(installer.prefix(), ui),
+        Query::Prefix(Some(formula)) => {
+            print_path(&installer.opt_path(&normalize_formula_name(&formula)?)?, ui)
+        }
+        Query::Cellar(None) => print_path(installer.cellar_dir(), ui),
+        Query::Cellar(Some(formula)) => print_path(
+            &installer.rack_path(&normalize_formula_name(&formula)?)?,
+            ui,
+        ),
+        Query::Env(formula) => print_exports(
+            &installer.build_flags(&normalize_formula_name(&formula)?)?,
+            ui,
+        ),
+    }
+}
+
+pub fn print_path(path: &Path, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    ui.println(path.display()).map_err(ui_error)
+}
+
+/// The flags as `export` lines a shell can `eval`.
+pub fn print_exports(flags: &zb_io::BuildFlags, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    for line in flags.to_shell().lines() {
+        ui.println(line).map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_is_a_query_only_on_its_own() {
+        assert_eq!(
+            Query::from_flags(Some(Some("openssl@3".into())), None, None).unwrap(),
+            (Query::Prefix(Some("openssl@3".to_string())), None)
+        );
+        assert_eq!(
+            Query::from_flags(Some(Some("/custom".into())), Some(None), None).unwrap(),
+            (Query::Cellar(None), Some(PathBuf::from("/custom")))
+        );
+        assert_eq!(
+            Query::from_flags(None, None, Some("readline".to_string())).unwrap(),
+            (Query::Env("readline".to_string()), None)
+        );
+        assert!(Query::from_flags(None, None, None).is_err());
+        assert!(Query::from_flags(Some(Some("./prefix".into())), None, None).is_err());
+    }
+
+    #[test]
+    fn bare_queries_need_no_installer() {
+        let prefix = Path::new("/opt/zerobrew/prefix");
+        assert_eq!(
+            Query::Cellar(None).answer_without_installer(prefix),
+            Some(PathBuf::from("/opt/zerobrew/prefix/Cellar"))
+        );
+        assert_eq!(
+            Query::Prefix(Some("jq".to_string())).answer_without_installer(prefix),
+            None
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+use console::style;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    force: bool,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(&formula)?;
+    let summary = installer.relink(&name, force)?;
+
+    if summary.restored.is_empty()
+        && summary.skipped.is_empty()
+        && summary.missing_targets.is_empty()
+    {
+        ui.heading(format!("Links of {} are intact", style(&name).bold()))
+            .map_err(ui_error)?;
+    } else if !summary.restored.is_empty() {
+        ui.heading(format!(
+            "Restored {} {} of {}",
+            summary.restored.len(),
+            if summary.restored.len() == 1 {
+                "link"
+            } else {
+                "links"
+            },
+            style(&name).bold()
+        ))
+        .map_err(ui_error)?;
+        for link in &summary.restored {
+            ui.bullet(link.display()).map_err(ui_error)?;
+        }
+    }
+
+    if !summary.skipped.is_empty() {
+        ui.warn(format!(
+            "Left {} in place of links of {name}; pass --force to replace them:",
+            if summary.skipped.len() == 1 {
+                "a file"
+            } else {
+                "files"
+            }
+        ))
+        .map_err(ui_error)?;
+        for link in &summary.skipped {
+            ui.bullet(link.display()).map_err(ui_error)?;
+        }
+    }
+    for link in &summary.missing_targets {
+        ui.warn(format!(
+            "Cannot restore {}: its keg file is gone; reinstall {name}",
+            link.display()
+        ))
+        .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use crate::ui::StdUi;
+use console::style;
+use zb_io::TranscriptEvent;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    file: &Path,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let events = zb_io::read_transcript(file)?;
+    ui.heading(format!("Replaying {}...", style(file.display()).bold()))
+        .map_err(ui_error)?;
+
+    let requests = events
+        .iter()
+        .filter(|event| matches!(event, TranscriptEvent::ApiRequest { .. }))
+        .count();
+    let checks = installer.replay_transcript(&events);
+    let problems: Vec<&str> = checks
+        .iter()
+        .filter_map(|check| check.problem.as_deref())
+        .collect();
+
+    for problem in &problems {
+        ui.println(format!("    {} {problem}", style("✗").red()))
+            .map_err(ui_error)?;
+    }
+    if problems.is_empty() {
+        ui.println(format!(
+            "    {} All {} recorded {} still hold",
+            style("✓").green(),
+            checks.len(),
+            if checks.len() == 1 { "fact" } else { "facts" }
+        ))
+        .map_err(ui_error)?;
+    } else {
+        ui.warn(format!(
+            "{} of {} recorded facts no longer hold",
+            problems.len(),
+            checks.len()
+        ))
+        .map_err(ui_error)?;
+    }
+    if requests > 0 {
+        ui.note(format!(
+            "The transcript also lists {requests} API {}.",
+            if requests == 1 { "request" } else { "requests" }
+        ))
+        .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
@@ -3,7 +3,7 @@ use std::process::Command;
 
 use zb_io::validate_privileged_path;
 
-use crate::init::{InitError, run_init};
+use crate::init::run_init;
 use crate::ui::{PromptDefault, StdUi};
 
 pub fn execute(
@@ -87,9 +87,7 @@ pub fn execute(
     }
 
     // Pass false for no_modify_shell since this is a re-initialization
-    run_init(root, prefix, false, ui).map_err(|e| match e {
-        InitError::Message(msg) => zb_core::Error::StoreCorruption { message: msg },
-    })?;
+    run_init(root, prefix, false, false, ui)?;
 
     ui.heading("Reset complete. Ready for cold install.")
         .map_err(ui_error)?;
@@ -98,7 +96,7 @@ pub fn execute(
 }
 
 fn ui_error(err: std::io::Error) -> zb_core::Error {
-    zb_core::Error::StoreCorruption {
+    zb_core::Error::FileError {
         message: format!("failed to write CLI output: {err}"),
     }
 }
@@ -0,0 +1,28 @@
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+use console::style;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let name = normalize_formula_name(&formula)?;
+    let current = installer.get_installed(&name).map(|keg| keg.version);
+
+    let version = installer.rollback(&name)?;
+    let from = current.map(|v| format!(" from {v}")).unwrap_or_default();
+    ui.heading(format!(
+        "Rolled back {}{from} to {}",
+        style(&name).bold(),
+        style(&version).green()
+    ))
+    .map_err(ui_error)?;
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
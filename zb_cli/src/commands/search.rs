@@ -0,0 +1,78 @@
+use console::style;
+use zb_io::AnalyticsPeriod;
+
+pub async fn execute(
+    installer: &zb_io::Installer,
+    query: Option<String>,
+    desc: bool,
+    popular: Option<u64>,
+    period: AnalyticsPeriod,
+) -> Result<(), zb_core::Error> {
+    if let Some(limit) = popular {
+        let counts = installer.popular_formulas(period, limit as usize).await?;
+        let width = counts.len().to_string().len();
+        for count in counts {
+            println!(
+                "{:>width$}. {} {}",
+                count.rank,
+                count.formula,
+                style(format!("({} installs/{period})", thousands(count.count))).dim()
+            );
+        }
+        return Ok(());
+    }
+
+    let query = query.unwrap_or_default();
+    let mut matches = installer.search_formulas(&query, desc).await?;
+    if matches.is_empty() {
+        return Err(zb_core::Error::MissingFormula { name: query });
+    }
+    installer.annotate_popularity(&mut matches, period).await;
+
+    for formula in matches {
+        let installs = formula
+            .installs
+            .map(|n| {
+                format!(
+                    " {}",
+                    style(format!("({} installs/{period})", thousands(n))).dim()
+                )
+            })
+            .unwrap_or_default();
+        match formula.desc.filter(|_| desc) {
+            Some(description) => println!(
+                "{}{} {}",
+                style(format!("{}:", formula.name)).bold(),
+                installs,
+                description
+            ),
+            None => println!("{}{}", formula.name, installs),
+        }
+    }
+    Ok(())
+}
+
+fn thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::thousands;
+
+    #[test]
+    fn counts_get_thousands_separators() {
+        assert_eq!(thousands(0), "0");
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(1_000), "1,000");
+        assert_eq!(thousands(1_234_567), "1,234,567");
+    }
+}
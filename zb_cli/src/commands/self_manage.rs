@@ -0,0 +1,487 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zb_io::{
+    Database, LinkMode, ReleaseClient, StorageLayout, is_newer_release, matches_release,
+    release_asset_name, stage_executable, validate_privileged_path, verify_attestation,
+};
+
+use crate::cli::SelfCommands;
+use crate::init::{PREFIX_MARKER, remove_shell_config};
+use crate::ui::{PromptDefault, StdUi};
+
+pub async fn execute(
+    layout: &StorageLayout,
+    command: SelfCommands,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    match command {
+        SelfCommands::Update {
+            check,
+            skip_attestation,
+        } => update(check, skip_attestation, ui).await,
+        SelfCommands::Uninstall { yes } => uninstall(layout, yes, ui),
+    }
+}
+
+async fn update(check: bool, skip_attestation: bool, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    let exe = current_exe()?;
+    let client = ReleaseClient::new();
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = client.latest_version().await?;
+    // A build from source can be ahead of the latest release; never
+    // "update" it backwards.
+    if !is_newer_release(&latest, current) {
+        return ui
+            .info(format!(
+                "zb {current} is up to date (the latest release is {latest})."
+            ))
+            .map_err(ui_error);
+    }
+    if check {
+        return ui
+            .info(format!(
+                "zb {latest} is available (this is {current}). Run `zb self update` to install it."
+            ))
+            .map_err(ui_error);
+    }
+
+    let checksums = client.checksums().await?;
+
+    let mut binaries = vec![("zb", exe.clone())];
+    let zbx = exe.with_file_name("zbx");
+    if zbx.exists() {
+        binaries.push(("zbx", zbx));
+    }
+
+    let mut stale = Vec::new();
+    for (binary, path) in binaries {
+        let asset = release_asset_name(binary).ok_or_else(|| zb_core::Error::InvalidArgument {
+            message: format!(
+                "no release binaries are published for {}-{}",
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            ),
+        })?;
+        if !matches_release(&path, &asset, &checksums) {
+            stale.push((asset, path));
+        }
+    }
+
+    // Download and verify everything before replacing anything, so a
+    // failed check leaves both binaries as they were.
+    let mut staged = Vec::new();
+    for (asset, path) in &stale {
+        ui.heading(format!("Downloading {asset}..."))
+            .map_err(ui_error)?;
+        let bytes = client.download(asset, &checksums).await?;
+        let executable = stage_executable(path, &bytes)?;
+        if !skip_attestation {
+            verify_attestation(executable.path())?;
+        }
+        staged.push((executable, path));
+    }
+    for (executable, path) in staged {
+        executable.commit()?;
+        ui.info(format!("Updated {}", path.display()))
+            .map_err(ui_error)?;
+    }
+    if skip_attestation {
+        ui.note("Checksums matched the release; build provenance was not verified.")
+            .map_err(ui_error)?;
+    } else {
+        ui.info(format!(
+            "Verified checksums and build provenance of zb {latest}."
+        ))
+        .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn uninstall(layout: &StorageLayout, yes: bool, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    for path in [
+        &layout.root,
+        &layout.prefix,
+        &layout.cache,
+        &layout.store,
+        &layout.cellar,
+        &layout.db,
+    ] {
+        validate_privileged_path(path)?;
+    }
+    let removals = plan_removals(layout)?;
+
+    let exe = current_exe()?;
+    let binaries: Vec<PathBuf> = [exe.clone(), exe.with_file_name("zbx")]
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+
+    if !yes {
+        ui.note("This will remove zerobrew and every package it installed:")
+            .map_err(ui_error)?;
+        for (dir, removal) in &removals {
+            match removal {
+                Removal::Whole => ui.bullet(dir.display()).map_err(ui_error)?,
+                Removal::Owned(_) => ui
+                    .bullet(format!(
+                        "the files zerobrew placed in {} (other files there are kept)",
+                        dir.display()
+                    ))
+                    .map_err(ui_error)?,
+            }
+        }
+        for path in &binaries {
+            ui.bullet(path.display()).map_err(ui_error)?;
+        }
+        ui.bullet("the zerobrew block in your shell configuration")
+            .map_err(ui_error)?;
+
+        if !ui
+            .prompt_yes_no("Continue? [y/N]", PromptDefault::No)
+            .map_err(ui_error)?
+        {
+            ui.info("Aborted.").map_err(ui_error)?;
+            return Ok(());
+        }
+    }
+
+    for (dir, removal) in &removals {
+        match removal {
+            Removal::Whole => {
+                ui.heading(format!("Removing {}...", dir.display()))
+                    .map_err(ui_error)?;
+                remove_path(dir)?;
+            }
+            Removal::Owned(owned) => {
+                ui.heading(format!(
+                    "Removing zerobrew's files from {}...",
+                    dir.display()
+                ))
+                .map_err(ui_error)?;
+                remove_owned(dir, owned)?;
+                if dir.exists() {
+                    ui.info(format!(
+                        "Kept {}: it holds files zerobrew did not put there",
+                        dir.display()
+                    ))
+                    .map_err(ui_error)?;
+                }
+            }
+        }
+    }
+
+    for path in remove_shell_config()? {
+        ui.info(format!(
+            "Removed zerobrew configuration from {}",
+            path.display()
+        ))
+        .map_err(ui_error)?;
+    }
+
+    // Unlinking a running executable is fine on Unix: this process keeps its
+    // inode until it exits.
+    for path in &binaries {
+        std::fs::remove_file(path).map_err(|e| zb_core::Error::FileError {
+            message: format!("failed to remove {}: {e}", path.display()),
+        })?;
+    }
+
+    ui.heading("zerobrew has been uninstalled.")
+        .map_err(ui_error)?;
+    ui.info("Restart your terminal to drop it from PATH.")
+        .map_err(ui_error)?;
+    Ok(())
+}
+
+/// How much of a directory an uninstall may remove. The prefix can be
+/// shared, say `/usr/local`, and so can a cache, store, cellar or database
+/// moved out of the root, so each goes as a whole only when nothing in it
+/// is foreign to zerobrew, or for the prefix, when `zb init` created it.
+#[derive(Debug, PartialEq, Eq)]
+enum Removal {
+    Whole,
+    /// Only these paths, then whatever directories that empties.
+    Owned(BTreeSet<PathBuf>),
+}
+
+impl Removal {
+    fn plan(dir: &Path, owned: &BTreeSet<PathBuf>) -> Self {
+        if has_foreign_content(dir, owned) {
+            Self::Owned(
+                owned
+                    .iter()
+                    .filter(|path| path.starts_with(dir))
+                    .cloned()
+                    .collect(),
+            )
+        } else {
+            Self::Whole
+        }
+    }
+}
+
+/// What to remove of the root, the prefix and every location moved out of
+/// them, in that order. The root is zerobrew's own and always goes whole.
+fn plan_removals(layout: &StorageLayout) -> Result<Vec<(PathBuf, Removal)>, zb_core::Error> {
+    let owned = owned_paths(layout)?;
+    let db_dir = layout.db.parent().unwrap_or(&layout.root);
+    let mut dirs: Vec<&Path> = vec![&layout.root];
+    for dir in [
+        layout.prefix.as_path(),
+        &layout.cache,
+        &layout.store,
+        &layout.cellar,
+        db_dir,
+    ] {
+        if !dirs.iter().any(|taken| dir.starts_with(taken)) {
+            dirs.push(dir);
+        }
+    }
+
+    let mut removals = Vec::new();
+    for dir in dirs.into_iter().filter(|dir| dir.exists()) {
+        let removal =
+            if *dir == layout.root || (*dir == layout.prefix && dir.join(PREFIX_MARKER).exists()) {
+                Removal::Whole
+            } else {
+                Removal::plan(dir, &owned)
+            };
+        removals.push((dir.to_path_buf(), removal));
+    }
+    Ok(removals)
+}
+
+/// Everything zerobrew placed outside its root: the kegs, opt links and
+/// linked files its database records, and what it keeps in the cache,
+/// store and database locations. Recorded links that were since replaced
+/// are left out.
+fn owned_paths(layout: &StorageLayout) -> Result<BTreeSet<PathBuf>, zb_core::Error> {
+    let prefix = &layout.prefix;
+    let mut owned = BTreeSet::from([layout.root.clone(), prefix.join("tmp/rb_cache")]);
+    if layout.cellar != layout.prefix_cellar() {
+        owned.insert(layout.prefix_cellar());
+    }
+    for name in CACHE_ENTRIES {
+        owned.insert(layout.cache.join(name));
+    }
+    if let Ok(entries) = std::fs::read_dir(&layout.store) {
+        owned.extend(
+            entries
+                .flatten()
+                .filter(|entry| is_store_entry(&entry.file_name().to_string_lossy()))
+                .map(|entry| entry.path()),
+        );
+    }
+    for suffix in ["", "-wal", "-shm", "-journal"] {
+        let mut path = layout.db.clone().into_os_string();
+        path.push(suffix);
+        owned.insert(PathBuf::from(path));
+    }
+
+    if !layout.db.exists() {
+        return Ok(owned);
+    }
+    let db = Database::open(&layout.db)?;
+    let kegs = db
+        .list_installed()?
+        .into_iter()
+        .map(|keg| (keg.name, keg.version))
+        .chain(
+            db.list_all_generations()?
+                .into_iter()
+                .map(|generation| (generation.name, generation.version)),
+        );
+    for (name, version) in kegs {
+        owned.insert(layout.cellar.join(&name).join(version));
+        owned.insert(prefix.join("opt").join(name));
+    }
+    for record in db.list_keg_files()? {
+        let link = PathBuf::from(&record.linked_path);
+        let still_placed = record.mode != LinkMode::Symlink
+            || std::fs::read_link(&link)
+                .is_ok_and(|target| target == Path::new(&record.target_path));
+        if still_placed {
+            owned.insert(link);
+        }
+    }
+    Ok(owned)
+}
+
+/// What zerobrew keeps in its cache directory.
+const CACHE_ENTRIES: &[&str] = &[
+    "blobs",
+    "tmp",
+    "quarantine",
+    "relocations",
+    "api-cache.sqlite",
+    "api-cache.sqlite-wal",
+    "api-cache.sqlite-shm",
+    "api-cache.sqlite-journal",
+];
+
+/// Whether `name` in the store is an entry, its manifest or an unpacking
+/// left over, all of which start with a bottle's SHA-256.
+fn is_store_entry(name: &str) -> bool {
+    let key = name.strip_prefix('.').unwrap_or(name);
+    key.len() >= 64 && key.as_bytes()[..64].iter().all(u8::is_ascii_hexdigit)
+}
+
+/// Whether `dir` holds a file or link that is neither in `owned` nor inside
+/// something that is.
+fn has_foreign_content(dir: &Path, owned: &BTreeSet<PathBuf>) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return true;
+    };
+    entries.flatten().any(|entry| {
+        let path = entry.path();
+        if owned.contains(&path) {
+            return false;
+        }
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => has_foreign_content(&path, owned),
+            _ => true,
+        }
+    })
+}
+
+/// Remove the `owned` paths, then the directories under `dir` this leaves
+/// empty.
+fn remove_owned(dir: &Path, owned: &BTreeSet<PathBuf>) -> Result<(), zb_core::Error> {
+    for path in owned {
+        if path.symlink_metadata().is_err() {
+            continue;
+        }
+        remove_path(path)?;
+        for parent in path.ancestors().skip(1) {
+            // Fails, as it should, on anything still holding files.
+            if parent == dir || !parent.starts_with(dir) || std::fs::remove_dir(parent).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove `path`, a directory tree or a single file or link, retrying under
+/// sudo when it is not ours to remove and there is a terminal to ask for a
+/// password on.
+fn remove_path(path: &Path) -> Result<(), zb_core::Error> {
+    let removed = match path.symlink_metadata() {
+        Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+        _ => std::fs::remove_file(path),
+    };
+    let Err(err) = removed else {
+        return Ok(());
+    };
+    if !std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+        return Err(zb_core::Error::FileError {
+            message: format!("failed to remove {}: {err}", path.display()),
+        });
+    }
+
+    let status = Command::new("sudo")
+        .args(["rm", "-rf", &path.to_string_lossy()])
+        .status();
+    if !status.is_ok_and(|status| status.success()) {
+        return Err(zb_core::Error::FileError {
+            message: format!("failed to remove {}", path.display()),
+        });
+    }
+    Ok(())
+}
+
+fn current_exe() -> Result<PathBuf, zb_core::Error> {
+    std::env::current_exe()
+        .and_then(|exe| exe.canonicalize())
+        .map_err(zb_core::Error::file("failed to locate the zb executable"))
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn layout(tmp: &TempDir) -> StorageLayout {
+        let layout = StorageLayout::new(&tmp.path().join("zerobrew"), &tmp.path().join("prefix"));
+        install_jq(&layout);
+        layout
+    }
+
+    fn install_jq(layout: &StorageLayout) {
+        fs::create_dir_all(layout.cellar.join("jq/1.7")).unwrap();
+        fs::create_dir_all(layout.prefix.join("bin")).unwrap();
+        fs::create_dir_all(layout.db.parent().unwrap()).unwrap();
+        let mut db = Database::open(&layout.db).unwrap();
+        let tx = db.transaction().unwrap();
+        tx.record_install("jq", "1.7", "abc").unwrap();
+        tx.commit().unwrap();
+    }
+
+    fn removal_of<'a>(removals: &'a [(PathBuf, Removal)], dir: &Path) -> &'a Removal {
+        &removals.iter().find(|(d, _)| d == dir).unwrap().1
+    }
+
+    #[test]
+    fn a_prefix_holding_only_zerobrew_content_is_removed_whole() {
+        let tmp = TempDir::new().unwrap();
+        let layout = layout(&tmp);
+        let removals = plan_removals(&layout).unwrap();
+        assert_eq!(removal_of(&removals, &layout.root), &Removal::Whole);
+        assert_eq!(removal_of(&removals, &layout.prefix), &Removal::Whole);
+    }
+
+    #[test]
+    fn a_shared_prefix_keeps_foreign_files() {
+        let tmp = TempDir::new().unwrap();
+        let layout = layout(&tmp);
+        let foreign = layout.prefix.join("bin/someone-elses-tool");
+        fs::write(&foreign, b"#!/bin/sh\n").unwrap();
+
+        let removals = plan_removals(&layout).unwrap();
+        let Removal::Owned(owned) = removal_of(&removals, &layout.prefix) else {
+            panic!("a prefix with foreign files must not be removed whole");
+        };
+        remove_owned(&layout.prefix, owned).unwrap();
+        assert!(foreign.exists());
+        assert!(!layout.cellar.exists());
+
+        // A prefix `zb init` created is zerobrew's, whatever it holds.
+        fs::write(layout.prefix.join(PREFIX_MARKER), b"").unwrap();
+        let removals = plan_removals(&layout).unwrap();
+        assert_eq!(removal_of(&removals, &layout.prefix), &Removal::Whole);
+    }
+
+    #[test]
+    fn moved_locations_are_removed_with_the_same_guard() {
+        let tmp = TempDir::new().unwrap();
+        let shared = tmp.path().join("shared");
+        let layout = StorageLayout::new(&tmp.path().join("zerobrew"), &tmp.path().join("prefix"))
+            .with_cache_dir(tmp.path().join("cache"))
+            .with_cellar_dir(tmp.path().join("kegs"))
+            .with_db_path(shared.join("zb.sqlite3"));
+        install_jq(&layout);
+        fs::create_dir_all(layout.cache.join("blobs")).unwrap();
+        fs::write(shared.join("notes.txt"), b"mine").unwrap();
+
+        let removals = plan_removals(&layout).unwrap();
+        assert_eq!(removal_of(&removals, &layout.cache), &Removal::Whole);
+        assert_eq!(removal_of(&removals, &layout.cellar), &Removal::Whole);
+        let Removal::Owned(owned) = removal_of(&removals, &shared) else {
+            panic!("a shared database directory must not be removed whole");
+        };
+        remove_owned(&shared, owned).unwrap();
+        assert!(!layout.db.exists());
+        assert!(shared.join("notes.txt").exists());
+    }
+}
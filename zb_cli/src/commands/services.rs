@@ -0,0 +1,369 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Timelike};
+use console::style;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use zb_core::{CronSchedule, HealthCheck, RunType, ServiceDefinition};
+use zb_io::{Database, ServiceManager, ServiceOverrides, ServiceState, ServiceStatus};
+
+use crate::cli::ServicesCommands;
+use crate::ui::StdUi;
+use crate::utils::normalize_formula_name;
+
+/// How far ahead to look for the next run of a cron schedule.
+const CRON_HORIZON_DAYS: i64 = 366;
+
+pub async fn execute(
+    installer: &mut zb_io::Installer,
+    root: &Path,
+    command: Option<ServicesCommands>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let services = ServiceManager::new(Database::open(&root.join("db/zb.sqlite3"))?)?;
+    match command.unwrap_or(ServicesCommands::List) {
+        ServicesCommands::List => list(&services, ui),
+        ServicesCommands::Start { formula } => {
+            let name = normalize_formula_name(&formula)?;
+            let definition = installer.service_definition(&name).await?;
+            let path = services.start(&name, &definition)?;
+            ui.heading(format!("Started {}", style(&name).bold()))
+                .map_err(ui_error)?;
+            ui.bullet(path.display()).map_err(ui_error)?;
+            Ok(())
+        }
+        ServicesCommands::Stop { formula } => {
+            let name = normalize_formula_name(&formula)?;
+            services.stop(&name)?;
+            ui.heading(format!("Stopped {}", style(&name).bold()))
+                .map_err(ui_error)?;
+            Ok(())
+        }
+        ServicesCommands::Run { formula } => {
+            let name = normalize_formula_name(&formula)?;
+            let definition = installer.service_definition(&name).await?;
+            run_foreground(&services, &name, &definition, ui)
+        }
+        ServicesCommands::Status { formula } => {
+            let name = normalize_formula_name(&formula)?;
+            status(&services, &name, ui)
+        }
+        ServicesCommands::Edit {
+            formula,
+            env,
+            unset_env,
+            arg,
+            workdir,
+            restart,
+            health_port,
+            health_cmd,
+            no_health_check,
+            reset,
+        } => {
+            let name = normalize_formula_name(&formula)?;
+            let definition = installer.service_definition(&name).await?;
+            let mut overrides = if reset {
+                ServiceOverrides::default()
+            } else {
+                services
+                    .settings(&name)?
+                    .map(|settings| settings.overrides)
+                    .unwrap_or_default()
+            };
+            overrides.environment.extend(env);
+            for key in &unset_env {
+                overrides.environment.remove(key);
+            }
+            if !arg.is_empty() {
+                overrides.args = arg;
+            }
+            if let Some(dir) = workdir {
+                if !dir.is_dir() {
+                    return Err(zb_core::Error::InvalidArgument {
+                        message: format!("{} is not a directory", dir.display()),
+                    });
+                }
+                overrides.working_dir = Some(
+                    std::path::absolute(&dir)
+                        .map_err(zb_core::Error::file("failed to resolve working directory"))?
+                        .to_string_lossy()
+                        .into_owned(),
+                );
+            }
+            if restart.is_some() {
+                overrides.restart = restart;
+            }
+            if let Some(port) = health_port {
+                overrides.health_check = Some(HealthCheck::Tcp(port));
+            } else if let Some(command) = health_cmd {
+                overrides.health_check = Some(HealthCheck::Command(command));
+            } else if no_health_check {
+                overrides.health_check = None;
+            }
+            services.set_overrides(&name, &overrides)?;
+            ui.heading(format!("Updated {}", style(&name).bold()))
+                .map_err(ui_error)?;
+            restart_if_started(&services, &name, &definition, ui)
+        }
+        ServicesCommands::Schedule {
+            formula,
+            cron,
+            interval,
+            clear: _,
+        } => {
+            let name = normalize_formula_name(&formula)?;
+            let schedule = match (cron, interval) {
+                (Some(expression), _) => Some(RunType::Cron(CronSchedule::parse(&expression)?)),
+                (None, Some(secs)) => Some(RunType::Interval(secs)),
+                (None, None) => None,
+            };
+            schedule_service(installer, &services, &name, schedule, ui).await
+        }
+    }
+}
+
+async fn schedule_service(
+    installer: &mut zb_io::Installer,
+    services: &ServiceManager,
+    name: &str,
+    schedule: Option<RunType>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    // Fail before persisting anything for formulas without a service.
+    let definition = installer.service_definition(name).await?;
+    services.set_schedule(name, schedule.as_ref())?;
+
+    let message = match &schedule {
+        Some(schedule) => format!("Scheduled {} ({schedule})", style(name).bold()),
+        None => format!("Cleared the schedule of {}", style(name).bold()),
+    };
+    ui.heading(message).map_err(ui_error)?;
+    restart_if_started(services, name, &definition, ui)
+}
+
+fn run_foreground(
+    services: &ServiceManager,
+    name: &str,
+    definition: &ServiceDefinition,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    if services.file(name).is_some() {
+        ui.warn(format!(
+            "{name} is also started as a service; stop it first if they would conflict"
+        ))
+        .map_err(ui_error)?;
+    }
+    let mut command = services.foreground_command(name, definition)?;
+    let command_line: Vec<_> = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect();
+    ui.heading(format!("Running {} in the foreground", style(name).bold()))
+        .map_err(ui_error)?;
+    ui.bullet(command_line.join(" ")).map_err(ui_error)?;
+
+    let err = command.exec();
+    Err(zb_core::Error::ExecutionError {
+        message: format!("failed to run the {name} service: {err}"),
+    })
+}
+
+/// Apply changed settings to a started service, or say when they apply.
+fn restart_if_started(
+    services: &ServiceManager,
+    name: &str,
+    definition: &ServiceDefinition,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    if services.file(name).is_some() {
+        services.start(name, definition)?;
+        ui.bullet("restarted with the new settings")
+            .map_err(ui_error)?;
+    } else {
+        ui.note(format!("takes effect on `zb services start {name}`"))
+            .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn status(services: &ServiceManager, name: &str, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    let status = services.status(name)?;
+    let overrides = services
+        .settings(name)?
+        .map(|settings| settings.overrides)
+        .unwrap_or_default();
+
+    ui.heading(style(name).bold()).map_err(ui_error)?;
+    let mut field = |label: &str, value: String| {
+        ui.println(format!("{:<10}  {value}", style(label).dim()))
+            .map_err(ui_error)
+    };
+    field("State", state_label(status.state))?;
+    if let Some(file) = &status.file {
+        field("File", file.display().to_string())?;
+    }
+    if let Some(schedule) = &status.schedule {
+        let next = next_run(&status, Local::now())
+            .map(|next| format!(", next run {}", next.format("%Y-%m-%d %H:%M")))
+            .unwrap_or_default();
+        field("Schedule", format!("{schedule}{next}"))?;
+    }
+    if let Some(restart) = overrides.restart {
+        field("Restart", restart.to_string())?;
+    }
+    if let Some(check) = &overrides.health_check {
+        field("Health", check.to_string())?;
+    }
+    if let Some(dir) = &overrides.working_dir {
+        field("Workdir", dir.clone())?;
+    }
+    if !overrides.args.is_empty() {
+        field("Args", overrides.args.join(" "))?;
+    }
+    for (key, value) in &overrides.environment {
+        field("Env", format!("{key}={value}"))?;
+    }
+    Ok(())
+}
+
+fn list(services: &ServiceManager, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    let statuses = services.list()?;
+    if statuses.is_empty() {
+        ui.println("No services started.").map_err(ui_error)?;
+        return Ok(());
+    }
+
+    let name_width = statuses.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    let now = Local::now();
+    for status in &statuses {
+        let state = state_label(status.state);
+        let schedule = match (&status.schedule, next_run(status, now)) {
+            (Some(schedule), Some(next)) => {
+                format!("{schedule}, next run {}", next.format("%Y-%m-%d %H:%M"))
+            }
+            (Some(schedule), None) => schedule.to_string(),
+            (None, _) => String::new(),
+        };
+        ui.println(format!(
+            "{}  {:<18}  {}",
+            style(format!("{:<name_width$}", status.name)).bold(),
+            state,
+            style(schedule).dim()
+        ))
+        .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn state_label(state: ServiceState) -> String {
+    match state {
+        ServiceState::Running { pid: Some(pid) } => format!("running (pid {pid})"),
+        ServiceState::Running { pid: None } => "running".to_string(),
+        ServiceState::Unhealthy { pid: Some(pid) } => format!("unhealthy (pid {pid})"),
+        ServiceState::Unhealthy { pid: None } => "unhealthy".to_string(),
+        ServiceState::Scheduled => "scheduled".to_string(),
+        ServiceState::Stopped => "stopped".to_string(),
+    }
+}
+
+/// When a loaded scheduled service will next run, if it will.
+fn next_run(status: &ServiceStatus, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    if status.state == ServiceState::Stopped {
+        return None;
+    }
+    match status.schedule.as_ref()? {
+        RunType::Immediate => None,
+        RunType::Interval(secs) => {
+            let started: DateTime<Local> = status.started_at?.into();
+            Some(next_interval_run(started, *secs, now))
+        }
+        RunType::Cron(schedule) => {
+            let next = next_cron_run(schedule, now.naive_local())?;
+            Local.from_local_datetime(&next).earliest()
+        }
+    }
+}
+
+fn next_interval_run(
+    started: DateTime<Local>,
+    interval_secs: u64,
+    now: DateTime<Local>,
+) -> DateTime<Local> {
+    let interval = interval_secs as i64;
+    let elapsed = now.signed_duration_since(started).num_seconds().max(0);
+    started + Duration::seconds((elapsed / interval + 1) * interval)
+}
+
+/// The first minute after `after` that `schedule` matches.
+fn next_cron_run(schedule: &CronSchedule, after: NaiveDateTime) -> Option<NaiveDateTime> {
+    let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+    let horizon = start + Duration::days(CRON_HORIZON_DAYS);
+    let mut candidate = start;
+    while candidate < horizon {
+        if schedule.matches(
+            candidate.minute(),
+            candidate.hour(),
+            candidate.day(),
+            candidate.month(),
+            candidate.weekday().num_days_from_sunday(),
+        ) {
+            return Some(candidate);
+        }
+        candidate += Duration::minutes(1);
+    }
+    None
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn next_cron_run_finds_following_match() {
+        let nightly = CronSchedule::parse("0 3 * * *").unwrap();
+        assert_eq!(
+            next_cron_run(&nightly, at(2026, 10, 16, 2, 59)),
+            Some(at(2026, 10, 16, 3, 0))
+        );
+        assert_eq!(
+            next_cron_run(&nightly, at(2026, 10, 16, 3, 0)),
+            Some(at(2026, 10, 17, 3, 0))
+        );
+
+        // 2026-10-16 is a Friday.
+        let weekdays = CronSchedule::parse("30 9 * * 1-5").unwrap();
+        assert_eq!(
+            next_cron_run(&weekdays, at(2026, 10, 16, 10, 0)),
+            Some(at(2026, 10, 19, 9, 30))
+        );
+    }
+
+    #[test]
+    fn next_cron_run_gives_up_on_impossible_dates() {
+        let never = CronSchedule::parse("0 0 31 2 *").unwrap();
+        assert_eq!(next_cron_run(&never, at(2026, 1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn next_interval_run_counts_from_start() {
+        let started = Local.with_ymd_and_hms(2026, 10, 16, 12, 0, 0).unwrap();
+        let now = started + Duration::seconds(5400);
+        assert_eq!(
+            next_interval_run(started, 3600, now),
+            started + Duration::seconds(7200)
+        );
+    }
+}
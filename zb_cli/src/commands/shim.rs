@@ -0,0 +1,252 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use console::style;
+
+use crate::cli::ShimCommands;
+use crate::ui::StdUi;
+use crate::utils::shell_quote;
+
+/// Marks a `brew` written by `zb shim install`, so it is never confused
+/// with a real Homebrew.
+const SHIM_MARKER: &str = "# zerobrew brew shim";
+
+/// Where Homebrew installs `brew`; unknown commands are passed on to the
+/// first one found.
+const HOMEBREW_BINARIES: &[&str] = &[
+    "/opt/homebrew/bin/brew",
+    "/usr/local/bin/brew",
+    "/home/linuxbrew/.linuxbrew/bin/brew",
+];
+
+pub fn execute(
+    root: &Path,
+    prefix: &Path,
+    command: ShimCommands,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let path = shim_path(prefix);
+    match command {
+        ShimCommands::Install { force } => {
+            if path.exists() && !is_shim(&path) && !force {
+                return Err(zb_core::Error::InvalidArgument {
+                    message: format!(
+                        "{} exists and is not a zerobrew shim (use --force to replace it)",
+                        path.display()
+                    ),
+                });
+            }
+            let zb = std::env::current_exe().map_err(|e| zb_core::Error::FileError {
+                message: format!("failed to locate the zb executable: {e}"),
+            })?;
+            write_shim(&path, &render_shim(&zb, root, prefix))?;
+            ui.heading(format!(
+                "Installed {} shim at {}",
+                style("brew").bold(),
+                path.display()
+            ))
+            .map_err(ui_error)?;
+            ui.note("install, uninstall, list, info, services, bundle and --prefix run zb")
+                .map_err(ui_error)?;
+        }
+        ShimCommands::Uninstall => {
+            if !path.exists() {
+                ui.info("No brew shim installed.").map_err(ui_error)?;
+                return Ok(());
+            }
+            if !is_shim(&path) {
+                return Err(zb_core::Error::InvalidArgument {
+                    message: format!("{} is not a zerobrew shim", path.display()),
+                });
+            }
+            fs::remove_file(&path).map_err(|e| zb_core::Error::FileError {
+                message: format!("failed to remove {}: {e}", path.display()),
+            })?;
+            ui.heading(format!("Removed {}", path.display()))
+                .map_err(ui_error)?;
+        }
+    }
+    Ok(())
+}
+
+fn shim_path(prefix: &Path) -> PathBuf {
+    prefix.join("bin/brew")
+}
+
+fn is_shim(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|contents| contents.contains(SHIM_MARKER))
+}
+
+fn write_shim(path: &Path, contents: &str) -> Result<(), zb_core::Error> {
+    let file_error = |e: std::io::Error| zb_core::Error::FileError {
+        message: format!("failed to write {}: {e}", path.display()),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(file_error)?;
+    }
+    // Replace rather than write through, in case `brew` is a symlink.
+    let _ = fs::remove_file(path);
+    fs::write(path, contents).map_err(file_error)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(file_error)
+}
+
+/// A `brew` script running `zb` at `zb` on `root` and `prefix` for the
+/// commands zerobrew implements, and a real Homebrew for the rest.
+fn render_shim(zb: &Path, root: &Path, prefix: &Path) -> String {
+    let homebrew = HOMEBREW_BINARIES.join(" ");
+    format!(
+        r#"#!/bin/sh
+{SHIM_MARKER}: generated by `zb shim install`, removed by `zb shim uninstall`.
+ZB={zb}
+ZB_ROOT={root}
+ZB_PREFIX={prefix}
+
+zb() {{
+    exec "$ZB" --root "$ZB_ROOT" --prefix "$ZB_PREFIX" "$@"
+}}
+
+passthrough() {{
+    for brew in {homebrew}; do
+        if [ -x "$brew" ] && [ "$brew" != "$ZB_PREFIX/bin/brew" ]; then
+            exec "$brew" "$@"
+        fi
+    done
+    echo "brew: '${{1:-}}' is not supported by the zerobrew shim and Homebrew is not installed" >&2
+    exit 1
+}}
+
+case "${{1:-}}" in
+    install|reinstall|uninstall|remove|rm|list|ls|info|services|bundle|--prefix|--cellar) ;;
+    *) passthrough "$@" ;;
+esac
+
+cmd=$1
+shift
+# Drop flags zb has no equivalent for.
+n=$#
+while [ "$n" -gt 0 ]; do
+    arg=$1
+    shift
+    n=$((n - 1))
+    case "$arg" in
+        --formula|--formulae|--installed|--quiet|-q|-1) ;;
+        *) set -- "$@" "$arg" ;;
+    esac
+done
+
+case "$cmd" in
+    install|reinstall) zb install "$@" ;;
+    uninstall|remove|rm) zb uninstall "$@" ;;
+    list|ls) zb list "$@" ;;
+    info) zb info "$@" ;;
+    services) zb services "$@" ;;
+    bundle)
+        case "${{1:-}}" in
+            ""|-*) zb bundle install "$@" ;;
+            *) zb bundle "$@" ;;
+        esac
+        ;;
+    --prefix)
+        if [ $# -eq 0 ]; then
+            echo "$ZB_PREFIX"
+        else
+            for formula in "$@"; do
+                echo "$ZB_PREFIX/opt/${{formula##*/}}"
+            done
+        fi
+        ;;
+    --cellar) echo "$ZB_PREFIX/Cellar" ;;
+esac
+"#,
+        zb = shell_quote(&zb.to_string_lossy()),
+        root = shell_quote(&root.to_string_lossy()),
+        prefix = shell_quote(&prefix.to_string_lossy()),
+    )
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn run_shim(tmp: &TempDir, zb: &Path, args: &[&str]) -> String {
+        let prefix = tmp.path().join("it's a prefix");
+        let shim = shim_path(&prefix);
+        write_shim(&shim, &render_shim(zb, &tmp.path().join("root"), &prefix)).unwrap();
+        let output = Command::new("sh").arg(&shim).args(args).output().unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn answers_prefix_queries() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("it's a prefix");
+
+        let out = run_shim(&tmp, Path::new("/bin/false"), &["--prefix"]);
+        assert_eq!(out, format!("{}\n", prefix.display()));
+
+        let out = run_shim(
+            &tmp,
+            Path::new("/bin/false"),
+            &[
+                "--prefix",
+                "--installed",
+                "openssl@3",
+                "homebrew/core/readline",
+            ],
+        );
+        assert_eq!(
+            out,
+            format!("{0}/opt/openssl@3\n{0}/opt/readline\n", prefix.display())
+        );
+    }
+
+    #[test]
+    fn translates_commands_to_zb() {
+        let tmp = TempDir::new().unwrap();
+        let out = run_shim(
+            &tmp,
+            Path::new("/bin/echo"),
+            &["install", "--formula", "jq", "wget"],
+        );
+        let prefix = tmp.path().join("it's a prefix");
+        let root = tmp.path().join("root");
+        assert_eq!(
+            out,
+            format!(
+                "--root {} --prefix {} install jq wget\n",
+                root.display(),
+                prefix.display()
+            )
+        );
+
+        let out = run_shim(&tmp, Path::new("/bin/echo"), &["bundle", "--file=Brewfile"]);
+        assert!(out.ends_with(" bundle install --file=Brewfile\n"), "{out}");
+    }
+
+    #[test]
+    fn only_shims_are_recognized() {
+        let tmp = TempDir::new().unwrap();
+        let brew = tmp.path().join("brew");
+        fs::write(&brew, "#!/bin/bash\n# Homebrew\n").unwrap();
+        assert!(!is_shim(&brew));
+
+        write_shim(
+            &brew,
+            &render_shim(Path::new("/bin/zb"), tmp.path(), tmp.path()),
+        )
+        .unwrap();
+        assert!(is_shim(&brew));
+    }
+}
@@ -0,0 +1,122 @@
+use indicatif::HumanBytes;
+use zb_io::StatsSummary;
+
+use crate::ui::StdUi;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    json: bool,
+    clear: bool,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    if clear {
+        let cleared = installer.clear_stats()?;
+        return ui
+            .heading(format!("Cleared {cleared} recorded installs"))
+            .map_err(ui_error);
+    }
+
+    let stats = installer.stats()?;
+    if json {
+        let output =
+            serde_json::to_string_pretty(&stats).map_err(|e| zb_core::Error::FileError {
+                message: format!("failed to encode stats: {e}"),
+            })?;
+        return ui.println(output).map_err(ui_error);
+    }
+
+    if stats.installs == 0 {
+        ui.println("No installs recorded.").map_err(ui_error)?;
+    } else {
+        for line in render(&stats) {
+            ui.println(line).map_err(ui_error)?;
+        }
+    }
+    if !installer.records_stats() {
+        ui.note(
+            "Stats are off; set ZEROBREW_RECORD_STATS=1 to record installs (they never leave this machine).",
+        )
+        .map_err(ui_error)?;
+    }
+    Ok(())
+}
+
+fn render(stats: &StatsSummary) -> Vec<String> {
+    let bottles = stats.installs - stats.source_builds;
+    let mut lines = vec![format!(
+        "Installs:            {} ({} bottles, {} source builds)",
+        stats.installs, bottles, stats.source_builds
+    )];
+    lines.push(format!(
+        "Mean install time:   {}",
+        seconds(stats.total_duration_ms / stats.installs)
+    ));
+    if let Some(rate) = (stats.cache_hits * 100).checked_div(bottles) {
+        lines.push(format!(
+            "Cache hit rate:      {}% ({} of {} bottles)",
+            rate, stats.cache_hits, bottles
+        ));
+    }
+    if let Some(per_second) = (stats.downloaded_bytes * 1000).checked_div(stats.download_ms) {
+        lines.push(format!(
+            "Download throughput: {}/s ({} in {})",
+            HumanBytes(per_second),
+            HumanBytes(stats.downloaded_bytes),
+            seconds(stats.download_ms)
+        ));
+    }
+    if let Some(mean) = stats.relocation_ms.checked_div(stats.relocations) {
+        lines.push(format!(
+            "Relocation:          {} kegs, {} on average",
+            stats.relocations,
+            seconds(mean)
+        ));
+    }
+    if !stats.slowest.is_empty() {
+        lines.push("Slowest installs:".to_string());
+        for (name, duration_ms) in &stats.slowest {
+            lines.push(format!("  {name:<20} {}", seconds(*duration_ms)));
+        }
+    }
+    lines
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_reports_rates_and_skips_empty_sections() {
+        let stats = StatsSummary {
+            installs: 4,
+            source_builds: 0,
+            cache_hits: 1,
+            total_duration_ms: 8_000,
+            downloaded_bytes: 3 * 1024 * 1024,
+            download_ms: 1_500,
+            relocations: 0,
+            relocation_ms: 0,
+            since: Some(0),
+            slowest: vec![("llvm".to_string(), 5_000)],
+        };
+
+        let lines = render(&stats);
+
+        assert!(lines[0].contains("4 (4 bottles, 0 source builds)"));
+        assert!(lines[1].ends_with("2.0s"));
+        assert!(lines[2].contains("25% (1 of 4 bottles)"));
+        assert!(lines[3].contains("2.00 MiB/s"));
+        assert!(!lines.iter().any(|l| l.starts_with("Relocation")));
+        assert!(lines.last().unwrap().contains("llvm"));
+    }
+}
@@ -0,0 +1,99 @@
+use console::style;
+use zb_io::StoreKeg;
+
+use crate::cli::StoreCommands;
+use crate::ui::StdUi;
+
+/// Key characters `zb store ls` prints; `zb store info` accepts any unique
+/// prefix.
+const SHORT_KEY_LEN: usize = 12;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    command: Option<StoreCommands>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    match command.unwrap_or(StoreCommands::Ls { json: false }) {
+        StoreCommands::Ls { json } => {
+            let entries = installer.store_entries()?;
+            if json {
+                return print_json(&entries);
+            }
+            if entries.is_empty() {
+                ui.info("The store is empty.").map_err(ui_error)?;
+            }
+            for entry in &entries {
+                ui.println(format!(
+                    "{}  {:>2} {}  {}",
+                    style(&entry.key[..entry.key.len().min(SHORT_KEY_LEN)]).bold(),
+                    entry.refcount,
+                    if entry.refcount == 1 { "ref " } else { "refs" },
+                    kegs_label(&entry.kegs)
+                ))
+                .map_err(ui_error)?;
+            }
+            Ok(())
+        }
+        StoreCommands::Info { key, json } => {
+            let entry = installer.store_entry(&key)?;
+            if json {
+                return print_json(&entry);
+            }
+            ui.heading(style(&entry.key).bold()).map_err(ui_error)?;
+            let mut field = |label: &str, value: String| {
+                ui.println(format!("{:<8}  {value}", style(label).dim()))
+                    .map_err(ui_error)
+            };
+            field("Path", entry.path.display().to_string())?;
+            field("Refs", entry.refcount.to_string())?;
+            field("Kegs", kegs_label(&entry.kegs))?;
+            Ok(())
+        }
+    }
+}
+
+/// `name version` for each keg, marking generations kept for rollback.
+fn kegs_label(kegs: &[StoreKeg]) -> String {
+    if kegs.is_empty() {
+        return "unreferenced".to_string();
+    }
+    kegs.iter()
+        .map(|keg| {
+            let kept = if keg.installed { "" } else { " (kept)" };
+            format!("{} {}{kept}", keg.name, keg.version)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_json<T: serde::Serialize + ?Sized>(value: &T) -> Result<(), zb_core::Error> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(zb_core::Error::file("failed to encode store entries"))?;
+    println!("{json}");
+    Ok(())
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kegs_label_marks_kept_generations() {
+        let keg = |version: &str, installed| StoreKeg {
+            name: "jq".into(),
+            version: version.into(),
+            installed,
+        };
+        assert_eq!(
+            kegs_label(&[keg("1.7.1", true), keg("1.7.0", false)]),
+            "jq 1.7.1, jq 1.7.0 (kept)"
+        );
+        assert_eq!(kegs_label(&[]), "unreferenced");
+    }
+}
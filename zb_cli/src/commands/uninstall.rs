@@ -6,9 +6,18 @@ pub fn execute(
     installer: &mut zb_io::Installer,
     formulas: Vec<String>,
     all: bool,
+    tag: Option<String>,
     ui: &mut StdUi,
 ) -> Result<(), zb_core::Error> {
-    let formulas = if all {
+    let formulas = if let Some(tag) = tag {
+        let tagged = installer.installed_with_tag(&tag)?;
+        if tagged.is_empty() {
+            ui.info(format!("No installed packages tagged {tag}."))
+                .map_err(ui_error)?;
+            return Ok(());
+        }
+        tagged.into_iter().map(|k| k.name).collect()
+    } else if all {
         let installed = installer.list_installed()?;
         if installed.is_empty() {
             ui.info("No formulas installed.").map_err(ui_error)?;
@@ -63,7 +72,7 @@ pub fn execute(
 }
 
 fn ui_error(err: std::io::Error) -> zb_core::Error {
-    zb_core::Error::StoreCorruption {
+    zb_core::Error::FileError {
         message: format!("failed to write CLI output: {err}"),
     }
 }
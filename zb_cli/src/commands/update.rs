@@ -1,6 +1,6 @@
 use console::style;
 
-pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
+pub async fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
     let removed = installer.clear_api_cache()?;
     if removed == 0 {
         println!("{} No cached entries to clear.", style("==>").cyan().bold());
@@ -12,6 +12,26 @@ pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
             if removed == 1 { "entry" } else { "entries" }
         );
     }
+    let refresh = installer.refresh_formula_index().await?;
+    println!(
+        "{} Stored metadata for {} formulas ({} new or changed).",
+        style("==>").cyan().bold(),
+        style(refresh.formulas).green().bold(),
+        refresh.changed
+    );
+    // Only `zb which` needs the executables index, so a failed fetch should
+    // not fail the update.
+    match installer.refresh_executables_index().await {
+        Ok(count) => println!(
+            "{} Indexed {} executables for `zb which`.",
+            style("==>").cyan().bold(),
+            style(count).green().bold()
+        ),
+        Err(e) => eprintln!(
+            "{} Could not refresh the executables index: {e}",
+            style("Warning:").yellow().bold()
+        ),
+    }
     println!(
         "{}",
         style("Run `zb outdated` to check package updates.").dim()
@@ -1,10 +1,8 @@
 use console::style;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
 use std::time::Instant;
-use zb_io::{InstallProgress, ProgressCallback};
+use zb_io::{channel_callback, progress_channel};
 
+use crate::progress::ProgressRenderer;
 use crate::ui::StdUi;
 use crate::utils::normalize_formula_name;
 
@@ -69,101 +67,8 @@ pub async fn execute(
     ui.heading(format!("Upgrading {}...", style(outdated.len()).bold()))
         .map_err(ui_error)?;
 
-    let multi = MultiProgress::new();
-    let bars: Arc<Mutex<HashMap<String, ProgressBar>>> = Arc::new(Mutex::new(HashMap::new()));
-
-    let spinner_style = ProgressStyle::default_spinner()
-        .template("    {prefix:<16} {spinner:.cyan} {msg}")
-        .unwrap()
-        .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏");
-
-    let done_style = ProgressStyle::default_spinner()
-        .template("    {prefix:<16} {msg}")
-        .unwrap();
-
-    let bars_clone = bars.clone();
-    let multi_clone = multi.clone();
-    let spinner_style_clone = spinner_style.clone();
-    let done_style_clone = done_style.clone();
-
-    let progress_callback: Arc<ProgressCallback> = Arc::new(Box::new(move |event| {
-        let mut bars = bars_clone.lock().unwrap();
-        match event {
-            InstallProgress::DownloadStarted { name, total_bytes } => {
-                let pb = if let Some(total) = total_bytes {
-                    let pb = multi_clone.add(ProgressBar::new(total));
-                    pb.set_style(
-                        ProgressStyle::default_bar()
-                            .template("    {prefix:<16} {bar:25.cyan/dim} {bytes:>10}/{total_bytes:<10} {eta:>6}")
-                            .unwrap()
-                            .progress_chars("━━╸"),
-                    );
-                    pb
-                } else {
-                    let pb = multi_clone.add(ProgressBar::new_spinner());
-                    pb.set_style(spinner_style_clone.clone());
-                    pb.set_message("downloading...");
-                    pb.enable_steady_tick(std::time::Duration::from_millis(80));
-                    pb
-                };
-                pb.set_prefix(name.clone());
-                bars.insert(name, pb);
-            }
-            InstallProgress::DownloadProgress {
-                name,
-                downloaded,
-                total_bytes,
-            } => {
-                if let Some(pb) = bars.get(&name)
-                    && total_bytes.is_some()
-                {
-                    pb.set_position(downloaded);
-                }
-            }
-            InstallProgress::DownloadCompleted { name, total_bytes } => {
-                if let Some(pb) = bars.get(&name) {
-                    if total_bytes > 0 {
-                        pb.set_position(total_bytes);
-                    }
-                    pb.set_style(spinner_style_clone.clone());
-                    pb.set_message("unpacking...");
-                    pb.enable_steady_tick(std::time::Duration::from_millis(80));
-                }
-            }
-            InstallProgress::UnpackStarted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message("unpacking...");
-                }
-            }
-            InstallProgress::UnpackCompleted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message("unpacked");
-                }
-            }
-            InstallProgress::LinkStarted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message("linking...");
-                }
-            }
-            InstallProgress::LinkCompleted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message("linked");
-                }
-            }
-            InstallProgress::LinkSkipped { name, reason } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_message(format!("keg-only ({})", reason));
-                }
-            }
-            InstallProgress::InstallCompleted { name } => {
-                if let Some(pb) = bars.get(&name) {
-                    pb.set_style(done_style_clone.clone());
-                    pb.set_message(format!("{} upgraded", style("✓").green()));
-                    pb.finish();
-                }
-            }
-        }
-    }));
+    let (events, receiver) = progress_channel();
+    let renderer = tokio::spawn(ProgressRenderer::new("upgraded").run(receiver));
 
     let mut upgraded = 0usize;
     let mut errors: Vec<(String, zb_core::Error)> = Vec::new();
@@ -177,7 +82,7 @@ pub async fn execute(
                 name,
                 build_from_source,
                 !no_link,
-                Some(progress_callback.clone()),
+                Some(channel_callback(events.clone())),
             )
             .await
         {
@@ -192,14 +97,8 @@ pub async fn execute(
         }
     }
 
-    {
-        let bars = bars.lock().unwrap();
-        for (_, pb) in bars.iter() {
-            if !pb.is_finished() {
-                pb.finish();
-            }
-        }
-    }
+    drop(events);
+    let _ = renderer.await;
 
     let elapsed = start.elapsed();
     ui.blank_line().map_err(ui_error)?;
@@ -0,0 +1,118 @@
+use clap_complete::shells::Shell;
+use console::style;
+
+use crate::ui::StdUi;
+
+pub fn execute(
+    installer: &zb_io::Installer,
+    command: String,
+    suggest: bool,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let providers = installer.which(&command)?;
+    if suggest {
+        return print_suggestion(&command, &providers, ui);
+    }
+    if providers.is_empty() {
+        return Err(zb_core::Error::InvalidArgument {
+            message: format!(
+                "no known formula provides '{command}' (run `zb update` to refresh the executables index)"
+            ),
+        });
+    }
+
+    if let Some(installed) = &providers.installed {
+        ui.println(format!("{installed} {}", style("(installed)").green()))
+            .map_err(ui_error)?;
+    }
+    for formula in &providers.available {
+        if providers.installed.as_ref() != Some(formula) {
+            ui.println(formula).map_err(ui_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// Called by the shell hook when `command` is not on `PATH`; everything
+/// goes to stderr so the hook can leave the exit status to the shell.
+fn print_suggestion(
+    command: &str,
+    providers: &zb_io::CommandProviders,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    ui.eprintln(format!("{command}: command not found"))
+        .map_err(ui_error)?;
+    if let Some(installed) = &providers.installed {
+        ui.eprintln(format!(
+            "It is linked by {installed}, but the zerobrew prefix is not on your PATH."
+        ))
+        .map_err(ui_error)?;
+        return Ok(());
+    }
+    match providers.available.as_slice() {
+        [] => {}
+        [formula] => {
+            ui.eprintln(format!("Install it with: zb install {formula}"))
+                .map_err(ui_error)?;
+        }
+        formulas => {
+            ui.eprintln("It is provided by:").map_err(ui_error)?;
+            for formula in formulas {
+                ui.eprintln(format!("  zb install {formula}"))
+                    .map_err(ui_error)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn print_hook(shell: Shell, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    ui.println(render_hook(shell)?).map_err(ui_error)
+}
+
+/// A command-not-found handler for `shell` that asks `zb which` for a
+/// formula and keeps the shell's usual exit status of 127.
+fn render_hook(shell: Shell) -> Result<&'static str, zb_core::Error> {
+    match shell {
+        Shell::Bash => Ok(r#"command_not_found_handle() {
+    command zb which --suggest "$1"
+    return 127
+}"#),
+        Shell::Zsh => Ok(r#"command_not_found_handler() {
+    command zb which --suggest "$1"
+    return 127
+}"#),
+        Shell::Fish => Ok(r#"function fish_command_not_found
+    command zb which --suggest $argv[1]
+end"#),
+        other => Err(zb_core::Error::InvalidArgument {
+            message: format!("no command-not-found hook for {other}; use bash, zsh or fish"),
+        }),
+    }
+}
+
+fn ui_error(err: std::io::Error) -> zb_core::Error {
+    zb_core::Error::FileError {
+        message: format!("failed to write CLI output: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks_call_zb_which_and_keep_status_127() {
+        for shell in [Shell::Bash, Shell::Zsh] {
+            let hook = render_hook(shell).unwrap();
+            assert!(hook.contains("zb which --suggest \"$1\""));
+            assert!(hook.contains("return 127"));
+        }
+        assert!(
+            render_hook(Shell::Fish)
+                .unwrap()
+                .starts_with("function fish_command_not_found")
+        );
+        assert!(render_hook(Shell::PowerShell).is_err());
+    }
+}
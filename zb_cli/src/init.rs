@@ -19,6 +19,14 @@ impl std::fmt::Display for InitError {
     }
 }
 
+impl From<InitError> for zb_core::Error {
+    fn from(err: InitError) -> Self {
+        zb_core::Error::InitError {
+            message: err.to_string(),
+        }
+    }
+}
+
 impl From<std::io::Error> for InitError {
     fn from(err: std::io::Error) -> Self {
         InitError::Message(format!("I/O error: {err}"))
@@ -50,10 +58,42 @@ pub fn is_writable(path: &Path) -> bool {
 /// prefix must be no longer than the original.  `/opt/homebrew` = 13 chars.
 const MAX_PREFIX_LEN_MACOS: usize = 13;
 
+/// Left by `zb init` in a prefix it created, so `zb self uninstall` knows the
+/// whole directory is zerobrew's to remove.
+pub const PREFIX_MARKER: &str = ".zerobrew-prefix";
+
+fn init_dirs(root: &Path, prefix: &Path) -> Vec<PathBuf> {
+    vec![
+        root.to_path_buf(),
+        root.join("store"),
+        root.join("db"),
+        root.join("cache"),
+        root.join("locks"),
+        prefix.to_path_buf(),
+        prefix.join("bin"),
+        prefix.join("Cellar"),
+    ]
+}
+
+/// Whether creating the directories for `root` and `prefix` takes sudo.
+pub fn needs_sudo(root: &Path, prefix: &Path) -> bool {
+    init_dirs(root, prefix).iter().any(|d| {
+        if d.exists() {
+            !is_writable(d)
+        } else {
+            d.ancestors()
+                .skip(1)
+                .find(|p| p.exists())
+                .is_none_or(|p| !is_writable(p))
+        }
+    })
+}
+
 pub fn run_init(
     root: &Path,
     prefix: &Path,
     no_modify_path: bool,
+    no_sudo: bool,
     ui: &mut StdUi,
 ) -> Result<(), InitError> {
     validate_privileged_path(root)
@@ -97,28 +137,18 @@ pub fn run_init(
     };
     let zerobrew_bin = format!("{}/bin", zerobrew_dir);
 
-    let dirs_to_create: Vec<PathBuf> = vec![
-        root.to_path_buf(),
-        root.join("store"),
-        root.join("db"),
-        root.join("cache"),
-        root.join("locks"),
-        prefix.to_path_buf(),
-        prefix.join("bin"),
-        prefix.join("Cellar"),
-    ];
+    let dirs_to_create = init_dirs(root, prefix);
+    let creates_prefix = !prefix.exists();
 
-    let need_sudo = dirs_to_create.iter().any(|d| {
-        if d.exists() {
-            !is_writable(d)
-        } else {
-            d.parent()
-                .map(|p| p.exists() && !is_writable(p))
-                .unwrap_or(true)
+    if needs_sudo(root, prefix) {
+        if no_sudo {
+            return Err(InitError::Message(format!(
+                "{} or {} cannot be created without sudo (--no-sudo); choose paths you own with --root and --prefix",
+                root.display(),
+                prefix.display()
+            )));
         }
-    });
 
-    if need_sudo {
         ui.info("Creating directories (requires sudo)...")?;
 
         for dir in &dirs_to_create {
@@ -178,6 +208,16 @@ pub fn run_init(
         }
     }
 
+    if creates_prefix {
+        std::fs::write(prefix.join(PREFIX_MARKER), b"").map_err(|e| {
+            InitError::Message(format!(
+                "Failed to mark {} as zerobrew's: {}",
+                prefix.display(),
+                e
+            ))
+        })?;
+    }
+
     add_to_path(
         prefix,
         &zerobrew_dir,
@@ -195,16 +235,22 @@ pub fn run_init(
 const ZB_BLOCK_START: &str = "# >>> zerobrew >>>";
 const ZB_BLOCK_END: &str = "# <<< zerobrew <<<";
 
+/// Byte range of the managed block in `existing`, including the line break
+/// after its end marker.
+fn managed_block_range(existing: &str) -> Option<(usize, usize)> {
+    let start_idx = existing.find(ZB_BLOCK_START)?;
+    let end_rel_idx = existing[start_idx..].find(ZB_BLOCK_END)?;
+    let mut end_idx = start_idx + end_rel_idx + ZB_BLOCK_END.len();
+    if existing[end_idx..].starts_with("\r\n") {
+        end_idx += 2;
+    } else if existing[end_idx..].starts_with('\n') {
+        end_idx += 1;
+    }
+    Some((start_idx, end_idx))
+}
+
 fn upsert_managed_block(existing: &str, managed_block: &str) -> String {
-    if let Some(start_idx) = existing.find(ZB_BLOCK_START)
-        && let Some(end_rel_idx) = existing[start_idx..].find(ZB_BLOCK_END)
-    {
-        let mut end_idx = start_idx + end_rel_idx + ZB_BLOCK_END.len();
-        if existing[end_idx..].starts_with("\r\n") {
-            end_idx += 2;
-        } else if existing[end_idx..].starts_with('\n') {
-            end_idx += 1;
-        }
+    if let Some((start_idx, end_idx)) = managed_block_range(existing) {
         let mut out = String::with_capacity(existing.len() + managed_block.len());
         out.push_str(&existing[..start_idx]);
         out.push_str(managed_block);
@@ -225,6 +271,60 @@ fn upsert_managed_block(existing: &str, managed_block: &str) -> String {
     }
 }
 
+fn remove_managed_block(existing: &str) -> Option<String> {
+    let (start_idx, end_idx) = managed_block_range(existing)?;
+    Some(format!(
+        "{}{}",
+        &existing[..start_idx],
+        &existing[end_idx..]
+    ))
+}
+
+/// Undo the shell configuration `zb init` wrote: strip the managed block
+/// from every startup file it may have picked, and delete the fish snippet.
+/// Returns the files that were changed.
+pub fn remove_shell_config() -> Result<Vec<PathBuf>, zb_core::Error> {
+    let home = PathBuf::from(
+        std::env::var("HOME").map_err(|_| zb_core::Error::FileError {
+            message: "HOME not set".to_string(),
+        })?,
+    );
+    let mut candidates = vec![
+        home.join(".zshenv"),
+        home.join(".zshrc"),
+        home.join(".bash_profile"),
+        home.join(".bashrc"),
+        home.join(".profile"),
+    ];
+    if let Ok(zdotdir) = std::env::var("ZDOTDIR") {
+        let zdotdir = PathBuf::from(zdotdir);
+        candidates.push(zdotdir.join(".zshenv"));
+        candidates.push(zdotdir.join(".zshrc"));
+    }
+    candidates.sort();
+    candidates.dedup();
+
+    let mut changed = Vec::new();
+    for path in candidates {
+        let Ok(existing) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        if let Some(updated) = remove_managed_block(&existing) {
+            std::fs::write(&path, updated)
+                .map_err(zb_core::Error::file("failed to update shell config"))?;
+            changed.push(path);
+        }
+    }
+
+    let fish = home.join(".config/fish/conf.d/zerobrew.fish");
+    if std::fs::read_to_string(&fish).is_ok_and(|content| content.contains(ZB_BLOCK_START)) {
+        std::fs::remove_file(&fish)
+            .map_err(zb_core::Error::file("failed to remove fish config"))?;
+        changed.push(fish);
+    }
+    Ok(changed)
+}
+
 fn posix_shell_quote(value: &str) -> String {
     if value.is_empty() {
         return "''".to_string();
@@ -521,26 +621,24 @@ pub fn ensure_init(
             .prompt_yes_no("Initialize now? [Y/n]", PromptDefault::Yes)
             .map_err(io_to_core_error)?
         {
-            return Err(zb_core::Error::StoreCorruption {
+            return Err(zb_core::Error::InitError {
                 message: "Initialization required. Run 'zb init' first.".to_string(),
             });
         }
     }
     if !is_interactive && !auto_init {
-        return Err(zb_core::Error::StoreCorruption {
+        return Err(zb_core::Error::InitError {
             message: "Initialization required. Run 'zb init' first.".to_string(),
         });
     }
     // Auto-initialize without prompting when non-interactive or auto_init is set
 
     // Pass false for no_modify_shell since user confirmed they want full initialization
-    run_init(root, prefix, false, ui).map_err(|e| match e {
-        InitError::Message(msg) => zb_core::Error::StoreCorruption { message: msg },
-    })
+    Ok(run_init(root, prefix, false, false, ui)?)
 }
 
 fn io_to_core_error(err: std::io::Error) -> zb_core::Error {
-    zb_core::Error::StoreCorruption {
+    zb_core::Error::FileError {
         message: format!("failed to write CLI output: {err}"),
     }
 }
@@ -552,6 +650,17 @@ mod tests {
     use std::fs;
     use std::path::Path;
 
+    #[test]
+    fn init_errors_convert_to_init_variant() {
+        let err: zb_core::Error = InitError::Message("HOME not set".to_string()).into();
+        assert_eq!(
+            err,
+            zb_core::Error::InitError {
+                message: "HOME not set".to_string()
+            }
+        );
+    }
+
     fn add_to_path(
         prefix: &Path,
         zerobrew_dir: &str,
@@ -1072,6 +1181,39 @@ mod tests {
         assert!(content.contains("# zerobrew"));
     }
 
+    #[test]
+    fn remove_shell_config_undoes_add_to_path() {
+        let _lock = env_lock();
+        let tmp = TempDir::new().unwrap();
+        let home = tmp.path();
+        let prefix = tmp.path().join("prefix");
+        let root = tmp.path().join("root");
+        let bashrc = home.join(".bashrc");
+        let fish_config = home.join(".config/fish/conf.d/zerobrew.fish");
+        fs::write(&bashrc, "export KEEP_ME=true\n").unwrap();
+
+        unsafe {
+            std::env::set_var("HOME", home.to_str().unwrap());
+            std::env::remove_var("ZDOTDIR");
+            std::env::set_var("SHELL", "/bin/bash");
+        }
+        add_to_path(&prefix, "/zb", "/zb/bin", &root, false).unwrap();
+        unsafe {
+            std::env::set_var("SHELL", "/usr/bin/fish");
+        }
+        add_to_path(&prefix, "/zb", "/zb/bin", &root, false).unwrap();
+
+        let changed = remove_shell_config().unwrap();
+
+        assert_eq!(changed, vec![bashrc.clone(), fish_config.clone()]);
+        assert_eq!(
+            fs::read_to_string(&bashrc).unwrap(),
+            "export KEEP_ME=true\n"
+        );
+        assert!(!fish_config.exists());
+        assert!(remove_shell_config().unwrap().is_empty());
+    }
+
     #[test]
     fn upsert_managed_block_replacement_consumes_trailing_newline() {
         let managed_block =
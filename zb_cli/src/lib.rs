@@ -2,5 +2,6 @@ pub mod cli;
 pub mod commands;
 pub mod init;
 pub mod logging;
+pub mod progress;
 pub mod ui;
 pub mod utils;
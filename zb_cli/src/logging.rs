@@ -1,42 +1,144 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::EnvFilter;
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::Targets;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
+use zb_core::{LogLevel, LoggerHandle};
 
-pub fn init(verbose: u8, quiet: bool) {
-    let level = if quiet {
-        LevelFilter::ERROR
-    } else {
-        match verbose {
-            0 => LevelFilter::WARN,
-            1 => LevelFilter::INFO,
-            2 => LevelFilter::DEBUG,
-            _ => LevelFilter::TRACE,
-        }
-    };
+/// Size at which the log file is rotated on the next run.
+const MAX_LOG_BYTES: u64 = 5 << 20;
+/// Rotated logs kept next to the current one, as `zb.log.1` (newest) and up.
+const ROTATED_LOGS: usize = 3;
 
-    let filter = EnvFilter::builder()
-        .with_default_directive(level.into())
+/// Set up console logging at `logger.level` and, if `logger.log_file` can be
+/// opened, a debug log of zerobrew's own events. Returns the log file path
+/// when it is being written.
+pub fn init(logger: &LoggerHandle) -> Option<PathBuf> {
+    let console_filter = EnvFilter::builder()
+        .with_default_directive(level_filter(logger.level).into())
         .from_env_lossy();
+    let console = tracing_subscriber::fmt::layer()
+        .with_target(false)
+        .without_time()
+        .with_filter(console_filter);
 
-    let _ = tracing_subscriber::registry()
-        .with(filter)
-        .with(
+    let mut log_path = None;
+    let file_layer = logger.log_file.as_deref().and_then(|path| {
+        let file = open_log_file(path).ok()?;
+        log_path = Some(path.to_path_buf());
+        Some(
             tracing_subscriber::fmt::layer()
-                .with_target(false)
-                .without_time(),
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .with_filter(
+                    Targets::new()
+                        .with_default(LevelFilter::WARN)
+                        .with_target("zb_core", LevelFilter::DEBUG)
+                        .with_target("zb_io", LevelFilter::DEBUG)
+                        .with_target("zb_cli", LevelFilter::DEBUG)
+                        .with_target("zb", LevelFilter::DEBUG),
+                ),
         )
-        .try_init();
+    });
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file_layer)
+        .try_init()
+        .ok()
+        .and(log_path)
+}
+
+fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Trace => LevelFilter::TRACE,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Error => LevelFilter::ERROR,
+    }
+}
+
+fn open_log_file(path: &Path) -> io::Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    rotate(path)?;
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Shift `zb.log` to `zb.log.1` (and older ones up) once it outgrows
+/// `MAX_LOG_BYTES`, dropping the oldest.
+fn rotate(path: &Path) -> io::Result<()> {
+    let size = fs::metadata(path).map_or(0, |m| m.len());
+    if size < MAX_LOG_BYTES {
+        return Ok(());
+    }
+    for index in (1..ROTATED_LOGS).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::init;
+    use super::*;
 
     #[test]
     fn init_is_idempotent() {
-        init(0, false);
-        init(2, false);
-        init(0, true);
+        init(&LoggerHandle::default());
+        init(&LoggerHandle {
+            level: LogLevel::Debug,
+            log_file: None,
+        });
+        init(&LoggerHandle {
+            level: LogLevel::Error,
+            log_file: None,
+        });
+    }
+
+    #[test]
+    fn rotates_oversized_log() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("logs/zb.log");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(rotated_path(&path, 1), "older").unwrap();
+        fs::write(&path, vec![b'x'; MAX_LOG_BYTES as usize]).unwrap();
+
+        open_log_file(&path).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert_eq!(
+            fs::metadata(rotated_path(&path, 1)).unwrap().len(),
+            MAX_LOG_BYTES
+        );
+        assert_eq!(fs::read_to_string(rotated_path(&path, 2)).unwrap(), "older");
+    }
+
+    #[test]
+    fn small_log_is_appended_to() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("zb.log");
+        fs::write(&path, "earlier run\n").unwrap();
+
+        open_log_file(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "earlier run\n");
+        assert!(!rotated_path(&path, 1).exists());
     }
 }
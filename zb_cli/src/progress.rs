@@ -0,0 +1,352 @@
+use console::style;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::HashMap;
+use std::time::Duration;
+use zb_io::{InstallProgress, ProgressReceiver};
+
+const TICK: Duration = Duration::from_millis(80);
+
+/// What a package's bar is currently showing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BarState {
+    Downloading {
+        downloaded: u64,
+        total_bytes: Option<u64>,
+    },
+    Retrying {
+        attempt: u32,
+        max_attempts: u32,
+    },
+    Unpacking,
+    Unpacked,
+    Linking,
+    Linked,
+    KegOnly(String),
+    Done,
+}
+
+impl BarState {
+    fn message(&self, done_label: &str) -> String {
+        match self {
+            BarState::Downloading { .. } => "downloading...".to_string(),
+            BarState::Retrying {
+                attempt,
+                max_attempts,
+            } => format!("retrying {attempt}/{max_attempts}..."),
+            BarState::Unpacking => "unpacking...".to_string(),
+            BarState::Unpacked => "unpacked".to_string(),
+            BarState::Linking => "linking...".to_string(),
+            BarState::Linked => "linked".to_string(),
+            BarState::KegOnly(reason) => format!("keg-only ({reason})"),
+            BarState::Done => format!("{} {}", style("✓").green(), done_label),
+        }
+    }
+}
+
+/// The state `event` moves a bar into, or `None` if the event does not
+/// apply. Only `DownloadStarted` creates a bar; every other event is ignored
+/// for packages without one, and a finished bar never changes again.
+pub fn next_state(current: Option<&BarState>, event: &InstallProgress) -> Option<BarState> {
+    if let InstallProgress::DownloadStarted { total_bytes, .. } = event {
+        return Some(BarState::Downloading {
+            downloaded: 0,
+            total_bytes: *total_bytes,
+        });
+    }
+
+    let current = current?;
+    if *current == BarState::Done {
+        return None;
+    }
+
+    match event {
+        InstallProgress::DownloadStarted { .. } => unreachable!(),
+        InstallProgress::DownloadProgress {
+            downloaded,
+            total_bytes,
+            ..
+        } => match current {
+            BarState::Downloading { .. } if total_bytes.is_some() => Some(BarState::Downloading {
+                downloaded: *downloaded,
+                total_bytes: *total_bytes,
+            }),
+            _ => None,
+        },
+        InstallProgress::DownloadRetrying {
+            attempt,
+            max_attempts,
+            ..
+        } => Some(BarState::Retrying {
+            attempt: *attempt,
+            max_attempts: *max_attempts,
+        }),
+        InstallProgress::DownloadCompleted { .. } | InstallProgress::UnpackStarted { .. } => {
+            Some(BarState::Unpacking)
+        }
+        InstallProgress::UnpackCompleted { .. } => Some(BarState::Unpacked),
+        InstallProgress::LinkStarted { .. } => Some(BarState::Linking),
+        InstallProgress::LinkCompleted { .. } => Some(BarState::Linked),
+        InstallProgress::LinkSkipped { reason, .. } => Some(BarState::KegOnly(reason.clone())),
+        InstallProgress::InstallCompleted { .. } => Some(BarState::Done),
+    }
+}
+
+fn event_name(event: &InstallProgress) -> &str {
+    match event {
+        InstallProgress::DownloadStarted { name, .. }
+        | InstallProgress::DownloadProgress { name, .. }
+        | InstallProgress::DownloadRetrying { name, .. }
+        | InstallProgress::DownloadCompleted { name, .. }
+        | InstallProgress::UnpackStarted { name }
+        | InstallProgress::UnpackCompleted { name }
+        | InstallProgress::LinkStarted { name }
+        | InstallProgress::LinkCompleted { name }
+        | InstallProgress::LinkSkipped { name, .. }
+        | InstallProgress::InstallCompleted { name } => name,
+    }
+}
+
+/// Renders install progress events as one bar per package.
+pub struct ProgressRenderer {
+    multi: MultiProgress,
+    bars: HashMap<String, (ProgressBar, BarState)>,
+    done_label: &'static str,
+    download_style: ProgressStyle,
+    spinner_style: ProgressStyle,
+    done_style: ProgressStyle,
+}
+
+impl ProgressRenderer {
+    /// `done_label` follows the check mark once a package finishes, e.g.
+    /// "installed" or "upgraded".
+    pub fn new(done_label: &'static str) -> Self {
+        Self::with_multi(MultiProgress::new(), done_label)
+    }
+
+    /// A renderer that tracks state without drawing anything.
+    pub fn hidden(done_label: &'static str) -> Self {
+        Self::with_multi(
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            done_label,
+        )
+    }
+
+    fn with_multi(multi: MultiProgress, done_label: &'static str) -> Self {
+        Self {
+            multi,
+            bars: HashMap::new(),
+            done_label,
+            download_style: ProgressStyle::default_bar()
+                .template(
+                    "    {prefix:<16} {bar:25.cyan/dim} {bytes:>10}/{total_bytes:<10} {eta:>6}",
+                )
+                .unwrap()
+                .progress_chars("━━╸"),
+            spinner_style: ProgressStyle::default_spinner()
+                .template("    {prefix:<16} {spinner:.cyan} {msg}")
+                .unwrap()
+                .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏"),
+            done_style: ProgressStyle::default_spinner()
+                .template("    {prefix:<16} {msg}")
+                .unwrap(),
+        }
+    }
+
+    pub fn state(&self, name: &str) -> Option<&BarState> {
+        self.bars.get(name).map(|(_, state)| state)
+    }
+
+    pub fn handle(&mut self, event: InstallProgress) {
+        let name = event_name(&event);
+        let current = self.bars.get(name).map(|(_, state)| state);
+        let Some(next) = next_state(current, &event) else {
+            return;
+        };
+
+        if let InstallProgress::DownloadStarted { name, total_bytes } = event {
+            let pb = match total_bytes {
+                Some(total) => {
+                    let pb = self.multi.add(ProgressBar::new(total));
+                    pb.set_style(self.download_style.clone());
+                    pb
+                }
+                None => {
+                    let pb = self.multi.add(ProgressBar::new_spinner());
+                    pb.set_style(self.spinner_style.clone());
+                    pb.set_message(next.message(self.done_label));
+                    pb.enable_steady_tick(TICK);
+                    pb
+                }
+            };
+            pb.set_prefix(name.clone());
+            if let Some((old, _)) = self.bars.insert(name, (pb, next)) {
+                old.finish_and_clear();
+            }
+            return;
+        }
+
+        let Some((pb, state)) = self.bars.get_mut(name) else {
+            return;
+        };
+        match (&next, &event) {
+            (BarState::Downloading { downloaded, .. }, _) => pb.set_position(*downloaded),
+            (BarState::Unpacking, InstallProgress::DownloadCompleted { total_bytes, .. }) => {
+                if *total_bytes > 0 {
+                    pb.set_position(*total_bytes);
+                }
+                pb.set_style(self.spinner_style.clone());
+                pb.set_message(next.message(self.done_label));
+                pb.enable_steady_tick(TICK);
+            }
+            (BarState::Retrying { .. }, _) => {
+                pb.set_style(self.spinner_style.clone());
+                pb.set_message(next.message(self.done_label));
+                pb.enable_steady_tick(TICK);
+            }
+            (BarState::Done, _) => {
+                pb.set_style(self.done_style.clone());
+                pb.set_message(next.message(self.done_label));
+                pb.finish();
+            }
+            _ => pb.set_message(next.message(self.done_label)),
+        }
+        *state = next;
+    }
+
+    /// Stop every bar that has not reached its final state.
+    pub fn finish(&mut self) {
+        for (pb, _) in self.bars.values() {
+            if !pb.is_finished() {
+                pb.finish();
+            }
+        }
+    }
+
+    /// Render events until the sending side is dropped.
+    pub async fn run(mut self, mut events: ProgressReceiver) {
+        while let Some(event) = events.recv().await {
+            self.handle(event);
+        }
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BarState, ProgressRenderer, next_state};
+    use zb_io::InstallProgress;
+
+    fn name() -> String {
+        "jq".to_string()
+    }
+
+    #[test]
+    fn events_without_a_bar_are_ignored() {
+        let event = InstallProgress::UnpackStarted { name: name() };
+        assert_eq!(next_state(None, &event), None);
+    }
+
+    #[test]
+    fn progress_without_known_total_keeps_spinner_state() {
+        let state = BarState::Downloading {
+            downloaded: 0,
+            total_bytes: None,
+        };
+        let event = InstallProgress::DownloadProgress {
+            name: name(),
+            downloaded: 10,
+            total_bytes: None,
+        };
+        assert_eq!(next_state(Some(&state), &event), None);
+    }
+
+    #[test]
+    fn retry_moves_bar_to_retrying() {
+        let state = BarState::Downloading {
+            downloaded: 10,
+            total_bytes: Some(100),
+        };
+        let event = InstallProgress::DownloadRetrying {
+            name: name(),
+            attempt: 2,
+            max_attempts: 3,
+            reason: "HTTP 503".to_string(),
+        };
+        let next = next_state(Some(&state), &event).unwrap();
+        assert_eq!(
+            next,
+            BarState::Retrying {
+                attempt: 2,
+                max_attempts: 3
+            }
+        );
+        assert_eq!(next.message("installed"), "retrying 2/3...");
+    }
+
+    #[test]
+    fn done_is_terminal() {
+        let event = InstallProgress::LinkStarted { name: name() };
+        assert_eq!(next_state(Some(&BarState::Done), &event), None);
+    }
+
+    #[test]
+    fn renderer_walks_full_install_lifecycle() {
+        let mut renderer = ProgressRenderer::hidden("installed");
+
+        renderer.handle(InstallProgress::DownloadStarted {
+            name: name(),
+            total_bytes: Some(100),
+        });
+        renderer.handle(InstallProgress::DownloadProgress {
+            name: name(),
+            downloaded: 40,
+            total_bytes: Some(100),
+        });
+        assert_eq!(
+            renderer.state("jq"),
+            Some(&BarState::Downloading {
+                downloaded: 40,
+                total_bytes: Some(100)
+            })
+        );
+
+        renderer.handle(InstallProgress::DownloadCompleted {
+            name: name(),
+            total_bytes: 100,
+        });
+        assert_eq!(renderer.state("jq"), Some(&BarState::Unpacking));
+
+        renderer.handle(InstallProgress::UnpackCompleted { name: name() });
+        assert_eq!(renderer.state("jq"), Some(&BarState::Unpacked));
+
+        renderer.handle(InstallProgress::LinkSkipped {
+            name: name(),
+            reason: "keg-only".to_string(),
+        });
+        assert_eq!(
+            renderer.state("jq"),
+            Some(&BarState::KegOnly("keg-only".to_string()))
+        );
+
+        renderer.handle(InstallProgress::InstallCompleted { name: name() });
+        assert_eq!(renderer.state("jq"), Some(&BarState::Done));
+
+        renderer.handle(InstallProgress::LinkStarted { name: name() });
+        assert_eq!(renderer.state("jq"), Some(&BarState::Done));
+        assert_eq!(renderer.state("wget"), None);
+    }
+
+    #[tokio::test]
+    async fn run_finishes_when_channel_closes() {
+        let (events, receiver) = zb_io::progress_channel();
+        events
+            .send(InstallProgress::DownloadStarted {
+                name: name(),
+                total_bytes: None,
+            })
+            .unwrap();
+        drop(events);
+
+        ProgressRenderer::hidden("upgraded").run(receiver).await;
+    }
+}
@@ -133,6 +133,14 @@ pub fn get_root_path(cli_root: Option<PathBuf>) -> PathBuf {
         return legacy_root;
     }
 
+    // Set up by `zb init --no-sudo`; found even before the shell config
+    // exporting ZEROBREW_ROOT has been loaded.
+    if let Some(local_root) = user_local_root()
+        && local_root.join("db").is_dir()
+    {
+        return local_root;
+    }
+
     if cfg!(target_os = "macos") {
         legacy_root
     } else {
@@ -148,6 +156,17 @@ pub fn get_root_path(cli_root: Option<PathBuf>) -> PathBuf {
     }
 }
 
+/// Root of the user-local layout `zb init --no-sudo` falls back to when the
+/// default root cannot be created without sudo.
+pub fn user_local_root() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".zerobrew"))
+}
+
+/// The prefix used for `root` when none is configured.
+pub fn default_prefix(root: &Path) -> PathBuf {
+    default_prefix_for_os(root, cfg!(target_os = "macos"))
+}
+
 pub fn get_prefix_path(cli_prefix: Option<PathBuf>, root: &Path) -> PathBuf {
     if let Some(prefix) = cli_prefix {
         return prefix;
@@ -179,6 +198,11 @@ fn is_legacy_macos_default_prefix(prefix: &Path, root: &Path) -> bool {
     prefix == root.join("prefix")
 }
 
+/// Quote `value` for a POSIX shell.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
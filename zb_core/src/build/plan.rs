@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-use crate::Formula;
+use crate::{Formula, FormulaResource, HeadSource};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BuildSystem {
@@ -15,6 +15,19 @@ pub enum BuildSystem {
 pub enum InstallMethod {
     Bottle(crate::SelectedBottle),
     Source(BuildPlan),
+    /// Release binaries unpacked into the keg's `bin`.
+    Binary(crate::SelectedBinary),
+}
+
+impl InstallMethod {
+    /// `bottle`, `source` or `binary`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InstallMethod::Bottle(_) => "bottle",
+            InstallMethod::Source(_) => "source",
+            InstallMethod::Binary(_) => "binary",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,9 +36,13 @@ pub struct BuildPlan {
     pub version: String,
     pub source_url: String,
     pub source_checksum: Option<String>,
+    /// Commit to check out when `source_url` is a git repository.
+    pub source_revision: Option<String>,
     pub ruby_source_path: Option<String>,
     pub build_dependencies: Vec<String>,
     pub runtime_dependencies: Vec<String>,
+    /// Downloaded and verified before the build, for `install` to stage.
+    pub resources: Vec<FormulaResource>,
     pub detected_system: BuildSystem,
     pub prefix: PathBuf,
     pub cellar_path: PathBuf,
@@ -45,14 +62,41 @@ impl BuildPlan {
             version,
             source_url: source.url.clone(),
             source_checksum: source.checksum.clone(),
+            source_revision: None,
             ruby_source_path: formula.ruby_source_path.clone(),
             build_dependencies: all_build_deps,
             runtime_dependencies: formula.runtime_dependencies(),
+            resources: formula.resources.clone(),
             detected_system,
             prefix: prefix.to_path_buf(),
             cellar_path,
         })
     }
+
+    /// Build commit `revision` of the `head` repository. The keg is
+    /// versioned `HEAD-<short sha>`.
+    pub fn from_head(formula: &Formula, head: &HeadSource, revision: &str, prefix: &Path) -> Self {
+        let version = HeadSource::version_for(revision);
+        let cellar_path = prefix.join("Cellar").join(&formula.name).join(&version);
+
+        let all_build_deps = formula.all_build_dependencies();
+        let detected_system = detect_build_system(&head.url, &all_build_deps);
+
+        Self {
+            formula_name: formula.name.clone(),
+            version,
+            source_url: head.url.clone(),
+            source_checksum: None,
+            source_revision: Some(revision.to_string()),
+            ruby_source_path: formula.ruby_source_path.clone(),
+            build_dependencies: all_build_deps,
+            runtime_dependencies: formula.runtime_dependencies(),
+            resources: formula.resources.clone(),
+            detected_system,
+            prefix: prefix.to_path_buf(),
+            cellar_path,
+        }
+    }
 }
 
 fn detect_build_system(source_url: &str, build_deps: &[String]) -> BuildSystem {
@@ -86,6 +130,7 @@ mod tests {
             BottleFile {
                 url: format!("https://example.com/{name}.tar.gz"),
                 sha256: "deadbeef".repeat(8),
+                cellar: None,
             },
         );
 
@@ -97,11 +142,15 @@ mod tests {
             dependencies: vec!["libfoo".to_string()],
             bottle: Bottle {
                 stable: BottleStable { files, rebuild: 0 },
+                variants: BTreeMap::new(),
             },
             revision: 0,
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: build_deps.iter().map(|s| s.to_string()).collect(),
+            test_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: Some(FormulaUrls {
                 stable: Some(SourceUrl {
                     url: source_url.to_string(),
@@ -116,6 +165,14 @@ mod tests {
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
+            service: None,
+            desc: None,
+            homepage: None,
+            license: None,
+            binary: None,
+            resources: Vec::new(),
         }
     }
 
@@ -161,4 +218,23 @@ mod tests {
             PathBuf::from("/opt/zerobrew/Cellar/wget/1.0.0")
         );
     }
+
+    #[test]
+    fn head_plan_checks_out_revision() {
+        let f = test_formula("tool", "https://example.com/src.tar.gz", &["go"]);
+        let head = HeadSource {
+            url: "https://github.com/org/tool.git".to_string(),
+            branch: None,
+        };
+        let prefix = PathBuf::from("/opt/zerobrew");
+        let plan = BuildPlan::from_head(&f, &head, "0123456789abcdef", &prefix);
+        assert_eq!(plan.version, "HEAD-0123456");
+        assert_eq!(plan.source_url, head.url);
+        assert_eq!(plan.source_revision.as_deref(), Some("0123456789abcdef"));
+        assert_eq!(plan.source_checksum, None);
+        assert_eq!(
+            plan.cellar_path,
+            PathBuf::from("/opt/zerobrew/Cellar/tool/HEAD-0123456")
+        );
+    }
 }
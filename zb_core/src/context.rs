@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Paths {
@@ -8,6 +9,7 @@ pub struct Paths {
     pub cache: PathBuf,
     pub db: PathBuf,
     pub locks: PathBuf,
+    pub logs: PathBuf,
 }
 
 impl Paths {
@@ -17,6 +19,7 @@ impl Paths {
         let cache = root.join("cache");
         let db = root.join("db").join("zb.sqlite3");
         let locks = root.join("locks");
+        let logs = root.join("logs");
 
         Self {
             root,
@@ -25,52 +28,160 @@ impl Paths {
             cache,
             db,
             locks,
+            logs,
         }
     }
+
+    /// The debug log every run appends to.
+    pub fn log_file(&self) -> PathBuf {
+        self.logs.join("zb.log")
+    }
 }
 
+/// How much of each kind of install work runs at once.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ConcurrencyLimits {
+    /// Bottle downloads in flight.
     pub download: usize,
+    /// Bottles unpacked into the store while they download. Downloads past
+    /// the limit are unpacked from the cache afterwards.
     pub unpack: usize,
-    pub materialize: usize,
+    /// Threads patching binaries while a keg is relocated.
+    pub relocate: usize,
+    /// Threads linking a keg, one top-level directory (`bin`, `lib`, ...)
+    /// each.
+    pub link: usize,
 }
 
-impl Default for ConcurrencyLimits {
-    fn default() -> Self {
+/// Memory set aside for each concurrent download or unpack when deriving
+/// limits from the available memory.
+const MEMORY_PER_JOB: u64 = 128 << 20;
+
+impl ConcurrencyLimits {
+    /// Limits for a host with `cpus` cores and `memory_bytes` of available
+    /// memory, if known.
+    pub fn tuned(cpus: usize, memory_bytes: Option<u64>) -> Self {
+        let cpus = cpus.max(1);
+        let memory_jobs = memory_bytes
+            .map(|bytes| (bytes / MEMORY_PER_JOB).max(2) as usize)
+            .unwrap_or(usize::MAX);
         Self {
-            download: 20,
-            unpack: 4,
-            materialize: 4,
+            download: memory_jobs.min(20),
+            unpack: cpus.clamp(2, 8).min(memory_jobs),
+            relocate: cpus,
+            // Kegs have at most six directories to link.
+            link: cpus.min(6),
         }
     }
+
+    /// Limits tuned for this machine, measured once per process.
+    pub fn detect() -> Self {
+        static DETECTED: OnceLock<ConcurrencyLimits> = OnceLock::new();
+        DETECTED
+            .get_or_init(|| {
+                let cpus = std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(4);
+                Self::tuned(cpus, available_memory())
+            })
+            .clone()
+    }
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self::detect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn available_memory() -> Option<u64> {
+    parse_mem_available(&std::fs::read_to_string("/proc/meminfo").ok()?)
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// macOS has no cheap "available" figure, so this is physical memory.
+#[cfg(target_os = "macos")]
+fn available_memory() -> Option<u64> {
+    let out = std::process::Command::new("sysctl")
+        .args(["-n", "hw.memsize"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())?;
+    String::from_utf8_lossy(&out.stdout).trim().parse().ok()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn available_memory() -> Option<u64> {
+    None
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))?;
+    let kib: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
+    Trace,
+    Debug,
     Info,
     Warn,
     Error,
 }
 
+impl LogLevel {
+    /// Console level for `-v` given `verbose` times, or `--quiet`. Warnings
+    /// are shown by default.
+    pub fn from_verbosity(verbose: u8, quiet: bool) -> Self {
+        if quiet {
+            return LogLevel::Error;
+        }
+        match verbose {
+            0 => LogLevel::Warn,
+            1 => LogLevel::Info,
+            2 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct LoggerHandle {
     pub level: LogLevel,
+    /// Debug log written independently of `level`; `None` disables it.
+    pub log_file: Option<PathBuf>,
 }
 
 impl Default for LoggerHandle {
     fn default() -> Self {
         Self {
             level: LogLevel::Info,
+            log_file: None,
         }
     }
 }
 
+/// Mirrors to fetch from instead of the public services, e.g. on an
+/// air-gapped network. `None` keeps the default.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ApiEndpoints {
+    /// Stands in for `https://formulae.brew.sh/api/formula`.
+    pub api_url: Option<String>,
+    /// Stands in for `https://raw.githubusercontent.com`, where tap and
+    /// homebrew-core formula sources are read from.
+    pub tap_base_url: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Context {
     pub paths: Paths,
     pub concurrency: ConcurrencyLimits,
     pub logger: LoggerHandle,
+    pub endpoints: ApiEndpoints,
 }
 
 impl Context {
@@ -79,6 +190,7 @@ impl Context {
             paths: Paths::from_root(PathBuf::from("/opt/zerobrew")),
             concurrency: ConcurrencyLimits::default(),
             logger: LoggerHandle::default(),
+            endpoints: ApiEndpoints::default(),
         }
     }
 }
@@ -112,5 +224,51 @@ mod tests {
             context.paths.locks,
             PathBuf::from("/opt/zerobrew").join("locks")
         );
+        assert_eq!(
+            context.paths.log_file(),
+            PathBuf::from("/opt/zerobrew").join("logs").join("zb.log")
+        );
+    }
+
+    #[test]
+    fn verbosity_maps_to_log_levels() {
+        assert_eq!(LogLevel::from_verbosity(0, false), LogLevel::Warn);
+        assert_eq!(LogLevel::from_verbosity(1, false), LogLevel::Info);
+        assert_eq!(LogLevel::from_verbosity(2, false), LogLevel::Debug);
+        assert_eq!(LogLevel::from_verbosity(5, false), LogLevel::Trace);
+        assert_eq!(LogLevel::from_verbosity(2, true), LogLevel::Error);
+    }
+
+    #[test]
+    fn concurrency_limits_follow_cpus_and_memory() {
+        let limits = ConcurrencyLimits::tuned(10, Some(32 << 30));
+        assert_eq!(
+            limits,
+            ConcurrencyLimits {
+                download: 20,
+                unpack: 8,
+                relocate: 10,
+                link: 6,
+            }
+        );
+
+        let small = ConcurrencyLimits::tuned(1, Some(512 << 20));
+        assert_eq!(
+            small,
+            ConcurrencyLimits {
+                download: 4,
+                unpack: 2,
+                relocate: 1,
+                link: 1,
+            }
+        );
+        assert_eq!(ConcurrencyLimits::tuned(4, None).download, 20);
+    }
+
+    #[test]
+    fn parses_available_memory_from_meminfo() {
+        let meminfo = "MemTotal:       16318480 kB\nMemFree:         1191532 kB\nMemAvailable:    8429180 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(8_429_180 * 1024));
+        assert_eq!(parse_mem_available("MemTotal: 1 kB\n"), None);
     }
 }
@@ -9,19 +9,153 @@ pub struct ConflictedLink {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Error {
-    UnsupportedBottle { name: String },
-    ChecksumMismatch { expected: String, actual: String },
-    LinkConflict { conflicts: Vec<ConflictedLink> },
-    StoreCorruption { message: String },
-    NetworkFailure { message: String },
-    MissingFormula { name: String },
-    UnsupportedTap { name: String },
-    UnsupportedFormula { name: String, reason: String },
-    DependencyCycle { cycle: Vec<String> },
-    NotInstalled { name: String },
-    FileError { message: String },
-    InvalidArgument { message: String },
-    ExecutionError { message: String },
+    UnsupportedBottle {
+        name: String,
+    },
+    /// An explicitly requested bottle tag is not published for the formula.
+    BottleTagUnavailable {
+        name: String,
+        tag: String,
+        available: Vec<String>,
+    },
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    /// Linking a keg would replace files already in the prefix. `formula`
+    /// is the keg being linked, when the caller knows it.
+    LinkConflict {
+        formula: Option<String>,
+        conflicts: Vec<ConflictedLink>,
+    },
+    StoreCorruption {
+        message: String,
+    },
+    /// Another zerobrew process holds the database's write lock for longer
+    /// than writers wait. Nothing is wrong with the store; retrying later
+    /// succeeds.
+    DatabaseLocked {
+        context: String,
+    },
+    NetworkFailure {
+        message: String,
+    },
+    /// The server answered with a non-success status.
+    HttpStatus {
+        status: u16,
+        message: String,
+    },
+    /// A retryable operation kept failing; `last` is the final attempt's error.
+    RetriesExhausted {
+        attempts: u32,
+        last: Box<Error>,
+    },
+    MissingFormula {
+        name: String,
+    },
+    /// Formula JSON from the API does not match the schema zerobrew reads.
+    /// `name` is missing when the JSON has no readable name either.
+    FormulaSchema {
+        name: Option<String>,
+        message: String,
+    },
+    UnsupportedTap {
+        name: String,
+    },
+    UnsupportedFormula {
+        name: String,
+        reason: String,
+    },
+    /// Installing `name` would link over files of a formula it declares
+    /// `conflicts_with`.
+    FormulaConflict {
+        name: String,
+        conflicting: String,
+        reason: Option<String>,
+    },
+    /// The host does not meet the formula's declared requirements.
+    UnsatisfiedRequirement {
+        name: String,
+        missing: Vec<String>,
+    },
+    /// A Linux bottle was built against a newer glibc than the host has.
+    GlibcTooOld {
+        name: String,
+        required: String,
+        found: String,
+    },
+    DependencyCycle {
+        cycle: Vec<String>,
+    },
+    /// Relocated files of a keg reference libraries that do not exist.
+    DanglingReferences {
+        name: String,
+        references: Vec<String>,
+    },
+    /// Installed packages are affected by known vulnerabilities.
+    Vulnerable {
+        names: Vec<String>,
+    },
+    NotInstalled {
+        name: String,
+    },
+    FileError {
+        message: String,
+    },
+    InvalidArgument {
+        message: String,
+    },
+    ExecutionError {
+        message: String,
+    },
+    /// Managing a background service failed.
+    ServiceError {
+        message: String,
+    },
+    /// The program a service runs does not exist.
+    ServiceBinaryMissing {
+        name: String,
+        path: PathBuf,
+    },
+    /// The program a service runs exists but cannot be executed.
+    ServiceBinaryNotExecutable {
+        name: String,
+        path: PathBuf,
+    },
+    /// A service cannot be written as a valid launchd plist or systemd unit.
+    ServiceFileInvalid {
+        name: String,
+        reason: String,
+    },
+    /// Homebrew already runs the service under its own label.
+    ServiceLabelTaken {
+        name: String,
+        label: String,
+    },
+    /// A Brewfile or package manifest could not be read or understood.
+    ImportError {
+        message: String,
+    },
+    /// Moving packages over from Homebrew failed.
+    MigrationError {
+        message: String,
+    },
+    /// zerobrew is not initialized, or setting it up failed.
+    InitError {
+        message: String,
+    },
+    /// A `pre-plan` or `pre-install` hook refused the install. `formula`
+    /// is set for hooks that run per formula.
+    HookRejected {
+        hook: String,
+        formula: Option<String>,
+        message: String,
+    },
+    /// The packages resolved from the API are not the ones a lockfile
+    /// pinned, one line per difference.
+    LockfileMismatch {
+        differences: Vec<String>,
+    },
 }
 
 impl fmt::Display for Error {
@@ -30,10 +164,25 @@ impl fmt::Display for Error {
             Error::UnsupportedBottle { name } => {
                 write!(f, "unsupported bottle for formula '{name}'")
             }
+            Error::BottleTagUnavailable {
+                name,
+                tag,
+                available,
+            } => {
+                write!(f, "no '{tag}' bottle for formula '{name}'")?;
+                if available.is_empty() {
+                    write!(f, " (no bottles published)")
+                } else {
+                    write!(f, " (available: {})", available.join(", "))
+                }
+            }
             Error::ChecksumMismatch { expected, actual } => {
                 write!(f, "checksum mismatch (expected {expected}, got {actual})")
             }
-            Error::LinkConflict { conflicts } => {
+            Error::LinkConflict { formula, conflicts } => {
+                if let Some(name) = formula {
+                    write!(f, "cannot link {name}: ")?;
+                }
                 if conflicts.len() == 1 {
                     let c = &conflicts[0];
                     write!(f, "link conflict at '{}'", c.path.display())?;
@@ -52,8 +201,26 @@ impl fmt::Display for Error {
                 Ok(())
             }
             Error::StoreCorruption { message } => write!(f, "store corruption: {message}"),
+            Error::DatabaseLocked { context } => write!(
+                f,
+                "{context}: the database is locked by another zerobrew process; \
+                 try again once it finishes"
+            ),
             Error::NetworkFailure { message } => write!(f, "network failure: {message}"),
+            Error::HttpStatus { status, message } => {
+                write!(f, "network failure: {message} (HTTP {status})")
+            }
+            Error::RetriesExhausted { attempts, last } => {
+                write!(f, "giving up after {attempts} attempts: {last}")
+            }
             Error::MissingFormula { name } => write!(f, "missing formula '{name}'"),
+            Error::FormulaSchema { name, message } => {
+                match name {
+                    Some(name) => write!(f, "unexpected API JSON for formula '{name}'")?,
+                    None => write!(f, "unexpected formula API JSON")?,
+                }
+                write!(f, ": {message} (zerobrew may need updating)")
+            }
             Error::UnsupportedTap { name } => {
                 write!(
                     f,
@@ -63,19 +230,152 @@ impl fmt::Display for Error {
             Error::UnsupportedFormula { name, reason } => {
                 write!(f, "formula '{name}' is not supported: {reason}")
             }
+            Error::FormulaConflict {
+                name,
+                conflicting,
+                reason,
+            } => {
+                write!(f, "formula '{name}' conflicts with '{conflicting}'")?;
+                if let Some(reason) = reason {
+                    write!(f, " ({reason})")?;
+                }
+                write!(
+                    f,
+                    "\n\nInstall with --no-link to keep both, or run `zb uninstall {conflicting}` first."
+                )
+            }
+            Error::UnsatisfiedRequirement { name, missing } => {
+                write!(
+                    f,
+                    "formula '{name}' cannot be installed here: {}",
+                    missing.join(", ")
+                )
+            }
+            Error::GlibcTooOld {
+                name,
+                required,
+                found,
+            } => write!(
+                f,
+                "the bottle for '{name}' needs glibc {required} or newer, but this system has glibc {found}\n\n\
+                 Build it from source instead with `zb install --build-from-source {name}`."
+            ),
             Error::DependencyCycle { cycle } => {
                 let rendered = cycle.join(" -> ");
-                write!(f, "dependency cycle detected: {rendered}")
+                write!(
+                    f,
+                    "dependency cycle detected: {rendered}\n\n\
+                     Each formula depends on the next, so none can be installed first. \
+                     One of these dependencies is wrong; check the `depends_on` lines of \
+                     the tap formulas involved."
+                )
+            }
+            Error::DanglingReferences { name, references } => {
+                write!(f, "'{name}' references missing libraries after relocation:")?;
+                for reference in references {
+                    write!(f, "\n  {reference}")?;
+                }
+                Ok(())
+            }
+            Error::Vulnerable { names } => {
+                write!(
+                    f,
+                    "known vulnerabilities affect {} installed {}: {}",
+                    names.len(),
+                    if names.len() == 1 {
+                        "package"
+                    } else {
+                        "packages"
+                    },
+                    names.join(", ")
+                )
             }
             Error::NotInstalled { name } => write!(f, "formula '{name}' is not installed"),
             Error::FileError { message } => write!(f, "file error: {message}"),
             Error::InvalidArgument { message } => write!(f, "invalid argument: {message}"),
             Error::ExecutionError { message } => write!(f, "{message}"),
+            Error::ServiceError { message } => write!(f, "service error: {message}"),
+            Error::ServiceBinaryMissing { name, path } => write!(
+                f,
+                "the {name} service runs '{}', which does not exist; reinstall {name} to restore it",
+                path.display()
+            ),
+            Error::ServiceBinaryNotExecutable { name, path } => write!(
+                f,
+                "the {name} service runs '{}', which is not executable",
+                path.display()
+            ),
+            Error::ServiceFileInvalid { name, reason } => {
+                write!(f, "cannot write a service file for {name}: {reason}")
+            }
+            Error::ServiceLabelTaken { name, label } => write!(
+                f,
+                "Homebrew already runs {name} as {label}\n\n\
+                 Stop it first with `brew services stop {name}`."
+            ),
+            Error::ImportError { message } => write!(f, "import failed: {message}"),
+            Error::MigrationError { message } => write!(f, "migration failed: {message}"),
+            Error::InitError { message } => write!(f, "{message}"),
+            Error::HookRejected {
+                hook,
+                formula,
+                message,
+            } => {
+                match formula {
+                    Some(name) => write!(f, "{hook} hook rejected installing '{name}'")?,
+                    None => write!(f, "{hook} hook rejected the install")?,
+                }
+                if !message.is_empty() {
+                    write!(f, ": {message}")?;
+                }
+                Ok(())
+            }
+            Error::LockfileMismatch { differences } => {
+                writeln!(f, "resolved packages differ from the lockfile:")?;
+                for difference in differences {
+                    writeln!(f, "  {difference}")?;
+                }
+                write!(
+                    f,
+                    "\nUpdate it with `zb install --lockfile` once the new versions are wanted."
+                )
+            }
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::RetriesExhausted { last, .. } => Some(last.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// Whether the failure is plausibly transient: connection-level errors,
+    /// timeouts, rate limiting and server errors. Everything else, including
+    /// a 404 or a checksum mismatch, will fail the same way again.
+    /// Whether the network could not be reached, after any retries.
+    pub fn is_offline(&self) -> bool {
+        match self {
+            Error::NetworkFailure { .. } => true,
+            Error::RetriesExhausted { last, .. } => last.is_offline(),
+            _ => false,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::NetworkFailure { .. } => true,
+            Error::HttpStatus { status, .. } => {
+                *status == 408 || *status == 429 || (500..600).contains(status)
+            }
+            _ => false,
+        }
+    }
+}
 
 macro_rules! error_helpers {
     ($($fn_name:ident => $variant:ident),* $(,)?) => {
@@ -94,6 +394,10 @@ error_helpers! {
     network => NetworkFailure,
     file    => FileError,
     exec    => ExecutionError,
+    service => ServiceError,
+    import  => ImportError,
+    migration => MigrationError,
+    init    => InitError,
 }
 
 #[cfg(test)]
@@ -108,4 +412,152 @@ mod tests {
 
         assert!(err.to_string().contains("libheif"));
     }
+
+    #[test]
+    fn link_conflict_names_formula_and_owner() {
+        let err = Error::LinkConflict {
+            formula: Some("python@3.11".to_string()),
+            conflicts: vec![ConflictedLink {
+                path: PathBuf::from("/opt/zerobrew/prefix/bin/python3"),
+                owned_by: Some("python@3.12".to_string()),
+            }],
+        };
+
+        assert_eq!(
+            err.to_string(),
+            "cannot link python@3.11: link conflict at '/opt/zerobrew/prefix/bin/python3' (owned by python@3.12)"
+        );
+    }
+
+    #[test]
+    fn hook_rejection_names_hook_and_formula() {
+        let err = Error::HookRejected {
+            hook: "pre-install".to_string(),
+            formula: Some("jq".to_string()),
+            message: "not on the approved list".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "pre-install hook rejected installing 'jq': not on the approved list"
+        );
+        let err = Error::HookRejected {
+            hook: "pre-plan".to_string(),
+            formula: None,
+            message: String::new(),
+        };
+        assert_eq!(err.to_string(), "pre-plan hook rejected the install");
+    }
+
+    #[test]
+    fn formula_schema_error_suggests_updating() {
+        let err = Error::FormulaSchema {
+            name: Some("jq".to_string()),
+            message: "missing field `versions`".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "unexpected API JSON for formula 'jq': missing field `versions` (zerobrew may need updating)"
+        );
+    }
+
+    #[test]
+    fn retryable_classification() {
+        let status = |status| Error::HttpStatus {
+            status,
+            message: "GET".to_string(),
+        };
+        assert!(status(503).is_retryable());
+        assert!(status(429).is_retryable());
+        assert!(!status(404).is_retryable());
+        assert!(
+            Error::NetworkFailure {
+                message: "connection reset".to_string()
+            }
+            .is_retryable()
+        );
+        assert!(
+            !Error::MissingFormula {
+                name: "jq".to_string()
+            }
+            .is_retryable()
+        );
+    }
+
+    #[test]
+    fn database_locked_is_not_reported_as_corruption() {
+        let err = Error::DatabaseLocked {
+            context: "failed to start transaction".to_string(),
+        };
+        let message = err.to_string();
+        assert_eq!(
+            message,
+            "failed to start transaction: the database is locked by another zerobrew process; \
+             try again once it finishes"
+        );
+        assert!(!message.contains("corruption"));
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    fn helpers_pick_matching_variant() {
+        let err = (Error::import("failed to read Brewfile"))("no such file");
+        assert_eq!(
+            err,
+            Error::ImportError {
+                message: "failed to read Brewfile: no such file".to_string()
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "import failed: failed to read Brewfile: no such file"
+        );
+        assert!(matches!(
+            (Error::migration("brew leaves failed"))("exit 1"),
+            Error::MigrationError { .. }
+        ));
+    }
+
+    #[test]
+    fn retries_exhausted_chains_last_error() {
+        use std::error::Error as _;
+
+        let err = Error::RetriesExhausted {
+            attempts: 3,
+            last: Box::new(Error::HttpStatus {
+                status: 503,
+                message: "bottle download".to_string(),
+            }),
+        };
+        assert!(!err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "giving up after 3 attempts: network failure: bottle download (HTTP 503)"
+        );
+        assert!(err.source().unwrap().to_string().contains("503"));
+    }
+
+    #[test]
+    fn offline_sees_through_exhausted_retries() {
+        let offline = Error::NetworkFailure {
+            message: "connection refused".to_string(),
+        };
+        assert!(offline.is_offline());
+        assert!(
+            Error::RetriesExhausted {
+                attempts: 3,
+                last: Box::new(offline),
+            }
+            .is_offline()
+        );
+        assert!(
+            !Error::RetriesExhausted {
+                attempts: 3,
+                last: Box::new(Error::HttpStatus {
+                    status: 503,
+                    message: "formula".to_string(),
+                }),
+            }
+            .is_offline()
+        );
+    }
 }
@@ -0,0 +1,205 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::Formula;
+
+/// Prebuilt release binaries a formula ships instead of bottles, typically
+/// assets of a GitHub release:
+///
+/// ```json
+/// "binary": {
+///   "url": "https://github.com/o/tool/releases/download/v{version}/tool-{os}-{arch}.tar.gz",
+///   "platforms": {
+///     "darwin-arm64": { "sha256": "…" },
+///     "linux-x64": { "sha256": "…", "url": "https://…/tool-linux-musl.zip" }
+///   },
+///   "strip_components": 1,
+///   "binaries": ["tool", { "source": "extra/toolctl", "target": "tctl" }]
+/// }
+/// ```
+///
+/// Platforms are keyed `<os>-<arch>` like zerobrew's own release assets,
+/// and `{version}`, `{os}` and `{arch}` in URLs expand to match.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct BinaryArtifact {
+    /// URL template for platforms that do not give their own.
+    #[serde(default)]
+    pub url: Option<String>,
+    pub platforms: BTreeMap<String, BinaryPlatform>,
+    /// Leading directories to drop from archive paths. Unset, a lone
+    /// top-level directory is stepped into, as Homebrew stages archives.
+    #[serde(default)]
+    pub strip_components: Option<usize>,
+    pub binaries: Vec<BinaryTarget>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct BinaryPlatform {
+    #[serde(default)]
+    pub url: Option<String>,
+    pub sha256: String,
+}
+
+/// A file of the download installed into the keg's `bin` as `target`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryTarget {
+    /// Path inside the archive, after stripping; ignored for a download
+    /// that is the binary itself.
+    pub source: String,
+    pub target: String,
+}
+
+impl<'de> Deserialize<'de> for BinaryTarget {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Entry {
+            Path(String),
+            Renamed {
+                source: String,
+                target: Option<String>,
+            },
+        }
+
+        let (source, target) = match Entry::deserialize(deserializer)? {
+            Entry::Path(source) => (source, None),
+            Entry::Renamed { source, target } => (source, target),
+        };
+        let target = target.unwrap_or_else(|| {
+            source
+                .rsplit('/')
+                .find(|segment| !segment.is_empty())
+                .unwrap_or(&source)
+                .to_string()
+        });
+        Ok(BinaryTarget { source, target })
+    }
+}
+
+/// The release binary download for one platform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectedBinary {
+    /// The `<os>-<arch>` key it was selected by.
+    pub platform: String,
+    pub url: String,
+    pub sha256: String,
+    pub strip_components: Option<usize>,
+    pub binaries: Vec<BinaryTarget>,
+}
+
+/// The `<os>-<arch>` key of the platform running `os` on `arch`, both as
+/// Rust names them (`macos`, `aarch64`, ...).
+pub fn binary_platform_key(os: &str, arch: &str) -> Option<String> {
+    let os = match os {
+        "macos" => "darwin",
+        "linux" => "linux",
+        _ => return None,
+    };
+    let arch = match arch {
+        "aarch64" => "arm64",
+        "x86_64" => "x64",
+        _ => return None,
+    };
+    Some(format!("{os}-{arch}"))
+}
+
+/// The release binary `formula` ships for this OS on `arch`, with its URL
+/// template expanded.
+pub fn select_binary(formula: &Formula, arch: &str) -> Option<SelectedBinary> {
+    let artifact = formula.binary.as_ref()?;
+    let key = binary_platform_key(std::env::consts::OS, arch)?;
+    let platform = artifact.platforms.get(&key)?;
+    let template = platform.url.as_ref().or(artifact.url.as_ref())?;
+    if artifact.binaries.is_empty() {
+        return None;
+    }
+
+    let (os, arch) = key.split_once('-').expect("platform keys are <os>-<arch>");
+    let url = template
+        .replace("{version}", &formula.versions.stable)
+        .replace("{os}", os)
+        .replace("{arch}", arch);
+    Some(SelectedBinary {
+        url,
+        sha256: platform.sha256.clone(),
+        strip_components: artifact.strip_components,
+        binaries: artifact.binaries.clone(),
+        platform: key,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formula(binary: serde_json::Value) -> Formula {
+        serde_json::from_value(serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "2.1.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": {} } },
+            "binary": binary,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn expands_url_template_for_the_host_platform() {
+        let key = binary_platform_key(std::env::consts::OS, "x86_64").unwrap();
+        let platforms = serde_json::json!({ key.as_str(): { "sha256": "abc" } });
+        let formula = formula(serde_json::json!({
+            "url": "https://github.com/o/tool/releases/download/v{version}/tool-{os}-{arch}.tar.gz",
+            "platforms": platforms,
+            "strip_components": 1,
+            "binaries": ["tool", { "source": "extra/toolctl", "target": "tctl" }, { "source": "bin/helper" }],
+        }));
+
+        let selected = select_binary(&formula, "x86_64").unwrap();
+        assert_eq!(
+            selected.url,
+            format!("https://github.com/o/tool/releases/download/v2.1.0/tool-{key}.tar.gz")
+        );
+        assert_eq!(selected.sha256, "abc");
+        assert_eq!(selected.platform, key);
+        assert_eq!(selected.strip_components, Some(1));
+        assert_eq!(
+            selected
+                .binaries
+                .iter()
+                .map(|b| (b.source.as_str(), b.target.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("tool", "tool"),
+                ("extra/toolctl", "tctl"),
+                ("bin/helper", "helper")
+            ]
+        );
+
+        assert_eq!(select_binary(&formula, "aarch64"), None);
+    }
+
+    #[test]
+    fn platform_url_overrides_the_template() {
+        let key = binary_platform_key(std::env::consts::OS, "aarch64").unwrap();
+        let formula = formula(serde_json::json!({
+            "platforms": { key: { "sha256": "abc", "url": "https://example.com/tool-{version}.zip" } },
+            "binaries": ["tool"],
+        }));
+        let selected = select_binary(&formula, "aarch64").unwrap();
+        assert_eq!(selected.url, "https://example.com/tool-2.1.0.zip");
+        assert_eq!(selected.strip_components, None);
+    }
+
+    #[test]
+    fn platform_keys_match_release_asset_names() {
+        assert_eq!(
+            binary_platform_key("macos", "aarch64").as_deref(),
+            Some("darwin-arm64")
+        );
+        assert_eq!(
+            binary_platform_key("linux", "x86_64").as_deref(),
+            Some("linux-x64")
+        );
+        assert_eq!(binary_platform_key("windows", "x86_64"), None);
+    }
+}
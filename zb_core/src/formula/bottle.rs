@@ -5,32 +5,76 @@ pub struct SelectedBottle {
     pub tag: String,
     pub url: String,
     pub sha256: String,
+    pub cellar: BottleCellar,
 }
 
-const MACOS_CODENAMES_NEWEST_FIRST: &[&str] = &["tahoe", "sequoia", "sonoma", "ventura"];
+/// The cellar requirement a bottle was published with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BottleCellar {
+    /// `:any_skip_relocation`: the bottle holds no prefix or cellar paths
+    /// and pours as is.
+    AnySkipRelocation,
+    /// `:any`: the bottle pours into any cellar once its placeholders are
+    /// relocated. Assumed when the metadata does not say.
+    #[default]
+    Any,
+    /// The bottle was built for this cellar path and is relocated like
+    /// `:any` elsewhere.
+    Path(String),
+}
 
-#[cfg(any(target_os = "linux", test))]
-fn preferred_linux_bottle_tags_for_arch(arch: &str) -> &'static [&'static str] {
-    match arch {
-        "aarch64" => &["arm64_linux", "aarch64_linux"],
-        "x86_64" => &["x86_64_linux"],
-        _ => &[],
+impl BottleCellar {
+    /// Read the `cellar` field of a bottle file.
+    pub fn parse(cellar: Option<&str>) -> Self {
+        match cellar {
+            Some(":any_skip_relocation") => BottleCellar::AnySkipRelocation,
+            None | Some(":any") => BottleCellar::Any,
+            Some(path) => BottleCellar::Path(path.to_string()),
+        }
+    }
+
+    /// Whether poured kegs must have their placeholders patched.
+    pub fn needs_relocation(&self) -> bool {
+        !matches!(self, BottleCellar::AnySkipRelocation)
     }
 }
 
-#[cfg(target_os = "linux")]
-fn preferred_linux_bottle_tags() -> &'static [&'static str] {
-    preferred_linux_bottle_tags_for_arch(std::env::consts::ARCH)
+/// How far [`select_bottle`] may stray from the host's own platform tag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BottleStrictness {
+    /// Only a bottle built for the running OS release, or an `all` bottle.
+    Exact,
+    /// Like Homebrew, fall back to bottles built for older OS releases when
+    /// the running one has none, e.g. on a macOS release newer than any
+    /// published bottle.
+    #[default]
+    Compatible,
 }
 
-#[cfg(any(target_os = "linux", test))]
-fn is_compatible_linux_bottle_tag_for_arch(tag: &str, arch: &str) -> bool {
-    preferred_linux_bottle_tags_for_arch(arch).contains(&tag)
+/// macOS releases with bottles, newest first, with their major versions.
+const MACOS_RELEASES_NEWEST_FIRST: &[(&str, u32)] = &[
+    ("tahoe", 26),
+    ("sequoia", 15),
+    ("sonoma", 14),
+    ("ventura", 13),
+];
+
+/// Oldest glibc that Homebrew's Linux bottles run on. They are built on
+/// Ubuntu 22.04.
+const LINUX_BOTTLE_GLIBC: (u32, u32) = (2, 35);
+
+/// Minimum glibc the bottle for `tag` was built against, for Linux tags.
+pub fn bottle_glibc_baseline(tag: &str) -> Option<(u32, u32)> {
+    tag.ends_with("_linux").then_some(LINUX_BOTTLE_GLIBC)
 }
 
-#[cfg(target_os = "linux")]
-fn is_compatible_linux_bottle_tag(tag: &str) -> bool {
-    is_compatible_linux_bottle_tag_for_arch(tag, std::env::consts::ARCH)
+#[cfg(any(target_os = "linux", test))]
+fn preferred_linux_bottle_tags_for_arch(arch: &str) -> &'static [&'static str] {
+    match arch {
+        "aarch64" => &["arm64_linux", "aarch64_linux"],
+        "x86_64" => &["x86_64_linux"],
+        _ => &[],
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -43,144 +87,157 @@ pub fn macos_major_version() -> Option<u32> {
     version.trim().split('.').next()?.parse().ok()
 }
 
+#[cfg(any(target_os = "macos", test))]
 fn codename_for_major(major: u32) -> Option<&'static str> {
-    match major {
-        26 => Some("tahoe"),
-        15 => Some("sequoia"),
-        14 => Some("sonoma"),
-        13 => Some("ventura"),
-        _ => None,
-    }
+    MACOS_RELEASES_NEWEST_FIRST
+        .iter()
+        .find(|(_, m)| *m == major)
+        .map(|(codename, _)| *codename)
 }
 
+/// Codenames whose bottles run on macOS `major_version`, newest first.
+///
+/// A release newer than any known one can use all of them; an unknown
+/// version is assumed to be new.
 pub fn compatible_codenames(major_version: Option<u32>) -> Vec<&'static str> {
-    let Some(major) = major_version else {
-        return MACOS_CODENAMES_NEWEST_FIRST.to_vec();
-    };
+    MACOS_RELEASES_NEWEST_FIRST
+        .iter()
+        .filter(|(_, major)| major_version.is_none_or(|host| *major <= host))
+        .map(|(codename, _)| *codename)
+        .collect()
+}
 
-    let Some(pos) = codename_for_major(major)
-        .and_then(|c| MACOS_CODENAMES_NEWEST_FIRST.iter().position(|&t| t == c))
-    else {
-        return MACOS_CODENAMES_NEWEST_FIRST.to_vec();
+#[cfg(any(target_os = "macos", test))]
+fn macos_candidate_tags(
+    arm64: bool,
+    macos_version: Option<u32>,
+    strictness: BottleStrictness,
+) -> Vec<String> {
+    let codenames = match strictness {
+        BottleStrictness::Compatible => compatible_codenames(macos_version),
+        BottleStrictness::Exact => macos_version
+            .and_then(codename_for_major)
+            .into_iter()
+            .collect(),
     };
+    codenames
+        .into_iter()
+        .map(|codename| {
+            if arm64 {
+                format!("arm64_{codename}")
+            } else {
+                codename.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Tags to try for `arch` on the host OS, most preferred first, before
+/// falling back to an `all` bottle.
+fn host_candidate_tags(
+    arch: &str,
+    macos_version: Option<u32>,
+    strictness: BottleStrictness,
+) -> Vec<String> {
+    // Consumed only on some platforms; silence unused-variable elsewhere.
+    let _ = (&arch, &macos_version, &strictness);
+
+    #[cfg(target_os = "macos")]
+    let tags = macos_candidate_tags(arch == "aarch64", macos_version, strictness);
+
+    #[cfg(target_os = "linux")]
+    let tags = preferred_linux_bottle_tags_for_arch(arch)
+        .iter()
+        .map(|tag| tag.to_string())
+        .collect();
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    let tags = Vec::new();
 
-    MACOS_CODENAMES_NEWEST_FIRST[pos..].to_vec()
+    tags
 }
 
 pub fn select_bottle(formula: &Formula) -> Result<SelectedBottle, Error> {
+    select_bottle_with_strictness(formula, BottleStrictness::default())
+}
+
+pub fn select_bottle_with_strictness(
+    formula: &Formula,
+    strictness: BottleStrictness,
+) -> Result<SelectedBottle, Error> {
+    select_bottle_for_arch(formula, std::env::consts::ARCH, strictness)
+}
+
+/// Select a bottle for the host OS but another CPU architecture, e.g.
+/// Intel bottles to run under Rosetta 2 on Apple Silicon.
+pub fn select_bottle_for_arch(
+    formula: &Formula,
+    arch: &str,
+    strictness: BottleStrictness,
+) -> Result<SelectedBottle, Error> {
     #[cfg(target_os = "macos")]
     let macos_version = macos_major_version();
     #[cfg(not(target_os = "macos"))]
     let macos_version: Option<u32> = None;
 
-    select_bottle_with_version(formula, macos_version)
+    select_bottle_with_version(formula, arch, macos_version, strictness)
 }
 
 fn select_bottle_with_version(
     formula: &Formula,
+    arch: &str,
     macos_version: Option<u32>,
+    strictness: BottleStrictness,
 ) -> Result<SelectedBottle, Error> {
-    // Consumed only in #[cfg(target_os = "macos")] blocks; silence unused-variable on Linux.
-    let _ = &macos_version;
-
-    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-    {
-        let codenames = compatible_codenames(macos_version);
-        let tags: Vec<String> = codenames.iter().map(|c| format!("arm64_{c}")).collect();
-
-        for tag in &tags {
-            if let Some(file) = formula.bottle.stable.files.get(tag.as_str()) {
-                return Ok(SelectedBottle {
-                    tag: tag.clone(),
-                    url: file.url.clone(),
-                    sha256: file.sha256.clone(),
-                });
-            }
-        }
-    }
-
-    #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-    {
-        let tags = compatible_codenames(macos_version);
-
-        for tag in &tags {
-            if let Some(file) = formula.bottle.stable.files.get(*tag) {
-                return Ok(SelectedBottle {
-                    tag: tag.to_string(),
-                    url: file.url.clone(),
-                    sha256: file.sha256.clone(),
-                });
-            }
-        }
-    }
-
-    #[cfg(target_os = "linux")]
-    {
-        for &preferred_tag in preferred_linux_bottle_tags() {
-            if let Some(file) = formula.bottle.stable.files.get(preferred_tag) {
-                return Ok(SelectedBottle {
-                    tag: preferred_tag.to_string(),
-                    url: file.url.clone(),
-                    sha256: file.sha256.clone(),
-                });
-            }
-        }
-    }
-
-    if let Some(file) = formula.bottle.stable.files.get("all") {
-        return Ok(SelectedBottle {
-            tag: "all".to_string(),
+    let candidates = host_candidate_tags(arch, macos_version, strictness);
+    candidates
+        .iter()
+        .map(String::as_str)
+        .chain(["all"])
+        .find_map(|tag| formula.bottle.stable.files.get_key_value(tag))
+        .map(|(tag, file)| SelectedBottle {
+            tag: tag.clone(),
             url: file.url.clone(),
             sha256: file.sha256.clone(),
-        });
-    }
-
-    #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-    {
-        let codenames = compatible_codenames(macos_version);
-        for (tag, file) in &formula.bottle.stable.files {
-            if tag.starts_with("arm64_") && !tag.contains("linux") {
-                let bare = tag.strip_prefix("arm64_").unwrap_or(tag);
-                if codenames.contains(&bare) {
-                    return Ok(SelectedBottle {
-                        tag: tag.clone(),
-                        url: file.url.clone(),
-                        sha256: file.sha256.clone(),
-                    });
-                }
-            }
-        }
-    }
-
-    #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-    {
-        let codenames = compatible_codenames(macos_version);
-        for (tag, file) in &formula.bottle.stable.files {
-            if !tag.starts_with("arm64_") && !tag.contains("linux") && tag != "all" {
-                if codenames.contains(&tag.as_str()) {
-                    return Ok(SelectedBottle {
-                        tag: tag.clone(),
-                        url: file.url.clone(),
-                        sha256: file.sha256.clone(),
-                    });
-                }
-            }
-        }
-    }
+            cellar: BottleCellar::parse(file.cellar.as_deref()),
+        })
+        .ok_or_else(|| Error::UnsupportedBottle {
+            name: formula.name.clone(),
+        })
+}
 
-    #[cfg(target_os = "linux")]
-    for (tag, file) in &formula.bottle.stable.files {
-        if is_compatible_linux_bottle_tag(tag) {
-            return Ok(SelectedBottle {
-                tag: tag.clone(),
-                url: file.url.clone(),
-                sha256: file.sha256.clone(),
-            });
-        }
-    }
+/// Bottle tags the formula publishes, in sorted order.
+pub fn bottle_tags(formula: &Formula) -> Vec<&str> {
+    formula
+        .bottle
+        .stable
+        .files
+        .keys()
+        .map(String::as_str)
+        .collect()
+}
 
-    Err(Error::UnsupportedBottle {
-        name: formula.name.clone(),
+/// Select the bottle for an explicit platform `tag` instead of the host.
+///
+/// A formula that only ships an `all` bottle satisfies any tag. Used to
+/// prefetch bottles for other machines.
+pub fn select_bottle_for_tag(formula: &Formula, tag: &str) -> Result<SelectedBottle, Error> {
+    let files = &formula.bottle.stable.files;
+    let (tag, file) = match files.get_key_value(tag) {
+        Some(found) => found,
+        None => files
+            .get_key_value("all")
+            .ok_or_else(|| Error::BottleTagUnavailable {
+                name: formula.name.clone(),
+                tag: tag.to_string(),
+                available: bottle_tags(formula).into_iter().map(String::from).collect(),
+            })?,
+    };
+    Ok(SelectedBottle {
+        tag: tag.clone(),
+        url: file.url.clone(),
+        sha256: file.sha256.clone(),
+        cellar: BottleCellar::parse(file.cellar.as_deref()),
     })
 }
 
@@ -237,6 +294,53 @@ mod tests {
         }
     }
 
+    #[test]
+    fn selects_explicit_tag_regardless_of_host() {
+        let fixture = include_str!("../../fixtures/formula_foo.json");
+        let formula: Formula = serde_json::from_str(fixture).unwrap();
+
+        assert_eq!(
+            bottle_tags(&formula),
+            vec!["arm64_sonoma", "sonoma", "x86_64_linux"]
+        );
+        let selected = select_bottle_for_tag(&formula, "arm64_sonoma").unwrap();
+        assert_eq!(selected.tag, "arm64_sonoma");
+        assert_eq!(
+            selected.url,
+            "https://example.com/foo-1.2.3.arm64_sonoma.bottle.tar.gz"
+        );
+
+        let err = select_bottle_for_tag(&formula, "arm64_linux").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no 'arm64_linux' bottle for formula 'foo' (available: arm64_sonoma, sonoma, x86_64_linux)"
+        );
+    }
+
+    #[test]
+    fn selected_bottle_carries_cellar_requirement() {
+        let mut formula: Formula =
+            serde_json::from_str(include_str!("../../fixtures/formula_foo.json")).unwrap();
+        assert_eq!(
+            select_bottle_for_tag(&formula, "sonoma").unwrap().cellar,
+            BottleCellar::Any
+        );
+
+        let files = &mut formula.bottle.stable.files;
+        files.get_mut("sonoma").unwrap().cellar = Some(":any_skip_relocation".to_string());
+        files.get_mut("arm64_sonoma").unwrap().cellar = Some("/opt/homebrew/Cellar".to_string());
+        let skip = select_bottle_for_tag(&formula, "sonoma").unwrap();
+        assert_eq!(skip.cellar, BottleCellar::AnySkipRelocation);
+        assert!(!skip.cellar.needs_relocation());
+        let fixed = select_bottle_for_tag(&formula, "arm64_sonoma").unwrap();
+        assert_eq!(
+            fixed.cellar,
+            BottleCellar::Path("/opt/homebrew/Cellar".to_string())
+        );
+        assert!(fixed.cellar.needs_relocation());
+        assert!(BottleCellar::parse(Some(":any")).needs_relocation());
+    }
+
     #[test]
     fn linux_arm_prefers_arm64_bottle_tags() {
         assert_eq!(
@@ -254,15 +358,36 @@ mod tests {
     }
 
     #[test]
-    fn linux_fallback_rejects_cross_arch_bottle_tags() {
-        assert!(!is_compatible_linux_bottle_tag_for_arch(
-            "x86_64_linux",
-            "aarch64"
-        ));
-        assert!(!is_compatible_linux_bottle_tag_for_arch(
-            "arm64_linux",
-            "x86_64"
-        ));
+    fn linux_rejects_cross_arch_bottle_tags() {
+        assert!(!preferred_linux_bottle_tags_for_arch("aarch64").contains(&"x86_64_linux"));
+        assert!(!preferred_linux_bottle_tags_for_arch("x86_64").contains(&"arm64_linux"));
+    }
+
+    #[test]
+    fn newer_macos_falls_back_to_older_bottles() {
+        assert_eq!(
+            macos_candidate_tags(true, Some(27), BottleStrictness::Compatible),
+            vec![
+                "arm64_tahoe",
+                "arm64_sequoia",
+                "arm64_sonoma",
+                "arm64_ventura"
+            ]
+        );
+        assert_eq!(
+            macos_candidate_tags(false, Some(14), BottleStrictness::Compatible),
+            vec!["sonoma", "ventura"]
+        );
+    }
+
+    #[test]
+    fn exact_strictness_only_tries_the_host_release() {
+        assert_eq!(
+            macos_candidate_tags(true, Some(15), BottleStrictness::Exact),
+            vec!["arm64_sequoia"]
+        );
+        assert!(macos_candidate_tags(true, Some(27), BottleStrictness::Exact).is_empty());
+        assert!(macos_candidate_tags(false, None, BottleStrictness::Exact).is_empty());
     }
 
     #[test]
@@ -274,6 +399,7 @@ mod tests {
                 url: "https://ghcr.io/v2/homebrew/core/ca-certificates/blobs/sha256:abc123"
                     .to_string(),
                 sha256: "abc123".to_string(),
+                cellar: None,
             },
         );
 
@@ -285,22 +411,38 @@ mod tests {
             dependencies: Vec::new(),
             bottle: Bottle {
                 stable: BottleStable { files, rebuild: 0 },
+                variants: BTreeMap::new(),
             },
             revision: 0,
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            test_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
+            service: None,
+            desc: None,
+            homepage: None,
+            license: None,
+            binary: None,
+            resources: Vec::new(),
         };
 
         let selected = select_bottle(&formula).unwrap();
         assert_eq!(selected.tag, "all");
         assert!(selected.url.contains("ca-certificates"));
+        assert_eq!(
+            select_bottle_for_tag(&formula, "arm64_tahoe").unwrap().tag,
+            "all"
+        );
     }
 
     #[test]
@@ -313,6 +455,7 @@ mod tests {
                 url: "https://example.com/legacy.tar.gz".to_string(),
                 sha256: "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc"
                     .to_string(),
+                cellar: None,
             },
         );
 
@@ -324,17 +467,29 @@ mod tests {
             dependencies: Vec::new(),
             bottle: Bottle {
                 stable: BottleStable { files, rebuild: 0 },
+                variants: BTreeMap::new(),
             },
             revision: 0,
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            test_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
+            service: None,
+            desc: None,
+            homepage: None,
+            license: None,
+            binary: None,
+            resources: Vec::new(),
         };
 
         let err = select_bottle(&formula).unwrap_err();
@@ -354,6 +509,7 @@ mod tests {
                 url: "https://example.com/legacy.tar.gz".to_string(),
                 sha256: "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
                     .to_string(),
+                cellar: None,
             },
         );
 
@@ -365,17 +521,29 @@ mod tests {
             dependencies: Vec::new(),
             bottle: Bottle {
                 stable: BottleStable { files, rebuild: 0 },
+                variants: BTreeMap::new(),
             },
             revision: 0,
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            test_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
+            service: None,
+            desc: None,
+            homepage: None,
+            license: None,
+            binary: None,
+            resources: Vec::new(),
         };
 
         let err = select_bottle(&formula).unwrap_err();
@@ -409,12 +577,74 @@ mod tests {
         assert_eq!(codenames, vec!["ventura"]);
     }
 
+    #[test]
+    fn selects_bottle_for_other_arch() {
+        let fixture = include_str!("../../fixtures/formula_foo.json");
+        let formula: Formula = serde_json::from_str(fixture).unwrap();
+
+        let intel =
+            select_bottle_for_arch(&formula, "x86_64", BottleStrictness::Compatible).unwrap();
+        #[cfg(target_os = "macos")]
+        assert_eq!(intel.tag, "sonoma");
+        #[cfg(target_os = "linux")]
+        assert_eq!(intel.tag, "x86_64_linux");
+
+        #[cfg(target_os = "linux")]
+        assert!(select_bottle_for_arch(&formula, "aarch64", BottleStrictness::Compatible).is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn aarch64_linux_prefers_arm64_linux_bottles() {
+        let formula = |tags: &[&str]| -> Formula {
+            let files: serde_json::Map<String, serde_json::Value> = tags
+                .iter()
+                .map(|tag| {
+                    (
+                        tag.to_string(),
+                        serde_json::json!({
+                            "url": format!("https://example.com/foo.{tag}.bottle.tar.gz"),
+                            "sha256": "ab".repeat(32),
+                        }),
+                    )
+                })
+                .collect();
+            serde_json::from_value(serde_json::json!({
+                "name": "foo",
+                "versions": { "stable": "1.0" },
+                "dependencies": [],
+                "bottle": { "stable": { "files": files } }
+            }))
+            .unwrap()
+        };
+
+        let arm = |tags: &[&str]| {
+            select_bottle_for_arch(&formula(tags), "aarch64", BottleStrictness::Compatible)
+                .map(|bottle| bottle.tag)
+        };
+        assert_eq!(
+            arm(&["x86_64_linux", "arm64_linux", "all"]).unwrap(),
+            "arm64_linux"
+        );
+        assert_eq!(
+            arm(&["x86_64_linux", "aarch64_linux"]).unwrap(),
+            "aarch64_linux"
+        );
+        assert_eq!(arm(&["x86_64_linux", "all"]).unwrap(), "all");
+        assert!(arm(&["x86_64_linux", "arm64_sonoma"]).is_err());
+    }
+
     #[test]
     fn compatible_codenames_unknown_version_returns_all() {
         let codenames = compatible_codenames(Some(99));
         assert_eq!(codenames, vec!["tahoe", "sequoia", "sonoma", "ventura"]);
     }
 
+    #[test]
+    fn compatible_codenames_before_oldest_release_is_empty() {
+        assert!(compatible_codenames(Some(12)).is_empty());
+    }
+
     #[test]
     fn compatible_codenames_none_returns_all() {
         let codenames = compatible_codenames(None);
@@ -430,6 +660,7 @@ mod tests {
             BottleFile {
                 url: "https://example.com/tahoe.tar.gz".to_string(),
                 sha256: "aaaa".repeat(16),
+                cellar: None,
             },
         );
         files.insert(
@@ -437,6 +668,7 @@ mod tests {
             BottleFile {
                 url: "https://example.com/sequoia.tar.gz".to_string(),
                 sha256: "bbbb".repeat(16),
+                cellar: None,
             },
         );
 
@@ -448,20 +680,38 @@ mod tests {
             dependencies: Vec::new(),
             bottle: Bottle {
                 stable: BottleStable { files, rebuild: 0 },
+                variants: BTreeMap::new(),
             },
             revision: 0,
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            test_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
+            service: None,
+            desc: None,
+            homepage: None,
+            license: None,
+            binary: None,
+            resources: Vec::new(),
         };
 
-        let selected = select_bottle_with_version(&formula, Some(15)).unwrap();
+        let selected = select_bottle_with_version(
+            &formula,
+            std::env::consts::ARCH,
+            Some(15),
+            BottleStrictness::Compatible,
+        )
+        .unwrap();
 
         #[cfg(target_arch = "aarch64")]
         assert_eq!(selected.tag, "arm64_sequoia");
@@ -479,6 +729,7 @@ mod tests {
             BottleFile {
                 url: "https://example.com/tahoe.tar.gz".to_string(),
                 sha256: "aaaa".repeat(16),
+                cellar: None,
             },
         );
         files.insert(
@@ -486,6 +737,7 @@ mod tests {
             BottleFile {
                 url: "https://example.com/sequoia.tar.gz".to_string(),
                 sha256: "bbbb".repeat(16),
+                cellar: None,
             },
         );
 
@@ -497,20 +749,38 @@ mod tests {
             dependencies: Vec::new(),
             bottle: Bottle {
                 stable: BottleStable { files, rebuild: 0 },
+                variants: BTreeMap::new(),
             },
             revision: 0,
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            test_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
+            service: None,
+            desc: None,
+            homepage: None,
+            license: None,
+            binary: None,
+            resources: Vec::new(),
         };
 
-        let selected = select_bottle_with_version(&formula, Some(26)).unwrap();
+        let selected = select_bottle_with_version(
+            &formula,
+            std::env::consts::ARCH,
+            Some(26),
+            BottleStrictness::Compatible,
+        )
+        .unwrap();
 
         #[cfg(target_arch = "aarch64")]
         assert_eq!(selected.tag, "arm64_tahoe");
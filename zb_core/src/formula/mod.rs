@@ -1,15 +1,34 @@
+pub mod binary;
 pub mod bottle;
+pub mod platform;
+pub mod requirements;
 pub mod resolve;
 pub mod types;
 
-pub use bottle::{SelectedBottle, compatible_codenames, select_bottle};
+pub use binary::{
+    BinaryArtifact, BinaryPlatform, BinaryTarget, SelectedBinary, binary_platform_key,
+    select_binary,
+};
+pub use bottle::{
+    BottleCellar, BottleStrictness, SelectedBottle, bottle_tags, compatible_codenames,
+    select_bottle, select_bottle_for_arch, select_bottle_for_tag, select_bottle_with_strictness,
+};
 
 #[cfg(target_os = "macos")]
 pub use bottle::macos_major_version;
-pub use resolve::resolve_closure;
+pub use platform::{Platform, parse_arch};
+pub use requirements::{
+    GlibcVersion, HostFacts, Requirement, check_bottle_glibc, check_requirements,
+    parse_glibc_version,
+};
+pub use resolve::{
+    DependencyGraph, dependency_graph_for, dependency_graph_with, resolve_closure,
+    resolve_closure_for, resolve_closure_with,
+};
 pub use types::{
-    Bottle, BottleFile, BottleStable, Formula, FormulaUrls, KegOnly, KegOnlyReason,
-    RubySourceChecksum, SourceUrl, UsesFromMacos, Versions,
+    Bottle, BottleFile, BottleStable, DependencyClass, DependencyClasses, Formula, FormulaResource,
+    FormulaUrls, HeadSource, KegOnly, KegOnlyReason, RubySourceChecksum, SourceUrl, UsesFromMacos,
+    Versions,
 };
 
 /// Extract the formula token from an install key.
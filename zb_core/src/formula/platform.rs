@@ -0,0 +1,100 @@
+/// The operating system a formula's dependencies are resolved for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Macos,
+    /// `arch` is a Rust target architecture such as `x86_64` or `aarch64`.
+    Linux {
+        arch: &'static str,
+    },
+}
+
+impl Platform {
+    pub fn host() -> Self {
+        if cfg!(target_os = "macos") {
+            Platform::Macos
+        } else {
+            Platform::Linux {
+                arch: std::env::consts::ARCH,
+            }
+        }
+    }
+
+    /// The platform a bottle `tag` targets, or `None` for `all` and
+    /// unrecognized Linux architectures.
+    pub fn from_bottle_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "all" => None,
+            "arm64_linux" | "aarch64_linux" => Some(Platform::Linux { arch: "aarch64" }),
+            "x86_64_linux" => Some(Platform::Linux { arch: "x86_64" }),
+            _ if tag.ends_with("_linux") => None,
+            _ => Some(Platform::Macos),
+        }
+    }
+
+    /// Whether `uses_from_macos` formulas are real dependencies here. macOS
+    /// ships them with the OS; everywhere else they must be installed.
+    pub fn installs_uses_from_macos(self) -> bool {
+        !matches!(self, Platform::Macos)
+    }
+
+    /// Keys of the formula `variations` entries that apply, in preference
+    /// order.
+    pub(crate) fn variation_keys(self) -> &'static [&'static str] {
+        match self {
+            Platform::Macos => &[],
+            Platform::Linux { arch: "aarch64" } => &["arm64_linux", "aarch64_linux"],
+            Platform::Linux { arch: "x86_64" } => &["x86_64_linux"],
+            Platform::Linux { .. } => &[],
+        }
+    }
+}
+
+/// Normalize a user-facing CPU architecture name (`arm64`, `intel`, ...) to
+/// the Rust target arch bottles are selected for.
+pub fn parse_arch(arch: &str) -> Option<&'static str> {
+    match arch {
+        "arm64" | "aarch64" => Some("aarch64"),
+        "x86_64" | "intel" | "amd64" => Some("x86_64"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_matches_target_os() {
+        assert_eq!(
+            Platform::host() == Platform::Macos,
+            cfg!(target_os = "macos")
+        );
+    }
+
+    #[test]
+    fn only_macos_skips_uses_from_macos() {
+        assert!(!Platform::Macos.installs_uses_from_macos());
+        assert!(Platform::Linux { arch: "aarch64" }.installs_uses_from_macos());
+    }
+
+    #[test]
+    fn bottle_tags_map_to_platforms() {
+        assert_eq!(
+            Platform::from_bottle_tag("arm64_linux"),
+            Some(Platform::Linux { arch: "aarch64" })
+        );
+        assert_eq!(
+            Platform::from_bottle_tag("arm64_sonoma"),
+            Some(Platform::Macos)
+        );
+        assert_eq!(Platform::from_bottle_tag("all"), None);
+        assert_eq!(Platform::from_bottle_tag("riscv64_linux"), None);
+    }
+
+    #[test]
+    fn parses_arch_aliases() {
+        assert_eq!(parse_arch("arm64"), Some("aarch64"));
+        assert_eq!(parse_arch("intel"), Some("x86_64"));
+        assert_eq!(parse_arch("ppc"), None);
+    }
+}
@@ -0,0 +1,390 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::formula::bottle::bottle_glibc_baseline;
+use crate::{Error, Formula, Platform, SelectedBottle};
+
+/// A `depends_on` restriction from a formula's `requirements` list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Requirement {
+    /// `depends_on :macos`, optionally with a minimum release.
+    Macos {
+        minimum: Option<MacosVersion>,
+    },
+    MaximumMacos(MacosVersion),
+    Linux,
+    /// `depends_on arch: ...`, normalized to a Rust target arch.
+    Arch(String),
+    Xcode {
+        version: Option<String>,
+    },
+}
+
+/// A macOS release as `(major, minor)`, e.g. `(10, 15)` or `(13, 0)`.
+pub type MacosVersion = (u32, u32);
+
+#[derive(Deserialize)]
+struct RawRequirement {
+    name: String,
+    #[serde(default)]
+    version: Option<String>,
+}
+
+impl Requirement {
+    /// Parse one entry of the formula API's `requirements` array. Returns
+    /// `None` for requirements zerobrew does not check.
+    pub fn parse(value: &serde_json::Value) -> Option<Self> {
+        let raw = RawRequirement::deserialize(value).ok()?;
+        let version = raw.version.as_deref().filter(|v| !v.is_empty());
+        match raw.name.as_str() {
+            "macos" => Some(Requirement::Macos {
+                minimum: version.and_then(parse_macos_version),
+            }),
+            "maximum_macos" => version
+                .and_then(parse_macos_version)
+                .map(Requirement::MaximumMacos),
+            "linux" => Some(Requirement::Linux),
+            "arch" => version.map(|arch| Requirement::Arch(normalize_arch(arch).to_string())),
+            "xcode" => Some(Requirement::Xcode {
+                version: version.map(str::to_string),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Bottles ship prebuilt, so Xcode only matters when building.
+    fn only_for_builds(&self) -> bool {
+        matches!(self, Requirement::Xcode { .. })
+    }
+
+    /// Why `host` does not satisfy this requirement, or `None` if it does.
+    fn unmet(&self, host: &HostFacts) -> Option<String> {
+        let on_macos = host.platform == Platform::Macos;
+        match self {
+            Requirement::Macos { .. } if !on_macos => Some("needs macOS".to_string()),
+            Requirement::Macos {
+                minimum: Some(minimum),
+            } => match host.macos_version {
+                Some(major) if (major, u32::MAX) < *minimum => {
+                    Some(format!("needs macOS >= {}", format_macos_version(*minimum)))
+                }
+                _ => None,
+            },
+            Requirement::Macos { minimum: None } => None,
+            Requirement::MaximumMacos(maximum) => match host.macos_version {
+                Some(major) if (major, 0) > *maximum => {
+                    Some(format!("needs macOS <= {}", format_macos_version(*maximum)))
+                }
+                _ => None,
+            },
+            Requirement::Linux if on_macos => Some("needs Linux".to_string()),
+            Requirement::Linux => None,
+            Requirement::Arch(arch) if arch != host.arch => Some(format!("{arch} only")),
+            Requirement::Arch(_) => None,
+            Requirement::Xcode { version } if on_macos && !host.has_full_xcode() => {
+                Some(match version {
+                    Some(version) => format!("needs Xcode >= {version}"),
+                    None => "needs Xcode".to_string(),
+                })
+            }
+            Requirement::Xcode { .. } => None,
+        }
+    }
+}
+
+fn parse_macos_version(version: &str) -> Option<MacosVersion> {
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |m| m.parse().ok())?;
+    Some((major, minor))
+}
+
+fn format_macos_version((major, minor): MacosVersion) -> String {
+    if minor == 0 {
+        major.to_string()
+    } else {
+        format!("{major}.{minor}")
+    }
+}
+
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "arm64" | "arm" | "aarch64" => "aarch64",
+        "intel" | "x86_64" => "x86_64",
+        other => other,
+    }
+}
+
+/// What requirements are checked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostFacts {
+    pub platform: Platform,
+    /// Rust target arch, e.g. `aarch64`.
+    pub arch: &'static str,
+    pub macos_version: Option<u32>,
+    /// Active developer directory from `xcode-select -p`, if any.
+    pub developer_dir: Option<PathBuf>,
+    /// System glibc on Linux; `None` elsewhere or when it cannot be
+    /// determined (e.g. on musl).
+    pub glibc_version: Option<GlibcVersion>,
+}
+
+/// A glibc release as `(major, minor)`, e.g. `(2, 35)`.
+pub type GlibcVersion = (u32, u32);
+
+/// Parse `2.35`, `2.35.1` or `getconf`'s `glibc 2.35`.
+pub fn parse_glibc_version(version: &str) -> Option<GlibcVersion> {
+    let version = version.trim();
+    let version = version.strip_prefix("glibc ").unwrap_or(version);
+    let mut parts = version.split(['.', '-', '_']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+impl HostFacts {
+    /// Facts about the running machine, probed once per process.
+    pub fn current() -> &'static HostFacts {
+        static CURRENT: OnceLock<HostFacts> = OnceLock::new();
+        CURRENT.get_or_init(HostFacts::detect)
+    }
+
+    fn detect() -> Self {
+        #[cfg(target_os = "macos")]
+        let (macos_version, developer_dir) = (
+            crate::formula::bottle::macos_major_version(),
+            std::process::Command::new("xcode-select")
+                .arg("-p")
+                .output()
+                .ok()
+                .filter(|out| out.status.success())
+                .map(|out| PathBuf::from(String::from_utf8_lossy(&out.stdout).trim())),
+        );
+        #[cfg(not(target_os = "macos"))]
+        let (macos_version, developer_dir) = (None, None);
+
+        #[cfg(target_os = "linux")]
+        let glibc_version = std::process::Command::new("getconf")
+            .arg("GNU_LIBC_VERSION")
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .and_then(|out| parse_glibc_version(&String::from_utf8_lossy(&out.stdout)));
+        #[cfg(not(target_os = "linux"))]
+        let glibc_version = None;
+
+        Self {
+            platform: Platform::host(),
+            arch: std::env::consts::ARCH,
+            macos_version,
+            developer_dir,
+            glibc_version,
+        }
+    }
+
+    /// Whether the developer directory belongs to Xcode.app rather than the
+    /// standalone Command Line Tools.
+    fn has_full_xcode(&self) -> bool {
+        self.developer_dir
+            .as_deref()
+            .is_some_and(|dir| dir.to_string_lossy().contains(".app/"))
+    }
+}
+
+/// Check `formula`'s requirements against `host`, failing with every unmet
+/// one at once. Build-only requirements are checked when `from_source` is
+/// set; on macOS a source build also needs the Command Line Tools.
+pub fn check_requirements(
+    formula: &Formula,
+    host: &HostFacts,
+    from_source: bool,
+) -> Result<(), Error> {
+    let mut missing: Vec<String> = formula
+        .requirements
+        .iter()
+        .filter_map(Requirement::parse)
+        .filter(|req| from_source || !req.only_for_builds())
+        .filter_map(|req| req.unmet(host))
+        .collect();
+
+    if from_source && host.platform == Platform::Macos && host.developer_dir.is_none() {
+        missing
+            .push("needs the Xcode Command Line Tools (run `xcode-select --install`)".to_string());
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::UnsatisfiedRequirement {
+            name: formula.name.clone(),
+            missing,
+        })
+    }
+}
+
+/// Check that `glibc` can run `bottle`. Linux bottles are linked against
+/// the glibc of the system they were built on; an unknown host glibc is
+/// given the benefit of the doubt.
+pub fn check_bottle_glibc(
+    name: &str,
+    bottle: &SelectedBottle,
+    glibc: Option<GlibcVersion>,
+) -> Result<(), Error> {
+    match (bottle_glibc_baseline(&bottle.tag), glibc) {
+        (Some(required), Some(found)) if found < required => Err(Error::GlibcTooOld {
+            name: name.to_string(),
+            required: format!("{}.{}", required.0, required.1),
+            found: format!("{}.{}", found.0, found.1),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn formula_with(requirements: serde_json::Value) -> Formula {
+        let mut formula: Formula =
+            serde_json::from_str(include_str!("../../fixtures/formula_foo.json")).unwrap();
+        formula.requirements = serde_json::from_value(requirements).unwrap();
+        formula
+    }
+
+    fn mac(version: u32, developer_dir: Option<&str>) -> HostFacts {
+        HostFacts {
+            platform: Platform::Macos,
+            arch: "aarch64",
+            macos_version: Some(version),
+            developer_dir: developer_dir.map(PathBuf::from),
+            glibc_version: None,
+        }
+    }
+
+    fn linux() -> HostFacts {
+        HostFacts {
+            platform: Platform::Linux { arch: "x86_64" },
+            arch: "x86_64",
+            macos_version: None,
+            developer_dir: None,
+            glibc_version: Some((2, 35)),
+        }
+    }
+
+    #[test]
+    fn parses_homebrew_requirement_shapes() {
+        assert_eq!(
+            Requirement::parse(&json!({"name": "macos", "version": "10.15", "contexts": []})),
+            Some(Requirement::Macos {
+                minimum: Some((10, 15))
+            })
+        );
+        assert_eq!(
+            Requirement::parse(&json!({"name": "arch", "version": "arm64"})),
+            Some(Requirement::Arch("aarch64".to_string()))
+        );
+        assert_eq!(
+            Requirement::parse(&json!({"name": "java", "version": "21"})),
+            None
+        );
+    }
+
+    #[test]
+    fn reports_every_unmet_requirement() {
+        let formula = formula_with(json!([
+            {"name": "macos", "version": "15", "contexts": []},
+            {"name": "arch", "version": "x86_64", "contexts": []}
+        ]));
+
+        let err = check_requirements(&formula, &mac(14, None), false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "formula 'foo' cannot be installed here: needs macOS >= 15, x86_64 only"
+        );
+        assert!(check_requirements(&formula, &mac(15, None), false).is_err());
+    }
+
+    #[test]
+    fn minimum_and_maximum_macos_compare_by_release() {
+        let formula = formula_with(json!([
+            {"name": "macos", "version": "13"},
+            {"name": "maximum_macos", "version": "14"}
+        ]));
+        assert!(check_requirements(&formula, &mac(13, None), false).is_ok());
+        assert!(check_requirements(&formula, &mac(14, None), false).is_ok());
+
+        let err = check_requirements(&formula, &mac(15, None), false).unwrap_err();
+        assert!(err.to_string().contains("needs macOS <= 14"));
+    }
+
+    #[test]
+    fn platform_requirements() {
+        let mac_only = formula_with(json!([{"name": "macos", "version": null}]));
+        let err = check_requirements(&mac_only, &linux(), false).unwrap_err();
+        assert!(err.to_string().contains("needs macOS"));
+
+        let linux_only = formula_with(json!([{"name": "linux"}]));
+        assert!(check_requirements(&linux_only, &linux(), false).is_ok());
+        assert!(check_requirements(&linux_only, &mac(15, None), false).is_err());
+    }
+
+    #[test]
+    fn xcode_is_only_required_for_source_builds() {
+        let formula = formula_with(json!([
+            {"name": "xcode", "version": "16.0", "contexts": ["build"]}
+        ]));
+        let clt = mac(15, Some("/Library/Developer/CommandLineTools"));
+        let xcode = mac(15, Some("/Applications/Xcode.app/Contents/Developer"));
+
+        assert!(check_requirements(&formula, &clt, false).is_ok());
+        let err = check_requirements(&formula, &clt, true).unwrap_err();
+        assert!(err.to_string().contains("needs Xcode >= 16.0"));
+        assert!(check_requirements(&formula, &xcode, true).is_ok());
+        assert!(check_requirements(&formula, &linux(), true).is_ok());
+    }
+
+    #[test]
+    fn source_builds_on_macos_need_command_line_tools() {
+        let formula = formula_with(json!([]));
+        let err = check_requirements(&formula, &mac(15, None), true).unwrap_err();
+        assert!(err.to_string().contains("xcode-select --install"));
+        assert!(check_requirements(&formula, &mac(15, None), false).is_ok());
+    }
+
+    #[test]
+    fn parses_glibc_versions() {
+        assert_eq!(parse_glibc_version("glibc 2.35\n"), Some((2, 35)));
+        assert_eq!(parse_glibc_version("2.17"), Some((2, 17)));
+        assert_eq!(parse_glibc_version("2.39_1"), Some((2, 39)));
+        assert_eq!(parse_glibc_version("musl"), None);
+    }
+
+    #[test]
+    fn linux_bottles_need_baseline_glibc() {
+        let bottle = |tag: &str| SelectedBottle {
+            tag: tag.to_string(),
+            url: String::new(),
+            sha256: String::new(),
+            cellar: Default::default(),
+        };
+
+        let err = check_bottle_glibc("jq", &bottle("x86_64_linux"), Some((2, 31))).unwrap_err();
+        assert_eq!(
+            err,
+            Error::GlibcTooOld {
+                name: "jq".to_string(),
+                required: "2.35".to_string(),
+                found: "2.31".to_string(),
+            }
+        );
+        assert!(err.to_string().contains("--build-from-source jq"));
+
+        assert!(check_bottle_glibc("jq", &bottle("arm64_linux"), Some((2, 39))).is_ok());
+        assert!(check_bottle_glibc("jq", &bottle("x86_64_linux"), None).is_ok());
+        assert!(check_bottle_glibc("jq", &bottle("arm64_sonoma"), Some((2, 17))).is_ok());
+        assert!(check_bottle_glibc("jq", &bottle("all"), Some((2, 17))).is_ok());
+    }
+}
@@ -1,9 +1,29 @@
-use crate::{Error, Formula};
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::{DependencyClasses, Error, Formula, Platform};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 
 pub fn resolve_closure(
     roots: &[String],
     formulas: &BTreeMap<String, Formula>,
+) -> Result<Vec<String>, Error> {
+    resolve_closure_for(roots, formulas, Platform::host())
+}
+
+/// Order `roots` and their dependencies on `platform` so every formula comes
+/// after its dependencies. Dependencies missing from `formulas` are skipped.
+pub fn resolve_closure_for(
+    roots: &[String],
+    formulas: &BTreeMap<String, Formula>,
+    platform: Platform,
+) -> Result<Vec<String>, Error> {
+    resolve_closure_with(roots, formulas, platform, DependencyClasses::default())
+}
+
+/// Like [`resolve_closure_for`], following the dependency `classes` too.
+pub fn resolve_closure_with(
+    roots: &[String],
+    formulas: &BTreeMap<String, Formula>,
+    platform: Platform,
+    classes: DependencyClasses,
 ) -> Result<Vec<String>, Error> {
     let name_to_idx: HashMap<&str, usize> = formulas
         .keys()
@@ -13,15 +33,16 @@ pub fn resolve_closure(
     let idx_to_name: Vec<&str> = formulas.keys().map(|k| k.as_str()).collect();
     let n = idx_to_name.len();
 
-    let closure = compute_closure(roots, formulas, &name_to_idx)?;
+    let closure = compute_closure(roots, formulas, &name_to_idx, platform, classes)?;
 
     let mut indegree = vec![0u32; n];
     let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); n];
 
     for &idx in &closure {
         let formula = &formulas[idx_to_name[idx]];
         let mut dep_indices: Vec<usize> = formula
-            .runtime_dependencies()
+            .dependencies_for(platform, classes)
             .iter()
             .filter_map(|dep| {
                 let &di = name_to_idx.get(dep.as_str())?;
@@ -29,10 +50,12 @@ pub fn resolve_closure(
             })
             .collect();
         dep_indices.sort_unstable();
-        for di in dep_indices {
+        dep_indices.dedup();
+        for &di in &dep_indices {
             indegree[idx] += 1;
             adjacency[di].push(idx);
         }
+        dependencies[idx] = dep_indices;
     }
 
     let mut ready: BTreeSet<usize> = closure
@@ -54,10 +77,9 @@ pub fn resolve_closure(
     }
 
     if ordered.len() != closure.len() {
-        let cycle: Vec<String> = closure
-            .iter()
-            .filter(|&&i| indegree[i] > 0)
-            .map(|&i| idx_to_name[i].to_string())
+        let cycle = find_cycle(&closure, &indegree, &dependencies)
+            .into_iter()
+            .map(|i| idx_to_name[i].to_string())
             .collect();
         return Err(Error::DependencyCycle { cycle });
     }
@@ -68,10 +90,217 @@ pub fn resolve_closure(
         .collect())
 }
 
+/// Which formula pulled in which within a resolved closure, for explaining
+/// why a package is part of an install.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    roots: Vec<String>,
+    dependencies: BTreeMap<String, Vec<String>>,
+}
+
+impl DependencyGraph {
+    /// A graph of `roots` over known edges, such as the dependencies
+    /// recorded for installed kegs.
+    pub fn from_edges(roots: Vec<String>, dependencies: BTreeMap<String, Vec<String>>) -> Self {
+        Self {
+            roots,
+            dependencies,
+        }
+    }
+
+    /// The requested formulas, in request order.
+    pub fn roots(&self) -> &[String] {
+        &self.roots
+    }
+
+    /// Direct dependencies of `name` within the closure.
+    pub fn dependencies(&self, name: &str) -> &[String] {
+        self.dependencies.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.dependencies.contains_key(name)
+    }
+
+    /// The shortest path from a requested formula to `name`, both included,
+    /// or `None` when `name` is not in the closure.
+    pub fn chain(&self, name: &str) -> Option<Vec<String>> {
+        let mut parent: HashMap<&str, Option<&str>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for root in &self.roots {
+            if parent.insert(root, None).is_none() {
+                queue.push_back(root.as_str());
+            }
+        }
+
+        while let Some(current) = queue.pop_front() {
+            if current == name {
+                let mut chain = vec![current.to_string()];
+                let mut next = parent[current];
+                while let Some(p) = next {
+                    chain.push(p.to_string());
+                    next = parent[p];
+                }
+                chain.reverse();
+                return Some(chain);
+            }
+            for dep in self.dependencies(current) {
+                if !parent.contains_key(dep.as_str()) {
+                    parent.insert(dep, Some(current));
+                    queue.push_back(dep);
+                }
+            }
+        }
+        None
+    }
+
+    /// Every formula in the graph, sorted by name.
+    fn nodes(&self) -> BTreeSet<&str> {
+        self.dependencies
+            .iter()
+            .flat_map(|(name, deps)| std::iter::once(name).chain(deps))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The graph in Graphviz DOT, with requested formulas drawn bold.
+    pub fn to_dot(&self) -> String {
+        fn quote(name: &str) -> String {
+            format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+
+        let mut out = String::from("digraph dependencies {\n");
+        for node in self.nodes() {
+            let style = if self.roots.iter().any(|root| root == node) {
+                " [style=bold]"
+            } else {
+                ""
+            };
+            out.push_str(&format!("    {}{style};\n", quote(node)));
+        }
+        for (name, deps) in &self.dependencies {
+            for dep in deps {
+                out.push_str(&format!("    {} -> {};\n", quote(name), quote(dep)));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// The graph as a Mermaid flowchart, with requested formulas in
+    /// rounded boxes.
+    pub fn to_mermaid(&self) -> String {
+        let nodes = self.nodes();
+        let ids: HashMap<&str, usize> = nodes.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+
+        let mut out = String::from("graph TD\n");
+        for node in &nodes {
+            let label = node.replace('"', "#quot;");
+            let id = ids[node];
+            if self.roots.iter().any(|root| root == node) {
+                out.push_str(&format!("    n{id}(\"{label}\")\n"));
+            } else {
+                out.push_str(&format!("    n{id}[\"{label}\"]\n"));
+            }
+        }
+        for (name, deps) in &self.dependencies {
+            for dep in deps {
+                out.push_str(&format!(
+                    "    n{} --> n{}\n",
+                    ids[name.as_str()],
+                    ids[dep.as_str()]
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// Record, for `roots` and everything they depend on, the dependencies each
+/// formula contributes on `platform`. Dependencies missing from `formulas`
+/// are skipped, as in [`resolve_closure_for`].
+pub fn dependency_graph_for(
+    roots: &[String],
+    formulas: &BTreeMap<String, Formula>,
+    platform: Platform,
+) -> DependencyGraph {
+    dependency_graph_with(roots, formulas, platform, DependencyClasses::default())
+}
+
+/// Like [`dependency_graph_for`], following the dependency `classes` too.
+pub fn dependency_graph_with(
+    roots: &[String],
+    formulas: &BTreeMap<String, Formula>,
+    platform: Platform,
+    classes: DependencyClasses,
+) -> DependencyGraph {
+    let mut graph = DependencyGraph {
+        roots: roots.to_vec(),
+        dependencies: BTreeMap::new(),
+    };
+    let mut stack: Vec<&str> = roots.iter().map(String::as_str).collect();
+
+    while let Some(name) = stack.pop() {
+        let Some(formula) = formulas.get(name) else {
+            continue;
+        };
+        if graph.dependencies.contains_key(name) {
+            continue;
+        }
+        let deps: Vec<String> = formula
+            .dependencies_for(platform, classes)
+            .into_iter()
+            .filter(|dep| formulas.contains_key(dep))
+            .collect();
+        for dep in &deps {
+            if let Some((key, _)) = formulas.get_key_value(dep) {
+                stack.push(key);
+            }
+        }
+        graph.dependencies.insert(name.to_string(), deps);
+    }
+
+    graph
+}
+
+/// One cycle among the formulas the topological sort could not order, as
+/// `a, b, c, a` where each depends on the next.
+///
+/// Every such formula still waits on a dependency that is itself unordered,
+/// so following those dependencies from any of them must revisit one.
+fn find_cycle(
+    closure: &BTreeSet<usize>,
+    indegree: &[u32],
+    dependencies: &[Vec<usize>],
+) -> Vec<usize> {
+    let stuck = |i: usize| indegree[i] > 0;
+    let Some(mut current) = closure.iter().copied().find(|&i| stuck(i)) else {
+        return Vec::new();
+    };
+    let mut path = Vec::new();
+    let mut position = HashMap::new();
+    loop {
+        if let Some(&start) = position.get(&current) {
+            let mut cycle = path.split_off(start);
+            cycle.push(current);
+            return cycle;
+        }
+        position.insert(current, path.len());
+        path.push(current);
+        current = dependencies[current]
+            .iter()
+            .copied()
+            .find(|&dep| stuck(dep))
+            .expect("an unordered formula waits on an unordered dependency");
+    }
+}
+
 fn compute_closure(
     roots: &[String],
     formulas: &BTreeMap<String, Formula>,
     name_to_idx: &HashMap<&str, usize>,
+    platform: Platform,
+    classes: DependencyClasses,
 ) -> Result<BTreeSet<usize>, Error> {
     let mut closure = BTreeSet::new();
     let mut stack: Vec<usize> = Vec::with_capacity(roots.len());
@@ -91,7 +320,7 @@ fn compute_closure(
         }
 
         let formula = &formulas[idx_to_name[idx]];
-        for dep in formula.runtime_dependencies() {
+        for dep in formula.dependencies_for(platform, classes) {
             if let Some(&di) = name_to_idx.get(dep.as_str())
                 && !closure.contains(&di)
             {
@@ -116,6 +345,7 @@ mod tests {
             BottleFile {
                 url: format!("https://example.com/{name}.tar.gz"),
                 sha256: "deadbeef".repeat(8),
+                cellar: None,
             },
         );
 
@@ -127,17 +357,29 @@ mod tests {
             dependencies: deps.iter().map(|dep| dep.to_string()).collect(),
             bottle: Bottle {
                 stable: BottleStable { files, rebuild: 0 },
+                variants: BTreeMap::new(),
             },
             revision: 0,
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            test_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
             uses_from_macos: Vec::new(),
             requirements: Vec::new(),
             variations: None,
+            conflicts_with: Vec::new(),
+            conflicts_with_reasons: Vec::new(),
+            service: None,
+            desc: None,
+            homepage: None,
+            license: None,
+            binary: None,
+            resources: Vec::new(),
         }
     }
 
@@ -165,6 +407,74 @@ mod tests {
         assert_eq!(order, vec!["shared", "a", "b"]);
     }
 
+    #[test]
+    fn includes_dependency_classes_on_request() {
+        let mut app = formula("app", &["lib"]);
+        app.build_dependencies = vec!["cmake".to_string()];
+        app.test_dependencies = vec!["bats".to_string()];
+        app.recommended_dependencies = vec!["readline".to_string()];
+        app.optional_dependencies = vec!["x11".to_string()];
+        let mut formulas = BTreeMap::new();
+        formulas.insert("app".to_string(), app);
+        for name in ["lib", "cmake", "bats", "readline", "x11"] {
+            formulas.insert(name.to_string(), formula(name, &[]));
+        }
+        let roots = ["app".to_string()];
+        let platform = Platform::host();
+
+        let order = resolve_closure_for(&roots, &formulas, platform).unwrap();
+        assert_eq!(order, ["lib", "readline", "app"]);
+
+        let classes = DependencyClasses {
+            build: true,
+            optional: true,
+            ..DependencyClasses::default()
+        };
+        let order = resolve_closure_with(&roots, &formulas, platform, classes).unwrap();
+        assert_eq!(order, ["cmake", "lib", "readline", "x11", "app"]);
+        let graph = dependency_graph_with(&roots, &formulas, platform, classes);
+        assert_eq!(graph.chain("cmake").unwrap(), ["app", "cmake"]);
+        assert!(!graph.contains("bats"));
+    }
+
+    #[test]
+    fn explains_shortest_chain_to_each_dependency() {
+        let mut formulas = BTreeMap::new();
+        formulas.insert("app".to_string(), formula("app", &["lib", "tool"]));
+        formulas.insert("tool".to_string(), formula("tool", &["deep"]));
+        formulas.insert("lib".to_string(), formula("lib", &["deep", "missing"]));
+        formulas.insert("deep".to_string(), formula("deep", &[]));
+        formulas.insert("other".to_string(), formula("other", &["tool"]));
+
+        let graph = dependency_graph_for(&["app".to_string()], &formulas, Platform::host());
+
+        assert_eq!(graph.dependencies("app"), ["lib", "tool"]);
+        assert_eq!(graph.dependencies("lib"), ["deep"]);
+        assert_eq!(graph.chain("deep").unwrap(), ["app", "lib", "deep"]);
+        assert_eq!(graph.chain("app").unwrap(), ["app"]);
+        assert!(!graph.contains("other"));
+        assert_eq!(graph.chain("other"), None);
+    }
+
+    #[test]
+    fn graph_exports_as_dot_and_mermaid() {
+        let mut formulas = BTreeMap::new();
+        formulas.insert("app".to_string(), formula("app", &["lib", "tool"]));
+        formulas.insert("tool".to_string(), formula("tool", &["lib"]));
+        formulas.insert("lib".to_string(), formula("lib", &[]));
+
+        let graph = dependency_graph_for(&["app".to_string()], &formulas, Platform::host());
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph dependencies {\n    \"app\" [style=bold];\n    \"lib\";\n    \"tool\";\n    \"app\" -> \"lib\";\n    \"app\" -> \"tool\";\n    \"tool\" -> \"lib\";\n}\n"
+        );
+        assert_eq!(
+            graph.to_mermaid(),
+            "graph TD\n    n0(\"app\")\n    n1[\"lib\"]\n    n2[\"tool\"]\n    n0 --> n1\n    n0 --> n2\n    n2 --> n1\n"
+        );
+    }
+
     #[test]
     fn detects_cycles() {
         let mut formulas = BTreeMap::new();
@@ -172,8 +482,95 @@ mod tests {
         formulas.insert("beta".to_string(), formula("beta", &["gamma"]));
         formulas.insert("gamma".to_string(), formula("gamma", &["alpha"]));
 
-        let err = resolve_closure(&["alpha".to_string()], &formulas).unwrap_err();
-        assert!(matches!(err, Error::DependencyCycle { .. }));
+        // A formula outside the cycle that depends on it is not part of it.
+        formulas.insert("app".to_string(), formula("app", &["alpha"]));
+
+        let err = resolve_closure(&["app".to_string()], &formulas).unwrap_err();
+        let Error::DependencyCycle { cycle } = &err else {
+            panic!("expected a dependency cycle, got {err:?}");
+        };
+        assert_eq!(cycle, &["alpha", "beta", "gamma", "alpha"]);
+        assert!(
+            err.to_string()
+                .starts_with("dependency cycle detected: alpha -> beta -> gamma -> alpha")
+        );
+
+        formulas.insert("solo".to_string(), formula("solo", &["solo"]));
+        let err = resolve_closure(&["solo".to_string()], &formulas).unwrap_err();
+        assert!(matches!(err, Error::DependencyCycle { cycle } if cycle == ["solo", "solo"]));
+    }
+
+    mod random_graphs {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Up to 12 formulas `f0`..`f11`, each with up to 4 dependencies
+        /// drawn from the same names, plus up to 3 roots.
+        fn graph() -> impl Strategy<Value = (BTreeMap<String, Formula>, Vec<String>)> {
+            (1usize..12).prop_flat_map(|n| {
+                (
+                    prop::collection::vec(prop::collection::vec(0..n, 0..4), n),
+                    prop::collection::vec(0..n, 1..4),
+                )
+                    .prop_map(move |(edges, roots)| {
+                        let names: Vec<String> = (0..n).map(|i| format!("f{i}")).collect();
+                        let formulas = edges
+                            .iter()
+                            .enumerate()
+                            .map(|(i, deps)| {
+                                let deps: Vec<&str> =
+                                    deps.iter().map(|&d| names[d].as_str()).collect();
+                                (names[i].clone(), formula(&names[i], &deps))
+                            })
+                            .collect();
+                        let roots = roots.into_iter().map(|r| names[r].clone()).collect();
+                        (formulas, roots)
+                    })
+            })
+        }
+
+        fn deps_of<'a>(formulas: &'a BTreeMap<String, Formula>, name: &str) -> &'a [String] {
+            &formulas[name].dependencies
+        }
+
+        proptest! {
+            #[test]
+            fn resolution_orders_or_names_a_real_cycle((formulas, roots) in graph()) {
+                match resolve_closure(&roots, &formulas) {
+                    Ok(order) => {
+                        for (i, name) in order.iter().enumerate() {
+                            for dep in deps_of(&formulas, name) {
+                                let pos = order.iter().position(|n| n == dep);
+                                prop_assert!(pos.is_some_and(|p| p < i), "{dep} must precede {name}");
+                            }
+                        }
+                        for root in &roots {
+                            prop_assert!(order.contains(root));
+                        }
+                    }
+                    Err(Error::DependencyCycle { cycle }) => {
+                        prop_assert!(cycle.len() >= 2);
+                        prop_assert_eq!(cycle.first(), cycle.last());
+                        let inner: BTreeSet<&String> = cycle[1..].iter().collect();
+                        prop_assert_eq!(inner.len(), cycle.len() - 1, "cycle repeats a formula");
+                        for pair in cycle.windows(2) {
+                            prop_assert!(deps_of(&formulas, &pair[0]).contains(&pair[1]));
+                        }
+                    }
+                    Err(e) => prop_assert!(false, "unexpected error {e}"),
+                }
+            }
+
+            #[test]
+            fn graph_walks_terminate((formulas, roots) in graph()) {
+                let graph = dependency_graph_for(&roots, &formulas, Platform::host());
+                for name in formulas.keys() {
+                    if let Some(chain) = graph.chain(name) {
+                        prop_assert_eq!(chain.last(), Some(name));
+                    }
+                }
+            }
+        }
     }
 
     #[test]
@@ -206,4 +603,52 @@ mod tests {
         let order = resolve_closure(&["python@3.14".to_string()], &formulas).unwrap();
         assert_eq!(order, vec!["expat", "openssl@3", "python@3.14"]);
     }
+
+    fn zlib_consumer() -> BTreeMap<String, Formula> {
+        use crate::formula::UsesFromMacos;
+
+        let mut formulas = BTreeMap::new();
+        let mut curl = formula("curl", &["openssl@3"]);
+        curl.uses_from_macos = vec![
+            UsesFromMacos::Plain("zlib".to_string()),
+            UsesFromMacos::WithContext {
+                name: "pkgconf".to_string(),
+                context: "build".to_string(),
+            },
+        ];
+        formulas.insert("curl".to_string(), curl);
+        formulas.insert("openssl@3".to_string(), formula("openssl@3", &[]));
+        formulas.insert("zlib".to_string(), formula("zlib", &[]));
+        formulas.insert("pkgconf".to_string(), formula("pkgconf", &[]));
+        formulas
+    }
+
+    #[test]
+    fn uses_from_macos_is_a_runtime_dependency_on_linux() {
+        let order = resolve_closure_for(
+            &["curl".to_string()],
+            &zlib_consumer(),
+            Platform::Linux { arch: "x86_64" },
+        )
+        .unwrap();
+        assert_eq!(order, vec!["openssl@3", "zlib", "curl"]);
+    }
+
+    #[test]
+    fn uses_from_macos_is_skipped_on_macos() {
+        let order =
+            resolve_closure_for(&["curl".to_string()], &zlib_consumer(), Platform::Macos).unwrap();
+        assert_eq!(order, vec!["openssl@3", "curl"]);
+    }
+
+    #[test]
+    fn build_only_uses_from_macos_is_a_build_dependency_on_linux() {
+        let formulas = zlib_consumer();
+        let curl = &formulas["curl"];
+        assert_eq!(
+            curl.build_dependencies_for(Platform::Linux { arch: "aarch64" }),
+            vec!["zlib", "pkgconf"]
+        );
+        assert!(curl.build_dependencies_for(Platform::Macos).is_empty());
+    }
 }
@@ -1,6 +1,10 @@
 use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use std::collections::BTreeMap;
 
+use super::{BinaryArtifact, Platform};
+use crate::Error;
+
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum KegOnly {
     #[default]
@@ -56,6 +60,41 @@ pub struct FormulaUrls {
     pub head: Option<serde_json::Value>,
 }
 
+/// A `resource` of a formula: an extra download its `install` stages, e.g.
+/// the Python packages a tool vendors into its `libexec` virtualenv.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct FormulaResource {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// Prefix of the version given to kegs built from a `head` checkout.
+pub const HEAD_VERSION_PREFIX: &str = "HEAD-";
+
+/// The git repository a formula's `head` spec builds from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadSource {
+    pub url: String,
+    pub branch: Option<String>,
+}
+
+impl HeadSource {
+    /// Keg version for a build of commit `revision`, e.g. `HEAD-1a2b3c4`.
+    pub fn version_for(revision: &str) -> String {
+        let short: String = revision.chars().take(7).collect();
+        format!("{HEAD_VERSION_PREFIX}{short}")
+    }
+
+    /// The abbreviated commit a `HEAD-` keg version was built from.
+    pub fn revision_of(version: &str) -> Option<&str> {
+        version
+            .strip_prefix(HEAD_VERSION_PREFIX)
+            .filter(|rev| !rev.is_empty())
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct RubySourceChecksum {
     pub sha256: String,
@@ -108,35 +147,128 @@ impl UsesFromMacos {
     }
 }
 
+/// The kinds of dependency a formula declares, as in Homebrew's
+/// `depends_on "x" => :build`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DependencyClass {
+    Required,
+    Recommended,
+    Optional,
+    Build,
+    Test,
+}
+
+/// Which classes of dependency an install pulls in besides the required
+/// and recommended ones, which are always installed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DependencyClasses {
+    pub optional: bool,
+    pub build: bool,
+    pub test: bool,
+}
+
+impl DependencyClasses {
+    pub fn includes(&self, class: DependencyClass) -> bool {
+        match class {
+            DependencyClass::Required | DependencyClass::Recommended => true,
+            DependencyClass::Optional => self.optional,
+            DependencyClass::Build => self.build,
+            DependencyClass::Test => self.test,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct Formula {
     pub name: String,
     pub versions: Versions,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub dependencies: Vec<String>,
+    /// Empty for formulas without bottles.
+    #[serde(default, deserialize_with = "null_as_default")]
     pub bottle: Bottle,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "null_as_default")]
     pub revision: u32,
     #[serde(default)]
     pub keg_only: KegOnly,
     #[serde(default)]
     pub keg_only_reason: Option<KegOnlyReason>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "null_as_default")]
     pub build_dependencies: Vec<String>,
+    /// Only needed to run the formula's tests.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub test_dependencies: Vec<String>,
+    /// Runtime dependencies a formula can be built without, but that are
+    /// installed by default.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub recommended_dependencies: Vec<String>,
+    /// Dependencies only installed when asked for.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub optional_dependencies: Vec<String>,
     #[serde(default)]
     pub urls: Option<FormulaUrls>,
     #[serde(default)]
     pub ruby_source_path: Option<String>,
     #[serde(default)]
     pub ruby_source_checksum: Option<RubySourceChecksum>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "null_as_default")]
     pub uses_from_macos: Vec<UsesFromMacos>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "null_as_default")]
     pub requirements: Vec<serde_json::Value>,
     #[serde(default)]
     pub variations: Option<serde_json::Value>,
+    /// Formulas that install the same files and cannot be linked alongside
+    /// this one.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub conflicts_with: Vec<String>,
+    /// Explanations matching `conflicts_with` by position.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub conflicts_with_reasons: Vec<String>,
+    /// The raw `service` block; see [`crate::ServiceDefinition`].
+    #[serde(default)]
+    pub service: Option<serde_json::Value>,
+    /// One-line summary of what the formula is.
+    #[serde(default)]
+    pub desc: Option<String>,
+    #[serde(default)]
+    pub homepage: Option<String>,
+    /// SPDX license expression, e.g. `MIT` or `Apache-2.0 OR MIT`.
+    #[serde(default)]
+    pub license: Option<String>,
+    /// Release binaries installed when there is no bottle.
+    #[serde(default)]
+    pub binary: Option<BinaryArtifact>,
+    /// Downloads staged by a source build. Only tap formulas list them; the
+    /// API leaves them to the formula's Ruby source.
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub resources: Vec<FormulaResource>,
 }
 
 impl Formula {
+    /// Parse formula JSON from the API. A response that does not match the
+    /// schema is a `FormulaSchema` error naming the formula, not a network
+    /// failure, so it is neither retried nor blamed on the connection.
+    pub fn from_json(raw: &str) -> Result<Formula, Error> {
+        let value: serde_json::Value =
+            serde_json::from_str(raw).map_err(|e| Error::FormulaSchema {
+                name: None,
+                message: e.to_string(),
+            })?;
+        Formula::from_json_value(value)
+    }
+
+    /// Like [`Formula::from_json`], for JSON that is already parsed.
+    pub fn from_json_value(value: serde_json::Value) -> Result<Formula, Error> {
+        let name = value
+            .get("name")
+            .and_then(|n| n.as_str())
+            .map(str::to_string);
+        serde_json::from_value(value).map_err(|e| Error::FormulaSchema {
+            name,
+            message: e.to_string(),
+        })
+    }
+
     pub fn effective_version(&self) -> String {
         if self.revision > 0 {
             format!("{}_{}", self.versions.stable, self.revision)
@@ -169,124 +301,219 @@ impl Formula {
         self.source_url().is_some()
     }
 
+    /// The `head` spec, given either as a bare URL or as an object with
+    /// `url` and optional `branch`.
+    pub fn head_source(&self) -> Option<HeadSource> {
+        match self.urls.as_ref()?.head.as_ref()? {
+            serde_json::Value::String(url) => Some(HeadSource {
+                url: url.clone(),
+                branch: None,
+            }),
+            serde_json::Value::Object(map) => Some(HeadSource {
+                url: map.get("url")?.as_str()?.to_string(),
+                branch: map
+                    .get("branch")
+                    .and_then(|b| b.as_str())
+                    .map(str::to_string),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Why this formula conflicts with `other`, if it declares a reason.
+    pub fn conflict_reason(&self, other: &str) -> Option<&str> {
+        let index = self.conflicts_with.iter().position(|c| c == other)?;
+        self.conflicts_with_reasons
+            .get(index)
+            .map(String::as_str)
+            .filter(|reason| !reason.is_empty())
+    }
+
     pub fn all_build_dependencies(&self) -> Vec<String> {
-        let deps = self.build_dependencies.clone();
-        #[cfg(not(target_os = "macos"))]
-        let deps = {
-            let mut deps = deps;
-            for u in self.active_uses_from_macos() {
+        self.build_dependencies_for(Platform::host())
+    }
+
+    /// Build dependencies on `platform`. Off macOS every `uses_from_macos`
+    /// entry is needed to build, whatever its context.
+    pub fn build_dependencies_for(&self, platform: Platform) -> Vec<String> {
+        let mut deps = self
+            .variation_field(platform, "build_dependencies")
+            .unwrap_or_else(|| self.build_dependencies.clone());
+        if platform.installs_uses_from_macos() {
+            for u in self.active_uses_from_macos(platform) {
                 push_unique_dep(&mut deps, u.name());
             }
-            deps
-        };
+        }
         deps
     }
 
     pub fn runtime_dependencies(&self) -> Vec<String> {
-        #[cfg(not(target_os = "macos"))]
-        {
-            let mut deps = self.platform_dependencies();
+        self.runtime_dependencies_for(Platform::host())
+    }
+
+    /// Runtime dependencies on `platform`: the required and recommended
+    /// ones, and runtime `uses_from_macos` entries where the OS does not
+    /// provide them.
+    pub fn runtime_dependencies_for(&self, platform: Platform) -> Vec<String> {
+        let mut deps = self
+            .variation_field(platform, "dependencies")
+            .unwrap_or_else(|| self.dependencies.clone());
+        for dep in self.dependencies_in(DependencyClass::Recommended, platform) {
+            push_unique_dep(&mut deps, &dep);
+        }
+        if platform.installs_uses_from_macos() {
             for dep in self
-                .active_uses_from_macos()
+                .active_uses_from_macos(platform)
                 .iter()
                 .filter(|dep| dep.is_runtime_dependency())
             {
                 push_unique_dep(&mut deps, dep.name());
             }
-            deps
         }
+        deps
+    }
 
-        #[cfg(target_os = "macos")]
-        {
-            self.platform_dependencies()
-        }
+    /// The dependencies of one `class` on `platform`. Required ones leave
+    /// out `uses_from_macos` entries; see [`Formula::runtime_dependencies_for`].
+    pub fn dependencies_in(&self, class: DependencyClass, platform: Platform) -> Vec<String> {
+        let (field, declared) = match class {
+            DependencyClass::Required => ("dependencies", &self.dependencies),
+            DependencyClass::Recommended => {
+                ("recommended_dependencies", &self.recommended_dependencies)
+            }
+            DependencyClass::Optional => ("optional_dependencies", &self.optional_dependencies),
+            DependencyClass::Build => ("build_dependencies", &self.build_dependencies),
+            DependencyClass::Test => ("test_dependencies", &self.test_dependencies),
+        };
+        self.variation_field(platform, field)
+            .unwrap_or_else(|| declared.clone())
     }
 
-    fn platform_dependencies(&self) -> Vec<String> {
-        #[cfg(target_os = "linux")]
-        if let Some(deps) = self.variation_dependencies(preferred_linux_variation_keys()) {
-            return deps;
+    /// The runtime dependencies on `platform`, plus those of each class
+    /// `classes` includes.
+    pub fn dependencies_for(&self, platform: Platform, classes: DependencyClasses) -> Vec<String> {
+        let mut deps = self.runtime_dependencies_for(platform);
+        if classes.build {
+            for dep in self.build_dependencies_for(platform) {
+                push_unique_dep(&mut deps, &dep);
+            }
         }
-
-        self.dependencies.clone()
+        for class in [DependencyClass::Optional, DependencyClass::Test] {
+            if classes.includes(class) {
+                for dep in self.dependencies_in(class, platform) {
+                    push_unique_dep(&mut deps, &dep);
+                }
+            }
+        }
+        deps
     }
 
-    #[cfg(target_os = "linux")]
-    fn variation_dependencies(&self, keys: &[&str]) -> Option<Vec<String>> {
-        let variations = self.variations.as_ref()?.as_object()?;
-        for key in keys {
-            if let Some(deps) = variations
-                .get(*key)
-                .and_then(|variation| variation.get("dependencies"))
-                .and_then(|deps| deps.as_array())
-            {
-                return Some(
-                    deps.iter()
-                        .filter_map(|dep| dep.as_str().map(ToString::to_string))
-                        .collect(),
-                );
-            }
+    /// Fold the `variations` entry for `platform` into the top-level fields.
+    ///
+    /// Homebrew publishes the macOS shape of a formula and describes other
+    /// platforms as overrides, e.g. extra runtime libraries on Linux. The
+    /// result no longer carries `variations`, so later dependency queries
+    /// see the `platform` view regardless of the host.
+    pub fn with_variation_applied(mut self, platform: Platform) -> Self {
+        if let Some(deps) = self.variation_field(platform, "dependencies") {
+            self.dependencies = deps;
         }
-        None
+        if let Some(deps) = self.variation_field(platform, "build_dependencies") {
+            self.build_dependencies = deps;
+        }
+        if let Some(deps) = self.variation_field(platform, "test_dependencies") {
+            self.test_dependencies = deps;
+        }
+        if let Some(deps) = self.variation_field(platform, "recommended_dependencies") {
+            self.recommended_dependencies = deps;
+        }
+        if let Some(deps) = self.variation_field(platform, "optional_dependencies") {
+            self.optional_dependencies = deps;
+        }
+        if let Some(uses) = self.variation_field(platform, "uses_from_macos") {
+            self.uses_from_macos = uses;
+        }
+        if let Some(requirements) = self.variation_field(platform, "requirements") {
+            self.requirements = requirements;
+        }
+        self.variations = None;
+        self
     }
 
-    #[cfg(not(target_os = "macos"))]
-    fn active_uses_from_macos(&self) -> Vec<UsesFromMacos> {
-        #[cfg(target_os = "linux")]
-        if let Some(deps) = self.variation_uses_from_macos(preferred_linux_variation_keys()) {
-            return deps;
+    /// Swap the bottles of the named `variant` in for the default ones, so
+    /// bottle selection only sees the variant's.
+    pub fn with_bottle_variant(mut self, variant: &str) -> Result<Self, Error> {
+        if let Some(stable) = self.bottle.variants.remove(variant) {
+            self.bottle.stable = stable;
+            return Ok(self);
         }
+        let message = if self.bottle.variants.is_empty() {
+            format!("'{}' has no bottle variants", self.name)
+        } else {
+            let available: Vec<&str> = self.bottle.variants.keys().map(String::as_str).collect();
+            format!(
+                "'{}' has no bottle variant '{variant}' (available: {})",
+                self.name,
+                available.join(", ")
+            )
+        };
+        Err(Error::InvalidArgument { message })
+    }
 
-        self.uses_from_macos.clone()
+    fn active_uses_from_macos(&self, platform: Platform) -> Vec<UsesFromMacos> {
+        self.variation_field(platform, "uses_from_macos")
+            .unwrap_or_else(|| self.uses_from_macos.clone())
     }
 
-    #[cfg(not(target_os = "macos"))]
-    fn variation_uses_from_macos(&self, keys: &[&str]) -> Option<Vec<UsesFromMacos>> {
+    /// `field` from the first variation for `platform` that overrides it.
+    fn variation_field<T: DeserializeOwned>(&self, platform: Platform, field: &str) -> Option<T> {
         let variations = self.variations.as_ref()?.as_object()?;
-        for key in keys {
-            if let Some(value) = variations
-                .get(*key)
-                .and_then(|variation| variation.get("uses_from_macos"))
-            {
-                return serde_json::from_value(value.clone()).ok();
-            }
-        }
-        None
+        platform.variation_keys().iter().find_map(|key| {
+            let value = variations.get(*key)?.get(field)?;
+            serde_json::from_value(value.clone()).ok()
+        })
     }
 }
 
-#[cfg(not(target_os = "macos"))]
+/// Read a field the API may send as `null` as if it were missing. Fields
+/// are added to the formula JSON as optional first, and unknown ones are
+/// ignored, so newer API responses keep parsing.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 fn push_unique_dep(deps: &mut Vec<String>, name: &str) {
     if !deps.iter().any(|existing| existing == name) {
         deps.push(name.to_string());
     }
 }
 
-#[cfg(target_os = "linux")]
-fn preferred_linux_variation_keys() -> &'static [&'static str] {
-    match std::env::consts::ARCH {
-        "aarch64" => &["arm64_linux", "aarch64_linux"],
-        "x86_64" => &["x86_64_linux"],
-        _ => &[],
-    }
-}
-
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct Versions {
     pub stable: String,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
 pub struct Bottle {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub stable: BottleStable,
+    /// Alternative bottles a tap publishes for the same version, by name
+    /// (e.g. `no-gui`). See [`Formula::with_bottle_variant`].
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub variants: BTreeMap<String, BottleStable>,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
 pub struct BottleStable {
+    #[serde(default, deserialize_with = "null_as_default")]
     pub files: BTreeMap<String, BottleFile>,
     /// Rebuild number for the bottle. When > 0, the bottle's internal paths
     /// use `{version}_{rebuild}` instead of just `{version}`.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "null_as_default")]
     pub rebuild: u32,
 }
 
@@ -294,6 +521,10 @@ pub struct BottleStable {
 pub struct BottleFile {
     pub url: String,
     pub sha256: String,
+    /// Where the bottle may be poured: `:any`, `:any_skip_relocation` or
+    /// the one cellar path it was built for.
+    #[serde(default)]
+    pub cellar: Option<String>,
 }
 
 #[cfg(test)]
@@ -315,6 +546,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn tolerates_null_and_missing_optional_fields() {
+        let formula = Formula::from_json(
+            r#"{
+                "name": "sparse",
+                "versions": { "stable": "1.0", "head": null, "bottle": false },
+                "dependencies": null,
+                "build_dependencies": null,
+                "uses_from_macos": null,
+                "requirements": null,
+                "conflicts_with": null,
+                "revision": null,
+                "bottle": {},
+                "some_field_added_later": { "nested": [1, 2, 3] }
+            }"#,
+        )
+        .unwrap();
+        assert!(formula.dependencies.is_empty());
+        assert!(formula.bottle.stable.files.is_empty());
+        assert_eq!(formula.effective_version(), "1.0");
+
+        let formula = Formula::from_json(
+            r#"{ "name": "nobottle", "versions": { "stable": "2.0" }, "bottle": { "stable": null } }"#,
+        )
+        .unwrap();
+        assert_eq!(formula.bottle, Bottle::default());
+    }
+
+    #[test]
+    fn schema_errors_name_the_formula() {
+        let err = Formula::from_json(r#"{ "name": "broken", "versions": { "stable": null } }"#)
+            .unwrap_err();
+        let Error::FormulaSchema { name, message } = &err else {
+            panic!("expected a schema error, got {err:?}");
+        };
+        assert_eq!(name.as_deref(), Some("broken"));
+        assert!(message.contains("invalid type: null"), "{message}");
+        assert!(!err.is_retryable());
+
+        assert!(matches!(
+            Formula::from_json("<html>"),
+            Err(Error::FormulaSchema { name: None, .. })
+        ));
+    }
+
+    #[test]
+    fn bottle_variants_replace_the_default_bottles() {
+        let formula = Formula::from_json(
+            r#"{
+                "name": "viewer",
+                "versions": { "stable": "2.0" },
+                "bottle": {
+                    "stable": { "files": { "x86_64_linux": { "url": "https://example.com/gui", "sha256": "aa" } } },
+                    "variants": {
+                        "no-gui": { "rebuild": 1, "files": { "x86_64_linux": { "url": "https://example.com/cli", "sha256": "bb" } } }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let variant = formula.clone().with_bottle_variant("no-gui").unwrap();
+        assert_eq!(variant.bottle.stable.rebuild, 1);
+        assert_eq!(variant.bottle.stable.files["x86_64_linux"].sha256, "bb");
+
+        let err = formula.with_bottle_variant("tiny").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("no bottle variant 'tiny' (available: no-gui)"),
+            "{err}"
+        );
+    }
+
     #[test]
     fn effective_version_without_revision() {
         let fixture = include_str!("../../fixtures/formula_foo.json");
@@ -589,4 +893,88 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn applying_linux_variation_overrides_dependencies() {
+        let mut formula: Formula =
+            serde_json::from_str(include_str!("../../fixtures/formula_foo.json")).unwrap();
+        formula.dependencies = vec!["openssl@3".to_string()];
+        formula.build_dependencies = vec!["pkgconf".to_string()];
+        formula.variations = Some(serde_json::json!({
+            "x86_64_linux": {
+                "dependencies": ["openssl@3", "gcc"],
+                "build_dependencies": ["pkgconf", "patchelf"]
+            }
+        }));
+        let linux = Platform::Linux { arch: "x86_64" };
+
+        let applied = formula.clone().with_variation_applied(linux);
+        assert_eq!(applied.dependencies, vec!["openssl@3", "gcc"]);
+        assert_eq!(applied.build_dependencies, vec!["pkgconf", "patchelf"]);
+        assert!(applied.variations.is_none());
+        assert_eq!(
+            applied.runtime_dependencies_for(Platform::Macos),
+            vec!["openssl@3", "gcc"]
+        );
+
+        let arm = formula
+            .clone()
+            .with_variation_applied(Platform::Linux { arch: "aarch64" });
+        assert_eq!(arm.dependencies, vec!["openssl@3"]);
+        let mac = formula.with_variation_applied(Platform::Macos);
+        assert_eq!(mac.dependencies, vec!["openssl@3"]);
+    }
+
+    #[test]
+    fn conflicts_with_pairs_reasons_by_position() {
+        let json = r#"{
+            "name": "node",
+            "versions": { "stable": "24.0.0" },
+            "dependencies": [],
+            "conflicts_with": ["node@20", "nodebrew"],
+            "conflicts_with_reasons": ["both install `node` binaries", ""],
+            "bottle": { "stable": { "files": {} } }
+        }"#;
+        let formula: Formula = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            formula.conflict_reason("node@20"),
+            Some("both install `node` binaries")
+        );
+        assert_eq!(formula.conflict_reason("nodebrew"), None);
+        assert_eq!(formula.conflict_reason("yarn"), None);
+    }
+
+    #[test]
+    fn head_source_accepts_object_or_url() {
+        let json = r#"{
+            "name": "tool",
+            "versions": { "stable": "1.0" },
+            "dependencies": [],
+            "urls": { "head": { "url": "https://github.com/org/tool.git", "branch": "main" } },
+            "bottle": { "stable": { "files": {} } }
+        }"#;
+        let mut formula: Formula = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            formula.head_source(),
+            Some(HeadSource {
+                url: "https://github.com/org/tool.git".to_string(),
+                branch: Some("main".to_string()),
+            })
+        );
+
+        formula.urls.as_mut().unwrap().head =
+            Some(serde_json::json!("https://github.com/org/tool.git"));
+        assert_eq!(formula.head_source().unwrap().branch, None);
+
+        formula.urls = None;
+        assert_eq!(formula.head_source(), None);
+    }
+
+    #[test]
+    fn head_versions_carry_short_revision() {
+        let version = HeadSource::version_for("1a2b3c4d5e6f");
+        assert_eq!(version, "HEAD-1a2b3c4");
+        assert_eq!(HeadSource::revision_of(&version), Some("1a2b3c4"));
+        assert_eq!(HeadSource::revision_of("1.2.3"), None);
+    }
 }
@@ -2,14 +2,21 @@ pub mod build;
 pub mod context;
 pub mod errors;
 pub mod formula;
+pub mod service;
 
 pub use build::{BuildPlan, BuildSystem, InstallMethod};
-pub use context::{ConcurrencyLimits, Context, LogLevel, LoggerHandle, Paths};
+pub use context::{ApiEndpoints, ConcurrencyLimits, Context, LogLevel, LoggerHandle, Paths};
 pub use errors::{ConflictedLink, Error};
 pub use formula::{
-    Formula, KegOnly, KegOnlyReason, SelectedBottle, compatible_codenames, formula_token,
-    resolve_closure, select_bottle,
+    BinaryTarget, BottleCellar, BottleStrictness, DependencyClass, DependencyClasses,
+    DependencyGraph, Formula, FormulaResource, GlibcVersion, HeadSource, HostFacts, KegOnly,
+    KegOnlyReason, Platform, Requirement, SelectedBinary, SelectedBottle, bottle_tags,
+    check_bottle_glibc, check_requirements, compatible_codenames, dependency_graph_for,
+    dependency_graph_with, formula_token, parse_arch, parse_glibc_version, resolve_closure,
+    resolve_closure_for, resolve_closure_with, select_binary, select_bottle,
+    select_bottle_for_arch, select_bottle_for_tag, select_bottle_with_strictness,
 };
+pub use service::{CronSchedule, HealthCheck, RestartPolicy, RunType, ServiceDefinition};
 
 #[cfg(target_os = "macos")]
 pub use formula::macos_major_version;
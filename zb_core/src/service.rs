@@ -0,0 +1,487 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::{Error, Formula};
+
+/// How a formula's background service is run, from the `service` block of
+/// its formula JSON with `$HOMEBREW_PREFIX` and friends already resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDefinition {
+    pub run: Vec<String>,
+    pub run_type: RunType,
+    /// Whether the service manager restarts the process when it exits.
+    pub restart: RestartPolicy,
+    /// How to tell the service is ready, beyond its process being alive.
+    pub health_check: Option<HealthCheck>,
+    pub working_dir: Option<String>,
+    pub log_path: Option<String>,
+    pub error_log_path: Option<String>,
+    pub environment_variables: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// Only when it exits unsuccessfully.
+    OnFailure,
+    Always,
+}
+
+impl RestartPolicy {
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        match s {
+            "never" => Ok(Self::Never),
+            "on-failure" => Ok(Self::OnFailure),
+            "always" => Ok(Self::Always),
+            other => Err(Error::InvalidArgument {
+                message: format!(
+                    "unknown restart policy '{other}' (expected always, on-failure or never)"
+                ),
+            }),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Never => "never",
+            Self::OnFailure => "on-failure",
+            Self::Always => "always",
+        }
+    }
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A probe that succeeds once the service is ready.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthCheck {
+    /// Something accepts TCP connections on this local port.
+    Tcp(u16),
+    /// This shell command exits successfully.
+    Command(String),
+}
+
+impl fmt::Display for HealthCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthCheck::Tcp(port) => write!(f, "tcp port {port}"),
+            HealthCheck::Command(command) => write!(f, "`{command}`"),
+        }
+    }
+}
+
+/// When the service manager starts the service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunType {
+    /// As soon as it is loaded, and kept running.
+    Immediate,
+    /// Every this many seconds.
+    Interval(u64),
+    /// Whenever the cron schedule matches.
+    Cron(CronSchedule),
+}
+
+impl fmt::Display for RunType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunType::Immediate => write!(f, "at load"),
+            RunType::Interval(secs) => write!(f, "every {secs}s"),
+            RunType::Cron(schedule) => write!(f, "cron \"{schedule}\""),
+        }
+    }
+}
+
+impl ServiceDefinition {
+    /// The service `formula` declares, if any. Paths are resolved against
+    /// `prefix`.
+    pub fn for_formula(formula: &Formula, prefix: &Path) -> Result<Option<Self>, Error> {
+        formula
+            .service
+            .as_ref()
+            .map(|value| Self::from_json(&formula.name, value, prefix))
+            .transpose()
+    }
+
+    fn from_json(name: &str, value: &Value, prefix: &Path) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidArgument {
+            message: format!("invalid service definition for '{name}': {reason}"),
+        };
+        let resolve = |s: &str| resolve_placeholders(s, prefix);
+        let string_field = |key: &str| value.get(key).and_then(Value::as_str).map(resolve);
+
+        let run = match value.get("run") {
+            Some(Value::Object(by_os)) => {
+                let os = if cfg!(target_os = "macos") {
+                    "macos"
+                } else {
+                    "linux"
+                };
+                by_os.get(os).map(command_line).unwrap_or_default()
+            }
+            Some(other) => command_line(other),
+            None => Vec::new(),
+        };
+        if run.is_empty() {
+            return Err(invalid("no run command for this platform"));
+        }
+
+        let run_type = match value.get("run_type").and_then(Value::as_str) {
+            None | Some("immediate") => RunType::Immediate,
+            Some("interval") => RunType::Interval(
+                value
+                    .get("interval")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| invalid("interval service without an interval"))?,
+            ),
+            Some("cron") => {
+                let expression = match value.get("cron") {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(Value::Object(fields)) => cron_from_fields(fields),
+                    _ => return Err(invalid("cron service without a schedule")),
+                };
+                RunType::Cron(CronSchedule::parse(&expression)?)
+            }
+            Some(other) => return Err(invalid(&format!("unknown run_type '{other}'"))),
+        };
+
+        let restart = match value.get("keep_alive") {
+            Some(Value::Bool(true)) => RestartPolicy::Always,
+            Some(Value::Object(map)) => {
+                let flag = |key: &str| map.get(key).and_then(Value::as_bool);
+                if flag("always") == Some(true) {
+                    RestartPolicy::Always
+                } else if flag("successful_exit") == Some(false) || flag("crashed") == Some(true) {
+                    RestartPolicy::OnFailure
+                } else {
+                    RestartPolicy::Never
+                }
+            }
+            _ => RestartPolicy::Never,
+        };
+
+        let environment_variables = value
+            .get("environment_variables")
+            .and_then(Value::as_object)
+            .map(|vars| {
+                vars.iter()
+                    .filter_map(|(k, v)| Some((k.clone(), resolve(v.as_str()?))))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            run: run.iter().map(|arg| resolve(arg)).collect(),
+            run_type,
+            restart,
+            health_check: None,
+            working_dir: string_field("working_dir"),
+            log_path: string_field("log_path"),
+            error_log_path: string_field("error_log_path"),
+            environment_variables,
+        })
+    }
+}
+
+fn command_line(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(args) => args
+            .iter()
+            .filter_map(|a| a.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn resolve_placeholders(s: &str, prefix: &Path) -> String {
+    let prefix = prefix.display().to_string();
+    let mut resolved = s
+        .replace("$HOMEBREW_CELLAR", &format!("{prefix}/Cellar"))
+        .replace("$HOMEBREW_PREFIX", &prefix);
+    if let Some(rest) = resolved.strip_prefix('~')
+        && let Some(home) = std::env::var_os("HOME")
+    {
+        resolved = format!("{}{rest}", home.to_string_lossy());
+    }
+    resolved
+}
+
+/// Homebrew serializes some cron schedules as launchd-style fields.
+fn cron_from_fields(fields: &serde_json::Map<String, Value>) -> String {
+    ["Minute", "Hour", "Day", "Month", "Weekday"]
+        .iter()
+        .map(|key| match fields.get(*key) {
+            Some(Value::Number(n)) => n.to_string(),
+            Some(Value::String(s)) => s.clone(),
+            _ => "*".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A five-field cron schedule (`minute hour day month weekday`).
+///
+/// Each field is `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a
+/// comma-separated list of those. A field of `None` matches anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    pub minute: Option<BTreeSet<u32>>,
+    pub hour: Option<BTreeSet<u32>>,
+    pub day: Option<BTreeSet<u32>>,
+    pub month: Option<BTreeSet<u32>>,
+    /// Days of the week, 0 being Sunday.
+    pub weekday: Option<BTreeSet<u32>>,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, Error> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "invalid cron schedule '{expression}': expected 5 fields (minute hour day month weekday)"
+                ),
+            });
+        };
+
+        let field = |text: &str, min: u32, max: u32| {
+            parse_cron_field(text, min, max).map_err(|reason| Error::InvalidArgument {
+                message: format!("invalid cron schedule '{expression}': {reason}"),
+            })
+        };
+        let weekday = field(weekday, 0, 7)?.map(|days| days.into_iter().map(|d| d % 7).collect());
+
+        Ok(Self {
+            expression: expression.trim().to_string(),
+            minute: field(minute, 0, 59)?,
+            hour: field(hour, 0, 23)?,
+            day: field(day, 1, 31)?,
+            month: field(month, 1, 12)?,
+            weekday,
+        })
+    }
+
+    /// Whether the schedule fires at this minute. As in cron, when both day
+    /// and weekday are restricted either one matching is enough.
+    pub fn matches(&self, minute: u32, hour: u32, day: u32, month: u32, weekday: u32) -> bool {
+        let hit = |field: &Option<BTreeSet<u32>>, value: u32| {
+            field.as_ref().is_none_or(|set| set.contains(&value))
+        };
+        let day_matches = match (&self.day, &self.weekday) {
+            (Some(days), Some(weekdays)) => days.contains(&day) || weekdays.contains(&weekday),
+            _ => hit(&self.day, day) && hit(&self.weekday, weekday),
+        };
+        hit(&self.minute, minute) && hit(&self.hour, hour) && hit(&self.month, month) && day_matches
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn parse_cron_field(text: &str, min: u32, max: u32) -> Result<Option<BTreeSet<u32>>, String> {
+    if text == "*" {
+        return Ok(None);
+    }
+    let number = |s: &str| {
+        s.parse::<u32>()
+            .ok()
+            .filter(|n| (min..=max).contains(n))
+            .ok_or_else(|| format!("'{s}' is not a number between {min} and {max}"))
+    };
+
+    let mut values = BTreeSet::new();
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step in '{part}'"))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (number(a)?, number(b)?),
+                None if step > 1 => (number(range)?, max),
+                None => {
+                    let n = number(range)?;
+                    (n, n)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("range '{range}' is backwards"));
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+    Ok(Some(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn formula_with_service(service: Value) -> Formula {
+        let mut formula: Formula =
+            serde_json::from_str(include_str!("../fixtures/formula_foo.json")).unwrap();
+        formula.service = Some(service);
+        formula
+    }
+
+    #[test]
+    fn resolves_prefix_placeholders() {
+        let formula = formula_with_service(serde_json::json!({
+            "run": ["$HOMEBREW_PREFIX/opt/foo/bin/foo", "--config", "$HOMEBREW_PREFIX/etc/foo.conf"],
+            "keep_alive": { "always": true },
+            "working_dir": "$HOMEBREW_PREFIX/var",
+            "log_path": "$HOMEBREW_PREFIX/var/log/foo.log",
+            "environment_variables": { "FOO_HOME": "$HOMEBREW_PREFIX/var/foo" }
+        }));
+        let service = ServiceDefinition::for_formula(&formula, &PathBuf::from("/opt/zb"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            service.run,
+            [
+                "/opt/zb/opt/foo/bin/foo",
+                "--config",
+                "/opt/zb/etc/foo.conf"
+            ]
+        );
+        assert_eq!(service.run_type, RunType::Immediate);
+        assert_eq!(service.restart, RestartPolicy::Always);
+        assert_eq!(service.working_dir.as_deref(), Some("/opt/zb/var"));
+        assert_eq!(service.environment_variables["FOO_HOME"], "/opt/zb/var/foo");
+    }
+
+    #[test]
+    fn reads_scheduled_run_types() {
+        let prefix = PathBuf::from("/opt/zb");
+        let interval = formula_with_service(serde_json::json!({
+            "run": "$HOMEBREW_PREFIX/bin/foo", "run_type": "interval", "interval": 300
+        }));
+        assert_eq!(
+            ServiceDefinition::for_formula(&interval, &prefix)
+                .unwrap()
+                .unwrap()
+                .run_type,
+            RunType::Interval(300)
+        );
+
+        let cron = formula_with_service(serde_json::json!({
+            "run": ["foo"], "run_type": "cron", "cron": "0 3 * * *"
+        }));
+        let RunType::Cron(schedule) = ServiceDefinition::for_formula(&cron, &prefix)
+            .unwrap()
+            .unwrap()
+            .run_type
+        else {
+            panic!("expected cron schedule");
+        };
+        assert_eq!(schedule.to_string(), "0 3 * * *");
+    }
+
+    #[test]
+    fn keep_alive_conditions_restart_on_failure() {
+        let prefix = PathBuf::from("/opt/zb");
+        for keep_alive in [
+            serde_json::json!({ "successful_exit": false }),
+            serde_json::json!({ "crashed": true }),
+        ] {
+            let formula = formula_with_service(serde_json::json!({
+                "run": "foo", "keep_alive": keep_alive
+            }));
+            let service = ServiceDefinition::for_formula(&formula, &prefix)
+                .unwrap()
+                .unwrap();
+            assert_eq!(service.restart, RestartPolicy::OnFailure);
+        }
+
+        let formula = formula_with_service(serde_json::json!({ "run": "foo" }));
+        let service = ServiceDefinition::for_formula(&formula, &prefix)
+            .unwrap()
+            .unwrap();
+        assert_eq!(service.restart, RestartPolicy::Never);
+        assert!(RestartPolicy::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn formula_without_service_has_no_definition() {
+        let mut formula = formula_with_service(Value::Null);
+        formula.service = None;
+        assert_eq!(
+            ServiceDefinition::for_formula(&formula, Path::new("/")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_cron_fields() {
+        let schedule = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        assert_eq!(schedule.minute, Some(BTreeSet::from([0, 15, 30, 45])));
+        assert_eq!(schedule.hour.as_ref().unwrap().len(), 9);
+        assert_eq!(schedule.day, None);
+        assert!(schedule.matches(30, 9, 12, 6, 3));
+        assert!(!schedule.matches(30, 9, 12, 6, 0));
+
+        let sunday = CronSchedule::parse("0 0 * * 7").unwrap();
+        assert_eq!(sunday.weekday, Some(BTreeSet::from([0])));
+        assert_eq!(
+            CronSchedule::parse("@daily").unwrap().hour,
+            Some(BTreeSet::from([0]))
+        );
+    }
+
+    #[test]
+    fn cron_day_and_weekday_match_either() {
+        let schedule = CronSchedule::parse("0 0 1 * 1").unwrap();
+        assert!(schedule.matches(0, 0, 1, 5, 4));
+        assert!(schedule.matches(0, 0, 8, 5, 1));
+        assert!(!schedule.matches(0, 0, 8, 5, 2));
+    }
+
+    #[test]
+    fn rejects_malformed_cron() {
+        for expression in [
+            "0 3 * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "5-1 * * * *",
+            "*/0 * * * *",
+        ] {
+            assert!(
+                matches!(
+                    CronSchedule::parse(expression),
+                    Err(Error::InvalidArgument { .. })
+                ),
+                "{expression} should be rejected"
+            );
+        }
+    }
+}
@@ -59,6 +59,9 @@ pub fn build_env(plan: &BuildPlan, prefix: &Path) -> HashMap<String, String> {
     );
     env.insert("ZEROBREW_FORMULA_NAME".into(), plan.formula_name.clone());
     env.insert("ZEROBREW_FORMULA_VERSION".into(), plan.version.clone());
+    if plan.source_revision.is_some() {
+        env.insert("ZEROBREW_BUILD_HEAD".into(), "1".into());
+    }
 
     env.insert("MAKEFLAGS".into(), format!("-j{}", num_cpus()));
 
@@ -93,9 +96,11 @@ mod tests {
             version: "1.0.0".to_string(),
             source_url: "https://example.com/test.tar.gz".to_string(),
             source_checksum: None,
+            source_revision: None,
             ruby_source_path: None,
             build_dependencies: Vec::new(),
             runtime_dependencies: Vec::new(),
+            resources: Vec::new(),
             detected_system: BuildSystem::Autoconf,
             prefix: PathBuf::from("/opt/zerobrew/prefix"),
             cellar_path: PathBuf::from("/opt/zerobrew/cellar/test/1.0.0"),
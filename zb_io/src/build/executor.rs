@@ -8,7 +8,7 @@ use tokio::process::Command;
 use zb_core::{BuildPlan, Error};
 
 use super::environment::build_env;
-use super::source::download_and_extract_source;
+use super::source::{clone_git_source, download_and_extract_source, download_resources};
 
 const SHIM_RUBY: &str = include_str!("shim.rb");
 
@@ -32,12 +32,19 @@ impl BuildExecutor {
         let work_dir = self.work_root.join(&plan.formula_name);
         self.prepare_work_dir(&work_dir).await?;
 
-        let source_root = download_and_extract_source(
-            &plan.source_url,
-            plan.source_checksum.as_deref(),
-            &work_dir,
-        )
-        .await?;
+        let source_root = match plan.source_revision.as_deref() {
+            Some(revision) => clone_git_source(&plan.source_url, revision, &work_dir).await?,
+            None => {
+                download_and_extract_source(
+                    &plan.source_url,
+                    plan.source_checksum.as_deref(),
+                    &work_dir,
+                )
+                .await?
+            }
+        };
+
+        let resources = download_resources(&plan.resources, &work_dir.join("resources")).await?;
 
         let shim_path = work_dir.join("zerobrew_shim.rb");
         fs::write(&shim_path, SHIM_RUBY)
@@ -56,6 +63,8 @@ impl BuildExecutor {
 
         let deps_json = serde_json::to_string(installed_deps).unwrap_or_else(|_| "{}".into());
         env.insert("ZEROBREW_INSTALLED_DEPS".into(), deps_json);
+        let resources_json = serde_json::to_string(&resources).unwrap_or_else(|_| "{}".into());
+        env.insert("ZEROBREW_RESOURCES".into(), resources_json);
 
         let ruby = find_ruby().await?;
         run_build(&ruby, &shim_path, &source_root, &env).await?;
@@ -17,6 +17,7 @@ require "json"
 require "tmpdir"
 require "tempfile"
 require "digest/sha2"
+require "uri"
 
 module ZeroBrewChecksum
   module_function
@@ -48,6 +49,8 @@ FORMULA_NAME = ENV.fetch("ZEROBREW_FORMULA_NAME")
 FORMULA_VERSION = ENV.fetch("ZEROBREW_FORMULA_VERSION")
 FORMULA_FILE = ENV.fetch("ZEROBREW_FORMULA_FILE")
 INSTALLED_DEPS = JSON.parse(ENV.fetch("ZEROBREW_INSTALLED_DEPS", "{}"))
+# Resources zerobrew already downloaded and verified, by name.
+PREFETCHED_RESOURCES = JSON.parse(ENV.fetch("ZEROBREW_RESOURCES", "{}"))
 
 module OS
   def self.mac?
@@ -198,7 +201,10 @@ class ResourceDSL
 end
 
 class StagedResource
-  def initialize(url, sha256)
+  attr_reader :name, :url
+
+  def initialize(name, url, sha256)
+    @name = name
     @url = url
     @sha256 = sha256
   end
@@ -207,10 +213,14 @@ class StagedResource
     Dir.mktmpdir("zb_resource_") do |dir|
       basename = File.basename(URI.parse(@url).path) rescue "resource.tar.gz"
       archive = File.join(dir, basename)
-      Kernel.system("curl", "-sSL", "-o", archive, @url)
-      unless $?.success?
-        $stderr.puts "Error: failed to download resource #{@url}"
-        exit 1
+      if (prefetched = PREFETCHED_RESOURCES[@name])
+        FileUtils.cp(prefetched, archive)
+      else
+        Kernel.system("curl", "-sSL", "-o", archive, @url)
+        unless $?.success?
+          $stderr.puts "Error: failed to download resource #{@url}"
+          exit 1
+        end
       end
       ZeroBrewChecksum.verify_file!(archive, @sha256, "resource #{@url}")
       extract_resource(archive, dir)
@@ -247,8 +257,8 @@ class StagedResource
 end
 
 class BuildOptions
-  def head?; false; end
-  def stable?; true; end
+  def head?; ENV["ZEROBREW_BUILD_HEAD"] == "1"; end
+  def stable?; !head?; end
   def with?(name); false; end
   def without?(name); true; end
 end
@@ -435,7 +445,13 @@ class Formula
   def resource(name)
     res_info = self.class.instance_variable_get(:@_resources)&.dig(name.to_s)
     raise "Resource '#{name}' not defined" unless res_info
-    StagedResource.new(res_info[:url], res_info[:sha256])
+    StagedResource.new(name.to_s, res_info[:url], res_info[:sha256])
+  end
+
+  def resources
+    (self.class.instance_variable_get(:@_resources) || {}).map do |res_name, info|
+      StagedResource.new(res_name, info[:url], info[:sha256])
+    end
   end
 
   def etc
@@ -499,6 +515,19 @@ class Formula
     ["--prefix=#{prefix}", "--libdir=lib", "--buildtype=release", "--wrap-mode=nofallback"]
   end
 
+  def std_npm_args(prefix: libexec)
+    args = ["--loglevel=silly", "--build-from-source", "--cache=#{buildpath}/.npm_cache"]
+    args += ["--global", "--prefix=#{prefix}"] if prefix
+    args
+  end
+
+  def std_pip_args(prefix: false, build_isolation: false)
+    args = ["--verbose", "--no-deps", "--no-binary=:all:", "--ignore-installed", "--no-compile"]
+    args << "--prefix=#{prefix}" if prefix
+    args << "--no-build-isolation" unless build_isolation
+    args
+  end
+
   def std_go_args(**overrides)
     ldflags = overrides.fetch(:ldflags, "")
     output = overrides.fetch(:output, bin + name)
@@ -575,6 +604,66 @@ module Language
       parts = raw.split(".")
       "#{parts[0]}.#{parts[1]}"
     end
+
+    # Homebrew's convention for Python tools: a virtualenv in `libexec`
+    # holding the formula's resources and the package itself, with the
+    # package's scripts linked into `bin`.
+    module Virtualenv
+      def virtualenv_create(venv_root, python = "python3", **_kwargs)
+        venv = ZeroBrewVirtualenv.new(self, Pathname.new(venv_root.to_s), python.to_s)
+        venv.create
+        venv
+      end
+
+      def virtualenv_install_with_resources(using: nil, **kwargs)
+        venv = virtualenv_create(libexec, using || "python3", **kwargs)
+        venv.pip_install resources
+        venv.pip_install_and_link buildpath
+        venv
+      end
+    end
+  end
+end
+
+class ZeroBrewVirtualenv
+  attr_reader :root
+
+  def initialize(formula, root, python)
+    @formula = formula
+    @root = root
+    @python = python
+  end
+
+  def create
+    return if (@root + "bin" + "python").exist?
+    puts "==> Creating virtualenv in #{@root}"
+    odie "failed to create virtualenv in #{@root}" unless Kernel.system(@python, "-m", "venv", @root.to_s)
+  end
+
+  def pip_install(targets, build_isolation: true)
+    Array(targets).each do |target|
+      if target.is_a?(StagedResource)
+        target.stage { |dir| install_target(dir, build_isolation) }
+      else
+        install_target(target, build_isolation)
+      end
+    end
+  end
+
+  def pip_install_and_link(targets, **kwargs)
+    before = Dir[(@root + "bin" + "*").to_s]
+    pip_install(targets, **kwargs)
+    @formula.bin.install_symlink(Dir[(@root + "bin" + "*").to_s] - before)
+  end
+
+  private
+
+  def install_target(target, build_isolation)
+    args = ["--verbose", "--no-deps", "--no-binary=:all:", "--ignore-installed", "--no-compile"]
+    args << "--no-build-isolation" unless build_isolation
+    cmd = [(@root + "bin" + "python").to_s, "-m", "pip", "install", *args, target.to_s]
+    puts "==> #{cmd.join(" ")}"
+    odie "pip install of #{target} failed" unless Kernel.system(*cmd)
   end
 end
 
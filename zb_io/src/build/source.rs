@@ -1,7 +1,10 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
+use futures::future::try_join_all;
 use tokio::fs;
-use zb_core::Error;
+use tokio::process::Command;
+use zb_core::{Error, FormulaResource};
 
 use crate::checksum::verify_sha256_bytes;
 use crate::extraction::extract_tarball;
@@ -26,6 +29,97 @@ pub async fn download_and_extract_source(
     find_source_root(&src_dir).await
 }
 
+/// Download the `resources` with a checksum into `dir` and verify them,
+/// returning the archive of each by name. The build's `install` stages them
+/// from there; resources without a checksum, such as git checkouts, are
+/// left to the formula.
+pub async fn download_resources(
+    resources: &[FormulaResource],
+    dir: &Path,
+) -> Result<BTreeMap<String, PathBuf>, Error> {
+    let downloads = resources
+        .iter()
+        .filter(|resource| resource.sha256.is_some())
+        .map(|resource| async move {
+            let resource_dir = dir.join(&resource.name);
+            fs::create_dir_all(&resource_dir)
+                .await
+                .map_err(Error::file("failed to create resource directory"))?;
+            let archive = resource_dir.join(resource_file_name(&resource.url));
+            download_source(&resource.url, &archive).await?;
+            verify_checksum(&archive, resource.sha256.as_deref(), &resource.url).await?;
+            Ok::<_, Error>((resource.name.clone(), archive))
+        });
+    Ok(try_join_all(downloads).await?.into_iter().collect())
+}
+
+fn resource_file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name,
+        _ => "resource",
+    }
+}
+
+/// The commit `branch` of the repository at `url` points at, or that of its
+/// default branch.
+pub async fn resolve_head_revision(url: &str, branch: Option<&str>) -> Result<String, Error> {
+    let reference = branch.map_or_else(|| "HEAD".to_string(), |b| format!("refs/heads/{b}"));
+    let output = git(&["ls-remote", url, &reference], None).await?;
+    output
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+        .ok_or_else(|| Error::NetworkFailure {
+            message: format!("{url} has no {reference}"),
+        })
+}
+
+/// Check out `revision` of the repository at `url` under `work_dir` and
+/// return the checkout.
+pub async fn clone_git_source(
+    url: &str,
+    revision: &str,
+    work_dir: &Path,
+) -> Result<PathBuf, Error> {
+    let src_dir = work_dir.join("src");
+    fs::create_dir_all(&src_dir)
+        .await
+        .map_err(Error::file("failed to create source directory"))?;
+
+    git(&["init", "--quiet"], Some(&src_dir)).await?;
+    git(
+        &["fetch", "--quiet", "--depth", "1", url, revision],
+        Some(&src_dir),
+    )
+    .await?;
+    git(&["checkout", "--quiet", "FETCH_HEAD"], Some(&src_dir)).await?;
+
+    Ok(src_dir)
+}
+
+async fn git(args: &[&str], dir: Option<&Path>) -> Result<String, Error> {
+    let mut command = Command::new("git");
+    command.args(args).env("GIT_TERMINAL_PROMPT", "0");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command.output().await.map_err(|e| Error::ExecutionError {
+        message: format!("git is required to build HEAD versions: {e}"),
+    })?;
+
+    if !output.status.success() {
+        return Err(Error::ExecutionError {
+            message: format!(
+                "git {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
 async fn download_source(url: &str, dest: &Path) -> Result<(), Error> {
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(300))
@@ -100,3 +194,98 @@ async fn find_source_root(src_dir: &Path) -> Result<PathBuf, Error> {
 
     Ok(src_dir.to_path_buf())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command as StdCommand;
+
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::checksum::sha256_hex;
+
+    fn git_in(dir: &Path, args: &[&str]) -> String {
+        let output = StdCommand::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_AUTHOR_NAME", "zb")
+            .env("GIT_AUTHOR_EMAIL", "zb@example.com")
+            .env("GIT_COMMITTER_NAME", "zb")
+            .env("GIT_COMMITTER_EMAIL", "zb@example.com")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[tokio::test]
+    async fn checks_out_resolved_head_revision() {
+        let tmp = TempDir::new().unwrap();
+        let repo = tmp.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        git_in(&repo, &["init", "--quiet", "--initial-branch", "main"]);
+        std::fs::write(repo.join("README"), "first").unwrap();
+        git_in(&repo, &["add", "README"]);
+        git_in(&repo, &["commit", "--quiet", "-m", "first"]);
+        let first = git_in(&repo, &["rev-parse", "HEAD"]).trim().to_string();
+        std::fs::write(repo.join("README"), "second").unwrap();
+        git_in(&repo, &["commit", "--quiet", "-am", "second"]);
+        let second = git_in(&repo, &["rev-parse", "HEAD"]).trim().to_string();
+
+        let url = repo.to_str().unwrap();
+        assert_eq!(resolve_head_revision(url, None).await.unwrap(), second);
+        assert_eq!(
+            resolve_head_revision(url, Some("main")).await.unwrap(),
+            second
+        );
+        assert!(resolve_head_revision(url, Some("missing")).await.is_err());
+
+        let checkout = clone_git_source(url, &first, &tmp.path().join("work"))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(checkout.join("README")).unwrap(),
+            "first"
+        );
+    }
+
+    #[tokio::test]
+    async fn downloads_and_verifies_resources_with_checksums() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/click-8.1.7.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"click".to_vec()))
+            .mount(&server)
+            .await;
+        let tmp = TempDir::new().unwrap();
+        let resource = |name: &str, sha256: Option<String>| FormulaResource {
+            name: name.to_string(),
+            url: format!("{}/click-8.1.7.tar.gz?download=1", server.uri()),
+            sha256,
+        };
+
+        let downloaded = download_resources(
+            &[
+                resource("click", Some(sha256_hex(Sha256::new_with_prefix(b"click")))),
+                resource("unchecked", None),
+            ],
+            tmp.path(),
+        )
+        .await
+        .unwrap();
+        let archive = tmp.path().join("click/click-8.1.7.tar.gz");
+        assert_eq!(
+            downloaded,
+            BTreeMap::from([("click".to_string(), archive.clone())])
+        );
+        assert_eq!(std::fs::read(&archive).unwrap(), b"click");
+
+        let err = download_resources(&[resource("click", Some("0".repeat(64)))], tmp.path())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+    }
+}
@@ -0,0 +1,652 @@
+//! Checking that the libraries and interpreters referenced by a keg's
+//! Mach-O and ELF files exist once the keg has been relocated, along with
+//! the `share/` data paths compiled into them.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use object::elf::{DT_NEEDED, DT_RPATH, DT_RUNPATH, PT_INTERP};
+use object::macho::{FatArch32, FatArch64, MH_EXECUTE, MachHeader32, MachHeader64};
+use object::read::elf::{Dyn, FileHeader, ProgramHeader};
+use object::read::macho::{FatArch, LoadCommandVariant, MachHeader, MachOFatFile};
+use object::{Endianness, FileKind};
+use zb_core::Error;
+
+use super::shebang;
+
+/// Directories the glibc loader searches after a binary's own runpath.
+const DEFAULT_ELF_LIBRARY_DIRS: &[&str] = &["/lib", "/usr/lib", "/lib64", "/usr/lib64"];
+
+/// Libraries under these directories live in the dyld shared cache on macOS
+/// and are never present on disk.
+const MACOS_SYSTEM_LIBRARY_DIRS: &[&str] = &["/usr/lib/", "/System/Library/"];
+
+/// Homebrew prefixes that a bottle's data paths may still point into when
+/// they could not be relocated.
+const HOMEBREW_DATA_PREFIXES: &[&str] = &["/home/linuxbrew/.linuxbrew", "/opt/homebrew"];
+
+/// A library or interpreter referenced by a keg file that does not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingReference {
+    /// The referencing file, relative to the keg.
+    pub file: PathBuf,
+    /// The reference as recorded in the file.
+    pub reference: String,
+    /// How the reference could be fixed, when a likely target is found.
+    pub suggestion: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelocationAudit {
+    /// Mach-O and ELF files inspected.
+    pub files_scanned: usize,
+    pub dangling: Vec<DanglingReference>,
+    /// `share/` paths compiled into binaries that do not exist. Data may be
+    /// optional, so these are reported but never fail an install.
+    pub missing_data: Vec<DanglingReference>,
+    /// Scripts whose `#!` interpreter does not exist. Reported but never
+    /// fatal, as the formula providing it may be installed later.
+    pub missing_interpreters: Vec<DanglingReference>,
+}
+
+impl RelocationAudit {
+    /// Fail with every dangling reference of the keg of `name`, if any.
+    pub fn ensure_clean(&self, name: &str) -> Result<(), Error> {
+        if self.dangling.is_empty() {
+            return Ok(());
+        }
+        Err(Error::DanglingReferences {
+            name: name.to_string(),
+            references: self
+                .dangling
+                .iter()
+                .map(|d| match &d.suggestion {
+                    Some(suggestion) => {
+                        format!("{}: {} ({suggestion})", d.file.display(), d.reference)
+                    }
+                    None => format!("{}: {}", d.file.display(), d.reference),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// What a binary asks the dynamic loader for.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct LinkedReferences {
+    libraries: BTreeSet<String>,
+    rpaths: Vec<String>,
+    interpreter: Option<String>,
+    /// `@executable_path` means this file's directory only for executables.
+    executable: bool,
+    elf: bool,
+}
+
+/// Scan the keg at `keg_path`, installed under `prefix` with kegs kept in
+/// `cellar_dir`, for references to libraries that do not exist.
+pub fn audit_keg(
+    keg_path: &Path,
+    prefix: &Path,
+    cellar_dir: &Path,
+) -> Result<RelocationAudit, Error> {
+    let mut audit = RelocationAudit::default();
+    let system_dirs = elf_library_dirs();
+    let mut data_roots: Vec<String> = vec![
+        prefix.to_string_lossy().into_owned(),
+        cellar_dir.to_string_lossy().into_owned(),
+    ];
+    data_roots.extend(HOMEBREW_DATA_PREFIXES.iter().map(|p| p.to_string()));
+
+    for entry in walkdir::WalkDir::new(keg_path).follow_links(false) {
+        let entry = entry.map_err(Error::store("failed to walk keg"))?;
+        if !entry.file_type().is_file() || !has_binary_magic(entry.path()) {
+            continue;
+        }
+        let data = fs::read(entry.path()).map_err(Error::store("failed to read keg file"))?;
+        let Some(references) = linked_references(&data) else {
+            continue;
+        };
+        audit.files_scanned += 1;
+
+        let file_dir = entry.path().parent().unwrap_or(keg_path);
+        let relative = entry
+            .path()
+            .strip_prefix(keg_path)
+            .unwrap_or(entry.path())
+            .to_path_buf();
+        for reference in missing_references(&references, file_dir, &system_dirs) {
+            audit.dangling.push(DanglingReference {
+                file: relative.clone(),
+                suggestion: suggest_fix(&reference, prefix, cellar_dir),
+                reference,
+            });
+        }
+        for reference in data_references(&data, &data_roots) {
+            if Path::new(&reference).exists() {
+                continue;
+            }
+            audit.missing_data.push(DanglingReference {
+                file: relative.clone(),
+                suggestion: suggest_data_fix(&reference, prefix, cellar_dir),
+                reference,
+            });
+        }
+    }
+
+    audit.missing_interpreters = shebang::missing_interpreters(keg_path, prefix, cellar_dir)?;
+    Ok(audit)
+}
+
+fn has_binary_magic(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    let Ok(mut file) = fs::File::open(path) else {
+        return false;
+    };
+    if file.read_exact(&mut magic).is_err() {
+        return false;
+    }
+    magic == *b"\x7fELF"
+        || matches!(
+            u32::from_be_bytes(magic),
+            0xfeedface | 0xfeedfacf | 0xcafebabe | 0xcefaedfe | 0xcffaedfe
+        )
+}
+
+/// The loader references of an ELF or (possibly universal) Mach-O file.
+fn linked_references(data: &[u8]) -> Option<LinkedReferences> {
+    match FileKind::parse(data).ok()? {
+        FileKind::Elf32 => elf_references::<object::elf::FileHeader32<Endianness>>(data),
+        FileKind::Elf64 => elf_references::<object::elf::FileHeader64<Endianness>>(data),
+        FileKind::MachO32 => macho_references::<MachHeader32<Endianness>>(data),
+        FileKind::MachO64 => macho_references::<MachHeader64<Endianness>>(data),
+        FileKind::MachOFat32 => fat_references::<FatArch32>(data),
+        FileKind::MachOFat64 => fat_references::<FatArch64>(data),
+        _ => None,
+    }
+}
+
+fn elf_references<Elf: FileHeader<Endian = Endianness>>(data: &[u8]) -> Option<LinkedReferences> {
+    let header = Elf::parse(data).ok()?;
+    let endian = header.endian().ok()?;
+    let mut references = LinkedReferences {
+        elf: true,
+        ..LinkedReferences::default()
+    };
+
+    for segment in header.program_headers(endian, data).ok()? {
+        if segment.p_type(endian) == PT_INTERP
+            && let Ok(Some(interpreter)) = segment.interpreter(endian, data)
+        {
+            references.interpreter = Some(String::from_utf8_lossy(interpreter).into_owned());
+        }
+    }
+
+    let sections = header.sections(endian, data).ok()?;
+    let Some((entries, strings_index)) = sections.dynamic(endian, data).ok()? else {
+        return Some(references);
+    };
+    let strings = sections.strings(endian, data, strings_index).ok()?;
+    for entry in entries {
+        let tag = entry.tag(endian);
+        if !matches!(tag, DT_NEEDED | DT_RPATH | DT_RUNPATH) {
+            continue;
+        }
+        let Ok(value) = entry.string(endian, strings) else {
+            continue;
+        };
+        let value = String::from_utf8_lossy(value).into_owned();
+        if tag == DT_NEEDED {
+            references.libraries.insert(value);
+        } else {
+            references
+                .rpaths
+                .extend(value.split(':').filter(|p| !p.is_empty()).map(String::from));
+        }
+    }
+    Some(references)
+}
+
+fn macho_references<Mach: MachHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> Option<LinkedReferences> {
+    let header = Mach::parse(data, 0).ok()?;
+    let endian = header.endian().ok()?;
+    let mut references = LinkedReferences {
+        executable: header.filetype(endian) == MH_EXECUTE,
+        ..LinkedReferences::default()
+    };
+
+    let mut commands = header.load_commands(endian, data, 0).ok()?;
+    while let Ok(Some(command)) = commands.next() {
+        match command.variant() {
+            // A weak library may be absent; the loader carries on without it.
+            Ok(LoadCommandVariant::Dylib(dylib))
+                if command.cmd() != object::macho::LC_LOAD_WEAK_DYLIB =>
+            {
+                if let Ok(name) = command.string(endian, dylib.dylib.name) {
+                    references
+                        .libraries
+                        .insert(String::from_utf8_lossy(name).into_owned());
+                }
+            }
+            Ok(LoadCommandVariant::Rpath(rpath)) => {
+                if let Ok(path) = command.string(endian, rpath.path) {
+                    references
+                        .rpaths
+                        .push(String::from_utf8_lossy(path).into_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(references)
+}
+
+/// References of every slice of a universal binary, merged.
+fn fat_references<Fat: FatArch>(data: &[u8]) -> Option<LinkedReferences> {
+    let fat = MachOFatFile::<Fat>::parse(data).ok()?;
+    let mut merged: Option<LinkedReferences> = None;
+    for arch in fat.arches() {
+        let Some(slice) = arch.data(data).ok().and_then(linked_references) else {
+            continue;
+        };
+        match &mut merged {
+            None => merged = Some(slice),
+            Some(merged) => {
+                merged.libraries.extend(slice.libraries);
+                for rpath in slice.rpaths {
+                    if !merged.rpaths.contains(&rpath) {
+                        merged.rpaths.push(rpath);
+                    }
+                }
+                merged.executable |= slice.executable;
+            }
+        }
+    }
+    merged
+}
+
+/// The references the loader would fail to find for a file in `file_dir`.
+fn missing_references(
+    references: &LinkedReferences,
+    file_dir: &Path,
+    system_dirs: &[PathBuf],
+) -> Vec<String> {
+    let mut missing = Vec::new();
+    if let Some(interpreter) = &references.interpreter
+        && !Path::new(interpreter).exists()
+    {
+        missing.push(interpreter.clone());
+    }
+
+    // Placeholders left in search paths break every lookup through them.
+    missing.extend(
+        references
+            .rpaths
+            .iter()
+            .filter(|rpath| rpath.contains("@@HOMEBREW_"))
+            .cloned(),
+    );
+
+    for library in &references.libraries {
+        let found = if references.elf {
+            resolve_elf_library(library, &references.rpaths, file_dir, system_dirs)
+        } else {
+            resolve_macho_library(library, references, file_dir)
+        };
+        if !found {
+            missing.push(library.clone());
+        }
+    }
+    missing
+}
+
+fn resolve_elf_library(
+    library: &str,
+    rpaths: &[String],
+    file_dir: &Path,
+    system_dirs: &[PathBuf],
+) -> bool {
+    if library.contains('/') {
+        return Path::new(library).exists();
+    }
+    let origin = file_dir.to_string_lossy();
+    rpaths
+        .iter()
+        .map(|rpath| {
+            PathBuf::from(
+                rpath
+                    .replace("${ORIGIN}", &origin)
+                    .replace("$ORIGIN", &origin),
+            )
+        })
+        .chain(system_dirs.iter().cloned())
+        .any(|dir| dir.join(library).exists())
+}
+
+/// Whether dyld would find `library`. References it cannot know without the
+/// loading executable count as found.
+fn resolve_macho_library(library: &str, references: &LinkedReferences, file_dir: &Path) -> bool {
+    let expand = |path: &str| -> Option<PathBuf> {
+        if let Some(rest) = path.strip_prefix("@loader_path/") {
+            Some(file_dir.join(rest))
+        } else if let Some(rest) = path.strip_prefix("@executable_path/") {
+            references.executable.then(|| file_dir.join(rest))
+        } else if path.starts_with('/') {
+            Some(PathBuf::from(path))
+        } else {
+            None
+        }
+    };
+
+    if let Some(rest) = library.strip_prefix("@rpath/") {
+        let mut unknown = false;
+        for rpath in &references.rpaths {
+            match expand(rpath) {
+                Some(dir) if dir.join(rest).exists() => return true,
+                Some(_) => {}
+                None => unknown = true,
+            }
+        }
+        // A library without search paths of its own relies on those of the
+        // executable loading it.
+        return unknown || (!references.executable && references.rpaths.is_empty());
+    }
+    if MACOS_SYSTEM_LIBRARY_DIRS
+        .iter()
+        .any(|dir| library.starts_with(dir))
+    {
+        return true;
+    }
+    match expand(library) {
+        Some(path) => path.exists(),
+        None => true,
+    }
+}
+
+/// The default glibc search path plus the directories from `ld.so.conf.d`.
+fn elf_library_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = DEFAULT_ELF_LIBRARY_DIRS.iter().map(PathBuf::from).collect();
+    let Ok(entries) = fs::read_dir("/etc/ld.so.conf.d") else {
+        return dirs;
+    };
+    for entry in entries.flatten() {
+        let Ok(conf) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        dirs.extend(
+            conf.lines()
+                .map(str::trim)
+                .filter(|line| line.starts_with('/'))
+                .map(PathBuf::from),
+        );
+    }
+    dirs
+}
+
+/// Paths into a `share/` directory under one of `roots` that appear as
+/// strings in `data`.
+fn data_references(data: &[u8], roots: &[String]) -> BTreeSet<String> {
+    let mut references = BTreeSet::new();
+    for root in roots {
+        let needle = format!("{root}/");
+        let needle = needle.as_bytes();
+        let mut i = 0;
+        while i + needle.len() <= data.len() {
+            if data[i..i + needle.len()] != *needle
+                || (i > 0 && is_path_byte(data[i - 1]) && data[i - 1] != b'=')
+            {
+                i += 1;
+                continue;
+            }
+            let end = data[i..]
+                .iter()
+                .position(|&b| !is_path_byte(b))
+                .map_or(data.len(), |n| i + n);
+            let path = String::from_utf8_lossy(&data[i..end]);
+            let path = path.trim_end_matches('/');
+            if path[root.len()..].contains("/share/") {
+                references.insert(path.to_string());
+            }
+            i = end;
+        }
+    }
+    references
+}
+
+/// Whether `byte` can be part of a literal path; format directives and
+/// separators end one.
+fn is_path_byte(byte: u8) -> bool {
+    byte.is_ascii_graphic() && !b"%$:;,'\"`*?<>|()[]{}".contains(&byte)
+}
+
+/// A hint for a missing data path: Homebrew's prefix left unrelocated, or
+/// the hints [`suggest_fix`] gives.
+fn suggest_data_fix(reference: &str, prefix: &Path, cellar_dir: &Path) -> Option<String> {
+    if HOMEBREW_DATA_PREFIXES
+        .iter()
+        .any(|p| reference.starts_with(&format!("{p}/")))
+    {
+        return Some(
+            "path under Homebrew's prefix was not relocated; `zb compat enable` can provide it"
+                .to_string(),
+        );
+    }
+    suggest_fix(reference, prefix, cellar_dir)
+}
+
+/// A hint for fixing a dangling `reference`: an unrelocated placeholder, a
+/// keg version that is not installed, or a file of the same name elsewhere
+/// in the prefix.
+fn suggest_fix(reference: &str, prefix: &Path, cellar_dir: &Path) -> Option<String> {
+    if reference.contains("@@HOMEBREW_") {
+        return Some("placeholder was never relocated; reinstall the formula".to_string());
+    }
+
+    let cellar = format!("{}/", cellar_dir.display());
+    if let Some(rest) = reference.strip_prefix(&cellar)
+        && let [name, version, ..] = rest.split('/').collect::<Vec<_>>()[..]
+        && let Ok(versions) = fs::read_dir(cellar_dir.join(name))
+    {
+        let installed: Vec<String> = versions
+            .flatten()
+            .filter_map(|v| v.file_name().into_string().ok())
+            .filter(|v| v != version)
+            .collect();
+        if !installed.is_empty() {
+            return Some(format!(
+                "{name} {version} is not installed, but {} is; link against {}",
+                installed.join(", "),
+                prefix.join("opt").join(name).display()
+            ));
+        }
+    }
+
+    let file_name = Path::new(reference).file_name()?;
+    let mut candidates = vec![prefix.join("lib").join(file_name)];
+    if let Ok(opt) = fs::read_dir(prefix.join("opt")) {
+        candidates.extend(opt.flatten().map(|e| e.path().join("lib").join(file_name)));
+    }
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.exists())
+        .map(|found| format!("found at {}", found.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn macho(libraries: &[&str], rpaths: &[&str]) -> LinkedReferences {
+        LinkedReferences {
+            libraries: libraries.iter().map(|l| l.to_string()).collect(),
+            rpaths: rpaths.iter().map(|r| r.to_string()).collect(),
+            ..LinkedReferences::default()
+        }
+    }
+
+    #[test]
+    fn resolves_macho_install_names() {
+        let tmp = TempDir::new().unwrap();
+        let lib = tmp.path().join("lib");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("libzstd.1.dylib"), b"").unwrap();
+        let bin = tmp.path().join("bin");
+        fs::create_dir_all(&bin).unwrap();
+
+        let references = macho(
+            &[
+                "@rpath/libzstd.1.dylib",
+                "@loader_path/../lib/libzstd.1.dylib",
+                "/usr/lib/libSystem.B.dylib",
+                "@rpath/liblzma.5.dylib",
+                "/opt/zb/Cellar/mpdecimal/4.0.0/lib/libmpdec.4.dylib",
+            ],
+            &["@loader_path/../lib"],
+        );
+        assert_eq!(
+            missing_references(&references, &bin, &[]),
+            [
+                "/opt/zb/Cellar/mpdecimal/4.0.0/lib/libmpdec.4.dylib",
+                "@rpath/liblzma.5.dylib",
+            ]
+        );
+
+        // Without the executable, @executable_path cannot be checked.
+        let references = macho(&["@executable_path/../lib/libfoo.dylib"], &[]);
+        assert!(missing_references(&references, &bin, &[]).is_empty());
+    }
+
+    #[test]
+    fn suggests_installed_version_or_file_in_prefix() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path();
+        let cellar = prefix.join("Cellar");
+        fs::create_dir_all(cellar.join("mpdecimal/4.0.1/lib")).unwrap();
+        fs::create_dir_all(prefix.join("opt/xz/lib")).unwrap();
+        fs::write(prefix.join("opt/xz/lib/liblzma.5.dylib"), b"").unwrap();
+
+        let stale = format!("{}/mpdecimal/4.0.0/lib/libmpdec.4.dylib", cellar.display());
+        assert_eq!(
+            suggest_fix(&stale, prefix, &cellar).unwrap(),
+            format!(
+                "mpdecimal 4.0.0 is not installed, but 4.0.1 is; link against {}",
+                prefix.join("opt/mpdecimal").display()
+            )
+        );
+        assert_eq!(
+            suggest_fix("@rpath/liblzma.5.dylib", prefix, &cellar).unwrap(),
+            format!(
+                "found at {}",
+                prefix.join("opt/xz/lib/liblzma.5.dylib").display()
+            )
+        );
+        assert!(
+            suggest_fix("@@HOMEBREW_PREFIX@@/lib/libz.dylib", prefix, &cellar)
+                .unwrap()
+                .contains("never relocated")
+        );
+        assert_eq!(suggest_fix("@rpath/libnope.dylib", prefix, &cellar), None);
+    }
+
+    #[test]
+    fn finds_share_paths_compiled_into_binaries() {
+        let roots = [
+            "/opt/zb".to_string(),
+            "/home/linuxbrew/.linuxbrew".to_string(),
+        ];
+        let data = b"\0/opt/zb/Cellar/wordnet/3.1/share/wordnet/dict/\0\
+            WNHOME=/opt/zb/share/wordnet\0/opt/zb/lib/libwn.so\0\
+            %s/opt/zb/share/%s\0/usr/opt/zb/share/x\0\
+            /home/linuxbrew/.linuxbrew/share/misc:/opt/zb/share/misc\0";
+        assert_eq!(
+            data_references(data, &roots)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [
+                "/home/linuxbrew/.linuxbrew/share/misc",
+                "/opt/zb/Cellar/wordnet/3.1/share/wordnet/dict",
+                "/opt/zb/share/misc",
+                "/opt/zb/share/wordnet",
+            ]
+        );
+        assert!(
+            suggest_data_fix(
+                "/home/linuxbrew/.linuxbrew/share/misc",
+                Path::new("/opt/zb"),
+                Path::new("/opt/zb/Cellar")
+            )
+            .unwrap()
+            .contains("zb compat enable")
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn reports_missing_elf_libraries() {
+        let tmp = TempDir::new().unwrap();
+        let keg = tmp.path().join("Cellar/tool/1.0");
+        let (bin, lib) = (keg.join("bin"), keg.join("lib"));
+        fs::create_dir_all(&bin).unwrap();
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(tmp.path().join("dep.c"), "int dep(void) { return 1; }").unwrap();
+        fs::write(
+            tmp.path().join("main.c"),
+            "int dep(void); int main(void) { return dep(); }",
+        )
+        .unwrap();
+
+        let cc = |args: &[&str]| {
+            Command::new("cc")
+                .current_dir(tmp.path())
+                .args(args)
+                .status()
+                .is_ok_and(|s| s.success())
+        };
+        let lib_arg = lib.to_string_lossy();
+        let out = bin.join("tool");
+        if !cc(&[
+            "-shared",
+            "-fPIC",
+            "dep.c",
+            "-o",
+            &format!("{lib_arg}/libdep.so"),
+        ]) || !cc(&[
+            "main.c",
+            "-o",
+            &out.to_string_lossy(),
+            &format!("-L{lib_arg}"),
+            "-ldep",
+            "-Wl,-rpath,$ORIGIN/../lib",
+        ]) {
+            eprintln!("skipping: no working C compiler");
+            return;
+        }
+
+        let cellar = tmp.path().join("Cellar");
+        let audit = audit_keg(&keg, tmp.path(), &cellar).unwrap();
+        assert_eq!(audit.files_scanned, 2);
+        assert!(audit.dangling.is_empty(), "{:?}", audit.dangling);
+
+        fs::create_dir_all(tmp.path().join("lib")).unwrap();
+        fs::rename(lib.join("libdep.so"), tmp.path().join("lib/libdep.so")).unwrap();
+        let audit = audit_keg(&keg, tmp.path(), &cellar).unwrap();
+        assert_eq!(
+            audit.dangling,
+            [DanglingReference {
+                file: PathBuf::from("bin/tool"),
+                reference: "libdep.so".to_string(),
+                suggestion: Some(format!(
+                    "found at {}",
+                    tmp.path().join("lib/libdep.so").display()
+                )),
+            }]
+        );
+    }
+}
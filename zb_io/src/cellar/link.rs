@@ -1,7 +1,16 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::io;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 
+use rayon::ThreadPool;
+use rayon::prelude::*;
+
+use tracing::debug;
 use zb_core::{ConflictedLink, Error};
 
 const LINK_DIRS: &[&str] = &["bin", "lib", "libexec", "include", "share", "etc"];
@@ -77,16 +86,67 @@ fn is_python_version_dir(name: &str) -> bool {
         .is_some_and(|c| c.is_ascii_digit())
 }
 
+/// How a keg's executables are placed in the prefix's `bin`. Everything
+/// else is always symlinked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkMode {
+    #[default]
+    Symlink,
+    /// A hard link to the keg's file, for tools that follow their own
+    /// symlink and then cannot find their files relative to it.
+    Hardlink,
+    /// A shell script that runs the keg's file by its real path, with the
+    /// keg's `bin` first on `PATH`, for tools that locate themselves from
+    /// `argv[0]`.
+    Wrapper,
+}
+
+impl LinkMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkMode::Symlink => "symlink",
+            LinkMode::Hardlink => "hardlink",
+            LinkMode::Wrapper => "wrapper",
+        }
+    }
+}
+
+impl fmt::Display for LinkMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LinkMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "symlink" => Ok(LinkMode::Symlink),
+            "hardlink" => Ok(LinkMode::Hardlink),
+            "wrapper" => Ok(LinkMode::Wrapper),
+            other => Err(Error::InvalidArgument {
+                message: format!(
+                    "unknown link mode '{other}' (expected symlink, hardlink or wrapper)"
+                ),
+            }),
+        }
+    }
+}
+
 pub struct Linker {
     prefix: PathBuf,
     bin_dir: PathBuf,
     opt_dir: PathBuf,
+    /// Links a keg's top-level directories in parallel when set.
+    pool: Option<Arc<ThreadPool>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct LinkedFile {
     pub link_path: PathBuf,
     pub target_path: PathBuf,
+    pub mode: LinkMode,
 }
 
 fn keg_name_from_path(path: &Path) -> Option<String> {
@@ -102,6 +162,69 @@ fn keg_name_from_path(path: &Path) -> Option<String> {
     None
 }
 
+/// How `dst` was placed in the prefix for the keg file `src`, if it was.
+fn placed_for(dst: &Path, src: &Path) -> Option<LinkMode> {
+    let metadata = dst.symlink_metadata().ok()?;
+    if metadata.is_symlink() {
+        let target = fs::read_link(dst).ok()?;
+        let resolved = if target.is_relative() {
+            dst.parent().unwrap_or(Path::new("")).join(&target)
+        } else {
+            target
+        };
+        let same = fs::canonicalize(&resolved).ok()? == fs::canonicalize(src).ok()?;
+        return same.then_some(LinkMode::Symlink);
+    }
+    if !metadata.is_file() {
+        return None;
+    }
+    let source = fs::metadata(src).ok()?;
+    if metadata.dev() == source.dev() && metadata.ino() == source.ino() {
+        return Some(LinkMode::Hardlink);
+    }
+    is_wrapper_for(dst, src).then_some(LinkMode::Wrapper)
+}
+
+fn is_wrapper_for(dst: &Path, src: &Path) -> bool {
+    let script = wrapper_script(src);
+    dst.symlink_metadata()
+        .is_ok_and(|m| m.is_file() && m.len() == script.len() as u64)
+        && fs::read(dst).is_ok_and(|contents| contents == script.as_bytes())
+}
+
+fn wrapper_script(src: &Path) -> String {
+    let bin_dir = src.parent().unwrap_or(Path::new("/"));
+    format!(
+        "#!/bin/sh\n# Linked by zerobrew\nPATH={}:\"$PATH\"\nexport PATH\nexec {} \"$@\"\n",
+        sh_quote(&bin_dir.to_string_lossy()),
+        sh_quote(&src.to_string_lossy())
+    )
+}
+
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Put `dst` in place for the keg file `src` according to `mode`.
+fn place(src: &Path, dst: &Path, mode: LinkMode) -> Result<(), Error> {
+    match mode {
+        LinkMode::Symlink => {
+            std::os::unix::fs::symlink(src, dst).map_err(Error::store("failed to create symlink"))
+        }
+        LinkMode::Hardlink => {
+            // Link the file itself, not a symlink to it inside the keg.
+            let file = fs::canonicalize(src).map_err(Error::store("failed to resolve keg file"))?;
+            fs::hard_link(&file, dst).map_err(Error::store("failed to create hard link"))
+        }
+        LinkMode::Wrapper => {
+            fs::write(dst, wrapper_script(src))
+                .map_err(Error::store("failed to write wrapper script"))?;
+            fs::set_permissions(dst, fs::Permissions::from_mode(0o755))
+                .map_err(Error::store("failed to make wrapper script executable"))
+        }
+    }
+}
+
 fn keg_name_from_symlink(dst: &Path) -> Option<String> {
     let target = fs::read_link(dst).ok()?;
     let resolved = if target.is_relative() {
@@ -130,9 +253,19 @@ impl Linker {
             prefix: prefix.to_path_buf(),
             bin_dir,
             opt_dir,
+            pool: None,
         })
     }
 
+    /// Link the directories of a keg (`bin`, `lib`, ...) on up to
+    /// `threads` threads. One thread links them in turn.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.pool = (threads > 1)
+            .then(|| super::thread_pool("link", threads))
+            .flatten();
+        self
+    }
+
     /// Pre-flight check: scan all destinations for conflicts without creating any symlinks.
     /// Returns Ok(()) if no conflicts, or Err(LinkConflict) with all conflicts collected.
     pub fn check_conflicts(&self, keg_path: &Path) -> Result<(), Error> {
@@ -147,7 +280,43 @@ impl Linker {
         if conflicts.is_empty() {
             Ok(())
         } else {
-            Err(Error::LinkConflict { conflicts })
+            Err(Error::LinkConflict {
+                formula: None,
+                conflicts,
+            })
+        }
+    }
+
+    /// Every prefix path linking `keg_path` would put a symlink at, found
+    /// without touching the prefix.
+    pub fn link_destinations(&self, keg_path: &Path) -> Vec<PathBuf> {
+        let mut destinations = Vec::new();
+        for dir_name in LINK_DIRS {
+            Self::collect_destinations(
+                &keg_path.join(dir_name),
+                &self.prefix.join(dir_name),
+                &mut destinations,
+            );
+        }
+        destinations
+    }
+
+    fn collect_destinations(src: &Path, dst: &Path, destinations: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(src) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            if should_skip_link_entry(src, &file_name) {
+                continue;
+            }
+            let src_path = entry.path();
+            let dst_path = dst.join(&file_name);
+            if src_path.is_dir() {
+                Self::collect_destinations(&src_path, &dst_path, destinations);
+            } else {
+                destinations.push(dst_path);
+            }
         }
     }
 
@@ -187,15 +356,8 @@ impl Linker {
             }
 
             if dst_path.symlink_metadata().is_ok() {
-                if let Ok(target) = fs::read_link(&dst_path) {
-                    let resolved = if target.is_relative() {
-                        dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                    } else {
-                        target
-                    };
-                    if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
-                        continue;
-                    }
+                if placed_for(&dst_path, &src_path).is_some() {
+                    continue;
                 }
                 conflicts.push(ConflictedLink {
                     path: dst_path.clone(),
@@ -254,20 +416,69 @@ impl Linker {
     }
 
     pub fn link_keg(&self, keg_path: &Path) -> Result<Vec<LinkedFile>, Error> {
-        self.check_conflicts(keg_path)?;
+        self.link_keg_renaming(keg_path, &HashMap::new(), LinkMode::Symlink)
+    }
+
+    /// Like `link_keg`, but a file that would be linked at one of the keys of
+    /// `renames` is linked at the mapped path instead, and executables are
+    /// placed according to `mode`. Conflicts at renamed paths are not
+    /// reported. Executables this keg already placed another way are
+    /// replaced.
+    pub fn link_keg_renaming(
+        &self,
+        keg_path: &Path,
+        renames: &HashMap<PathBuf, PathBuf>,
+        mode: LinkMode,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        if let Err(Error::LinkConflict { formula, conflicts }) = self.check_conflicts(keg_path) {
+            let conflicts: Vec<_> = conflicts
+                .into_iter()
+                .filter(|c| !renames.contains_key(&c.path))
+                .collect();
+            if !conflicts.is_empty() {
+                let e = Error::LinkConflict { formula, conflicts };
+                debug!(keg = %keg_path.display(), error = %e, "not linking keg");
+                return Err(e);
+            }
+        }
         self.link_opt(keg_path)?;
-        let mut linked = Vec::new();
-        for dir_name in LINK_DIRS {
+        // The directories link into disjoint parts of the prefix.
+        let link_dir = |dir_name: &&str| {
             let src_dir = keg_path.join(dir_name);
             let dst_dir = self.prefix.join(dir_name);
+            let mode = if *dir_name == "bin" {
+                mode
+            } else {
+                LinkMode::Symlink
+            };
             if src_dir.exists() {
-                linked.extend(Self::link_recursive(&src_dir, &dst_dir)?);
+                Self::link_recursive(&src_dir, &dst_dir, renames, mode)
+            } else {
+                Ok(Vec::new())
             }
-        }
+        };
+        let per_dir: Vec<Vec<LinkedFile>> = match &self.pool {
+            Some(pool) => {
+                pool.install(|| LINK_DIRS.par_iter().map(link_dir).collect::<Result<_, _>>())?
+            }
+            None => LINK_DIRS.iter().map(link_dir).collect::<Result<_, _>>()?,
+        };
+        let linked: Vec<LinkedFile> = per_dir.into_iter().flatten().collect();
+        debug!(
+            keg = %keg_path.display(),
+            files = linked.len(),
+            prefix = %self.prefix.display(),
+            "linked keg"
+        );
         Ok(linked)
     }
 
-    fn link_recursive(src: &Path, dst: &Path) -> Result<Vec<LinkedFile>, Error> {
+    fn link_recursive(
+        src: &Path,
+        dst: &Path,
+        renames: &HashMap<PathBuf, PathBuf>,
+        mode: LinkMode,
+    ) -> Result<Vec<LinkedFile>, Error> {
         let mut linked = Vec::new();
         if !dst.exists() {
             fs::create_dir_all(dst).map_err(Error::store("failed to create directory"))?;
@@ -291,47 +502,40 @@ impl Linker {
                     let old_target = fs::read_link(&dst_path)
                         .map_err(Error::store("failed to read symlink target"))?;
                     let _ = fs::remove_file(&dst_path);
-                    Self::link_recursive(&old_target, &dst_path)?;
+                    Self::link_recursive(&old_target, &dst_path, &HashMap::new(), mode)?;
                 }
-                linked.extend(Self::link_recursive(&src_path, &dst_path)?);
+                linked.extend(Self::link_recursive(&src_path, &dst_path, renames, mode)?);
                 continue;
             }
+            let dst_path = renames.get(&dst_path).cloned().unwrap_or(dst_path);
 
             if dst_path.symlink_metadata().is_ok() {
-                if let Ok(target) = fs::read_link(&dst_path) {
-                    let resolved = if target.is_relative() {
-                        dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                    } else {
-                        target
-                    };
-                    if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
-                        if resolved.exists() {
-                            linked.push(LinkedFile {
-                                link_path: dst_path,
-                                target_path: src_path,
-                            });
-                            continue;
-                        } else {
-                            let _ = fs::remove_file(&dst_path);
-                        }
-                    } else {
+                match placed_for(&dst_path, &src_path) {
+                    Some(placed) if placed == mode => {
+                        linked.push(LinkedFile {
+                            link_path: dst_path,
+                            target_path: src_path,
+                            mode,
+                        });
+                        continue;
+                    }
+                    Some(_) => {
+                        fs::remove_file(&dst_path)
+                            .map_err(Error::store("failed to replace link"))?;
+                    }
+                    None => {
                         return Err(Error::LinkConflict {
+                            formula: None,
                             conflicts: vec![ConflictedLink {
                                 path: dst_path.clone(),
                                 owned_by: keg_name_from_symlink(&dst_path),
                             }],
                         });
                     }
-                } else {
-                    return Err(Error::LinkConflict {
-                        conflicts: vec![ConflictedLink {
-                            path: dst_path,
-                            owned_by: None,
-                        }],
-                    });
                 }
             } else if dst_path.exists() {
                 return Err(Error::LinkConflict {
+                    formula: None,
                     conflicts: vec![ConflictedLink {
                         path: dst_path,
                         owned_by: None,
@@ -339,12 +543,11 @@ impl Linker {
                 });
             }
 
-            #[cfg(unix)]
-            std::os::unix::fs::symlink(&src_path, &dst_path)
-                .map_err(Error::store("failed to create symlink"))?;
+            place(&src_path, &dst_path, mode)?;
             linked.push(LinkedFile {
                 link_path: dst_path,
                 target_path: src_path,
+                mode,
             });
         }
         Ok(linked)
@@ -395,16 +598,9 @@ impl Linker {
                 continue;
             }
 
-            if let Ok(target) = fs::read_link(&dst_path) {
-                let resolved = if target.is_relative() {
-                    dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                } else {
-                    target
-                };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
-                    let _ = fs::remove_file(&dst_path);
-                    unlinked.push(dst_path);
-                }
+            if placed_for(&dst_path, &src_path).is_some() {
+                let _ = fs::remove_file(&dst_path);
+                unlinked.push(dst_path);
             }
         }
         Ok(unlinked)
@@ -430,18 +626,12 @@ impl Linker {
                 continue;
             }
 
-            if let Ok(target) = fs::read_link(&dst_path) {
-                let resolved = if target.is_relative() {
-                    dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                } else {
-                    target
-                };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
-                    linked.push(LinkedFile {
-                        link_path: dst_path,
-                        target_path: src_path,
-                    });
-                }
+            if let Some(mode) = placed_for(&dst_path, &src_path) {
+                linked.push(LinkedFile {
+                    link_path: dst_path,
+                    target_path: src_path,
+                    mode,
+                });
             }
         }
         Ok(linked)
@@ -496,6 +686,53 @@ impl Linker {
         Ok(())
     }
 
+    /// Remove `link`, recorded as placed by `mode` for the keg file
+    /// `target`, if it is still what was placed. Once the keg file is gone a
+    /// hard link can only be told apart by being a regular file, so the
+    /// record is trusted. Returns whether it was removed.
+    pub fn remove_recorded(&self, link: &Path, target: &Path, mode: LinkMode) -> bool {
+        self.is_recorded(link, target, mode) && fs::remove_file(link).is_ok()
+    }
+
+    /// Whether `link` is still what `mode` placed for the keg file `target`.
+    pub fn is_recorded(&self, link: &Path, target: &Path, mode: LinkMode) -> bool {
+        match mode {
+            LinkMode::Symlink => fs::read_link(link).is_ok_and(|t| t == target),
+            LinkMode::Hardlink if target.exists() => {
+                placed_for(link, target) == Some(LinkMode::Hardlink)
+            }
+            LinkMode::Hardlink => link.symlink_metadata().is_ok_and(|m| m.is_file()),
+            LinkMode::Wrapper => is_wrapper_for(link, target),
+        }
+    }
+
+    /// Put `link` back as `mode` placed it for the keg file `target`,
+    /// replacing whatever file or symlink is there now. Directories are
+    /// never replaced.
+    pub fn restore_recorded(
+        &self,
+        link: &Path,
+        target: &Path,
+        mode: LinkMode,
+    ) -> Result<(), Error> {
+        if let Ok(metadata) = link.symlink_metadata() {
+            if metadata.is_dir() {
+                return Err(Error::LinkConflict {
+                    formula: None,
+                    conflicts: vec![ConflictedLink {
+                        path: link.to_path_buf(),
+                        owned_by: None,
+                    }],
+                });
+            }
+            fs::remove_file(link).map_err(Error::store("failed to replace link"))?;
+        }
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent).map_err(Error::store("failed to create directory"))?;
+        }
+        place(target, link, mode)
+    }
+
     pub fn is_linked(&self, keg_path: &Path) -> bool {
         let keg_bin = keg_path.join("bin");
         if !keg_bin.exists() {
@@ -504,15 +741,8 @@ impl Linker {
         if let Ok(entries) = fs::read_dir(&keg_bin) {
             for entry in entries.flatten() {
                 let dst_path = self.bin_dir.join(entry.file_name());
-                if let Ok(target) = fs::read_link(&dst_path) {
-                    let resolved = if target.is_relative() {
-                        dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                    } else {
-                        target
-                    };
-                    if fs::canonicalize(&resolved).ok() == fs::canonicalize(entry.path()).ok() {
-                        return true;
-                    }
+                if placed_for(&dst_path, &entry.path()).is_some() {
+                    return true;
                 }
             }
         }
@@ -545,6 +775,91 @@ mod tests {
         assert!(tmp.path().join("bin/foo").exists());
     }
 
+    #[test]
+    fn links_directories_in_parallel() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let keg = setup_keg(&tmp, "foo");
+        for dir in ["lib", "share/doc", "include"] {
+            fs::create_dir_all(keg.join(dir)).unwrap();
+            fs::write(keg.join(dir).join("foo.txt"), dir).unwrap();
+        }
+        let linker = Linker::new(&prefix).unwrap().with_threads(4);
+
+        let linked: Vec<_> = linker
+            .link_keg(&keg)
+            .unwrap()
+            .into_iter()
+            .map(|l| l.link_path.strip_prefix(&prefix).unwrap().to_path_buf())
+            .collect();
+        // Same order as linking the directories one after another.
+        assert_eq!(
+            linked,
+            [
+                "bin/foo",
+                "lib/foo.txt",
+                "include/foo.txt",
+                "share/doc/foo.txt"
+            ]
+            .map(PathBuf::from)
+        );
+        assert!(prefix.join("share/doc/foo.txt").is_symlink());
+    }
+
+    #[test]
+    fn executables_link_by_mode() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let keg = setup_keg(&tmp, "foo");
+        let exe = keg.join("bin/foo");
+        fs::write(&exe, "#!/bin/sh\necho \"$0\"\n").unwrap();
+        fs::create_dir_all(keg.join("share")).unwrap();
+        fs::write(keg.join("share/foo.txt"), "doc").unwrap();
+        let linker = Linker::new(&prefix).unwrap();
+        let link = prefix.join("bin/foo");
+        let no_renames = HashMap::new();
+
+        let linked = linker
+            .link_keg_renaming(&keg, &no_renames, LinkMode::Hardlink)
+            .unwrap();
+        assert!(!link.is_symlink());
+        assert_eq!(
+            fs::metadata(&link).unwrap().ino(),
+            fs::metadata(&exe).unwrap().ino()
+        );
+        assert!(prefix.join("share/foo.txt").is_symlink());
+        let modes: Vec<_> = linked
+            .iter()
+            .map(|l| (l.link_path.clone(), l.mode))
+            .collect();
+        assert!(modes.contains(&(link.clone(), LinkMode::Hardlink)));
+        assert!(linker.is_linked(&keg));
+
+        // Relinking by another mode replaces the executable's link.
+        linker
+            .link_keg_renaming(&keg, &no_renames, LinkMode::Wrapper)
+            .unwrap();
+        let output = std::process::Command::new(&link).output().unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap().trim(),
+            exe.to_str().unwrap()
+        );
+        assert!(linker.check_conflicts(&keg).is_ok());
+        assert_eq!(linker.collect_linked_files(&keg).unwrap().len(), 2);
+
+        let unlinked = linker.unlink_keg(&keg).unwrap();
+        assert_eq!(unlinked.len(), 2);
+        assert!(link.symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn link_modes_parse() {
+        for mode in [LinkMode::Symlink, LinkMode::Hardlink, LinkMode::Wrapper] {
+            assert_eq!(mode.as_str().parse::<LinkMode>().unwrap(), mode);
+        }
+        assert!("copy".parse::<LinkMode>().is_err());
+    }
+
     #[test]
     fn merging_directories_works() {
         let tmp = TempDir::new().unwrap();
@@ -779,6 +1094,27 @@ mod tests {
         assert!(!prefix.join("libexec/bin/pygmentize").exists());
     }
 
+    #[test]
+    fn link_destinations_match_the_links_created() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        fs::create_dir_all(keg.join("share/man/man1")).unwrap();
+        fs::write(keg.join("share/man/man1/foo.1"), b"").unwrap();
+        let linker = Linker::new(&tmp.path().join("prefix")).unwrap();
+
+        let mut destinations = linker.link_destinations(&keg);
+        destinations.sort();
+        let mut linked: Vec<PathBuf> = linker
+            .link_keg(&keg)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.link_path)
+            .collect();
+        linked.sort();
+        assert_eq!(destinations, linked);
+        assert_eq!(destinations.len(), 2);
+    }
+
     #[test]
     fn check_conflicts_passes_when_clean() {
         let tmp = TempDir::new().unwrap();
@@ -805,7 +1141,7 @@ mod tests {
 
         let result = linker.check_conflicts(&keg2);
         assert!(result.is_err());
-        if let Err(Error::LinkConflict { conflicts }) = result {
+        if let Err(Error::LinkConflict { conflicts, .. }) = result {
             assert_eq!(conflicts.len(), 1);
             assert!(conflicts[0].path.ends_with("bin/pkg1"));
             assert_eq!(conflicts[0].owned_by.as_deref(), Some("pkg1"));
@@ -835,7 +1171,7 @@ mod tests {
 
         let result = linker.check_conflicts(&keg2);
         assert!(result.is_err());
-        if let Err(Error::LinkConflict { conflicts }) = result {
+        if let Err(Error::LinkConflict { conflicts, .. }) = result {
             assert_eq!(conflicts.len(), 2);
         }
     }
@@ -1,8 +1,15 @@
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rayon::ThreadPool;
+use tracing::debug;
 use zb_core::Error;
 
+use super::relocation::{KegSnapshot, RelocationCache};
+
 #[cfg(target_os = "linux")]
 use crate::extraction::patch::linux::patch_placeholders;
 
@@ -16,8 +23,13 @@ pub enum CopyStrategy {
     Copy,
 }
 
+#[derive(Clone)]
 pub struct Cellar {
     cellar_dir: PathBuf,
+    prefix: Option<PathBuf>,
+    relocations: Option<RelocationCache>,
+    /// Patches binaries during relocation; rayon's global pool if unset.
+    relocation_pool: Option<Arc<ThreadPool>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -34,7 +46,67 @@ impl Cellar {
 
     pub fn new_at(cellar_dir: PathBuf) -> io::Result<Self> {
         fs::create_dir_all(&cellar_dir)?;
-        Ok(Self { cellar_dir })
+        Ok(Self {
+            cellar_dir,
+            prefix: None,
+            relocations: None,
+            relocation_pool: None,
+        })
+    }
+
+    /// Relocate kegs for `prefix` rather than the cellar's parent
+    /// directory, which is only the prefix for the default layout.
+    pub fn with_prefix(mut self, prefix: PathBuf) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+
+    /// The prefix `@@HOMEBREW_PREFIX@@` placeholders are relocated to.
+    pub fn prefix(&self) -> Result<&Path, Error> {
+        self.prefix
+            .as_deref()
+            .or_else(|| self.cellar_dir.parent())
+            .ok_or_else(|| Error::StoreCorruption {
+                message: format!(
+                    "Invalid cellar directory (no parent): {}",
+                    self.cellar_dir.display()
+                ),
+            })
+    }
+
+    /// Replay relocations recorded in `cache` instead of patching kegs
+    /// materialized from a store entry this cellar has relocated before.
+    pub fn with_relocation_cache(mut self, cache: RelocationCache) -> Self {
+        self.relocations = Some(cache);
+        self
+    }
+
+    /// Patch binaries on `threads` threads of their own when relocating.
+    pub fn with_relocation_threads(mut self, threads: usize) -> Self {
+        self.relocation_pool = super::thread_pool("relocate", threads);
+        self
+    }
+
+    pub fn relocation_cache(&self) -> Option<&RelocationCache> {
+        self.relocations.as_ref()
+    }
+
+    /// The sibling cellar holding kegs for a foreign CPU architecture, e.g.
+    /// `Cellar-x86_64` next to `Cellar`. Created on first materialize.
+    pub fn for_arch(&self, arch: &str) -> Cellar {
+        let mut dir_name = self.cellar_dir.file_name().unwrap_or_default().to_owned();
+        dir_name.push(format!("-{arch}"));
+        Cellar {
+            cellar_dir: self.cellar_dir.with_file_name(dir_name),
+            prefix: self.prefix.clone(),
+            relocations: self.relocations.clone(),
+            relocation_pool: self.relocation_pool.clone(),
+        }
+    }
+
+    /// The directory kegs are materialized into.
+    pub fn dir(&self) -> &Path {
+        &self.cellar_dir
     }
 
     pub fn keg_path(&self, name: &str, version: &str) -> PathBuf {
@@ -42,7 +114,47 @@ impl Cellar {
     }
 
     pub fn has_keg(&self, name: &str, version: &str) -> bool {
-        self.keg_path(name, version).exists()
+        self.keg_path(name, version).exists() && !self.incomplete_marker(name, version).exists()
+    }
+
+    /// Present while a keg is being copied and relocated, so a keg left
+    /// behind by an interrupted install is not mistaken for a complete one.
+    fn incomplete_marker(&self, name: &str, version: &str) -> PathBuf {
+        self.cellar_dir
+            .join(name)
+            .join(format!(".{version}{INCOMPLETE_SUFFIX}"))
+    }
+
+    /// Remove kegs an interrupted install left half-materialized, returning
+    /// them. Only call this while holding the install lock.
+    pub fn remove_incomplete_kegs(&self) -> Result<Vec<MaterializedKeg>, Error> {
+        let mut removed = Vec::new();
+        let Ok(name_entries) = fs::read_dir(&self.cellar_dir) else {
+            return Ok(removed);
+        };
+        for name_entry in name_entries.flatten() {
+            let Ok(entries) = fs::read_dir(name_entry.path()) else {
+                continue;
+            };
+            let name = name_entry.file_name().to_string_lossy().into_owned();
+            for entry in entries.flatten() {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let Some(version) = file_name
+                    .strip_prefix('.')
+                    .and_then(|rest| rest.strip_suffix(INCOMPLETE_SUFFIX))
+                else {
+                    continue;
+                };
+                let keg_path = self.keg_path(&name, version);
+                remove_incomplete_keg(&keg_path, &entry.path())?;
+                removed.push(MaterializedKeg {
+                    name: name.clone(),
+                    version: version.to_string(),
+                    path: keg_path,
+                });
+            }
+        }
+        Ok(removed)
     }
 
     pub fn list_kegs(&self) -> Result<Vec<MaterializedKeg>, Error> {
@@ -78,6 +190,9 @@ impl Cellar {
                 let Some(version) = version_entry.file_name().to_str().map(str::to_owned) else {
                     continue;
                 };
+                if self.incomplete_marker(&name, &version).exists() {
+                    continue;
+                }
 
                 kegs.push(MaterializedKeg {
                     name: name.clone(),
@@ -90,16 +205,37 @@ impl Cellar {
         Ok(kegs)
     }
 
+    /// Copy the keg for `name` out of `store_entry` into the cellar,
+    /// patching Homebrew placeholders unless `relocate` is false, as for
+    /// `:any_skip_relocation` bottles.
     pub fn materialize(
         &self,
         name: &str,
         version: &str,
         store_entry: &Path,
+        relocate: bool,
     ) -> Result<PathBuf, Error> {
+        self.materialize_timed(name, version, store_entry, relocate)
+            .map(|(keg_path, _)| keg_path)
+    }
+
+    /// Like [`Cellar::materialize`], also returning how much of the time
+    /// went into relocation.
+    pub fn materialize_timed(
+        &self,
+        name: &str,
+        version: &str,
+        store_entry: &Path,
+        relocate: bool,
+    ) -> Result<(PathBuf, Duration), Error> {
         let keg_path = self.keg_path(name, version);
+        let marker = self.incomplete_marker(name, version);
 
-        if keg_path.exists() {
-            return Ok(keg_path);
+        if marker.exists() {
+            debug!(keg = %keg_path.display(), "redoing keg left incomplete by an interrupted install");
+            remove_incomplete_keg(&keg_path, &marker)?;
+        } else if keg_path.exists() {
+            return Ok((keg_path, Duration::ZERO));
         }
 
         // Create parent directory for the keg
@@ -108,38 +244,90 @@ impl Cellar {
                 .map_err(Error::store("failed to create keg parent directory"))?;
         }
 
+        let guard = IncompleteKeg::begin(keg_path.clone(), marker)?;
+        let relocation = self.fill_keg(name, version, store_entry, relocate, &keg_path)?;
+        guard.finish()?;
+        Ok((keg_path, relocation))
+    }
+
+    /// Copy the keg out of `store_entry` into `keg_path` and relocate it,
+    /// returning how long relocation took.
+    fn fill_keg(
+        &self,
+        name: &str,
+        version: &str,
+        store_entry: &Path,
+        relocate: bool,
+        keg_path: &Path,
+    ) -> Result<Duration, Error> {
         // Homebrew bottles have structure {name}/{version}/ inside
         // Find the source directory to copy from
         let src_path = find_bottle_content(store_entry, name, version)?;
 
         // Copy the content to the cellar using best available strategy
-        copy_dir_with_fallback(&src_path, &keg_path)?;
+        copy_dir_with_fallback(&src_path, keg_path)?;
+
+        if !relocate {
+            debug!(keg = %keg_path.display(), "bottle needs no relocation");
+            return Ok(Duration::ZERO);
+        }
+
+        let started = Instant::now();
+        // The store entry directory is named after its store key.
+        let store_key = store_entry.file_name().and_then(|key| key.to_str());
+        let (Some(cache), Some(store_key)) = (&self.relocations, store_key) else {
+            self.relocate(name, version, keg_path)?;
+            return Ok(started.elapsed());
+        };
+
+        let prefix = self.prefix()?;
+        if cache.replay(store_key, &self.cellar_dir, prefix, name, version, keg_path)? {
+            debug!(keg = %keg_path.display(), "replayed cached relocation");
+            return Ok(started.elapsed());
+        }
+
+        let before = KegSnapshot::take(keg_path)?;
+        self.relocate(name, version, keg_path)?;
+        if let Err(e) = cache.record(
+            store_key,
+            &self.cellar_dir,
+            prefix,
+            name,
+            version,
+            keg_path,
+            &before,
+        ) {
+            debug!(keg = %keg_path.display(), error = %e, "failed to cache relocation");
+        }
+
+        Ok(started.elapsed())
+    }
+
+    fn relocate(&self, name: &str, version: &str, keg_path: &Path) -> Result<(), Error> {
+        match &self.relocation_pool {
+            Some(pool) => pool.install(|| self.relocate_keg(name, version, keg_path)),
+            None => self.relocate_keg(name, version, keg_path),
+        }
+    }
+
+    fn relocate_keg(&self, name: &str, version: &str, keg_path: &Path) -> Result<(), Error> {
+        debug!(keg = %keg_path.display(), "relocating Homebrew placeholders");
+
+        let prefix = self.prefix()?;
 
         // Patch Homebrew placeholders in Mach-O binaries
         #[cfg(target_os = "macos")]
-        patch_homebrew_placeholders(&keg_path, &self.cellar_dir, name, version)?;
+        patch_homebrew_placeholders(keg_path, &self.cellar_dir, prefix, name, version)?;
 
         // Patch Homebrew placeholders in ELF binaries
         #[cfg(target_os = "linux")]
-        {
-            // Derive prefix from cellar_dir directly without hardcoded fallback
-            let prefix = self
-                .cellar_dir
-                .parent()
-                .ok_or_else(|| Error::StoreCorruption {
-                    message: format!(
-                        "Invalid cellar directory (no parent): {}",
-                        self.cellar_dir.display()
-                    ),
-                })?;
-            patch_placeholders(&keg_path, prefix, name, version)?;
-        }
+        patch_placeholders(keg_path, prefix, name, version)?;
 
         // Strip quarantine xattrs and ad-hoc sign Mach-O binaries
         #[cfg(target_os = "macos")]
-        codesign_and_strip_xattrs(&keg_path)?;
+        codesign_and_strip_xattrs(keg_path)?;
 
-        Ok(keg_path)
+        Ok(())
     }
 
     pub fn remove_keg(&self, name: &str, version: &str) -> Result<(), Error> {
@@ -158,13 +346,89 @@ impl Cellar {
 
         Ok(())
     }
+
+    /// Apparent size in bytes of a keg, counting hardlinked files once.
+    pub fn keg_size(&self, name: &str, version: &str) -> io::Result<u64> {
+        disk_usage(&self.keg_path(name, version))
+    }
+
+    /// Bytes of a keg that are the files of `store_entry` themselves,
+    /// hardlinked or cloned rather than copied, so take no space of their
+    /// own. Files rewritten by relocation no longer count.
+    pub fn keg_shared_size(
+        &self,
+        name: &str,
+        version: &str,
+        store_entry: &Path,
+    ) -> Result<u64, Error> {
+        let content = find_bottle_content(store_entry, name, version)?;
+        shared_usage(&self.keg_path(name, version), &content)
+            .map_err(Error::store("failed to measure keg"))
+    }
+}
+
+pub(crate) fn disk_usage(path: &Path) -> io::Result<u64> {
+    use std::collections::HashSet;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut seen = HashSet::new();
+    let mut total = 0;
+    for entry in walkdir::WalkDir::new(path) {
+        let entry = entry.map_err(io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let metadata = entry.metadata().map_err(io::Error::other)?;
+        if seen.insert((metadata.dev(), metadata.ino())) {
+            total += metadata.len();
+        }
+    }
+    Ok(total)
+}
+
+/// Bytes of the files under `keg` that are the file at the same path under
+/// `content`: the same inode for a hardlink, or on macOS, where
+/// `clonefile` keeps timestamps, the same size and modification time for a
+/// clone.
+fn shared_usage(keg: &Path, content: &Path) -> io::Result<u64> {
+    use std::collections::HashSet;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut seen = HashSet::new();
+    let mut total = 0;
+    for entry in walkdir::WalkDir::new(keg) {
+        let entry = entry.map_err(io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(keg) else {
+            continue;
+        };
+        let Ok(source) = fs::symlink_metadata(content.join(relative)) else {
+            continue;
+        };
+        let metadata = entry.metadata().map_err(io::Error::other)?;
+        let hardlinked = (metadata.dev(), metadata.ino()) == (source.dev(), source.ino());
+        let cloned = cfg!(target_os = "macos")
+            && source.is_file()
+            && metadata.len() == source.len()
+            && metadata.modified().ok() == source.modified().ok();
+        if (hardlinked || cloned) && seen.insert((metadata.dev(), metadata.ino())) {
+            total += metadata.len();
+        }
+    }
+    Ok(total)
 }
 
 /// Find the bottle content directory inside a store entry.
 /// Homebrew bottles have structure {name}/{version}/ inside the tarball.
 /// This function finds that directory, falling back to the store_entry root
 /// if the expected structure isn't found.
-fn find_bottle_content(store_entry: &Path, name: &str, version: &str) -> Result<PathBuf, Error> {
+pub(crate) fn find_bottle_content(
+    store_entry: &Path,
+    name: &str,
+    version: &str,
+) -> Result<PathBuf, Error> {
     // Try the expected Homebrew structure: {name}/{version}/
     let expected_path = store_entry.join(name).join(version);
     if expected_path.exists() && expected_path.is_dir() {
@@ -191,7 +455,57 @@ fn find_bottle_content(store_entry: &Path, name: &str, version: &str) -> Result<
     Ok(store_entry.to_path_buf())
 }
 
+/// Suffix of the marker kept next to a keg while it is materialized.
+const INCOMPLETE_SUFFIX: &str = ".zb-incomplete";
+
+/// Marks a keg as incomplete until [`IncompleteKeg::finish`]. Dropped
+/// unfinished, as when materializing fails, it removes the partial keg; a
+/// process killed part way leaves the marker for the next install to clean.
+struct IncompleteKeg {
+    keg_path: PathBuf,
+    marker: PathBuf,
+    finished: bool,
+}
+
+impl IncompleteKeg {
+    fn begin(keg_path: PathBuf, marker: PathBuf) -> Result<Self, Error> {
+        fs::write(&marker, b"").map_err(Error::store("failed to mark keg as incomplete"))?;
+        Ok(Self {
+            keg_path,
+            marker,
+            finished: false,
+        })
+    }
+
+    fn finish(mut self) -> Result<(), Error> {
+        self.finished = true;
+        fs::remove_file(&self.marker).map_err(Error::store("failed to mark keg as complete"))
+    }
+}
+
+impl Drop for IncompleteKeg {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = remove_incomplete_keg(&self.keg_path, &self.marker);
+        }
+    }
+}
+
+fn remove_incomplete_keg(keg_path: &Path, marker: &Path) -> Result<(), Error> {
+    if keg_path.exists() {
+        fs::remove_dir_all(keg_path).map_err(Error::store("failed to remove incomplete keg"))?;
+    }
+    fs::remove_file(marker).map_err(Error::store("failed to remove incomplete keg marker"))
+}
+
 fn copy_dir_with_fallback(src: &Path, dst: &Path) -> Result<(), Error> {
+    // Clones and hardlinks cannot cross filesystems, as when the store and
+    // the cellar live on separate volumes; don't attempt one per file.
+    if !same_filesystem(src, dst.parent().unwrap_or(dst)) {
+        debug!(keg = %dst.display(), "store is on another filesystem; copying");
+        return copy_dir_recursive(src, dst, false);
+    }
+
     // Try clonefile first (APFS), then hardlink, then copy
     #[cfg(target_os = "macos")]
     {
@@ -204,6 +518,17 @@ fn copy_dir_with_fallback(src: &Path, dst: &Path) -> Result<(), Error> {
     copy_dir_recursive(src, dst, true)
 }
 
+/// Whether `a` and `b` are on the same device. Unknown counts as the same,
+/// leaving the per-file fallbacks to sort it out.
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true,
+    }
+}
+
 #[cfg(target_os = "macos")]
 fn try_clonefile_dir(src: &Path, dst: &Path) -> io::Result<()> {
     use std::ffi::CString;
@@ -321,7 +646,9 @@ mod tests {
         let store_entry = setup_store_entry(&tmp);
 
         let cellar = Cellar::new(tmp.path()).unwrap();
-        let keg_path = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+        let keg_path = cellar
+            .materialize("foo", "1.2.3", &store_entry, true)
+            .unwrap();
 
         // Check directory structure exists
         assert!(keg_path.exists());
@@ -367,26 +694,165 @@ mod tests {
         let cellar = Cellar::new(tmp.path()).unwrap();
 
         // First materialize
-        let keg_path1 = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+        let keg_path1 = cellar
+            .materialize("foo", "1.2.3", &store_entry, true)
+            .unwrap();
 
         // Add a marker file
         fs::write(keg_path1.join("marker.txt"), b"original").unwrap();
 
         // Second materialize should be no-op
-        let keg_path2 = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+        let keg_path2 = cellar
+            .materialize("foo", "1.2.3", &store_entry, true)
+            .unwrap();
         assert_eq!(keg_path1, keg_path2);
 
         // Marker should still exist
         assert!(keg_path2.join("marker.txt").exists());
     }
 
+    #[test]
+    fn interrupted_kegs_are_redone_or_removed() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+        let cellar = Cellar::new(tmp.path()).unwrap();
+
+        // A process killed mid-copy leaves the keg half-written and marked.
+        let keg_path = cellar.keg_path("foo", "1.2.3");
+        fs::create_dir_all(keg_path.join("bin")).unwrap();
+        fs::write(keg_path.join("stale.txt"), b"partial").unwrap();
+        fs::write(cellar.incomplete_marker("foo", "1.2.3"), b"").unwrap();
+        assert!(!cellar.has_keg("foo", "1.2.3"));
+        assert!(cellar.list_kegs().unwrap().is_empty());
+
+        cellar
+            .materialize("foo", "1.2.3", &store_entry, true)
+            .unwrap();
+        assert!(cellar.has_keg("foo", "1.2.3"));
+        assert!(!keg_path.join("stale.txt").exists());
+        assert!(keg_path.join("bin/foo").exists());
+
+        let bar = cellar.keg_path("bar", "2.0");
+        fs::create_dir_all(&bar).unwrap();
+        fs::write(cellar.incomplete_marker("bar", "2.0"), b"").unwrap();
+        let removed = cellar.remove_incomplete_kegs().unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].name, "bar");
+        assert!(!bar.exists());
+        assert!(!cellar.incomplete_marker("bar", "2.0").exists());
+        assert!(cellar.has_keg("foo", "1.2.3"));
+
+        // A failed materialize does not leave a keg behind.
+        assert!(
+            cellar
+                .materialize("baz", "1.0", &tmp.path().join("store/missing"), true)
+                .is_err()
+        );
+        assert!(!cellar.keg_path("baz", "1.0").exists());
+        assert!(!cellar.incomplete_marker("baz", "1.0").exists());
+    }
+
+    #[test]
+    fn rematerialize_replays_cached_relocation() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = tmp.path().join("store/abc123");
+        fs::create_dir_all(store_entry.join("bin")).unwrap();
+        fs::write(
+            store_entry.join("bin/foo-config"),
+            "prefix=@@HOMEBREW_PREFIX@@\n",
+        )
+        .unwrap();
+
+        let cellar = Cellar::new(tmp.path())
+            .unwrap()
+            .with_relocation_cache(RelocationCache::new(tmp.path().join("relocations")));
+        let keg_path = cellar
+            .materialize("foo", "1.2.3", &store_entry, true)
+            .unwrap();
+        let relocated = fs::read_to_string(keg_path.join("bin/foo-config")).unwrap();
+        assert!(!relocated.contains("@@HOMEBREW_PREFIX@@"));
+        cellar.remove_keg("foo", "1.2.3").unwrap();
+
+        // A fresh relocation would keep the extra line; the replay does not.
+        fs::remove_file(store_entry.join("bin/foo-config")).unwrap();
+        fs::write(
+            store_entry.join("bin/foo-config"),
+            "prefix=@@HOMEBREW_PREFIX@@\nextra\n",
+        )
+        .unwrap();
+        let keg_path = cellar
+            .materialize("foo", "1.2.3", &store_entry, true)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(keg_path.join("bin/foo-config")).unwrap(),
+            relocated
+        );
+    }
+
+    #[test]
+    fn relocation_uses_the_configured_prefix() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = tmp.path().join("store/abc123");
+        fs::create_dir_all(store_entry.join("bin")).unwrap();
+        fs::write(
+            store_entry.join("bin/foo-config"),
+            "prefix=@@HOMEBREW_PREFIX@@\n",
+        )
+        .unwrap();
+        let prefix = tmp.path().join("home/.zerobrew");
+        let cellar = Cellar::new_at(tmp.path().join("kegs"))
+            .unwrap()
+            .with_prefix(prefix.clone());
+
+        let keg_path = cellar
+            .materialize("foo", "1.2.3", &store_entry, true)
+            .unwrap();
+
+        assert_eq!(cellar.prefix().unwrap(), prefix);
+        assert_eq!(
+            fs::read_to_string(keg_path.join("bin/foo-config")).unwrap(),
+            format!("prefix={}\n", prefix.display())
+        );
+        // The keg file started as a hardlink; the store's copy is untouched.
+        assert_eq!(
+            fs::read_to_string(store_entry.join("bin/foo-config")).unwrap(),
+            "prefix=@@HOMEBREW_PREFIX@@\n"
+        );
+    }
+
+    #[test]
+    fn skip_relocation_copies_the_keg_untouched() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = tmp.path().join("store/abc123");
+        fs::create_dir_all(store_entry.join("bin")).unwrap();
+        fs::write(
+            store_entry.join("bin/foo-config"),
+            "prefix=@@HOMEBREW_PREFIX@@\n",
+        )
+        .unwrap();
+
+        let cellar = Cellar::new(tmp.path())
+            .unwrap()
+            .with_relocation_cache(RelocationCache::new(tmp.path().join("relocations")));
+        let keg_path = cellar
+            .materialize("foo", "1.2.3", &store_entry, false)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(keg_path.join("bin/foo-config")).unwrap(),
+            "prefix=@@HOMEBREW_PREFIX@@\n"
+        );
+        assert!(!tmp.path().join("relocations").exists());
+    }
+
     #[test]
     fn remove_keg_cleans_up() {
         let tmp = TempDir::new().unwrap();
         let store_entry = setup_store_entry(&tmp);
 
         let cellar = Cellar::new(tmp.path()).unwrap();
-        cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+        cellar
+            .materialize("foo", "1.2.3", &store_entry, true)
+            .unwrap();
 
         assert!(cellar.has_keg("foo", "1.2.3"));
 
@@ -395,6 +861,43 @@ mod tests {
         assert!(!cellar.has_keg("foo", "1.2.3"));
     }
 
+    #[test]
+    fn keg_size_counts_hardlinks_once() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let keg = cellar.keg_path("foo", "1.0.0");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/foo"), b"12345").unwrap();
+        fs::hard_link(keg.join("bin/foo"), keg.join("bin/foo-alias")).unwrap();
+        fs::write(keg.join("README"), b"abc").unwrap();
+
+        assert_eq!(cellar.keg_size("foo", "1.0.0").unwrap(), 8);
+    }
+
+    #[test]
+    fn keg_shared_size_counts_files_still_linked_to_the_store() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = tmp.path().join("store/abc");
+        let content = store_entry.join("foo/1.0.0");
+        fs::create_dir_all(content.join("bin")).unwrap();
+        fs::write(content.join("bin/foo"), b"12345").unwrap();
+        fs::write(content.join("README"), b"abc").unwrap();
+
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let keg = cellar.keg_path("foo", "1.0.0");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::hard_link(content.join("bin/foo"), keg.join("bin/foo")).unwrap();
+        // Rewritten in the keg, as relocation does.
+        fs::write(keg.join("README"), b"xyz").unwrap();
+
+        assert_eq!(
+            cellar
+                .keg_shared_size("foo", "1.0.0", &store_entry)
+                .unwrap(),
+            5
+        );
+    }
+
     #[test]
     fn keg_path_format() {
         let tmp = TempDir::new().unwrap();
@@ -404,6 +907,28 @@ mod tests {
         assert!(path.ends_with("cellar/libheif/2.0.1"));
     }
 
+    #[test]
+    fn arch_cellar_is_a_sibling_directory() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new_at(tmp.path().join("Cellar")).unwrap();
+
+        let path = cellar.for_arch("x86_64").keg_path("libheif", "2.0.1");
+        assert_eq!(path, tmp.path().join("Cellar-x86_64/libheif/2.0.1"));
+    }
+
+    #[test]
+    fn same_filesystem_compares_devices() {
+        let tmp = TempDir::new().unwrap();
+        fs::create_dir_all(tmp.path().join("a")).unwrap();
+        assert!(same_filesystem(tmp.path(), &tmp.path().join("a")));
+        assert!(same_filesystem(tmp.path(), &tmp.path().join("missing")));
+        if let Ok(proc_dir) = fs::metadata("/proc")
+            && proc_dir.is_dir()
+        {
+            assert!(!same_filesystem(tmp.path(), Path::new("/proc")));
+        }
+    }
+
     #[test]
     fn hardlink_fallback_to_copy_works() {
         // Test that copy fallback works when hardlink fails
@@ -434,7 +959,9 @@ mod tests {
         let store_entry = setup_store_entry(&tmp);
 
         let cellar = Cellar::new(tmp.path()).unwrap();
-        let keg_path = cellar.materialize("clone", "1.0.0", &store_entry).unwrap();
+        let keg_path = cellar
+            .materialize("clone", "1.0.0", &store_entry, true)
+            .unwrap();
 
         // Verify content is correct regardless of which strategy was used
         assert_eq!(
@@ -1,5 +1,27 @@
+pub mod audit;
 pub mod link;
 pub mod materialize;
+pub mod relocation;
+pub mod shebang;
 
-pub use link::{LinkedFile, Linker};
+pub use audit::{DanglingReference, RelocationAudit, audit_keg};
+pub use link::{LinkMode, LinkedFile, Linker};
 pub use materialize::{Cellar, CopyStrategy, MaterializedKeg};
+pub use relocation::RelocationCache;
+
+use std::sync::Arc;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tracing::warn;
+
+/// A pool of `threads` workers named `zb-{name}-N`. Falls back to rayon's
+/// global pool when the threads cannot be spawned.
+fn thread_pool(name: &'static str, threads: usize) -> Option<Arc<ThreadPool>> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads.max(1))
+        .thread_name(move |i| format!("zb-{name}-{i}"))
+        .build()
+        .inspect_err(|e| warn!(pool = name, error = %e, "failed to start thread pool"))
+        .ok()
+        .map(Arc::new)
+}
@@ -0,0 +1,366 @@
+//! Remembering how a store entry was relocated, so that materializing it
+//! again for the same prefix copies the patched files instead of scanning
+//! and patching every binary a second time.
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zb_core::Error;
+
+use crate::checksum::sha256_hex;
+
+/// Bump whenever relocation changes what it writes into a keg, so copies
+/// patched by an older zerobrew are not replayed.
+const RELOCATION_FORMAT: u32 = 2;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const FILES_DIR: &str = "files";
+
+/// The files relocation changed in a keg, relative to the keg.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RelocationManifest {
+    files: Vec<PatchedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PatchedFile {
+    path: PathBuf,
+    size: u64,
+}
+
+/// Identity of every regular file in a keg, taken before relocation so the
+/// files it patched can be told apart afterwards.
+pub struct KegSnapshot {
+    files: HashMap<PathBuf, FileStamp>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    inode: (u64, u64),
+    size: u64,
+    modified: (i64, i64),
+}
+
+impl KegSnapshot {
+    pub fn take(keg_path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            files: stamp_files(keg_path)?,
+        })
+    }
+}
+
+/// Patched copies of relocated files, one directory per store entry and
+/// cellar they were relocated for.
+#[derive(Clone)]
+pub struct RelocationCache {
+    dir: PathBuf,
+}
+
+impl RelocationCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Where the relocation of `store_key` as `name`/`version` into
+    /// `cellar_dir`, for kegs linked into `prefix`, is kept. The key also
+    /// covers the relocation format and, on Linux, the interpreter binaries
+    /// are pointed at, as either changes the patched files.
+    fn entry_dir(
+        &self,
+        store_key: &str,
+        cellar_dir: &Path,
+        prefix: &Path,
+        name: &str,
+        version: &str,
+    ) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(RELOCATION_FORMAT.to_le_bytes());
+        #[cfg(target_os = "linux")]
+        if let Some(interpreter) = crate::extraction::patch::linux::host_interpreter(prefix) {
+            hasher.update(interpreter.as_os_str().as_encoded_bytes());
+        }
+        hasher.update([0]);
+        hasher.update(cellar_dir.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+        hasher.update(prefix.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(version.as_bytes());
+        let target = sha256_hex(hasher);
+        self.dir.join(format!("{store_key}-{}", &target[..16]))
+    }
+
+    /// Overwrite the files a previous relocation patched in `keg_path` with
+    /// their patched copies. Returns false, leaving the keg untouched, when
+    /// there is no complete record to replay.
+    pub fn replay(
+        &self,
+        store_key: &str,
+        cellar_dir: &Path,
+        prefix: &Path,
+        name: &str,
+        version: &str,
+        keg_path: &Path,
+    ) -> Result<bool, Error> {
+        let entry = self.entry_dir(store_key, cellar_dir, prefix, name, version);
+        let Ok(contents) = fs::read(entry.join(MANIFEST_FILE)) else {
+            return Ok(false);
+        };
+        let Ok(manifest) = serde_json::from_slice::<RelocationManifest>(&contents) else {
+            return Ok(false);
+        };
+
+        let files = entry.join(FILES_DIR);
+        let complete = manifest.files.iter().all(|file| {
+            fs::symlink_metadata(files.join(&file.path))
+                .is_ok_and(|m| m.is_file() && m.len() == file.size)
+                && keg_path.join(&file.path).is_file()
+        });
+        if !complete {
+            return Ok(false);
+        }
+
+        for file in &manifest.files {
+            let target = keg_path.join(&file.path);
+            fs::remove_file(&target).map_err(Error::store("failed to replace relocated file"))?;
+            link_or_copy(&files.join(&file.path), &target)?;
+        }
+        Ok(true)
+    }
+
+    /// Keep the files relocation changed in `keg_path` since `before`, so
+    /// the next materialize of `store_key` for the same cellar and prefix
+    /// can replay them.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &self,
+        store_key: &str,
+        cellar_dir: &Path,
+        prefix: &Path,
+        name: &str,
+        version: &str,
+        keg_path: &Path,
+        before: &KegSnapshot,
+    ) -> Result<(), Error> {
+        let entry = self.entry_dir(store_key, cellar_dir, prefix, name, version);
+        if entry.join(MANIFEST_FILE).exists() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir)
+            .map_err(Error::store("failed to create relocation cache directory"))?;
+        let staging = tempfile::TempDir::new_in(&self.dir).map_err(Error::store(
+            "failed to create relocation cache staging directory",
+        ))?;
+
+        let mut manifest = RelocationManifest::default();
+        for (path, stamp) in stamp_files(keg_path)? {
+            if before.files.get(&path) == Some(&stamp) {
+                continue;
+            }
+            let cached = staging.path().join(FILES_DIR).join(&path);
+            if let Some(parent) = cached.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(Error::store("failed to create relocation cache directory"))?;
+            }
+            link_or_copy(&keg_path.join(&path), &cached)?;
+            manifest.files.push(PatchedFile {
+                path,
+                size: stamp.size,
+            });
+        }
+        manifest.files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let contents = serde_json::to_vec(&manifest).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to serialize relocation manifest: {e}"),
+        })?;
+        fs::write(staging.path().join(MANIFEST_FILE), contents)
+            .map_err(Error::store("failed to write relocation manifest"))?;
+
+        let staging = staging.keep();
+        if fs::rename(&staging, &entry).is_err() {
+            // Another process recorded the same relocation first.
+            let _ = fs::remove_dir_all(&staging);
+        }
+        Ok(())
+    }
+
+    /// Forget every relocation of `store_key`, once its store entry is gone.
+    pub fn remove(&self, store_key: &str) -> Result<(), Error> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Ok(());
+        };
+        let prefix = format!("{store_key}-");
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                fs::remove_dir_all(entry.path())
+                    .map_err(Error::store("failed to remove relocation cache entry"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn stamp_files(keg_path: &Path) -> Result<HashMap<PathBuf, FileStamp>, Error> {
+    let mut files = HashMap::new();
+    for entry in walkdir::WalkDir::new(keg_path).follow_links(false) {
+        let entry = entry.map_err(|e| Error::StoreCorruption {
+            message: format!("failed to walk keg {}: {e}", keg_path.display()),
+        })?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let metadata = entry.metadata().map_err(|e| Error::StoreCorruption {
+            message: format!("failed to read {}: {e}", entry.path().display()),
+        })?;
+        let Ok(relative) = entry.path().strip_prefix(keg_path) else {
+            continue;
+        };
+        files.insert(
+            relative.to_path_buf(),
+            FileStamp {
+                inode: (metadata.dev(), metadata.ino()),
+                size: metadata.len(),
+                modified: (metadata.mtime(), metadata.mtime_nsec()),
+            },
+        );
+    }
+    Ok(files)
+}
+
+fn link_or_copy(src: &Path, dst: &Path) -> Result<(), Error> {
+    if fs::hard_link(src, dst).is_ok() {
+        return Ok(());
+    }
+    fs::copy(src, dst).map_err(Error::store("failed to copy relocated file"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn keg(tmp: &TempDir, name: &str) -> PathBuf {
+        let keg = tmp.path().join(name);
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/tool"), "prefix=@@HOMEBREW_PREFIX@@\n").unwrap();
+        fs::write(keg.join("README"), "unchanged\n").unwrap();
+        keg
+    }
+
+    /// Patch the way relocation does: write a new file and rename it over.
+    fn relocate(keg: &Path, prefix: &str) {
+        let tmp = keg.join("bin/tool.tmp_patch");
+        fs::write(&tmp, format!("prefix={prefix}\n")).unwrap();
+        fs::rename(tmp, keg.join("bin/tool")).unwrap();
+    }
+
+    #[test]
+    fn replays_only_the_files_relocation_changed() {
+        let tmp = TempDir::new().unwrap();
+        let cache = RelocationCache::new(tmp.path().join("relocations"));
+        let cellar = Path::new("/opt/zerobrew/prefix/Cellar");
+        let prefix = Path::new("/opt/zerobrew/prefix");
+
+        let first = keg(&tmp, "first");
+        let before = KegSnapshot::take(&first).unwrap();
+        relocate(&first, "/opt/zerobrew/prefix");
+        cache
+            .record("abc", cellar, prefix, "tool", "1.0", &first, &before)
+            .unwrap();
+
+        let second = keg(&tmp, "second");
+        assert!(
+            cache
+                .replay("abc", cellar, prefix, "tool", "1.0", &second)
+                .unwrap()
+        );
+        assert_eq!(
+            fs::read_to_string(second.join("bin/tool")).unwrap(),
+            "prefix=/opt/zerobrew/prefix\n"
+        );
+        assert_eq!(
+            fs::read_to_string(second.join("README")).unwrap(),
+            "unchanged\n"
+        );
+    }
+
+    #[test]
+    fn other_cellars_prefixes_and_store_keys_miss() {
+        let tmp = TempDir::new().unwrap();
+        let cache = RelocationCache::new(tmp.path().join("relocations"));
+        let cellar = Path::new("/opt/zerobrew/prefix/Cellar");
+        let prefix = Path::new("/opt/zerobrew/prefix");
+
+        let first = keg(&tmp, "first");
+        let before = KegSnapshot::take(&first).unwrap();
+        relocate(&first, "/opt/zerobrew/prefix");
+        cache
+            .record("abc", cellar, prefix, "tool", "1.0", &first, &before)
+            .unwrap();
+
+        let second = keg(&tmp, "second");
+        let elsewhere = Path::new("/home/me/.zerobrew/Cellar");
+        assert!(
+            !cache
+                .replay("abc", elsewhere, prefix, "tool", "1.0", &second)
+                .unwrap()
+        );
+        let other_prefix = Path::new("/home/me/.zerobrew");
+        assert!(
+            !cache
+                .replay("abc", cellar, other_prefix, "tool", "1.0", &second)
+                .unwrap()
+        );
+        assert!(
+            !cache
+                .replay("def", cellar, prefix, "tool", "1.0", &second)
+                .unwrap()
+        );
+
+        cache.remove("abc").unwrap();
+        assert!(
+            !cache
+                .replay("abc", cellar, prefix, "tool", "1.0", &second)
+                .unwrap()
+        );
+        assert_eq!(
+            fs::read_to_string(second.join("bin/tool")).unwrap(),
+            "prefix=@@HOMEBREW_PREFIX@@\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn installing_zerobrews_glibc_misses() {
+        let tmp = TempDir::new().unwrap();
+        let cache = RelocationCache::new(tmp.path().join("relocations"));
+        let prefix = tmp.path().join("prefix");
+        let cellar = prefix.join("Cellar");
+
+        let first = keg(&tmp, "first");
+        let before = KegSnapshot::take(&first).unwrap();
+        relocate(&first, &prefix.to_string_lossy());
+        cache
+            .record("abc", &cellar, &prefix, "tool", "1.0", &first, &before)
+            .unwrap();
+
+        let lib = cellar.join("glibc/2.39/lib");
+        fs::create_dir_all(&lib).unwrap();
+        for name in ["ld-linux-x86-64.so.2", "ld-linux-aarch64.so.1"] {
+            fs::write(lib.join(name), "mock").unwrap();
+        }
+        let second = keg(&tmp, "second");
+        assert!(
+            !cache
+                .replay("abc", &cellar, &prefix, "tool", "1.0", &second)
+                .unwrap()
+        );
+    }
+}
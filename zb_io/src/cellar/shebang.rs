@@ -0,0 +1,389 @@
+//! Checking and repairing the `#!` interpreters of a keg's scripts, which a
+//! bottle built elsewhere may point at Homebrew's prefix, at one version of
+//! a keg, or at a directory on the build host.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+use zb_core::Error;
+
+use super::audit::DanglingReference;
+
+/// Homebrew prefixes that interpreters of bottles built elsewhere point
+/// into. `/usr/local` is left out: it is just as often the system's own.
+const HOMEBREW_PREFIXES: &[&str] = &["/opt/homebrew", "/home/linuxbrew/.linuxbrew"];
+
+/// Interpreters here belong to the operating system and are never
+/// rewritten, only reported when missing.
+const SYSTEM_BIN_DIRS: &[&str] = &["/bin/", "/usr/bin/", "/sbin/", "/usr/sbin/"];
+
+const ENV: &str = "/usr/bin/env";
+
+/// Bytes read when looking for a `#!` line. Kernels cut it at 256 bytes
+/// (127 before Linux 5.1), so anything longer is broken anyway.
+const MAX_SHEBANG_LINE: usize = 256;
+
+/// The `#!` line of a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Shebang {
+    interpreter: String,
+    /// Everything after the interpreter, which the kernel passes on as one
+    /// argument.
+    argument: Option<String>,
+}
+
+impl Shebang {
+    fn line(&self) -> String {
+        match &self.argument {
+            Some(argument) => format!("#!{} {argument}", self.interpreter),
+            None => format!("#!{}", self.interpreter),
+        }
+    }
+}
+
+/// What to do about a script's interpreter.
+#[derive(Debug, PartialEq, Eq)]
+enum Resolution {
+    Keep,
+    /// The interpreter lives at this path under the zerobrew prefix.
+    Relocate(String),
+    /// The interpreter does not exist; `fallback` finds it on `PATH`
+    /// instead, when the line can be rewritten to do so.
+    Missing {
+        fallback: Option<String>,
+    },
+}
+
+/// Point the scripts of the keg at `keg_path` at the interpreters zerobrew
+/// provides: Homebrew prefixes and versioned keg paths become stable opt
+/// paths under `prefix`, and interpreters that do not exist fall back to
+/// `/usr/bin/env <name>`.
+///
+/// Returns the scripts whose interpreter does not exist, with the formula
+/// expected to provide it where known.
+pub fn fix_shebangs(
+    keg_path: &Path,
+    prefix: &Path,
+    cellar_dir: &Path,
+) -> Result<Vec<DanglingReference>, Error> {
+    scan(keg_path, prefix, cellar_dir, true)
+}
+
+/// The scripts of the keg at `keg_path` whose interpreter does not exist.
+pub fn missing_interpreters(
+    keg_path: &Path,
+    prefix: &Path,
+    cellar_dir: &Path,
+) -> Result<Vec<DanglingReference>, Error> {
+    scan(keg_path, prefix, cellar_dir, false)
+}
+
+fn scan(
+    keg_path: &Path,
+    prefix: &Path,
+    cellar_dir: &Path,
+    rewrite: bool,
+) -> Result<Vec<DanglingReference>, Error> {
+    let mut missing = Vec::new();
+    for entry in walkdir::WalkDir::new(keg_path)
+        .follow_links(false)
+        .sort_by_file_name()
+    {
+        let entry = entry.map_err(Error::store("failed to walk keg"))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Some(shebang) = read_shebang(entry.path()) else {
+            continue;
+        };
+        let relative = entry
+            .path()
+            .strip_prefix(keg_path)
+            .unwrap_or(entry.path())
+            .to_path_buf();
+
+        match resolve(&shebang, prefix, cellar_dir) {
+            Resolution::Keep => {}
+            Resolution::Relocate(interpreter) => {
+                if rewrite {
+                    rewrite_interpreter(entry.path(), &shebang, interpreter)?;
+                }
+            }
+            Resolution::Missing { fallback } => {
+                let formula = providing_formula(&shebang.interpreter, prefix, cellar_dir);
+                if rewrite && let Some(fallback) = &fallback {
+                    rewrite_interpreter(entry.path(), &shebang, fallback.clone())?;
+                }
+                missing.push(DanglingReference {
+                    file: relative,
+                    suggestion: suggestion(formula, fallback.filter(|_| rewrite)),
+                    reference: shebang.interpreter,
+                });
+            }
+        }
+    }
+    Ok(missing)
+}
+
+fn read_shebang(path: &Path) -> Option<Shebang> {
+    let mut head = Vec::with_capacity(MAX_SHEBANG_LINE);
+    fs::File::open(path)
+        .ok()?
+        .take(MAX_SHEBANG_LINE as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    let line = head.split(|&b| b == b'\n').next()?;
+    parse_shebang(std::str::from_utf8(line).ok()?)
+}
+
+fn parse_shebang(line: &str) -> Option<Shebang> {
+    let rest = line.strip_prefix("#!")?.trim();
+    let (interpreter, argument) = match rest.split_once(char::is_whitespace) {
+        Some((interpreter, argument)) => (interpreter, Some(argument.trim())),
+        None => (rest, None),
+    };
+    if interpreter.is_empty() {
+        return None;
+    }
+    Some(Shebang {
+        interpreter: interpreter.to_string(),
+        argument: argument.filter(|a| !a.is_empty()).map(str::to_string),
+    })
+}
+
+fn resolve(shebang: &Shebang, prefix: &Path, cellar_dir: &Path) -> Resolution {
+    let interpreter = &shebang.interpreter;
+    if !interpreter.starts_with('/') || interpreter == ENV {
+        return Resolution::Keep;
+    }
+
+    let relocated = relocated_interpreter(interpreter, prefix, cellar_dir);
+    if Path::new(&relocated).is_file() {
+        return if relocated == *interpreter {
+            Resolution::Keep
+        } else {
+            Resolution::Relocate(relocated)
+        };
+    }
+
+    // `env` would take `name arg` as one command name.
+    let fallback = if SYSTEM_BIN_DIRS.iter().any(|d| interpreter.starts_with(d))
+        || shebang.argument.is_some()
+    {
+        None
+    } else {
+        Path::new(interpreter)
+            .file_name()
+            .map(|name| format!("{ENV} {}", name.to_string_lossy()))
+    };
+    Resolution::Missing { fallback }
+}
+
+/// Where `interpreter` lives under the zerobrew prefix: Homebrew prefixes
+/// are swapped for it, and a path into one version of a keg becomes one
+/// through the keg's opt link, which survives upgrades.
+fn relocated_interpreter(interpreter: &str, prefix: &Path, cellar_dir: &Path) -> String {
+    let prefix_str = prefix.to_string_lossy();
+    let mut path = interpreter.to_string();
+    for homebrew in HOMEBREW_PREFIXES {
+        if let Some(rest) = path.strip_prefix(homebrew)
+            && rest.starts_with('/')
+        {
+            path = format!("{prefix_str}{rest}");
+            break;
+        }
+    }
+
+    for cellar in [cellar_dir.to_path_buf(), prefix.join("Cellar")] {
+        let cellar = format!("{}/", cellar.display());
+        if let Some(rest) = path.strip_prefix(&cellar)
+            && let Some((name, rest)) = rest.split_once('/')
+            && let Some((_version, rest)) = rest.split_once('/')
+        {
+            return format!("{prefix_str}/opt/{name}/{rest}");
+        }
+    }
+    path
+}
+
+/// The formula whose opt path or keg holds `interpreter`, once relocated.
+fn providing_formula(interpreter: &str, prefix: &Path, cellar_dir: &Path) -> Option<String> {
+    let relocated = relocated_interpreter(interpreter, prefix, cellar_dir);
+    let opt = format!("{}/", prefix.join("opt").display());
+    let (name, _) = relocated.strip_prefix(&opt)?.split_once('/')?;
+    Some(name.to_string())
+}
+
+fn suggestion(formula: Option<String>, fallback: Option<String>) -> Option<String> {
+    match (formula, fallback) {
+        (Some(formula), Some(fallback)) => Some(format!(
+            "{formula} is not installed; now runs `{fallback}` from PATH"
+        )),
+        (Some(formula), None) => Some(format!("install {formula}")),
+        (None, Some(fallback)) => Some(format!("now runs `{fallback}` from PATH")),
+        (None, None) => None,
+    }
+}
+
+/// Replace the `#!` line of `path` with one running `interpreter`.
+fn rewrite_interpreter(path: &Path, shebang: &Shebang, interpreter: String) -> Result<(), Error> {
+    let ctx = format!("failed to rewrite the interpreter of {}", path.display());
+    let content = fs::read(path).map_err(Error::store(&ctx))?;
+    let body = content
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(&[][..], |n| &content[n..]);
+    let line = Shebang {
+        interpreter,
+        argument: shebang.argument.clone(),
+    }
+    .line();
+    if line.len() >= MAX_SHEBANG_LINE {
+        debug!(path = %path.display(), "rewritten interpreter line would be too long");
+        return Ok(());
+    }
+
+    // Write a new file rather than through the old one: the keg file may be
+    // a hardlink into the store, which must stay untouched.
+    let metadata = fs::metadata(path).map_err(Error::store(&ctx))?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path: PathBuf = path.with_file_name(format!(".{file_name}.zb-shebang"));
+    let mut rewritten = line.into_bytes();
+    rewritten.extend_from_slice(body);
+    fs::write(&temp_path, rewritten).map_err(Error::store(&ctx))?;
+    fs::set_permissions(&temp_path, metadata.permissions()).map_err(Error::store(&ctx))?;
+    fs::rename(&temp_path, path).map_err(Error::store(&ctx))?;
+
+    debug!(
+        path = %path.display(),
+        from = %shebang.interpreter,
+        "rewrote script interpreter"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn script(keg: &Path, name: &str, shebang: &str) -> PathBuf {
+        let path = keg.join("bin").join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, format!("{shebang}\necho {name}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn first_line(path: &Path) -> String {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn parses_interpreter_and_argument() {
+        assert_eq!(
+            parse_shebang("#! /usr/bin/perl -w "),
+            Some(Shebang {
+                interpreter: "/usr/bin/perl".to_string(),
+                argument: Some("-w".to_string()),
+            })
+        );
+        assert_eq!(parse_shebang("#!/bin/sh").unwrap().argument, None::<String>);
+        assert_eq!(parse_shebang("#!"), None);
+        assert_eq!(parse_shebang("echo hi"), None);
+    }
+
+    #[test]
+    fn rewrites_interpreters_to_the_prefix_or_env() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let cellar = tmp.path().join("Cellar");
+        let python = prefix.join("opt/python@3.12/bin/python3");
+        fs::create_dir_all(python.parent().unwrap()).unwrap();
+        fs::write(&python, "").unwrap();
+        let keg = cellar.join("tool/1.0");
+
+        let homebrew = script(
+            &keg,
+            "homebrew",
+            "#!/opt/homebrew/opt/python@3.12/bin/python3",
+        );
+        let versioned = script(
+            &keg,
+            "versioned",
+            &format!("#!{}/python@3.12/3.12.0/bin/python3 -u", cellar.display()),
+        );
+        let uninstalled = script(
+            &keg,
+            "uninstalled",
+            &format!("#!{}/opt/ruby/bin/ruby", prefix.display()),
+        );
+        let build_host = script(&keg, "build-host", "#!/private/tmp/build/bin/perl -w");
+        let env = script(&keg, "env", "#!/usr/bin/env bash");
+        let system = script(&keg, "system", "#!/bin/sh");
+
+        let missing = fix_shebangs(&keg, &prefix, &cellar).unwrap();
+        assert_eq!(
+            missing,
+            [
+                DanglingReference {
+                    file: PathBuf::from("bin/build-host"),
+                    reference: "/private/tmp/build/bin/perl".to_string(),
+                    suggestion: None,
+                },
+                DanglingReference {
+                    file: PathBuf::from("bin/uninstalled"),
+                    reference: format!("{}/opt/ruby/bin/ruby", prefix.display()),
+                    suggestion: Some(
+                        "ruby is not installed; now runs `/usr/bin/env ruby` from PATH".to_string()
+                    ),
+                },
+            ]
+        );
+
+        assert_eq!(first_line(&homebrew), format!("#!{}", python.display()));
+        assert_eq!(first_line(&versioned), format!("#!{} -u", python.display()));
+        assert_eq!(first_line(&uninstalled), "#!/usr/bin/env ruby");
+        assert_eq!(first_line(&build_host), "#!/private/tmp/build/bin/perl -w");
+        assert_eq!(first_line(&env), "#!/usr/bin/env bash");
+        assert_eq!(first_line(&system), "#!/bin/sh");
+        assert_eq!(
+            fs::read_to_string(&homebrew).unwrap().lines().nth(1),
+            Some("echo homebrew")
+        );
+        assert_eq!(
+            fs::metadata(&homebrew).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+
+        // Only the script that could not be rewritten is still broken.
+        let missing = missing_interpreters(&keg, &prefix, &cellar).unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].file, PathBuf::from("bin/build-host"));
+    }
+
+    #[test]
+    fn reports_the_formula_an_interpreter_needs() {
+        let prefix = Path::new("/opt/zb");
+        let cellar = Path::new("/opt/zb/Cellar");
+        assert_eq!(
+            providing_formula("/opt/homebrew/opt/node/bin/node", prefix, cellar).as_deref(),
+            Some("node")
+        );
+        assert_eq!(
+            providing_formula("/opt/zb/Cellar/perl/5.40.0/bin/perl", prefix, cellar).as_deref(),
+            Some("perl")
+        );
+        assert_eq!(providing_formula("/usr/bin/perl", prefix, cellar), None);
+    }
+}
@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Cursor, Read};
 use std::path::{Path, PathBuf};
 
 use flate2::read::GzDecoder;
@@ -9,52 +9,178 @@ use zstd::stream::read::Decoder as ZstdDecoder;
 
 use zb_core::Error;
 
+use super::parallel::{ReadAhead, WriterPool};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum CompressionFormat {
     Gzip,
     Xz,
     Zstd,
     Zip,
+    /// An uncompressed POSIX tar.
+    Tar,
     Unknown,
 }
 
+/// Bytes needed to tell formats apart: a tar's `ustar` magic ends at 262.
+const MAGIC_LEN: usize = 262;
+
+/// Limits and safety rules applied to every entry of an archive.
+///
+/// Absolute paths and `..` traversal are always rejected; the remaining knobs
+/// default to conservative values that fit all but the largest bottles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractPolicy {
+    /// Maximum number of entries (files, directories, links) in the archive.
+    pub max_entries: u64,
+    /// Maximum total size in bytes of all regular file contents.
+    pub max_unpacked_bytes: u64,
+    /// Allow block/character devices and FIFOs.
+    pub allow_special_files: bool,
+    /// Allow symlinks whose target resolves outside the destination.
+    pub allow_escaping_symlinks: bool,
+    /// Threads writing extracted files. With more than one, the archive is
+    /// also decompressed on a thread of its own ahead of the tar parser.
+    pub writers: usize,
+}
+
+impl ExtractPolicy {
+    pub const DEFAULT_MAX_ENTRIES: u64 = 250_000;
+    pub const DEFAULT_MAX_UNPACKED_BYTES: u64 = 8 << 30;
+    /// Upper bound for [`ExtractPolicy::default_writers`]; past this the
+    /// disk rather than the CPU is the limit.
+    pub const MAX_WRITERS: usize = 8;
+    /// Compressed size past which a bottle is unpacked with
+    /// [`ExtractPolicy::large_bottle`] limits.
+    pub const LARGE_BOTTLE_BYTES: u64 = 128 << 20;
+
+    /// Writer threads to use on this machine: one per core, up to
+    /// [`ExtractPolicy::MAX_WRITERS`].
+    pub fn default_writers() -> usize {
+        std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(Self::MAX_WRITERS)
+    }
+
+    /// Policy for Homebrew bottles, which legitimately ship relative symlinks
+    /// that climb out of the keg into the prefix (e.g. python's
+    /// `site-packages`). `tar` still refuses to write through such links.
+    pub fn bottle() -> Self {
+        Self {
+            allow_escaping_symlinks: true,
+            writers: Self::default_writers(),
+            ..Self::default()
+        }
+    }
+
+    /// Bottle policy with raised limits for toolchains such as gcc or llvm.
+    pub fn large_bottle() -> Self {
+        Self {
+            max_entries: Self::DEFAULT_MAX_ENTRIES * 4,
+            max_unpacked_bytes: Self::DEFAULT_MAX_UNPACKED_BYTES * 4,
+            ..Self::bottle()
+        }
+    }
+
+    /// Bottle policy for an archive of `archive_bytes`: toolchains whose
+    /// archives pass [`ExtractPolicy::LARGE_BOTTLE_BYTES`] get the raised
+    /// limits of [`ExtractPolicy::large_bottle`].
+    pub fn for_bottle(archive_bytes: u64) -> Self {
+        if archive_bytes > Self::LARGE_BOTTLE_BYTES {
+            Self::large_bottle()
+        } else {
+            Self::bottle()
+        }
+    }
+}
+
+impl Default for ExtractPolicy {
+    fn default() -> Self {
+        Self {
+            max_entries: Self::DEFAULT_MAX_ENTRIES,
+            max_unpacked_bytes: Self::DEFAULT_MAX_UNPACKED_BYTES,
+            allow_special_files: false,
+            allow_escaping_symlinks: false,
+            writers: 1,
+        }
+    }
+}
+
+/// Running totals checked against an `ExtractPolicy` while unpacking.
+#[derive(Default)]
+struct ExtractBudget {
+    entries: u64,
+    unpacked_bytes: u64,
+}
+
+impl ExtractBudget {
+    fn charge(&mut self, policy: &ExtractPolicy, size: u64) -> Result<(), Error> {
+        self.entries += 1;
+        if self.entries > policy.max_entries {
+            return Err(Error::StoreCorruption {
+                message: format!(
+                    "archive exceeds the maximum of {} entries",
+                    policy.max_entries
+                ),
+            });
+        }
+
+        self.unpacked_bytes = self.unpacked_bytes.saturating_add(size);
+        if self.unpacked_bytes > policy.max_unpacked_bytes {
+            return Err(Error::StoreCorruption {
+                message: format!(
+                    "archive exceeds the maximum unpacked size of {} bytes",
+                    policy.max_unpacked_bytes
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
 pub fn is_archive(path: &Path) -> Result<bool, Error> {
     detect_compression(path).map(|fmt| !matches!(fmt, CompressionFormat::Unknown))
 }
 
 fn detect_compression(path: &Path) -> Result<CompressionFormat, Error> {
-    let mut file = File::open(path).map_err(Error::store("failed to open tarball"))?;
+    let file = File::open(path).map_err(Error::store("failed to open tarball"))?;
 
-    let mut magic = [0u8; 6];
-    let bytes_read = file
-        .read(&mut magic)
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    file.take(MAGIC_LEN as u64)
+        .read_to_end(&mut magic)
         .map_err(Error::store("failed to read magic bytes"))?;
 
-    if bytes_read < 2 {
-        return Ok(CompressionFormat::Unknown);
-    }
+    Ok(compression_from_magic(&magic))
+}
 
+fn compression_from_magic(magic: &[u8]) -> CompressionFormat {
     // Gzip: 1f 8b
-    if magic[0] == 0x1f && magic[1] == 0x8b {
-        return Ok(CompressionFormat::Gzip);
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        return CompressionFormat::Gzip;
     }
 
     // XZ: fd 37 7a 58 5a 00 (FD 7zXZ\0)
-    if bytes_read >= 6 && magic[0..6] == [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00] {
-        return Ok(CompressionFormat::Xz);
+    if magic.starts_with(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]) {
+        return CompressionFormat::Xz;
     }
 
     // Zstd: 28 b5 2f fd
-    if bytes_read >= 4 && magic[0..4] == [0x28, 0xb5, 0x2f, 0xfd] {
-        return Ok(CompressionFormat::Zstd);
+    if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        return CompressionFormat::Zstd;
     }
 
     // ZIP: 50 4b 03 04
-    if bytes_read >= 4 && magic[0..4] == [0x50, 0x4b, 0x03, 0x04] {
-        return Ok(CompressionFormat::Zip);
+    if magic.starts_with(&[0x50, 0x4b, 0x03, 0x04]) {
+        return CompressionFormat::Zip;
+    }
+
+    // Tar: "ustar" at offset 257, in both POSIX and GNU headers
+    if magic.get(257..262) == Some(b"ustar") {
+        return CompressionFormat::Tar;
     }
 
-    Ok(CompressionFormat::Unknown)
+    CompressionFormat::Unknown
 }
 
 pub fn extract_tarball(tarball_path: &Path, dest_dir: &Path) -> Result<(), Error> {
@@ -62,6 +188,14 @@ pub fn extract_tarball(tarball_path: &Path, dest_dir: &Path) -> Result<(), Error
 }
 
 pub fn extract_archive(archive_path: &Path, dest_dir: &Path) -> Result<(), Error> {
+    extract_archive_with_policy(archive_path, dest_dir, &ExtractPolicy::default())
+}
+
+pub fn extract_archive_with_policy(
+    archive_path: &Path,
+    dest_dir: &Path,
+    policy: &ExtractPolicy,
+) -> Result<(), Error> {
     let format = detect_compression(archive_path)?;
 
     let file = File::open(archive_path).map_err(Error::store("failed to open archive"))?;
@@ -70,28 +204,51 @@ pub fn extract_archive(archive_path: &Path, dest_dir: &Path) -> Result<(), Error
     match format {
         CompressionFormat::Gzip => {
             let decoder = GzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, policy)
         }
         CompressionFormat::Xz => {
             let decoder = XzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, policy)
         }
         CompressionFormat::Zstd => {
             let decoder =
                 ZstdDecoder::new(reader).map_err(Error::store("failed to create zstd decoder"))?;
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, policy)
         }
-        CompressionFormat::Zip => extract_zip_archive(archive_path, dest_dir),
-        CompressionFormat::Unknown => {
-            // Try gzip as fallback
-            let decoder = GzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
+        CompressionFormat::Zip => extract_zip_archive(archive_path, dest_dir, policy),
+        // Old v7 tars have no magic at all; anything else fails as a tar.
+        CompressionFormat::Tar | CompressionFormat::Unknown => {
+            extract_tar_archive(reader, dest_dir, policy)
         }
     }
 }
 
-fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error> {
+fn extract_tar_archive<R: Read + Send>(
+    reader: R,
+    dest_dir: &Path,
+    policy: &ExtractPolicy,
+) -> Result<(), Error> {
+    if policy.writers <= 1 {
+        return unpack_tar_entries(reader, dest_dir, policy, None);
+    }
+
+    std::thread::scope(|scope| {
+        let reader = ReadAhead::spawn(scope, reader);
+        let mut pool = WriterPool::spawn(scope, policy.writers, dest_dir)?;
+        let unpacked = unpack_tar_entries(reader, dest_dir, policy, Some(&mut pool));
+        let written = pool.finish();
+        unpacked.and(written)
+    })
+}
+
+fn unpack_tar_entries<R: Read>(
+    reader: R,
+    dest_dir: &Path,
+    policy: &ExtractPolicy,
+    mut pool: Option<&mut WriterPool<'_>>,
+) -> Result<(), Error> {
     let mut archive = Archive::new(reader);
+    let mut budget = ExtractBudget::default();
 
     archive.set_preserve_permissions(true);
     archive.set_unpack_xattrs(true);
@@ -104,7 +261,8 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
 
         let entry_path = entry
             .path()
-            .map_err(Error::store("failed to read entry path"))?;
+            .map_err(Error::store("failed to read entry path"))?
+            .into_owned();
 
         // Store path as owned string for error message
         let path_display = entry_path.display().to_string();
@@ -112,16 +270,60 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
         // Security check: validate path doesn't escape destination
         validate_path(&entry_path, dest_dir)?;
 
+        let entry_type = entry.header().entry_type();
+        let size = if entry_type.is_file() {
+            entry.header().size().unwrap_or(0)
+        } else {
+            0
+        };
+        budget.charge(policy, size)?;
+
+        if !policy.allow_special_files
+            && (entry_type.is_block_special()
+                || entry_type.is_character_special()
+                || entry_type.is_fifo())
+        {
+            return Err(Error::StoreCorruption {
+                message: format!("special file in archive: {path_display}"),
+            });
+        }
+
+        if let Some(link_name) = entry
+            .link_name()
+            .map_err(Error::store("failed to read link target"))?
+        {
+            if entry_type.is_hard_link() {
+                validate_path(&link_name, dest_dir)?;
+            } else if entry_type.is_symlink() && !policy.allow_escaping_symlinks {
+                validate_symlink_target(&entry_path, &link_name, dest_dir)?;
+            }
+        }
+
         let ctx = format!("failed to unpack entry {path_display}");
-        entry.unpack_in(dest_dir).map_err(Error::store(&ctx))?;
+        match pool.as_deref_mut() {
+            Some(pool) => pool.unpack(&mut entry, &entry_path, &ctx)?,
+            None => {
+                entry.unpack_in(dest_dir).map_err(Error::store(&ctx))?;
+            }
+        }
     }
 
     Ok(())
 }
 
-fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
+/// File type bits of a unix mode, as zip archivers record them.
+const S_IFMT: u32 = 0o170_000;
+const S_IFLNK: u32 = 0o120_000;
+const S_IFIFO: u32 = 0o010_000;
+const S_IFCHR: u32 = 0o020_000;
+const S_IFBLK: u32 = 0o060_000;
+
+fn extract_zip_archive(path: &Path, dest_dir: &Path, policy: &ExtractPolicy) -> Result<(), Error> {
     let file = File::open(path).map_err(Error::store("failed to open zip archive"))?;
     let mut zip = zip::ZipArchive::new(file).map_err(Error::store("failed to open zip archive"))?;
+    let mut budget = ExtractBudget::default();
+    let resolved_dest =
+        std::fs::canonicalize(dest_dir).map_err(Error::store("failed to resolve destination"))?;
 
     for i in 0..zip.len() {
         let mut entry = zip
@@ -134,18 +336,46 @@ fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
         };
 
         validate_path(&raw_path, dest_dir)?;
+        budget.charge(policy, entry.size())?;
+
+        let file_type = entry.unix_mode().unwrap_or(0) & S_IFMT;
+        if !policy.allow_special_files && matches!(file_type, S_IFIFO | S_IFCHR | S_IFBLK) {
+            return Err(Error::StoreCorruption {
+                message: format!("special file in archive: {}", raw_path.display()),
+            });
+        }
 
         let out_path = dest_dir.join(&raw_path);
 
         if entry.is_dir() {
             std::fs::create_dir_all(&out_path)
                 .map_err(Error::store("failed to create output directory"))?;
+            ensure_within(&out_path, &resolved_dest, &raw_path)?;
             continue;
         }
 
         if let Some(parent) = out_path.parent() {
             std::fs::create_dir_all(parent)
                 .map_err(Error::store("failed to create output parent directory"))?;
+            ensure_within(parent, &resolved_dest, &raw_path)?;
+        }
+        // Replace rather than follow whatever an earlier entry left here.
+        if out_path.symlink_metadata().is_ok() {
+            std::fs::remove_file(&out_path)
+                .map_err(Error::store("failed to replace extracted file"))?;
+        }
+
+        if file_type == S_IFLNK {
+            let mut target = String::new();
+            entry
+                .read_to_string(&mut target)
+                .map_err(Error::store("failed to read zip symlink target"))?;
+            if !policy.allow_escaping_symlinks {
+                validate_symlink_target(&raw_path, Path::new(&target), dest_dir)?;
+            }
+            std::os::unix::fs::symlink(&target, &out_path)
+                .map_err(Error::store("failed to create zip symlink"))?;
+            continue;
         }
 
         let mut output =
@@ -157,7 +387,7 @@ fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
         {
             use std::os::unix::fs::PermissionsExt;
             if let Some(mode) = entry.unix_mode() {
-                let perms = std::fs::Permissions::from_mode(mode);
+                let perms = std::fs::Permissions::from_mode(mode & 0o7777);
                 std::fs::set_permissions(&out_path, perms)
                     .map_err(Error::store("failed to set zip file permissions"))?;
             }
@@ -167,6 +397,21 @@ fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Refuse to write `entry` under `dir` when a symlink unpacked earlier has
+/// `dir` resolve outside `resolved_dest`; `tar` refuses the same.
+fn ensure_within(dir: &Path, resolved_dest: &Path, entry: &Path) -> Result<(), Error> {
+    let resolved = std::fs::canonicalize(dir).map_err(Error::store("failed to resolve entry"))?;
+    if !resolved.starts_with(resolved_dest) {
+        return Err(Error::StoreCorruption {
+            message: format!(
+                "entry would be written outside the destination: {}",
+                entry.display()
+            ),
+        });
+    }
+    Ok(())
+}
+
 /// Validate that a path from a tar entry is safe to extract.
 ///
 /// This function ensures:
@@ -216,6 +461,30 @@ fn validate_path(path: &Path, dest_dir: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Validate that a symlink at `link_path` pointing to `target` stays inside
+/// `dest_dir` once resolved relative to the link's parent directory.
+fn validate_symlink_target(link_path: &Path, target: &Path, dest_dir: &Path) -> Result<(), Error> {
+    let escapes = if target.is_absolute() {
+        true
+    } else {
+        let parent = link_path.parent().unwrap_or(Path::new(""));
+        let resolved = normalize_path(&dest_dir.join(parent).join(target));
+        !resolved.starts_with(normalize_path(dest_dir))
+    };
+
+    if escapes {
+        return Err(Error::StoreCorruption {
+            message: format!(
+                "symlink escapes destination directory: {} -> {}",
+                link_path.display(),
+                target.display()
+            ),
+        });
+    }
+
+    Ok(())
+}
+
 /// Normalize a path by resolving . and .. components without filesystem access.
 ///
 /// This is safer than `canonicalize()` because:
@@ -271,11 +540,45 @@ fn normalize_path(path: &Path) -> PathBuf {
     components.iter().collect()
 }
 
-/// Extract a tarball from a reader (assumes gzip compression).
-/// For file-based extraction with auto-detection, use `extract_tarball` instead.
-pub fn extract_tarball_from_reader<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error> {
-    let decoder = GzDecoder::new(reader);
-    extract_tar_archive(decoder, dest_dir)
+/// Extract a tarball as it is read, detecting the compression from its
+/// first bytes. Zip archives need random access and are refused.
+pub fn extract_tar_stream<R: Read + Send>(
+    mut reader: R,
+    dest_dir: &Path,
+    policy: &ExtractPolicy,
+) -> Result<(), Error> {
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    (&mut reader)
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut magic)
+        .map_err(Error::store("failed to read magic bytes"))?;
+    let format = compression_from_magic(&magic);
+    let reader = BufReader::new(Cursor::new(magic).chain(reader));
+
+    match format {
+        CompressionFormat::Gzip => extract_tar_archive(GzDecoder::new(reader), dest_dir, policy),
+        CompressionFormat::Tar | CompressionFormat::Unknown => {
+            extract_tar_archive(reader, dest_dir, policy)
+        }
+        CompressionFormat::Xz => extract_tar_archive(XzDecoder::new(reader), dest_dir, policy),
+        CompressionFormat::Zstd => {
+            let decoder =
+                ZstdDecoder::new(reader).map_err(Error::store("failed to create zstd decoder"))?;
+            extract_tar_archive(decoder, dest_dir, policy)
+        }
+        CompressionFormat::Zip => Err(Error::StoreCorruption {
+            message: "zip archives cannot be extracted while streaming".to_string(),
+        }),
+    }
+}
+
+/// Extract a tarball from a reader with the default policy, detecting
+/// its compression like [`extract_tar_stream`].
+pub fn extract_tarball_from_reader<R: Read + Send>(
+    reader: R,
+    dest_dir: &Path,
+) -> Result<(), Error> {
+    extract_tar_stream(reader, dest_dir, &ExtractPolicy::default())
 }
 
 #[cfg(test)]
@@ -506,6 +809,203 @@ mod tests {
         assert!(err.to_string().contains("absolute path"));
     }
 
+    fn write_archive(tmp: &TempDir, data: &[u8]) -> (PathBuf, PathBuf) {
+        let archive_path = tmp.path().join("test.tar.gz");
+        fs::write(&archive_path, data).unwrap();
+        let dest = tmp.path().join("extracted");
+        fs::create_dir(&dest).unwrap();
+        (archive_path, dest)
+    }
+
+    #[test]
+    fn policy_rejects_too_many_entries() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = create_test_tarball(vec![
+            ("a.txt", b"a", None),
+            ("b.txt", b"b", None),
+            ("c.txt", b"c", None),
+        ]);
+        let (archive, dest) = write_archive(&tmp, &tarball);
+
+        let policy = ExtractPolicy {
+            max_entries: 2,
+            ..ExtractPolicy::default()
+        };
+        let err = extract_archive_with_policy(&archive, &dest, &policy).unwrap_err();
+        assert!(err.to_string().contains("maximum of 2 entries"));
+    }
+
+    #[test]
+    fn policy_rejects_oversized_archives() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = create_test_tarball(vec![("big.bin", &[0u8; 64], None)]);
+        let (archive, dest) = write_archive(&tmp, &tarball);
+
+        let policy = ExtractPolicy {
+            max_unpacked_bytes: 63,
+            ..ExtractPolicy::default()
+        };
+        let err = extract_archive_with_policy(&archive, &dest, &policy).unwrap_err();
+        assert!(err.to_string().contains("maximum unpacked size"));
+
+        let policy = ExtractPolicy {
+            max_unpacked_bytes: 64,
+            ..ExtractPolicy::default()
+        };
+        let dest = tmp.path().join("extracted-ok");
+        fs::create_dir(&dest).unwrap();
+        extract_archive_with_policy(&archive, &dest, &policy).unwrap();
+    }
+
+    #[test]
+    fn rejects_device_nodes() {
+        let tmp = TempDir::new().unwrap();
+        let mut builder = Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Char);
+        header.set_path("dev/null").unwrap();
+        header.set_size(0);
+        header.set_mode(0o666);
+        header.set_cksum();
+        builder.append(&header, &[][..]).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        let (archive, dest) = write_archive(&tmp, &encoder.finish().unwrap());
+
+        let err = extract_tarball(&archive, &dest).unwrap_err();
+        assert!(err.to_string().contains("special file"));
+    }
+
+    #[test]
+    fn rejects_symlinks_escaping_destination() {
+        for target in ["../../etc/passwd", "/etc/passwd"] {
+            let tmp = TempDir::new().unwrap();
+            let tarball = create_tarball_with_symlink("bin/evil", target);
+            let (archive, dest) = write_archive(&tmp, &tarball);
+
+            let err = extract_tarball(&archive, &dest).unwrap_err();
+            assert!(
+                err.to_string().contains("symlink escapes"),
+                "{target}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_relative_symlinks_within_destination() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = create_tarball_with_symlink("foo/1.0/bin/foo", "../libexec/foo");
+        let (archive, dest) = write_archive(&tmp, &tarball);
+
+        extract_tarball(&archive, &dest).unwrap();
+        assert_eq!(
+            fs::read_link(dest.join("foo/1.0/bin/foo")).unwrap(),
+            PathBuf::from("../libexec/foo")
+        );
+    }
+
+    #[test]
+    fn bottle_policy_allows_symlinks_into_prefix() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = create_tarball_with_symlink(
+            "python/3.12/lib/site-packages",
+            "../../../../lib/python3.12/site-packages",
+        );
+        let (archive, dest) = write_archive(&tmp, &tarball);
+
+        extract_archive_with_policy(&archive, &dest, &ExtractPolicy::bottle()).unwrap();
+        assert!(
+            dest.join("python/3.12/lib/site-packages")
+                .symlink_metadata()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn large_bottles_get_raised_limits() {
+        assert_eq!(ExtractPolicy::for_bottle(1 << 20), ExtractPolicy::bottle());
+        assert_eq!(
+            ExtractPolicy::for_bottle(ExtractPolicy::LARGE_BOTTLE_BYTES + 1),
+            ExtractPolicy::large_bottle()
+        );
+    }
+
+    fn create_zip_with_symlink(name: &str, target: &str, then: Option<(&str, &[u8])>) -> Vec<u8> {
+        use zip::write::SimpleFileOptions;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.add_symlink(name, target, SimpleFileOptions::default())
+            .unwrap();
+        if let Some((path, content)) = then {
+            zip.start_file(path, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn zip_preserves_symlinks() {
+        let tmp = TempDir::new().unwrap();
+        let zip_data = create_zip_with_symlink("bin/alias", "tool", None);
+        let (archive, dest) = write_archive(&tmp, &zip_data);
+
+        extract_archive(&archive, &dest).unwrap();
+        assert_eq!(
+            fs::read_link(dest.join("bin/alias")).unwrap(),
+            PathBuf::from("tool")
+        );
+    }
+
+    #[test]
+    fn zip_rejects_symlinks_escaping_destination() {
+        for target in ["../../etc/passwd", "/etc/passwd"] {
+            let tmp = TempDir::new().unwrap();
+            let zip_data = create_zip_with_symlink("bin/evil", target, None);
+            let (archive, dest) = write_archive(&tmp, &zip_data);
+
+            let err = extract_archive(&archive, &dest).unwrap_err();
+            assert!(
+                err.to_string().contains("symlink escapes"),
+                "{target}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn zip_refuses_to_write_through_escaping_symlinks() {
+        let tmp = TempDir::new().unwrap();
+        let zip_data = create_zip_with_symlink(
+            "tool/lib",
+            "../../outside",
+            Some(("tool/lib/evil", b"evil")),
+        );
+        fs::create_dir(tmp.path().join("outside")).unwrap();
+        let (archive, dest) = write_archive(&tmp, &zip_data);
+
+        let err =
+            extract_archive_with_policy(&archive, &dest, &ExtractPolicy::bottle()).unwrap_err();
+        assert!(err.to_string().contains("outside the destination"), "{err}");
+        assert!(!tmp.path().join("outside/evil").exists());
+    }
+
+    #[test]
+    fn zip_rejects_special_files() {
+        let tmp = TempDir::new().unwrap();
+        let mut zip_data = create_test_zip(vec![("dev/fifo", b"")]);
+        // The zip writer only records regular permissions, so mark the
+        // entry a FIFO in its central directory record by hand.
+        let central = zip_data
+            .windows(4)
+            .position(|w| w == [0x50, 0x4b, 0x01, 0x02])
+            .unwrap();
+        let mode = (0o010_644u32 << 16).to_le_bytes();
+        zip_data[central + 38..central + 42].copy_from_slice(&mode);
+        let (archive, dest) = write_archive(&tmp, &zip_data);
+
+        let err = extract_archive(&archive, &dest).unwrap_err();
+        assert!(err.to_string().contains("special file"), "{err}");
+    }
+
     #[test]
     fn normalize_path_removes_dot_components() {
         let path = PathBuf::from("/foo/./bar/./baz");
@@ -622,6 +1122,214 @@ mod tests {
         assert!(is_archive(&path).unwrap());
     }
 
+    fn plain_tar(path: &str, content: &[u8]) -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        let mut header = tar::Header::new_ustar();
+        header.set_path(path).unwrap();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, content).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    /// A tar in every supported compression, named without a telling
+    /// extension.
+    fn tars_in_every_format(content: &[u8]) -> Vec<(&'static str, Vec<u8>)> {
+        let tar = plain_tar("bin/tool", content);
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&tar).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&tar).unwrap();
+        vec![
+            ("gzip", gzip.finish().unwrap()),
+            ("xz", xz.finish().unwrap()),
+            ("zstd", zstd::encode_all(tar.as_slice(), 3).unwrap()),
+            ("tar", tar),
+        ]
+    }
+
+    /// Hands out at most a few bytes per read, like a slow network stream.
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(7);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn extracts_every_format_by_magic_bytes() {
+        for (format, data) in tars_in_every_format(b"tool contents") {
+            let tmp = TempDir::new().unwrap();
+            let path = tmp.path().join("tool-1.0.bottle");
+            fs::write(&path, &data).unwrap();
+            assert!(is_archive(&path).unwrap(), "{format}");
+
+            let dest = tmp.path().join("from-file");
+            fs::create_dir(&dest).unwrap();
+            extract_tarball(&path, &dest).unwrap();
+            assert_eq!(
+                fs::read(dest.join("bin/tool")).unwrap(),
+                b"tool contents",
+                "{format}"
+            );
+
+            let dest = tmp.path().join("from-stream");
+            fs::create_dir(&dest).unwrap();
+            extract_tar_stream(Trickle(data.as_slice()), &dest, &ExtractPolicy::default()).unwrap();
+            assert_eq!(
+                fs::read(dest.join("bin/tool")).unwrap(),
+                b"tool contents",
+                "{format}"
+            );
+        }
+    }
+
+    /// A bottle-like archive with every kind of entry the writer pool has
+    /// to order correctly.
+    fn mixed_tarball() -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        let mut append = |path: &str, entry_type: tar::EntryType, mode: u32, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_path(path).unwrap();
+            header.set_size(data.len() as u64);
+            header.set_mode(mode);
+            header.set_mtime(1_700_000_000);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        };
+
+        append("tool/1.0/lib", tar::EntryType::Directory, 0o755, &[]);
+        for i in 0..64 {
+            let mode = if i % 2 == 0 { 0o755 } else { 0o644 };
+            let content = format!("file {i}\n").repeat(i + 1);
+            append(
+                &format!("tool/1.0/share/{i}/data.txt"),
+                tar::EntryType::Regular,
+                mode,
+                content.as_bytes(),
+            );
+        }
+        append(
+            "tool/1.0/lib/big.a",
+            tar::EntryType::Regular,
+            0o644,
+            &vec![7u8; (2 << 20) + 3],
+        );
+        // Written twice: the later copy wins, as with tar.
+        append("tool/1.0/bin/tool", tar::EntryType::Regular, 0o755, b"old");
+        append("tool/1.0/bin/tool", tar::EntryType::Regular, 0o555, b"new");
+
+        let mut link = |path: &str, entry_type: tar::EntryType, target: &str| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(0);
+            header.set_mode(0o777);
+            header.set_mtime(1_700_000_000);
+            builder.append_link(&mut header, path, target).unwrap();
+        };
+        link("tool/1.0/bin/alias", tar::EntryType::Symlink, "tool");
+        link(
+            "tool/1.0/bin/hard",
+            tar::EntryType::Link,
+            "tool/1.0/share/3/data.txt",
+        );
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn parallel_policy() -> ExtractPolicy {
+        ExtractPolicy {
+            writers: 4,
+            ..ExtractPolicy::bottle()
+        }
+    }
+
+    #[test]
+    fn writer_pool_unpacks_the_same_tree() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new().unwrap();
+        let (archive, sequential) = write_archive(&tmp, &mixed_tarball());
+        let policy = ExtractPolicy {
+            writers: 1,
+            ..ExtractPolicy::bottle()
+        };
+        extract_archive_with_policy(&archive, &sequential, &policy).unwrap();
+        let parallel = tmp.path().join("parallel");
+        fs::create_dir(&parallel).unwrap();
+        extract_archive_with_policy(&archive, &parallel, &parallel_policy()).unwrap();
+
+        for entry in walkdir::WalkDir::new(&sequential).min_depth(1) {
+            let entry = entry.unwrap();
+            let relative = entry.path().strip_prefix(&sequential).unwrap();
+            let expected = fs::symlink_metadata(entry.path()).unwrap();
+            let actual = fs::symlink_metadata(parallel.join(relative)).unwrap();
+            assert_eq!(actual.mode(), expected.mode(), "{}", relative.display());
+            if expected.is_file() {
+                assert_eq!(actual.mtime(), expected.mtime(), "{}", relative.display());
+                assert_eq!(
+                    fs::read(parallel.join(relative)).unwrap(),
+                    fs::read(entry.path()).unwrap(),
+                    "{}",
+                    relative.display()
+                );
+            }
+        }
+        assert_eq!(
+            walkdir::WalkDir::new(&parallel).into_iter().count(),
+            walkdir::WalkDir::new(&sequential).into_iter().count()
+        );
+
+        let bin = parallel.join("tool/1.0/bin");
+        assert_eq!(fs::read(bin.join("tool")).unwrap(), b"new");
+        assert_eq!(fs::read_link(bin.join("alias")).unwrap(), Path::new("tool"));
+        assert_eq!(fs::metadata(bin.join("hard")).unwrap().nlink(), 2);
+    }
+
+    #[test]
+    fn writer_pool_refuses_to_write_through_escaping_symlinks() {
+        let tmp = TempDir::new().unwrap();
+        let mut builder = Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        builder
+            .append_link(&mut header, "tool/lib", "../../outside")
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_path("tool/lib/evil").unwrap();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"evil"[..]).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+
+        fs::create_dir(tmp.path().join("outside")).unwrap();
+        let (archive, dest) = write_archive(&tmp, &encoder.finish().unwrap());
+        let err = extract_archive_with_policy(&archive, &dest, &parallel_policy()).unwrap_err();
+        assert!(err.to_string().contains("outside the destination"), "{err}");
+        assert!(!tmp.path().join("outside/evil").exists());
+    }
+
+    #[test]
+    fn writer_pool_reports_truncated_archives() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = mixed_tarball();
+        let dest = tmp.path().join("extracted");
+        fs::create_dir(&dest).unwrap();
+
+        let truncated = &tarball[..tarball.len() / 2];
+        assert!(extract_tar_stream(truncated, &dest, &parallel_policy()).is_err());
+    }
+
     #[test]
     fn is_archive_false_for_raw_binary() {
         let tmp = TempDir::new().unwrap();
@@ -1,4 +1,10 @@
 pub mod extract;
+mod parallel;
 pub mod patch;
+pub mod stream;
 
-pub use extract::{extract_archive, extract_tarball, extract_tarball_from_reader, is_archive};
+pub use extract::{
+    ExtractPolicy, extract_archive, extract_archive_with_policy, extract_tar_stream,
+    extract_tarball, extract_tarball_from_reader, is_archive,
+};
+pub use stream::StreamingExtractor;
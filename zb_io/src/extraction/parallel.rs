@@ -0,0 +1,331 @@
+use std::collections::HashSet;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{Scope, ScopedJoinHandle};
+use std::time::{Duration, SystemTime};
+
+use tar::Entry;
+use zb_core::Error;
+
+/// Size of the chunks the read-ahead thread decompresses at a time.
+const READ_AHEAD_CHUNK: usize = 256 << 10;
+
+/// Decompressed chunks buffered ahead of the tar parser.
+const READ_AHEAD_CHUNKS: usize = 16;
+
+/// Files larger than this are unpacked in place rather than buffered in
+/// memory for a writer thread.
+const MAX_BUFFERED_FILE: u64 = 1 << 20;
+
+/// Files queued per writer thread before the parser waits for the writers.
+const QUEUED_FILES_PER_WRITER: usize = 8;
+
+/// Decompresses an archive on its own thread, so inflating the next chunk
+/// overlaps with parsing and writing the current one.
+pub(super) struct ReadAhead {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    offset: usize,
+}
+
+impl ReadAhead {
+    pub(super) fn spawn<'scope, R: Read + Send + 'scope>(
+        scope: &'scope Scope<'scope, '_>,
+        mut reader: R,
+    ) -> Self {
+        let (sender, receiver) = sync_channel(READ_AHEAD_CHUNKS);
+        scope.spawn(move || {
+            loop {
+                let mut chunk = Vec::with_capacity(READ_AHEAD_CHUNK);
+                match (&mut reader)
+                    .take(READ_AHEAD_CHUNK as u64)
+                    .read_to_end(&mut chunk)
+                {
+                    Ok(0) => break,
+                    // A send only fails once the parser has stopped reading.
+                    Ok(_) => {
+                        if sender.send(Ok(chunk)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+        Self {
+            receiver,
+            current: Vec::new(),
+            offset: 0,
+        }
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.current.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.current = chunk?;
+                    self.offset = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.current.len() - self.offset);
+        buf[..len].copy_from_slice(&self.current[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+/// A regular file read out of the archive, waiting to be written.
+struct FileJob {
+    path: PathBuf,
+    data: Vec<u8>,
+    mode: u32,
+    mtime: u64,
+}
+
+/// Whether the entry carries extended attributes, which only `tar` knows
+/// how to restore.
+fn has_xattrs<R: Read>(entry: &mut Entry<'_, R>) -> io::Result<bool> {
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(false);
+    };
+    for extension in extensions {
+        if extension?
+            .key()
+            .is_ok_and(|key| key.starts_with("SCHILY.xattr."))
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// State shared between the parser and the writer threads.
+#[derive(Default)]
+struct Progress {
+    /// Files handed to the writers and not yet written.
+    pending: Mutex<usize>,
+    idle: Condvar,
+    /// The first write that failed; later files are skipped.
+    error: Mutex<Option<Error>>,
+}
+
+/// Writes buffered files on a fixed set of threads while the parser moves on
+/// to the next entry.
+///
+/// Everything other than small regular files is still unpacked in order by
+/// the parser, which first waits for the writers whenever it would touch a
+/// path they may still be working on or create a hard link.
+pub(super) struct WriterPool<'scope> {
+    sender: Option<SyncSender<FileJob>>,
+    handles: Vec<ScopedJoinHandle<'scope, ()>>,
+    progress: Arc<Progress>,
+    /// Paths queued since the last flush.
+    queued: HashSet<PathBuf>,
+    /// The canonical destination, which buffered files must stay inside.
+    dest_dir: PathBuf,
+}
+
+impl<'scope> WriterPool<'scope> {
+    pub(super) fn spawn(
+        scope: &'scope Scope<'scope, '_>,
+        writers: usize,
+        dest_dir: &Path,
+    ) -> Result<Self, Error> {
+        let dest_dir =
+            fs::canonicalize(dest_dir).map_err(Error::store("failed to resolve destination"))?;
+        let (sender, receiver) = sync_channel::<FileJob>(writers * QUEUED_FILES_PER_WRITER);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let progress = Arc::new(Progress::default());
+
+        let handles = (0..writers)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let progress = Arc::clone(&progress);
+                scope.spawn(move || {
+                    loop {
+                        let job = match receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => break,
+                        };
+                        let Ok(job) = job else { break };
+                        progress.write(job);
+                    }
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            sender: Some(sender),
+            handles,
+            progress,
+            queued: HashSet::new(),
+            dest_dir,
+        })
+    }
+
+    /// Unpack the already validated `entry` at `path`: small regular files
+    /// are read into memory and queued for a writer, everything else is
+    /// unpacked in place by `tar`.
+    pub(super) fn unpack<R: Read>(
+        &mut self,
+        entry: &mut Entry<'_, R>,
+        path: &Path,
+        ctx: &str,
+    ) -> Result<(), Error> {
+        let target = path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .fold(self.dest_dir.clone(), |target, c| target.join(c));
+        let entry_type = entry.header().entry_type();
+        let size = entry.header().size().unwrap_or(u64::MAX);
+        let buffered = entry_type.is_file()
+            && size <= MAX_BUFFERED_FILE
+            && target != self.dest_dir
+            && !has_xattrs(entry).map_err(Error::store(ctx))?;
+
+        // A hard link needs its target written, and no path may be written
+        // twice at once.
+        if entry_type.is_hard_link() || self.queued.contains(&target) {
+            self.flush()?;
+        }
+        if !buffered {
+            entry.unpack_in(&self.dest_dir).map_err(Error::store(ctx))?;
+            return Ok(());
+        }
+
+        // tar refuses to write through a symlink that leaves the
+        // destination; so does this.
+        let parent = target.parent().unwrap_or(&self.dest_dir);
+        fs::create_dir_all(parent).map_err(Error::store(ctx))?;
+        if !fs::canonicalize(parent)
+            .map_err(Error::store(ctx))?
+            .starts_with(&self.dest_dir)
+        {
+            return Err(Error::StoreCorruption {
+                message: format!(
+                    "entry would be written outside the destination: {}",
+                    path.display()
+                ),
+            });
+        }
+
+        let mut data = Vec::with_capacity(size as usize);
+        entry.read_to_end(&mut data).map_err(Error::store(ctx))?;
+        let header = entry.header();
+        self.submit(FileJob {
+            path: target,
+            data,
+            mode: header.mode().map_err(Error::store(ctx))? & 0o7777,
+            mtime: header.mtime().unwrap_or(0),
+        })
+    }
+
+    /// Queue `job` for writing, or return the error a writer already hit.
+    fn submit(&mut self, job: FileJob) -> Result<(), Error> {
+        self.check()?;
+        self.queued.insert(job.path.clone());
+        *lock(&self.progress.pending) += 1;
+        if let Some(sender) = &self.sender
+            && sender.send(job).is_err()
+        {
+            return Err(Error::StoreCorruption {
+                message: "archive writer threads stopped unexpectedly".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Wait until every queued file has been written.
+    fn flush(&mut self) -> Result<(), Error> {
+        let mut pending = lock(&self.progress.pending);
+        while *pending > 0 {
+            pending = self
+                .progress
+                .idle
+                .wait(pending)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        drop(pending);
+        self.queued.clear();
+        self.check()
+    }
+
+    /// Write the remaining files and stop the writer threads.
+    pub(super) fn finish(mut self) -> Result<(), Error> {
+        self.sender = None;
+        for handle in self.handles.drain(..) {
+            if handle.join().is_err() {
+                return Err(Error::StoreCorruption {
+                    message: "archive writer thread panicked".to_string(),
+                });
+            }
+        }
+        self.check()
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        match lock(&self.progress.error).take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Progress {
+    fn write(&self, job: FileJob) {
+        let skip = lock(&self.error).is_some();
+        if !skip && let Err(err) = write_file(&job) {
+            lock(&self.error).get_or_insert(err);
+        }
+
+        let mut pending = lock(&self.pending);
+        *pending -= 1;
+        if *pending == 0 {
+            self.idle.notify_all();
+        }
+    }
+}
+
+/// Write a file the way `tar` unpacks one: never through an existing file or
+/// link at the path, with the archived mode and modification time.
+fn write_file(job: &FileJob) -> Result<(), Error> {
+    let ctx = format!("failed to unpack entry {}", job.path.display());
+    let mut file = create_new(&job.path).map_err(Error::store(&ctx))?;
+    file.write_all(&job.data).map_err(Error::store(&ctx))?;
+
+    // Like tar, a zero mtime becomes 1 so files never look unset.
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(job.mtime.max(1));
+    file.set_times(FileTimes::new().set_accessed(mtime).set_modified(mtime))
+        .map_err(Error::store(&ctx))?;
+    file.set_permissions(fs::Permissions::from_mode(job.mode))
+        .map_err(Error::store(&ctx))
+}
+
+fn create_new(path: &Path) -> io::Result<File> {
+    let open = || OpenOptions::new().write(true).create_new(true).open(path);
+    open().or_else(|err| {
+        if err.kind() != ErrorKind::AlreadyExists {
+            return Err(err);
+        }
+        match fs::remove_file(path) {
+            Ok(()) => open(),
+            Err(e) if e.kind() == ErrorKind::NotFound => open(),
+            Err(e) => Err(e),
+        }
+    })
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
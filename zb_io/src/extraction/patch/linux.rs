@@ -6,7 +6,7 @@ use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 use rayon::prelude::*;
-use tracing::warn;
+use tracing::{debug, warn};
 use zb_core::Error;
 
 const LINUX_HOMEBREW_PREFIX: &str = "/home/linuxbrew/.linuxbrew";
@@ -20,6 +20,7 @@ pub fn patch_placeholders(
     _pkg_version: &str,
 ) -> Result<(), Error> {
     patch_elf_placeholders(keg_path, prefix_dir)?;
+    patch_elf_data_strings(keg_path, prefix_dir)?;
     patch_text_placeholders(keg_path, prefix_dir)?;
     Ok(())
 }
@@ -33,99 +34,97 @@ fn rewrite_homebrew_prefixes(input: &str, prefix_dir: &Path) -> String {
         .replace(LINUX_HOMEBREW_PREFIX, &prefix_str)
 }
 
-/// Detect if zerobrew has installed its own glibc and return the path to its ld.so interpreter.
-/// Returns None if zerobrew's glibc is not found, indicating we should use the system ld.so.
-fn detect_zerobrew_glibc(prefix_dir: &Path) -> Option<PathBuf> {
-    let cellar = prefix_dir.join("Cellar").join("glibc");
+/// Where glibc's dynamic linker for ELF `machine` lives: the path the
+/// toolchain bakes into binaries first, then where multiarch distributions
+/// (Debian, Ubuntu) keep the real file.
+fn system_ld_so_candidates(machine: u16) -> &'static [&'static str] {
+    match machine {
+        object::elf::EM_X86_64 => &[
+            "/lib64/ld-linux-x86-64.so.2",
+            "/usr/lib64/ld-linux-x86-64.so.2",
+            "/lib/x86_64-linux-gnu/ld-linux-x86-64.so.2",
+            "/usr/lib/x86_64-linux-gnu/ld-linux-x86-64.so.2",
+        ],
+        object::elf::EM_AARCH64 => &[
+            "/lib/ld-linux-aarch64.so.1",
+            "/usr/lib/ld-linux-aarch64.so.1",
+            "/lib/aarch64-linux-gnu/ld-linux-aarch64.so.1",
+            "/usr/lib/aarch64-linux-gnu/ld-linux-aarch64.so.1",
+            "/lib64/ld-linux-aarch64.so.1",
+        ],
+        object::elf::EM_ARM => &[
+            "/lib/ld-linux-armhf.so.3",
+            "/usr/lib/ld-linux-armhf.so.3",
+            "/lib/ld-linux.so.3",
+        ],
+        object::elf::EM_PPC64 => &["/lib64/ld64.so.2"],
+        object::elf::EM_S390 => &["/lib64/ld64.so.1"],
+        _ => &[],
+    }
+}
 
-    if !cellar.exists() {
-        return None;
+/// ELF machine of the host, for binaries built for the CPU we run on.
+fn host_elf_machine() -> u16 {
+    match std::env::consts::ARCH {
+        "x86_64" => object::elf::EM_X86_64,
+        "aarch64" => object::elf::EM_AARCH64,
+        "arm" => object::elf::EM_ARM,
+        "powerpc64" => object::elf::EM_PPC64,
+        "s390x" => object::elf::EM_S390,
+        _ => object::elf::EM_NONE,
     }
+}
 
-    // Look for glibc installations in the Cellar
-    let glibc_entries = match fs::read_dir(&cellar) {
-        Ok(entries) => entries,
-        Err(_) => return None,
-    };
+/// Detect if zerobrew has installed its own glibc and return the path to its ld.so
+/// interpreter for ELF `machine`. Returns None if zerobrew's glibc is not found,
+/// indicating we should use the system ld.so.
+fn detect_zerobrew_glibc(prefix_dir: &Path, machine: u16) -> Option<PathBuf> {
+    let cellar = prefix_dir.join("Cellar").join("glibc");
+    let names: Vec<&str> = system_ld_so_candidates(machine)
+        .iter()
+        .filter_map(|path| path.rsplit('/').next())
+        .collect();
 
     // Find the most recent glibc version directory
-    let mut glibc_versions: Vec<PathBuf> = glibc_entries
+    let mut glibc_versions: Vec<PathBuf> = fs::read_dir(&cellar)
+        .ok()?
         .filter_map(|e| e.ok())
         .map(|e| e.path())
         .filter(|p| p.is_dir())
         .collect();
 
-    if glibc_versions.is_empty() {
-        return None;
-    }
-
     // Sort to get the newest version (simple lexicographic sort should work for version numbers)
     glibc_versions.sort();
     glibc_versions.reverse();
 
-    // Look for the ld.so interpreter in the glibc lib directory
-    // Common names: ld-linux-x86-64.so.2, ld-linux-aarch64.so.1, ld-linux.so.2, etc.
-    for glibc_dir in glibc_versions {
+    glibc_versions.into_iter().find_map(|glibc_dir| {
         let lib_dir = glibc_dir.join("lib");
-        if !lib_dir.exists() {
-            continue;
-        }
-
-        let entries = match fs::read_dir(&lib_dir) {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
-
-        for entry in entries.filter_map(|e| e.ok()) {
-            let path = entry.path();
-            let filename = match path.file_name() {
-                Some(name) => name.to_string_lossy(),
-                None => continue,
-            };
-
-            // Match ld-linux*.so* patterns
-            if filename.starts_with("ld-linux") && filename.contains(".so") {
-                return Some(path);
-            }
-            // Also check for ld64.so.2 (ppc64)
-            if filename == "ld64.so.2" || filename.starts_with("ld64.so.") {
-                return Some(path);
-            }
-            // And ld-linux.so.* variants
-            if filename.starts_with("ld-linux.so.") {
-                return Some(path);
-            }
-        }
-    }
-
-    None
+        names
+            .iter()
+            .map(|name| lib_dir.join(name))
+            .find(|path| path.exists())
+    })
 }
 
-/// Find the system's dynamic linker (ld.so).
+/// Find the system's dynamic linker (ld.so) for ELF `machine`.
 /// Returns the path to the system ld.so if found, None otherwise.
-fn find_system_ld_so() -> Option<PathBuf> {
-    // Common paths for system dynamic linkers on Linux
-    let candidates = [
-        "/lib64/ld-linux-x86-64.so.2",     // x86_64
-        "/usr/lib64/ld-linux-x86-64.so.2", // x86_64
-        "/lib/ld-linux-aarch64.so.1",      // aarch64/ARM64
-        "/usr/lib/ld-linux-aarch64.so.1",  // aarch64/ARM64
-        "/lib/ld-linux-armhf.so.3",        // ARM hard float
-        "/usr/lib/ld-linux-armhf.so.3",    // ARM hard float
-        "/lib/ld-linux.so.3",              // ARM
-        "/lib/ld-linux.so.2",              // old ARM
-        "/lib64/ld64.so.2",                // ppc64
-        "/lib64/ld64.so.1",                // s390x
-    ];
-
-    for candidate in &candidates {
-        let path = PathBuf::from(candidate);
-        if path.exists() {
-            return Some(path);
-        }
-    }
+fn find_system_ld_so(machine: u16) -> Option<PathBuf> {
+    system_ld_so_candidates(machine)
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
 
-    None
+/// The interpreter for binaries of ELF `machine`: zerobrew's glibc when it
+/// is installed, else the system's.
+fn target_interpreter(prefix_dir: &Path, machine: u16) -> Option<PathBuf> {
+    detect_zerobrew_glibc(prefix_dir, machine).or_else(|| find_system_ld_so(machine))
+}
+
+/// The interpreter relocation gives binaries built for this host, which
+/// changes when zerobrew's glibc is installed or upgraded.
+pub fn host_interpreter(prefix_dir: &Path) -> Option<PathBuf> {
+    target_interpreter(prefix_dir, host_elf_machine())
 }
 
 /// Patch @@HOMEBREW_CELLAR@@ and @@HOMEBREW_PREFIX@@ placeholders in ELF binaries.
@@ -133,47 +132,19 @@ fn find_system_ld_so() -> Option<PathBuf> {
 fn patch_elf_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Error> {
     let lib_path = prefix_dir.join("lib").to_string_lossy().to_string();
 
-    // Detect if zerobrew has installed its own glibc
-    let zerobrew_interpreter = detect_zerobrew_glibc(prefix_dir);
-
-    // Determine which interpreter to use:
-    // - If zerobrew has glibc, use zerobrew's ld.so
-    // - Otherwise, use the system ld.so (fallback)
-    let target_interpreter = if let Some(ref zb_ld) = zerobrew_interpreter {
-        Some(zb_ld.clone())
-    } else {
-        // Find system ld.so - common paths for Linux
-        find_system_ld_so()
-    };
-
-    // Collect all ELF files
-    let elf_files: Vec<PathBuf> = walkdir::WalkDir::new(keg_path)
-        .follow_links(false)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter(|e| {
-            // Read only first 4 bytes to check magic
-            let mut file = match fs::File::open(e.path()) {
-                Ok(f) => f,
-                Err(_) => return false,
-            };
-            let mut magic = [0u8; 4];
-            if file.read_exact(&mut magic).is_ok() {
-                return magic == *b"\x7fELF";
-            }
-            false
-        })
-        .map(|e| e.path().to_path_buf())
-        .collect();
+    // Binaries for the host CPU are the common case, so resolve their
+    // interpreter once; foreign ones (e.g. under binfmt emulation) look
+    // theirs up per file.
+    let host_machine = host_elf_machine();
+    let host_interpreter = target_interpreter(prefix_dir, host_machine);
+
+    let elf_files = elf_files(keg_path);
 
     let patch_failures = AtomicUsize::new(0);
     // Use a dashmap or similar for thread-safe inode tracking if needed,
     // but we can just collect and then process, or use a Mutex.
     let processed_inodes = std::sync::Mutex::new(std::collections::HashSet::new());
 
-    // Clone for use in parallel closure
-    let target_interpreter = target_interpreter.clone();
     let new_prefix = prefix_dir.to_string_lossy().to_string();
 
     elf_files.par_iter().for_each(|path| {
@@ -256,6 +227,7 @@ fn patch_elf_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Erro
 
             if is_executable && let Some(current_interp_bytes) = elf.inner.elf_interpreter() {
                 let current_interp_str = String::from_utf8_lossy(current_interp_bytes);
+                let machine = elf.inner.builder().header.e_machine;
 
                 let target_interp_path = if current_interp_str.contains("@@HOMEBREW_PREFIX@@")
                     || current_interp_str.contains(LINUX_HOMEBREW_PREFIX)
@@ -265,10 +237,12 @@ fn patch_elf_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Erro
                     if expanded_path.exists() {
                         Some(expanded_path)
                     } else {
-                        find_system_ld_so()
+                        find_system_ld_so(machine)
                     }
+                } else if machine == host_machine {
+                    host_interpreter.clone()
                 } else {
-                    target_interpreter.clone()
+                    target_interpreter(prefix_dir, machine)
                 };
 
                 if let Some(target_path) = target_interp_path {
@@ -290,6 +264,7 @@ fn patch_elf_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Erro
             perms.set_mode(original_mode);
             fs::set_permissions(path, perms)?;
 
+            debug!(path = %path.display(), "relocated ELF interpreter and rpaths");
             Ok(())
         })();
 
@@ -310,6 +285,77 @@ fn patch_elf_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Erro
     Ok(())
 }
 
+/// Every ELF file in `keg_path`.
+fn elf_files(keg_path: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(keg_path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            // Read only first 4 bytes to check magic
+            let mut file = match fs::File::open(e.path()) {
+                Ok(f) => f,
+                Err(_) => return false,
+            };
+            let mut magic = [0u8; 4];
+            if file.read_exact(&mut magic).is_ok() {
+                return magic == *b"\x7fELF";
+            }
+            false
+        })
+        .map(|e| e.path().to_path_buf())
+        .collect()
+}
+
+/// Rewrite the build machine's Homebrew prefix where bottles compile it into
+/// ELF data, such as the dictionary path of wordnet. The loader never sees
+/// these strings, so patching the dynamic section leaves them behind. They
+/// are rewritten in place, NUL-padded, which only works when the prefix is
+/// no longer than Homebrew's; otherwise they are left and reported.
+fn patch_elf_data_strings(keg_path: &Path, prefix_dir: &Path) -> Result<(), Error> {
+    let old = LINUX_HOMEBREW_PREFIX.as_bytes();
+    let new_prefix = prefix_dir.to_string_lossy().into_owned();
+    let new = new_prefix.as_bytes();
+
+    elf_files(keg_path).par_iter().for_each(|path| {
+        let result = (|| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            let mut contents = fs::read(path)?;
+            if !contents.windows(old.len()).any(|w| w == old) {
+                return Ok(());
+            }
+            if new.len() > old.len() {
+                warn!(
+                    path = %path.display(),
+                    prefix = %new_prefix,
+                    "binary embeds {LINUX_HOMEBREW_PREFIX} paths that cannot be rewritten to a longer prefix"
+                );
+                return Ok(());
+            }
+            if super::rewrite_c_string_prefixes(&mut contents, old, new) == 0 {
+                return Ok(());
+            }
+
+            // As with text files, the keg file may be a hardlink into the
+            // store, so write a new file rather than through it.
+            let metadata = fs::metadata(path)?;
+            let temp_path = path.with_extension("tmp_patch");
+            fs::write(&temp_path, &contents)?;
+            fs::set_permissions(&temp_path, metadata.permissions())?;
+            fs::rename(temp_path, path)?;
+
+            debug!(path = %path.display(), "relocated Homebrew paths in ELF data");
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            warn!(path = %path.display(), error = %e, "failed to patch ELF data strings");
+        }
+    });
+
+    Ok(())
+}
+
 /// Patch text files containing @@HOMEBREW_...@@ placeholders
 fn patch_text_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Error> {
     let cellar_str = prefix_dir.join("Cellar").to_string_lossy().to_string();
@@ -355,26 +401,15 @@ fn patch_text_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Err
                 .replace("@@HOMEBREW_PERL@@", "/usr/bin/perl")
                 .replace("@@HOMEBREW_JAVA@@", "/usr/bin/java");
 
-            // Write back
-            // Check readonly
+            // Write a new file rather than through the old one: the keg file
+            // may be a hardlink into the store, which must stay untouched.
             let metadata = fs::metadata(path)?;
-            let original_mode = metadata.permissions().mode();
-            let is_readonly = original_mode & 0o200 == 0;
-
-            if is_readonly {
-                let mut perms = metadata.permissions();
-                perms.set_mode(original_mode | 0o200);
-                fs::set_permissions(path, perms)?;
-            }
-
-            fs::write(path, new_content)?;
-
-            if is_readonly {
-                let mut perms = metadata.permissions();
-                perms.set_mode(original_mode);
-                fs::set_permissions(path, perms)?;
-            }
+            let temp_path = path.with_extension("tmp_patch");
+            fs::write(&temp_path, new_content)?;
+            fs::set_permissions(&temp_path, metadata.permissions())?;
+            fs::rename(temp_path, path)?;
 
+            debug!(path = %path.display(), "relocated text placeholders");
             Ok(())
         })();
 
@@ -512,6 +547,48 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn patches_homebrew_paths_in_elf_data() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = PathBuf::from("/opt/zb");
+        let bin_dir = tmp.path().join("wordnet/3.1_2/bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+
+        let path = bin_dir.join("wn");
+        let mut contents = b"\x7fELF not really".to_vec();
+        contents.extend_from_slice(
+            b"\0/home/linuxbrew/.linuxbrew/Cellar/wordnet/3.1_2/share/wordnet/dict\0tail\0",
+        );
+        fs::write(&path, &contents).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o555)).unwrap();
+
+        patch_elf_data_strings(tmp.path(), &prefix).unwrap();
+        let patched = fs::read(&path).unwrap();
+        assert_eq!(patched.len(), contents.len());
+        let strings: Vec<&[u8]> = patched
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .collect();
+        assert_eq!(
+            strings[1..],
+            [
+                &b"/opt/zb/Cellar/wordnet/3.1_2/share/wordnet/dict"[..],
+                b"tail"
+            ]
+        );
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o555
+        );
+
+        // A prefix longer than Homebrew's leaves the binary untouched.
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, &contents).unwrap();
+        patch_elf_data_strings(tmp.path(), Path::new("/a/prefix/longer/than/linuxbrew/s")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), contents);
+    }
+
     #[test]
     #[cfg(target_os = "linux")]
     fn test_glibc_detection() {
@@ -519,7 +596,7 @@ mod tests {
         let prefix = tmp.path().join("prefix");
 
         // Test 1: No glibc installed - should return None
-        assert!(detect_zerobrew_glibc(&prefix).is_none());
+        assert!(detect_zerobrew_glibc(&prefix, object::elf::EM_X86_64).is_none());
 
         // Test 2: Create a mock glibc installation
         let glibc_dir = prefix.join("Cellar/glibc/2.38");
@@ -530,10 +607,11 @@ mod tests {
         let ld_so = lib_dir.join("ld-linux-x86-64.so.2");
         fs::write(&ld_so, "mock").unwrap();
 
-        // Should now detect the glibc
-        let detected = detect_zerobrew_glibc(&prefix);
+        // Should now detect the glibc, but only for x86_64 binaries
+        let detected = detect_zerobrew_glibc(&prefix, object::elf::EM_X86_64);
         assert!(detected.is_some());
         assert_eq!(detected.unwrap(), ld_so);
+        assert!(detect_zerobrew_glibc(&prefix, object::elf::EM_AARCH64).is_none());
 
         // Test 3: Multiple glibc versions - should pick the newest
         let glibc_dir_newer = prefix.join("Cellar/glibc/2.39");
@@ -542,8 +620,35 @@ mod tests {
         let ld_so_newer = lib_dir_newer.join("ld-linux-x86-64.so.2");
         fs::write(&ld_so_newer, "mock").unwrap();
 
-        let detected = detect_zerobrew_glibc(&prefix);
+        let detected = detect_zerobrew_glibc(&prefix, object::elf::EM_X86_64);
         assert!(detected.is_some());
         assert_eq!(detected.unwrap(), ld_so_newer);
+
+        // Test 4: An aarch64 glibc provides ld-linux-aarch64.so.1
+        let ld_so_arm = lib_dir_newer.join("ld-linux-aarch64.so.1");
+        fs::write(&ld_so_arm, "mock").unwrap();
+        assert_eq!(
+            detect_zerobrew_glibc(&prefix, object::elf::EM_AARCH64),
+            Some(ld_so_arm)
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn system_interpreter_matches_the_binary_arch() {
+        assert_eq!(
+            system_ld_so_candidates(object::elf::EM_AARCH64)[0],
+            "/lib/ld-linux-aarch64.so.1"
+        );
+        assert_eq!(
+            system_ld_so_candidates(object::elf::EM_X86_64)[0],
+            "/lib64/ld-linux-x86-64.so.2"
+        );
+        assert!(
+            system_ld_so_candidates(object::elf::EM_X86_64)
+                .iter()
+                .all(|path| !path.contains("aarch64"))
+        );
+        assert_ne!(host_elf_machine(), object::elf::EM_NONE);
     }
 }
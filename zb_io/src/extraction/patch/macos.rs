@@ -1,8 +1,10 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use tracing::warn;
+use tracing::{debug, warn};
 use zb_core::Error;
 
+use super::rewrite_c_string_prefixes;
+
 const HOMEBREW_PREFIXES: &[&str] = &[
     "/opt/homebrew",
     "/usr/local/Homebrew",
@@ -152,19 +154,8 @@ fn patch_macho_binary_strings(path: &Path, new_prefix: &str) -> Result<(), Error
             continue;
         }
 
-        let mut i = 0;
-        while i + old_bytes.len() <= contents.len() {
-            if contents[i..i + old_bytes.len()] == *old_bytes
-                && matches!(
-                    contents.get(i + old_bytes.len()).copied(),
-                    None | Some(0) | Some(b'/')
-                )
-            {
-                contents[i..i + new_bytes.len()].copy_from_slice(new_bytes);
-                contents[i + new_bytes.len()..i + old_bytes.len()].fill(0);
-                patched = true;
-            }
-            i += 1;
+        if rewrite_c_string_prefixes(&mut contents, old_bytes, new_bytes) > 0 {
+            patched = true;
         }
     }
 
@@ -222,6 +213,7 @@ fn patch_macho_binary_strings(path: &Path, new_prefix: &str) -> Result<(), Error
 pub fn patch_homebrew_placeholders(
     keg_path: &Path,
     cellar_dir: &Path,
+    prefix: &Path,
     pkg_name: &str,
     pkg_version: &str,
 ) -> Result<(), Error> {
@@ -232,9 +224,6 @@ pub fn patch_homebrew_placeholders(
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::{Arc, Mutex};
 
-    // Derive prefix from cellar (cellar_dir is typically prefix/Cellar)
-    let prefix = cellar_dir.parent().unwrap_or(Path::new("/opt/homebrew"));
-
     let cellar_str = cellar_dir.to_string_lossy().to_string();
     let prefix_str = prefix.to_string_lossy().to_string();
 
@@ -439,13 +428,19 @@ pub fn patch_homebrew_placeholders(
     Ok(())
 }
 
+/// Binaries checked per `codesign --verify` process.
+const VERIFY_BATCH_SIZE: usize = 64;
+
 /// Strip quarantine extended attributes and ad-hoc sign unsigned Mach-O binaries.
 /// Homebrew bottles from ghcr.io are already adhoc signed, so this is mostly a no-op.
-/// We use a fast heuristic: only process binaries that fail signature verification.
+/// We use a fast heuristic: only process binaries that fail signature verification,
+/// verified in batches so a keg with hundreds of binaries (llvm) spawns a handful
+/// of `codesign` processes rather than one per binary.
 pub fn codesign_and_strip_xattrs(keg_path: &Path) -> Result<(), Error> {
     use rayon::prelude::*;
     use std::os::unix::fs::PermissionsExt;
     use std::process::Command;
+    use std::time::Instant;
 
     // First, do a quick recursive xattr strip (single command, very fast)
     let _ = Command::new("xattr")
@@ -470,33 +465,16 @@ pub fn codesign_and_strip_xattrs(keg_path: &Path) -> Result<(), Error> {
         .map(|e| e.path().to_path_buf())
         .collect();
 
-    // Only process files that need signing
-    bin_files.par_iter().for_each(|path| {
-        // Quick check: is it a Mach-O?
-        let data = match fs::read(path) {
-            Ok(d) if d.len() >= 4 => d,
-            _ => return,
-        };
-        let magic = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
-        let is_macho = matches!(
-            magic,
-            0xfeedface | 0xfeedfacf | 0xcafebabe | 0xcefaedfe | 0xcffaedfe
-        );
-        if !is_macho {
-            return;
-        }
-
-        // Verify signature - if valid, skip
-        let verify = Command::new("codesign")
-            .args(["-v", &path.to_string_lossy()])
-            .stderr(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .status();
-
-        if verify.map(|s| s.success()).unwrap_or(false) {
-            return; // Already signed
-        }
+    let started = Instant::now();
+    let machos: Vec<PathBuf> = bin_files.into_par_iter().filter(|p| is_macho(p)).collect();
+    let unsigned: Vec<PathBuf> = machos
+        .par_chunks(VERIFY_BATCH_SIZE)
+        .flat_map_iter(unsigned_binaries)
+        .collect();
+    let verify_elapsed = started.elapsed();
 
+    // Only process files that need signing
+    unsigned.par_iter().for_each(|path| {
         // Get permissions and make writable
         let metadata = match fs::metadata(path) {
             Ok(m) => m,
@@ -524,9 +502,59 @@ pub fn codesign_and_strip_xattrs(keg_path: &Path) -> Result<(), Error> {
         }
     });
 
+    debug!(
+        keg = %keg_path.display(),
+        binaries = machos.len(),
+        batches = machos.len().div_ceil(VERIFY_BATCH_SIZE),
+        signed = unsigned.len(),
+        verify_ms = verify_elapsed.as_millis() as u64,
+        sign_ms = (started.elapsed() - verify_elapsed).as_millis() as u64,
+        "checked Mach-O signatures"
+    );
+
     Ok(())
 }
 
+/// Whether `path` starts with a Mach-O or fat binary magic number.
+fn is_macho(path: &Path) -> bool {
+    use std::io::Read as _;
+
+    let mut magic = [0u8; 4];
+    let read = fs::File::open(path).and_then(|mut file| file.read_exact(&mut magic));
+    read.is_ok()
+        && matches!(
+            u32::from_be_bytes(magic),
+            0xfeedface | 0xfeedfacf | 0xcafebabe | 0xcefaedfe | 0xcffaedfe
+        )
+}
+
+/// The binaries in `batch` whose signature does not verify. The whole batch
+/// is checked by one `codesign` process, which fails if any binary does; a
+/// failing batch is split in half until the culprits are found.
+fn unsigned_binaries(batch: &[PathBuf]) -> Vec<PathBuf> {
+    if batch.is_empty() || signatures_verify(batch) {
+        return Vec::new();
+    }
+    if batch.len() == 1 {
+        return batch.to_vec();
+    }
+    let (left, right) = batch.split_at(batch.len() / 2);
+    let mut unsigned = unsigned_binaries(left);
+    unsigned.extend(unsigned_binaries(right));
+    unsigned
+}
+
+fn signatures_verify(paths: &[PathBuf]) -> bool {
+    std::process::Command::new("codesign")
+        .arg("--verify")
+        .args(paths)
+        .stderr(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -700,4 +728,20 @@ echo "Hello from $PREFIX"
         assert!(patched.contains("/opt/zerobrew/prefix/Library"));
         assert!(patched.contains("/usr/bin/perl"));
     }
+
+    #[test]
+    fn unsigned_binaries_are_found_within_a_batch() {
+        let tmp = TempDir::new().unwrap();
+        let signed = tmp.path().join("true");
+        fs::copy("/usr/bin/true", &signed).unwrap();
+        let unsigned = tmp.path().join("fake");
+        fs::write(&unsigned, b"\xcf\xfa\xed\xfenot really a binary").unwrap();
+        let text = tmp.path().join("script");
+        fs::write(&text, "#!/bin/sh\n").unwrap();
+
+        assert!(is_macho(&signed) && is_macho(&unsigned) && !is_macho(&text));
+        let batch = vec![signed.clone(), unsigned.clone(), signed];
+        assert_eq!(unsigned_binaries(&batch), [unsigned]);
+        assert!(unsigned_binaries(&batch[..1]).is_empty());
+    }
 }
@@ -9,3 +9,57 @@ pub use linux::patch_placeholders;
 
 #[cfg(target_os = "macos")]
 pub use macos::{codesign_and_strip_xattrs, patch_homebrew_placeholders};
+
+/// Rewrite every path in `contents` that starts with `old` (followed by `/`
+/// or the end of its NUL-terminated string) to start with `new` instead,
+/// in place. The rest of the string moves up behind `new` and the bytes it
+/// frees are NUL-padded, so offsets into the file stay valid. `new` must not
+/// be longer than `old`. Returns the number of paths rewritten.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub(crate) fn rewrite_c_string_prefixes(contents: &mut [u8], old: &[u8], new: &[u8]) -> usize {
+    assert!(new.len() <= old.len(), "replacement must not grow the file");
+    let mut rewritten = 0;
+    let mut i = 0;
+    while i + old.len() <= contents.len() {
+        let at_boundary = matches!(contents.get(i + old.len()).copied(), None | Some(0 | b'/'));
+        if !at_boundary || contents[i..i + old.len()] != *old {
+            i += 1;
+            continue;
+        }
+        let end = contents[i..]
+            .iter()
+            .position(|&b| b == 0)
+            .map_or(contents.len(), |n| i + n);
+        contents[i..i + new.len()].copy_from_slice(new);
+        contents.copy_within(i + old.len()..end, i + new.len());
+        contents[end - (old.len() - new.len())..end].fill(0);
+        rewritten += 1;
+        i += new.len().max(1);
+    }
+    rewritten
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewritten_paths_keep_their_tails_and_the_file_length() {
+        let mut contents =
+            b"\0/home/linuxbrew/.linuxbrew/share/dict\0/home/linuxbrew/.linuxbrewery\0".to_vec();
+        let length = contents.len();
+        let rewritten =
+            rewrite_c_string_prefixes(&mut contents, b"/home/linuxbrew/.linuxbrew", b"/opt/zb");
+        assert_eq!(rewritten, 1);
+        assert_eq!(contents.len(), length);
+        let strings: Vec<&[u8]> = contents
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .collect();
+        assert_eq!(
+            strings,
+            [&b"/opt/zb/share/dict"[..], b"/home/linuxbrew/.linuxbrewery"]
+        );
+    }
+}
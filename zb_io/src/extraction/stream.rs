@@ -0,0 +1,141 @@
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use tokio::sync::{OwnedSemaphorePermit, mpsc};
+use tokio::task::JoinHandle;
+
+use zb_core::Error;
+
+use super::extract::{ExtractPolicy, extract_tar_stream};
+
+/// Chunks buffered between the download and the extractor before the
+/// download waits for the extractor to catch up.
+const STREAM_BUFFER_CHUNKS: usize = 64;
+
+/// Unpacks an archive on a blocking thread while its bytes are still
+/// arriving, so a download can be written to the cache and the store in a
+/// single pass.
+pub struct StreamingExtractor {
+    sender: Option<mpsc::Sender<Bytes>>,
+    handle: JoinHandle<Result<(), Error>>,
+    /// Released once extraction has finished.
+    slot: Option<OwnedSemaphorePermit>,
+}
+
+impl StreamingExtractor {
+    pub fn spawn(dest_dir: PathBuf, policy: ExtractPolicy) -> Self {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+        let handle = tokio::task::spawn_blocking(move || {
+            let reader = ChannelReader {
+                receiver,
+                current: Bytes::new(),
+            };
+            extract_tar_stream(reader, &dest_dir, &policy)
+        });
+        Self {
+            sender: Some(sender),
+            handle,
+            slot: None,
+        }
+    }
+
+    /// Hold `slot` until extraction finishes.
+    pub fn holding(mut self, slot: OwnedSemaphorePermit) -> Self {
+        self.slot = Some(slot);
+        self
+    }
+
+    /// Hand the next chunk of the archive to the extractor. Chunks sent after
+    /// the extractor finished or failed are dropped; `finish` reports why.
+    pub async fn feed(&mut self, chunk: Bytes) {
+        if let Some(sender) = &self.sender
+            && sender.send(chunk).await.is_err()
+        {
+            self.sender = None;
+        }
+    }
+
+    /// Signal the end of the archive and wait for extraction to complete.
+    pub async fn finish(mut self) -> Result<(), Error> {
+        self.sender = None;
+        self.handle
+            .await
+            .map_err(Error::store("streaming extraction task failed"))?
+    }
+}
+
+/// Blocking reader over the chunks sent to a `StreamingExtractor`.
+struct ChannelReader {
+    receiver: mpsc::Receiver<Bytes>,
+    current: Bytes,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.current = chunk,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn tarball() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let content = b"#!/bin/sh\necho hi\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_path("tool/1.0/bin/tool").unwrap();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append(&header, &content[..]).unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn extracts_archive_fed_in_small_chunks() {
+        let tmp = TempDir::new().unwrap();
+        let mut extractor =
+            StreamingExtractor::spawn(tmp.path().to_path_buf(), ExtractPolicy::bottle());
+        for chunk in tarball().chunks(7) {
+            extractor.feed(Bytes::copy_from_slice(chunk)).await;
+        }
+        extractor.finish().await.unwrap();
+
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("tool/1.0/bin/tool")).unwrap(),
+            "#!/bin/sh\necho hi\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn truncated_archive_fails() {
+        let tmp = TempDir::new().unwrap();
+        let mut extractor =
+            StreamingExtractor::spawn(tmp.path().to_path_buf(), ExtractPolicy::bottle());
+        let archive = tarball();
+        extractor
+            .feed(Bytes::copy_from_slice(&archive[..archive.len() / 2]))
+            .await;
+        assert!(extractor.finish().await.is_err());
+    }
+}
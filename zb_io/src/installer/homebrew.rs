@@ -105,10 +105,10 @@ pub fn get_homebrew_packages() -> Result<HomebrewMigrationPackages, Error> {
     let leaves_output = Command::new("brew")
         .args(["leaves"])
         .output()
-        .map_err(Error::exec("failed to run 'brew leaves'"))?;
+        .map_err(Error::migration("failed to run 'brew leaves'"))?;
 
     if !leaves_output.status.success() {
-        return Err((Error::exec("brew leaves failed"))(
+        return Err((Error::migration("brew leaves failed"))(
             String::from_utf8_lossy(&leaves_output.stderr),
         ));
     }
@@ -122,16 +122,16 @@ pub fn get_homebrew_packages() -> Result<HomebrewMigrationPackages, Error> {
             .args(["info", "--json=v1"])
             .args(&leaves)
             .output()
-            .map_err(Error::exec("failed to run 'brew info'"))?;
+            .map_err(Error::migration("failed to run 'brew info'"))?;
 
         if !formulas_output.status.success() {
-            return Err((Error::exec("brew info failed"))(String::from_utf8_lossy(
-                &formulas_output.stderr,
-            )));
+            return Err((Error::migration("brew info failed"))(
+                String::from_utf8_lossy(&formulas_output.stderr),
+            ));
         }
 
         let formulas_json: serde_json::Value = serde_json::from_slice(&formulas_output.stdout)
-            .map_err(Error::exec("failed to parse brew info JSON"))?;
+            .map_err(Error::migration("failed to parse brew info JSON"))?;
 
         parse_formulas_from_json(&formulas_json)
     };
@@ -139,10 +139,10 @@ pub fn get_homebrew_packages() -> Result<HomebrewMigrationPackages, Error> {
     let casks_output = Command::new("brew")
         .args(["list", "--cask"])
         .output()
-        .map_err(Error::exec("failed to run 'brew list --cask'"))?;
+        .map_err(Error::migration("failed to run 'brew list --cask'"))?;
 
     if !casks_output.status.success() {
-        return Err((Error::exec("brew list --cask failed"))(
+        return Err((Error::migration("brew list --cask failed"))(
             String::from_utf8_lossy(&casks_output.stderr),
         ));
     }
@@ -152,6 +152,23 @@ pub fn get_homebrew_packages() -> Result<HomebrewMigrationPackages, Error> {
     let all_packages: Vec<HomebrewPackage> = formulas.into_iter().chain(casks).collect();
     Ok(categorize_packages(all_packages))
 }
+
+/// Stop the service Homebrew runs for `name` and remove its launch agent or
+/// unit, so it does not come back at the next login.
+pub fn stop_homebrew_service(name: &str) -> Result<(), Error> {
+    let output = Command::new("brew")
+        .args(["services", "stop", name])
+        .output()
+        .map_err(Error::migration("failed to run 'brew services stop'"))?;
+
+    if !output.status.success() {
+        return Err((Error::migration("brew services stop failed"))(
+            String::from_utf8_lossy(&output.stderr),
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
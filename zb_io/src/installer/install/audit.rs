@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use tracing::warn;
+use zb_core::{Error, formula_token};
+
+use crate::cellar::audit::{RelocationAudit, audit_keg};
+use crate::cellar::materialize::Cellar;
+use crate::cellar::shebang::fix_shebangs;
+
+use super::Installer;
+
+impl Installer {
+    /// Audit every bottle right after relocating it, failing the install
+    /// when the keg references libraries that do not exist.
+    pub fn with_paranoid(mut self, paranoid: bool) -> Self {
+        self.paranoid = paranoid;
+        self
+    }
+
+    /// Check the installed keg of `name` for library and interpreter
+    /// references that do not resolve, and for data paths that do not
+    /// exist.
+    pub fn audit_relocation(&self, name: &str) -> Result<RelocationAudit, Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let cellar = self.installed_cellar(name);
+        let keg_path = cellar.keg_path(formula_token(name), &installed.version);
+        audit_keg(&keg_path, &self.prefix, cellar.dir())
+    }
+
+    /// Point the scripts of a freshly relocated `keg_path` at interpreters
+    /// under the prefix, or at `/usr/bin/env` when theirs does not exist,
+    /// warning about each script whose interpreter is missing.
+    pub(super) fn fix_script_interpreters(
+        &self,
+        name: &str,
+        keg_path: &Path,
+        cellar: &Cellar,
+        relocated: bool,
+    ) -> Result<(), Error> {
+        if !relocated {
+            return Ok(());
+        }
+        for missing in fix_shebangs(keg_path, &self.prefix, cellar.dir())? {
+            warn!(
+                formula = %name,
+                file = %missing.file.display(),
+                interpreter = %missing.reference,
+                suggestion = missing.suggestion.as_deref().unwrap_or(""),
+                "script interpreter does not exist"
+            );
+        }
+        Ok(())
+    }
+
+    /// Verify the references of a freshly poured `keg_path`. Relocated
+    /// kegs are always audited and warn about dangling references and
+    /// missing data paths; in paranoid mode every keg is audited and any
+    /// dangling reference fails the install.
+    pub(super) fn check_relocation(
+        &self,
+        name: &str,
+        keg_path: &Path,
+        cellar: &Cellar,
+        relocated: bool,
+    ) -> Result<(), Error> {
+        if !self.paranoid && !relocated {
+            return Ok(());
+        }
+        let audit = audit_keg(keg_path, &self.prefix, cellar.dir())?;
+        if self.paranoid {
+            return audit.ensure_clean(name);
+        }
+        for dangling in &audit.dangling {
+            warn!(
+                formula = %name,
+                file = %dangling.file.display(),
+                reference = %dangling.reference,
+                "relocated keg references a missing library"
+            );
+        }
+        for missing in &audit.missing_data {
+            warn!(
+                formula = %name,
+                file = %missing.file.display(),
+                path = %missing.reference,
+                "relocated keg references a missing data path"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+use zb_core::{BinaryTarget, Error, SelectedBinary};
+
+use crate::network::download::DownloadRequest;
+use crate::progress::InstallProgress;
+
+use super::{Installer, PlannedInstall};
+
+impl Installer {
+    /// Install `item` from the release binaries its formula ships: the
+    /// download is unpacked into the store when it is an archive, and each
+    /// listed binary is copied into the keg's `bin`.
+    pub(super) async fn install_binary(
+        &mut self,
+        item: &PlannedInstall,
+        binary: &SelectedBinary,
+        link: bool,
+        report: &impl Fn(InstallProgress),
+    ) -> Result<(), Error> {
+        let started = Instant::now();
+        let formula_name = &item.formula.name;
+        let version = item.formula.effective_version();
+
+        self.import_file_url(&binary.url, &binary.sha256)?;
+        let blob_path = self
+            .downloader
+            .download_single(
+                DownloadRequest {
+                    url: binary.url.clone(),
+                    sha256: binary.sha256.clone(),
+                    name: formula_name.clone(),
+                },
+                None,
+            )
+            .await?;
+
+        let keg_path = self.cellar.keg_path(formula_name, &version);
+        let staged = if crate::extraction::is_archive(&blob_path)? {
+            self.store
+                .ensure_entry(&binary.sha256, &blob_path)
+                .and_then(|entry| {
+                    let root = strip_components(&entry, binary.strip_components)?;
+                    stage_archive_binaries(&root, &keg_path, &binary.binaries)
+                })
+        } else {
+            stage_raw_binary(&blob_path, &keg_path, &binary.binaries)
+        };
+        if let Err(e) = staged {
+            Self::cleanup_materialized(&self.cellar, formula_name, &version);
+            return Err(e);
+        }
+
+        report(InstallProgress::UnpackCompleted {
+            name: formula_name.clone(),
+        });
+        self.register_keg(item, &binary.sha256, "binary", started, link, report)
+    }
+}
+
+/// The directory archive paths are relative to once `strip` leading
+/// directories are dropped. Each stripped level must be a lone directory;
+/// with `strip` unset, a lone top-level directory is stepped into.
+fn strip_components(root: &Path, strip: Option<usize>) -> Result<PathBuf, Error> {
+    let mut dir = root.to_path_buf();
+    for level in 0..strip.unwrap_or(1) {
+        let entries = fs::read_dir(&dir)
+            .map_err(Error::store("failed to read release archive"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to read release archive"))?;
+        match entries.as_slice() {
+            [entry] if entry.path().is_dir() => dir = entry.path(),
+            _ if strip.is_none() => break,
+            _ => {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "cannot strip {} path components: level {} of the release archive is not a single directory",
+                        strip.unwrap_or_default(),
+                        level + 1
+                    ),
+                });
+            }
+        }
+    }
+    Ok(dir)
+}
+
+fn stage_archive_binaries(
+    root: &Path,
+    keg_path: &Path,
+    binaries: &[BinaryTarget],
+) -> Result<(), Error> {
+    for binary in binaries {
+        let source = Path::new(&binary.source);
+        if source.is_absolute()
+            || source
+                .components()
+                .any(|component| matches!(component, Component::ParentDir))
+        {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "release binary source '{}' must be a relative path inside the archive",
+                    binary.source
+                ),
+            });
+        }
+        let source = root.join(source);
+        if !source.is_file() {
+            return Err(Error::InvalidArgument {
+                message: format!("release binary '{}' not found in archive", binary.source),
+            });
+        }
+        install_executable(&source, keg_path, &binary.target)?;
+    }
+    Ok(())
+}
+
+/// A download that is the binary itself can only provide one.
+fn stage_raw_binary(
+    blob_path: &Path,
+    keg_path: &Path,
+    binaries: &[BinaryTarget],
+) -> Result<(), Error> {
+    let [binary] = binaries else {
+        return Err(Error::InvalidArgument {
+            message: format!(
+                "release download is a raw binary but {} binaries are listed; expected exactly 1",
+                binaries.len()
+            ),
+        });
+    };
+    install_executable(blob_path, keg_path, &binary.target)
+}
+
+/// Copy `source` to `bin/<target>` in the keg and make it executable.
+fn install_executable(source: &Path, keg_path: &Path, target: &str) -> Result<(), Error> {
+    if target.is_empty() || target.contains('/') || target == "." || target == ".." {
+        return Err(Error::InvalidArgument {
+            message: format!("release binary target '{target}' must be a plain file name"),
+        });
+    }
+    let bin_dir = keg_path.join("bin");
+    fs::create_dir_all(&bin_dir).map_err(Error::store("failed to create keg bin dir"))?;
+
+    let target_path = bin_dir.join(target);
+    if target_path.exists() {
+        fs::remove_file(&target_path)
+            .map_err(Error::store("failed to replace existing release binary"))?;
+    }
+    fs::copy(source, &target_path).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to stage release binary '{target}': {e}"),
+    })?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&target_path, fs::Permissions::from_mode(0o755))
+            .map_err(Error::store("failed to make release binary executable"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+
+    fn release_zip() -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, body) in [
+            ("tool-2.0.0/tool", "#!/bin/sh\necho tool\n"),
+            ("tool-2.0.0/extra/toolctl", "#!/bin/sh\necho ctl\n"),
+            ("tool-2.0.0/README.md", "docs"),
+        ] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(body.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn installs_release_binaries_from_an_archive() {
+        let server = MockServer::start().await;
+        let archive = release_zip();
+        let sha = sha256_hex(&archive);
+        let key =
+            zb_core::formula::binary_platform_key(std::env::consts::OS, std::env::consts::ARCH)
+                .unwrap();
+        let formula = serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "2.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": {} } },
+            "binary": {
+                "url": format!("{}/releases/v{{version}}/tool-{{os}}-{{arch}}.zip", server.uri()),
+                "platforms": { key.as_str(): { "sha256": sha } },
+                "binaries": ["tool", { "source": "extra/toolctl", "target": "tctl" }],
+            }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/tool.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/releases/v2.0.0/tool-{key}.zip")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(archive))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &server.uri());
+
+        let plan = installer.plan(&["tool".to_string()]).await.unwrap();
+        assert!(matches!(
+            plan.items[0].method,
+            zb_core::InstallMethod::Binary(_)
+        ));
+        installer.execute(plan, true).await.unwrap();
+
+        let keg = root.join("cellar/tool/2.0.0");
+        assert_eq!(
+            fs::read_to_string(keg.join("bin/tool")).unwrap(),
+            "#!/bin/sh\necho tool\n"
+        );
+        assert!(keg.join("bin/tctl").is_file());
+        assert!(!keg.join("bin/README.md").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(keg.join("bin/tool")).unwrap().permissions();
+            assert_eq!(mode.mode() & 0o111, 0o111);
+        }
+        assert!(prefix.join("bin/tctl").exists());
+        assert_eq!(installer.get_installed("tool").unwrap().store_key, sha);
+    }
+
+    #[test]
+    fn strip_components_descends_single_directories() {
+        let tmp = TempDir::new().unwrap();
+        fs::create_dir_all(tmp.path().join("tool-1.0/bin")).unwrap();
+        fs::write(tmp.path().join("tool-1.0/bin/tool"), "").unwrap();
+
+        assert_eq!(
+            strip_components(tmp.path(), None).unwrap(),
+            tmp.path().join("tool-1.0")
+        );
+        assert_eq!(strip_components(tmp.path(), Some(0)).unwrap(), tmp.path());
+        assert_eq!(
+            strip_components(tmp.path(), Some(2)).unwrap(),
+            tmp.path().join("tool-1.0/bin")
+        );
+        assert!(strip_components(tmp.path(), Some(3)).is_err());
+
+        // Without an explicit count, an archive with several top-level
+        // entries is used as is.
+        fs::write(tmp.path().join("LICENSE"), "").unwrap();
+        assert_eq!(strip_components(tmp.path(), None).unwrap(), tmp.path());
+    }
+
+    #[test]
+    fn staging_rejects_paths_outside_the_archive() {
+        let tmp = TempDir::new().unwrap();
+        let keg = tmp.path().join("keg");
+        let binaries = |source: &str, target: &str| {
+            vec![BinaryTarget {
+                source: source.to_string(),
+                target: target.to_string(),
+            }]
+        };
+
+        assert!(stage_archive_binaries(tmp.path(), &keg, &binaries("../tool", "tool")).is_err());
+        assert!(stage_archive_binaries(tmp.path(), &keg, &binaries("/bin/sh", "sh")).is_err());
+        fs::write(tmp.path().join("tool"), "").unwrap();
+        assert!(stage_archive_binaries(tmp.path(), &keg, &binaries("tool", "../tool")).is_err());
+        stage_archive_binaries(tmp.path(), &keg, &binaries("tool", "tool")).unwrap();
+        assert!(keg.join("bin/tool").is_file());
+    }
+}
@@ -1,26 +1,52 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use tracing::warn;
+use tracing::{debug, warn};
 use zb_core::{Error, InstallMethod, formula_token};
 
 use crate::cellar::link::Linker;
-use crate::cellar::materialize::Cellar;
+use crate::cellar::materialize::{Cellar, disk_usage};
 use crate::installer::cask::resolve_cask;
 use crate::network::download::{DownloadProgressCallback, DownloadRequest, DownloadResult};
 use crate::progress::InstallProgress;
+use crate::storage::db::InstallStat;
+use crate::transcript::TranscriptEvent;
+
+use super::files::keg_manifest;
+use super::stats::millis;
+use super::timings::InstallPhase;
+use super::{Installer, MAX_CORRUPTION_RETRIES, PlannedInstall, REPEATED_CORRUPTION_THRESHOLD};
+
+/// A bottle unpacked into its keg but not yet recorded or linked, so it
+/// can still be removed without a trace.
+pub(super) struct MaterializedBottle {
+    keg_path: PathBuf,
+    store_entry: PathBuf,
+    started: Instant,
+    relocation_ms: Option<u64>,
+}
 
-use super::{Installer, MAX_CORRUPTION_RETRIES, PlannedInstall};
+impl MaterializedBottle {
+    pub(super) fn discard(self, installer: &Installer, item: &PlannedInstall) {
+        Installer::cleanup_materialized(
+            &installer.cellar_for(installer.arch),
+            &item.formula.name,
+            &item.formula.effective_version(),
+        );
+    }
+}
 
 impl Installer {
-    pub(super) async fn process_bottle_item(
+    /// Unpack the bottle of `item` into its keg, relocated and checked,
+    /// leaving the database and the prefix untouched.
+    pub(super) async fn materialize_bottle_item(
         &mut self,
         item: &PlannedInstall,
         download: &DownloadResult,
         download_progress: &Option<DownloadProgressCallback>,
-        link: bool,
         report: &impl Fn(InstallProgress),
-    ) -> Result<(), Error> {
+    ) -> Result<MaterializedBottle, Error> {
         let InstallMethod::Bottle(ref bottle) = item.method else {
             unreachable!()
         };
@@ -28,6 +54,8 @@ impl Installer {
         let formula_name = &item.formula.name;
         let version = item.formula.effective_version();
         let store_key = &bottle.sha256;
+        let started = Instant::now();
+        self.report_phase(install_name, InstallPhase::Download, download.elapsed);
 
         report(InstallProgress::UnpackStarted {
             name: formula_name.clone(),
@@ -36,26 +64,109 @@ impl Installer {
         let store_entry = self
             .extract_with_retry(download, &item.formula, bottle, download_progress.clone())
             .await?;
+        self.report_phase(install_name, InstallPhase::Extract, started.elapsed());
 
-        let keg_path = self
-            .cellar
-            .materialize(formula_name, &version, &store_entry)?;
+        if let Some(base) = item.delta_base.as_deref()
+            && base != store_key
+        {
+            self.share_with_delta_base(install_name, store_key, base);
+        }
+
+        self.store.verify_entry(store_key)?;
+        let cellar = self.cellar_for(self.arch);
+        let relocate = bottle.cellar.needs_relocation();
+        let materialize_started = Instant::now();
+        let (keg_path, relocation) =
+            cellar.materialize_timed(formula_name, &version, &store_entry, relocate)?;
+        self.fix_script_interpreters(formula_name, &keg_path, &cellar, relocate)
+            .and_then(|()| self.check_relocation(formula_name, &keg_path, &cellar, relocate))
+            .inspect_err(|_| Self::cleanup_materialized(&cellar, formula_name, &version))?;
+        let materialize = materialize_started.elapsed();
+        self.report_phase(
+            install_name,
+            InstallPhase::Materialize,
+            materialize.saturating_sub(relocation),
+        );
+        self.report_phase(install_name, InstallPhase::Relocate, relocation);
+        let relocation_ms = relocate.then(|| millis(materialize));
+        self.record_event(TranscriptEvent::Relocation {
+            formula: install_name.clone(),
+            keg: keg_path.clone(),
+            relocated: relocate,
+        });
 
         report(InstallProgress::UnpackCompleted {
             name: formula_name.clone(),
         });
 
+        Ok(MaterializedBottle {
+            keg_path,
+            store_entry,
+            started,
+            relocation_ms,
+        })
+    }
+
+    /// Record a materialized bottle as installed and link it.
+    pub(super) fn finish_bottle_item(
+        &mut self,
+        item: &PlannedInstall,
+        download: &DownloadResult,
+        materialized: MaterializedBottle,
+        link: bool,
+        report: &impl Fn(InstallProgress),
+    ) -> Result<(), Error> {
+        let InstallMethod::Bottle(ref bottle) = item.method else {
+            unreachable!()
+        };
+        let install_name = &item.install_name;
+        let formula_name = &item.formula.name;
+        let version = item.formula.effective_version();
+        let store_key = &bottle.sha256;
+        let cellar = self.cellar_for(self.arch);
+        let MaterializedBottle {
+            keg_path,
+            store_entry,
+            started,
+            relocation_ms,
+        } = materialized;
+
+        self.write_receipt(item, &keg_path);
+        self.write_env_hint(item, &keg_path);
+        let manifest = keg_manifest(&keg_path)
+            .inspect_err(
+                |e| debug!(formula = %install_name, error = %e, "failed to read keg files"),
+            )
+            .unwrap_or_default();
+
         let tx = self.db.transaction().inspect_err(|_| {
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
+            Self::cleanup_materialized(&cellar, formula_name, &version);
         })?;
 
         tx.record_install(install_name, &version, store_key)
+            .and_then(|()| {
+                tx.record_keg_metadata(
+                    install_name,
+                    disk_usage(&keg_path).ok(),
+                    &item.formula.runtime_dependencies(),
+                )
+            })
+            .and_then(|()| tx.record_keg_manifest(install_name, &version, &manifest))
+            .and_then(|()| tx.record_keg_arch(install_name, self.arch))
+            .and_then(|()| {
+                tx.record_keg_shared_size(
+                    install_name,
+                    cellar
+                        .keg_shared_size(formula_name, &version, &store_entry)
+                        .ok(),
+                )
+            })
             .inspect_err(|_| {
-                Self::cleanup_materialized(&self.cellar, formula_name, &version);
+                Self::cleanup_materialized(&cellar, formula_name, &version);
             })?;
 
         tx.commit().inspect_err(|_| {
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
+            Self::cleanup_materialized(&cellar, formula_name, &version);
         })?;
 
         if let Err(e) = self.linker.link_opt(&keg_path) {
@@ -66,15 +177,17 @@ impl Installer {
             report(InstallProgress::LinkStarted {
                 name: formula_name.clone(),
             });
-            match self.linker.link_keg(&keg_path) {
-                Ok(linked_files) => {
+            let link_started = Instant::now();
+            let linked =
+                self.link_installed_keg(install_name, &version, &keg_path, self.link_strategy);
+            self.report_phase(install_name, InstallPhase::Link, link_started.elapsed());
+            match linked {
+                Ok(_) => {
                     report(InstallProgress::LinkCompleted {
                         name: formula_name.clone(),
                     });
-                    self.record_linked_files(install_name, &version, &linked_files);
                 }
                 Err(e) => {
-                    let _ = self.linker.unlink_keg(&keg_path);
                     report(InstallProgress::InstallCompleted {
                         name: formula_name.clone(),
                     });
@@ -87,12 +200,24 @@ impl Installer {
                 _ if formula_name.contains('@') => "versioned formula".to_string(),
                 _ => "keg-only formula".to_string(),
             };
+            debug!(formula = %install_name, %reason, "not linking keg-only formula");
             report(InstallProgress::LinkSkipped {
                 name: formula_name.clone(),
                 reason,
             });
         }
 
+        let download_bytes = (!item.cached).then_some(item.download_size).flatten();
+        self.record_install_stat(InstallStat {
+            name: install_name.clone(),
+            method: "bottle",
+            duration_ms: millis(download.elapsed + started.elapsed()),
+            cached: item.cached,
+            download_bytes,
+            download_ms: download_bytes.map(|_| millis(download.elapsed)),
+            relocation_ms,
+        });
+
         report(InstallProgress::InstallCompleted {
             name: formula_name.clone(),
         });
@@ -100,6 +225,26 @@ impl Installer {
         Ok(())
     }
 
+    /// Best effort: sharing only saves space, so failures are logged.
+    fn share_with_delta_base(&self, install_name: &str, store_key: &str, base: &str) {
+        if !self.store.has_entry(base) {
+            return;
+        }
+        match self.store.share_unchanged_files(store_key, base) {
+            Ok(summary) => debug!(
+                formula = %install_name,
+                files_shared = summary.files_linked,
+                bytes_shared = summary.bytes_saved,
+                "reused unchanged files from previous version"
+            ),
+            Err(e) => warn!(
+                formula = %install_name,
+                error = %e,
+                "failed to reuse files from previous version"
+            ),
+        }
+    }
+
     async fn extract_with_retry(
         &self,
         download: &DownloadResult,
@@ -114,14 +259,14 @@ impl Installer {
             match self.store.ensure_entry(&bottle.sha256, &blob_path) {
                 Ok(entry) => return Ok(entry),
                 Err(Error::StoreCorruption { message }) => {
-                    self.downloader.remove_blob(&bottle.sha256);
+                    self.quarantine_corrupt_blob(&formula.name, bottle);
 
                     if attempt + 1 < MAX_CORRUPTION_RETRIES {
                         warn!(
                             formula = %formula.name,
                             attempt = attempt + 2,
                             max_retries = MAX_CORRUPTION_RETRIES,
-                            "corrupted download detected; retrying"
+                            "corrupted download quarantined; fetching it again"
                         );
 
                         let request = DownloadRequest {
@@ -139,6 +284,9 @@ impl Installer {
                                 blob_path = new_path;
                             }
                             Err(e) => {
+                                if matches!(e, Error::ChecksumMismatch { .. }) {
+                                    self.record_corrupt_download(&formula.name, bottle);
+                                }
                                 last_error = Some(e);
                                 break;
                             }
@@ -163,7 +311,31 @@ impl Installer {
         }))
     }
 
-    fn record_linked_files(
+    /// Set aside the cached blob of `bottle`, which failed to unpack, so it
+    /// is fetched again.
+    fn quarantine_corrupt_blob(&self, formula: &str, bottle: &zb_core::SelectedBottle) {
+        if let Some(path) = self.downloader.quarantine_blob(&bottle.sha256) {
+            debug!(formula, path = %path.display(), "quarantined corrupt blob");
+        }
+        self.record_corrupt_download(formula, bottle);
+    }
+
+    /// Remember that the URL of `bottle` served bad data, warning when it
+    /// has done so before.
+    fn record_corrupt_download(&self, formula: &str, bottle: &zb_core::SelectedBottle) {
+        match self.db.record_corrupt_download(&bottle.url, &bottle.sha256) {
+            Ok(failures) if failures >= REPEATED_CORRUPTION_THRESHOLD => warn!(
+                formula,
+                url = %bottle.url,
+                failures,
+                "this bottle keeps downloading corrupted; run `zb doctor` for details"
+            ),
+            Ok(_) => {}
+            Err(e) => debug!(formula, error = %e, "failed to record corrupt download"),
+        }
+    }
+
+    pub(super) fn record_linked_files(
         &mut self,
         name: &str,
         version: &str,
@@ -178,6 +350,7 @@ impl Installer {
                         version,
                         &linked.link_path.to_string_lossy(),
                         &linked.target_path.to_string_lossy(),
+                        linked.mode,
                     )
                     .is_err()
                 {
@@ -269,6 +442,7 @@ impl Installer {
                 &cask.version,
                 &linked.link_path.to_string_lossy(),
                 &linked.target_path.to_string_lossy(),
+                linked.mode,
             )?;
         }
         tx.commit()?;
@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use tracing::warn;
+use zb_core::Error;
+
+use crate::storage::blob::BlobEntry;
+
+use super::Installer;
+
+#[derive(Debug, Clone, Default)]
+pub struct CleanupOptions {
+    /// Evict unreferenced blobs and API responses last used longer ago than this.
+    pub max_age: Option<Duration>,
+    /// Evict least-recently-used blobs until the blob cache fits in this many bytes.
+    pub max_cache_size: Option<u64>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct CleanupSummary {
+    pub removed_blobs: Vec<BlobEntry>,
+    pub pruned_api_entries: usize,
+    pub cache_size_before: u64,
+    pub cache_size_after: u64,
+}
+
+impl CleanupSummary {
+    pub fn freed_bytes(&self) -> u64 {
+        self.removed_blobs.iter().map(|b| b.size).sum()
+    }
+}
+
+impl Installer {
+    /// Configure a blob cache size limit that is enforced after every install.
+    pub fn with_max_cache_size(mut self, max_cache_size: Option<u64>) -> Self {
+        self.max_cache_size = max_cache_size;
+        self
+    }
+
+    pub fn blob_cache_size(&self) -> Result<u64, Error> {
+        self.downloader
+            .blob_cache()
+            .total_size()
+            .map_err(Error::store("failed to measure blob cache"))
+    }
+
+    /// Evict cached blobs and API responses according to `options`.
+    ///
+    /// The age policy only touches blobs that no installed keg references.
+    /// The size policy walks blobs in LRU order, unreferenced ones first, and
+    /// may evict referenced blobs too since their store entries stay intact.
+    pub fn cleanup(&mut self, options: &CleanupOptions) -> Result<CleanupSummary, Error> {
+        let blob_cache = self.downloader.blob_cache();
+        let mut blobs = blob_cache
+            .list_blobs()
+            .map_err(Error::store("failed to list blob cache"))?;
+
+        let referenced: HashSet<String> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .map(|keg| keg.store_key)
+            .collect();
+
+        let cache_size_before: u64 = blobs.iter().map(|b| b.size).sum();
+        let mut evict = Vec::new();
+
+        if let Some(max_age) = options.max_age {
+            let cutoff = SystemTime::now()
+                .checked_sub(max_age)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let (stale, keep): (Vec<_>, Vec<_>) = blobs
+                .into_iter()
+                .partition(|b| !referenced.contains(&b.sha256) && b.last_used < cutoff);
+            evict.extend(stale);
+            blobs = keep;
+        }
+
+        if let Some(max_size) = options.max_cache_size {
+            blobs.sort_by_key(|b| (referenced.contains(&b.sha256), b.last_used));
+            let mut remaining: u64 = blobs.iter().map(|b| b.size).sum();
+            let mut keep = Vec::new();
+            for blob in blobs {
+                if remaining > max_size {
+                    remaining -= blob.size;
+                    evict.push(blob);
+                } else {
+                    keep.push(blob);
+                }
+            }
+            blobs = keep;
+        }
+
+        if !options.dry_run {
+            for blob in &evict {
+                blob_cache
+                    .remove_blob(&blob.sha256)
+                    .map_err(Error::store("failed to remove cached blob"))?;
+            }
+        }
+
+        let pruned_api_entries = match options.max_age {
+            Some(max_age) => self.api_client.prune_cache(max_age, options.dry_run)?,
+            None => 0,
+        };
+
+        evict.sort_by(|a, b| a.sha256.cmp(&b.sha256));
+        Ok(CleanupSummary {
+            removed_blobs: evict,
+            pruned_api_entries,
+            cache_size_before,
+            cache_size_after: blobs.iter().map(|b| b.size).sum(),
+        })
+    }
+
+    /// Apply the configured cache size limit, logging instead of failing so a
+    /// successful install is never reported as an error.
+    pub(crate) fn enforce_cache_limit(&mut self) {
+        let Some(max_cache_size) = self.max_cache_size else {
+            return;
+        };
+        let options = CleanupOptions {
+            max_cache_size: Some(max_cache_size),
+            ..Default::default()
+        };
+        if let Err(e) = self.cleanup(&options) {
+            warn!(error = %e, "failed to enforce blob cache size limit");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::TempDir;
+
+    use super::CleanupOptions;
+    use crate::Installer;
+    use crate::installer::install::test_support::{NO_API, installer};
+    use crate::network::api::ApiClient;
+    use crate::network::cache::{ApiCache, CacheEntry};
+    use crate::storage::blob::BlobCache;
+
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    fn setup(tmp: &Path) -> (Installer, BlobCache) {
+        let mut installer = installer(tmp, NO_API);
+        let api_cache = ApiCache::in_memory().unwrap();
+        api_cache
+            .put(
+                "https://example.com/fresh.json",
+                &CacheEntry {
+                    etag: None,
+                    last_modified: None,
+                    body: "{}".to_string(),
+                },
+            )
+            .unwrap();
+        installer.api_client = ApiClient::new().with_cache(api_cache);
+        let blob_cache = BlobCache::new(&tmp.join("zerobrew/cache")).unwrap();
+        (installer, blob_cache)
+    }
+
+    fn write_blob(cache: &BlobCache, sha: &str, size: usize, age: Duration) {
+        let mut writer = cache.start_write(sha).unwrap();
+        writer.write_all(&vec![0u8; size]).unwrap();
+        let path = writer.commit().unwrap();
+        fs::File::options()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    fn record_install(installer: &mut Installer, name: &str, store_key: &str) {
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install(name, "1.0.0", store_key).unwrap();
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn max_age_evicts_only_old_unreferenced_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let (mut installer, cache) = setup(tmp.path());
+
+        write_blob(&cache, "old", 10, 40 * DAY);
+        write_blob(&cache, "new", 10, DAY);
+        write_blob(&cache, "installed", 10, 40 * DAY);
+        record_install(&mut installer, "foo", "installed");
+
+        let summary = installer
+            .cleanup(&CleanupOptions {
+                max_age: Some(30 * DAY),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let removed: Vec<_> = summary
+            .removed_blobs
+            .iter()
+            .map(|b| b.sha256.as_str())
+            .collect();
+        assert_eq!(removed, vec!["old"]);
+        assert_eq!(summary.freed_bytes(), 10);
+        assert_eq!(summary.cache_size_before, 30);
+        assert_eq!(summary.cache_size_after, 20);
+        assert!(!cache.has_blob("old"));
+        assert!(cache.has_blob("new"));
+        assert!(cache.has_blob("installed"));
+        assert_eq!(summary.pruned_api_entries, 0);
+    }
+
+    #[test]
+    fn max_cache_size_evicts_least_recently_used_first() {
+        let tmp = TempDir::new().unwrap();
+        let (mut installer, cache) = setup(tmp.path());
+
+        write_blob(&cache, "oldest", 10, 3 * DAY);
+        write_blob(&cache, "middle", 10, 2 * DAY);
+        write_blob(&cache, "newest", 10, DAY);
+
+        let summary = installer
+            .cleanup(&CleanupOptions {
+                max_cache_size: Some(15),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let removed: Vec<_> = summary
+            .removed_blobs
+            .iter()
+            .map(|b| b.sha256.as_str())
+            .collect();
+        assert_eq!(removed, vec!["middle", "oldest"]);
+        assert!(cache.has_blob("newest"));
+        assert_eq!(installer.blob_cache_size().unwrap(), 10);
+    }
+
+    #[test]
+    fn max_cache_size_prefers_unreferenced_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let (mut installer, cache) = setup(tmp.path());
+
+        write_blob(&cache, "installed", 10, 3 * DAY);
+        write_blob(&cache, "orphan", 10, DAY);
+        record_install(&mut installer, "foo", "installed");
+
+        installer
+            .cleanup(&CleanupOptions {
+                max_cache_size: Some(10),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert!(cache.has_blob("installed"));
+        assert!(!cache.has_blob("orphan"));
+    }
+
+    #[test]
+    fn dry_run_leaves_cache_untouched() {
+        let tmp = TempDir::new().unwrap();
+        let (mut installer, cache) = setup(tmp.path());
+
+        write_blob(&cache, "old", 10, 40 * DAY);
+
+        let summary = installer
+            .cleanup(&CleanupOptions {
+                max_age: Some(30 * DAY),
+                max_cache_size: Some(0),
+                dry_run: true,
+            })
+            .unwrap();
+
+        assert_eq!(summary.removed_blobs.len(), 1);
+        assert_eq!(summary.cache_size_after, 0);
+        assert!(cache.has_blob("old"));
+    }
+
+    #[test]
+    fn enforce_cache_limit_is_noop_without_limit() {
+        let tmp = TempDir::new().unwrap();
+        let (installer, cache) = setup(tmp.path());
+
+        write_blob(&cache, "blob", 10, DAY);
+
+        let mut installer = installer.with_max_cache_size(None);
+        installer.enforce_cache_limit();
+        assert!(cache.has_blob("blob"));
+
+        let mut installer = installer.with_max_cache_size(Some(5));
+        installer.enforce_cache_limit();
+        assert!(!cache.has_blob("blob"));
+    }
+}
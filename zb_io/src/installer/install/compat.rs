@@ -0,0 +1,267 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+use zb_core::{Error, formula_token};
+
+use super::{Installer, acquire_install_lock};
+
+/// The layout kept in a compatibility root: `Cellar/<name>/<version>` and
+/// `opt/<name>`, each a symlink to the zerobrew keg.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompatSync {
+    /// Links created or repointed.
+    pub linked: usize,
+    /// Links removed because their keg is gone.
+    pub removed: usize,
+    /// Paths left alone because something other than zerobrew owns them.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl Installer {
+    /// Directories kept mirroring Homebrew's Cellar and opt layout.
+    pub fn compat_roots(&self) -> Result<Vec<PathBuf>, Error> {
+        self.db.list_compat_roots()
+    }
+
+    /// Keep `root` mirroring Homebrew's layout over the installed kegs, for
+    /// tools that look for `/opt/homebrew/Cellar` or
+    /// `/home/linuxbrew/.linuxbrew/opt` paths. Installs, uninstalls and
+    /// rollbacks keep it up to date from now on.
+    pub fn enable_compat_root(&self, root: &Path) -> Result<CompatSync, Error> {
+        let _lock = acquire_install_lock(&self.locks_dir)?;
+        if !root.is_absolute() {
+            return Err(Error::InvalidArgument {
+                message: format!("compat root {} must be an absolute path", root.display()),
+            });
+        }
+        if root == self.prefix {
+            return Err(Error::InvalidArgument {
+                message: "the zerobrew prefix already has a Homebrew layout".to_string(),
+            });
+        }
+        let sync = self.sync_compat_root(root)?;
+        self.db.add_compat_root(root)?;
+        Ok(sync)
+    }
+
+    /// Stop keeping `root` up to date and remove the links zerobrew made in
+    /// it. Anything else in it stays.
+    pub fn disable_compat_root(&self, root: &Path) -> Result<usize, Error> {
+        let _lock = acquire_install_lock(&self.locks_dir)?;
+        if !self.db.remove_compat_root(root)? {
+            return Err(Error::InvalidArgument {
+                message: format!("{} is not a compat root", root.display()),
+            });
+        }
+        self.remove_compat_links(root, &BTreeSet::new())
+            .map_err(Error::store("failed to clean compat root"))
+    }
+
+    /// Bring every compat root up to date with the installed kegs. Best
+    /// effort: a root that cannot be updated never fails the operation
+    /// that changed the kegs.
+    pub(super) fn sync_compat_roots(&self) {
+        let roots = match self.db.list_compat_roots() {
+            Ok(roots) => roots,
+            Err(e) => {
+                warn!(error = %e, "failed to list compat roots");
+                return;
+            }
+        };
+        for root in roots {
+            if let Err(e) = self.sync_compat_root(&root) {
+                warn!(root = %root.display(), error = %e, "failed to update compat root");
+            }
+        }
+    }
+
+    fn sync_compat_root(&self, root: &Path) -> Result<CompatSync, Error> {
+        let mut sync = CompatSync::default();
+        let mut kegs = BTreeSet::new();
+        for keg in self.db.list_installed()? {
+            let keg_path = self.installed_keg_path(&keg);
+            let token = formula_token(&keg.name);
+            for link in [
+                root.join("Cellar").join(token).join(&keg.version),
+                root.join("opt").join(token),
+            ] {
+                match self.place_compat_link(&link, &keg_path) {
+                    Ok(true) => sync.linked += 1,
+                    Ok(false) => {}
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                        sync.skipped.push(link);
+                    }
+                    Err(e) => {
+                        return Err(Error::StoreCorruption {
+                            message: format!("failed to link {}: {e}", link.display()),
+                        });
+                    }
+                }
+            }
+            kegs.insert(keg_path);
+        }
+        sync.removed = self
+            .remove_compat_links(root, &kegs)
+            .map_err(Error::store("failed to clean compat root"))?;
+        Ok(sync)
+    }
+
+    /// Point `link` at `keg_path`. Returns whether anything changed, or
+    /// `AlreadyExists` when `link` is not zerobrew's to replace.
+    fn place_compat_link(&self, link: &Path, keg_path: &Path) -> io::Result<bool> {
+        match fs::read_link(link) {
+            Ok(target) if target == keg_path => return Ok(false),
+            Ok(target) if self.is_zerobrew_keg(&target) => fs::remove_file(link)?,
+            Ok(_) => return Err(io::ErrorKind::AlreadyExists.into()),
+            Err(_) if link.symlink_metadata().is_ok() => {
+                return Err(io::ErrorKind::AlreadyExists.into());
+            }
+            Err(_) => {}
+        }
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::os::unix::fs::symlink(keg_path, link)?;
+        Ok(true)
+    }
+
+    /// Remove links in `root` to zerobrew kegs other than `keep`, and the
+    /// `Cellar/<name>` directories this empties.
+    fn remove_compat_links(&self, root: &Path, keep: &BTreeSet<PathBuf>) -> io::Result<usize> {
+        let mut removed = 0;
+        let mut remove_stale = |link: &Path| -> io::Result<()> {
+            if let Ok(target) = fs::read_link(link)
+                && self.is_zerobrew_keg(&target)
+                && !keep.contains(&target)
+            {
+                fs::remove_file(link)?;
+                removed += 1;
+            }
+            Ok(())
+        };
+        for entry in read_dir_or_empty(&root.join("opt"))? {
+            remove_stale(&entry)?;
+        }
+        for formula_dir in read_dir_or_empty(&root.join("Cellar"))? {
+            if !formula_dir.is_dir() || formula_dir.is_symlink() {
+                continue;
+            }
+            for entry in read_dir_or_empty(&formula_dir)? {
+                remove_stale(&entry)?;
+            }
+            // Only succeeds once nothing is left in it.
+            let _ = fs::remove_dir(&formula_dir);
+        }
+        Ok(removed)
+    }
+
+    /// Whether `path` is a keg in the zerobrew cellar or one of its
+    /// per-arch siblings.
+    fn is_zerobrew_keg(&self, path: &Path) -> bool {
+        let cellar = self.cellar.dir();
+        let Some(dir) = path.parent().and_then(Path::parent) else {
+            return false;
+        };
+        if dir == cellar {
+            return true;
+        }
+        let (Some(name), Some(cellar_name)) = (dir.file_name(), cellar.file_name()) else {
+            return false;
+        };
+        dir.parent() == cellar.parent()
+            && name
+                .to_string_lossy()
+                .starts_with(&format!("{}-", cellar_name.to_string_lossy()))
+    }
+}
+
+fn read_dir_or_empty(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.map(|entry| entry.map(|e| e.path())).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+
+    async fn mount_formula(server: &MockServer, name: &str) {
+        let bottle = create_bottle_tarball(name);
+        let tag = get_test_bottle_tag();
+        let bottle_path = format!("/bottles/{name}.tar.gz");
+        let formula = serde_json::json!({
+            "name": name,
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}{bottle_path}", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path(format!("/formula/{name}.json")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn compat_roots_follow_installs_and_leave_foreign_files() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "alpha").await;
+        mount_formula(&server, "beta").await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let homebrew = tmp.path().join("homebrew");
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer
+            .install(&["alpha".to_string()], true)
+            .await
+            .unwrap();
+
+        // A real Homebrew keg in the way is never replaced.
+        fs::create_dir_all(homebrew.join("opt/alpha")).unwrap();
+        let alpha = installer.keg_path("alpha", "1.0.0");
+        let sync = installer.enable_compat_root(&homebrew).unwrap();
+        assert_eq!(sync.linked, 1);
+        assert_eq!(sync.skipped, [homebrew.join("opt/alpha")]);
+        assert_eq!(
+            fs::read_link(homebrew.join("Cellar/alpha/1.0.0")).unwrap(),
+            alpha
+        );
+        assert!(installer.enable_compat_root(&prefix).is_err());
+
+        installer
+            .install(&["beta".to_string()], true)
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::read_link(homebrew.join("opt/beta")).unwrap(),
+            installer.keg_path("beta", "1.0.0")
+        );
+
+        installer.uninstall("alpha").unwrap();
+        assert!(!homebrew.join("Cellar/alpha").exists());
+        assert!(homebrew.join("opt/alpha").is_dir());
+
+        assert_eq!(installer.disable_compat_root(&homebrew).unwrap(), 2);
+        assert!(!homebrew.join("opt/beta").exists());
+        assert!(installer.compat_roots().unwrap().is_empty());
+        assert!(installer.disable_compat_root(&homebrew).is_err());
+    }
+}
@@ -3,9 +3,9 @@ use std::path::PathBuf;
 
 use zb_core::{Error, formula_token};
 
-use crate::storage::db::StoreRef;
+use crate::storage::db::{CorruptDownload, Database, StoreRef};
 
-use super::Installer;
+use super::{Installer, LinkDrift, REPEATED_CORRUPTION_THRESHOLD, ShadowedCommand};
 
 #[derive(Debug, Default)]
 pub struct DiagnosticReport {
@@ -14,7 +14,24 @@ pub struct DiagnosticReport {
     pub orphaned_store_entries: Vec<String>,
     pub stale_store_refs: Vec<StaleStoreRef>,
     pub broken_symlinks: Vec<PathBuf>,
+    /// Recorded links that were removed, repointed or replaced by hand.
+    pub drifted_links: Vec<LinkDrift>,
     pub stale_keg_file_records: usize,
+    /// Blobs set aside after failing to unpack.
+    pub quarantined_blobs: Vec<PathBuf>,
+    /// Bottle URLs that served corrupt data more than once.
+    pub corrupt_downloads: Vec<CorruptDownload>,
+    /// Set when the database was written by a newer zerobrew.
+    pub newer_database: Option<NewerDatabase>,
+    /// Commands in the prefix that other `PATH` entries also provide.
+    /// Informational: they do not make the installation unhealthy.
+    pub shadowed_commands: Vec<ShadowedCommand>,
+}
+
+#[derive(Debug)]
+pub struct NewerDatabase {
+    pub schema_version: u32,
+    pub supported_version: u32,
 }
 
 #[derive(Debug)]
@@ -46,7 +63,11 @@ impl DiagnosticReport {
             && self.orphaned_store_entries.is_empty()
             && self.stale_store_refs.is_empty()
             && self.broken_symlinks.is_empty()
+            && self.drifted_links.is_empty()
             && self.stale_keg_file_records == 0
+            && self.quarantined_blobs.is_empty()
+            && self.corrupt_downloads.is_empty()
+            && self.newer_database.is_none()
     }
 }
 
@@ -55,6 +76,7 @@ impl Installer {
         let mut report = DiagnosticReport::default();
 
         let installed = self.db.list_installed()?;
+        let generations = self.db.list_all_generations()?;
         let db_store_refs = self.db.list_store_refs()?;
         let disk_store_entries = self.store.list_entries()?;
         let cellar_kegs = self.cellar.list_kegs()?;
@@ -64,8 +86,15 @@ impl Installer {
             .map(|k| (formula_token(&k.name), k))
             .collect();
 
+        let generation_kegs: HashSet<(&str, &str)> = generations
+            .iter()
+            .map(|g| (formula_token(&g.name), g.version.as_str()))
+            .collect();
+
         for keg in &cellar_kegs {
-            if !installed_by_token.contains_key(keg.name.as_str()) {
+            if !installed_by_token.contains_key(keg.name.as_str())
+                && !generation_kegs.contains(&(keg.name.as_str(), keg.version.as_str()))
+            {
                 report.orphaned_cellar_kegs.push(OrphanedKeg {
                     name: keg.name.clone(),
                     version: keg.version.clone(),
@@ -76,7 +105,9 @@ impl Installer {
 
         for keg in &installed {
             let token = formula_token(&keg.name);
-            let expected_path = self.cellar.keg_path(token, &keg.version);
+            let expected_path = self
+                .cellar_for(keg.arch.as_deref())
+                .keg_path(token, &keg.version);
             if !expected_path.exists() {
                 report.missing_cellar_kegs.push(MissingKeg {
                     name: keg.name.clone(),
@@ -93,8 +124,12 @@ impl Installer {
 
         let store_keys_used: HashMap<&str, i64> = {
             let mut map = HashMap::new();
-            for keg in &installed {
-                *map.entry(keg.store_key.as_str()).or_insert(0) += 1;
+            let store_keys = installed
+                .iter()
+                .map(|k| k.store_key.as_str())
+                .chain(generations.iter().map(|g| g.store_key.as_str()));
+            for store_key in store_keys {
+                *map.entry(store_key).or_insert(0) += 1;
             }
             map
         };
@@ -132,7 +167,9 @@ impl Installer {
 
         for keg in &installed {
             let token = formula_token(&keg.name);
-            let keg_path = self.cellar.keg_path(token, &keg.version);
+            let keg_path = self
+                .cellar_for(keg.arch.as_deref())
+                .keg_path(token, &keg.version);
             if keg_path.exists() {
                 let linked = self.linker.collect_linked_files(&keg_path)?;
                 for file in linked {
@@ -143,11 +180,22 @@ impl Installer {
             }
         }
 
+        // Restoring a drifted link fixes it, so it is not broken too.
+        report.drifted_links = self.link_drift()?;
+        let drifted: HashSet<&std::path::Path> = report
+            .drifted_links
+            .iter()
+            .map(|d| d.link.as_path())
+            .collect();
+
         for record in &keg_files {
             if !installed_set.contains(&(record.name.as_str(), record.version.as_str())) {
                 continue;
             }
             let link = PathBuf::from(&record.linked_path);
+            if drifted.contains(link.as_path()) {
+                continue;
+            }
             if link.is_symlink() && !link.exists() && !report.broken_symlinks.contains(&link) {
                 report.broken_symlinks.push(link);
             }
@@ -155,10 +203,37 @@ impl Installer {
 
         report.stale_keg_file_records = self.db.count_stale_keg_file_records()?;
 
+        report.quarantined_blobs = self
+            .downloader
+            .blob_cache()
+            .list_quarantined()
+            .map_err(Error::store("failed to list quarantined blobs"))?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        report.corrupt_downloads = self
+            .db
+            .list_corrupt_downloads()?
+            .into_iter()
+            .filter(|d| d.failures >= REPEATED_CORRUPTION_THRESHOLD)
+            .collect();
+
+        if self.db.is_newer_than_supported() {
+            report.newer_database = Some(NewerDatabase {
+                schema_version: self.db.schema_version(),
+                supported_version: Database::supported_schema_version(),
+            });
+        }
+
+        report.shadowed_commands =
+            self.shadowed_commands(&std::env::var_os("PATH").unwrap_or_default())?;
+
         Ok(report)
     }
 
     pub fn repair(&mut self, report: &DiagnosticReport) -> Result<RepairSummary, Error> {
+        // What looks broken may just be data this version does not know.
+        self.db.ensure_writable()?;
         let mut summary = RepairSummary::default();
 
         for orphan in &report.orphaned_cellar_kegs {
@@ -179,9 +254,14 @@ impl Installer {
 
         if needs_refcount_recompute {
             let installed = self.db.list_installed()?;
+            let generations = self.db.list_all_generations()?;
             let mut corrected: HashMap<&str, i64> = HashMap::new();
-            for keg in &installed {
-                *corrected.entry(keg.store_key.as_str()).or_insert(0) += 1;
+            let store_keys = installed
+                .iter()
+                .map(|k| k.store_key.as_str())
+                .chain(generations.iter().map(|g| g.store_key.as_str()));
+            for store_key in store_keys {
+                *corrected.entry(store_key).or_insert(0) += 1;
             }
 
             let corrected_refs: Vec<StoreRef> = corrected
@@ -198,7 +278,7 @@ impl Installer {
         }
 
         for key in &report.orphaned_store_entries {
-            self.store.remove_entry(key)?;
+            self.remove_store_entry(key)?;
             summary.removed_orphaned_store_entries += 1;
         }
 
@@ -207,10 +287,29 @@ impl Installer {
             summary.removed_broken_symlinks += 1;
         }
 
+        for drift in report.drifted_links.iter().filter(|d| d.is_restorable()) {
+            self.linker
+                .restore_recorded(&drift.link, &drift.target, drift.mode)?;
+            summary.restored_links += 1;
+        }
+
         if report.stale_keg_file_records > 0 {
             summary.pruned_keg_file_records = self.db.prune_stale_keg_file_records()?;
         }
 
+        if !report.quarantined_blobs.is_empty() {
+            summary.removed_quarantined_blobs = self
+                .downloader
+                .blob_cache()
+                .clear_quarantine()
+                .map_err(Error::store("failed to clear quarantined blobs"))?;
+        }
+
+        if !report.corrupt_downloads.is_empty() {
+            self.db.clear_corrupt_downloads()?;
+            summary.cleared_corrupt_downloads = report.corrupt_downloads.len();
+        }
+
         Ok(summary)
     }
 }
@@ -222,7 +321,12 @@ pub struct RepairSummary {
     pub fixed_store_refs: usize,
     pub removed_orphaned_store_entries: usize,
     pub removed_broken_symlinks: usize,
+    /// Drifted links put back; files put in their place are left alone.
+    pub restored_links: usize,
     pub pruned_keg_file_records: usize,
+    pub removed_quarantined_blobs: usize,
+    /// Corrupt download records forgotten, so those URLs start afresh.
+    pub cleared_corrupt_downloads: usize,
 }
 
 impl RepairSummary {
@@ -232,6 +336,9 @@ impl RepairSummary {
             + self.fixed_store_refs
             + self.removed_orphaned_store_entries
             + self.removed_broken_symlinks
+            + self.restored_links
             + self.pruned_keg_file_records
+            + self.removed_quarantined_blobs
+            + self.cleared_corrupt_downloads
     }
 }
@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use zb_core::Error;
+
+use crate::cellar::link::LinkMode;
+use crate::storage::db::KegFileRecord;
+
+use super::{Installer, acquire_install_lock};
+
+/// A link in the prefix that no longer matches what zerobrew recorded
+/// placing there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkDrift {
+    pub name: String,
+    pub version: String,
+    pub link: PathBuf,
+    pub target: PathBuf,
+    pub mode: LinkMode,
+    pub kind: DriftKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftKind {
+    /// Nothing is at the link path any more.
+    Missing,
+    /// A symlink is there, but it points somewhere else.
+    Retargeted,
+    /// A file or directory zerobrew did not place is there.
+    Replaced,
+}
+
+impl LinkDrift {
+    /// Whether restoring the link would only replace a symlink, never a
+    /// file someone put there.
+    pub fn is_restorable(&self) -> bool {
+        self.kind != DriftKind::Replaced
+    }
+}
+
+/// What `Installer::relink` did to a keg's links.
+#[derive(Debug, Default)]
+pub struct RelinkSummary {
+    pub restored: Vec<PathBuf>,
+    /// Links occupied by files zerobrew did not place, left alone.
+    pub skipped: Vec<PathBuf>,
+    /// Links whose keg file is gone, so there is nothing to restore.
+    pub missing_targets: Vec<PathBuf>,
+}
+
+impl Installer {
+    /// Every recorded link of an installed keg that no longer matches the
+    /// prefix. Links whose keg file is gone are broken rather than drifted
+    /// and are left to `doctor`.
+    pub fn link_drift(&self) -> Result<Vec<LinkDrift>, Error> {
+        let records = self.db.installed_keg_files()?;
+        Ok(records.iter().filter_map(|r| self.drift_of(r)).collect())
+    }
+
+    /// Like `link_drift`, but checks at most `limit` records picked at
+    /// random, so repeated runs cover them all. Cheap enough to run before
+    /// any command.
+    pub fn sample_link_drift(&self, limit: usize) -> Result<Vec<LinkDrift>, Error> {
+        let records = self.db.sample_installed_keg_files(limit)?;
+        Ok(records.iter().filter_map(|r| self.drift_of(r)).collect())
+    }
+
+    /// Restore the links of the installed keg of `name` to what was recorded
+    /// when it was linked. Symlinks that were removed or repointed are put
+    /// back; files put in their place are only replaced with `force`.
+    pub fn relink(&mut self, name: &str, force: bool) -> Result<RelinkSummary, Error> {
+        let _lock = acquire_install_lock(&self.locks_dir)?;
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+
+        let mut summary = RelinkSummary::default();
+        for record in self.db.keg_files(&installed.name)? {
+            if record.version != installed.version {
+                continue;
+            }
+            let link = PathBuf::from(&record.linked_path);
+            let target = PathBuf::from(&record.target_path);
+            if self.linker.is_recorded(&link, &target, record.mode) {
+                continue;
+            }
+            if !target.exists() {
+                summary.missing_targets.push(link);
+                continue;
+            }
+            let occupied = link
+                .symlink_metadata()
+                .is_ok_and(|m| !m.file_type().is_symlink());
+            if occupied && (!force || link.is_dir()) {
+                summary.skipped.push(link);
+                continue;
+            }
+            self.linker.restore_recorded(&link, &target, record.mode)?;
+            summary.restored.push(link);
+        }
+        Ok(summary)
+    }
+
+    fn drift_of(&self, record: &KegFileRecord) -> Option<LinkDrift> {
+        let link = Path::new(&record.linked_path);
+        let target = Path::new(&record.target_path);
+        if !target.exists() || self.linker.is_recorded(link, target, record.mode) {
+            return None;
+        }
+        let kind = match link.symlink_metadata() {
+            Err(_) => DriftKind::Missing,
+            Ok(m) if m.file_type().is_symlink() => DriftKind::Retargeted,
+            Ok(_) => DriftKind::Replaced,
+        };
+        Some(LinkDrift {
+            name: record.name.clone(),
+            version: record.version.clone(),
+            link: link.to_path_buf(),
+            target: target.to_path_buf(),
+            mode: record.mode,
+            kind,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+
+    #[tokio::test]
+    async fn detects_and_restores_links_changed_by_hand() {
+        let server = MockServer::start().await;
+        let bottle = create_bottle_tarball("drifty");
+        let tag = get_test_bottle_tag();
+        let formula = serde_json::json!({
+            "name": "drifty",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}/bottles/drifty-1.0.0.{tag}.bottle.tar.gz", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/drifty.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/drifty-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer
+            .install(&["drifty".to_string()], true)
+            .await
+            .unwrap();
+        let link = prefix.join("bin/drifty");
+        let kinds = |installer: &Installer| -> Vec<DriftKind> {
+            installer
+                .link_drift()
+                .unwrap()
+                .iter()
+                .map(|d| d.kind)
+                .collect()
+        };
+        assert!(kinds(&installer).is_empty());
+        assert!(installer.sample_link_drift(1).unwrap().is_empty());
+
+        fs::remove_file(&link).unwrap();
+        assert_eq!(kinds(&installer), [DriftKind::Missing]);
+        assert_eq!(installer.sample_link_drift(8).unwrap().len(), 1);
+        let report = installer.doctor().unwrap();
+        assert_eq!(report.drifted_links.len(), 1);
+        assert!(report.broken_symlinks.is_empty());
+        assert_eq!(installer.repair(&report).unwrap().restored_links, 1);
+        assert!(installer.doctor().unwrap().is_healthy());
+
+        let elsewhere = tmp.path().join("elsewhere");
+        fs::write(&elsewhere, "").unwrap();
+        fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink(&elsewhere, &link).unwrap();
+        assert_eq!(kinds(&installer), [DriftKind::Retargeted]);
+        let summary = installer.relink("drifty", false).unwrap();
+        assert_eq!(summary.restored, std::slice::from_ref(&link));
+        assert!(kinds(&installer).is_empty());
+
+        fs::remove_file(&link).unwrap();
+        fs::write(&link, "mine").unwrap();
+        assert_eq!(kinds(&installer), [DriftKind::Replaced]);
+        let report = installer.doctor().unwrap();
+        assert_eq!(installer.repair(&report).unwrap().restored_links, 0);
+        let summary = installer.relink("drifty", false).unwrap();
+        assert_eq!(summary.skipped, std::slice::from_ref(&link));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "mine");
+
+        let summary = installer.relink("drifty", true).unwrap();
+        assert_eq!(summary.restored, std::slice::from_ref(&link));
+        assert!(link.is_symlink());
+        assert!(installer.doctor().unwrap().is_healthy());
+
+        installer.uninstall("drifty").unwrap();
+        assert!(!link.exists());
+    }
+}
@@ -0,0 +1,121 @@
+use serde::Serialize;
+use zb_core::Error;
+
+use crate::services::ServiceStatus;
+
+use super::Installer;
+use super::receipt::source_tap;
+
+/// An installed package as `zb export` describes it to other tools.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExportedPackage {
+    pub name: String,
+    pub version: String,
+    /// Tap in Homebrew's `user/repo` form.
+    pub tap: String,
+    pub linked: bool,
+    /// False for packages installed only as a dependency.
+    pub installed_on_request: bool,
+    /// Foreign architecture the keg was installed for, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// State of its service, for packages zerobrew has managed one of.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+}
+
+impl Installer {
+    /// Every installed package, sorted by name, with the state of its
+    /// service taken from `services`.
+    pub fn export_packages(
+        &self,
+        services: &[ServiceStatus],
+    ) -> Result<Vec<ExportedPackage>, Error> {
+        let mut packages = Vec::new();
+        for keg in self.db.list_installed()? {
+            let keg_path = self.installed_keg_path(&keg);
+            let receipt = self.read_receipt(&keg.name).ok().flatten();
+            packages.push(ExportedPackage {
+                tap: receipt
+                    .as_ref()
+                    .and_then(|r| r.source.tap.clone())
+                    .unwrap_or_else(|| source_tap(&keg.name)),
+                linked: self.linker.is_linked(&keg_path),
+                installed_on_request: receipt.is_none_or(|r| r.installed_on_request),
+                service: services
+                    .iter()
+                    .find(|s| s.name == keg.name)
+                    .map(|s| s.state.as_str().to_string()),
+                name: keg.name,
+                version: keg.version,
+                arch: keg.arch,
+            });
+        }
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+    use crate::services::ServiceState;
+
+    #[tokio::test]
+    async fn exported_packages_carry_links_taps_and_services() {
+        let server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("tool");
+        let formula = serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}/bottles/tool.tar.gz", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/tool.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/tool.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer
+            .install(&["tool".to_string()], false)
+            .await
+            .unwrap();
+
+        let services = [ServiceStatus {
+            name: "tool".into(),
+            state: ServiceState::Scheduled,
+            schedule: None,
+            file: None,
+            started_at: None,
+        }];
+        assert_eq!(
+            installer.export_packages(&services).unwrap(),
+            [ExportedPackage {
+                name: "tool".into(),
+                version: "1.0.0".into(),
+                tap: "homebrew/core".into(),
+                linked: false,
+                installed_on_request: true,
+                arch: None,
+                service: Some("scheduled".into()),
+            }]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use zb_core::{Error, Formula, InstallMethod};
+
+use crate::network::download::{DownloadProgressCallback, DownloadRequest};
+use crate::progress::ProgressCallback;
+
+use super::{InstallPlan, Installer};
+
+impl Installer {
+    pub async fn get_formula(&self, name: &str) -> Result<Formula, Error> {
+        self.api_client.get_formula(name).await
+    }
+
+    /// Download the plan's bottles and release binaries into the blob cache
+    /// without unpacking or installing them. Source-build items are skipped.
+    ///
+    /// Returns the cached blob paths in plan order. Combined with
+    /// [`Installer::with_bottle_tag`] this prefetches bottles for another
+    /// platform.
+    pub async fn fetch(
+        &self,
+        plan: &InstallPlan,
+        progress: Option<Arc<ProgressCallback>>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let requests: Vec<DownloadRequest> = plan
+            .items
+            .iter()
+            .filter_map(|item| match &item.method {
+                InstallMethod::Bottle(bottle) => Some(DownloadRequest {
+                    url: bottle.url.clone(),
+                    sha256: bottle.sha256.clone(),
+                    name: item.formula.name.clone(),
+                }),
+                InstallMethod::Binary(binary) => Some(DownloadRequest {
+                    url: binary.url.clone(),
+                    sha256: binary.sha256.clone(),
+                    name: item.formula.name.clone(),
+                }),
+                InstallMethod::Source(_) => None,
+            })
+            .collect();
+
+        let download_progress: Option<DownloadProgressCallback> =
+            progress.map(|cb| Arc::new(move |event| cb(event)) as DownloadProgressCallback);
+
+        self.downloader
+            .download_all_with_progress(requests, download_progress)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::Installer;
+    use crate::installer::install::test_support::*;
+
+    const FOREIGN_TAG: &str = "arm64_tahoe";
+
+    async fn setup(mock_server: &MockServer, tmp: &TempDir) -> (Installer, String) {
+        let bottle = create_bottle_tarball("crosspkg");
+        let bottle_sha = sha256_hex(&bottle);
+        let formula_json = format!(
+            r#"{{
+                "name": "crosspkg",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{FOREIGN_TAG}": {{
+                                "url": "{}/bottles/crosspkg-1.0.0.{FOREIGN_TAG}.bottle.tar.gz",
+                                "sha256": "{bottle_sha}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/crosspkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/crosspkg-1.0.0.{FOREIGN_TAG}.bottle.tar.gz"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(mock_server)
+            .await;
+
+        let installer = installer(tmp.path(), &mock_server.uri());
+        (installer, bottle_sha)
+    }
+
+    #[tokio::test]
+    async fn fetches_bottle_for_requested_tag_without_installing() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let (installer, bottle_sha) = setup(&mock_server, &tmp).await;
+        let mut installer = installer.with_bottle_tag(FOREIGN_TAG);
+
+        let plan = installer.plan(&["crosspkg".to_string()]).await.unwrap();
+        let zb_core::InstallMethod::Bottle(ref bottle) = plan.items[0].method else {
+            panic!("expected a bottle");
+        };
+        assert_eq!(bottle.tag, FOREIGN_TAG);
+
+        let blobs = installer.fetch(&plan, None).await.unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert!(installer.downloader.blob_cache().has_blob(&bottle_sha));
+
+        let err = installer.execute(plan, true).await.err().unwrap();
+        assert!(matches!(err, zb_core::Error::InvalidArgument { .. }));
+        assert!(!installer.is_installed("crosspkg"));
+    }
+
+    #[tokio::test]
+    async fn plan_fails_when_requested_tag_is_unavailable() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let (installer, _) = setup(&mock_server, &tmp).await;
+        let installer = installer.with_bottle_tag("x86_64_linux");
+
+        let err = installer.plan(&["crosspkg".to_string()]).await.unwrap_err();
+        match err {
+            zb_core::Error::BottleTagUnavailable {
+                name,
+                tag,
+                available,
+            } => {
+                assert_eq!(name, "crosspkg");
+                assert_eq!(tag, "x86_64_linux");
+                assert_eq!(available, vec![FOREIGN_TAG.to_string()]);
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+}
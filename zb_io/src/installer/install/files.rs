@@ -0,0 +1,228 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use zb_core::{Error, formula_token};
+
+use crate::checksum::sha256_hex;
+use crate::storage::db::{InstalledKeg, ManifestEntry};
+
+use super::Installer;
+
+/// The installed keg a path belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOwner {
+    pub name: String,
+    pub version: String,
+    /// The file inside the keg the path is, or links to.
+    pub file: PathBuf,
+}
+
+impl Installer {
+    /// The files of the installed keg of `name`. Kegs installed before
+    /// manifests were recorded are read from disk and recorded now.
+    pub fn list_files(&mut self, name: &str) -> Result<Vec<ManifestEntry>, Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let entries = self.db.keg_manifest(name)?;
+        if !entries.is_empty() {
+            return Ok(entries);
+        }
+
+        let keg_path = self.installed_keg_path(&installed);
+        let entries = keg_manifest(&keg_path).map_err(Error::store("failed to read keg files"))?;
+        let tx = self.db.transaction()?;
+        tx.record_keg_manifest(name, &installed.version, &entries)?;
+        tx.commit()?;
+        Ok(entries)
+    }
+
+    /// The installed formula `path` belongs to: a file inside its keg, or a
+    /// link zerobrew created for it in the prefix.
+    pub fn owns(&self, path: &Path) -> Result<Option<FileOwner>, Error> {
+        let path = std::path::absolute(path).map_err(Error::store("failed to resolve path"))?;
+
+        if let Ok(resolved) = fs::canonicalize(&path) {
+            for keg in self.db.list_installed()? {
+                let keg_path = self.installed_keg_path(&keg);
+                let keg_path = fs::canonicalize(&keg_path).unwrap_or(keg_path);
+                if resolved.starts_with(&keg_path) {
+                    return Ok(Some(FileOwner {
+                        name: keg.name,
+                        version: keg.version,
+                        file: resolved,
+                    }));
+                }
+            }
+        }
+
+        // A recorded link whose keg file is gone no longer resolves.
+        Ok(self
+            .db
+            .list_keg_files()?
+            .into_iter()
+            .find(|record| Path::new(&record.linked_path) == path)
+            .map(|record| FileOwner {
+                name: record.name,
+                version: record.version,
+                file: PathBuf::from(record.target_path),
+            }))
+    }
+
+    /// Remove links in the prefix that point at files the manifest of
+    /// `name` lists, even when the keg itself is already gone.
+    pub(super) fn unlink_manifest_files(&self, name: &str, keg_path: &Path) -> Result<(), Error> {
+        for entry in self.db.keg_manifest(name)? {
+            let link = self.prefix.join(&entry.path);
+            if fs::read_link(&link).is_ok_and(|target| target.starts_with(keg_path)) {
+                let _ = fs::remove_file(&link);
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the installed keg `keg` lives, in whichever cellar it was
+    /// installed to.
+    pub fn installed_keg_path(&self, keg: &InstalledKeg) -> PathBuf {
+        self.installed_cellar(&keg.name)
+            .keg_path(formula_token(&keg.name), &keg.version)
+    }
+}
+
+/// Every file and symlink under `keg_path`, with sizes and content hashes.
+pub(super) fn keg_manifest(keg_path: &Path) -> io::Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    for entry in walkdir::WalkDir::new(keg_path).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::other)?;
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .strip_prefix(keg_path)
+            .map_err(io::Error::other)?
+            .to_string_lossy()
+            .into_owned();
+        let size_bytes = entry.metadata().map_err(io::Error::other)?.len();
+        let sha256 = if file_type.is_file() {
+            Some(hash_file(entry.path())?)
+        } else {
+            None
+        };
+        entries.push(ManifestEntry {
+            path,
+            size_bytes,
+            sha256,
+        });
+    }
+    Ok(entries)
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(sha256_hex(hasher))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::{
+        create_bottle_tarball, get_test_bottle_tag, installer, sha256_hex,
+    };
+
+    #[test]
+    fn manifest_hashes_files_and_records_symlinks() {
+        let tmp = TempDir::new().unwrap();
+        let keg = tmp.path().join("keg");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/tool"), "hello").unwrap();
+        symlink("tool", keg.join("bin/alias")).unwrap();
+
+        let entries = keg_manifest(&keg).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "bin/alias");
+        assert_eq!(entries[0].sha256, None);
+        assert_eq!(entries[1].path, "bin/tool");
+        assert_eq!(entries[1].size_bytes, 5);
+        assert_eq!(
+            entries[1].sha256.as_deref(),
+            Some("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+        );
+    }
+
+    #[tokio::test]
+    async fn manifest_answers_owns_and_cleans_stray_links() {
+        let server = MockServer::start().await;
+        let bottle = create_bottle_tarball("tool");
+        let sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+        let bottle_path = format!("/bottles/tool-1.0.0.{tag}.bottle.tar.gz");
+        let formula = serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}{bottle_path}", server.uri()),
+                "sha256": sha,
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/tool.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer
+            .install(&["tool".to_string()], true)
+            .await
+            .unwrap();
+
+        let files = installer.list_files("tool").unwrap();
+        assert!(files.iter().any(|e| e.path == "bin/tool"));
+
+        let owner = installer.owns(&prefix.join("bin/tool")).unwrap().unwrap();
+        assert_eq!(owner.name, "tool");
+        assert!(owner.file.ends_with("tool/1.0.0/bin/tool"));
+        assert!(installer.owns(tmp.path()).unwrap().is_none());
+
+        // A link the database lost track of, into a keg removed by hand, is
+        // still found through the manifest.
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.clear_keg_file_records("tool").unwrap();
+            tx.commit().unwrap();
+        }
+        fs::remove_dir_all(root.join("cellar/tool/1.0.0")).unwrap();
+        installer.uninstall("tool").unwrap();
+
+        assert!(prefix.join("bin/tool").symlink_metadata().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use tracing::{debug, warn};
+use zb_core::{Error, Formula};
+
+use crate::network::tap_formula::parse_tap_formula_ref;
+use crate::storage::db::FormulaRecord;
+
+use super::Installer;
+
+pub(super) const DEFAULT_FORMULA_METADATA_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// What `refresh_formula_index` stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormulaIndexRefresh {
+    pub formulas: usize,
+    /// Formulas added or changed since the last refresh.
+    pub changed: usize,
+}
+
+impl Installer {
+    /// Resolve from stored formula metadata younger than `ttl` without
+    /// asking the API.
+    pub fn with_formula_metadata_ttl(mut self, ttl: Duration) -> Self {
+        self.formula_metadata_ttl = ttl;
+        self
+    }
+
+    /// Download the full formula index into the database so the next plans
+    /// resolve locally.
+    pub async fn refresh_formula_index(&self) -> Result<FormulaIndexRefresh, Error> {
+        let raw = self.api_client.get_all_formulas_raw().await?;
+        let records: Vec<FormulaRecord> = formula_records(&raw)?
+            .into_iter()
+            .map(|(record, _)| record)
+            .collect();
+        let changed = self.db.replace_formulas(&records)?;
+        Ok(FormulaIndexRefresh {
+            formulas: records.len(),
+            changed,
+        })
+    }
+
+    /// `name` from stored metadata while it is fresh, otherwise from the
+    /// API. Stale metadata still answers when the network is down.
+    pub(super) async fn resolve_formula(&self, name: &str) -> Result<Formula, Error> {
+        if let Some(local) = self.local_formula(name) {
+            return Ok(local.formula.clone());
+        }
+        if parse_tap_formula_ref(name).is_some() {
+            return self.api_client.get_formula(name).await;
+        }
+
+        let cached = self.db.cached_formula(name).unwrap_or_else(|error| {
+            warn!(formula = name, %error, "ignoring unreadable formula metadata");
+            None
+        });
+        let max_age = self.formula_metadata_ttl.as_secs() as i64;
+        if let Some(cached) = &cached
+            && unix_now() - cached.fetched_at <= max_age
+            && let Ok(formula) = serde_json::from_str(&cached.json)
+        {
+            return Ok(formula);
+        }
+
+        match self.fetch_formula(name).await {
+            Err(error) if error.is_offline() => {
+                let Some(formula) = cached.and_then(|c| serde_json::from_str(&c.json).ok()) else {
+                    return Err(error);
+                };
+                warn!(formula = name, %error, "offline; using stored formula metadata");
+                Ok(formula)
+            }
+            result => result,
+        }
+    }
+
+    /// `name` from the API, remembering its metadata for later resolves.
+    pub(super) async fn fetch_formula(&self, name: &str) -> Result<Formula, Error> {
+        if let Some(local) = self.local_formula(name) {
+            return Ok(local.formula.clone());
+        }
+        if parse_tap_formula_ref(name).is_some() {
+            return self.api_client.get_formula(name).await;
+        }
+
+        let raw = self.api_client.get_formula_raw(name).await?;
+        let value: serde_json::Value =
+            serde_json::from_str(&raw).map_err(|e| Error::FormulaSchema {
+                name: Some(name.to_string()),
+                message: e.to_string(),
+            })?;
+        let (record, formula) = formula_record(value)?;
+        if let Err(error) = self.db.store_formula(&record) {
+            warn!(formula = name, %error, "failed to store formula metadata");
+        }
+        Ok(formula)
+    }
+}
+
+/// Parse the bulk formula JSON, skipping entries that do not parse.
+pub(super) fn formula_records(raw: &str) -> Result<Vec<(FormulaRecord, Formula)>, Error> {
+    let entries: Vec<serde_json::Value> =
+        serde_json::from_str(raw).map_err(Error::network("failed to parse bulk formula JSON"))?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| match formula_record(entry) {
+            Ok(parsed) => Some(parsed),
+            Err(error) => {
+                debug!(%error, "skipping unparsable formula in index");
+                None
+            }
+        })
+        .collect())
+}
+
+fn formula_record(value: serde_json::Value) -> Result<(FormulaRecord, Formula), Error> {
+    let names = |key: &str| -> Vec<String> {
+        value
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|n| n.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let aliases = [names("aliases"), names("oldnames")].concat();
+    let json = value.to_string();
+    let formula = Formula::from_json_value(value)?;
+    let record = FormulaRecord {
+        name: formula.name.clone(),
+        version: formula.effective_version(),
+        dependencies: formula.dependencies.clone(),
+        aliases,
+        json,
+    };
+    Ok((record, formula))
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::installer::install::test_support::{NO_API, installer};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stale_metadata_resolves_while_offline() {
+        let tmp = TempDir::new().unwrap();
+        // Stored metadata is never fresh.
+        let installer = installer(tmp.path(), NO_API).with_formula_metadata_ttl(Duration::ZERO);
+        let json = serde_json::json!({
+            "name": "jq",
+            "versions": { "stable": "1.7.1" },
+            "dependencies": ["oniguruma"],
+            "aliases": ["jq@1"],
+            "bottle": { "stable": { "files": {} } },
+        });
+        let (record, _) = formula_record(json).unwrap();
+        installer.db.store_formula(&record).unwrap();
+
+        let formula = installer.resolve_formula("jq@1").await.unwrap();
+        assert_eq!(formula.name, "jq");
+        assert_eq!(formula.dependencies, ["oniguruma"]);
+
+        // With the default retry policy the failure comes back wrapped.
+        let err = installer.resolve_formula("yq").await.unwrap_err();
+        assert!(matches!(err, Error::RetriesExhausted { .. }));
+        assert!(err.is_offline());
+    }
+}
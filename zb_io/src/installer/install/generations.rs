@@ -0,0 +1,147 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::warn;
+use zb_core::{Error, formula_token};
+
+use crate::storage::db::KegGeneration;
+
+use super::history::OperationKind;
+use super::{Installer, acquire_install_lock};
+
+/// How long superseded kegs survive `gc` by default.
+pub(super) const DEFAULT_GENERATION_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+impl Installer {
+    /// Keep up to `count` previous kegs of each formula after an upgrade so
+    /// `rollback` can return to them. Zero removes old kegs immediately.
+    pub fn with_keep_generations(mut self, count: usize) -> Self {
+        self.keep_generations = count;
+        self
+    }
+
+    /// How long `gc` leaves superseded kegs in place before collecting them.
+    pub fn with_generation_retention(mut self, retention: Duration) -> Self {
+        self.generation_retention = retention;
+        self
+    }
+
+    /// Previous generations of `name`, most recently superseded first.
+    pub fn list_generations(&self, name: &str) -> Result<Vec<KegGeneration>, Error> {
+        self.db.list_generations(name)
+    }
+
+    /// Switch `name` back to the keg the last upgrade replaced.
+    ///
+    /// The current keg becomes a generation in turn, so a second rollback
+    /// undoes the first. The previous keg is linked if the current one was.
+    /// Returns the version now installed.
+    pub fn rollback(&mut self, name: &str) -> Result<String, Error> {
+        let _lock = acquire_install_lock(&self.locks_dir)?;
+        let before = self.installed_versions();
+        let result = self.roll_back(name);
+        self.record_operation(OperationKind::Rollback, before, &result, None);
+        result
+    }
+
+    /// `rollback` for callers already holding the install lock, without
+    /// recording it in the history.
+    pub(super) fn roll_back(&mut self, name: &str) -> Result<String, Error> {
+        let previous = self
+            .db
+            .list_generations(name)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::InvalidArgument {
+                message: format!("{name} has no previous version to roll back to"),
+            })?;
+        let keg_name = formula_token(name);
+
+        let was_linked = match self.db.get_installed(name) {
+            Some(current) => {
+                let keg_path = self
+                    .cellar_for(current.arch.as_deref())
+                    .keg_path(keg_name, &current.version);
+                let linked = !self.linker.collect_linked_files(&keg_path)?.is_empty();
+                self.unlink_installed_keg(name, &keg_path)?;
+
+                let tx = self.db.transaction()?;
+                tx.retire_to_generation(name)?;
+                tx.restore_generation(name, &previous.version)?;
+                tx.commit()?;
+                linked
+            }
+            // A failed upgrade leaves only the generation behind.
+            None => {
+                let tx = self.db.transaction()?;
+                tx.restore_generation(name, &previous.version)?;
+                tx.commit()?;
+                true
+            }
+        };
+
+        let keg_path = self
+            .cellar_for(previous.arch.as_deref())
+            .keg_path(keg_name, &previous.version);
+        if let Err(e) = self.linker.link_opt(&keg_path) {
+            warn!(formula = %name, error = %e, "failed to create opt link");
+        }
+        if was_linked {
+            self.link_installed_keg(name, &previous.version, &keg_path, self.link_strategy)?;
+        }
+
+        self.sync_compat_roots();
+        self.prune_generations(name, self.keep_generations.max(1))?;
+        Ok(previous.version)
+    }
+
+    /// Unlink the installed keg of `name` and keep it as a generation
+    /// instead of removing it, leaving the name free for a new install.
+    pub(super) fn retire_keg(&mut self, name: &str, version: &str) -> Result<(), Error> {
+        let keg_path = self
+            .installed_cellar(name)
+            .keg_path(formula_token(name), version);
+        self.unlink_installed_keg(name, &keg_path)?;
+
+        let tx = self.db.transaction()?;
+        tx.retire_to_generation(name)?;
+        tx.commit()
+    }
+
+    /// Remove generations of `name` beyond the `keep` most recent.
+    pub(super) fn prune_generations(&mut self, name: &str, keep: usize) -> Result<(), Error> {
+        for generation in self.db.list_generations(name)?.into_iter().skip(keep) {
+            self.remove_generation(&generation)?;
+        }
+        Ok(())
+    }
+
+    /// Remove every generation superseded longer ago than the retention
+    /// window. Their store entries are left for `gc` to reclaim.
+    pub(super) fn collect_expired_generations(&mut self) -> Result<usize, Error> {
+        let cutoff = SystemTime::now()
+            .checked_sub(self.generation_retention)
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs() as i64);
+
+        let mut removed = 0;
+        for generation in self.db.list_all_generations()? {
+            if generation.superseded_at <= cutoff {
+                self.remove_generation(&generation)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    pub(super) fn remove_generation(&mut self, generation: &KegGeneration) -> Result<(), Error> {
+        let cellar = self.cellar_for(generation.arch.as_deref());
+        let keg_path = cellar.keg_path(formula_token(&generation.name), &generation.version);
+        self.unlink_from_profiles(&generation.name, &generation.version, &keg_path);
+
+        let tx = self.db.transaction()?;
+        tx.delete_generation(&generation.name, &generation.version)?;
+        tx.commit()?;
+
+        cellar.remove_keg(formula_token(&generation.name), &generation.version)
+    }
+}
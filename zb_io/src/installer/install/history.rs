@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use tracing::debug;
+use zb_core::Error;
+
+use crate::storage::db::{OperationRecord, PackageChange};
+
+use super::{Installer, acquire_install_lock};
+
+/// The kinds of operation kept in the history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum OperationKind {
+    Install,
+    Uninstall,
+    Upgrade,
+    Rollback,
+    Gc,
+    Undo,
+}
+
+impl OperationKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            OperationKind::Install => "install",
+            OperationKind::Uninstall => "uninstall",
+            OperationKind::Upgrade => "upgrade",
+            OperationKind::Rollback => "rollback",
+            OperationKind::Gc => "gc",
+            OperationKind::Undo => "undo",
+        }
+    }
+}
+
+impl Installer {
+    /// The most recent `limit` operations, newest first.
+    pub fn history(&self, limit: usize) -> Result<Vec<OperationRecord>, Error> {
+        self.db.operations(limit)
+    }
+
+    /// Reverse the recorded operation `id`: uninstall what it installed and
+    /// roll what it upgraded or rolled back to the version it replaced.
+    ///
+    /// Packages it removed cannot be restored, and every package must still
+    /// be at the version the operation left it at. Both are checked before
+    /// anything changes. A package it installed that a keg outside the undo
+    /// still depends on, by the edges recorded at install time, is kept.
+    /// Returns the changes the undo made.
+    pub fn undo(&mut self, id: i64) -> Result<Vec<PackageChange>, Error> {
+        let operation = self
+            .db
+            .operation(id)?
+            .ok_or_else(|| Error::InvalidArgument {
+                message: format!("there is no operation #{id} in the history"),
+            })?;
+        if operation.changes.is_empty() {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "operation #{id} ({}) changed no packages; there is nothing to undo",
+                    operation.kind
+                ),
+            });
+        }
+
+        let _lock = acquire_install_lock(&self.locks_dir)?;
+        for change in &operation.changes {
+            self.check_reversible(id, change)?;
+        }
+
+        let needed = self.needed_outside(&operation.changes)?;
+        let (kept, reverted): (Vec<_>, Vec<_>) = operation
+            .changes
+            .iter()
+            .cloned()
+            .partition(|change| change.from.is_none() && needed.contains(&change.name));
+        let mut detail = format!("undid #{id} ({})", operation.kind);
+        if !kept.is_empty() {
+            let names: Vec<&str> = kept.iter().map(|change| change.name.as_str()).collect();
+            detail.push_str(&format!(
+                "; kept {}, still needed by other packages",
+                names.join(", ")
+            ));
+        }
+
+        let before = self.installed_versions();
+        let result = self.revert_changes(&reverted);
+        let changes = self.record_operation(OperationKind::Undo, before, &result, Some(detail));
+        result.map(|()| changes)
+    }
+
+    fn check_reversible(&self, id: i64, change: &PackageChange) -> Result<(), Error> {
+        let name = &change.name;
+        let Some(to) = &change.to else {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "operation #{id} removed {name}, which cannot be undone; \
+                     reinstall it with `zb install {name}`"
+                ),
+            });
+        };
+        let current = self.db.get_installed(name).map(|keg| keg.version);
+        if current.as_ref() != Some(to) {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "{name} is {} now, not {to}; a later operation changed it",
+                    current.map_or_else(|| "not installed".to_string(), |v| format!("at {v}"))
+                ),
+            });
+        }
+        if let Some(from) = &change.from {
+            let previous = self.db.list_generations(name)?.into_iter().next();
+            if previous.map(|g| g.version).as_ref() != Some(from) {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "{name} {from} is no longer kept as a generation, so it cannot be \
+                         restored; install it again instead"
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything the installed kegs the undo leaves in place depend on,
+    /// directly or transitively.
+    fn needed_outside(&self, changes: &[PackageChange]) -> Result<BTreeSet<String>, Error> {
+        let removed: BTreeSet<&str> = changes
+            .iter()
+            .filter(|change| change.from.is_none())
+            .map(|change| change.name.as_str())
+            .collect();
+        let mut queue: VecDeque<String> = VecDeque::new();
+        for keg in self.db.list_installed()? {
+            if !removed.contains(keg.name.as_str()) {
+                queue.extend(self.db.list_dependencies(&keg.name)?);
+            }
+        }
+        let mut needed = BTreeSet::new();
+        while let Some(name) = queue.pop_front() {
+            if needed.insert(name.clone()) {
+                queue.extend(self.db.list_dependencies(&name)?);
+            }
+        }
+        Ok(needed)
+    }
+
+    fn revert_changes(&mut self, changes: &[PackageChange]) -> Result<(), Error> {
+        for change in changes {
+            if change.from.is_some() {
+                self.roll_back(&change.name)?;
+            } else {
+                self.uninstall_keg(&change.name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The installed version of every package, for diffing around an
+    /// operation.
+    pub(super) fn installed_versions(&self) -> BTreeMap<String, String> {
+        self.db
+            .list_installed()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|keg| (keg.name, keg.version))
+            .collect()
+    }
+
+    /// Record what an operation changed since `before` in the history and
+    /// return those changes. Successful operations that changed nothing are
+    /// left out unless they carry a `detail`.
+    ///
+    /// Best effort, like stats: a failure to record is only logged.
+    pub(super) fn record_operation<T>(
+        &mut self,
+        kind: OperationKind,
+        before: BTreeMap<String, String>,
+        result: &Result<T, Error>,
+        detail: Option<String>,
+    ) -> Vec<PackageChange> {
+        let changes = diff_versions(before, self.installed_versions());
+        if result.is_ok() && changes.is_empty() && detail.is_none() {
+            return changes;
+        }
+        let error = result.as_ref().err().map(ToString::to_string);
+        if let Err(e) =
+            self.db
+                .record_operation(kind.as_str(), error.as_deref(), detail.as_deref(), &changes)
+        {
+            debug!(kind = kind.as_str(), error = %e, "failed to record operation");
+        }
+        changes
+    }
+}
+
+fn diff_versions(
+    mut before: BTreeMap<String, String>,
+    after: BTreeMap<String, String>,
+) -> Vec<PackageChange> {
+    let mut changes = Vec::new();
+    for (name, to) in after {
+        let from = before.remove(&name);
+        if from.as_ref() != Some(&to) {
+            changes.push(PackageChange {
+                name,
+                from,
+                to: Some(to),
+            });
+        }
+    }
+    changes.extend(before.into_iter().map(|(name, from)| PackageChange {
+        name,
+        from: Some(from),
+        to: None,
+    }));
+    changes.sort_by(|a, b| a.name.cmp(&b.name));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::GcOptions;
+    use crate::installer::install::test_support::*;
+
+    async fn mount_formula(server: &MockServer, name: &str, deps: &[&str]) {
+        let bottle = create_bottle_tarball(name);
+        let tag = get_test_bottle_tag();
+        let bottle_path = format!("/bottles/{name}-1.0.0.{tag}.bottle.tar.gz");
+        let formula = serde_json::json!({
+            "name": name,
+            "versions": { "stable": "1.0.0" },
+            "dependencies": deps,
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}{bottle_path}", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path(format!("/formula/{name}.json")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn records_operations_and_undoes_an_install() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "app", &["libdep"]).await;
+        mount_formula(&server, "libdep", &[]).await;
+
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+
+        installer.install(&["app".to_string()], true).await.unwrap();
+        // Already installed, so nothing changes and nothing is recorded.
+        installer.install(&["app".to_string()], true).await.unwrap();
+        let history = installer.history(10).unwrap();
+        assert_eq!(history.len(), 1);
+        let install = &history[0];
+        assert_eq!(install.kind, "install");
+        assert_eq!(install.error, None);
+        assert_eq!(
+            install.changes,
+            [
+                PackageChange {
+                    name: "app".to_string(),
+                    from: None,
+                    to: Some("1.0.0".to_string()),
+                },
+                PackageChange {
+                    name: "libdep".to_string(),
+                    from: None,
+                    to: Some("1.0.0".to_string()),
+                },
+            ]
+        );
+
+        let undone = installer.undo(install.id).unwrap();
+        assert!(undone.iter().all(|change| change.to.is_none()));
+        assert!(!installer.is_installed("app"));
+        assert!(!installer.is_installed("libdep"));
+
+        let undo = &installer.history(1).unwrap()[0];
+        assert_eq!(undo.kind, "undo");
+        assert_eq!(undo.detail.as_deref(), Some("undid #1 (install)"));
+        assert_eq!(undo.changes, undone);
+        // The undo removed packages, which cannot be brought back this way,
+        // and the install can no longer be undone either.
+        for id in [undo.id, install.id] {
+            assert!(matches!(
+                installer.undo(id),
+                Err(Error::InvalidArgument { .. })
+            ));
+        }
+        assert!(matches!(
+            installer.undo(99),
+            Err(Error::InvalidArgument { .. })
+        ));
+
+        installer.gc(&GcOptions::default()).unwrap();
+        let gc = &installer.history(1).unwrap()[0];
+        assert_eq!(gc.kind, "gc");
+        assert!(gc.changes.is_empty());
+        assert!(
+            gc.detail
+                .as_deref()
+                .unwrap()
+                .starts_with("removed 2 store entries")
+        );
+        assert_eq!(installer.history(10).unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn undoing_an_install_keeps_dependencies_a_later_install_needs() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "app", &["libdep"]).await;
+        mount_formula(&server, "tool", &["libdep"]).await;
+        mount_formula(&server, "libdep", &[]).await;
+
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer.install(&["app".to_string()], true).await.unwrap();
+        installer
+            .install(&["tool".to_string()], true)
+            .await
+            .unwrap();
+        let install = installer.history(10).unwrap().pop().unwrap();
+        assert_eq!(install.changes.len(), 2);
+
+        let undone = installer.undo(install.id).unwrap();
+        assert_eq!(
+            undone,
+            [PackageChange {
+                name: "app".to_string(),
+                from: Some("1.0.0".to_string()),
+                to: None,
+            }]
+        );
+        assert!(!installer.is_installed("app"));
+        assert!(installer.is_installed("libdep"));
+        assert!(installer.is_installed("tool"));
+        let undo = &installer.history(1).unwrap()[0];
+        assert_eq!(
+            undo.detail.as_deref(),
+            Some("undid #1 (install); kept libdep, still needed by other packages")
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use tracing::{debug, warn};
+use zb_core::{Error, InstallMethod};
+
+use super::{Installer, PlannedInstall};
+
+/// Where in an install a hook runs. Each is an executable of that name in
+/// the hooks directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    /// Before formulas are resolved, with the requested names.
+    PrePlan,
+    /// Before each formula is installed.
+    PreInstall,
+    /// After each formula is installed and linked.
+    PostInstall,
+    /// After a whole plan is executed, whether or not it succeeded.
+    PostExecute,
+}
+
+impl HookPoint {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookPoint::PrePlan => "pre-plan",
+            HookPoint::PreInstall => "pre-install",
+            HookPoint::PostInstall => "post-install",
+            HookPoint::PostExecute => "post-execute",
+        }
+    }
+
+    /// Whether a failing hook stops the install. Hooks that run afterwards
+    /// can only log.
+    fn can_reject(self) -> bool {
+        matches!(self, HookPoint::PrePlan | HookPoint::PreInstall)
+    }
+}
+
+impl Installer {
+    /// Run the executables in `dir` named after a `HookPoint` at that point
+    /// of each install, with what is being installed in `ZEROBREW_*`
+    /// environment variables. A `pre-plan` or `pre-install` hook exiting
+    /// non-zero stops the install.
+    pub fn with_hooks_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.hooks_dir = Some(dir.into());
+        self
+    }
+
+    /// Run the `pre-plan` hook for `names`.
+    pub(super) fn run_pre_plan_hook(&self, names: &[String]) -> Result<(), Error> {
+        self.run_hook(
+            HookPoint::PrePlan,
+            None,
+            vec![("ZEROBREW_FORMULAS", names.join(" "))],
+        )
+    }
+
+    /// Run the `pre-install` hook for `item`.
+    pub(super) fn run_pre_install_hook(&self, item: &PlannedInstall) -> Result<(), Error> {
+        self.run_formula_hook(HookPoint::PreInstall, item)
+    }
+
+    /// Run the `post-install` hook for the just installed `item`.
+    pub(super) fn run_post_install_hook(&self, item: &PlannedInstall) {
+        let _ = self.run_formula_hook(HookPoint::PostInstall, item);
+    }
+
+    fn run_formula_hook(&self, point: HookPoint, item: &PlannedInstall) -> Result<(), Error> {
+        let version = item.formula.effective_version();
+        let mut env = vec![
+            ("ZEROBREW_FORMULA", item.install_name.clone()),
+            ("ZEROBREW_VERSION", version.clone()),
+            ("ZEROBREW_INSTALL_METHOD", item.method.kind().to_string()),
+            (
+                "ZEROBREW_ON_REQUEST",
+                if item.installed_on_request { "1" } else { "0" }.to_string(),
+            ),
+        ];
+        let (url, sha256) = match &item.method {
+            InstallMethod::Bottle(bottle) => (bottle.url.clone(), Some(bottle.sha256.clone())),
+            InstallMethod::Source(build) => {
+                (build.source_url.clone(), build.source_checksum.clone())
+            }
+            InstallMethod::Binary(binary) => (binary.url.clone(), Some(binary.sha256.clone())),
+        };
+        env.push(("ZEROBREW_URL", url));
+        if let Some(sha256) = sha256 {
+            env.push(("ZEROBREW_SHA256", sha256));
+        }
+        if point == HookPoint::PostInstall {
+            let keg = self
+                .installed_cellar(&item.install_name)
+                .keg_path(&item.formula.name, &version);
+            env.push(("ZEROBREW_KEG", keg.to_string_lossy().into_owned()));
+        }
+        self.run_hook(point, Some(&item.install_name), env)
+    }
+
+    /// Run the `post-execute` hook with the formulas that were installed.
+    pub(super) fn run_post_execute_hook(&self, installed: &[String], succeeded: bool) {
+        let status = if succeeded { "success" } else { "failure" };
+        let _ = self.run_hook(
+            HookPoint::PostExecute,
+            None,
+            vec![
+                ("ZEROBREW_INSTALLED", installed.join(" ")),
+                ("ZEROBREW_STATUS", status.to_string()),
+            ],
+        );
+    }
+
+    /// Run the hook for `point`, if there is one. Only hooks that can
+    /// reject return an error; other failures are logged.
+    fn run_hook(
+        &self,
+        point: HookPoint,
+        formula: Option<&str>,
+        env: Vec<(&str, String)>,
+    ) -> Result<(), Error> {
+        let Some(dir) = &self.hooks_dir else {
+            return Ok(());
+        };
+        let path = dir.join(point.as_str());
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        debug!(hook = point.as_str(), formula = ?formula, "running install hook");
+        let output = Command::new(&path)
+            .envs(env)
+            .env("ZEROBREW_HOOK", point.as_str())
+            .env("ZEROBREW_PREFIX", &self.prefix)
+            .stdin(Stdio::null())
+            .output();
+        let message = match output {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                match stderr.trim() {
+                    "" => format!("exited with {}", output.status),
+                    stderr => stderr.to_string(),
+                }
+            }
+            Err(e) => format!("failed to run {}: {e}", path.display()),
+        };
+        if point.can_reject() {
+            return Err(Error::HookRejected {
+                hook: point.as_str().to_string(),
+                formula: formula.map(str::to_string),
+                message,
+            });
+        }
+        warn!(hook = point.as_str(), formula = ?formula, %message, "install hook failed");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+
+    fn write_hook(dir: &Path, point: HookPoint, script: &str) {
+        let path = dir.join(point.as_str());
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    async fn hooked_installer(server: &MockServer, tmp: &TempDir) -> Installer {
+        let bottle = create_bottle_tarball("hooked");
+        let tag = get_test_bottle_tag();
+        let formula = serde_json::json!({
+            "name": "hooked",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}/bottles/hooked-1.0.0.{tag}.bottle.tar.gz", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/hooked.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/hooked-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(server)
+            .await;
+
+        installer(tmp.path(), &server.uri()).with_hooks_dir(tmp.path().join("hooks"))
+    }
+
+    #[tokio::test]
+    async fn hooks_run_around_each_install_with_formula_metadata() {
+        let server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let hooks = tmp.path().join("hooks");
+        fs::create_dir_all(&hooks).unwrap();
+        let log = tmp.path().join("hooks.log");
+        let append = |line: &str| format!("echo \"{line}\" >> '{}'", log.display());
+        write_hook(
+            &hooks,
+            HookPoint::PrePlan,
+            &append("$ZEROBREW_HOOK $ZEROBREW_FORMULAS"),
+        );
+        write_hook(
+            &hooks,
+            HookPoint::PreInstall,
+            &append(
+                "$ZEROBREW_HOOK $ZEROBREW_FORMULA $ZEROBREW_VERSION $ZEROBREW_INSTALL_METHOD $ZEROBREW_ON_REQUEST",
+            ),
+        );
+        write_hook(
+            &hooks,
+            HookPoint::PostInstall,
+            &format!(
+                "test -d \"$ZEROBREW_KEG\" && {}",
+                append("$ZEROBREW_HOOK $ZEROBREW_FORMULA")
+            ),
+        );
+        write_hook(
+            &hooks,
+            HookPoint::PostExecute,
+            &append("$ZEROBREW_HOOK $ZEROBREW_STATUS $ZEROBREW_INSTALLED"),
+        );
+
+        let mut installer = hooked_installer(&server, &tmp).await;
+        installer
+            .install(&["hooked".to_string()], true)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            "pre-plan hooked\n\
+             pre-install hooked 1.0.0 bottle 1\n\
+             post-install hooked\n\
+             post-execute success hooked\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_pre_install_hook_blocks_the_install() {
+        let server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let hooks = tmp.path().join("hooks");
+        fs::create_dir_all(&hooks).unwrap();
+        let log = tmp.path().join("hooks.log");
+        write_hook(
+            &hooks,
+            HookPoint::PreInstall,
+            "echo \"$ZEROBREW_FORMULA is not approved\" >&2\nexit 1",
+        );
+        // A failing post hook is only logged.
+        write_hook(
+            &hooks,
+            HookPoint::PostExecute,
+            &format!("echo \"$ZEROBREW_STATUS\" > '{}'\nexit 3", log.display()),
+        );
+
+        let mut installer = hooked_installer(&server, &tmp).await;
+        let Err(err) = installer.install(&["hooked".to_string()], true).await else {
+            panic!("expected the pre-install hook to block the install");
+        };
+
+        assert_eq!(
+            err,
+            Error::HookRejected {
+                hook: "pre-install".to_string(),
+                formula: Some("hooked".to_string()),
+                message: "hooked is not approved".to_string(),
+            }
+        );
+        assert!(!installer.is_installed("hooked"));
+        assert_eq!(fs::read_to_string(&log).unwrap(), "failure\n");
+    }
+}
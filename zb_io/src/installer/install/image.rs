@@ -0,0 +1,436 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use tar::{Builder, EntryType, Header, HeaderMode};
+use walkdir::WalkDir;
+use zb_core::{Error, formula_token};
+
+use crate::checksum::sha256_hex;
+use crate::storage::db::InstalledKeg;
+
+use super::Installer;
+
+/// The generated environment script, relative to the prefix.
+const ENV_SCRIPT: &str = "etc/zerobrew/env.sh";
+
+/// What `Installer::export_image` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageLayout {
+    /// A root filesystem directory, to `COPY` into an image.
+    Directory,
+    /// A gzipped OCI image layer, to `ADD` into an image or push as a
+    /// layer of one.
+    Layer,
+}
+
+/// The result of exporting a formula as an image.
+#[derive(Debug, Clone)]
+pub struct ExportedImage {
+    /// The formula's keg followed by its runtime dependencies.
+    pub kegs: Vec<InstalledKeg>,
+    /// Where the environment script lives inside the image.
+    pub env_script: PathBuf,
+    /// The variables the script exports.
+    pub env: Vec<(&'static str, String)>,
+    /// `sha256:` digest of the compressed layer, for `ImageLayout::Layer`.
+    pub digest: Option<String>,
+}
+
+impl Installer {
+    /// Assemble the installed formula `name` and every installed keg it
+    /// depends on into `output`, at the same absolute paths they have here,
+    /// with their opt links and a script setting `PATH` and friends. The
+    /// result runs in a container without zerobrew installed in it.
+    ///
+    /// A directory `output` must be empty or missing; a layer overwrites.
+    pub fn export_image(
+        &self,
+        name: &str,
+        output: &Path,
+        layout: ImageLayout,
+    ) -> Result<ExportedImage, Error> {
+        let formula = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let mut kegs = vec![formula];
+        kegs.extend(self.installed_dependencies_of(name)?);
+
+        let names: Vec<String> = kegs.iter().map(|keg| keg.name.clone()).collect();
+        let env = self.runtime_env(&names, false)?;
+        let env_script = self.prefix.join(ENV_SCRIPT);
+
+        let mut image = match layout {
+            ImageLayout::Directory => ImageWriter::directory(output)?,
+            ImageLayout::Layer => ImageWriter::layer(output)?,
+        };
+        for keg in &kegs {
+            let keg_path = self.installed_keg_path(keg);
+            image.add_tree(&keg_path)?;
+            let opt = self.prefix.join("opt").join(formula_token(&keg.name));
+            let target = fs::read_link(&opt).unwrap_or(keg_path);
+            image.add_symlink(&opt, &target)?;
+        }
+        image.add_file(&env_script, env_script_for(name, &env).as_bytes(), 0o644)?;
+        let digest = image.finish()?;
+
+        Ok(ExportedImage {
+            kegs,
+            env_script,
+            env,
+            digest,
+        })
+    }
+}
+
+fn env_script_for(name: &str, env: &[(&'static str, String)]) -> String {
+    let mut script = format!(
+        "# Environment for {name}, generated by `zb export-image`.\n\
+         # Source it, or copy these variables into the image's config.\n"
+    );
+    for (var, value) in env {
+        script.push_str(&format!(
+            "export {var}='{}'\n",
+            value.replace('\'', r"'\''")
+        ));
+    }
+    script
+}
+
+/// `path` as it appears inside the image: relative to its root.
+fn image_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect()
+}
+
+/// Writes the image's files, either as a tree on disk or into a layer
+/// tarball, taking host paths and placing them at the same path inside.
+enum ImageWriter {
+    Directory {
+        root: PathBuf,
+        /// Modes applied to directories once their contents are written.
+        dir_modes: Vec<(PathBuf, u32)>,
+    },
+    Layer {
+        path: PathBuf,
+        builder: Box<Builder<GzEncoder<BufWriter<File>>>>,
+        /// Directories already in the layer.
+        dirs: BTreeSet<PathBuf>,
+    },
+}
+
+impl ImageWriter {
+    fn directory(root: &Path) -> Result<Self, Error> {
+        let ctx = format!("failed to create {}", root.display());
+        if root.exists()
+            && fs::read_dir(root)
+                .map_err(Error::file(&ctx))?
+                .next()
+                .is_some()
+        {
+            return Err(Error::InvalidArgument {
+                message: format!("{} already exists and is not empty", root.display()),
+            });
+        }
+        fs::create_dir_all(root).map_err(Error::file(&ctx))?;
+        Ok(Self::Directory {
+            root: root.to_path_buf(),
+            dir_modes: Vec::new(),
+        })
+    }
+
+    fn layer(path: &Path) -> Result<Self, Error> {
+        let file = File::create(path)
+            .map_err(Error::file(&format!("failed to create {}", path.display())))?;
+        let mut builder =
+            Builder::new(GzEncoder::new(BufWriter::new(file), Compression::default()));
+        // Reproducible layers: fixed mtimes and root ownership.
+        builder.mode(HeaderMode::Deterministic);
+        builder.follow_symlinks(false);
+        Ok(Self::Layer {
+            path: path.to_path_buf(),
+            builder: Box::new(builder),
+            dirs: BTreeSet::new(),
+        })
+    }
+
+    /// Copy the directory `host` and everything in it, keeping symlinks.
+    fn add_tree(&mut self, host: &Path) -> Result<(), Error> {
+        for entry in WalkDir::new(host).follow_links(false).sort_by_file_name() {
+            let entry = entry.map_err(Error::file("failed to read keg"))?;
+            let source = entry.path();
+            let ctx = format!("failed to add {} to the image", source.display());
+            let inside = image_path(source);
+            let file_type = entry.file_type();
+            match self {
+                Self::Directory { root, dir_modes } => {
+                    let dest = root.join(&inside);
+                    if file_type.is_dir() {
+                        fs::create_dir_all(&dest).map_err(Error::file(&ctx))?;
+                        let metadata = entry.metadata().map_err(Error::file(&ctx))?;
+                        dir_modes.push((dest, metadata.permissions().mode() & 0o7777));
+                    } else if file_type.is_symlink() {
+                        let target = fs::read_link(source).map_err(Error::file(&ctx))?;
+                        std::os::unix::fs::symlink(target, &dest).map_err(Error::file(&ctx))?;
+                    } else {
+                        fs::copy(source, &dest).map_err(Error::file(&ctx))?;
+                    }
+                }
+                Self::Layer { builder, dirs, .. } => {
+                    add_layer_parents(builder, dirs, &inside).map_err(Error::file(&ctx))?;
+                    if file_type.is_dir() {
+                        dirs.insert(inside.clone());
+                    }
+                    builder
+                        .append_path_with_name(source, &inside)
+                        .map_err(Error::file(&ctx))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_symlink(&mut self, host: &Path, target: &Path) -> Result<(), Error> {
+        let ctx = format!("failed to add {} to the image", host.display());
+        let inside = image_path(host);
+        match self {
+            Self::Directory { root, .. } => {
+                let dest = root.join(&inside);
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent).map_err(Error::file(&ctx))?;
+                }
+                std::os::unix::fs::symlink(target, &dest).map_err(Error::file(&ctx))
+            }
+            Self::Layer { builder, dirs, .. } => {
+                add_layer_parents(builder, dirs, &inside).map_err(Error::file(&ctx))?;
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Symlink);
+                header.set_mode(0o777);
+                header.set_size(0);
+                builder
+                    .append_link(&mut header, &inside, target)
+                    .map_err(Error::file(&ctx))
+            }
+        }
+    }
+
+    fn add_file(&mut self, host: &Path, content: &[u8], mode: u32) -> Result<(), Error> {
+        let ctx = format!("failed to add {} to the image", host.display());
+        let inside = image_path(host);
+        match self {
+            Self::Directory { root, .. } => {
+                let dest = root.join(&inside);
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent).map_err(Error::file(&ctx))?;
+                }
+                fs::write(&dest, content).map_err(Error::file(&ctx))?;
+                fs::set_permissions(&dest, fs::Permissions::from_mode(mode))
+                    .map_err(Error::file(&ctx))
+            }
+            Self::Layer { builder, dirs, .. } => {
+                add_layer_parents(builder, dirs, &inside).map_err(Error::file(&ctx))?;
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Regular);
+                header.set_mode(mode);
+                header.set_size(content.len() as u64);
+                builder
+                    .append_data(&mut header, &inside, content)
+                    .map_err(Error::file(&ctx))
+            }
+        }
+    }
+
+    /// Apply directory modes or close the layer, returning its digest.
+    fn finish(self) -> Result<Option<String>, Error> {
+        match self {
+            Self::Directory { dir_modes, .. } => {
+                // Deepest first, so read-only directories are set last.
+                for (dir, mode) in dir_modes.into_iter().rev() {
+                    fs::set_permissions(&dir, fs::Permissions::from_mode(mode)).map_err(
+                        Error::file(&format!("failed to set the mode of {}", dir.display())),
+                    )?;
+                }
+                Ok(None)
+            }
+            Self::Layer { path, builder, .. } => {
+                let ctx = format!("failed to write {}", path.display());
+                builder
+                    .into_inner()
+                    .and_then(GzEncoder::finish)
+                    .and_then(|writer| writer.into_inner().map_err(io::Error::other))
+                    .map_err(Error::file(&ctx))?;
+                layer_digest(&path).map(Some)
+            }
+        }
+    }
+}
+
+/// Append an entry for each directory above `inside` not yet in the layer,
+/// as image unpackers expect every parent to be listed.
+fn add_layer_parents<W: io::Write>(
+    builder: &mut Builder<W>,
+    dirs: &mut BTreeSet<PathBuf>,
+    inside: &Path,
+) -> io::Result<()> {
+    let parents: Vec<&Path> = inside
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    for dir in parents.into_iter().rev() {
+        if dirs.insert(dir.to_path_buf()) {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            builder.append_data(&mut header, dir, io::empty())?;
+        }
+    }
+    Ok(())
+}
+
+fn layer_digest(path: &Path) -> Result<String, Error> {
+    let ctx = format!("failed to read {}", path.display());
+    let mut file = File::open(path).map_err(Error::file(&ctx))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 << 10];
+    loop {
+        let read = file.read(&mut buf).map_err(Error::file(&ctx))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(format!("sha256:{}", sha256_hex(hasher)))
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::read::GzDecoder;
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::{
+        create_bottle_tarball, get_test_bottle_tag, installer, sha256_hex,
+    };
+
+    async fn mount_formula(server: &MockServer, name: &str, deps: &[&str]) {
+        let bottle = create_bottle_tarball(name);
+        let tag = get_test_bottle_tag();
+        let bottle_path = format!("/bottles/{name}-1.0.0.{tag}.bottle.tar.gz");
+        let formula = serde_json::json!({
+            "name": name,
+            "versions": { "stable": "1.0.0" },
+            "dependencies": deps,
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}{bottle_path}", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path(format!("/formula/{name}.json")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn exports_the_runtime_closure_as_a_directory_and_a_layer() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "app", &["libdep"]).await;
+        mount_formula(&server, "libdep", &[]).await;
+        mount_formula(&server, "other", &[]).await;
+
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer
+            .install(&["app".to_string(), "other".to_string()], true)
+            .await
+            .unwrap();
+
+        let out = tmp.path().join("image");
+        let image = installer
+            .export_image("app", &out, ImageLayout::Directory)
+            .unwrap();
+        let names: Vec<&str> = image.kegs.iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["app", "libdep"]);
+        assert_eq!(image.digest, None);
+
+        // Kegs keep their absolute paths, so the opt links stay valid.
+        let inside = |path: &Path| out.join(image_path(path));
+        let app_keg = installer.keg_path("app", "1.0.0");
+        let app_bin = inside(&app_keg.join("bin/app"));
+        assert_eq!(
+            fs::read_to_string(&app_bin).unwrap(),
+            "#!/bin/sh\necho app v1.0.0"
+        );
+        assert!(fs::metadata(&app_bin).unwrap().permissions().mode() & 0o111 != 0);
+        assert_eq!(
+            fs::read_link(inside(&prefix.join("opt/app"))).unwrap(),
+            app_keg
+        );
+        assert!(inside(&installer.keg_path("libdep", "1.0.0").join("bin/libdep")).exists());
+        assert!(
+            inside(&prefix.join("opt/other"))
+                .symlink_metadata()
+                .is_err()
+        );
+
+        let script = fs::read_to_string(inside(&image.env_script)).unwrap();
+        let app_path = prefix.join("opt/app/bin").display().to_string();
+        assert!(
+            script.contains(&format!("export PATH='{app_path}:")),
+            "{script}"
+        );
+        assert!(!script.contains(&format!("{}:", prefix.join("bin").display())));
+
+        // Directories are never merged into.
+        assert!(matches!(
+            installer.export_image("app", &out, ImageLayout::Directory),
+            Err(Error::InvalidArgument { .. })
+        ));
+        assert!(matches!(
+            installer.export_image("missing", &tmp.path().join("x"), ImageLayout::Directory),
+            Err(Error::NotInstalled { .. })
+        ));
+
+        let layer = tmp.path().join("app.tar.gz");
+        let image = installer
+            .export_image("app", &layer, ImageLayout::Layer)
+            .unwrap();
+        let digest = image.digest.unwrap();
+        assert_eq!(digest, layer_digest(&layer).unwrap());
+
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(&layer).unwrap()));
+        let mut seen = BTreeSet::new();
+        for entry in archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            let path = entry.path().unwrap().into_owned();
+            assert!(path.is_relative(), "{}", path.display());
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                assert!(
+                    seen.contains(parent),
+                    "{} before its parent",
+                    path.display()
+                );
+            }
+            seen.insert(path);
+        }
+        assert!(seen.contains(&image_path(&prefix.join("opt/app"))));
+        assert!(seen.contains(&image_path(&image.env_script)));
+    }
+}
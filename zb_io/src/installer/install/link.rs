@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+use zb_core::{ConflictedLink, Error, InstallMethod, formula_token};
+
+use crate::cellar::link::{LinkMode, LinkedFile};
+use crate::cellar::materialize::find_bottle_content;
+use crate::network::download::DownloadResult;
+use crate::transcript::TranscriptEvent;
+
+use super::{Installer, PlannedInstall, acquire_install_lock};
+
+/// What to do when linking a keg would replace files already in the prefix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkStrategy {
+    /// Leave the keg unlinked and report the conflicts.
+    #[default]
+    Refuse,
+    /// Replace links that belong to another installed formula. Files
+    /// zerobrew did not create are never touched.
+    Overwrite,
+    /// Link the conflicting files of a versioned formula (`name@X`) with an
+    /// `-X` suffix instead, e.g. `bin/python3-3.11`.
+    KeepBoth,
+}
+
+impl Installer {
+    /// How `execute` resolves link conflicts. Defaults to refusing.
+    pub fn with_link_strategy(mut self, strategy: LinkStrategy) -> Self {
+        self.link_strategy = strategy;
+        self
+    }
+
+    /// How executables are linked for formulas without a mode of their own.
+    /// Defaults to symlinks.
+    pub fn with_link_mode(mut self, mode: LinkMode) -> Self {
+        self.link_mode = mode;
+        self
+    }
+
+    /// How the executables of `name` are linked: its own mode if it has one,
+    /// the global mode otherwise.
+    pub fn link_mode_for(&self, name: &str) -> LinkMode {
+        self.db
+            .link_mode(name)
+            .ok()
+            .flatten()
+            .unwrap_or(self.link_mode)
+    }
+
+    /// Link the installed keg of `name` into the prefix, for example after
+    /// an install left it unlinked. Returns the number of files linked. A
+    /// `mode` is remembered for `name` and used by its later installs and
+    /// upgrades too; links already placed another way are replaced.
+    pub fn link(
+        &mut self,
+        name: &str,
+        strategy: LinkStrategy,
+        mode: Option<LinkMode>,
+    ) -> Result<usize, Error> {
+        let _lock = acquire_install_lock(&self.locks_dir)?;
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        if mode.is_some() {
+            self.db.set_link_mode(name, mode)?;
+        }
+        let keg_path = self
+            .installed_cellar(name)
+            .keg_path(formula_token(name), &installed.version);
+        let linked = self.link_installed_keg(name, &installed.version, &keg_path, strategy)?;
+        Ok(linked.len())
+    }
+
+    /// Link `keg_path` and record the links as owned by `name`, resolving
+    /// conflicts according to `strategy`. On failure the keg is left
+    /// unlinked and conflicts are attributed to the formulas that own them.
+    pub(super) fn link_installed_keg(
+        &mut self,
+        name: &str,
+        version: &str,
+        keg_path: &Path,
+        strategy: LinkStrategy,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        let mode = self.link_mode_for(name);
+        let result = match self
+            .linker
+            .link_keg_renaming(keg_path, &HashMap::new(), mode)
+        {
+            Err(Error::LinkConflict { conflicts, .. }) if strategy != LinkStrategy::Refuse => {
+                self.resolve_link_conflicts(name, keg_path, conflicts, strategy, mode)
+            }
+            result => result,
+        };
+
+        match result {
+            Ok(linked_files) => {
+                for file in &linked_files {
+                    self.record_event(TranscriptEvent::Link {
+                        formula: name.to_string(),
+                        link: file.link_path.clone(),
+                        target: file.target_path.clone(),
+                    });
+                }
+                self.record_linked_files(name, version, &linked_files);
+                Ok(linked_files)
+            }
+            Err(e) => {
+                let _ = self.linker.unlink_keg(keg_path);
+                Err(self.attribute_link_conflict(name, e))
+            }
+        }
+    }
+
+    /// Pre-flight for a whole plan, run before any keg is materialized:
+    /// find every file the downloaded bottles would link over, whether it
+    /// already exists in the prefix or another keg of the plan links it
+    /// too. Kegs are read from their unpacked store entries; keg-only
+    /// formulas and links the formula itself already owns are skipped.
+    pub(super) fn check_plan_links(
+        &self,
+        kegs: &[(&PlannedInstall, &DownloadResult)],
+    ) -> Result<(), Error> {
+        let mut claimed: HashMap<PathBuf, &str> = HashMap::new();
+        let mut conflicts = Vec::new();
+        let mut conflicting = Vec::new();
+        for (item, download) in kegs {
+            let InstallMethod::Bottle(ref bottle) = item.method else {
+                continue;
+            };
+            if item.formula.is_keg_only() {
+                continue;
+            }
+            let name = item.install_name.as_str();
+            // A bottle that fails to unpack is retried, and checked when
+            // linked, by the install itself.
+            let Ok(content) = self
+                .store
+                .ensure_entry(&bottle.sha256, &download.blob_path)
+                .and_then(|entry| {
+                    find_bottle_content(
+                        &entry,
+                        &item.formula.name,
+                        &item.formula.effective_version(),
+                    )
+                })
+            else {
+                continue;
+            };
+
+            let mut found = Vec::new();
+            if let Err(Error::LinkConflict { conflicts, .. }) =
+                self.linker.check_conflicts(&content)
+            {
+                for c in conflicts {
+                    let owned_by = self.db.link_owner(&c.path).ok().flatten().or(c.owned_by);
+                    let own = owned_by
+                        .as_deref()
+                        .is_some_and(|owner| owner == name || owner == formula_token(name));
+                    if !own {
+                        found.push(ConflictedLink {
+                            path: c.path,
+                            owned_by,
+                        });
+                    }
+                }
+            }
+            for path in self.linker.link_destinations(&content) {
+                match claimed.get(&path) {
+                    Some(other) => found.push(ConflictedLink {
+                        owned_by: Some(other.to_string()),
+                        path,
+                    }),
+                    None => {
+                        claimed.insert(path, name);
+                    }
+                }
+            }
+
+            if !found.is_empty() {
+                conflicting.push(name);
+                conflicts.extend(found);
+            }
+        }
+
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+        Err(Error::LinkConflict {
+            formula: match conflicting.as_slice() {
+                [name] => Some(name.to_string()),
+                _ => None,
+            },
+            conflicts,
+        })
+    }
+
+    fn resolve_link_conflicts(
+        &mut self,
+        name: &str,
+        keg_path: &Path,
+        conflicts: Vec<ConflictedLink>,
+        strategy: LinkStrategy,
+        mode: LinkMode,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        match strategy {
+            LinkStrategy::Refuse => Err(Error::LinkConflict {
+                formula: None,
+                conflicts,
+            }),
+            LinkStrategy::Overwrite => {
+                for conflict in &conflicts {
+                    if !conflict.path.is_symlink() || self.db.link_owner(&conflict.path)?.is_none()
+                    {
+                        return Err(Error::LinkConflict {
+                            formula: None,
+                            conflicts,
+                        });
+                    }
+                }
+
+                let tx = self.db.transaction()?;
+                for conflict in &conflicts {
+                    debug!(formula = %name, path = %conflict.path.display(), "overwriting link");
+                    fs::remove_file(&conflict.path)
+                        .map_err(Error::store("failed to remove conflicting link"))?;
+                    tx.forget_linked_file(&conflict.path)?;
+                }
+                tx.commit()?;
+                self.linker
+                    .link_keg_renaming(keg_path, &HashMap::new(), mode)
+            }
+            LinkStrategy::KeepBoth => {
+                let Some((_, suffix)) = name.split_once('@') else {
+                    return Err(Error::LinkConflict {
+                        formula: None,
+                        conflicts,
+                    });
+                };
+                let renames: HashMap<PathBuf, PathBuf> = conflicts
+                    .into_iter()
+                    .map(|c| {
+                        let renamed = suffixed_link_path(&c.path, suffix);
+                        (c.path, renamed)
+                    })
+                    .collect();
+                self.linker.link_keg_renaming(keg_path, &renames, mode)
+            }
+        }
+    }
+
+    /// Name the formula being linked and, where the database knows it, the
+    /// formula that owns each conflicting link.
+    fn attribute_link_conflict(&self, name: &str, error: Error) -> Error {
+        let Error::LinkConflict { conflicts, .. } = error else {
+            return error;
+        };
+        let conflicts = conflicts
+            .into_iter()
+            .map(|c| ConflictedLink {
+                owned_by: self.db.link_owner(&c.path).ok().flatten().or(c.owned_by),
+                path: c.path,
+            })
+            .collect();
+        Error::LinkConflict {
+            formula: Some(name.to_string()),
+            conflicts,
+        }
+    }
+
+    /// Unlink `keg_path`, including links recorded for `name` under another
+    /// file name (see [`LinkStrategy::KeepBoth`]), hard links and wrappers
+    /// whose keg file is already gone, and links to files of its manifest,
+    /// which walking the keg does not find.
+    pub(super) fn unlink_installed_keg(&self, name: &str, keg_path: &Path) -> Result<(), Error> {
+        self.linker.unlink_keg(keg_path)?;
+        for record in self.db.keg_files(name)? {
+            self.linker.remove_recorded(
+                Path::new(&record.linked_path),
+                Path::new(&record.target_path),
+                record.mode,
+            );
+        }
+        self.unlink_manifest_files(name, keg_path)
+    }
+}
+
+fn suffixed_link_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!("-{suffix}"));
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::Installer;
+    use crate::installer::install::test_support::*;
+
+    use super::*;
+
+    async fn mount_formula(server: &MockServer, name: &str, version: &str) {
+        let bottle = create_bottle_tarball_with_version(name, version);
+        mount_bottle(server, name, version, bottle).await;
+    }
+
+    async fn mount_bottle(server: &MockServer, name: &str, version: &str, bottle: Vec<u8>) {
+        let sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+        let bottle_path = format!("/bottles/{name}-{version}.{tag}.bottle.tar.gz");
+        let formula = serde_json::json!({
+            "name": name,
+            "versions": { "stable": version },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}{bottle_path}", server.uri()),
+                "sha256": sha,
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path(format!("/formula/{name}.json")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(server)
+            .await;
+    }
+
+    /// A bottle of `name` shipping the executables `bins`.
+    fn bottle_with_bins(name: &str, bins: &[&str]) -> Vec<u8> {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut builder = tar::Builder::new(Vec::new());
+        for bin in bins {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(format!("{name}/1.0.0/bin/{bin}")).unwrap();
+            header.set_size(2);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append(&header, &b"hi"[..]).unwrap();
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn install(installer: &mut Installer, name: &str) {
+        let plan = installer.plan(&[name.to_string()]).await.unwrap();
+        installer.execute(plan, true).await.unwrap();
+    }
+
+    fn record_foreign_link(installer: &mut Installer, owner: &str, link: &Path, target: &Path) {
+        std::os::unix::fs::symlink(target, link).unwrap();
+        let tx = installer.db.transaction().unwrap();
+        tx.record_linked_file(
+            owner,
+            "1.0",
+            &link.to_string_lossy(),
+            &target.to_string_lossy(),
+            LinkMode::Symlink,
+        )
+        .unwrap();
+        tx.commit().unwrap();
+    }
+
+    #[tokio::test]
+    async fn conflicts_name_the_owning_formula() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "tool", "1.0.0").await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &server.uri());
+
+        let other = tmp.path().join("other-tool");
+        fs::write(&other, "other").unwrap();
+        record_foreign_link(&mut installer, "other", &prefix.join("bin/tool"), &other);
+
+        let plan = installer.plan(&["tool".to_string()]).await.unwrap();
+        let err = installer.execute(plan, true).await.err().unwrap();
+
+        match &err {
+            Error::LinkConflict { formula, conflicts } => {
+                assert_eq!(formula.as_deref(), Some("tool"));
+                assert_eq!(conflicts[0].owned_by.as_deref(), Some("other"));
+            }
+            other => panic!("expected link conflict, got {other:?}"),
+        }
+        assert!(err.to_string().starts_with("cannot link tool:"));
+    }
+
+    #[tokio::test]
+    async fn plan_conflicts_are_found_before_anything_is_installed() {
+        let server = MockServer::start().await;
+        mount_bottle(
+            &server,
+            "alpha",
+            "1.0.0",
+            bottle_with_bins("alpha", &["alpha"]),
+        )
+        .await;
+        mount_bottle(
+            &server,
+            "beta",
+            "1.0.0",
+            bottle_with_bins("beta", &["alpha", "beta", "stray"]),
+        )
+        .await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &server.uri());
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(prefix.join("bin/stray"), "user file").unwrap();
+
+        let names = ["alpha".to_string(), "beta".to_string()];
+        let plan = installer.plan(&names).await.unwrap();
+        let err = installer.execute(plan, true).await.err().unwrap();
+
+        match &err {
+            Error::LinkConflict { formula, conflicts } => {
+                assert_eq!(formula.as_deref(), Some("beta"));
+                let found: Vec<_> = conflicts
+                    .iter()
+                    .map(|c| (c.path.clone(), c.owned_by.clone()))
+                    .collect();
+                assert_eq!(
+                    found,
+                    [
+                        (prefix.join("bin/alpha"), Some("alpha".to_string())),
+                        (prefix.join("bin/stray"), None),
+                    ]
+                );
+            }
+            other => panic!("expected link conflict, got {other:?}"),
+        }
+        assert!(!installer.is_installed("alpha"));
+        assert!(!installer.is_installed("beta"));
+        assert!(!prefix.join("bin/alpha").exists());
+        assert!(!installer.cellar.keg_path("alpha", "1.0.0").exists());
+    }
+
+    #[tokio::test]
+    async fn overwrite_replaces_only_zerobrew_links() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "tool", "1.0.0").await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let mut installer =
+            installer(tmp.path(), &server.uri()).with_link_strategy(LinkStrategy::Overwrite);
+        let link = prefix.join("bin/tool");
+
+        let other = tmp.path().join("other-tool");
+        fs::write(&other, "other").unwrap();
+        record_foreign_link(&mut installer, "other", &link, &other);
+
+        install(&mut installer, "tool").await;
+        assert!(
+            fs::read_link(&link)
+                .unwrap()
+                .ends_with("tool/1.0.0/bin/tool")
+        );
+        assert_eq!(
+            installer.db.link_owner(&link).unwrap().as_deref(),
+            Some("tool")
+        );
+
+        // A file zerobrew did not create is left alone.
+        let keg = installer.cellar.keg_path("tool", "1.0.0");
+        installer.linker.unlink_keg(&keg).unwrap();
+        fs::write(&link, "user file").unwrap();
+        let err = installer
+            .link("tool", LinkStrategy::Overwrite, None)
+            .unwrap_err();
+        assert!(matches!(err, Error::LinkConflict { .. }));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "user file");
+    }
+
+    #[tokio::test]
+    async fn keep_both_suffixes_versioned_links() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "tool@2", "2.0.0").await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &server.uri());
+
+        // Versioned formulas are keg-only, so installing does not link.
+        install(&mut installer, "tool@2").await;
+        fs::write(prefix.join("bin/tool@2"), "existing").unwrap();
+
+        assert!(matches!(
+            installer
+                .link("tool@2", LinkStrategy::Refuse, None)
+                .unwrap_err(),
+            Error::LinkConflict { .. }
+        ));
+        assert_eq!(
+            installer
+                .link("tool@2", LinkStrategy::KeepBoth, None)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/tool@2")).unwrap(),
+            "existing"
+        );
+        let suffixed = prefix.join("bin/tool@2-2");
+        assert!(
+            fs::read_link(&suffixed)
+                .unwrap()
+                .ends_with("2.0.0/bin/tool@2")
+        );
+
+        installer.uninstall("tool@2").unwrap();
+        assert!(suffixed.symlink_metadata().is_err());
+    }
+
+    #[tokio::test]
+    async fn link_mode_is_remembered_and_undone_on_uninstall() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "tool", "1.0.0").await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &server.uri()).with_link_mode(LinkMode::Hardlink);
+        let link = prefix.join("bin/tool");
+
+        install(&mut installer, "tool").await;
+        assert!(link.is_file() && !link.is_symlink());
+
+        installer
+            .link("tool", LinkStrategy::Refuse, Some(LinkMode::Wrapper))
+            .unwrap();
+        assert_eq!(installer.link_mode_for("tool"), LinkMode::Wrapper);
+        assert!(fs::read_to_string(&link).unwrap().starts_with("#!/bin/sh"));
+        let records = installer.db.list_keg_files().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].mode, LinkMode::Wrapper);
+
+        // The wrapper goes even when its keg is already gone.
+        let keg = installer.cellar.keg_path("tool", "1.0.0");
+        fs::remove_dir_all(&keg).unwrap();
+        installer.uninstall("tool").unwrap();
+        assert!(link.symlink_metadata().is_err());
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use zb_core::{DependencyGraph, Error, formula_token};
+
+use crate::storage::db::InstalledKeg;
+
+use super::Installer;
+
+impl Installer {
+    /// Compute and cache sizes for kegs installed before sizes were tracked.
+    pub fn backfill_keg_sizes(&self) -> Result<(), Error> {
+        for keg in self.db.list_installed()? {
+            if keg.size_bytes.is_some() {
+                continue;
+            }
+            let cellar = self.cellar_for(keg.arch.as_deref());
+            if let Ok(size) = cellar.keg_size(formula_token(&keg.name), &keg.version) {
+                self.db.set_keg_size(&keg.name, size)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The kegs for `names` when each is installed for the current arch
+    /// together with every dependency recorded for it, so installing them
+    /// again would change nothing; `None` as soon as anything is missing.
+    /// Reads only the database. Formulas loaded from local files never
+    /// count, as the file may hold another version.
+    pub fn installed_closure(&self, names: &[String]) -> Result<Option<Vec<InstalledKeg>>, Error> {
+        let mut kegs = Vec::with_capacity(names.len());
+        let mut seen = BTreeSet::new();
+        let mut queue: VecDeque<String> = names.iter().cloned().collect();
+        while let Some(name) = queue.pop_front() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if self.local_formula(&name).is_some() {
+                return Ok(None);
+            }
+            let Some(keg) = self.db.get_installed(&name) else {
+                return Ok(None);
+            };
+            if keg.arch.as_deref() != self.arch {
+                return Ok(None);
+            }
+            queue.extend(self.db.list_dependencies(&name)?);
+            if names.contains(&name) {
+                kegs.push(keg);
+            }
+        }
+        Ok(Some(kegs))
+    }
+
+    /// Installed kegs that `name` depends on, directly or transitively,
+    /// sorted by name.
+    pub fn installed_dependencies_of(&self, name: &str) -> Result<Vec<InstalledKeg>, Error> {
+        if self.db.get_installed(name).is_none() {
+            return Err(Error::NotInstalled {
+                name: name.to_string(),
+            });
+        }
+
+        let mut found = BTreeMap::new();
+        let mut queue = VecDeque::from([name.to_string()]);
+        while let Some(current) = queue.pop_front() {
+            for dep in self.db.list_dependencies(&current)? {
+                if found.contains_key(&dep) || dep == name {
+                    continue;
+                }
+                if let Some(keg) = self.db.get_installed(&dep) {
+                    queue.push_back(dep.clone());
+                    found.insert(dep, keg);
+                }
+            }
+        }
+
+        Ok(found.into_values().collect())
+    }
+
+    /// Installed kegs that are neither in `keep` nor a dependency, direct
+    /// or transitive, of an installed keg that is, sorted by name. What a
+    /// sync to exactly `keep` would uninstall.
+    pub fn unneeded_kegs(&self, keep: &[String]) -> Result<Vec<InstalledKeg>, Error> {
+        let kept = |name: &str| {
+            keep.iter()
+                .any(|k| k == name || formula_token(k) == formula_token(name))
+        };
+        let mut installed = self.db.list_installed()?;
+        let mut needed = BTreeSet::new();
+        let mut queue: VecDeque<String> = installed
+            .iter()
+            .filter(|keg| kept(&keg.name))
+            .map(|keg| keg.name.clone())
+            .collect();
+        while let Some(name) = queue.pop_front() {
+            if needed.insert(name.clone()) {
+                queue.extend(self.db.list_dependencies(&name)?);
+            }
+        }
+
+        installed.retain(|keg| !needed.contains(&keg.name));
+        installed.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(installed)
+    }
+
+    /// The dependency graph of the installed `names`, or of every installed
+    /// keg nothing else depends on when `names` is empty, from the edges
+    /// recorded at install time. Needs no network access.
+    pub fn installed_dependency_graph(&self, names: &[String]) -> Result<DependencyGraph, Error> {
+        let mut edges = BTreeMap::new();
+        for keg in self.db.list_installed()? {
+            let deps: Vec<String> = self
+                .db
+                .list_dependencies(&keg.name)?
+                .into_iter()
+                .filter(|dep| self.db.get_installed(dep).is_some())
+                .collect();
+            edges.insert(keg.name, deps);
+        }
+
+        let roots = if names.is_empty() {
+            let depended_on: BTreeSet<&String> = edges.values().flatten().collect();
+            edges
+                .keys()
+                .filter(|name| !depended_on.contains(name))
+                .cloned()
+                .collect()
+        } else {
+            if let Some(name) = names.iter().find(|name| !edges.contains_key(*name)) {
+                return Err(Error::NotInstalled { name: name.clone() });
+            }
+            names.to_vec()
+        };
+
+        // Keep only what the roots reach.
+        let mut reachable = BTreeMap::new();
+        let mut queue: VecDeque<&String> = roots.iter().collect();
+        while let Some(name) = queue.pop_front() {
+            if reachable.contains_key(name) {
+                continue;
+            }
+            let deps = edges.get(name).cloned().unwrap_or_default();
+            queue.extend(
+                deps.iter()
+                    .filter_map(|dep| edges.get_key_value(dep).map(|(k, _)| k)),
+            );
+            reachable.insert(name.clone(), deps);
+        }
+
+        Ok(DependencyGraph::from_edges(roots, reachable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::super::test_support::{NO_API, installer};
+    use crate::Installer;
+    use crate::storage::db::InstalledKeg;
+
+    fn record(installer: &mut Installer, name: &str, size: Option<u64>, deps: &[&str]) {
+        let deps: Vec<String> = deps.iter().map(|d| d.to_string()).collect();
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install(name, "1.0.0", name).unwrap();
+        tx.record_keg_metadata(name, size, &deps).unwrap();
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn installed_dependencies_of_walks_transitively() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), NO_API);
+
+        record(&mut installer, "app", None, &["liba", "missing"]);
+        record(&mut installer, "liba", None, &["libb"]);
+        record(&mut installer, "libb", None, &["app"]);
+        record(&mut installer, "unrelated", None, &[]);
+
+        let deps: Vec<_> = installer
+            .installed_dependencies_of("app")
+            .unwrap()
+            .into_iter()
+            .map(|k| k.name)
+            .collect();
+        assert_eq!(deps, vec!["liba", "libb"]);
+    }
+
+    #[test]
+    fn installed_closure_needs_every_recorded_dependency() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), NO_API);
+
+        record(&mut installer, "app", None, &["liba"]);
+        record(&mut installer, "liba", None, &["libb"]);
+        let names = ["app".to_string()];
+        assert!(installer.installed_closure(&names).unwrap().is_none());
+        assert!(
+            installer
+                .installed_closure(&["new".into()])
+                .unwrap()
+                .is_none()
+        );
+
+        record(&mut installer, "libb", None, &[]);
+        let kegs = installer.installed_closure(&names).unwrap().unwrap();
+        assert_eq!(kegs.len(), 1);
+        assert_eq!(kegs[0].name, "app");
+        let names = ["app".to_string(), "libb".to_string()];
+        assert_eq!(
+            installer.installed_closure(&names).unwrap().unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn unneeded_kegs_keeps_dependencies_of_kept_packages() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), NO_API);
+
+        record(&mut installer, "app", None, &["liba"]);
+        record(&mut installer, "liba", None, &["libb"]);
+        record(&mut installer, "libb", None, &[]);
+        record(&mut installer, "tool", None, &["libc", "libb"]);
+        record(&mut installer, "libc", None, &[]);
+        record(&mut installer, "cask:docker", None, &[]);
+
+        let names = |kegs: Vec<InstalledKeg>| kegs.into_iter().map(|k| k.name).collect::<Vec<_>>();
+        let keep = [
+            "app".to_string(),
+            "cask:docker".to_string(),
+            "gone".to_string(),
+        ];
+        assert_eq!(
+            names(installer.unneeded_kegs(&keep).unwrap()),
+            ["libc", "tool"]
+        );
+        assert_eq!(
+            names(
+                installer
+                    .unneeded_kegs(&["homebrew/core/tool".to_string()])
+                    .unwrap()
+            ),
+            ["app", "cask:docker", "liba"]
+        );
+    }
+
+    #[test]
+    fn installed_dependencies_of_requires_installed_formula() {
+        let tmp = TempDir::new().unwrap();
+        let installer = installer(tmp.path(), NO_API);
+
+        let err = installer.installed_dependencies_of("nope").unwrap_err();
+        assert!(matches!(err, zb_core::Error::NotInstalled { .. }));
+    }
+
+    #[test]
+    fn installed_dependency_graph_uses_recorded_edges() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), NO_API);
+
+        record(&mut installer, "app", None, &["liba", "missing"]);
+        record(&mut installer, "liba", None, &["libb"]);
+        record(&mut installer, "libb", None, &[]);
+        record(&mut installer, "tool", None, &["libb"]);
+
+        let graph = installer
+            .installed_dependency_graph(&["app".to_string()])
+            .unwrap();
+        assert_eq!(graph.roots(), ["app"]);
+        assert_eq!(graph.dependencies("app"), ["liba"]);
+        assert_eq!(graph.dependencies("liba"), ["libb"]);
+        assert!(!graph.contains("tool"));
+
+        let graph = installer.installed_dependency_graph(&[]).unwrap();
+        assert_eq!(graph.roots(), ["app", "tool"]);
+        assert!(graph.contains("libb"));
+
+        assert!(matches!(
+            installer.installed_dependency_graph(&["nope".to_string()]),
+            Err(zb_core::Error::NotInstalled { .. })
+        ));
+    }
+
+    #[test]
+    fn backfill_keg_sizes_measures_unsized_kegs() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), NO_API);
+
+        record(&mut installer, "foo", None, &[]);
+        record(&mut installer, "bar", Some(99), &[]);
+        let keg = installer.keg_path("foo", "1.0.0");
+        fs::create_dir_all(&keg).unwrap();
+        fs::write(keg.join("file"), b"1234").unwrap();
+
+        installer.backfill_keg_sizes().unwrap();
+
+        assert_eq!(installer.get_installed("foo").unwrap().size_bytes, Some(4));
+        assert_eq!(installer.get_installed("bar").unwrap().size_bytes, Some(99));
+    }
+}
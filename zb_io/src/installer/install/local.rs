@@ -0,0 +1,261 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use zb_core::{Error, Formula};
+
+use crate::checksum::sha256_hex;
+use crate::network::tap_formula::{TapFormulaRef, parse_tap_formula_ruby};
+
+use super::Installer;
+
+/// `{name}-{version}.{tag}.bottle[.{rebuild}].tar.gz`, as `brew bottle`
+/// names its output.
+static BOTTLE_FILE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\.([a-z0-9_]+)\.bottle(?:\.(\d+))?\.tar\.gz$")
+        .expect("BOTTLE_FILE_RE must compile")
+});
+
+/// A formula read from a file on disk instead of the API.
+#[derive(Debug, Clone)]
+pub(super) struct LocalFormula {
+    pub(super) formula: Formula,
+    /// The `.rb` file or bottle tarball it came from.
+    pub(super) path: PathBuf,
+}
+
+impl Installer {
+    /// Read the formula at `path`, a Ruby formula file parsed like a tap
+    /// formula, and install it under the file's name instead of resolving
+    /// that name from the API. Returns the name. Its bottles may be
+    /// `file://` URLs, which are imported instead of downloaded.
+    pub fn load_formula_file(&mut self, path: &Path) -> Result<String, Error> {
+        let path = absolute(path)?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .filter(|stem| !stem.is_empty())
+            .ok_or_else(|| Error::InvalidArgument {
+                message: format!("{} is not a formula file", path.display()),
+            })?;
+        let source = fs::read_to_string(&path).map_err(|e| Error::FileError {
+            message: format!("failed to read {}: {e}", path.display()),
+        })?;
+        let spec = TapFormulaRef {
+            owner: "local".to_string(),
+            repo: "local".to_string(),
+            formula: name.clone(),
+        };
+        let mut formula = parse_tap_formula_ruby(&spec, &source)?;
+        formula.ruby_source_path = Some(path.to_string_lossy().into_owned());
+        self.local_formulas
+            .insert(name.clone(), LocalFormula { formula, path });
+        Ok(name)
+    }
+
+    /// Install the bottle tarball at `path` as `name` `version`, without
+    /// any network access. The bottle tag comes from the file name when it
+    /// follows `brew bottle`'s naming, and is `all` otherwise. The formula
+    /// has no dependencies.
+    pub fn load_bottle_file(
+        &mut self,
+        path: &Path,
+        name: &str,
+        version: &str,
+    ) -> Result<String, Error> {
+        let path = absolute(path)?;
+        let sha256 = self.import_bottle_file(&path, None)?;
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (tag, rebuild) = match BOTTLE_FILE_RE.captures(&file_name) {
+            Some(captures) => (
+                captures[1].to_string(),
+                captures
+                    .get(2)
+                    .and_then(|rebuild| rebuild.as_str().parse().ok())
+                    .unwrap_or(0u32),
+            ),
+            None => ("all".to_string(), 0),
+        };
+
+        let formula: Formula = serde_json::from_value(serde_json::json!({
+            "name": name,
+            "versions": { "stable": version },
+            "dependencies": [],
+            "bottle": { "stable": {
+                "rebuild": rebuild,
+                "files": { tag: {
+                    "url": format!("file://{}", path.display()),
+                    "sha256": sha256,
+                }},
+            }},
+        }))
+        .map_err(|e| Error::InvalidArgument {
+            message: format!("invalid bottle name or version: {e}"),
+        })?;
+        self.local_formulas
+            .insert(name.to_string(), LocalFormula { formula, path });
+        Ok(name.to_string())
+    }
+
+    pub(super) fn local_formula(&self, name: &str) -> Option<&LocalFormula> {
+        self.local_formulas.get(name)
+    }
+
+    /// Copy the bottle at a `file://` `url` into the blob cache, so it is
+    /// installed from there rather than downloaded.
+    pub(super) fn import_file_url(&self, url: &str, sha256: &str) -> Result<(), Error> {
+        let Some(path) = url.strip_prefix("file://") else {
+            return Ok(());
+        };
+        if self.downloader.blob_cache().has_blob(sha256) {
+            return Ok(());
+        }
+        self.import_bottle_file(Path::new(path), Some(sha256))?;
+        Ok(())
+    }
+
+    /// Copy the bottle at `path` into the blob cache, checking it against
+    /// `expected` if given. Returns its checksum.
+    fn import_bottle_file(&self, path: &Path, expected: Option<&str>) -> Result<String, Error> {
+        let read_error = |e: io::Error| Error::FileError {
+            message: format!("failed to read {}: {e}", path.display()),
+        };
+        let mut file = File::open(path).map_err(read_error)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf).map_err(read_error)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        let sha256 = sha256_hex(hasher);
+        if let Some(expected) = expected
+            && !expected.eq_ignore_ascii_case(&sha256)
+        {
+            return Err(Error::ChecksumMismatch {
+                expected: expected.to_string(),
+                actual: sha256,
+            });
+        }
+
+        let blob_cache = self.downloader.blob_cache();
+        if !blob_cache.has_blob(&sha256) {
+            let mut writer = blob_cache
+                .start_write(&sha256)
+                .map_err(Error::store("failed to stage bottle in cache"))?;
+            let mut file = File::open(path).map_err(read_error)?;
+            io::copy(&mut file, &mut writer).map_err(read_error)?;
+            writer.commit()?;
+        }
+        Ok(sha256)
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf, Error> {
+    fs::canonicalize(path).map_err(|e| Error::FileError {
+        message: format!("failed to read {}: {e}", path.display()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::MockServer;
+
+    use super::super::test_support::{
+        create_bottle_tarball, get_test_bottle_tag, installer, sha256_hex,
+    };
+    use super::{Error, fs};
+
+    #[tokio::test]
+    async fn local_bottle_installs_without_network() {
+        let server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+        let tag = get_test_bottle_tag();
+        let bottle = tmp.path().join(format!("mytool-1.0.0.{tag}.bottle.tar.gz"));
+        fs::write(&bottle, create_bottle_tarball("mytool")).unwrap();
+
+        let name = installer
+            .load_bottle_file(&bottle, "mytool", "1.0.0")
+            .unwrap();
+        installer.install(&[name], true).await.unwrap();
+
+        assert!(tmp.path().join("prefix/bin/mytool").exists());
+        let receipt = installer.read_receipt("mytool").unwrap().unwrap();
+        assert_eq!(receipt.bottle_tag.as_deref(), Some(tag));
+        assert_eq!(receipt.source.tap, None);
+        assert_eq!(
+            receipt.source.path,
+            Some(fs::canonicalize(&bottle).unwrap().display().to_string())
+        );
+        assert!(server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn formula_file_bottles_are_read_from_disk() {
+        let server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+        let tag = get_test_bottle_tag();
+        let bottles = tmp.path().join("bottles");
+        fs::create_dir_all(&bottles).unwrap();
+        let bottle = create_bottle_tarball("mytool");
+        fs::write(
+            bottles.join(format!("mytool-1.0.0.{tag}.bottle.tar.gz")),
+            &bottle,
+        )
+        .unwrap();
+        let formula = tmp.path().join("mytool.rb");
+        fs::write(
+            &formula,
+            format!(
+                r#"class Mytool < Formula
+  desc "Internal tool"
+  version "1.0.0"
+
+  bottle do
+    root_url "file://{}"
+    sha256 {tag}: "{}"
+  end
+end
+"#,
+                bottles.display(),
+                sha256_hex(&bottle)
+            ),
+        )
+        .unwrap();
+
+        let name = installer.load_formula_file(&formula).unwrap();
+        assert_eq!(name, "mytool");
+        installer.install(&[name], true).await.unwrap();
+
+        assert!(tmp.path().join("prefix/bin/mytool").exists());
+        let receipt = installer.read_receipt("mytool").unwrap().unwrap();
+        assert!(receipt.source.path.unwrap().ends_with("mytool.rb"));
+        assert!(server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn mismatched_file_bottles_are_refused() {
+        let server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let installer = installer(tmp.path(), &server.uri());
+        let bottle = tmp.path().join("foo.tar.gz");
+        fs::write(&bottle, b"not the bottle").unwrap();
+
+        let url = format!("file://{}", bottle.display());
+        assert!(matches!(
+            installer.import_file_url(&url, &"0".repeat(64)),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use zb_core::{Error, InstallMethod};
+
+use super::{InstallPlan, Installer, PlannedInstall};
+
+/// Format version written to new lockfiles.
+pub const LOCKFILE_VERSION: u32 = 1;
+
+/// The fully resolved set of packages an install planned, so the same set
+/// can be installed again elsewhere or refused when upstream moved on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    /// The formulas that were asked for; the other packages are their
+    /// dependencies.
+    pub formulas: Vec<String>,
+    /// Sorted by name.
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// `bottle`, `source` or `binary`.
+    pub method: String,
+    pub url: String,
+    /// Digest of the bottle, source archive or binary. Only `HEAD` builds
+    /// have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl LockedPackage {
+    fn from_item(item: &PlannedInstall) -> Self {
+        let (version, url, sha256) = match &item.method {
+            InstallMethod::Bottle(bottle) => (
+                item.formula.effective_version(),
+                bottle.url.clone(),
+                Some(bottle.sha256.clone()),
+            ),
+            InstallMethod::Source(build) => (
+                build.version.clone(),
+                build.source_url.clone(),
+                build.source_checksum.clone(),
+            ),
+            InstallMethod::Binary(binary) => (
+                item.formula.effective_version(),
+                binary.url.clone(),
+                Some(binary.sha256.clone()),
+            ),
+        };
+        Self {
+            name: item.install_name.clone(),
+            version,
+            method: item.method.kind().to_string(),
+            url,
+            sha256,
+        }
+    }
+}
+
+impl Lockfile {
+    /// Pin what `plan` resolved `formulas` to.
+    pub fn from_plan(formulas: &[String], plan: &InstallPlan) -> Self {
+        let mut packages: Vec<LockedPackage> =
+            plan.items.iter().map(LockedPackage::from_item).collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            version: LOCKFILE_VERSION,
+            formulas: formulas.to_vec(),
+            packages,
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let raw = fs::read_to_string(path).map_err(|e| Error::FileError {
+            message: format!("failed to read {}: {e}", path.display()),
+        })?;
+        let lockfile: Lockfile =
+            serde_json::from_str(&raw).map_err(|e| Error::InvalidArgument {
+                message: format!("{} is not a zerobrew lockfile: {e}", path.display()),
+            })?;
+        if lockfile.version > LOCKFILE_VERSION {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "{} was written by a newer zerobrew (lockfile version {})",
+                    path.display(),
+                    lockfile.version
+                ),
+            });
+        }
+        Ok(lockfile)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self).expect("lockfile serializes");
+        fs::write(path, json + "\n").map_err(|e| Error::FileError {
+            message: format!("failed to write {}: {e}", path.display()),
+        })
+    }
+
+    /// Check that `plan` installs exactly the locked packages, at the locked
+    /// versions and digests. Bottle URLs may move; their contents may not.
+    pub fn verify(&self, plan: &InstallPlan) -> Result<(), Error> {
+        let mut locked: BTreeMap<&str, &LockedPackage> = self
+            .packages
+            .iter()
+            .map(|package| (package.name.as_str(), package))
+            .collect();
+        let mut differences = Vec::new();
+
+        for item in &plan.items {
+            let resolved = LockedPackage::from_item(item);
+            let Some(pinned) = locked.remove(resolved.name.as_str()) else {
+                differences.push(format!(
+                    "{} {} is a new dependency",
+                    resolved.name, resolved.version
+                ));
+                continue;
+            };
+            if pinned.version != resolved.version {
+                differences.push(format!(
+                    "{}: locked {}, upstream now has {}",
+                    resolved.name, pinned.version, resolved.version
+                ));
+            } else if pinned.method != resolved.method {
+                differences.push(format!(
+                    "{} {}: locked as a {}, would install a {}",
+                    resolved.name, resolved.version, pinned.method, resolved.method
+                ));
+            } else if pinned.sha256 != resolved.sha256 {
+                differences.push(format!(
+                    "{} {}: {} digest changed from {} to {}",
+                    resolved.name,
+                    resolved.version,
+                    resolved.method,
+                    pinned.sha256.as_deref().unwrap_or("none"),
+                    resolved.sha256.as_deref().unwrap_or("none")
+                ));
+            }
+        }
+        for pinned in locked.values() {
+            differences.push(format!(
+                "{} {} is no longer a dependency",
+                pinned.name, pinned.version
+            ));
+        }
+
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::LockfileMismatch { differences })
+        }
+    }
+
+    /// The locked version of `name`.
+    pub fn locked_version(&self, name: &str) -> Option<&str> {
+        self.packages
+            .iter()
+            .find(|package| package.name == name)
+            .map(|package| package.version.as_str())
+    }
+}
+
+impl Installer {
+    /// Whether every locked package is installed at its locked version.
+    pub fn satisfies_lockfile(&self, lockfile: &Lockfile) -> bool {
+        lockfile.packages.iter().all(|package| {
+            self.db
+                .get_installed(&package.name)
+                .is_some_and(|keg| keg.version == package.version)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+
+    async fn mount_formula(server: &MockServer, name: &str, version: &str, deps: &[&str]) {
+        let bottle = create_bottle_tarball(name);
+        let tag = get_test_bottle_tag();
+        let formula = serde_json::json!({
+            "name": name,
+            "versions": { "stable": version },
+            "dependencies": deps,
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}/bottles/{name}-{version}.{tag}.bottle.tar.gz", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path(format!("/formula/{name}.json")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn lockfile_pins_the_resolved_closure() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "app", "1.0.0", &["libdep"]).await;
+        mount_formula(&server, "libdep", "2.0.0", &[]).await;
+
+        let tmp = TempDir::new().unwrap();
+        let installer = installer(tmp.path(), &server.uri());
+        let formulas = vec!["app".to_string()];
+        let plan = installer.plan(&formulas).await.unwrap();
+
+        let lockfile = Lockfile::from_plan(&formulas, &plan);
+        let path = tmp.path().join("zb.lock");
+        lockfile.write(&path).unwrap();
+        let lockfile = Lockfile::read(&path).unwrap();
+        assert_eq!(lockfile.formulas, formulas);
+        assert_eq!(
+            lockfile
+                .packages
+                .iter()
+                .map(|p| (p.name.as_str(), p.version.as_str(), p.method.as_str()))
+                .collect::<Vec<_>>(),
+            [("app", "1.0.0", "bottle"), ("libdep", "2.0.0", "bottle")]
+        );
+        lockfile.verify(&plan).unwrap();
+        assert!(!installer.satisfies_lockfile(&lockfile));
+
+        let mut stale = lockfile.clone();
+        stale.packages[1].version = "1.9.0".to_string();
+        stale.packages[0].sha256 = Some("0".repeat(64));
+        stale.packages.push(LockedPackage {
+            name: "olddep".to_string(),
+            version: "0.1".to_string(),
+            method: "bottle".to_string(),
+            url: String::new(),
+            sha256: None,
+        });
+        let Err(Error::LockfileMismatch { differences }) = stale.verify(&plan) else {
+            panic!("expected the stale lockfile to be refused");
+        };
+        assert_eq!(differences.len(), 3);
+        // In install order, dependencies first.
+        assert_eq!(
+            differences[0],
+            "libdep: locked 1.9.0, upstream now has 2.0.0"
+        );
+        assert!(differences[1].starts_with("app 1.0.0: bottle digest changed from 0000"));
+        assert_eq!(differences[2], "olddep 0.1 is no longer a dependency");
+
+        fs::write(&path, r#"{"version": 99, "formulas": [], "packages": []}"#).unwrap();
+        assert!(matches!(
+            Lockfile::read(&path),
+            Err(Error::InvalidArgument { .. })
+        ));
+    }
+}
@@ -1,32 +1,111 @@
+mod audit;
+mod binary;
 mod bottle;
+pub mod cleanup;
+mod compat;
 pub mod doctor;
+mod drift;
+mod export;
+mod fetch;
+mod files;
+mod formulas;
+mod generations;
+mod history;
+mod hooks;
+mod image;
+mod link;
+mod list;
+mod local;
+mod lockfile;
+mod notes;
 mod outdated;
+mod pipeline;
 mod plan;
+mod profiles;
+mod query;
+pub mod receipt;
+mod recover;
+mod search;
+mod services;
+mod shadowing;
+mod sizes;
 mod source;
+mod stats;
+mod store;
+mod timings;
+mod transcript;
 mod uninstall;
 mod upgrade;
+mod usage;
+mod vulns;
+mod which;
 
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use tracing::warn;
 
-use crate::cellar::link::Linker;
+use crate::cellar::link::{LinkMode, Linker};
 use crate::cellar::materialize::Cellar;
+use crate::cellar::relocation::RelocationCache;
+use crate::extraction::ExtractPolicy;
 use crate::network::api::ApiClient;
 use crate::network::cache::ApiCache;
-use crate::network::download::{DownloadProgressCallback, DownloadRequest, ParallelDownloader};
-use crate::progress::{InstallProgress, ProgressCallback};
+use crate::network::download::{
+    BandwidthLimit, DownloadProgressCallback, DownloadRequest, ParallelDownloader,
+};
+use crate::network::retry::RetryPolicy;
+use crate::progress::{InstallProgress, ProgressCallback, ProgressSender, channel_callback};
 use crate::storage::blob::BlobCache;
 use crate::storage::db::Database;
+use crate::storage::layout::StorageLayout;
 use crate::storage::store::Store;
+use crate::storage::verify::StoreVerification;
+use crate::transcript::{Transcript, TranscriptEvent};
 
-use zb_core::{Error, Formula, InstallMethod};
+use zb_core::{
+    ApiEndpoints, BottleStrictness, ConcurrencyLimits, DependencyClasses, DependencyGraph, Error,
+    Formula, InstallMethod,
+};
 
 use bottle::dependency_cellar_path;
-
-const MAX_CORRUPTION_RETRIES: usize = 3;
+use formulas::DEFAULT_FORMULA_METADATA_TTL;
+use generations::DEFAULT_GENERATION_RETENTION;
+use history::OperationKind;
+use pipeline::InstallPipeline;
+
+pub use compat::CompatSync;
+pub use drift::{DriftKind, LinkDrift, RelinkSummary};
+pub use export::ExportedPackage;
+pub use files::FileOwner;
+pub use formulas::FormulaIndexRefresh;
+pub use image::{ExportedImage, ImageLayout};
+pub use link::LinkStrategy;
+pub use lockfile::{LOCKFILE_VERSION, LockedPackage, Lockfile};
+pub use notes::PackageNotes;
+pub use profiles::{DEFAULT_PROFILE, Profile};
+pub use query::BuildFlags;
+pub use search::{FormulaMatch, KegLicense};
+pub use shadowing::ShadowedCommand;
+pub use sizes::PlanSizes;
+pub use store::{StoreEntry, StoreKeg};
+pub use timings::{InstallPhase, PhaseHook, PhaseTiming};
+pub use transcript::ReplayCheck;
+pub use uninstall::{GcOptions, GcSummary};
+pub use usage::DiskUsage;
+pub use vulns::{Advisory, PackageAudit};
+pub use which::CommandProviders;
+
+/// Attempts at unpacking a bottle: a corrupt blob is quarantined and
+/// downloaded again once.
+const MAX_CORRUPTION_RETRIES: usize = 2;
+
+/// Corrupt downloads from one URL after which it is reported as
+/// persistently bad.
+pub(crate) const REPEATED_CORRUPTION_THRESHOLD: u32 = 2;
 
 /// Acquire the cross-process install lock. The returned `File` must be kept
 /// alive (e.g. `let _lock = ...`) for the duration the lock should be held —
@@ -52,6 +131,37 @@ pub struct Installer {
     pub(crate) db: Database,
     prefix: PathBuf,
     locks_dir: PathBuf,
+    max_cache_size: Option<u64>,
+    bottle_tag: Option<String>,
+    /// Named bottle variant installed for requested formulas.
+    bottle_variant: Option<String>,
+    bottle_strictness: BottleStrictness,
+    /// Foreign CPU architecture to install for; `None` installs natively.
+    arch: Option<&'static str>,
+    /// Previous kegs kept per formula after an upgrade.
+    keep_generations: usize,
+    generation_retention: Duration,
+    link_strategy: LinkStrategy,
+    /// How executables are linked, for formulas without a mode of their own.
+    link_mode: LinkMode,
+    /// Build requested formulas from the latest commit of their `head` repo.
+    head: bool,
+    /// Dependency classes plans follow besides the runtime ones.
+    dependency_classes: DependencyClasses,
+    /// How old stored formula metadata may be and still resolve
+    /// dependencies without an API request.
+    formula_metadata_ttl: Duration,
+    /// Audit relocated kegs and fail installs that leave dangling library
+    /// references.
+    paranoid: bool,
+    /// Record install timings in the database for `zb stats`.
+    record_stats: bool,
+    phase_hook: Option<PhaseHook>,
+    /// Formulas loaded from `.rb` files or bottle tarballs, by name.
+    local_formulas: HashMap<String, local::LocalFormula>,
+    transcript: Option<Arc<Transcript>>,
+    /// Directory of executables run at each `HookPoint`.
+    hooks_dir: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -59,11 +169,34 @@ pub struct PlannedInstall {
     pub install_name: String,
     pub formula: Formula,
     pub method: InstallMethod,
+    /// Whether the user asked for this formula, as opposed to it being pulled
+    /// in as a dependency.
+    pub installed_on_request: bool,
+    /// Store key of a previous version of this formula whose unchanged files
+    /// the new store entry may share. Set by `upgrade`.
+    pub delta_base: Option<String>,
+    /// Installed formulas this one declares `conflicts_with`. Linking it
+    /// would overwrite their files, so `execute` refuses unless linking is
+    /// off.
+    pub conflicts: Vec<String>,
+    /// Whether the bottle is already in the download cache or store, so
+    /// executing the plan will not download it.
+    pub cached: bool,
+    /// Size of the bottle archive in bytes, when known.
+    pub download_size: Option<u64>,
+    /// The named bottle variant the formula was planned with, recorded in
+    /// its receipt.
+    pub bottle_variant: Option<String>,
+    /// Expected disk usage of the keg: exact once the bottle is in the
+    /// store, otherwise estimated from `download_size`.
+    pub install_size: Option<u64>,
 }
 
 #[derive(Debug)]
 pub struct InstallPlan {
     pub items: Vec<PlannedInstall>,
+    /// Why each item is part of the plan, for `install --explain`.
+    pub graph: DependencyGraph,
 }
 
 #[derive(Debug)]
@@ -111,9 +244,102 @@ impl Installer {
             db,
             prefix,
             locks_dir,
+            max_cache_size: None,
+            bottle_tag: None,
+            bottle_variant: None,
+            bottle_strictness: BottleStrictness::default(),
+            arch: None,
+            keep_generations: 1,
+            generation_retention: DEFAULT_GENERATION_RETENTION,
+            link_strategy: LinkStrategy::default(),
+            link_mode: LinkMode::default(),
+            head: false,
+            dependency_classes: DependencyClasses::default(),
+            formula_metadata_ttl: DEFAULT_FORMULA_METADATA_TTL,
+            paranoid: false,
+            record_stats: false,
+            phase_hook: None,
+            local_formulas: HashMap::new(),
+            transcript: None,
+            hooks_dir: None,
         }
     }
 
+    /// How many bottles unpack while downloading, and how many threads
+    /// relocate and link each keg. The download limit is fixed when the
+    /// installer is created.
+    pub fn with_concurrency_limits(mut self, limits: &ConcurrencyLimits) -> Self {
+        self.store = self.store.with_unpack_workers(limits.unpack);
+        self.cellar = self.cellar.with_relocation_threads(limits.relocate);
+        self.linker = self.linker.with_threads(limits.link);
+        self
+    }
+
+    /// Override the archive limits used when unpacking bottles into the store.
+    pub fn with_extract_policy(mut self, policy: ExtractPolicy) -> Self {
+        self.store = self.store.with_extract_policy(policy);
+        self
+    }
+
+    /// Check store entries against the manifest recorded when they were
+    /// unpacked before materializing them.
+    pub fn with_store_verification(mut self, verification: StoreVerification) -> Self {
+        self.store = self.store.with_verification(verification);
+        self
+    }
+
+    /// Retry policy for both API requests and bottle downloads.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.api_client = self.api_client.with_retry_policy(policy.clone());
+        self.downloader = self.downloader.with_retry_policy(policy);
+        self
+    }
+
+    /// Cap how fast bottles download, per download and across all of them.
+    pub fn with_bandwidth_limit(mut self, bandwidth: BandwidthLimit) -> Self {
+        self.downloader = self.downloader.with_bandwidth_limit(bandwidth);
+        self
+    }
+
+    /// Fetch formulas, casks and formula sources from the mirrors in
+    /// `endpoints`. Fails on a mirror URL that is not plain http(s).
+    pub fn with_api_endpoints(mut self, endpoints: &ApiEndpoints) -> Result<Self, Error> {
+        self.api_client = self.api_client.with_endpoints(endpoints)?;
+        Ok(self)
+    }
+
+    /// Plan bottles for `tag` (e.g. `arm64_sonoma`) instead of the host
+    /// platform. Planning fails for formulas without a matching bottle, and
+    /// the resulting plans can only be fetched, not installed.
+    pub fn with_bottle_tag(mut self, tag: impl Into<String>) -> Self {
+        self.bottle_tag = Some(tag.into());
+        self
+    }
+
+    /// Install the alternative bottles a tap publishes under `variant`
+    /// (e.g. `no-gui`) for the requested formulas. Dependencies keep their
+    /// default bottles, and upgrades stay on the variant.
+    pub fn with_bottle_variant(mut self, variant: impl Into<String>) -> Self {
+        self.bottle_variant = Some(variant.into());
+        self
+    }
+
+    /// Whether host bottle selection may fall back to bottles built for
+    /// older OS releases.
+    pub fn with_bottle_strictness(mut self, strictness: BottleStrictness) -> Self {
+        self.bottle_strictness = strictness;
+        self
+    }
+
+    /// Install bottles built for `arch` (a Rust target arch such as
+    /// `x86_64`) instead of the host's, e.g. Intel bottles under Rosetta 2.
+    /// Their kegs live in a separate, arch-scoped cellar. The host arch is
+    /// a no-op.
+    pub fn with_arch(mut self, arch: &'static str) -> Self {
+        self.arch = (arch != std::env::consts::ARCH).then_some(arch);
+        self
+    }
+
     pub fn clear_api_cache(&self) -> Result<usize, Error> {
         self.api_client.clear_cache()
     }
@@ -129,7 +355,27 @@ impl Installer {
         progress: Option<Arc<ProgressCallback>>,
     ) -> Result<ExecuteResult, Error> {
         let _lock = acquire_install_lock(&self.locks_dir)?;
-        self.execute_inner(plan, link, progress).await
+        self.recover_interrupted()?;
+        let before = self.installed_versions();
+        let result = self.execute_inner(plan, link, progress).await;
+        self.record_operation(OperationKind::Install, before, &result, None);
+        let result = result?;
+        self.enforce_cache_limit();
+        Ok(result)
+    }
+
+    /// Like `execute_with_progress`, but delivers events over a channel.
+    ///
+    /// The sender is dropped when execution finishes, so a consumer running
+    /// on another task can drain its receiver until it returns `None`.
+    pub async fn execute_streaming(
+        &mut self,
+        plan: InstallPlan,
+        link: bool,
+        events: ProgressSender,
+    ) -> Result<ExecuteResult, Error> {
+        self.execute_with_progress(plan, link, Some(channel_callback(events)))
+            .await
     }
 
     /// No-lock variant of `execute_with_progress`. Callers MUST already hold
@@ -141,18 +387,57 @@ impl Installer {
         link: bool,
         progress: Option<Arc<ProgressCallback>>,
     ) -> Result<ExecuteResult, Error> {
+        let mut installed = Vec::new();
+        let result = self
+            .execute_items(plan, link, progress, &mut installed)
+            .await;
+        self.run_post_execute_hook(&installed, result.is_ok());
+        result
+    }
+
+    /// Install the items of `plan`, adding the name of each installed
+    /// formula to `installed`.
+    async fn execute_items(
+        &mut self,
+        plan: InstallPlan,
+        link: bool,
+        progress: Option<Arc<ProgressCallback>>,
+        installed_names: &mut Vec<String>,
+    ) -> Result<ExecuteResult, Error> {
+        if let Some(tag) = &self.bottle_tag {
+            return Err(Error::InvalidArgument {
+                message: format!("bottles planned for '{tag}' can only be fetched, not installed"),
+            });
+        }
+
+        if link
+            && let Some((item, conflicting)) = plan
+                .items
+                .iter()
+                .find_map(|item| item.conflicts.first().map(|c| (item, c)))
+        {
+            return Err(Error::FormulaConflict {
+                name: item.formula.name.clone(),
+                conflicting: conflicting.clone(),
+                reason: item
+                    .formula
+                    .conflict_reason(conflicting)
+                    .map(str::to_string),
+            });
+        }
+
         let report = |event: InstallProgress| {
             if let Some(ref cb) = progress {
                 cb(event);
             }
         };
 
-        let (bottle_items, source_items): (Vec<_>, Vec<_>) = plan
+        let (bottle_items, other_items): (Vec<_>, Vec<_>) = plan
             .items
             .into_iter()
             .partition(|item| matches!(item.method, InstallMethod::Bottle(_)));
 
-        if bottle_items.is_empty() && source_items.is_empty() {
+        if bottle_items.is_empty() && other_items.is_empty() {
             return Ok(ExecuteResult { installed: 0 });
         }
 
@@ -160,6 +445,16 @@ impl Installer {
         let mut error: Option<Error> = None;
 
         if !bottle_items.is_empty() {
+            for item in &bottle_items {
+                if let InstallMethod::Bottle(ref bottle) = item.method {
+                    self.record_event(TranscriptEvent::Bottle {
+                        formula: item.install_name.clone(),
+                        url: bottle.url.clone(),
+                        sha256: bottle.sha256.clone(),
+                    });
+                    self.import_file_url(&bottle.url, &bottle.sha256)?;
+                }
+            }
             let requests: Vec<DownloadRequest> = bottle_items
                 .iter()
                 .map(|item| {
@@ -180,48 +475,146 @@ impl Installer {
                 }) as DownloadProgressCallback
             });
 
-            let mut rx = self
-                .downloader
-                .download_streaming(requests, download_progress.clone());
-
-            while let Some(result) = rx.recv().await {
-                match result {
-                    Ok(download) => {
-                        match self
-                            .process_bottle_item(
-                                &bottle_items[download.index],
-                                &download,
+            // Requests go out in plan order, dependencies first, so the
+            // kegs everything else waits on are downloaded first too.
+            let mut rx = self.downloader.download_streaming_into(
+                requests,
+                download_progress.clone(),
+                &self.store,
+            );
+
+            let names: Vec<&str> = bottle_items
+                .iter()
+                .map(|item| item.install_name.as_str())
+                .collect();
+            let mut pipeline = InstallPipeline::new(&names, &plan.graph);
+            let mut downloads = BTreeMap::new();
+            // Refusing conflicts means checking every keg's links before
+            // linking any. Kegs still materialize as they become ready, and
+            // wait here, unrecorded and unlinked, for the last download.
+            let link_when_ready = !link || self.link_strategy != LinkStrategy::Refuse;
+            let mut unlinked = Vec::new();
+            loop {
+                let finished = match rx.recv().await {
+                    Some(Ok(download)) => {
+                        pipeline.downloaded(download.index);
+                        downloads.insert(download.index, download);
+                        false
+                    }
+                    Some(Err(e)) => {
+                        error = Some(e);
+                        false
+                    }
+                    None => true,
+                };
+
+                while let Some(index) = pipeline.next_ready() {
+                    let download = &downloads[&index];
+                    let item = &bottle_items[index];
+                    let result = match self.run_pre_install_hook(item) {
+                        Ok(()) => {
+                            self.materialize_bottle_item(
+                                item,
+                                download,
                                 &download_progress,
-                                link,
                                 &report,
                             )
                             .await
-                        {
-                            Ok(()) => installed += 1,
-                            Err(e) => error = Some(e),
                         }
+                        Err(e) => Err(e),
+                    };
+                    let result = match result {
+                        Ok(materialized) if !link_when_ready => {
+                            unlinked.push((index, materialized));
+                            Ok(())
+                        }
+                        Ok(materialized) => {
+                            self.finish_bottle_item(item, download, materialized, link, &report)
+                        }
+                        Err(e) => Err(e),
+                    };
+                    pipeline.finished(index, result.is_ok());
+                    match result {
+                        Ok(()) if link_when_ready => {
+                            self.record_installed(item);
+                            self.run_post_install_hook(item);
+                            installed_names.push(item.install_name.clone());
+                            installed += 1;
+                        }
+                        Ok(()) => {}
+                        Err(e) => error = Some(e),
                     }
-                    Err(e) => {
-                        error = Some(e);
+                }
+                if finished {
+                    break;
+                }
+            }
+
+            if !link_when_ready {
+                let kegs: Vec<_> = downloads
+                    .values()
+                    .map(|download| (&bottle_items[download.index], download))
+                    .collect();
+                if let Err(e) = self.check_plan_links(&kegs) {
+                    for (index, materialized) in unlinked {
+                        materialized.discard(self, &bottle_items[index]);
+                    }
+                    return Err(e);
+                }
+                for (index, materialized) in unlinked {
+                    let item = &bottle_items[index];
+                    match self.finish_bottle_item(
+                        item,
+                        &downloads[&index],
+                        materialized,
+                        link,
+                        &report,
+                    ) {
+                        Ok(()) => {
+                            self.record_installed(item);
+                            self.run_post_install_hook(item);
+                            installed_names.push(item.install_name.clone());
+                            installed += 1;
+                        }
+                        Err(e) => error = Some(e),
                     }
                 }
             }
-        }
 
-        for item in &source_items {
-            let InstallMethod::Source(ref build_plan) = item.method else {
-                unreachable!()
-            };
+            for index in pipeline.held_back() {
+                warn!(
+                    formula = %bottle_items[index].install_name,
+                    "not installed because a dependency failed to install"
+                );
+            }
+        }
 
+        for item in &other_items {
+            if let Err(e) = self.run_pre_install_hook(item) {
+                error = Some(e);
+                continue;
+            }
             report(InstallProgress::UnpackStarted {
                 name: item.formula.name.clone(),
             });
 
-            match self
-                .install_from_source(item, build_plan, link, &report)
-                .await
-            {
-                Ok(()) => installed += 1,
+            let result = match item.method {
+                InstallMethod::Source(ref build_plan) => {
+                    self.install_from_source(item, build_plan, link, &report)
+                        .await
+                }
+                InstallMethod::Binary(ref binary) => {
+                    self.install_binary(item, binary, link, &report).await
+                }
+                InstallMethod::Bottle(_) => unreachable!(),
+            };
+            match result {
+                Ok(()) => {
+                    self.record_installed(item);
+                    self.run_post_install_hook(item);
+                    installed_names.push(item.install_name.clone());
+                    installed += 1;
+                }
                 Err(e) => {
                     error = Some(e);
                     continue;
@@ -229,6 +622,9 @@ impl Installer {
             }
         }
 
+        if installed > 0 {
+            self.sync_compat_roots();
+        }
         if let Some(e) = error {
             return Err(e);
         }
@@ -261,15 +657,21 @@ impl Installer {
         names: &[String],
         link: bool,
     ) -> Result<ExecuteResult, Error> {
+        let before = self.installed_versions();
         let mut installed = 0usize;
+        let mut result = Ok(());
         for name in names {
             let token = name
                 .strip_prefix("cask:")
                 .expect("install_casks expects cask: prefixed names");
-            self.install_single_cask(token, link).await?;
+            result = self.install_single_cask(token, link).await;
+            if result.is_err() {
+                break;
+            }
             installed += 1;
         }
-        Ok(ExecuteResult { installed })
+        self.record_operation(OperationKind::Install, before, &result, None);
+        result.map(|()| ExecuteResult { installed })
     }
 
     pub fn is_installed(&self, name: &str) -> bool {
@@ -285,7 +687,21 @@ impl Installer {
     }
 
     pub fn keg_path(&self, name: &str, version: &str) -> PathBuf {
-        self.cellar.keg_path(name, version)
+        self.installed_cellar(name).keg_path(name, version)
+    }
+
+    /// The cellar kegs for `arch` live in; `None` is the native cellar.
+    pub(crate) fn cellar_for(&self, arch: Option<&str>) -> Cellar {
+        match arch {
+            Some(arch) => self.cellar.for_arch(arch),
+            None => self.cellar.clone(),
+        }
+    }
+
+    /// The cellar holding the installed keg of `name`.
+    pub(crate) fn installed_cellar(&self, name: &str) -> Cellar {
+        let arch = self.db.get_installed(name).and_then(|keg| keg.arch);
+        self.cellar_for(arch.as_deref())
     }
 
     fn cleanup_materialized(cellar: &Cellar, name: &str, version: &str) {
@@ -305,6 +721,17 @@ pub fn create_installer(
     prefix: &Path,
     concurrency: usize,
 ) -> Result<Installer, Error> {
+    create_installer_with_layout(&StorageLayout::new(root, prefix), concurrency)
+}
+
+/// Like [`create_installer`], with the cache, store, cellar and database
+/// wherever `layout` puts them.
+pub fn create_installer_with_layout(
+    layout: &StorageLayout,
+    concurrency: usize,
+) -> Result<Installer, Error> {
+    let root = &layout.root;
+    let prefix = &layout.prefix;
     if !root.exists() {
         fs::create_dir_all(root).map_err(|e| {
             if e.kind() == std::io::ErrorKind::PermissionDenied {
@@ -325,12 +752,13 @@ pub fn create_installer(
         })?;
     }
 
-    fs::create_dir_all(root.join("db")).map_err(Error::store("failed to create db directory"))?;
+    if let Some(db_dir) = layout.db.parent() {
+        fs::create_dir_all(db_dir).map_err(Error::store("failed to create db directory"))?;
+    }
 
-    fs::create_dir_all(root.join("cache"))
-        .map_err(Error::store("failed to create cache directory"))?;
+    fs::create_dir_all(&layout.cache).map_err(Error::store("failed to create cache directory"))?;
 
-    let api_cache_path = root.join("cache/api-cache.sqlite");
+    let api_cache_path = layout.cache.join("api-cache.sqlite");
     let api_cache =
         ApiCache::open(&api_cache_path).map_err(Error::store("failed to open API cache"))?;
 
@@ -340,16 +768,20 @@ pub fn create_installer(
     }
     .with_cache(api_cache);
 
+    let locks_dir = root.join("locks");
     let blob_cache =
-        BlobCache::new(&root.join("cache")).map_err(Error::store("failed to create blob cache"))?;
-    let store = Store::new(root).map_err(Error::store("failed to create store"))?;
+        BlobCache::new(&layout.cache).map_err(Error::store("failed to create blob cache"))?;
+    let store = Store::new_at(layout.store.clone(), locks_dir.clone())
+        .map_err(Error::store("failed to create store"))?;
+    layout.link_cellar()?;
     // Use prefix/Cellar so bottles' hardcoded rpaths work
-    let cellar =
-        Cellar::new_at(prefix.join("Cellar")).map_err(Error::store("failed to create cellar"))?;
+    let cellar = Cellar::new_at(layout.prefix_cellar())
+        .map_err(Error::store("failed to create cellar"))?
+        .with_prefix(prefix.to_path_buf())
+        .with_relocation_cache(RelocationCache::new(layout.cache.join("relocations")));
     let linker = Linker::new(prefix).map_err(Error::store("failed to create linker"))?;
-    let db = Database::open(&root.join("db/zb.sqlite3"))?;
+    let db = Database::open(&layout.db)?;
 
-    let locks_dir = root.join("locks");
     fs::create_dir_all(&locks_dir).map_err(Error::store("failed to create locks directory"))?;
 
     let parallel_downloader = ParallelDownloader::with_concurrency(blob_cache, concurrency);
@@ -363,11 +795,61 @@ pub fn create_installer(
         db,
         prefix: prefix.to_path_buf(),
         locks_dir,
+        max_cache_size: None,
+        bottle_tag: None,
+        bottle_variant: None,
+        bottle_strictness: BottleStrictness::default(),
+        arch: None,
+        keep_generations: 1,
+        generation_retention: DEFAULT_GENERATION_RETENTION,
+        link_strategy: LinkStrategy::default(),
+        link_mode: LinkMode::default(),
+        head: false,
+        dependency_classes: DependencyClasses::default(),
+        formula_metadata_ttl: DEFAULT_FORMULA_METADATA_TTL,
+        paranoid: false,
+        record_stats: false,
+        phase_hook: None,
+        local_formulas: HashMap::new(),
+        transcript: None,
+        hooks_dir: None,
     })
 }
 
 #[cfg(test)]
 mod test_support {
+    use std::path::Path;
+
+    use crate::cellar::Cellar;
+    use crate::cellar::link::Linker;
+    use crate::network::api::ApiClient;
+    use crate::storage::blob::BlobCache;
+    use crate::storage::db::Database;
+    use crate::storage::store::Store;
+
+    use super::Installer;
+
+    /// An API base nothing listens on, for tests that never fetch formulas.
+    pub const NO_API: &str = "http://127.0.0.1:1";
+
+    /// An installer rooted at `tmp/zerobrew` with its prefix at
+    /// `tmp/prefix`, resolving formulas from `{base_url}/formula`.
+    pub fn installer(tmp: &Path, base_url: &str) -> Installer {
+        let root = tmp.join("zerobrew");
+        let prefix = tmp.join("prefix");
+        std::fs::create_dir_all(root.join("db")).unwrap();
+        Installer::new(
+            ApiClient::with_base_url(format!("{base_url}/formula")).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix,
+            root.join("locks"),
+        )
+    }
+
     pub fn create_bottle_tarball(formula_name: &str) -> Vec<u8> {
         create_bottle_tarball_with_version(formula_name, "1.0.0")
     }
@@ -407,7 +889,9 @@ mod test_support {
     }
 
     pub fn get_test_bottle_tag() -> &'static str {
-        if cfg!(target_os = "linux") {
+        if cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+            "arm64_linux"
+        } else if cfg!(target_os = "linux") {
             "x86_64_linux"
         } else if cfg!(target_arch = "x86_64") {
             "sonoma"
@@ -428,12 +912,9 @@ mod tests {
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
-    use crate::cellar::Cellar;
     use crate::network::api::ApiClient;
     use crate::storage::blob::BlobCache;
-    use crate::storage::db::Database;
-    use crate::storage::store::Store;
-    use crate::{Installer, Linker};
+    use crate::{InstallProgress, ProgressCallback};
 
     use super::test_support::*;
 
@@ -484,27 +965,9 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
+        let prefix = tmp.path().join("prefix");
 
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["testpkg".to_string()], true)
@@ -519,6 +982,81 @@ mod tests {
         assert_eq!(installed.unwrap().version, "1.0.0");
     }
 
+    #[tokio::test]
+    async fn execute_streaming_delivers_events_and_closes_channel() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("streampkg");
+        let bottle_sha = sha256_hex(&bottle);
+
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "streampkg",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{}": {{
+                                "url": "{}/bottles/streampkg-1.0.0.{}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            tag,
+            mock_server.uri(),
+            tag,
+            bottle_sha
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/streampkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/streampkg-1.0.0.{}.bottle.tar.gz",
+                tag
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+
+        let (events, mut receiver) = crate::progress::progress_channel();
+        let collector = tokio::spawn(async move {
+            let mut seen = Vec::new();
+            while let Some(event) = receiver.recv().await {
+                seen.push(event);
+            }
+            seen
+        });
+
+        let plan = installer.plan(&["streampkg".to_string()]).await.unwrap();
+        let result = installer
+            .execute_streaming(plan, true, events)
+            .await
+            .unwrap();
+        assert_eq!(result.installed, 1);
+
+        let seen = collector.await.unwrap();
+        assert!(matches!(
+            seen.first(),
+            Some(crate::progress::InstallProgress::DownloadStarted { .. })
+        ));
+        assert!(matches!(
+            seen.last(),
+            Some(crate::progress::InstallProgress::InstallCompleted { name }) if name == "streampkg"
+        ));
+    }
+
     #[tokio::test]
     async fn install_with_dependencies() {
         let mock_server = MockServer::start().await;
@@ -569,28 +1107,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["mainpkg".to_string()], true)
@@ -683,27 +1200,8 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
 
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         let result = installer
             .install(&["goodpkg".to_string(), "badpkg".to_string()], false)
@@ -760,29 +1258,10 @@ mod tests {
             .mount(&mock_server)
             .await;
 
+        let db_path = tmp.path().join("zerobrew/db/zb.sqlite3");
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let db_path = root.join("db/zb.sqlite3");
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&db_path).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         let conn = rusqlite::Connection::open(&db_path).unwrap();
         conn.execute("DROP TABLE installed_kegs", []).unwrap();
@@ -833,30 +1312,13 @@ end
             .mount(&mock_server)
             .await;
 
+        let db_path = tmp.path().join("zerobrew/db/zb.sqlite3");
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let db_path = root.join("db/zb.sqlite3");
-        let api_client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+        installer.api_client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
             .unwrap()
             .with_tap_raw_base_url(mock_server.uri());
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&db_path).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
 
         let conn = rusqlite::Connection::open(&db_path).unwrap();
         conn.execute("DROP TABLE installed_kegs", []).unwrap();
@@ -947,28 +1409,7 @@ end
                 .await;
         }
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["root".to_string()], true)
@@ -1032,33 +1473,34 @@ end
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
-
+        let prefix = tmp.path().join("prefix");
+
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let progress: Arc<ProgressCallback> = Arc::new(Box::new(move |event| {
+            let event = match event {
+                InstallProgress::DownloadCompleted { name, .. } => format!("downloaded {name}"),
+                InstallProgress::UnpackCompleted { name } => format!("unpacked {name}"),
+                InstallProgress::LinkStarted { name } => format!("linking {name}"),
+                _ => return,
+            };
+            recorded.lock().unwrap().push(event);
+        }));
+        let plan = installer.plan(&["slowpkg".to_string()]).await.unwrap();
         installer
-            .install(&["slowpkg".to_string()], true)
+            .execute_with_progress(plan, true, Some(progress))
             .await
             .unwrap();
 
+        // Unpacking goes ahead of the slow download; linking waits for the
+        // plan-wide conflict check after it.
+        let events = events.lock().unwrap();
+        let at = |event: &str| events.iter().position(|e| e == event).unwrap();
+        assert!(at("unpacked fastpkg") < at("downloaded slowpkg"));
+        assert!(at("downloaded slowpkg") < at("linking fastpkg"));
+
         assert!(installer.db.get_installed("fastpkg").is_some());
         assert!(installer.db.get_installed("slowpkg").is_some());
         assert!(root.join("cellar/fastpkg/1.0.0").exists());
@@ -1121,27 +1563,9 @@ end
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
+        let prefix = tmp.path().join("prefix");
 
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["retrypkg".to_string()], true)
@@ -1153,9 +1577,163 @@ end
         assert!(prefix.join("bin/retrypkg").exists());
     }
 
+    #[tokio::test]
+    async fn quarantines_corrupt_cached_blob_and_refetches() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("badpkg");
+        let bottle_sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+        let bottle_url = format!(
+            "{}/bottles/badpkg-1.0.0.{}.bottle.tar.gz",
+            mock_server.uri(),
+            tag
+        );
+        let formula_json = format!(
+            r#"{{
+                "name": "badpkg",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{ "url": "{bottle_url}", "sha256": "{bottle_sha}" }}
+                        }}
+                    }}
+                }}
+            }}"#
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/badpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/badpkg-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+
+        // A blob cached under the right checksum that is not a tarball.
+        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
+        let mut writer = blob_cache.start_write(&bottle_sha).unwrap();
+        std::io::Write::write_all(&mut writer, b"not a tarball").unwrap();
+        writer.commit().unwrap();
+
+        installer
+            .install(&["badpkg".to_string()], true)
+            .await
+            .unwrap();
+
+        assert!(installer.is_installed("badpkg"));
+        let quarantined = blob_cache.list_quarantined().unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(fs::read(&quarantined[0].0).unwrap(), b"not a tarball");
+
+        let corrupt = installer.db.list_corrupt_downloads().unwrap();
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(corrupt[0].url, bottle_url);
+        assert_eq!(corrupt[0].failures, 1);
+
+        // One bad download is noise; doctor only flags the quarantine.
+        let report = installer.doctor().unwrap();
+        assert_eq!(report.quarantined_blobs.len(), 1);
+        assert!(report.corrupt_downloads.is_empty());
+        let summary = installer.repair(&report).unwrap();
+        assert_eq!(summary.removed_quarantined_blobs, 1);
+        assert!(blob_cache.list_quarantined().unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn fails_after_max_retries() {
         // Validates the retry mechanism structure -- proper integration test
         // would need injection of corruption between download and extraction.
     }
+
+    #[tokio::test]
+    async fn foreign_arch_install_uses_scoped_cellar() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // A bottle for the arch the tests do not run on.
+        let (arch, tag) = if cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+            ("x86_64", "x86_64_linux")
+        } else if cfg!(target_os = "linux") {
+            ("aarch64", "arm64_linux")
+        } else if cfg!(target_arch = "x86_64") {
+            ("aarch64", "arm64_sonoma")
+        } else {
+            ("x86_64", "sonoma")
+        };
+        let bottle = create_bottle_tarball("rosettapkg");
+        let formula_json = format!(
+            r#"{{
+                "name": "rosettapkg",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{
+                                "url": "{}/bottles/rosettapkg-1.0.0.{tag}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+            sha256_hex(&bottle),
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/rosettapkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/rosettapkg-1.0.0.{tag}.bottle.tar.gz"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("prefix");
+
+        let installer = installer(tmp.path(), &mock_server.uri());
+
+        assert!(installer.plan(&["rosettapkg".to_string()]).await.is_err());
+
+        let mut installer = installer.with_arch(arch);
+        installer
+            .install(&["rosettapkg".to_string()], true)
+            .await
+            .unwrap();
+
+        let keg_path = root.join(format!("cellar-{arch}/rosettapkg/1.0.0"));
+        assert!(keg_path.exists());
+        assert!(!root.join("cellar/rosettapkg").exists());
+        assert!(prefix.join("bin/rosettapkg").exists());
+        assert_eq!(installer.keg_path("rosettapkg", "1.0.0"), keg_path);
+        assert_eq!(
+            installer
+                .get_installed("rosettapkg")
+                .unwrap()
+                .arch
+                .as_deref(),
+            Some(arch)
+        );
+        assert!(installer.read_receipt("rosettapkg").unwrap().is_some());
+
+        installer.uninstall("rosettapkg").unwrap();
+        assert!(!keg_path.exists());
+        assert!(!prefix.join("bin/rosettapkg").exists());
+    }
 }
@@ -0,0 +1,133 @@
+use serde::Serialize;
+use zb_core::Error;
+
+use crate::storage::db::InstalledKeg;
+
+use super::Installer;
+
+/// What was recorded about why a package is installed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PackageNotes {
+    pub note: Option<String>,
+    /// Sorted.
+    pub tags: Vec<String>,
+}
+
+impl Installer {
+    /// The note and tags of the installed package `name`.
+    pub fn package_notes(&self, name: &str) -> Result<PackageNotes, Error> {
+        self.require_installed(name)?;
+        Ok(PackageNotes {
+            note: self.db.package_note(name)?,
+            tags: self.db.package_tags(name)?,
+        })
+    }
+
+    /// Replace the note of the installed package `name`; `None` or a blank
+    /// note clears it. Notes are dropped when the package is uninstalled.
+    pub fn set_package_note(&mut self, name: &str, note: Option<&str>) -> Result<(), Error> {
+        self.require_installed(name)?;
+        let note = note.map(str::trim).filter(|n| !n.is_empty());
+        self.db.set_package_note(name, note)
+    }
+
+    /// Tag the installed package `name` with `add` and drop `remove` from
+    /// its tags. Tags are single words, so `zb list --tag` can match them.
+    pub fn tag_package(
+        &mut self,
+        name: &str,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<(), Error> {
+        self.require_installed(name)?;
+        for tag in add {
+            validate_tag(tag)?;
+        }
+        self.db.update_package_tags(name, add, remove)
+    }
+
+    /// Installed kegs tagged `tag`, sorted by name.
+    pub fn installed_with_tag(&self, tag: &str) -> Result<Vec<InstalledKeg>, Error> {
+        Ok(self
+            .db
+            .packages_tagged(tag)?
+            .iter()
+            .filter_map(|name| self.db.get_installed(name))
+            .collect())
+    }
+}
+
+fn validate_tag(tag: &str) -> Result<(), Error> {
+    if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+        return Err(Error::InvalidArgument {
+            message: format!("invalid tag '{tag}': tags are single words without commas"),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::installer::install::test_support::{NO_API, installer};
+
+    #[test]
+    fn notes_and_tags_follow_the_installed_package() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), NO_API);
+        let work = vec!["work".to_string()];
+        assert!(matches!(
+            installer.set_package_note("jq", Some("for X")),
+            Err(Error::NotInstalled { .. })
+        ));
+
+        for name in ["jq", "wget"] {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install(name, "1.0.0", &format!("{name}-key"))
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        installer
+            .set_package_note("jq", Some("  needed for project X "))
+            .unwrap();
+        installer
+            .tag_package("jq", &["work".to_string(), "ci".to_string()], &[])
+            .unwrap();
+        installer.tag_package("wget", &work, &[]).unwrap();
+        assert!(matches!(
+            installer.tag_package("jq", &["two words".to_string()], &[]),
+            Err(Error::InvalidArgument { .. })
+        ));
+
+        assert_eq!(
+            installer.package_notes("jq").unwrap(),
+            PackageNotes {
+                note: Some("needed for project X".to_string()),
+                tags: vec!["ci".to_string(), "work".to_string()],
+            }
+        );
+        let tagged = |installer: &Installer| -> Vec<String> {
+            installer
+                .installed_with_tag("work")
+                .unwrap()
+                .into_iter()
+                .map(|k| k.name)
+                .collect()
+        };
+        assert_eq!(tagged(&installer), ["jq", "wget"]);
+
+        installer.tag_package("wget", &[], &work).unwrap();
+        installer.uninstall("jq").unwrap();
+        assert!(tagged(&installer).is_empty());
+
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install("jq", "1.0.0", "jq-key").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(
+            installer.package_notes("jq").unwrap(),
+            PackageNotes::default()
+        );
+    }
+}
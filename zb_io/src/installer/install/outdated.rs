@@ -1,19 +1,70 @@
 use std::collections::HashMap;
 
-use zb_core::{Error, select_bottle};
+use tracing::warn;
+use zb_core::{Error, Formula, HeadSource, SelectedBottle, select_bottle_for_arch};
 
+use super::formulas::formula_records;
 use super::{Installer, OutdatedPackage};
+use crate::build::source::resolve_head_revision;
+use crate::storage::db::InstalledKeg;
 
 impl Installer {
+    /// The bottle `keg` would upgrade to, for the arch and bottle variant it
+    /// was installed for.
+    fn keg_bottle(&self, formula: &Formula, keg: &InstalledKeg) -> Result<SelectedBottle, Error> {
+        let variant_formula;
+        let formula = match self.installed_bottle_variant(&keg.name) {
+            Some(variant) => {
+                variant_formula = formula.clone().with_bottle_variant(&variant)?;
+                &variant_formula
+            }
+            None => formula,
+        };
+        match keg.arch.as_deref() {
+            Some(arch) => select_bottle_for_arch(formula, arch, self.bottle_strictness),
+            None => self.host_bottle(formula),
+        }
+    }
+
+    /// Compare a `HEAD-<sha>` keg against the commit its head branch points
+    /// at now.
+    async fn head_outdated(
+        &self,
+        keg: &InstalledKeg,
+        formula: &Formula,
+        installed_revision: &str,
+    ) -> Result<Option<OutdatedPackage>, Error> {
+        let head = formula
+            .head_source()
+            .ok_or_else(|| Error::UnsupportedFormula {
+                name: keg.name.clone(),
+                reason: "formula no longer has a head spec".to_string(),
+            })?;
+        let latest = resolve_head_revision(&head.url, head.branch.as_deref()).await?;
+        if latest.starts_with(installed_revision) {
+            return Ok(None);
+        }
+        Ok(Some(OutdatedPackage {
+            name: keg.name.clone(),
+            installed_version: keg.version.clone(),
+            installed_sha256: keg.store_key.clone(),
+            current_version: HeadSource::version_for(&latest),
+            current_sha256: String::new(),
+            is_source_build: true,
+        }))
+    }
+
     pub async fn is_outdated(&self, name: &str) -> Result<Option<OutdatedPackage>, Error> {
         let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
         })?;
 
-        let formula = self.api_client.get_formula(name).await?;
+        let formula = self.fetch_formula(name).await?;
         let is_source = installed.store_key.starts_with("source:");
 
-        if is_source {
+        if let Some(revision) = HeadSource::revision_of(&installed.version) {
+            self.head_outdated(&installed, &formula, revision).await
+        } else if is_source {
             let current_version = formula.effective_version();
             if installed.version == current_version {
                 Ok(None)
@@ -28,7 +79,7 @@ impl Installer {
                 }))
             }
         } else {
-            let bottle = select_bottle(&formula)?;
+            let bottle = self.keg_bottle(&formula, &installed)?;
             if installed.store_key == bottle.sha256 {
                 Ok(None)
             } else {
@@ -54,20 +105,18 @@ impl Installer {
             installed.iter().map(|k| k.name.as_str()).collect();
 
         let bulk_raw = self.api_client.get_all_formulas_raw().await?;
-        let bulk_values: Vec<serde_json::Value> = serde_json::from_str(&bulk_raw)
-            .map_err(Error::network("failed to parse bulk formula JSON"))?;
-
-        let mut bulk_map: HashMap<String, zb_core::Formula> = HashMap::new();
-        for val in bulk_values {
-            let name = match val.get("name").and_then(|n| n.as_str()) {
-                Some(n) if installed_names.contains(n) => n.to_string(),
-                _ => continue,
-            };
-            if let Ok(f) = serde_json::from_value(val) {
-                bulk_map.insert(name, f);
-            }
+        let (records, formulas): (Vec<_>, Vec<_>) = formula_records(&bulk_raw)?.into_iter().unzip();
+        // The whole index is in hand, so keep stored metadata current too.
+        if let Err(error) = self.db.replace_formulas(&records) {
+            warn!(%error, "failed to store formula metadata");
         }
 
+        let mut bulk_map: HashMap<String, zb_core::Formula> = formulas
+            .into_iter()
+            .filter(|f| installed_names.contains(f.name.as_str()))
+            .map(|f| (f.name.clone(), f))
+            .collect();
+
         let mut outdated = Vec::new();
         let mut warnings = Vec::new();
 
@@ -88,7 +137,13 @@ impl Installer {
 
             let is_source = keg.store_key.starts_with("source:");
 
-            if is_source {
+            if let Some(revision) = HeadSource::revision_of(&keg.version) {
+                match self.head_outdated(keg, &formula, revision).await {
+                    Ok(Some(package)) => outdated.push(package),
+                    Ok(None) => {}
+                    Err(e) => warnings.push(format!("{}: {}", keg.name, e)),
+                }
+            } else if is_source {
                 let current_version = formula.effective_version();
                 if keg.version != current_version {
                     outdated.push(OutdatedPackage {
@@ -101,7 +156,7 @@ impl Installer {
                     });
                 }
             } else {
-                match select_bottle(&formula) {
+                match self.keg_bottle(&formula, keg) {
                     Ok(bottle) => {
                         if keg.store_key != bottle.sha256 {
                             outdated.push(OutdatedPackage {
@@ -130,20 +185,13 @@ impl Installer {
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
-
     use tempfile::TempDir;
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
-    use crate::cellar::Cellar;
-    use crate::network::api::ApiClient;
-    use crate::storage::blob::BlobCache;
-    use crate::storage::db::Database;
-    use crate::storage::store::Store;
-    use crate::{Installer, Linker};
+    use crate::Installer;
 
-    use super::super::test_support::get_test_bottle_tag;
+    use super::super::test_support::{get_test_bottle_tag, installer};
 
     fn formula_json(name: &str, version: &str, sha256: &str) -> String {
         let tag = get_test_bottle_tag();
@@ -170,28 +218,8 @@ mod tests {
     async fn test_installer() -> (Installer, MockServer, TempDir) {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix,
-            root.join("locks"),
-        );
+
+        let installer = installer(tmp.path(), &mock_server.uri());
         (installer, mock_server, tmp)
     }
 
@@ -212,28 +240,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix,
-            root.join("locks"),
-        );
+        let installer = installer(tmp.path(), &mock_server.uri());
 
         let suggestions = installer.suggest_formulas("pythn", 3).await.unwrap();
         assert_eq!(suggestions.first().map(String::as_str), Some("python"));
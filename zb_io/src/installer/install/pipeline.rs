@@ -0,0 +1,127 @@
+use zb_core::DependencyGraph;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Waiting,
+    Installed,
+    Failed,
+}
+
+/// Decides when each bottle of a plan can be installed: as soon as its own
+/// download has finished and every formula it depends on within the plan is
+/// installed. Downloads finish in any order, so a keg is never linked ahead
+/// of its dependencies, and the dependents of a failed install are held
+/// back rather than installed broken.
+pub(super) struct InstallPipeline {
+    /// Plan indices of each item's dependencies within the plan.
+    dependencies: Vec<Vec<usize>>,
+    downloaded: Vec<bool>,
+    slots: Vec<Slot>,
+}
+
+impl InstallPipeline {
+    /// `names` are the plan's install names in plan order.
+    pub(super) fn new(names: &[&str], graph: &DependencyGraph) -> Self {
+        let dependencies = names
+            .iter()
+            .map(|name| {
+                graph
+                    .dependencies(name)
+                    .iter()
+                    .filter_map(|dep| names.iter().position(|n| n == dep))
+                    .collect()
+            })
+            .collect();
+        Self {
+            dependencies,
+            downloaded: vec![false; names.len()],
+            slots: vec![Slot::Waiting; names.len()],
+        }
+    }
+
+    pub(super) fn downloaded(&mut self, index: usize) {
+        self.downloaded[index] = true;
+    }
+
+    /// The first item, in plan order, that can be installed now.
+    pub(super) fn next_ready(&self) -> Option<usize> {
+        (0..self.slots.len()).find(|&index| {
+            self.slots[index] == Slot::Waiting
+                && self.downloaded[index]
+                && self.dependencies[index]
+                    .iter()
+                    .all(|&dep| self.slots[dep] == Slot::Installed)
+        })
+    }
+
+    pub(super) fn finished(&mut self, index: usize, installed: bool) {
+        self.slots[index] = if installed {
+            Slot::Installed
+        } else {
+            Slot::Failed
+        };
+    }
+
+    /// Downloaded items left uninstalled because a dependency failed.
+    pub(super) fn held_back(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.slots.len())
+            .filter(|&index| self.slots[index] == Slot::Waiting && self.downloaded[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn graph() -> DependencyGraph {
+        // app -> lib -> base; tool stands alone.
+        DependencyGraph::from_edges(
+            vec!["app".into(), "tool".into()],
+            BTreeMap::from([
+                ("base".into(), vec![]),
+                ("lib".into(), vec!["base".into()]),
+                ("app".into(), vec!["lib".into(), "zlib".into()]),
+                ("tool".into(), vec![]),
+            ]),
+        )
+    }
+
+    #[test]
+    fn items_install_once_their_dependencies_have() {
+        let mut pipeline = InstallPipeline::new(&["base", "lib", "app", "tool"], &graph());
+
+        pipeline.downloaded(2);
+        pipeline.downloaded(1);
+        assert_eq!(pipeline.next_ready(), None);
+
+        pipeline.downloaded(3);
+        assert_eq!(pipeline.next_ready(), Some(3));
+        pipeline.finished(3, true);
+
+        pipeline.downloaded(0);
+        let mut order = Vec::new();
+        while let Some(index) = pipeline.next_ready() {
+            pipeline.finished(index, true);
+            order.push(index);
+        }
+        assert_eq!(order, [0, 1, 2]);
+        assert_eq!(pipeline.held_back().count(), 0);
+    }
+
+    #[test]
+    fn dependents_of_a_failed_install_are_held_back() {
+        let mut pipeline = InstallPipeline::new(&["base", "lib", "app", "tool"], &graph());
+        for index in 0..4 {
+            pipeline.downloaded(index);
+        }
+
+        assert_eq!(pipeline.next_ready(), Some(0));
+        pipeline.finished(0, false);
+        assert_eq!(pipeline.next_ready(), Some(3));
+        pipeline.finished(3, true);
+        assert_eq!(pipeline.next_ready(), None);
+        assert_eq!(pipeline.held_back().collect::<Vec<_>>(), [1, 2]);
+    }
+}
@@ -1,11 +1,25 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
 
 use tracing::warn;
-use zb_core::{BuildPlan, Error, Formula, InstallMethod, select_bottle};
+use zb_core::{
+    BuildPlan, DependencyClasses, Error, Formula, HeadSource, HostFacts, InstallMethod, Platform,
+    SelectedBinary, SelectedBottle, check_bottle_glibc, check_requirements, parse_glibc_version,
+    select_binary, select_bottle_for_arch, select_bottle_for_tag, select_bottle_with_strictness,
+};
 
-use super::{InstallPlan, Installer, PlanFailure, PlannedInstall};
+use crate::build::source::resolve_head_revision;
+
+use super::{InstallPhase, InstallPlan, Installer, PlanFailure, PlannedInstall};
 
 impl Installer {
+    /// Also install the optional, build or test dependencies `classes`
+    /// selects. Bottle installs skip those by default.
+    pub fn with_dependency_classes(mut self, classes: DependencyClasses) -> Self {
+        self.dependency_classes = classes;
+        self
+    }
+
     pub async fn plan(&self, names: &[String]) -> Result<InstallPlan, Error> {
         self.plan_with_options(names, false).await
     }
@@ -15,16 +29,31 @@ impl Installer {
         names: &[String],
         build_from_source: bool,
     ) -> Result<InstallPlan, Error> {
+        self.run_pre_plan_hook(names)?;
+        let started = Instant::now();
         let formulas = self.fetch_all_formulas(names).await?;
-        let ordered = zb_core::resolve_closure(names, &formulas)?;
+        let platform = self.target_platform();
+        let classes = self.dependency_classes;
+        let ordered = zb_core::resolve_closure_with(names, &formulas, platform, classes)?;
+        let graph = zb_core::dependency_graph_with(names, &formulas, platform, classes);
+        let mut head_revisions = self.resolve_head_revisions(names, &formulas).await?;
 
         let mut items = Vec::with_capacity(ordered.len());
         for install_name in ordered {
             let formula = formulas.get(&install_name).cloned().unwrap();
-            items.push(self.plan_item(install_name, formula, build_from_source)?);
+            let on_request = names.contains(&install_name);
+            let item = match head_revisions.remove(&install_name) {
+                Some((head, revision)) => {
+                    self.plan_head_item(install_name, formula, &head, &revision)?
+                }
+                None => self.plan_item(install_name, formula, build_from_source, on_request)?,
+            };
+            items.push(item);
         }
+        self.measure_bottles(&mut items).await;
+        self.report_phase(&names.join(" "), InstallPhase::Resolve, started.elapsed());
 
-        Ok(InstallPlan { items })
+        Ok(InstallPlan { items, graph })
     }
 
     pub async fn plan_best_effort(
@@ -59,7 +88,13 @@ impl Installer {
                 continue;
             }
 
-            if let Some(failure) = root_dependency_failure(name, &formulas, &fetch_failures) {
+            if let Some(failure) = root_dependency_failure(
+                name,
+                &formulas,
+                &fetch_failures,
+                self.target_platform(),
+                self.dependency_classes,
+            ) {
                 failures.push(failure);
                 continue;
             }
@@ -67,12 +102,21 @@ impl Installer {
             valid_roots.push(name.clone());
         }
 
+        let platform = self.target_platform();
+        let classes = self.dependency_classes;
+        let graph = zb_core::dependency_graph_with(&valid_roots, &formulas, platform, classes);
         if !valid_roots.is_empty() {
-            match zb_core::resolve_closure(&valid_roots, &formulas) {
+            match zb_core::resolve_closure_with(&valid_roots, &formulas, platform, classes) {
                 Ok(ordered) => {
                     for install_name in ordered {
                         let formula = formulas.get(&install_name).cloned().unwrap();
-                        match self.plan_item(install_name.clone(), formula, build_from_source) {
+                        let on_request = valid_roots.contains(&install_name);
+                        match self.plan_item(
+                            install_name.clone(),
+                            formula,
+                            build_from_source,
+                            on_request,
+                        ) {
                             Ok(item) => items.push(item),
                             Err(error) => failures.push(PlanFailure {
                                 name: install_name,
@@ -90,7 +134,8 @@ impl Installer {
             }
         }
 
-        (InstallPlan { items }, failures)
+        self.measure_bottles(&mut items).await;
+        (InstallPlan { items, graph }, failures)
     }
 
     fn plan_item(
@@ -98,40 +143,268 @@ impl Installer {
         install_name: String,
         formula: Formula,
         build_from_source: bool,
+        installed_on_request: bool,
     ) -> Result<PlannedInstall, Error> {
-        let method = if build_from_source {
+        let bottle_variant = self.bottle_variant.clone().filter(|_| installed_on_request);
+        let formula = match &bottle_variant {
+            Some(variant) => formula.with_bottle_variant(variant)?,
+            None => formula,
+        };
+        self.check_host_requirements(&formula, false)?;
+        self.check_installed_arch(&install_name)?;
+
+        let method = if let Some(tag) = &self.bottle_tag {
+            InstallMethod::Bottle(select_bottle_for_tag(&formula, tag)?)
+        } else if bottle_variant.is_some() {
+            // A variant names bottles; falling back to a source build or
+            // release binary would install the default instead.
+            InstallMethod::Bottle(self.host_bottle(&formula)?)
+        } else if build_from_source {
             match BuildPlan::from_formula(&formula, &self.prefix) {
                 Some(plan) => InstallMethod::Source(plan),
-                None => match select_bottle(&formula) {
+                None => match self.host_bottle(&formula) {
                     Ok(bottle) => InstallMethod::Bottle(bottle),
-                    Err(_) => {
-                        return Err(Error::UnsupportedBottle {
-                            name: formula.name.clone(),
-                        });
-                    }
+                    Err(_) => match self.host_binary(&formula) {
+                        Some(binary) => InstallMethod::Binary(binary),
+                        None => {
+                            return Err(Error::UnsupportedBottle {
+                                name: formula.name.clone(),
+                            });
+                        }
+                    },
                 },
             }
         } else {
-            match select_bottle(&formula) {
+            match self.host_bottle(&formula) {
                 Ok(bottle) => InstallMethod::Bottle(bottle),
-                Err(_) => match BuildPlan::from_formula(&formula, &self.prefix) {
-                    Some(plan) => InstallMethod::Source(plan),
-                    None => {
-                        return Err(Error::UnsupportedBottle {
-                            name: formula.name.clone(),
-                        });
-                    }
+                Err(_) => match self.host_binary(&formula) {
+                    Some(binary) => InstallMethod::Binary(binary),
+                    None => match BuildPlan::from_formula(&formula, &self.prefix) {
+                        Some(plan) => InstallMethod::Source(plan),
+                        None => {
+                            return Err(Error::UnsupportedBottle {
+                                name: formula.name.clone(),
+                            });
+                        }
+                    },
                 },
             }
         };
 
+        if let InstallMethod::Bottle(bottle) = &method {
+            self.check_glibc(&formula, bottle)?;
+        }
+
+        if matches!(method, InstallMethod::Source(_)) {
+            if let Some(arch) = self.arch {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "'{}' has no {arch} bottle, and source builds are only supported for the host arch",
+                        formula.name
+                    ),
+                });
+            }
+            self.check_host_requirements(&formula, true)?;
+        }
+
+        let conflicts = self.installed_conflicts(&formula);
         Ok(PlannedInstall {
             install_name,
             formula,
             method,
+            installed_on_request,
+            delta_base: None,
+            conflicts,
+            cached: false,
+            download_size: None,
+            install_size: None,
+            bottle_variant,
         })
     }
 
+    /// With `head` set, the commit each requested formula's `head` branch
+    /// points at. Dependencies are installed as usual.
+    async fn resolve_head_revisions(
+        &self,
+        names: &[String],
+        formulas: &BTreeMap<String, Formula>,
+    ) -> Result<HashMap<String, (HeadSource, String)>, Error> {
+        if !self.head {
+            return Ok(HashMap::new());
+        }
+
+        let mut heads = Vec::with_capacity(names.len());
+        for name in names {
+            let Some(formula) = formulas.get(name) else {
+                continue;
+            };
+            let head = formula
+                .head_source()
+                .ok_or_else(|| Error::UnsupportedFormula {
+                    name: name.clone(),
+                    reason: "formula has no head spec".to_string(),
+                })?;
+            heads.push((name.clone(), head));
+        }
+
+        let revisions = futures::future::join_all(
+            heads
+                .iter()
+                .map(|(_, head)| resolve_head_revision(&head.url, head.branch.as_deref())),
+        )
+        .await;
+
+        heads
+            .into_iter()
+            .zip(revisions)
+            .map(|((name, head), revision)| Ok((name, (head, revision?))))
+            .collect()
+    }
+
+    /// A source build of `revision` from the formula's `head` repository.
+    fn plan_head_item(
+        &self,
+        install_name: String,
+        mut formula: Formula,
+        head: &HeadSource,
+        revision: &str,
+    ) -> Result<PlannedInstall, Error> {
+        if let Some(arch) = self.arch {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "'{}' cannot be built from HEAD for {arch}; source builds are only supported for the host arch",
+                    formula.name
+                ),
+            });
+        }
+        self.check_host_requirements(&formula, true)?;
+        self.check_installed_arch(&install_name)?;
+
+        let plan = BuildPlan::from_head(&formula, head, revision, &self.prefix);
+        formula.versions.stable = plan.version.clone();
+        formula.revision = 0;
+
+        let conflicts = self.installed_conflicts(&formula);
+        Ok(PlannedInstall {
+            install_name,
+            formula,
+            method: InstallMethod::Source(plan),
+            installed_on_request: true,
+            delta_base: None,
+            conflicts,
+            cached: false,
+            download_size: None,
+            install_size: None,
+            bottle_variant: None,
+        })
+    }
+
+    /// Reinstalling a keg for another arch would swap it out from under its
+    /// dependents, so a keg has to be uninstalled before switching arch.
+    fn check_installed_arch(&self, install_name: &str) -> Result<(), Error> {
+        let Some(keg) = self.db.get_installed(install_name) else {
+            return Ok(());
+        };
+        if keg.arch.as_deref() == self.arch {
+            return Ok(());
+        }
+        let installed = keg.arch.as_deref().unwrap_or(std::env::consts::ARCH);
+        let requested = self.arch.unwrap_or(std::env::consts::ARCH);
+        Err(Error::InvalidArgument {
+            message: format!(
+                "'{install_name}' is installed for {installed}; run `zb uninstall {install_name}` before installing it for {requested}"
+            ),
+        })
+    }
+
+    /// Installed formulas that `formula` cannot be linked alongside.
+    /// Keg-only formulas never link, so they conflict with nothing.
+    fn installed_conflicts(&self, formula: &Formula) -> Vec<String> {
+        if formula.is_keg_only() {
+            return Vec::new();
+        }
+        formula
+            .conflicts_with
+            .iter()
+            .filter(|name| **name != formula.name && self.db.get_installed(name).is_some())
+            .cloned()
+            .collect()
+    }
+
+    /// Requirements describe the machine the keg will run on, which is
+    /// unknown when planning for an explicit bottle tag.
+    fn check_host_requirements(&self, formula: &Formula, from_source: bool) -> Result<(), Error> {
+        if self.bottle_tag.is_some() {
+            return Ok(());
+        }
+        match self.arch {
+            Some(arch) => {
+                let host = HostFacts {
+                    arch,
+                    ..HostFacts::current().clone()
+                };
+                check_requirements(formula, &host, from_source)
+            }
+            None => check_requirements(formula, HostFacts::current(), from_source),
+        }
+    }
+
+    /// Linux bottles need the glibc they were built against. Once zerobrew
+    /// has installed its own glibc, bottles are patched to use it instead of
+    /// the system one.
+    fn check_glibc(&self, formula: &Formula, bottle: &SelectedBottle) -> Result<(), Error> {
+        if self.bottle_tag.is_some() || formula.name == "glibc" {
+            return Ok(());
+        }
+        let glibc = self
+            .db
+            .get_installed("glibc")
+            .and_then(|keg| parse_glibc_version(&keg.version))
+            .or(HostFacts::current().glibc_version);
+        check_bottle_glibc(&formula.name, bottle, glibc)
+    }
+
+    /// The platform plans are resolved for: the host, or the platform of an
+    /// explicit bottle tag or foreign arch.
+    fn target_platform(&self) -> Platform {
+        if let Some(platform) = self
+            .bottle_tag
+            .as_deref()
+            .and_then(Platform::from_bottle_tag)
+        {
+            return platform;
+        }
+        match (Platform::host(), self.arch) {
+            (Platform::Linux { .. }, Some(arch)) => Platform::Linux { arch },
+            (host, _) => host,
+        }
+    }
+
+    pub(super) fn host_bottle(&self, formula: &Formula) -> Result<SelectedBottle, Error> {
+        match self.arch {
+            Some(arch) => select_bottle_for_arch(formula, arch, self.bottle_strictness),
+            None => select_bottle_with_strictness(formula, self.bottle_strictness),
+        }
+    }
+
+    /// The release binaries `formula` ships for the target arch, used when
+    /// it has no bottle.
+    fn host_binary(&self, formula: &Formula) -> Option<SelectedBinary> {
+        select_binary(formula, self.arch.unwrap_or(std::env::consts::ARCH))
+    }
+
+    /// Formulas without a host bottle, release binary or source are skipped
+    /// while planning.
+    /// With an explicit bottle tag nothing is skipped, so `plan_item` can
+    /// report which tags the formula does publish.
+    fn has_installable_artifact(&self, formula: &Formula) -> bool {
+        self.bottle_tag.is_some()
+            || self.host_bottle(formula).is_ok()
+            || self.host_binary(formula).is_some()
+            || formula.has_source_url()
+            || (self.head && formula.head_source().is_some())
+    }
+
     async fn fetch_all_formulas_best_effort(
         &self,
         names: &[String],
@@ -155,38 +428,37 @@ impl Installer {
                 fetched.insert(n.clone());
             }
 
-            let futures: Vec<_> = batch
-                .iter()
-                .map(|n| self.api_client.get_formula(n))
-                .collect();
+            let futures: Vec<_> = batch.iter().map(|n| self.resolve_formula(n)).collect();
 
             let results = futures::future::join_all(futures).await;
 
             for (i, result) in results.into_iter().enumerate() {
                 let fetch_name = batch[i].clone();
                 let formula = match result {
-                    Ok(f) => f,
+                    Ok(f) => f.with_variation_applied(self.target_platform()),
                     Err(error) => {
                         failures.insert(fetch_name, error);
                         continue;
                     }
                 };
 
-                if select_bottle(&formula).is_err() && !formula.has_source_url() {
+                if !self.has_installable_artifact(&formula) {
                     warn!(
                         formula = %formula.name,
                         "skipping formula with no bottle or source available for this platform"
                     );
-                    failures.insert(
-                        fetch_name,
-                        Error::UnsupportedBottle {
+                    let error = self
+                        .check_host_requirements(&formula, false)
+                        .err()
+                        .unwrap_or(Error::UnsupportedBottle {
                             name: formula.name.clone(),
-                        },
-                    );
+                        });
+                    failures.insert(fetch_name, error);
                     continue;
                 }
 
-                for dep in formula.runtime_dependencies() {
+                for dep in formula.dependencies_for(self.target_platform(), self.dependency_classes)
+                {
                     if !fetched.contains(&dep)
                         && !to_fetch.contains(&dep)
                         && !failures.contains_key(&dep)
@@ -206,8 +478,6 @@ impl Installer {
         &self,
         names: &[String],
     ) -> Result<BTreeMap<String, Formula>, Error> {
-        use std::collections::HashSet;
-
         let mut formulas = BTreeMap::new();
         let mut fetched: HashSet<String> = HashSet::new();
         let mut to_fetch: Vec<String> = names.to_vec();
@@ -226,20 +496,19 @@ impl Installer {
                 fetched.insert(n.clone());
             }
 
-            let futures: Vec<_> = batch
-                .iter()
-                .map(|n| self.api_client.get_formula(n))
-                .collect();
+            let futures: Vec<_> = batch.iter().map(|n| self.resolve_formula(n)).collect();
 
             let results = futures::future::join_all(futures).await;
 
             for (i, result) in results.into_iter().enumerate() {
-                let formula = match result {
-                    Ok(f) => f,
-                    Err(e) => return Err(e),
-                };
+                let formula = result?.with_variation_applied(self.target_platform());
 
-                if select_bottle(&formula).is_err() && !formula.has_source_url() {
+                if !self.has_installable_artifact(&formula) {
+                    // A dependency nothing here can provide is skipped, but
+                    // a requested formula should say why it cannot install.
+                    if names.contains(&batch[i]) {
+                        self.check_host_requirements(&formula, false)?;
+                    }
                     warn!(
                         formula = %formula.name,
                         "skipping formula with no bottle or source available for this platform"
@@ -247,7 +516,8 @@ impl Installer {
                     continue;
                 }
 
-                for dep in formula.runtime_dependencies() {
+                for dep in formula.dependencies_for(self.target_platform(), self.dependency_classes)
+                {
                     if !fetched.contains(&dep) && !to_fetch.contains(&dep) {
                         to_fetch.push(dep);
                     }
@@ -265,6 +535,8 @@ fn root_dependency_failure(
     root: &str,
     formulas: &BTreeMap<String, Formula>,
     fetch_failures: &HashMap<String, Error>,
+    platform: Platform,
+    classes: DependencyClasses,
 ) -> Option<PlanFailure> {
     let mut seen = HashSet::new();
     let mut stack = vec![root.to_string()];
@@ -278,7 +550,7 @@ fn root_dependency_failure(
             continue;
         };
 
-        for dep in formula.runtime_dependencies() {
+        for dep in formula.dependencies_for(platform, classes) {
             if let Some(error) = fetch_failures.get(&dep) {
                 return Some(PlanFailure {
                     name: root.to_string(),
@@ -299,19 +571,13 @@ fn root_dependency_failure(
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
-
     use tempfile::TempDir;
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
-    use crate::cellar::Cellar;
+    use crate::InstallPlan;
     use crate::installer::install::test_support::*;
     use crate::network::api::ApiClient;
-    use crate::storage::blob::BlobCache;
-    use crate::storage::db::Database;
-    use crate::storage::store::Store;
-    use crate::{Installer, Linker};
 
     #[tokio::test]
     async fn plans_tapped_formula_with_core_dependency() {
@@ -370,29 +636,10 @@ end
             .mount(&mock_server)
             .await;
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+        installer.api_client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
             .unwrap()
             .with_tap_raw_base_url(mock_server.uri());
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.to_path_buf(),
-            root.join("locks"),
-        );
         let plan = installer
             .plan(&["hashicorp/tap/terraform".to_string()])
             .await
@@ -407,6 +654,55 @@ end
         assert!(planned_names.contains(&"go".to_string()));
     }
 
+    #[tokio::test]
+    async fn resolves_from_stored_formula_index() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let entry = |name: &str, deps: &[&str], aliases: &[&str]| {
+            serde_json::json!({
+                "name": name,
+                "aliases": aliases,
+                "versions": { "stable": "1.0.0" },
+                "dependencies": deps,
+                "bottle": { "stable": { "files": { tag: {
+                    "url": format!("{}/bottles/{name}.tar.gz", mock_server.uri()),
+                    "sha256": "a".repeat(64)
+                } } } }
+            })
+        };
+        let index = serde_json::json!([
+            entry("app", &["libfoo"], &["application"]),
+            entry("libfoo", &[], &[]),
+            { "name": "broken", "versions": "not an object" }
+        ]);
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(index.to_string()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula/app.json"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let installer = installer(tmp.path(), &mock_server.uri());
+
+        let refresh = installer.refresh_formula_index().await.unwrap();
+        assert_eq!((refresh.formulas, refresh.changed), (2, 2));
+        let plan = installer.plan(&["application".to_string()]).await.unwrap();
+        let names: Vec<&str> = plan
+            .items
+            .iter()
+            .map(|item| item.formula.name.as_str())
+            .collect();
+        assert_eq!(names, ["libfoo", "app"]);
+    }
+
     #[tokio::test]
     async fn falls_back_to_source_when_no_bottle() {
         let mock_server = MockServer::start().await;
@@ -433,28 +729,7 @@ end
             .mount(&mock_server)
             .await;
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let installer = installer(tmp.path(), &mock_server.uri());
 
         let plan = installer.plan(&["nobottle".to_string()]).await.unwrap();
 
@@ -510,28 +785,7 @@ end
             .mount(&mock_server)
             .await;
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let installer = installer(tmp.path(), &mock_server.uri());
 
         let plan = installer.plan(&["hasboth".to_string()]).await.unwrap();
 
@@ -560,28 +814,7 @@ end
             .mount(&mock_server)
             .await;
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let installer = installer(tmp.path(), &mock_server.uri());
 
         let result = installer.plan(&["nothing".to_string()]).await;
         assert!(result.is_err());
@@ -634,28 +867,7 @@ end
             .mount(&mock_server)
             .await;
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let installer = installer(tmp.path(), &mock_server.uri());
 
         let names = vec!["goodpkg".to_string(), "missingpkg".to_string()];
         let (plan, failures) = installer.plan_best_effort(&names, false).await;
@@ -669,4 +881,223 @@ end
             zb_core::Error::MissingFormula { .. }
         ));
     }
+
+    /// Plan `app`, whose `tag` variation adds a dependency on gcc, for a
+    /// `tag` bottle.
+    async fn plan_with_variation(tag: &str) -> InstallPlan {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = |name: &str| {
+            format!(
+                r#""bottle": {{ "stable": {{ "files": {{
+                    "{tag}": {{ "url": "{}/bottles/{name}.tar.gz", "sha256": "{}" }}
+                }} }} }}"#,
+                mock_server.uri(),
+                "ab".repeat(32)
+            )
+        };
+        let app_json = format!(
+            r#"{{
+                "name": "app",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "variations": {{ "{tag}": {{ "dependencies": ["gcc"] }} }},
+                {}
+            }}"#,
+            bottle("app")
+        );
+        let gcc_json = format!(
+            r#"{{
+                "name": "gcc",
+                "versions": {{ "stable": "15.1.0" }},
+                "dependencies": [],
+                {}
+            }}"#,
+            bottle("gcc")
+        );
+
+        for (name, body) in [("app", app_json), ("gcc", gcc_json)] {
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let installer = installer(tmp.path(), &mock_server.uri()).with_bottle_tag(tag);
+
+        installer.plan(&["app".to_string()]).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn plan_applies_variation_for_target_platform() {
+        for tag in ["x86_64_linux", "arm64_linux"] {
+            let plan = plan_with_variation(tag).await;
+            let names: Vec<&str> = plan
+                .items
+                .iter()
+                .map(|item| item.install_name.as_str())
+                .collect();
+            assert_eq!(names, vec!["gcc", "app"], "{tag}");
+            assert_eq!(plan.items[1].formula.dependencies, vec!["gcc"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn plan_reports_unsatisfied_requirements() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let other_arch = if cfg!(target_arch = "x86_64") {
+            "arm64"
+        } else {
+            "x86_64"
+        };
+        let formula_json = format!(
+            r#"{{
+                "name": "archonly",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "requirements": [{{ "name": "arch", "version": "{other_arch}", "contexts": [] }}],
+                "bottle": {{ "stable": {{ "files": {{}} }} }}
+            }}"#
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/archonly.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let installer = installer(tmp.path(), &mock_server.uri());
+
+        let err = installer.plan(&["archonly".to_string()]).await.unwrap_err();
+        assert!(
+            matches!(err, zb_core::Error::UnsatisfiedRequirement { ref missing, .. } if missing.len() == 1),
+            "unexpected error: {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn conflicting_formula_requires_no_link() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        for (name, conflicts) in [("mawk", "[]"), ("gawk", r#"["mawk"]"#)] {
+            let bottle = create_bottle_tarball(name);
+            let formula_json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": [],
+                    "conflicts_with": {conflicts},
+                    "conflicts_with_reasons": ["both install an `awk` executable"],
+                    "bottle": {{
+                        "stable": {{
+                            "files": {{
+                                "{tag}": {{
+                                    "url": "{}/bottles/{name}-1.0.0.{tag}.bottle.tar.gz",
+                                    "sha256": "{}"
+                                }}
+                            }}
+                        }}
+                    }}
+                }}"#,
+                mock_server.uri(),
+                sha256_hex(&bottle),
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}-1.0.0.{tag}.bottle.tar.gz")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+
+        let plan = installer.plan(&["gawk".to_string()]).await.unwrap();
+        assert!(plan.items[0].conflicts.is_empty());
+
+        installer
+            .install(&["mawk".to_string()], true)
+            .await
+            .unwrap();
+
+        let plan = installer.plan(&["gawk".to_string()]).await.unwrap();
+        assert_eq!(plan.items[0].conflicts, vec!["mawk".to_string()]);
+
+        let err = installer.execute(plan, true).await.err().unwrap();
+        assert_eq!(
+            err,
+            zb_core::Error::FormulaConflict {
+                name: "gawk".to_string(),
+                conflicting: "mawk".to_string(),
+                reason: Some("both install an `awk` executable".to_string()),
+            }
+        );
+        assert!(err.to_string().contains("--no-link"));
+        assert!(!installer.is_installed("gawk"));
+
+        let plan = installer.plan(&["gawk".to_string()]).await.unwrap();
+        installer.execute(plan, false).await.unwrap();
+        assert!(installer.is_installed("gawk"));
+    }
+
+    #[tokio::test]
+    #[cfg(target_os = "linux")]
+    async fn plan_rejects_bottles_newer_than_installed_glibc() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let formula_json = format!(
+            r#"{{
+                "name": "newlibc",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{
+                                "url": "{}/bottles/newlibc-1.0.0.{tag}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+            "a".repeat(64),
+        );
+        Mock::given(method("GET"))
+            .and(path("/formula/newlibc.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+
+        // A zerobrew-installed glibc stands in for the system one.
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("glibc", "2.17", "glibc-key").unwrap();
+            tx.commit().unwrap();
+        }
+
+        let err = installer.plan(&["newlibc".to_string()]).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                zb_core::Error::GlibcTooOld { ref found, .. } if found == "2.17"
+            ),
+            "unexpected error: {err:?}"
+        );
+    }
 }
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+use zb_core::Error;
+
+use crate::cellar::link::Linker;
+
+use super::{Installer, acquire_install_lock};
+
+/// The profile that is the prefix itself, where installs link.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The link that selects the active profile, inside the profiles directory.
+const CURRENT_LINK: &str = "current";
+
+/// A link tree populated from the installed kegs. `default` is the prefix
+/// itself; other profiles live under `prefix/profiles`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub path: PathBuf,
+    /// The kegs linked into it, as `(name, version)`, sorted by name.
+    pub kegs: Vec<(String, String)>,
+    /// Whether `prefix/profiles/current` points at it.
+    pub current: bool,
+}
+
+impl Installer {
+    fn profiles_dir(&self) -> PathBuf {
+        self.prefix.join("profiles")
+    }
+
+    pub fn profile_path(&self, name: &str) -> PathBuf {
+        if name == DEFAULT_PROFILE {
+            self.prefix.clone()
+        } else {
+            self.profiles_dir().join(name)
+        }
+    }
+
+    /// The link to put on `PATH` (as `.../current/bin`) to follow profile
+    /// switches.
+    pub fn current_profile_link(&self) -> PathBuf {
+        self.profiles_dir().join(CURRENT_LINK)
+    }
+
+    /// The profile `prefix/profiles/current` points at, `default` when it
+    /// was never switched.
+    pub fn current_profile(&self) -> String {
+        match fs::read_link(self.current_profile_link()) {
+            Ok(target) if target != self.prefix => target
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+            _ => DEFAULT_PROFILE.to_string(),
+        }
+    }
+
+    /// Every profile, `default` first.
+    pub fn list_profiles(&self) -> Result<Vec<Profile>, Error> {
+        let current = self.current_profile();
+        let mut default_kegs: Vec<(String, String)> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .filter(|keg| self.linker.is_linked(&self.installed_keg_path(keg)))
+            .map(|keg| (keg.name, keg.version))
+            .collect();
+        default_kegs.sort();
+        let mut profiles = vec![Profile {
+            name: DEFAULT_PROFILE.to_string(),
+            path: self.prefix.clone(),
+            kegs: default_kegs,
+            current: current == DEFAULT_PROFILE,
+        }];
+        for record in self.db.list_profiles()? {
+            profiles.push(Profile {
+                path: self.profile_path(&record.name),
+                current: current == record.name,
+                name: record.name,
+                kegs: record.kegs,
+            });
+        }
+        Ok(profiles)
+    }
+
+    /// Create profile `name` from the installed kegs of `formulas`, or of
+    /// every formula linked into the prefix when none are given. Kegs are
+    /// shared with the prefix, so a profile costs only its links.
+    pub fn create_profile(&mut self, name: &str, formulas: &[String]) -> Result<Profile, Error> {
+        let _lock = acquire_install_lock(&self.locks_dir)?;
+        validate_profile_name(name)?;
+        let path = self.profile_path(name);
+        if path.symlink_metadata().is_ok()
+            || self.db.list_profiles()?.iter().any(|p| p.name == name)
+        {
+            return Err(Error::InvalidArgument {
+                message: format!("profile '{name}' already exists"),
+            });
+        }
+
+        let mut kegs = Vec::new();
+        if formulas.is_empty() {
+            for keg in self.db.list_installed()? {
+                if self.linker.is_linked(&self.installed_keg_path(&keg)) {
+                    kegs.push(keg);
+                }
+            }
+        } else {
+            for formula in formulas {
+                kegs.push(self.db.get_installed(formula).ok_or(Error::NotInstalled {
+                    name: formula.clone(),
+                })?);
+            }
+        }
+        kegs.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let linker = Linker::new(&path).map_err(Error::store("failed to create profile"))?;
+        for keg in &kegs {
+            let keg_path = self.installed_keg_path(keg);
+            if let Err(e) =
+                linker.link_keg_renaming(&keg_path, &HashMap::new(), self.link_mode_for(&keg.name))
+            {
+                let _ = fs::remove_dir_all(&path);
+                return Err(match e {
+                    Error::LinkConflict { conflicts, .. } => Error::LinkConflict {
+                        formula: Some(keg.name.clone()),
+                        conflicts,
+                    },
+                    e => e,
+                });
+            }
+        }
+
+        let kegs: Vec<(String, String)> = kegs.into_iter().map(|k| (k.name, k.version)).collect();
+        if let Err(e) = self.db.create_profile(name, &kegs) {
+            let _ = fs::remove_dir_all(&path);
+            return Err(e);
+        }
+        Ok(Profile {
+            name: name.to_string(),
+            current: false,
+            path,
+            kegs,
+        })
+    }
+
+    /// Point `prefix/profiles/current` at profile `name`. The link is
+    /// replaced by a rename, so anything resolving it sees either the old
+    /// profile or the new one, never neither.
+    pub fn switch_profile(&self, name: &str) -> Result<PathBuf, Error> {
+        let path = self.profile_path(name);
+        if name != DEFAULT_PROFILE && !self.db.list_profiles()?.iter().any(|p| p.name == name) {
+            return Err(Error::InvalidArgument {
+                message: format!("no profile named '{name}'; create it with `zb profile create`"),
+            });
+        }
+
+        let dir = self.profiles_dir();
+        fs::create_dir_all(&dir).map_err(Error::store("failed to create profiles directory"))?;
+        let staged = dir.join(format!(".{CURRENT_LINK}-{}", std::process::id()));
+        let _ = fs::remove_file(&staged);
+        std::os::unix::fs::symlink(&path, &staged)
+            .map_err(Error::store("failed to stage profile link"))?;
+        fs::rename(&staged, self.current_profile_link()).map_err(|e| {
+            let _ = fs::remove_file(&staged);
+            Error::StoreCorruption {
+                message: format!("failed to switch profile: {e}"),
+            }
+        })?;
+        Ok(path)
+    }
+
+    /// Remove the links of keg `name` `version`, at `keg_path`, from every
+    /// profile holding it, before the keg itself goes away.
+    pub(super) fn unlink_from_profiles(&self, name: &str, version: &str, keg_path: &Path) {
+        let profiles = match self.db.forget_profile_keg(name, version) {
+            Ok(profiles) => profiles,
+            Err(e) => {
+                warn!(formula = %name, error = %e, "failed to update profiles");
+                return;
+            }
+        };
+        for profile in profiles {
+            let path = self.profile_path(&profile);
+            if let Err(e) = Linker::new(&path)
+                .map_err(Error::store("failed to open profile"))
+                .and_then(|linker| linker.unlink_keg(keg_path))
+            {
+                warn!(formula = %name, profile = %profile, error = %e, "failed to unlink from profile");
+            }
+        }
+    }
+}
+
+fn validate_profile_name(name: &str) -> Result<(), Error> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name != DEFAULT_PROFILE
+        && name != CURRENT_LINK
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument {
+            message: format!(
+                "invalid profile name '{name}': use letters, digits, '-', '_' and '.', other than 'default' and 'current'"
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+
+    async fn mount_formula(server: &MockServer, name: &str) {
+        let bottle = create_bottle_tarball(name);
+        let tag = get_test_bottle_tag();
+        let bottle_path = format!("/bottles/{name}.tar.gz");
+        let formula = serde_json::json!({
+            "name": name,
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}{bottle_path}", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path(format!("/formula/{name}.json")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn profiles_share_kegs_and_switch_atomically() {
+        let server = MockServer::start().await;
+        mount_formula(&server, "alpha").await;
+        mount_formula(&server, "beta").await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer
+            .install(&["alpha".to_string(), "beta".to_string()], true)
+            .await
+            .unwrap();
+
+        let work = installer
+            .create_profile("work", &["alpha".to_string()])
+            .unwrap();
+        assert!(work.path.join("bin/alpha").exists());
+        assert!(!work.path.join("bin/beta").exists());
+        let everything = installer.create_profile("everything", &[]).unwrap();
+        assert_eq!(everything.kegs.len(), 2);
+        assert!(installer.create_profile("work", &[]).is_err());
+        assert!(installer.create_profile("current", &[]).is_err());
+
+        let current = installer.current_profile_link();
+        assert_eq!(installer.current_profile(), DEFAULT_PROFILE);
+        installer.switch_profile("work").unwrap();
+        assert_eq!(installer.current_profile(), "work");
+        assert!(current.join("bin/alpha").exists());
+        assert!(!current.join("bin/beta").exists());
+        installer.switch_profile(DEFAULT_PROFILE).unwrap();
+        assert!(current.join("bin/beta").exists());
+        assert!(installer.switch_profile("missing").is_err());
+
+        let listed: Vec<(String, usize, bool)> = installer
+            .list_profiles()
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.name, p.kegs.len(), p.current))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("default".to_string(), 2, true),
+                ("everything".to_string(), 2, false),
+                ("work".to_string(), 1, false),
+            ]
+        );
+
+        installer.uninstall("alpha").unwrap();
+        assert!(work.path.join("bin/alpha").symlink_metadata().is_err());
+        assert!(installer.list_profiles().unwrap()[2].kegs.is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+use zb_core::{Error, formula_token};
+
+use super::{Installer, PlannedInstall};
+
+/// Written into keg-only kegs, so builds can source the formula's flags
+/// from `<prefix>/opt/<name>/.zb-env`.
+const ENV_HINT_FILE: &str = ".zb-env";
+
+/// Appended to the `PATH` of `zb exec`, so commands still find the base
+/// system tools without picking up anything else from the caller's `PATH`.
+const SYSTEM_PATH: &[&str] = &["/usr/bin", "/bin", "/usr/sbin", "/sbin"];
+
+/// Compiler and pkg-config search paths for building against an installed
+/// formula, through its opt link so they survive upgrades.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildFlags {
+    pub cppflags: Vec<String>,
+    pub ldflags: Vec<String>,
+    pub pkg_config_path: Vec<PathBuf>,
+}
+
+impl BuildFlags {
+    /// Flags for the directories `keg` has, spelled through `base`.
+    fn for_keg(keg: &Path, base: &Path) -> Self {
+        let mut flags = BuildFlags::default();
+        if keg.join("include").is_dir() {
+            flags
+                .cppflags
+                .push(format!("-I{}", base.join("include").display()));
+        }
+        if keg.join("lib").is_dir() {
+            flags
+                .ldflags
+                .push(format!("-L{}", base.join("lib").display()));
+        }
+        for dir in ["lib/pkgconfig", "share/pkgconfig"] {
+            if keg.join(dir).is_dir() {
+                flags.pkg_config_path.push(base.join(dir));
+            }
+        }
+        flags
+    }
+
+    /// Append the flags of `other` that are not already present.
+    pub fn extend(&mut self, other: BuildFlags) {
+        fn merge<T: PartialEq>(into: &mut Vec<T>, from: Vec<T>) {
+            for item in from {
+                if !into.contains(&item) {
+                    into.push(item);
+                }
+            }
+        }
+        merge(&mut self.cppflags, other.cppflags);
+        merge(&mut self.ldflags, other.ldflags);
+        merge(&mut self.pkg_config_path, other.pkg_config_path);
+    }
+
+    /// The flags as environment variables, leaving out empty ones.
+    pub fn to_env(&self) -> Vec<(&'static str, String)> {
+        let pkg_config_path = self
+            .pkg_config_path
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect::<Vec<_>>()
+            .join(":");
+        [
+            ("CPPFLAGS", self.cppflags.join(" ")),
+            ("LDFLAGS", self.ldflags.join(" ")),
+            ("PKG_CONFIG_PATH", pkg_config_path),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+    }
+
+    /// `export` lines for a POSIX shell.
+    pub fn to_shell(&self) -> String {
+        self.to_env()
+            .into_iter()
+            .map(|(name, value)| format!("export {name}='{}'\n", value.replace('\'', r"'\''")))
+            .collect()
+    }
+}
+
+impl Installer {
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// The directory kegs are installed into.
+    pub fn cellar_dir(&self) -> &Path {
+        self.cellar.dir()
+    }
+
+    /// The stable `opt` path of the installed formula `name`.
+    pub fn opt_path(&self, name: &str) -> Result<PathBuf, Error> {
+        self.require_installed(name)?;
+        Ok(self.prefix.join("opt").join(formula_token(name)))
+    }
+
+    /// The cellar directory holding every keg of the installed formula
+    /// `name`.
+    pub fn rack_path(&self, name: &str) -> Result<PathBuf, Error> {
+        self.require_installed(name)?;
+        Ok(self.installed_cellar(name).dir().join(formula_token(name)))
+    }
+
+    /// Flags for building against the installed formula `name`, covering
+    /// the directories its keg actually has.
+    pub fn build_flags(&self, name: &str) -> Result<BuildFlags, Error> {
+        let version = self.require_installed(name)?;
+        let token = formula_token(name);
+        let keg = self.installed_cellar(name).keg_path(token, &version);
+        let opt = self.prefix.join("opt").join(token);
+        Ok(BuildFlags::for_keg(&keg, &opt))
+    }
+
+    /// Flags for building against each of the installed formulas `names`,
+    /// followed by those of the prefix itself.
+    pub fn build_env(&self, names: &[String]) -> Result<BuildFlags, Error> {
+        let mut flags = BuildFlags::default();
+        for name in names {
+            flags.extend(self.build_flags(name)?);
+        }
+        flags.extend(BuildFlags::for_keg(&self.prefix, &self.prefix));
+        Ok(flags)
+    }
+
+    /// The environment `zb exec` runs commands in: `PATH`, `MANPATH` and,
+    /// on Linux, `LD_LIBRARY_PATH` built from the installed formulas
+    /// `names` and the prefix alone, followed by their build flags.
+    pub fn exec_env(&self, names: &[String]) -> Result<Vec<(&'static str, String)>, Error> {
+        let mut env = self.runtime_env(names, true)?;
+        env.extend(self.build_env(names)?.to_env());
+        Ok(env)
+    }
+
+    /// `PATH`, `MANPATH` and, on Linux, `LD_LIBRARY_PATH` covering the opt
+    /// paths of the installed formulas `names`, then the prefix itself when
+    /// `with_prefix` is set, then the system's own directories.
+    pub(super) fn runtime_env(
+        &self,
+        names: &[String],
+        with_prefix: bool,
+    ) -> Result<Vec<(&'static str, String)>, Error> {
+        let mut roots = Vec::new();
+        for name in names {
+            let version = self.require_installed(name)?;
+            let token = formula_token(name);
+            roots.push((
+                self.installed_cellar(name).keg_path(token, &version),
+                self.prefix.join("opt").join(token),
+            ));
+        }
+        if with_prefix {
+            roots.push((self.prefix.clone(), self.prefix.clone()));
+        }
+        let search_path = |dirs: &[&str]| -> Vec<String> {
+            let mut found = Vec::new();
+            for (keg, base) in &roots {
+                for dir in dirs {
+                    let path = base.join(dir).display().to_string();
+                    if keg.join(dir).is_dir() && !found.contains(&path) {
+                        found.push(path);
+                    }
+                }
+            }
+            found
+        };
+
+        let mut path = search_path(&["bin", "sbin"]);
+        path.extend(SYSTEM_PATH.iter().map(|dir| dir.to_string()));
+        let mut env = vec![
+            ("PATH", path.join(":")),
+            ("MANPATH", search_path(&["share/man"]).join(":")),
+        ];
+        // macOS kegs find their libraries through relocated install names.
+        if cfg!(target_os = "linux") {
+            env.push(("LD_LIBRARY_PATH", search_path(&["lib"]).join(":")));
+        }
+        env.retain(|(_, value)| !value.is_empty());
+        Ok(env)
+    }
+
+    /// Keg-only formulas are not linked into the prefix, so leave the flags
+    /// for building against them next to their files.
+    pub(super) fn write_env_hint(&self, item: &PlannedInstall, keg_path: &Path) {
+        if !item.formula.is_keg_only() {
+            return;
+        }
+        let opt = self
+            .prefix
+            .join("opt")
+            .join(formula_token(&item.install_name));
+        let flags = BuildFlags::for_keg(keg_path, &opt);
+        if let Err(e) = std::fs::write(keg_path.join(ENV_HINT_FILE), flags.to_shell()) {
+            warn!(formula = %item.install_name, error = %e, "failed to write environment hint");
+        }
+    }
+
+    /// The installed version of `name`, or `NotInstalled`.
+    pub(super) fn require_installed(&self, name: &str) -> Result<String, Error> {
+        self.db
+            .get_installed(name)
+            .map(|keg| keg.version)
+            .ok_or(Error::NotInstalled {
+                name: name.to_string(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::cellar::Cellar;
+    use crate::installer::install::test_support::{self, NO_API};
+
+    /// The shared installer, with its Cellar inside the prefix.
+    fn installer(tmp: &TempDir) -> Installer {
+        let mut installer = test_support::installer(tmp.path(), NO_API);
+        installer.cellar = Cellar::new_at(tmp.path().join("prefix/Cellar")).unwrap();
+        installer
+    }
+
+    #[test]
+    fn build_flags_point_at_the_opt_link() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(&tmp);
+        let keg = installer.cellar.keg_path("openssl@3", "3.4.0");
+        fs::create_dir_all(keg.join("include/openssl")).unwrap();
+        fs::create_dir_all(keg.join("lib/pkgconfig")).unwrap();
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("openssl@3", "3.4.0", "abc").unwrap();
+            tx.commit().unwrap();
+        }
+
+        let opt = tmp.path().join("prefix/opt/openssl@3");
+        assert_eq!(installer.opt_path("openssl@3").unwrap(), opt);
+        assert_eq!(
+            installer.rack_path("openssl@3").unwrap(),
+            tmp.path().join("prefix/Cellar/openssl@3")
+        );
+        assert_eq!(
+            installer.build_flags("openssl@3").unwrap().to_env(),
+            [
+                ("CPPFLAGS", format!("-I{}/include", opt.display())),
+                ("LDFLAGS", format!("-L{}/lib", opt.display())),
+                (
+                    "PKG_CONFIG_PATH",
+                    format!("{}/lib/pkgconfig", opt.display())
+                ),
+            ]
+        );
+
+        assert!(matches!(
+            installer.build_flags("readline"),
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+
+    #[test]
+    fn build_env_adds_the_prefix_after_formulas() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(&tmp);
+        let keg = installer.cellar.keg_path("readline", "8.2");
+        fs::create_dir_all(keg.join("include")).unwrap();
+        fs::create_dir_all(tmp.path().join("prefix/include")).unwrap();
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("readline", "8.2", "abc").unwrap();
+            tx.commit().unwrap();
+        }
+
+        let flags = installer.build_env(&["readline".to_string()]).unwrap();
+        let prefix = tmp.path().join("prefix");
+        assert_eq!(
+            flags.cppflags,
+            [
+                format!("-I{}/opt/readline/include", prefix.display()),
+                format!("-I{}/include", prefix.display()),
+            ]
+        );
+        assert_eq!(
+            flags.to_shell().lines().next().unwrap(),
+            format!(
+                "export CPPFLAGS='-I{0}/opt/readline/include -I{0}/include'",
+                prefix.display()
+            )
+        );
+    }
+
+    #[test]
+    fn exec_env_is_built_from_the_formulas_and_prefix() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(&tmp);
+        let keg = installer.cellar.keg_path("openssl@3", "3.4.0");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::create_dir_all(keg.join("lib/pkgconfig")).unwrap();
+        fs::create_dir_all(keg.join("share/man")).unwrap();
+        let prefix = tmp.path().join("prefix");
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("openssl@3", "3.4.0", "abc").unwrap();
+            tx.commit().unwrap();
+        }
+
+        let env: std::collections::HashMap<_, _> = installer
+            .exec_env(&["openssl@3".to_string()])
+            .unwrap()
+            .into_iter()
+            .collect();
+        let opt = prefix.join("opt/openssl@3");
+        assert_eq!(
+            env["PATH"],
+            format!(
+                "{}/bin:{}/bin:/usr/bin:/bin:/usr/sbin:/sbin",
+                opt.display(),
+                prefix.display()
+            )
+        );
+        assert_eq!(env["MANPATH"], format!("{}/share/man", opt.display()));
+        assert_eq!(
+            env["PKG_CONFIG_PATH"],
+            format!("{}/lib/pkgconfig", opt.display())
+        );
+        if cfg!(target_os = "linux") {
+            assert_eq!(
+                env["LD_LIBRARY_PATH"],
+                format!("{}/lib:{}/lib", opt.display(), prefix.display())
+            );
+        }
+
+        assert!(matches!(
+            installer.exec_env(&["readline".to_string()]),
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+}
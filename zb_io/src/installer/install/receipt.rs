@@ -0,0 +1,269 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::warn;
+use zb_core::{Error, InstallMethod, formula_token};
+
+use super::{Installer, PlannedInstall};
+
+pub const RECEIPT_FILE: &str = "INSTALL_RECEIPT.json";
+
+/// Per-keg install metadata, stored as `INSTALL_RECEIPT.json`.
+///
+/// Field names follow Homebrew's tab format so Homebrew can still read a keg
+/// zerobrew installed; zerobrew-only fields are added alongside. Unknown
+/// fields in receipts shipped inside bottles are preserved on write.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallReceipt {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub installed_on_request: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub installed_as_dependency: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub poured_from_bottle: bool,
+    #[serde(default)]
+    pub time: Option<i64>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub runtime_dependencies: Vec<ReceiptDependency>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub source: ReceiptSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bottle_tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The named bottle variant installed instead of the default bottle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bottle_variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zerobrew_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptDependency {
+    pub full_name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptSource {
+    #[serde(default)]
+    pub tap: Option<String>,
+    #[serde(default)]
+    pub spec: Option<String>,
+    /// The formula file or bottle tarball a local install came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl InstallReceipt {
+    pub fn read_from(keg_path: &Path) -> Result<Option<Self>, Error> {
+        let path = keg_path.join(RECEIPT_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::FileError {
+                    message: format!("failed to read {}: {e}", path.display()),
+                });
+            }
+        };
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| Error::FileError {
+                message: format!("invalid install receipt {}: {e}", path.display()),
+            })
+    }
+
+    /// Write the receipt into `keg_path`, merging over any receipt that
+    /// shipped with the bottle.
+    ///
+    /// The file is replaced via rename rather than rewritten in place because
+    /// keg files may be hardlinks into the shared store.
+    pub fn write_to(&self, keg_path: &Path) -> Result<(), Error> {
+        let path = keg_path.join(RECEIPT_FILE);
+        let mut merged = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+            .filter(serde_json::Value::is_object)
+            .unwrap_or_else(|| serde_json::json!({}));
+
+        let ours = serde_json::to_value(self).map_err(Error::file("failed to encode receipt"))?;
+        if let (Some(merged), serde_json::Value::Object(ours)) = (merged.as_object_mut(), ours) {
+            merged.extend(ours);
+        }
+
+        let mut tmp = tempfile::NamedTempFile::new_in(keg_path)
+            .map_err(Error::file("failed to create receipt temp file"))?;
+        serde_json::to_writer_pretty(&mut tmp, &merged)
+            .map_err(Error::file("failed to write receipt"))?;
+        tmp.write_all(b"\n")
+            .map_err(Error::file("failed to write receipt"))?;
+        tmp.persist(&path)
+            .map_err(Error::file("failed to persist receipt"))?;
+        Ok(())
+    }
+}
+
+/// Tap a formula was installed from, in Homebrew's `user/repo` form.
+pub(super) fn source_tap(install_name: &str) -> String {
+    let parts: Vec<&str> = install_name.split('/').collect();
+    match parts.as_slice() {
+        [user, repo, _formula] => format!("{user}/{repo}"),
+        _ => "homebrew/core".to_string(),
+    }
+}
+
+impl Installer {
+    pub fn read_receipt(&self, name: &str) -> Result<Option<InstallReceipt>, Error> {
+        let keg = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let cellar = self.cellar_for(keg.arch.as_deref());
+        InstallReceipt::read_from(&cellar.keg_path(formula_token(name), &keg.version))
+    }
+
+    /// The bottle variant the installed keg of `name` was poured from.
+    pub(super) fn installed_bottle_variant(&self, name: &str) -> Option<String> {
+        self.read_receipt(name)
+            .ok()
+            .flatten()
+            .and_then(|receipt| receipt.bottle_variant)
+    }
+
+    pub(super) fn build_receipt(&self, item: &PlannedInstall) -> InstallReceipt {
+        let runtime_dependencies = item
+            .formula
+            .runtime_dependencies()
+            .into_iter()
+            .map(|dep| ReceiptDependency {
+                version: self.db.get_installed(&dep).map(|k| k.version),
+                full_name: dep,
+            })
+            .collect();
+
+        let (bottle_tag, sha256) = match &item.method {
+            InstallMethod::Bottle(bottle) => {
+                (Some(bottle.tag.clone()), Some(bottle.sha256.clone()))
+            }
+            InstallMethod::Binary(binary) => (None, Some(binary.sha256.clone())),
+            InstallMethod::Source(_) => (None, None),
+        };
+
+        InstallReceipt {
+            installed_on_request: item.installed_on_request,
+            installed_as_dependency: !item.installed_on_request,
+            poured_from_bottle: bottle_tag.is_some(),
+            time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs() as i64),
+            runtime_dependencies,
+            source: match self.local_formula(&item.install_name) {
+                Some(local) => ReceiptSource {
+                    tap: None,
+                    spec: Some("stable".to_string()),
+                    path: Some(local.path.to_string_lossy().into_owned()),
+                },
+                None => ReceiptSource {
+                    tap: Some(source_tap(&item.install_name)),
+                    spec: Some("stable".to_string()),
+                    path: None,
+                },
+            },
+            bottle_tag,
+            sha256,
+            bottle_variant: item.bottle_variant.clone(),
+            zerobrew_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }
+    }
+
+    /// Best effort: a missing receipt never fails an otherwise good install.
+    pub(super) fn write_receipt(&self, item: &PlannedInstall, keg_path: &Path) {
+        if let Err(e) = self.build_receipt(item).write_to(keg_path) {
+            warn!(formula = %item.install_name, error = %e, "failed to write install receipt");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn source_tap_defaults_to_core() {
+        assert_eq!(source_tap("jq"), "homebrew/core");
+        assert_eq!(source_tap("hashicorp/tap/terraform"), "hashicorp/tap");
+    }
+
+    #[test]
+    fn reads_homebrew_receipt_with_nulls_and_extra_fields() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(
+            tmp.path().join(RECEIPT_FILE),
+            r#"{
+                "homebrew_version": "4.4.0",
+                "installed_on_request": null,
+                "poured_from_bottle": true,
+                "runtime_dependencies": null,
+                "source": {"tap": "homebrew/core", "spec": "stable", "path": "x"},
+                "arch": "arm64"
+            }"#,
+        )
+        .unwrap();
+
+        let receipt = InstallReceipt::read_from(tmp.path()).unwrap().unwrap();
+        assert!(!receipt.installed_on_request);
+        assert!(receipt.poured_from_bottle);
+        assert!(receipt.runtime_dependencies.is_empty());
+        assert_eq!(receipt.source.tap.as_deref(), Some("homebrew/core"));
+    }
+
+    #[test]
+    fn write_merges_over_bottle_receipt_without_touching_hardlinks() {
+        let tmp = TempDir::new().unwrap();
+        let keg = tmp.path().join("keg");
+        fs::create_dir(&keg).unwrap();
+        let original = r#"{"homebrew_version": "4.4.0", "installed_on_request": false}"#;
+        fs::write(keg.join(RECEIPT_FILE), original).unwrap();
+        let store_copy = tmp.path().join("store-receipt.json");
+        fs::hard_link(keg.join(RECEIPT_FILE), &store_copy).unwrap();
+
+        let receipt = InstallReceipt {
+            installed_on_request: true,
+            sha256: Some("abc".to_string()),
+            ..Default::default()
+        };
+        receipt.write_to(&keg).unwrap();
+
+        let raw: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(keg.join(RECEIPT_FILE)).unwrap()).unwrap();
+        assert_eq!(raw["homebrew_version"], "4.4.0");
+        assert_eq!(raw["installed_on_request"], true);
+        assert_eq!(raw["sha256"], "abc");
+        assert_eq!(fs::read_to_string(&store_copy).unwrap(), original);
+
+        let read_back = InstallReceipt::read_from(&keg).unwrap().unwrap();
+        assert_eq!(read_back, receipt);
+    }
+
+    #[test]
+    fn missing_receipt_reads_as_none() {
+        let tmp = TempDir::new().unwrap();
+        assert!(InstallReceipt::read_from(tmp.path()).unwrap().is_none());
+    }
+}
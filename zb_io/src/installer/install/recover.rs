@@ -0,0 +1,59 @@
+use tracing::warn;
+
+use zb_core::Error;
+
+use super::Installer;
+
+impl Installer {
+    /// Clean up after an install that was interrupted, e.g. by Ctrl-C:
+    /// half-materialized kegs and unpacked bottles that were never committed
+    /// to the store. Kegs in a foreign-arch cellar are redone when next
+    /// materialized instead. Callers MUST hold the install lock.
+    pub(super) fn recover_interrupted(&self) -> Result<(), Error> {
+        for keg in self.cellar.remove_incomplete_kegs()? {
+            warn!(
+                formula = %keg.name,
+                version = %keg.version,
+                "removed keg left incomplete by an interrupted install"
+            );
+        }
+        let staging = self.store.remove_stale_staging()?;
+        if staging > 0 {
+            warn!(
+                count = staging,
+                "removed bottles left half-unpacked by an interrupted install"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+    use wiremock::MockServer;
+
+    use crate::installer::install::test_support::installer;
+
+    #[tokio::test]
+    async fn leftovers_of_an_interrupted_install_are_removed() {
+        let server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let installer = installer(tmp.path(), &server.uri());
+
+        let staged = root.join("store/.abc123.zb-incomplete-x1y2z3");
+        fs::create_dir_all(staged.join("foo/1.0.0")).unwrap();
+        let keg = installer.cellar.keg_path("foo", "1.0.0");
+        fs::create_dir_all(&keg).unwrap();
+        fs::write(root.join("cellar/foo/.1.0.0.zb-incomplete"), b"").unwrap();
+
+        installer.recover_interrupted().unwrap();
+
+        assert!(!staged.exists());
+        assert!(!keg.exists());
+        assert!(!root.join("cellar/foo/.1.0.0.zb-incomplete").exists());
+    }
+}
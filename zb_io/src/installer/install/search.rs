@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tracing::{debug, warn};
+use zb_core::{Error, Formula};
+
+use crate::network::analytics::{AnalyticsPeriod, InstallCount};
+use crate::storage::db::InstalledKeg;
+
+use super::Installer;
+
+/// A formula matching `zb search`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaMatch {
+    pub name: String,
+    pub version: String,
+    pub desc: Option<String>,
+    /// Installs over the period passed to
+    /// [`Installer::annotate_popularity`], when known.
+    pub installs: Option<u64>,
+}
+
+/// An installed keg and the license its formula declares.
+#[derive(Debug, Clone)]
+pub struct KegLicense {
+    pub keg: InstalledKeg,
+    /// SPDX expression, `None` when the formula declares none or its
+    /// metadata is unavailable.
+    pub license: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    name: String,
+    #[serde(default)]
+    versions: Option<IndexVersions>,
+    #[serde(default)]
+    desc: Option<String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    oldnames: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct IndexVersions {
+    stable: String,
+}
+
+impl IndexEntry {
+    fn matches(&self, query: &str, descriptions: bool) -> bool {
+        let contains = |text: &str| text.to_lowercase().contains(query);
+        contains(&self.name)
+            || self
+                .aliases
+                .iter()
+                .chain(&self.oldnames)
+                .any(|n| contains(n))
+            || (descriptions && self.desc.as_deref().is_some_and(contains))
+    }
+}
+
+impl Installer {
+    /// Formulas whose name or alias contains `query`, ignoring case, and
+    /// with `descriptions` also those whose description does. Searches the
+    /// API's formula index, or the metadata stored by `zb update` when the
+    /// API is unreachable. Exact name matches come first.
+    pub async fn search_formulas(
+        &self,
+        query: &str,
+        descriptions: bool,
+    ) -> Result<Vec<FormulaMatch>, Error> {
+        let query = query.to_lowercase();
+        let entries: Vec<IndexEntry> = match self.api_client.get_all_formulas_raw().await {
+            Ok(raw) => serde_json::from_str(&raw)
+                .map_err(Error::network("failed to parse bulk formula JSON"))?,
+            Err(error) if error.is_offline() => {
+                warn!(%error, "offline; searching stored formula metadata");
+                self.db
+                    .stored_formulas()?
+                    .iter()
+                    .filter_map(|json| serde_json::from_str(json).ok())
+                    .collect()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut matches: Vec<FormulaMatch> = entries
+            .into_iter()
+            .filter(|entry| entry.matches(&query, descriptions))
+            .map(|entry| FormulaMatch {
+                version: entry.versions.map(|v| v.stable).unwrap_or_default(),
+                name: entry.name,
+                desc: entry.desc,
+                installs: None,
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            (a.name.to_lowercase() != query)
+                .cmp(&(b.name.to_lowercase() != query))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        Ok(matches)
+    }
+
+    /// The `limit` most installed formulas over `period`, most installed
+    /// first, from Homebrew's published analytics.
+    pub async fn popular_formulas(
+        &self,
+        period: AnalyticsPeriod,
+        limit: usize,
+    ) -> Result<Vec<InstallCount>, Error> {
+        let mut counts = self.api_client.get_install_counts(period).await?;
+        counts.truncate(limit);
+        Ok(counts)
+    }
+
+    /// Fill in each match's install count over `period`. Best effort:
+    /// without analytics the matches are left unannotated.
+    pub async fn annotate_popularity(&self, matches: &mut [FormulaMatch], period: AnalyticsPeriod) {
+        let counts = match self.api_client.get_install_counts(period).await {
+            Ok(counts) => counts,
+            Err(e) => {
+                debug!(error = %e, "install analytics unavailable");
+                return;
+            }
+        };
+        let counts: HashMap<String, u64> =
+            counts.into_iter().map(|c| (c.formula, c.count)).collect();
+        for m in matches {
+            m.installs = counts.get(&m.name).copied();
+        }
+    }
+
+    /// Metadata stored for `name` when it was last resolved, however old.
+    /// Never touches the network.
+    pub fn stored_formula(&self, name: &str) -> Option<Formula> {
+        let cached = self.db.cached_formula(name).ok()??;
+        serde_json::from_str(&cached.json).ok()
+    }
+
+    /// The license of every installed keg, sorted by name. Uses stored
+    /// formula metadata where there is some, so the licenses reflect the
+    /// formula as last resolved rather than the keg's exact version.
+    pub async fn installed_licenses(&self) -> Result<Vec<KegLicense>, Error> {
+        let mut licenses = Vec::new();
+        for keg in self.db.list_installed()? {
+            let formula = match self.stored_formula(&keg.name) {
+                Some(formula) => Some(formula),
+                None => self.resolve_formula(&keg.name).await.ok(),
+            };
+            licenses.push(KegLicense {
+                license: formula.and_then(|f| f.license),
+                keg,
+            });
+        }
+        licenses.sort_by(|a, b| a.keg.name.cmp(&b.keg.name));
+        Ok(licenses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::installer::install::test_support::{NO_API, installer};
+    use crate::network::analytics::AnalyticsPeriod;
+
+    fn index() -> serde_json::Value {
+        let formula = |name: &str, desc: &str, license: &str, aliases: &[&str]| {
+            serde_json::json!({
+                "name": name,
+                "desc": desc,
+                "homepage": format!("https://example.com/{name}"),
+                "license": license,
+                "aliases": aliases,
+                "versions": { "stable": "1.0" },
+                "dependencies": [],
+                "bottle": { "stable": { "files": {} } },
+            })
+        };
+        serde_json::json!([
+            formula(
+                "jq",
+                "Lightweight and flexible command-line JSON processor",
+                "MIT",
+                &[]
+            ),
+            formula("jless", "Command-line pager for JSON data", "MIT", &[]),
+            formula(
+                "yq",
+                "Process YAML documents from the CLI",
+                "MIT",
+                &["jq-yaml"]
+            ),
+        ])
+    }
+
+    #[tokio::test]
+    async fn search_matches_names_and_optionally_descriptions() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(index().to_string()))
+            .mount(&server)
+            .await;
+        let tmp = TempDir::new().unwrap();
+        let installer = installer(tmp.path(), &server.uri());
+
+        let names = |matches: Vec<super::FormulaMatch>| -> Vec<String> {
+            matches.into_iter().map(|m| m.name).collect()
+        };
+        assert_eq!(
+            names(installer.search_formulas("JQ", false).await.unwrap()),
+            ["jq", "yq"]
+        );
+        assert_eq!(
+            names(installer.search_formulas("json", false).await.unwrap()),
+            Vec::<String>::new()
+        );
+        let by_desc = installer.search_formulas("json", true).await.unwrap();
+        assert_eq!(names(by_desc.clone()), ["jless", "jq"]);
+        assert_eq!(
+            by_desc[1].desc.as_deref(),
+            Some("Lightweight and flexible command-line JSON processor")
+        );
+    }
+
+    #[tokio::test]
+    async fn popularity_comes_from_install_analytics() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(index().to_string()))
+            .mount(&server)
+            .await;
+        let analytics = serde_json::json!({
+            "items": [
+                {"number": 1, "formula": "jq", "count": "250,000", "percent": "2"},
+                {"number": 2, "formula": "wget", "count": "180,000", "percent": "1.5"},
+                {"number": 3, "formula": "yq", "count": "40,000", "percent": "0.3"},
+            ]
+        });
+        Mock::given(method("GET"))
+            .and(path("/analytics/install/90d.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(analytics.to_string()))
+            .mount(&server)
+            .await;
+        let tmp = TempDir::new().unwrap();
+        let mut online = installer(tmp.path(), &server.uri());
+        online.api_client = online
+            .api_client
+            .with_analytics_base_url(format!("{}/analytics", server.uri()));
+
+        let popular = online
+            .popular_formulas(AnalyticsPeriod::Days90, 2)
+            .await
+            .unwrap();
+        let names: Vec<&str> = popular.iter().map(|c| c.formula.as_str()).collect();
+        assert_eq!(names, ["jq", "wget"]);
+        assert_eq!(popular[1].count, 180_000);
+
+        let mut matches = online.search_formulas("j", true).await.unwrap();
+        online
+            .annotate_popularity(&mut matches, AnalyticsPeriod::Days90)
+            .await;
+        let installs: Vec<(&str, Option<u64>)> = matches
+            .iter()
+            .map(|m| (m.name.as_str(), m.installs))
+            .collect();
+        assert_eq!(
+            installs,
+            [("jless", None), ("jq", Some(250_000)), ("yq", Some(40_000))]
+        );
+
+        // Without analytics, results are simply left unannotated.
+        let offline_tmp = TempDir::new().unwrap();
+        let mut offline = installer(offline_tmp.path(), NO_API);
+        offline.api_client = offline
+            .api_client
+            .with_analytics_base_url("http://127.0.0.1:1/analytics".to_string());
+        offline
+            .annotate_popularity(&mut matches[..1], AnalyticsPeriod::Days30)
+            .await;
+        assert_eq!(matches[0].installs, None);
+    }
+
+    #[tokio::test]
+    async fn licenses_come_from_stored_metadata_offline() {
+        let tmp = TempDir::new().unwrap();
+        // Nothing listens on port 1.
+        let mut installer = installer(tmp.path(), NO_API);
+        let jq = index()[0].to_string();
+        let (record, _) = super::super::formulas::formula_records(&format!("[{jq}]"))
+            .unwrap()
+            .remove(0);
+        installer.db.store_formula(&record).unwrap();
+        for name in ["jq", "local-build"] {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install(name, "1.0", name).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let formula = installer.stored_formula("jq").unwrap();
+        assert_eq!(formula.homepage.as_deref(), Some("https://example.com/jq"));
+
+        let licenses = installer.installed_licenses().await.unwrap();
+        let found: Vec<(&str, Option<&str>)> = licenses
+            .iter()
+            .map(|l| (l.keg.name.as_str(), l.license.as_deref()))
+            .collect();
+        assert_eq!(found, [("jq", Some("MIT")), ("local-build", None)]);
+
+        let matches = installer.search_formulas("jq", false).await.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].version, "1.0");
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use zb_core::{Error, ServiceDefinition};
+
+use super::Installer;
+
+/// The variable the dynamic loader searches for libraries. On macOS the
+/// fallback path, so it never shadows the system's own libraries.
+const LIBRARY_PATH_VAR: &str = if cfg!(target_os = "macos") {
+    "DYLD_FALLBACK_LIBRARY_PATH"
+} else {
+    "LD_LIBRARY_PATH"
+};
+
+/// Searched after the kegs and the prefix, like launchd's default PATH.
+const SYSTEM_PATH: [&str; 4] = ["/usr/bin", "/bin", "/usr/sbin", "/sbin"];
+
+impl Installer {
+    /// The service the installed formula `name` declares, with `PATH` and
+    /// the library search path pointing at its keg and those of its
+    /// dependencies. Variables the formula sets itself are kept.
+    pub async fn service_definition(&self, name: &str) -> Result<ServiceDefinition, Error> {
+        let Some(keg) = self.db.get_installed(name) else {
+            return Err(Error::NotInstalled {
+                name: name.to_string(),
+            });
+        };
+        let formula = self.api_client.get_formula(name).await?;
+        let mut definition =
+            ServiceDefinition::for_formula(&formula, &self.prefix)?.ok_or_else(|| {
+                Error::ServiceError {
+                    message: format!("{name} does not define a service"),
+                }
+            })?;
+
+        let mut kegs = vec![self.installed_keg_path(&keg)];
+        for dep in self.installed_dependencies_of(name)? {
+            kegs.push(self.installed_keg_path(&dep));
+        }
+        for (key, value) in keg_environment(&kegs, &self.prefix) {
+            definition.environment_variables.entry(key).or_insert(value);
+        }
+        Ok(definition)
+    }
+}
+
+/// `PATH` and the library search path covering the `bin`, `sbin` and `lib`
+/// directories `kegs` actually have, in order.
+fn keg_environment(kegs: &[PathBuf], prefix: &Path) -> BTreeMap<String, String> {
+    let existing = |dirs: &[&str]| -> Vec<PathBuf> {
+        kegs.iter()
+            .flat_map(|keg| dirs.iter().map(move |dir| keg.join(dir)))
+            .filter(|dir| dir.is_dir())
+            .collect()
+    };
+    let join = |dirs: Vec<PathBuf>| {
+        dirs.iter()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join(":")
+    };
+
+    let mut path = existing(&["bin", "sbin"]);
+    path.extend([prefix.join("bin"), prefix.join("sbin")]);
+    path.extend(SYSTEM_PATH.map(PathBuf::from));
+    let mut environment = BTreeMap::from([("PATH".to_string(), join(path))]);
+
+    let libs = existing(&["lib"]);
+    if !libs.is_empty() {
+        environment.insert(LIBRARY_PATH_VAR.to_string(), join(libs));
+    }
+    environment
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn keg_environment_covers_existing_dirs_in_dependency_order() {
+        let tmp = TempDir::new().unwrap();
+        let postgresql = tmp.path().join("cellar/postgresql/17.0");
+        let icu = tmp.path().join("cellar/icu4c/76.1");
+        let tzdata = tmp.path().join("cellar/tzdata/2024a");
+        for dir in [
+            postgresql.join("bin"),
+            postgresql.join("lib"),
+            icu.join("sbin"),
+            icu.join("lib"),
+            tzdata.join("share"),
+        ] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let prefix = Path::new("/opt/zerobrew/prefix");
+
+        let environment = keg_environment(&[postgresql.clone(), icu.clone(), tzdata], prefix);
+        assert_eq!(
+            environment["PATH"],
+            format!(
+                "{}:{}:/opt/zerobrew/prefix/bin:/opt/zerobrew/prefix/sbin:/usr/bin:/bin:/usr/sbin:/sbin",
+                postgresql.join("bin").display(),
+                icu.join("sbin").display()
+            )
+        );
+        assert_eq!(
+            environment[LIBRARY_PATH_VAR],
+            format!(
+                "{}:{}",
+                postgresql.join("lib").display(),
+                icu.join("lib").display()
+            )
+        );
+        assert_eq!(environment.len(), 2);
+    }
+
+    #[test]
+    fn keg_environment_skips_library_path_without_libs() {
+        let tmp = TempDir::new().unwrap();
+        let environment = keg_environment(&[tmp.path().join("keg")], Path::new("/prefix"));
+        assert_eq!(
+            environment["PATH"],
+            "/prefix/bin:/prefix/sbin:/usr/bin:/bin:/usr/sbin:/sbin"
+        );
+        assert!(!environment.contains_key(LIBRARY_PATH_VAR));
+    }
+}
@@ -0,0 +1,221 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use zb_core::{Error, formula_token};
+
+use super::Installer;
+
+/// Directories Homebrew links its commands into.
+const HOMEBREW_BIN_DIRS: &[&str] = &[
+    "/opt/homebrew/bin",
+    "/usr/local/bin",
+    "/home/linuxbrew/.linuxbrew/bin",
+];
+
+/// Directories the operating system's own commands live in.
+const SYSTEM_BIN_DIRS: &[&str] = &["/usr/bin", "/bin", "/usr/sbin", "/sbin"];
+
+/// A command in `prefix/bin` that another directory on `PATH` also
+/// provides, such as a zerobrew `curl` next to `/usr/bin/curl`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowedCommand {
+    pub command: String,
+    pub prefix_path: PathBuf,
+    /// The first other copy on `PATH`.
+    pub other_path: PathBuf,
+    /// Whether the zerobrew copy comes first on `PATH`, and so is the one
+    /// that runs. False as well when `prefix/bin` is not on `PATH`.
+    pub prefix_wins: bool,
+}
+
+impl ShadowedCommand {
+    /// Where the other copy comes from, when it is a well-known place.
+    /// Homebrew's copies are links into its Cellar.
+    pub fn other_source(&self) -> Option<&'static str> {
+        let dir = self.other_path.parent()?;
+        if HOMEBREW_BIN_DIRS.iter().any(|d| dir == Path::new(d))
+            && fs::canonicalize(&self.other_path)
+                .is_ok_and(|target| target.components().any(|c| c.as_os_str() == "Cellar"))
+        {
+            Some("Homebrew")
+        } else if SYSTEM_BIN_DIRS.iter().any(|d| dir == Path::new(d)) {
+            Some("the system")
+        } else {
+            None
+        }
+    }
+
+    /// The copy that runs when the command is typed.
+    pub fn winner(&self) -> &Path {
+        if self.prefix_wins {
+            &self.prefix_path
+        } else {
+            &self.other_path
+        }
+    }
+}
+
+impl Installer {
+    /// Commands in `prefix/bin` that another directory on `path` (a
+    /// `PATH`-style list) also provides, sorted by command.
+    pub fn shadowed_commands(&self, path: &OsStr) -> Result<Vec<ShadowedCommand>, Error> {
+        let bin = self.prefix.join("bin");
+        let commands = match fs::read_dir(&bin) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        Ok(self.shadowing(commands, path))
+    }
+
+    /// Like [`Installer::shadowed_commands`], for the commands the
+    /// installed formulas `names` link into `prefix/bin`.
+    pub fn shadowed_commands_of(
+        &self,
+        names: &[String],
+        path: &OsStr,
+    ) -> Result<Vec<ShadowedCommand>, Error> {
+        let bin = self.prefix.join("bin");
+        let mut commands = Vec::new();
+        for name in names {
+            let Some(keg) = self.db.get_installed(name) else {
+                continue;
+            };
+            let keg_path = self
+                .installed_cellar(name)
+                .keg_path(formula_token(name), &keg.version);
+            for file in self.linker.collect_linked_files(&keg_path)? {
+                if file.link_path.parent() == Some(bin.as_path())
+                    && let Some(command) = file.link_path.file_name()
+                {
+                    commands.push(command.to_string_lossy().into_owned());
+                }
+            }
+        }
+        Ok(self.shadowing(commands, path))
+    }
+
+    fn shadowing(&self, mut commands: Vec<String>, path: &OsStr) -> Vec<ShadowedCommand> {
+        commands.sort();
+        commands.dedup();
+        let bin = self.prefix.join("bin");
+        let canonical_bin = fs::canonicalize(&bin).unwrap_or_else(|_| bin.clone());
+        let dirs: Vec<PathBuf> = std::env::split_paths(path)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+        let prefix_position = dirs
+            .iter()
+            .position(|dir| fs::canonicalize(dir).is_ok_and(|dir| dir == canonical_bin));
+
+        let mut shadowed = Vec::new();
+        for command in commands {
+            let prefix_path = bin.join(&command);
+            let other = dirs.iter().enumerate().find_map(|(position, dir)| {
+                if Some(position) == prefix_position
+                    || fs::canonicalize(dir).is_ok_and(|dir| dir == canonical_bin)
+                {
+                    return None;
+                }
+                let candidate = dir.join(&command);
+                is_executable(&candidate).then_some((position, candidate))
+            });
+            if let Some((position, other_path)) = other {
+                shadowed.push(ShadowedCommand {
+                    command,
+                    prefix_path,
+                    other_path,
+                    prefix_wins: prefix_position.is_some_and(|p| p < position),
+                });
+            }
+        }
+        shadowed
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::installer::install::test_support::{NO_API, installer};
+
+    fn write_command(dir: &Path, name: &str) {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn reports_which_copy_runs() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let installer = installer(tmp.path(), NO_API);
+        let system = tmp.path().join("system");
+        let late = tmp.path().join("late");
+        for command in ["curl", "git", "jq"] {
+            write_command(&prefix.join("bin"), command);
+        }
+        write_command(&system, "curl");
+        write_command(&late, "git");
+        // Not executable, so it cannot shadow anything.
+        fs::write(system.join("jq"), "").unwrap();
+
+        let path = std::env::join_paths([&system, &prefix.join("bin"), &late]).unwrap();
+        let shadowed = installer.shadowed_commands(&path).unwrap();
+        assert_eq!(
+            shadowed,
+            [
+                ShadowedCommand {
+                    command: "curl".to_string(),
+                    prefix_path: prefix.join("bin/curl"),
+                    other_path: system.join("curl"),
+                    prefix_wins: false,
+                },
+                ShadowedCommand {
+                    command: "git".to_string(),
+                    prefix_path: prefix.join("bin/git"),
+                    other_path: late.join("git"),
+                    prefix_wins: true,
+                },
+            ]
+        );
+        assert_eq!(shadowed[0].winner(), system.join("curl"));
+        assert_eq!(shadowed[1].winner(), prefix.join("bin/git"));
+        assert_eq!(shadowed[0].other_source(), None);
+
+        // Without prefix/bin on PATH the other copy always runs.
+        let path = std::env::join_paths([&late]).unwrap();
+        let shadowed = installer.shadowed_commands(&path).unwrap();
+        assert_eq!(shadowed.len(), 1);
+        assert!(!shadowed[0].prefix_wins);
+
+        assert!(
+            installer
+                .shadowed_commands(&OsString::new())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn system_copies_are_named() {
+        let shadowed = ShadowedCommand {
+            command: "curl".to_string(),
+            prefix_path: PathBuf::from("/opt/zerobrew/prefix/bin/curl"),
+            other_path: PathBuf::from("/usr/bin/curl"),
+            prefix_wins: true,
+        };
+        assert_eq!(shadowed.other_source(), Some("the system"));
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+
+use tracing::debug;
+use zb_core::InstallMethod;
+
+use crate::cellar::materialize::disk_usage;
+
+use super::{InstallPlan, Installer, PlannedInstall};
+
+/// Rough ratio of a keg's size on disk to its gzipped bottle, used until
+/// the bottle has been unpacked into the store.
+const INSTALL_SIZE_RATIO: u64 = 3;
+
+/// What executing a plan is expected to download and write to disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlanSizes {
+    /// Bottle bytes still to be downloaded.
+    pub download_bytes: u64,
+    /// Bottle bytes already in the download cache.
+    pub cached_bytes: u64,
+    /// Estimated disk usage of the installed kegs.
+    pub install_bytes: u64,
+    /// Items whose download size is unknown, such as source builds.
+    pub unknown: usize,
+}
+
+impl InstallPlan {
+    pub fn sizes(&self) -> PlanSizes {
+        let mut sizes = PlanSizes::default();
+        for item in &self.items {
+            match (item.cached, item.download_size) {
+                (true, size) => sizes.cached_bytes += size.unwrap_or(0),
+                (false, Some(size)) => sizes.download_bytes += size,
+                (false, None) => sizes.unknown += 1,
+            }
+            sizes.install_bytes += item.install_size.unwrap_or(0);
+        }
+        sizes
+    }
+}
+
+impl Installer {
+    /// Fill in the download and install size of each bottle. Bottles already
+    /// in the download cache or store are measured on disk, the rest with
+    /// parallel HEAD requests; a failed request only leaves the size unknown.
+    pub(super) async fn measure_bottles(&self, items: &mut [PlannedInstall]) {
+        let mut remote = Vec::new();
+        for (index, item) in items.iter_mut().enumerate() {
+            let InstallMethod::Bottle(bottle) = &item.method else {
+                continue;
+            };
+            let blob = self.downloader.blob_cache().blob_path(&bottle.sha256);
+            item.download_size = fs::metadata(&blob).ok().map(|m| m.len());
+            if self.store.has_entry(&bottle.sha256) {
+                item.cached = true;
+                item.install_size = disk_usage(&self.store.entry_path(&bottle.sha256)).ok();
+            } else if item.download_size.is_some() {
+                item.cached = true;
+            } else {
+                remote.push((index, bottle.url.clone()));
+            }
+        }
+
+        let lengths = futures::future::join_all(
+            remote
+                .iter()
+                .map(|(_, url)| self.downloader.content_length(url)),
+        )
+        .await;
+        for ((index, url), length) in remote.into_iter().zip(lengths) {
+            match length {
+                Ok(length) => items[index].download_size = length,
+                Err(e) => debug!(%url, error = %e, "could not determine bottle size"),
+            }
+        }
+
+        for item in items {
+            if item.install_size.is_none() {
+                item.install_size = item.download_size.map(|s| s * INSTALL_SIZE_RATIO);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::installer::install::test_support::*;
+    use crate::storage::blob::BlobCache;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn plan_reports_download_and_cached_sizes() {
+        let server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        let mut bottles = Vec::new();
+        for name in ["app", "lib"] {
+            let bottle = create_bottle_tarball(name);
+            let deps = if name == "app" { vec!["lib"] } else { vec![] };
+            let formula = serde_json::json!({
+                "name": name,
+                "versions": { "stable": "1.0.0" },
+                "dependencies": deps,
+                "bottle": { "stable": { "files": { tag: {
+                    "url": format!("{}/bottles/{name}.tar.gz", server.uri()),
+                    "sha256": sha256_hex(&bottle),
+                } } } }
+            });
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+                .mount(&server)
+                .await;
+            Mock::given(method("HEAD"))
+                .and(path(format!("/bottles/{name}.tar.gz")))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("content-length", bottle.len().to_string().as_str()),
+                )
+                .mount(&server)
+                .await;
+            bottles.push(bottle);
+        }
+
+        let tmp = TempDir::new().unwrap();
+        let installer = installer(tmp.path(), &server.uri());
+        let blob_cache = BlobCache::new(&tmp.path().join("zerobrew/cache")).unwrap();
+        // lib's bottle is already downloaded.
+        fs::write(blob_cache.blob_path(&sha256_hex(&bottles[1])), &bottles[1]).unwrap();
+
+        let plan = installer.plan(&["app".to_string()]).await.unwrap();
+        let app = plan.items.iter().find(|i| i.install_name == "app").unwrap();
+        let lib = plan.items.iter().find(|i| i.install_name == "lib").unwrap();
+        assert!(!app.cached);
+        assert_eq!(app.download_size, Some(bottles[0].len() as u64));
+        assert!(lib.cached);
+        assert_eq!(lib.download_size, Some(bottles[1].len() as u64));
+
+        let sizes = plan.sizes();
+        assert_eq!(sizes.download_bytes, bottles[0].len() as u64);
+        assert_eq!(sizes.cached_bytes, bottles[1].len() as u64);
+        assert_eq!(
+            sizes.install_bytes,
+            (bottles[0].len() + bottles[1].len()) as u64 * INSTALL_SIZE_RATIO
+        );
+        assert_eq!(sizes.unknown, 0);
+    }
+}
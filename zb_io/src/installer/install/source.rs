@@ -1,14 +1,28 @@
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use tracing::warn;
+use tracing::{debug, warn};
 use zb_core::{BuildPlan, Error};
 
+use crate::cellar::materialize::disk_usage;
 use crate::progress::InstallProgress;
+use crate::storage::db::InstallStat;
 
+use super::files::keg_manifest;
+use super::stats::millis;
+use super::timings::InstallPhase;
 use super::{Installer, PlannedInstall, dependency_cellar_path};
 
 impl Installer {
+    /// Build requested formulas from the latest commit of their `head`
+    /// repository instead of installing a release. Such kegs are versioned
+    /// `HEAD-<short sha>`.
+    pub fn with_head(mut self, head: bool) -> Self {
+        self.head = head;
+        self
+    }
+
     pub(super) async fn install_from_source(
         &mut self,
         item: &PlannedInstall,
@@ -19,6 +33,7 @@ impl Installer {
         let install_name = &item.install_name;
         let formula_name = &item.formula.name;
         let version = item.formula.effective_version();
+        let started = Instant::now();
 
         let ruby_source_path =
             item.formula
@@ -35,10 +50,14 @@ impl Installer {
             .as_ref()
             .map(|checksum| checksum.sha256.as_str());
 
-        let formula_rb = self
-            .api_client
-            .fetch_formula_rb(ruby_source_path, &cache_dir, formula_rb_checksum)
-            .await?;
+        let formula_rb = match self.local_formula(install_name) {
+            Some(local) => local.path.clone(),
+            None => {
+                self.api_client
+                    .fetch_formula_rb(ruby_source_path, &cache_dir, formula_rb_checksum)
+                    .await?
+            }
+        };
 
         let mut installed_deps = std::collections::HashMap::new();
         for dep_name in &build_plan.runtime_dependencies {
@@ -57,10 +76,12 @@ impl Installer {
             Self::backup_existing_source_keg(&keg_path, formula_name, &version)?;
 
         let executor = crate::build::BuildExecutor::new(self.prefix.clone());
-        if let Err(build_err) = executor
+        let build_started = Instant::now();
+        let built = executor
             .execute(build_plan, &formula_rb, &installed_deps)
-            .await
-        {
+            .await;
+        self.report_phase(install_name, InstallPhase::Build, build_started.elapsed());
+        if let Err(build_err) = built {
             if let Some(backup_path) = previous_keg_backup.as_ref() {
                 Self::restore_source_keg_from_backup(
                     &keg_path,
@@ -81,12 +102,48 @@ impl Installer {
         });
 
         let store_key = format!("source:{formula_name}:{version}");
+        self.register_keg(item, &store_key, "source", started, link, report)
+    }
+
+    /// Record the keg of `item`, staged in the cellar outside the store
+    /// pipeline, as installed from `store_key`, then link it.
+    pub(super) fn register_keg(
+        &mut self,
+        item: &PlannedInstall,
+        store_key: &str,
+        method: &'static str,
+        started: Instant,
+        link: bool,
+        report: &impl Fn(InstallProgress),
+    ) -> Result<(), Error> {
+        let install_name = &item.install_name;
+        let formula_name = &item.formula.name;
+        let version = item.formula.effective_version();
+        let keg_path = self.cellar.keg_path(formula_name, &version);
+
+        self.write_receipt(item, &keg_path);
+        self.write_env_hint(item, &keg_path);
+        let manifest = keg_manifest(&keg_path)
+            .inspect_err(
+                |e| debug!(formula = %install_name, error = %e, "failed to read keg files"),
+            )
+            .unwrap_or_default();
 
         let tx = self.db.transaction().inspect_err(|_| {
             Self::cleanup_materialized(&self.cellar, formula_name, &version);
         })?;
 
-        if let Err(e) = tx.record_install(install_name, &version, &store_key) {
+        if let Err(e) = tx
+            .record_install(install_name, &version, store_key)
+            .and_then(|()| {
+                tx.record_keg_metadata(
+                    install_name,
+                    disk_usage(&keg_path).ok(),
+                    &item.formula.runtime_dependencies(),
+                )
+            })
+            .and_then(|()| tx.record_keg_manifest(install_name, &version, &manifest))
+        {
             drop(tx);
             Self::cleanup_materialized(&self.cellar, formula_name, &version);
             return Err(e);
@@ -107,36 +164,17 @@ impl Installer {
             report(InstallProgress::LinkStarted {
                 name: formula_name.clone(),
             });
-            match self.linker.link_keg(&keg_path) {
-                Ok(files) => {
+            let link_started = Instant::now();
+            let linked =
+                self.link_installed_keg(install_name, &version, &keg_path, self.link_strategy);
+            self.report_phase(install_name, InstallPhase::Link, link_started.elapsed());
+            match linked {
+                Ok(_) => {
                     report(InstallProgress::LinkCompleted {
                         name: formula_name.clone(),
                     });
-                    if !files.is_empty()
-                        && let Ok(tx) = self.db.transaction()
-                    {
-                        let mut ok = true;
-                        for linked in &files {
-                            if tx
-                                .record_linked_file(
-                                    install_name,
-                                    &version,
-                                    &linked.link_path.to_string_lossy(),
-                                    &linked.target_path.to_string_lossy(),
-                                )
-                                .is_err()
-                            {
-                                ok = false;
-                                break;
-                            }
-                        }
-                        if ok {
-                            let _ = tx.commit();
-                        }
-                    }
                 }
                 Err(e) => {
-                    let _ = self.linker.unlink_keg(&keg_path);
                     report(InstallProgress::InstallCompleted {
                         name: formula_name.clone(),
                     });
@@ -155,6 +193,16 @@ impl Installer {
             });
         }
 
+        self.record_install_stat(InstallStat {
+            name: install_name.clone(),
+            method,
+            duration_ms: millis(started.elapsed()),
+            cached: false,
+            download_bytes: None,
+            download_ms: None,
+            relocation_ms: None,
+        });
+
         report(InstallProgress::InstallCompleted {
             name: formula_name.clone(),
         });
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use tracing::debug;
+use zb_core::Error;
+
+use crate::storage::db::{InstallStat, StatsSummary};
+
+use super::Installer;
+
+/// Installs `stats` lists as the slowest.
+const SLOWEST_INSTALLS: usize = 5;
+
+impl Installer {
+    /// Record how long each install takes, how often bottles come from the
+    /// cache, download throughput and relocation time. The numbers stay in
+    /// the local database; nothing is sent anywhere.
+    pub fn with_stats(mut self, record: bool) -> Self {
+        self.record_stats = record;
+        self
+    }
+
+    pub fn records_stats(&self) -> bool {
+        self.record_stats
+    }
+
+    /// Totals over every install recorded so far.
+    pub fn stats(&self) -> Result<StatsSummary, Error> {
+        self.db.install_stats(SLOWEST_INSTALLS)
+    }
+
+    /// Forget the recorded installs, returning how many there were.
+    pub fn clear_stats(&self) -> Result<usize, Error> {
+        self.db.clear_install_stats()
+    }
+
+    /// Best effort: stats are informational, so failures are only logged.
+    pub(super) fn record_install_stat(&self, stat: InstallStat) {
+        if !self.record_stats {
+            return;
+        }
+        if let Err(e) = self.db.record_install_stat(&stat) {
+            debug!(formula = %stat.name, error = %e, "failed to record install stats");
+        }
+    }
+}
+
+pub(super) fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::installer::install::test_support::{
+        create_bottle_tarball, get_test_bottle_tag, installer, sha256_hex,
+    };
+
+    #[tokio::test]
+    async fn installs_are_recorded_only_when_enabled() {
+        let server = MockServer::start().await;
+        let bottle = create_bottle_tarball("tool");
+        let sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+        let bottle_path = format!("/bottles/tool-1.0.0.{tag}.bottle.tar.gz");
+        let formula = serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}{bottle_path}", server.uri()),
+                "sha256": sha,
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/tool.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+        let tool = ["tool".to_string()];
+
+        installer.install(&tool, true).await.unwrap();
+        installer.uninstall("tool").unwrap();
+        assert_eq!(installer.stats().unwrap().installs, 0);
+
+        let mut installer = installer.with_stats(true);
+        installer.install(&tool, true).await.unwrap();
+
+        let stats = installer.stats().unwrap();
+        assert_eq!(stats.installs, 1);
+        assert_eq!(stats.cache_hits, 1);
+        assert_eq!(stats.slowest[0].0, "tool");
+
+        assert_eq!(installer.clear_stats().unwrap(), 1);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use zb_core::Error;
+
+use super::Installer;
+
+/// An entry of the content-addressable store: a bottle unpacked once and
+/// materialized into every keg installed from it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoreEntry {
+    /// SHA-256 of the bottle the entry was unpacked from.
+    pub key: String,
+    pub path: PathBuf,
+    /// References the database holds, one per installed keg or kept
+    /// generation. Entries at zero are removed by `zb gc`.
+    pub refcount: i64,
+    pub kegs: Vec<StoreKeg>,
+}
+
+/// A keg materialized from a store entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoreKeg {
+    pub name: String,
+    pub version: String,
+    /// False for a generation kept after an upgrade, for rollback.
+    pub installed: bool,
+}
+
+impl Installer {
+    /// Every entry in the store, sorted by key. Read-only: nothing is
+    /// locked, so entries being unpacked right now may be missing.
+    pub fn store_entries(&self) -> Result<Vec<StoreEntry>, Error> {
+        let mut kegs = self.store_kegs()?;
+        let mut keys: Vec<String> = self
+            .store
+            .list_entries()?
+            .into_iter()
+            .filter(|key| !key.starts_with('.'))
+            .collect();
+        keys.sort();
+        Ok(keys
+            .into_iter()
+            .map(|key| {
+                let kegs = kegs.remove(&key).unwrap_or_default();
+                self.describe_store_entry(key, kegs)
+            })
+            .collect())
+    }
+
+    /// The store entry whose key is or starts with `key`, so the short
+    /// keys `zb store ls` prints work too.
+    pub fn store_entry(&self, key: &str) -> Result<StoreEntry, Error> {
+        let mut matches: Vec<String> = self
+            .store
+            .list_entries()?
+            .into_iter()
+            .filter(|entry| !entry.starts_with('.') && entry.starts_with(key))
+            .collect();
+        if key.is_empty() || matches.len() > 1 {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "'{key}' matches {} store entries; give more of the key",
+                    matches.len()
+                ),
+            });
+        }
+        let Some(found) = matches.pop() else {
+            return Err(Error::InvalidArgument {
+                message: format!("no store entry matches '{key}'"),
+            });
+        };
+        let kegs = self.store_kegs()?.remove(&found).unwrap_or_default();
+        Ok(self.describe_store_entry(found, kegs))
+    }
+
+    fn describe_store_entry(&self, key: String, kegs: Vec<StoreKeg>) -> StoreEntry {
+        StoreEntry {
+            path: self.store.entry_path(&key),
+            refcount: self.db.get_store_refcount(&key),
+            kegs,
+            key,
+        }
+    }
+
+    /// Installed kegs and kept generations by the store key they came from.
+    fn store_kegs(&self) -> Result<BTreeMap<String, Vec<StoreKeg>>, Error> {
+        let mut kegs: BTreeMap<String, Vec<StoreKeg>> = BTreeMap::new();
+        for keg in self.db.list_installed()? {
+            kegs.entry(keg.store_key).or_default().push(StoreKeg {
+                name: keg.name,
+                version: keg.version,
+                installed: true,
+            });
+        }
+        for generation in self.db.list_all_generations()? {
+            kegs.entry(generation.store_key)
+                .or_default()
+                .push(StoreKeg {
+                    name: generation.name,
+                    version: generation.version,
+                    installed: false,
+                });
+        }
+        Ok(kegs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+
+    #[tokio::test]
+    async fn store_entries_name_the_kegs_using_them() {
+        let server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("tool");
+        let sha = sha256_hex(&bottle);
+        let formula = serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}/bottles/tool.tar.gz", server.uri()),
+                "sha256": sha,
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/tool.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/tool.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer
+            .install(&["tool".to_string()], false)
+            .await
+            .unwrap();
+
+        let entries = installer.store_entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, sha);
+        assert!(entries[0].path.is_dir());
+        assert_eq!(entries[0].refcount, 1);
+        assert_eq!(
+            entries[0].kegs,
+            [StoreKeg {
+                name: "tool".into(),
+                version: "1.0.0".into(),
+                installed: true,
+            }]
+        );
+
+        assert_eq!(installer.store_entry(&sha[..12]).unwrap(), entries[0]);
+        assert!(installer.store_entry("").is_err());
+        assert!(installer.store_entry("not-a-key").is_err());
+    }
+}
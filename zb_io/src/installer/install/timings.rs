@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::Installer;
+
+/// A step of installing a formula, timed separately so benchmarks can tell
+/// which one a regression comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InstallPhase {
+    /// Fetching metadata and resolving the dependency closure. Timed once
+    /// per plan, under the requested formulas.
+    Resolve,
+    /// Fetching the bottle, including checksum verification, which happens
+    /// as it streams in. Zero for cached bottles.
+    Download,
+    /// Unpacking the bottle into the store, when it was not unpacked while
+    /// downloading.
+    Extract,
+    /// Building a formula from source.
+    Build,
+    /// Copying the keg out of the store into the cellar.
+    Materialize,
+    /// Patching Homebrew placeholders in the keg.
+    Relocate,
+    /// Linking the keg into the prefix.
+    Link,
+}
+
+impl InstallPhase {
+    pub const ALL: [InstallPhase; 7] = [
+        InstallPhase::Resolve,
+        InstallPhase::Download,
+        InstallPhase::Extract,
+        InstallPhase::Build,
+        InstallPhase::Materialize,
+        InstallPhase::Relocate,
+        InstallPhase::Link,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InstallPhase::Resolve => "resolve",
+            InstallPhase::Download => "download",
+            InstallPhase::Extract => "extract",
+            InstallPhase::Build => "build",
+            InstallPhase::Materialize => "materialize",
+            InstallPhase::Relocate => "relocate",
+            InstallPhase::Link => "link",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseTiming {
+    pub formula: String,
+    pub phase: InstallPhase,
+    pub duration: Duration,
+}
+
+pub type PhaseHook = Arc<dyn Fn(PhaseTiming) + Send + Sync>;
+
+impl Installer {
+    /// Call `hook` with the duration of each phase of every install.
+    pub fn with_phase_hook(mut self, hook: PhaseHook) -> Self {
+        self.phase_hook = Some(hook);
+        self
+    }
+
+    pub(super) fn report_phase(&self, formula: &str, phase: InstallPhase, duration: Duration) {
+        if let Some(hook) = &self.phase_hook {
+            hook(PhaseTiming {
+                formula: formula.to_string(),
+                phase,
+                duration,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::{
+        create_bottle_tarball, get_test_bottle_tag, installer, sha256_hex,
+    };
+
+    #[tokio::test]
+    async fn hook_receives_each_phase_of_a_bottle_install() {
+        let server = MockServer::start().await;
+        let bottle = create_bottle_tarball("tool");
+        let sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+        let bottle_path = format!("/bottles/tool-1.0.0.{tag}.bottle.tar.gz");
+        let formula = serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}{bottle_path}", server.uri()),
+                "sha256": sha,
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/tool.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let timings = Arc::new(Mutex::new(Vec::new()));
+        let sink = timings.clone();
+        let mut installer = installer(tmp.path(), &server.uri())
+            .with_phase_hook(Arc::new(move |timing| sink.lock().unwrap().push(timing)));
+
+        installer
+            .install(&["tool".to_string()], true)
+            .await
+            .unwrap();
+
+        let timings = timings.lock().unwrap();
+        let phases: Vec<InstallPhase> = timings.iter().map(|t| t.phase).collect();
+        assert_eq!(
+            phases,
+            vec![
+                InstallPhase::Resolve,
+                InstallPhase::Download,
+                InstallPhase::Extract,
+                InstallPhase::Materialize,
+                InstallPhase::Relocate,
+                InstallPhase::Link,
+            ]
+        );
+        assert!(timings.iter().all(|t| t.formula == "tool"));
+    }
+}
@@ -0,0 +1,193 @@
+use std::fs;
+use std::sync::Arc;
+
+use crate::transcript::{Transcript, TranscriptEvent};
+
+use super::{Installer, PlannedInstall};
+
+/// A recorded event and how the system differs from it now, if it does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayCheck {
+    pub event: TranscriptEvent,
+    pub problem: Option<String>,
+}
+
+impl Installer {
+    /// Record API requests, chosen bottles, relocation decisions and links
+    /// into `transcript`.
+    pub fn with_transcript(mut self, transcript: Arc<Transcript>) -> Self {
+        self.api_client = self.api_client.with_transcript(transcript.clone());
+        self.transcript = Some(transcript);
+        self
+    }
+
+    pub(super) fn record_event(&self, event: TranscriptEvent) {
+        if let Some(transcript) = &self.transcript {
+            transcript.record(event);
+        }
+    }
+
+    pub(super) fn record_installed(&self, item: &PlannedInstall) {
+        self.record_event(TranscriptEvent::Installed {
+            formula: item.install_name.clone(),
+            version: item.formula.effective_version(),
+        });
+    }
+
+    /// Check each event of a transcript against the system as it is now:
+    /// the bottle each keg was installed from, its version, its keg and its
+    /// links. API requests describe nothing on disk and are not checked.
+    pub fn replay_transcript(&self, events: &[TranscriptEvent]) -> Vec<ReplayCheck> {
+        events
+            .iter()
+            .filter(|event| !matches!(event, TranscriptEvent::ApiRequest { .. }))
+            .map(|event| ReplayCheck {
+                problem: self.replay_problem(event),
+                event: event.clone(),
+            })
+            .collect()
+    }
+
+    fn replay_problem(&self, event: &TranscriptEvent) -> Option<String> {
+        match event {
+            TranscriptEvent::ApiRequest { .. } => None,
+            TranscriptEvent::Bottle {
+                formula, sha256, ..
+            } => match self.db.get_installed(formula) {
+                None => Some(format!("{formula} is not installed")),
+                Some(keg) if keg.store_key != *sha256 => Some(format!(
+                    "{formula} is installed from bottle {} instead",
+                    short_sha(&keg.store_key)
+                )),
+                Some(_) => None,
+            },
+            TranscriptEvent::Relocation { keg, .. } => {
+                (!keg.is_dir()).then(|| format!("keg {} no longer exists", keg.display()))
+            }
+            TranscriptEvent::Link { link, target, .. } => match fs::read_link(link) {
+                Err(_) => Some(format!("{} is not a symlink", link.display())),
+                Ok(current) if current != *target => Some(format!(
+                    "{} points to {} instead",
+                    link.display(),
+                    current.display()
+                )),
+                Ok(_) => None,
+            },
+            TranscriptEvent::Installed { formula, version } => {
+                match self.db.get_installed(formula) {
+                    None => Some(format!("{formula} is no longer installed")),
+                    Some(keg) if keg.version != *version => {
+                        Some(format!("{formula} {} is installed instead", keg.version))
+                    }
+                    Some(_) => None,
+                }
+            }
+        }
+    }
+}
+
+fn short_sha(sha256: &str) -> &str {
+    &sha256[..sha256.len().min(12)]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn recorded_installs_replay_until_the_system_changes() {
+        let server = MockServer::start().await;
+        let tag = get_test_bottle_tag();
+        let bottle = create_bottle_tarball("tool");
+        let sha = sha256_hex(&bottle);
+        let bottle_url = format!("{}/bottles/tool.tar.gz", server.uri());
+        let formula = serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: { "url": bottle_url, "sha256": sha } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/tool.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/tool.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let buffer = Buffer::default();
+        let mut installer = installer(tmp.path(), &server.uri())
+            .with_transcript(Arc::new(Transcript::to_writer(buffer.clone())));
+        installer
+            .install(&["tool".to_string()], true)
+            .await
+            .unwrap();
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<TranscriptEvent> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(events.contains(&TranscriptEvent::ApiRequest {
+            url: format!("{}/formula/tool.json", server.uri()),
+        }));
+        assert!(events.contains(&TranscriptEvent::Bottle {
+            formula: "tool".into(),
+            url: bottle_url,
+            sha256: sha,
+        }));
+        assert!(events.contains(&TranscriptEvent::Installed {
+            formula: "tool".into(),
+            version: "1.0.0".into(),
+        }));
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, TranscriptEvent::Relocation { .. }))
+        );
+        let link = prefix.join("bin/tool");
+        assert!(
+            events
+                .iter()
+                .any(|e| matches!(e, TranscriptEvent::Link { link: l, .. } if *l == link))
+        );
+
+        let checks = installer.replay_transcript(&events);
+        assert!(!checks.is_empty());
+        assert!(checks.iter().all(|c| c.problem.is_none()), "{checks:?}");
+
+        fs::remove_file(&link).unwrap();
+        let problems: Vec<String> = installer
+            .replay_transcript(&events)
+            .into_iter()
+            .filter_map(|c| c.problem)
+            .collect();
+        assert_eq!(problems, [format!("{} is not a symlink", link.display())]);
+    }
+}
@@ -1,20 +1,61 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rayon::prelude::*;
 use zb_core::{Error, formula_token};
 
+use crate::cellar::materialize::disk_usage;
+use crate::cellar::relocation::RelocationCache;
+use crate::storage::store::{DedupeSummary, Store};
+
 use super::Installer;
+use super::history::OperationKind;
+
+/// Which store entries no keg references `Installer::gc` keeps. The
+/// default keeps none.
+#[derive(Debug, Clone, Default)]
+pub struct GcOptions {
+    /// Keep entries that lost their last reference less than this long ago.
+    pub max_age: Option<Duration>,
+    /// Keep the entries of this many versions of each formula, newest
+    /// first, however old.
+    pub keep_versions: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct GcSummary {
+    /// Keys of the removed store entries, sorted.
+    pub removed: Vec<String>,
+    /// Unreferenced entries kept by the `GcOptions`.
+    pub kept: usize,
+    pub reclaimed_bytes: u64,
+}
 
 impl Installer {
     pub fn uninstall(&mut self, name: &str) -> Result<(), Error> {
+        let before = self.installed_versions();
+        let result = self.uninstall_keg(name);
+        self.record_operation(OperationKind::Uninstall, before, &result, None);
+        result
+    }
+
+    /// `uninstall` without recording it in the history.
+    pub(super) fn uninstall_keg(&mut self, name: &str) -> Result<(), Error> {
         let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
         })?;
-        self.uninstall_by_version(name, &installed.version)
+        self.uninstall_by_version(name, &installed.version)?;
+        self.prune_generations(name, 0)
     }
 
     pub fn uninstall_by_version(&mut self, name: &str, version: &str) -> Result<(), Error> {
         let keg_name = formula_token(name);
 
-        let keg_path = self.cellar.keg_path(keg_name, version);
-        self.linker.unlink_keg(&keg_path)?;
+        let cellar = self.installed_cellar(name);
+        let keg_path = cellar.keg_path(keg_name, version);
+        self.unlink_installed_keg(name, &keg_path)?;
+        self.unlink_from_profiles(name, version, &keg_path);
 
         {
             let tx = self.db.transaction()?;
@@ -22,23 +63,131 @@ impl Installer {
             tx.commit()?;
         }
 
-        self.cellar.remove_keg(keg_name, version)?;
+        cellar.remove_keg(keg_name, version)?;
+        self.sync_compat_roots();
 
         Ok(())
     }
 
-    pub fn gc(&mut self) -> Result<Vec<String>, Error> {
-        let unreferenced = self.db.get_unreferenced_store_keys()?;
-        let mut removed = Vec::new();
+    /// Remove store entries no keg references, after first collecting
+    /// generations older than the retention window. `options` keeps some
+    /// unreferenced entries around, so a reinstall can reuse them.
+    pub fn gc(&mut self, options: &GcOptions) -> Result<GcSummary, Error> {
+        let before = self.installed_versions();
+        let result = self.collect_garbage(options);
+        let detail = result.as_ref().ok().map(|summary| {
+            format!(
+                "removed {} store entries, reclaiming {} bytes",
+                summary.removed.len(),
+                summary.reclaimed_bytes
+            )
+        });
+        self.record_operation(OperationKind::Gc, before, &result, detail);
+        result
+    }
+
+    fn collect_garbage(&mut self, options: &GcOptions) -> Result<GcSummary, Error> {
+        self.collect_expired_generations()?;
+        let cutoff = options
+            .max_age
+            .and_then(|age| SystemTime::now().checked_sub(age))
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(i64::MAX, |d| d.as_secs() as i64);
+
+        let mut unreferenced: Vec<(String, i64)> = self
+            .db
+            .get_unreferenced_store_refs()?
+            .into_iter()
+            .map(|r| {
+                let released_at = r
+                    .released_at
+                    .unwrap_or_else(|| self.store_entry_mtime(&r.store_key));
+                (r.store_key, released_at)
+            })
+            .collect();
+        // Newest first, so the versions kept per formula are the latest.
+        unreferenced.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut summary = GcSummary::default();
+        let mut kept_per_formula: HashMap<String, usize> = HashMap::new();
+        let mut doomed = Vec::new();
+        for (store_key, released_at) in unreferenced {
+            let formula = self.store.entry_formula(&store_key);
+            let kept = formula
+                .as_ref()
+                .map_or(0, |f| kept_per_formula.get(f).copied().unwrap_or(0));
+            if released_at > cutoff || (formula.is_some() && kept < options.keep_versions) {
+                if let Some(formula) = formula {
+                    *kept_per_formula.entry(formula).or_default() += 1;
+                }
+                summary.kept += 1;
+            } else {
+                doomed.push(store_key);
+            }
+        }
 
-        for store_key in unreferenced {
-            self.store.remove_entry(&store_key)?;
-            self.db.delete_store_ref(&store_key)?;
-            removed.push(store_key);
+        let store = &self.store;
+        let relocations = self.cellar.relocation_cache();
+        let results: Vec<(String, Result<u64, Error>)> = doomed
+            .into_par_iter()
+            .map(|store_key| {
+                let size = disk_usage(&store.entry_path(&store_key)).unwrap_or(0);
+                let result = remove_entry_and_relocations(store, relocations, &store_key);
+                (store_key, result.map(|()| size))
+            })
+            .collect();
+
+        let mut first_error = None;
+        for (store_key, result) in results {
+            match result {
+                Ok(size) => {
+                    self.db.delete_store_ref(&store_key)?;
+                    summary.reclaimed_bytes += size;
+                    summary.removed.push(store_key);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        summary.removed.sort();
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(summary),
         }
+    }
 
-        Ok(removed)
+    /// When a store entry was unpacked, for entries released before release
+    /// times were recorded; 0 if unknown.
+    fn store_entry_mtime(&self, store_key: &str) -> i64 {
+        fs::metadata(self.store.entry_path(store_key))
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs() as i64)
     }
+
+    /// Remove a store entry together with the relocations recorded for it.
+    pub(super) fn remove_store_entry(&self, store_key: &str) -> Result<(), Error> {
+        remove_entry_and_relocations(&self.store, self.cellar.relocation_cache(), store_key)
+    }
+
+    /// Hardlink identical files across store entries to reclaim space.
+    pub fn dedupe_store(&mut self) -> Result<DedupeSummary, Error> {
+        self.store.dedupe()
+    }
+}
+
+fn remove_entry_and_relocations(
+    store: &Store,
+    relocations: Option<&RelocationCache>,
+    store_key: &str,
+) -> Result<(), Error> {
+    store.remove_entry(store_key)?;
+    if let Some(relocations) = relocations {
+        relocations.remove(store_key)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -49,13 +198,9 @@ mod tests {
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
-    use crate::cellar::Cellar;
+    use super::GcOptions;
     use crate::installer::install::test_support::*;
     use crate::network::api::ApiClient;
-    use crate::storage::blob::BlobCache;
-    use crate::storage::db::Database;
-    use crate::storage::store::Store;
-    use crate::{Installer, Linker};
 
     #[tokio::test]
     async fn uninstall_cleans_everything() {
@@ -104,27 +249,9 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+        let prefix = tmp.path().join("prefix");
+
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["uninstallme".to_string()], true)
@@ -132,6 +259,13 @@ mod tests {
             .unwrap();
 
         assert!(installer.is_installed("uninstallme"));
+        let size = installer.get_installed("uninstallme").unwrap().size_bytes;
+        assert!(size.is_some_and(|s| s > 0));
+        let receipt = installer.read_receipt("uninstallme").unwrap().unwrap();
+        assert!(receipt.installed_on_request);
+        assert!(receipt.poured_from_bottle);
+        assert_eq!(receipt.sha256.as_deref(), Some(bottle_sha.as_str()));
+        assert_eq!(receipt.bottle_tag.as_deref(), Some(tag));
         assert!(root.join("cellar/uninstallme/1.0.0").exists());
         assert!(prefix.join("bin/uninstallme").exists());
 
@@ -186,27 +320,8 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["gctest".to_string()], true)
@@ -219,9 +334,9 @@ mod tests {
 
         assert!(root.join("store").join(&bottle_sha).exists());
 
-        let removed = installer.gc().unwrap();
-        assert_eq!(removed.len(), 1);
-        assert_eq!(removed[0], bottle_sha);
+        let summary = installer.gc(&GcOptions::default()).unwrap();
+        assert_eq!(summary.removed, std::slice::from_ref(&bottle_sha));
+        assert!(summary.reclaimed_bytes > 0);
 
         assert!(!root.join("store").join(&bottle_sha).exists());
         assert!(
@@ -277,27 +392,8 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.clone(),
-            root.join("locks"),
-        );
+
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["keepme".to_string()], true)
@@ -306,8 +402,8 @@ mod tests {
 
         assert!(root.join("store").join(&bottle_sha).exists());
 
-        let removed = installer.gc().unwrap();
-        assert!(removed.is_empty());
+        let summary = installer.gc(&GcOptions::default()).unwrap();
+        assert!(summary.removed.is_empty());
 
         assert!(root.join("store").join(&bottle_sha).exists());
     }
@@ -351,28 +447,10 @@ end
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+        installer.api_client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
             .unwrap()
             .with_tap_raw_base_url(mock_server.uri());
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.to_path_buf(),
-            root.join("locks"),
-        );
 
         installer
             .install(&["hashicorp/tap/terraform".to_string()], true)
@@ -382,6 +460,11 @@ end
         assert!(installer.is_installed("hashicorp/tap/terraform"));
         assert!(!installer.is_installed("terraform"));
         assert!(root.join("cellar/terraform/1.10.0").exists());
+        let receipt = installer
+            .read_receipt("hashicorp/tap/terraform")
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.source.tap.as_deref(), Some("hashicorp/tap"));
         installer.uninstall("hashicorp/tap/terraform").unwrap();
         assert!(!installer.is_installed("hashicorp/tap/terraform"));
         assert!(!root.join("cellar/terraform/1.10.0").exists());
@@ -432,28 +515,7 @@ end
             .mount(&mock_server)
             .await;
 
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        fs::create_dir_all(root.join("db")).unwrap();
-
-        let api_client =
-            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(&root).unwrap();
-        let cellar = Cellar::new(&root).unwrap();
-        let linker = Linker::new(&prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-
-        let mut installer = Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.to_path_buf(),
-            root.join("locks"),
-        );
+        let mut installer = installer(tmp.path(), &mock_server.uri());
         installer
             .install(&["terraform".to_string()], true)
             .await
@@ -464,4 +526,73 @@ end
         assert!(matches!(err, zb_core::Error::NotInstalled { .. }));
         assert!(installer.is_installed("terraform"));
     }
+
+    #[test]
+    fn gc_keeps_recently_released_entries_and_latest_versions() {
+        use std::time::{Duration, SystemTime};
+
+        use crate::storage::db::StoreRef;
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let mut installer = installer(tmp.path(), NO_API);
+
+        // Entries released before release times were recorded fall back to
+        // when they were unpacked.
+        let days = |n: u64| Duration::from_secs(n * 24 * 60 * 60);
+        let entries = [
+            ("a", "foo", "1.0", 10),
+            ("b", "foo", "2.0", 5),
+            ("c", "foo", "3.0", 1),
+            ("d", "bar", "1.0", 10),
+        ];
+        for (key, name, version, age) in entries {
+            let entry = root.join("store").join(key);
+            fs::create_dir_all(entry.join(name).join(version)).unwrap();
+            fs::write(entry.join(name).join(version).join("file"), "1234").unwrap();
+            fs::File::open(&entry)
+                .unwrap()
+                .set_modified(SystemTime::now() - days(age))
+                .unwrap();
+        }
+        let refs: Vec<StoreRef> = entries
+            .iter()
+            .map(|(key, ..)| StoreRef {
+                store_key: key.to_string(),
+                refcount: 0,
+            })
+            .collect();
+        installer.db.replace_store_refs(&refs).unwrap();
+
+        let summary = installer
+            .gc(&GcOptions {
+                max_age: Some(days(7)),
+                keep_versions: 1,
+            })
+            .unwrap();
+        assert_eq!(summary.removed, ["a"]);
+        assert_eq!(summary.kept, 3);
+        assert_eq!(summary.reclaimed_bytes, 4);
+        assert!(!root.join("store/a").exists());
+
+        let summary = installer
+            .gc(&GcOptions {
+                max_age: None,
+                keep_versions: 1,
+            })
+            .unwrap();
+        assert_eq!(summary.removed, ["b"]);
+        assert_eq!(summary.kept, 2);
+
+        let summary = installer.gc(&GcOptions::default()).unwrap();
+        assert_eq!(summary.removed, ["c", "d"]);
+        assert_eq!(summary.kept, 0);
+        assert!(
+            installer
+                .db
+                .get_unreferenced_store_refs()
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
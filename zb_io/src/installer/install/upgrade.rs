@@ -1,10 +1,12 @@
 use std::sync::Arc;
 
-use zb_core::{Error, InstallMethod};
+use zb_core::{Error, HeadSource, InstallMethod, parse_arch};
 
+use super::history::OperationKind;
 use super::{InstallPlan, Installer, acquire_install_lock};
 use crate::network::download::{DownloadProgressCallback, DownloadRequest};
 use crate::progress::{InstallProgress, ProgressCallback};
+use crate::storage::db::InstalledKeg;
 
 impl Installer {
     /// Upgrade an installed package to its latest version.
@@ -14,10 +16,10 @@ impl Installer {
     /// existing installation intact. Source-built upgrades have no
     /// equivalent pre-build stage and still go through uninstall-first.
     ///
-    /// Uninstall-first on the link step is required either way: a fresh
-    /// install would hit `LinkConflict` on the old version's symlinks and
-    /// leave the old cellar directory behind on disk (the leak this method
-    /// exists to fix).
+    /// Unlinking first is required either way: a fresh install would hit
+    /// `LinkConflict` on the old version's symlinks. The old keg itself is
+    /// kept as a generation for `rollback` (see
+    /// [`Installer::with_keep_generations`]) or removed when none are kept.
     ///
     /// Returns `Ok(())` when the package is already on its latest version,
     /// `Error::NotInstalled` when there is no existing installation.
@@ -31,35 +33,97 @@ impl Installer {
         // One lock for the entire flow — uninstall + install must not race
         // with other zb processes touching the same package.
         let _lock = acquire_install_lock(&self.locks_dir)?;
+        self.recover_interrupted()?;
 
         let old = self.db.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
         })?;
+        let before = self.installed_versions();
+
+        // Upgrade the keg for the arch and bottle variant it was installed
+        // for, and a HEAD keg to the latest commit rather than to a release.
+        let arch = old.arch.as_deref().and_then(parse_arch);
+        let configured_arch = std::mem::replace(&mut self.arch, arch);
+        let head = HeadSource::revision_of(&old.version).is_some();
+        let configured_head = std::mem::replace(&mut self.head, head);
+        let variant = self.installed_bottle_variant(name);
+        let configured_variant = std::mem::replace(&mut self.bottle_variant, variant);
+        let result = self
+            .upgrade_keg(name, &old, build_from_source, link, progress)
+            .await;
+        self.arch = configured_arch;
+        self.head = configured_head;
+        self.bottle_variant = configured_variant;
+        self.record_operation(OperationKind::Upgrade, before, &result, None);
+        result
+    }
 
+    async fn upgrade_keg(
+        &mut self,
+        name: &str,
+        old: &InstalledKeg,
+        build_from_source: bool,
+        link: bool,
+        progress: Option<Arc<ProgressCallback>>,
+    ) -> Result<(), Error> {
         // `plan_with_options` doesn't consult the installed DB, so an
         // empty-plan check wouldn't fire on already-current packages.
         if self.is_outdated(name).await?.is_none() {
             return Ok(());
         }
 
-        let plan = self
+        let mut plan = self
             .plan_with_options(&[name.to_string()], build_from_source)
             .await?;
 
+        // Keep a dependency-only install marked as such across upgrades so
+        // autoremove can still reclaim it later.
+        let on_request = self
+            .read_receipt(name)
+            .ok()
+            .flatten()
+            .is_none_or(|receipt| receipt.installed_on_request);
+        for item in plan.items.iter_mut().filter(|i| i.install_name == name) {
+            item.installed_on_request = on_request;
+            item.delta_base = Some(old.store_key.clone());
+        }
+
         // Fetch new bottles before touching the old install — a download
         // failure here leaves the existing keg intact.
         self.prefetch_plan_bottles(&plan, progress.clone()).await?;
 
-        self.uninstall_by_version(name, &old.version)?;
+        let new_version = plan
+            .items
+            .iter()
+            .find(|item| item.install_name == name)
+            .map(|item| item.formula.effective_version());
+        // A rebuild of the same version reuses the keg directory, so there
+        // is nothing to keep.
+        if self.keep_generations > 0 && new_version.as_deref() != Some(old.version.as_str()) {
+            // Rolling forward again replaces any generation of the new version.
+            if let Some(stale) = self
+                .db
+                .list_generations(name)?
+                .into_iter()
+                .find(|g| Some(g.version.as_str()) == new_version.as_deref())
+            {
+                self.remove_generation(&stale)?;
+            }
+            self.retire_keg(name, &old.version)?;
+        } else {
+            self.uninstall_by_version(name, &old.version)?;
+        }
 
         // We already hold the lock, so call the no-lock variant.
         self.execute_inner(plan, link, progress).await?;
+        self.prune_generations(name, self.keep_generations)?;
+        self.enforce_cache_limit();
 
         Ok(())
     }
 
-    /// Pre-download bottle artifacts in `plan` into the blob cache. No-op
-    /// for source-only plans.
+    /// Pre-download bottles and release binaries in `plan` into the blob
+    /// cache. No-op for source-only plans.
     async fn prefetch_plan_bottles(
         &self,
         plan: &InstallPlan,
@@ -74,7 +138,12 @@ impl Installer {
                     sha256: bottle.sha256.clone(),
                     name: item.formula.name.clone(),
                 }),
-                _ => None,
+                InstallMethod::Binary(binary) => Some(DownloadRequest {
+                    url: binary.url.clone(),
+                    sha256: binary.sha256.clone(),
+                    name: item.formula.name.clone(),
+                }),
+                InstallMethod::Source(_) => None,
             })
             .collect();
 
@@ -88,9 +157,9 @@ impl Installer {
             }) as DownloadProgressCallback
         });
 
-        let mut rx = self
-            .downloader
-            .download_streaming(requests, download_progress);
+        let mut rx =
+            self.downloader
+                .download_streaming_into(requests, download_progress, &self.store);
         while let Some(result) = rx.recv().await {
             result?;
         }
@@ -106,13 +175,8 @@ mod tests {
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
-    use crate::cellar::Cellar;
+    use crate::installer::install::GcOptions;
     use crate::installer::install::test_support::*;
-    use crate::network::api::ApiClient;
-    use crate::storage::blob::BlobCache;
-    use crate::storage::db::Database;
-    use crate::storage::store::Store;
-    use crate::{Installer, Linker};
 
     fn formula_json(mock_uri: &str, name: &str, version: &str, tag: &str, sha: &str) -> String {
         format!(
@@ -134,30 +198,6 @@ mod tests {
         )
     }
 
-    fn make_installer(
-        root: &std::path::Path,
-        prefix: &std::path::Path,
-        mock_uri: &str,
-    ) -> Installer {
-        fs::create_dir_all(root.join("db")).unwrap();
-        let api_client = ApiClient::with_base_url(format!("{mock_uri}/formula")).unwrap();
-        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
-        let store = Store::new(root).unwrap();
-        let cellar = Cellar::new(root).unwrap();
-        let linker = Linker::new(prefix).unwrap();
-        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
-        Installer::new(
-            api_client,
-            blob_cache,
-            store,
-            cellar,
-            linker,
-            db,
-            prefix.to_path_buf(),
-            root.join("locks"),
-        )
-    }
-
     #[tokio::test]
     async fn upgrade_replaces_old_version_and_cleans_up() {
         let mock_server = MockServer::start().await;
@@ -205,8 +245,8 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        let mut installer = make_installer(&root, &prefix, &mock_server.uri());
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &mock_server.uri()).with_keep_generations(0);
 
         installer
             .install(&["testpkg".to_string()], true)
@@ -243,6 +283,98 @@ mod tests {
         assert_eq!(installed.version, "2.0.0");
     }
 
+    #[tokio::test]
+    async fn rollback_relinks_previous_generation() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let bottle_v1 = create_bottle_tarball_with_version("rollpkg", "1.0.0");
+        let sha_v1 = sha256_hex(&bottle_v1);
+        let bottle_v2 = create_bottle_tarball_with_version("rollpkg", "2.0.0");
+        let sha_v2 = sha256_hex(&bottle_v2);
+
+        Mock::given(method("GET"))
+            .and(path("/formula/rollpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json(
+                &mock_server.uri(),
+                "rollpkg",
+                "1.0.0",
+                tag,
+                &sha_v1,
+            )))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/rollpkg-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle_v1))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula/rollpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json(
+                &mock_server.uri(),
+                "rollpkg",
+                "2.0.0",
+                tag,
+                &sha_v2,
+            )))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/rollpkg-2.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle_v2))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &mock_server.uri());
+
+        installer
+            .install(&["rollpkg".to_string()], true)
+            .await
+            .unwrap();
+        installer
+            .upgrade("rollpkg", false, true, None)
+            .await
+            .unwrap();
+
+        // Undoing the upgrade rolls back, and undoing that rolls forward.
+        let upgrade = installer.history(1).unwrap().remove(0);
+        assert_eq!(upgrade.kind, "upgrade");
+        installer.undo(upgrade.id).unwrap();
+        assert_eq!(installer.get_installed("rollpkg").unwrap().version, "1.0.0");
+        let undo = installer.history(1).unwrap().remove(0);
+        installer.undo(undo.id).unwrap();
+        assert_eq!(installer.get_installed("rollpkg").unwrap().version, "2.0.0");
+
+        assert!(root.join("cellar/rollpkg/1.0.0").exists());
+        let generations = installer.list_generations("rollpkg").unwrap();
+        assert_eq!(generations.len(), 1);
+        assert_eq!(generations[0].version, "1.0.0");
+
+        let version = installer.rollback("rollpkg").unwrap();
+        assert_eq!(version, "1.0.0");
+        assert_eq!(installer.get_installed("rollpkg").unwrap().version, "1.0.0");
+        let target = fs::read_link(prefix.join("bin/rollpkg")).unwrap();
+        assert!(target.to_string_lossy().contains("1.0.0"));
+        assert_eq!(
+            installer.list_generations("rollpkg").unwrap()[0].version,
+            "2.0.0"
+        );
+        assert!(installer.doctor().unwrap().is_healthy());
+
+        // Expired generations are collected along with their store entries.
+        let mut installer = installer.with_generation_retention(std::time::Duration::ZERO);
+        let removed = installer.gc(&GcOptions::default()).unwrap().removed;
+        assert_eq!(removed, vec![sha_v2]);
+        assert!(!root.join("cellar/rollpkg/2.0.0").exists());
+        assert!(installer.rollback("rollpkg").is_err());
+    }
+
     #[tokio::test]
     async fn upgrade_with_no_link_does_not_create_symlinks() {
         let mock_server = MockServer::start().await;
@@ -294,8 +426,8 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        let mut installer = make_installer(&root, &prefix, &mock_server.uri());
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["nolinkpkg".to_string()], true)
@@ -309,7 +441,8 @@ mod tests {
             .unwrap();
 
         assert!(root.join("cellar/nolinkpkg/2.0.0").exists());
-        assert!(!root.join("cellar/nolinkpkg/1.0.0").exists());
+        // Kept as a generation, but no longer linked.
+        assert!(root.join("cellar/nolinkpkg/1.0.0").exists());
         assert!(
             !prefix.join("bin/nolinkpkg").exists(),
             "no symlinks expected when link=false"
@@ -346,8 +479,8 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        let mut installer = make_installer(&root, &prefix, &mock_server.uri());
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["steadypkg".to_string()], true)
@@ -367,13 +500,95 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn upgrade_stays_on_the_installed_bottle_variant() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+        let uri = mock_server.uri();
+
+        // The default bottles are never served, so only the variant installs.
+        let variant_json = |version: &str, sha: &str| {
+            format!(
+                r#"{{
+                    "name": "viewer",
+                    "versions": {{ "stable": "{version}" }},
+                    "bottle": {{
+                        "stable": {{ "files": {{ "{tag}": {{
+                            "url": "{uri}/bottles/viewer-{version}.gui.bottle.tar.gz",
+                            "sha256": "{}"
+                        }} }} }},
+                        "variants": {{ "no-gui": {{ "files": {{ "{tag}": {{
+                            "url": "{uri}/bottles/viewer-{version}.{tag}.bottle.tar.gz",
+                            "sha256": "{sha}"
+                        }} }} }} }}
+                    }}
+                }}"#,
+                "0".repeat(64)
+            )
+        };
+
+        let bottle_v1 = create_bottle_tarball_with_version("viewer", "1.0.0");
+        let sha_v1 = sha256_hex(&bottle_v1);
+        let bottle_v2 = create_bottle_tarball_with_version("viewer", "2.0.0");
+        let sha_v2 = sha256_hex(&bottle_v2);
+
+        Mock::given(method("GET"))
+            .and(path("/formula/viewer.json"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(variant_json("1.0.0", &sha_v1)),
+            )
+            .up_to_n_times(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula/viewer.json"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(variant_json("2.0.0", &sha_v2)),
+            )
+            .mount(&mock_server)
+            .await;
+        for (version, bottle) in [("1.0.0", bottle_v1), ("2.0.0", bottle_v2)] {
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/bottles/viewer-{version}.{tag}.bottle.tar.gz"
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut first_run = installer(tmp.path(), &uri)
+            .with_keep_generations(0)
+            .with_bottle_variant("no-gui");
+        first_run
+            .install(&["viewer".to_string()], true)
+            .await
+            .unwrap();
+        let receipt = first_run.read_receipt("viewer").unwrap().unwrap();
+        assert_eq!(receipt.bottle_variant.as_deref(), Some("no-gui"));
+
+        // A later run without --variant compares against the variant's
+        // bottle and upgrades to it.
+        let mut installer = installer(tmp.path(), &uri).with_keep_generations(0);
+        assert!(installer.is_outdated("viewer").await.unwrap().is_none());
+        installer
+            .upgrade("viewer", false, true, None)
+            .await
+            .unwrap();
+
+        let installed = installer.get_installed("viewer").unwrap();
+        assert_eq!(installed.version, "2.0.0");
+        assert_eq!(installed.store_key, sha_v2);
+        let receipt = installer.read_receipt("viewer").unwrap().unwrap();
+        assert_eq!(receipt.bottle_variant.as_deref(), Some("no-gui"));
+    }
+
     #[tokio::test]
     async fn upgrade_errors_when_not_installed() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        let mut installer = make_installer(&root, &prefix, &mock_server.uri());
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         let err = installer
             .upgrade("nonexistent", false, true, None)
@@ -430,8 +645,8 @@ mod tests {
             .await;
 
         let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
-        let mut installer = make_installer(&root, &prefix, &mock_server.uri());
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), &mock_server.uri());
 
         installer
             .install(&["flakypkg".to_string()], true)
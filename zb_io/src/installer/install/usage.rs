@@ -0,0 +1,134 @@
+use zb_core::{Error, formula_token};
+
+use crate::storage::db::InstalledKeg;
+
+use super::Installer;
+
+/// Where zerobrew's disk space goes, as `zb du` reports it.
+#[derive(Debug, Clone, Default)]
+pub struct DiskUsage {
+    /// Installed kegs with their cached sizes, sorted by name.
+    pub kegs: Vec<InstalledKeg>,
+    pub store_bytes: u64,
+    pub cache_bytes: u64,
+    /// Bytes of kegs that are hardlinks or clones of store files, so are
+    /// counted in both the kegs and the store but take space only once.
+    pub shared_bytes: u64,
+}
+
+impl DiskUsage {
+    pub fn keg_bytes(&self) -> u64 {
+        self.kegs.iter().filter_map(|k| k.size_bytes).sum()
+    }
+
+    /// Kegs, store and cache together, with shared bytes counted once.
+    pub fn total_bytes(&self) -> u64 {
+        (self.keg_bytes() + self.store_bytes + self.cache_bytes).saturating_sub(self.shared_bytes)
+    }
+}
+
+impl Installer {
+    /// Measure the kegs, store and download cache. Keg sizes and the bytes
+    /// each keg shares with the store are cached at install and only
+    /// measured here when missing, or for every keg with `recalculate`.
+    pub fn disk_usage(&self, recalculate: bool) -> Result<DiskUsage, Error> {
+        let mut kegs = self.db.list_installed()?;
+        for keg in &mut kegs {
+            let cellar = self.cellar_for(keg.arch.as_deref());
+            let keg_name = formula_token(&keg.name);
+            if (recalculate || keg.size_bytes.is_none())
+                && let Ok(size) = cellar.keg_size(keg_name, &keg.version)
+            {
+                self.db.set_keg_size(&keg.name, size)?;
+                keg.size_bytes = Some(size);
+            }
+            if recalculate || keg.shared_bytes.is_none() {
+                let entry = self.store.entry_path(&keg.store_key);
+                let shared = match entry.is_dir() {
+                    true => cellar
+                        .keg_shared_size(keg_name, &keg.version, &entry)
+                        .unwrap_or(0),
+                    false => 0,
+                };
+                self.db.set_keg_shared_size(&keg.name, shared)?;
+                keg.shared_bytes = Some(shared);
+            }
+        }
+
+        Ok(DiskUsage {
+            shared_bytes: kegs.iter().filter_map(|k| k.shared_bytes).sum(),
+            kegs,
+            store_bytes: self
+                .store
+                .total_size()
+                .map_err(Error::store("failed to measure store"))?,
+            cache_bytes: self.blob_cache_size()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use crate::installer::install::test_support::*;
+
+    #[tokio::test]
+    async fn disk_usage_caches_sizes_measured_at_install() {
+        let server = MockServer::start().await;
+        let bottle = create_bottle_tarball("sized");
+        let tag = get_test_bottle_tag();
+        let formula = serde_json::json!({
+            "name": "sized",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { tag: {
+                "url": format!("{}/bottles/sized-1.0.0.{tag}.bottle.tar.gz", server.uri()),
+                "sha256": sha256_hex(&bottle),
+            } } } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/formula/sized.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula.to_string()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/sized-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let mut installer = installer(tmp.path(), &server.uri());
+        installer
+            .install(&["sized".to_string()], true)
+            .await
+            .unwrap();
+
+        let keg = installer.get_installed("sized").unwrap();
+        let (size, shared) = (keg.size_bytes.unwrap(), keg.shared_bytes.unwrap());
+        assert!(size > 0);
+        assert!(shared <= size);
+
+        let usage = installer.disk_usage(false).unwrap();
+        assert_eq!(usage.keg_bytes(), size);
+        assert_eq!(usage.shared_bytes, shared);
+        assert_eq!(usage.cache_bytes, bottle.len() as u64);
+        assert!(usage.store_bytes >= shared);
+        assert_eq!(
+            usage.total_bytes(),
+            size + usage.store_bytes + usage.cache_bytes - shared
+        );
+
+        // A stale cached size is only replaced when asked to recalculate.
+        installer.db.set_keg_size("sized", 1).unwrap();
+        assert_eq!(installer.disk_usage(false).unwrap().keg_bytes(), 1);
+        assert_eq!(installer.disk_usage(true).unwrap().keg_bytes(), size);
+        assert_eq!(
+            installer.get_installed("sized").unwrap().size_bytes,
+            Some(size)
+        );
+    }
+}
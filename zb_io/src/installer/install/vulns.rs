@@ -0,0 +1,187 @@
+use futures::StreamExt;
+use tracing::warn;
+use zb_core::{Error, formula_token};
+
+use crate::network::osv::{OsvClient, parse_vulnerabilities};
+use crate::storage::db::InstalledKeg;
+
+use super::Installer;
+
+/// Vulnerability queries in flight at once during an audit.
+const OSV_CONCURRENCY: usize = 8;
+
+/// A known vulnerability affecting an installed keg.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advisory {
+    pub id: String,
+    /// Other identifiers of the same issue, usually its CVE.
+    pub aliases: Vec<String>,
+    pub summary: Option<String>,
+    /// The earliest version that fixes it, if known.
+    pub fixed: Option<String>,
+}
+
+/// An installed keg, its license and the vulnerabilities affecting it.
+#[derive(Debug, Clone)]
+pub struct PackageAudit {
+    pub keg: InstalledKeg,
+    pub license: Option<String>,
+    pub advisories: Vec<Advisory>,
+    /// When the vulnerability records used were fetched, or `None` when
+    /// there are none to check against.
+    pub checked_at: Option<i64>,
+}
+
+impl Installer {
+    /// Check every installed keg against the vulnerability records of its
+    /// package. With a `feed`, records are fetched fresh and kept as a
+    /// snapshot in the database; without one, or when a fetch fails, the
+    /// last snapshot is used.
+    pub async fn audit_vulnerabilities(
+        &self,
+        feed: Option<&OsvClient>,
+    ) -> Result<Vec<PackageAudit>, Error> {
+        let licenses = self.installed_licenses().await?;
+
+        if let Some(feed) = feed {
+            let fetched: Vec<_> = futures::stream::iter(&licenses)
+                .map(|entry| feed.package_vulnerabilities(osv_package(&entry.keg.name)))
+                .buffered(OSV_CONCURRENCY)
+                .collect()
+                .await;
+            for (entry, result) in licenses.iter().zip(fetched) {
+                match result {
+                    Ok(json) => self
+                        .db
+                        .store_advisories(osv_package(&entry.keg.name), &json)?,
+                    Err(e) => warn!(
+                        formula = %entry.keg.name,
+                        error = %e,
+                        "failed to fetch vulnerability records; using the last snapshot"
+                    ),
+                }
+            }
+        }
+
+        let mut audits = Vec::new();
+        for entry in licenses {
+            let package = osv_package(&entry.keg.name);
+            let version = upstream_version(&entry.keg.version);
+            let cached = self.db.cached_advisories(package)?;
+            let mut advisories = Vec::new();
+            if let Some(cached) = &cached {
+                for vuln in parse_vulnerabilities(&cached.json)? {
+                    if !vuln.affects(package, version) {
+                        continue;
+                    }
+                    advisories.push(Advisory {
+                        fixed: vuln.fixed_after(package, version),
+                        id: vuln.id,
+                        aliases: vuln.aliases,
+                        summary: vuln.summary,
+                    });
+                }
+            }
+            audits.push(PackageAudit {
+                keg: entry.keg,
+                license: entry.license,
+                advisories,
+                checked_at: cached.map(|cached| cached.fetched_at),
+            });
+        }
+        Ok(audits)
+    }
+}
+
+/// The upstream package a formula packages: `openssl@3` and
+/// `user/tap/openssl` are both `openssl`.
+fn osv_package(name: &str) -> &str {
+    let token = formula_token(name);
+    token.split_once('@').map_or(token, |(package, _)| package)
+}
+
+/// `version` without the formula revision Homebrew appends, e.g. `3.2.1_1`.
+fn upstream_version(version: &str) -> &str {
+    match version.rsplit_once('_') {
+        Some((upstream, revision)) if revision.bytes().all(|b| b.is_ascii_digit()) => upstream,
+        _ => version,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::installer::install::test_support::installer;
+
+    #[test]
+    fn formula_names_map_to_upstream_packages() {
+        assert_eq!(osv_package("openssl@3"), "openssl");
+        assert_eq!(osv_package("user/tap/jq"), "jq");
+        assert_eq!(upstream_version("3.2.1_1"), "3.2.1");
+        assert_eq!(upstream_version("1.0-beta_x"), "1.0-beta_x");
+    }
+
+    #[tokio::test]
+    async fn offline_audit_uses_the_last_snapshot() {
+        let api = MockServer::start().await;
+        let osv = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let mut installer = installer(tmp.path(), &api.uri());
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("openssl@3", "3.1.0_1", "key1").unwrap();
+            tx.record_install("jq", "1.7.1", "key2").unwrap();
+            tx.commit().unwrap();
+        }
+
+        let records = serde_json::json!({"vulns": [{
+            "id": "OSV-1",
+            "aliases": ["CVE-2024-0001"],
+            "affected": [{
+                "package": {"name": "openssl", "ecosystem": "Bitnami"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [
+                    {"introduced": "3.0.0"}, {"fixed": "3.1.2"}
+                ]}]
+            }]
+        }]});
+        Mock::given(method("POST"))
+            .and(path("/v1/query"))
+            .and(body_partial_json(
+                serde_json::json!({"package": {"name": "openssl", "ecosystem": "Bitnami"}}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(records))
+            .mount(&osv)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/query"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&osv)
+            .await;
+
+        let feed = OsvClient::with_base_url(osv.uri());
+        let online = installer.audit_vulnerabilities(Some(&feed)).await.unwrap();
+        let offline = installer.audit_vulnerabilities(None).await.unwrap();
+
+        for audits in [online, offline] {
+            let names: Vec<&str> = audits.iter().map(|a| a.keg.name.as_str()).collect();
+            assert_eq!(names, ["jq", "openssl@3"]);
+            assert!(audits[0].advisories.is_empty());
+            assert_eq!(audits[0].checked_at, None);
+            assert_eq!(
+                audits[1].advisories,
+                [Advisory {
+                    id: "OSV-1".to_string(),
+                    aliases: vec!["CVE-2024-0001".to_string()],
+                    summary: None,
+                    fixed: Some("3.1.2".to_string()),
+                }]
+            );
+            assert!(audits[1].checked_at.is_some());
+        }
+    }
+}
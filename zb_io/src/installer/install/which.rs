@@ -0,0 +1,125 @@
+use zb_core::Error;
+
+use super::Installer;
+
+/// The formulas that provide a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandProviders {
+    /// The installed formula linking the command into the prefix.
+    pub installed: Option<String>,
+    /// Formulas the executables index says install the command.
+    pub available: Vec<String>,
+}
+
+impl CommandProviders {
+    pub fn is_empty(&self) -> bool {
+        self.installed.is_none() && self.available.is_empty()
+    }
+}
+
+impl Installer {
+    /// Download the list of executables each core formula installs, so
+    /// `which` can answer for formulas that are not installed. Returns the
+    /// number of executables stored.
+    pub async fn refresh_executables_index(&self) -> Result<usize, Error> {
+        let raw = self.api_client.get_executables_index_raw().await?;
+        self.db.replace_executables(&parse_executables_index(&raw))
+    }
+
+    /// Which formulas provide `command`: the installed one whose link it is,
+    /// and any the executables index knows of.
+    pub fn which(&self, command: &str) -> Result<CommandProviders, Error> {
+        if command.is_empty() || command.contains('/') {
+            return Err(Error::InvalidArgument {
+                message: format!("'{command}' is not a command name"),
+            });
+        }
+
+        let mut installed = None;
+        for dir in ["bin", "sbin"] {
+            installed = self.db.link_owner(&self.prefix.join(dir).join(command))?;
+            if installed.is_some() {
+                break;
+            }
+        }
+        Ok(CommandProviders {
+            installed,
+            available: self.db.formulas_providing(command)?,
+        })
+    }
+}
+
+/// Parse `name(version):exe exe` lines, skipping malformed ones.
+fn parse_executables_index(raw: &str) -> Vec<(String, Vec<String>)> {
+    raw.lines()
+        .filter_map(|line| {
+            let (formula, executables) = line.split_once(':')?;
+            let name = formula.split_once('(').map_or(formula, |(name, _)| name);
+            let name = name.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let executables = executables.split_whitespace().map(str::to_string).collect();
+            Some((name.to_string(), executables))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::cellar::Cellar;
+    use crate::cellar::link::LinkMode;
+    use crate::installer::install::test_support::{NO_API, installer};
+
+    #[test]
+    fn parses_executables_index_lines() {
+        let raw = "jq(1.7.1):jq\nvim(9.1.0):vim vimdiff xxd\n\nbroken line\n(1.0):nameless\n";
+        assert_eq!(
+            parse_executables_index(raw),
+            [
+                ("jq".to_string(), vec!["jq".to_string()]),
+                (
+                    "vim".to_string(),
+                    vec!["vim".to_string(), "vimdiff".to_string(), "xxd".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn which_prefers_the_installed_link() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let mut installer = installer(tmp.path(), NO_API);
+        installer.cellar = Cellar::new_at(prefix.join("Cellar")).unwrap();
+        installer
+            .db
+            .replace_executables(&[
+                ("vim".to_string(), vec!["xxd".to_string()]),
+                ("xxd".to_string(), vec!["xxd".to_string()]),
+            ])
+            .unwrap();
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("vim", "9.1.0", "abc").unwrap();
+            tx.record_linked_file(
+                "vim",
+                "9.1.0",
+                &prefix.join("bin/xxd").to_string_lossy(),
+                &prefix.join("Cellar/vim/9.1.0/bin/xxd").to_string_lossy(),
+                LinkMode::Symlink,
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let providers = installer.which("xxd").unwrap();
+        assert_eq!(providers.installed.as_deref(), Some("vim"));
+        assert_eq!(providers.available, ["vim", "xxd"]);
+        assert!(installer.which("nope").unwrap().is_empty());
+        assert!(installer.which("../bin/xxd").is_err());
+    }
+}
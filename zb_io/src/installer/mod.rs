@@ -4,9 +4,17 @@ pub mod install;
 
 pub use homebrew::{
     HomebrewMigrationPackages, HomebrewPackage, categorize_packages, get_homebrew_packages,
-    parse_casks_from_plain_text, parse_formulas_from_json,
+    parse_casks_from_plain_text, parse_formulas_from_json, stop_homebrew_service,
 };
-pub use install::doctor::{DiagnosticReport, RepairSummary};
+pub use install::cleanup::{CleanupOptions, CleanupSummary};
+pub use install::doctor::{DiagnosticReport, NewerDatabase, RepairSummary};
+pub use install::receipt::{InstallReceipt, ReceiptDependency, ReceiptSource};
 pub use install::{
-    ExecuteResult, InstallPlan, Installer, OutdatedPackage, PlanFailure, create_installer,
+    Advisory, BuildFlags, CommandProviders, CompatSync, DEFAULT_PROFILE, DiskUsage, DriftKind,
+    ExecuteResult, ExportedImage, ExportedPackage, FileOwner, FormulaIndexRefresh, FormulaMatch,
+    GcOptions, GcSummary, ImageLayout, InstallPhase, InstallPlan, Installer, KegLicense,
+    LOCKFILE_VERSION, LinkDrift, LinkStrategy, LockedPackage, Lockfile, OutdatedPackage,
+    PackageAudit, PackageNotes, PhaseHook, PhaseTiming, PlanFailure, PlanSizes, Profile,
+    RelinkSummary, ReplayCheck, ShadowedCommand, StoreEntry, StoreKeg, create_installer,
+    create_installer_with_layout,
 };
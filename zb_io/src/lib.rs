@@ -6,21 +6,44 @@ pub mod installer;
 pub mod network;
 pub mod path;
 pub mod progress;
+pub mod services;
 pub mod ssl;
 pub mod storage;
+pub mod transcript;
 
 pub use build::{BuildExecutor, DepInfo};
-pub use cellar::{Cellar, LinkedFile, Linker, MaterializedKeg};
-pub use extraction::extract_tarball;
+pub use cellar::{
+    Cellar, DanglingReference, LinkMode, LinkedFile, Linker, MaterializedKeg, RelocationAudit,
+};
+pub use extraction::{ExtractPolicy, extract_tarball};
 pub use installer::{
-    DiagnosticReport, ExecuteResult, HomebrewMigrationPackages, HomebrewPackage, InstallPlan,
-    Installer, OutdatedPackage, PlanFailure, RepairSummary, create_installer,
-    get_homebrew_packages,
+    Advisory, BuildFlags, CleanupOptions, CleanupSummary, CommandProviders, CompatSync,
+    DEFAULT_PROFILE, DiagnosticReport, DiskUsage, DriftKind, ExecuteResult, ExportedImage,
+    ExportedPackage, FileOwner, FormulaIndexRefresh, FormulaMatch, GcOptions, GcSummary,
+    HomebrewMigrationPackages, HomebrewPackage, ImageLayout, InstallPhase, InstallPlan,
+    InstallReceipt, Installer, KegLicense, LOCKFILE_VERSION, LinkDrift, LinkStrategy,
+    LockedPackage, Lockfile, NewerDatabase, OutdatedPackage, PackageAudit, PackageNotes, PhaseHook,
+    PhaseTiming, PlanFailure, PlanSizes, Profile, ReceiptDependency, ReceiptSource, RelinkSummary,
+    RepairSummary, ReplayCheck, ShadowedCommand, StoreEntry, StoreKeg, create_installer,
+    create_installer_with_layout, get_homebrew_packages, stop_homebrew_service,
 };
 pub use network::{
-    ApiCache, ApiClient, DownloadProgressCallback, DownloadRequest, Downloader, ParallelDownloader,
+    AnalyticsPeriod, ApiCache, ApiClient, ArtifactKind, BandwidthLimit, DownloadProgressCallback,
+    DownloadRequest, Downloader, FormulaTemplate, InstallCount, OsvClient, ParallelDownloader,
+    ReleaseClient, RetryOn, RetryPolicy, SourceBuild, StagedExecutable, is_newer_release,
+    matches_release, release_asset_name, replace_executable, stage_executable, verify_attestation,
 };
 pub use path::validate_privileged_path;
-pub use progress::{InstallProgress, ProgressCallback};
+pub use progress::{
+    InstallProgress, ProgressCallback, ProgressReceiver, ProgressSender, channel_callback,
+    progress_channel,
+};
+pub use services::{ServiceManager, ServiceState, ServiceStatus};
 pub use ssl::{find_ca_bundle_from_prefix, find_ca_dir};
-pub use storage::{BlobCache, Database, InstalledKeg, KegFileRecord, Store, StoreRef};
+pub use storage::{
+    BlobCache, BlobEntry, CachedAdvisories, CachedFormula, CorruptDownload, Database,
+    DedupeSummary, FormulaRecord, InstallStat, InstalledKeg, KegFileRecord, KegGeneration,
+    ManifestEntry, OperationRecord, PackageChange, ProfileRecord, ServiceOverrides,
+    ServiceSettings, StatsSummary, StorageLayout, Store, StoreRef, StoreVerification,
+};
+pub use transcript::{Transcript, TranscriptEvent, read_transcript};
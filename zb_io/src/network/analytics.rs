@@ -0,0 +1,124 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use zb_core::Error;
+
+/// How far back Homebrew's published install counts reach.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnalyticsPeriod {
+    #[default]
+    Days30,
+    Days90,
+    Days365,
+}
+
+impl AnalyticsPeriod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnalyticsPeriod::Days30 => "30d",
+            AnalyticsPeriod::Days90 => "90d",
+            AnalyticsPeriod::Days365 => "365d",
+        }
+    }
+}
+
+impl fmt::Display for AnalyticsPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AnalyticsPeriod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "30d" => Ok(AnalyticsPeriod::Days30),
+            "90d" => Ok(AnalyticsPeriod::Days90),
+            "365d" => Ok(AnalyticsPeriod::Days365),
+            other => Err(Error::InvalidArgument {
+                message: format!("unknown analytics period '{other}' (expected 30d, 90d or 365d)"),
+            }),
+        }
+    }
+}
+
+/// How often a formula was installed over an [`AnalyticsPeriod`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallCount {
+    pub formula: String,
+    /// 1 for the most installed formula.
+    pub rank: u32,
+    pub count: u64,
+}
+
+#[derive(Deserialize)]
+struct AnalyticsPage {
+    items: Vec<AnalyticsItem>,
+}
+
+#[derive(Deserialize)]
+struct AnalyticsItem {
+    number: u32,
+    formula: String,
+    /// Formatted with thousands separators, e.g. `"1,234"`.
+    count: String,
+}
+
+/// Parse formulae.brew.sh's `analytics/install/{period}.json`, most
+/// installed first.
+pub fn parse_install_counts(json: &str) -> Result<Vec<InstallCount>, Error> {
+    let page: AnalyticsPage =
+        serde_json::from_str(json).map_err(Error::network("failed to parse install analytics"))?;
+    let mut counts: Vec<InstallCount> = page
+        .items
+        .into_iter()
+        .map(|item| InstallCount {
+            count: item.count.replace(',', "").parse().unwrap_or(0),
+            formula: item.formula,
+            rank: item.number,
+        })
+        .collect();
+    counts.sort_by_key(|c| c.rank);
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_parse_in_rank_order() {
+        let json = r#"{
+            "category": "install",
+            "total_items": 2,
+            "items": [
+                {"number": 2, "formula": "jq", "count": "98,765", "percent": "1.2"},
+                {"number": 1, "formula": "ca-certificates", "count": "1,234,567", "percent": "14"}
+            ]
+        }"#;
+        let counts = parse_install_counts(json).unwrap();
+        assert_eq!(
+            counts,
+            [
+                InstallCount {
+                    formula: "ca-certificates".into(),
+                    rank: 1,
+                    count: 1_234_567
+                },
+                InstallCount {
+                    formula: "jq".into(),
+                    rank: 2,
+                    count: 98_765
+                },
+            ]
+        );
+
+        assert_eq!(
+            "90d".parse::<AnalyticsPeriod>().unwrap(),
+            AnalyticsPeriod::Days90
+        );
+        assert!("7d".parse::<AnalyticsPeriod>().is_err());
+    }
+}
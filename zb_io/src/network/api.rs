@@ -2,14 +2,24 @@ use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
 use crate::checksum::verify_sha256_bytes;
+use crate::network::analytics::{AnalyticsPeriod, InstallCount, parse_install_counts};
 use crate::network::cache::{ApiCache, CacheEntry};
+use crate::network::retry::RetryPolicy;
 use crate::network::suggest::rank_formula_suggestions;
 use crate::network::tap_formula::{parse_tap_formula_ref, parse_tap_formula_ruby};
+use crate::transcript::{Transcript, TranscriptEvent};
 use futures_util::stream::{self, StreamExt};
-use zb_core::{Error, Formula};
+use tracing::{debug, warn};
+use zb_core::{ApiEndpoints, Error, Formula};
 
-const HOMEBREW_CORE_RAW_BASE: &str =
-    "https://raw.githubusercontent.com/Homebrew/homebrew-core/main";
+const DEFAULT_TAP_RAW_BASE_URL: &str = "https://raw.githubusercontent.com";
+
+/// homebrew-core's formula sources, under the raw tap base URL.
+const HOMEBREW_CORE_RAW_PATH: &str = "Homebrew/homebrew-core/main";
+
+/// Executables of every core formula, one `name(version):exe exe` line each,
+/// under the raw tap base URL.
+const EXECUTABLES_INDEX_PATH: &str = "Homebrew/homebrew-command-not-found/HEAD/executables.txt";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum RubySourceLocator<'a> {
@@ -41,9 +51,12 @@ impl<'a> RubySourceLocator<'a> {
         }
     }
 
-    fn to_url(self) -> String {
+    fn to_url(self, tap_raw_base_url: &str) -> String {
         match self {
-            Self::CoreRelativePath(path) => format!("{HOMEBREW_CORE_RAW_BASE}/{path}"),
+            Self::CoreRelativePath(path) => format!(
+                "{}/{HOMEBREW_CORE_RAW_PATH}/{path}",
+                tap_raw_base_url.trim_end_matches('/')
+            ),
             Self::AbsoluteUrl(url) | Self::TapEncodedUrl(url) => url.to_string(),
         }
     }
@@ -53,11 +66,6 @@ impl<'a> RubySourceLocator<'a> {
     }
 }
 
-enum CachedGetResult {
-    Cached(String),
-    Fresh(reqwest::Response),
-}
-
 #[derive(Debug, serde::Deserialize)]
 struct FormulaSuggestionEntry {
     #[serde(default)]
@@ -72,11 +80,14 @@ struct FormulaSuggestionEntry {
 pub struct ApiClient {
     base_url: String,
     cask_base_url: String,
+    analytics_base_url: String,
     tap_raw_base_url: String,
     client: reqwest::Client,
     cache: Option<ApiCache>,
     formula_candidates: RwLock<Option<Arc<[String]>>>,
     alias_map: RwLock<Option<Arc<HashMap<String, String>>>>,
+    retry_policy: RetryPolicy,
+    transcript: Option<Arc<Transcript>>,
 }
 
 impl ApiClient {
@@ -88,24 +99,29 @@ impl ApiClient {
 
     /// Rejects non-http(s) schemes and URLs containing credentials.
     pub fn with_base_url(base_url: String) -> Result<Self, Error> {
-        let parsed = reqwest::Url::parse(&base_url).map_err(|e| Error::InvalidArgument {
-            message: format!("invalid API base URL: {e}"),
-        })?;
-        if parsed.scheme() != "http" && parsed.scheme() != "https" {
-            return Err(Error::InvalidArgument {
-                message: format!(
-                    "API base URL must use http or https scheme, got: {}",
-                    parsed.scheme()
-                ),
-            });
+        validate_base_url(&base_url, "API base URL")?;
+        Ok(Self::build_client(base_url))
+    }
+
+    /// Fetch from the mirrors in `endpoints` instead of the public
+    /// services. A formula API mirror ending in `/formula` serves casks and
+    /// analytics from the sibling `/cask` and `/analytics`, as
+    /// formulae.brew.sh does.
+    pub fn with_endpoints(mut self, endpoints: &ApiEndpoints) -> Result<Self, Error> {
+        if let Some(api_url) = &endpoints.api_url {
+            validate_base_url(api_url, "API base URL")?;
+            let api_url = api_url.trim_end_matches('/');
+            if let Some(api_root) = api_url.strip_suffix("/formula") {
+                self.cask_base_url = format!("{api_root}/cask");
+                self.analytics_base_url = format!("{api_root}/analytics");
+            }
+            self.base_url = api_url.to_string();
         }
-        if !parsed.username().is_empty() || parsed.password().is_some() {
-            return Err(Error::InvalidArgument {
-                message: "Bad ZEROBREW_API_URL configuration".to_string(),
-            });
+        if let Some(tap_base_url) = &endpoints.tap_base_url {
+            validate_base_url(tap_base_url, "tap base URL")?;
+            self.tap_raw_base_url = tap_base_url.trim_end_matches('/').to_string();
         }
-
-        Ok(Self::build_client(base_url))
+        Ok(self)
     }
 
     fn build_client(base_url: String) -> Self {
@@ -119,14 +135,23 @@ impl ApiClient {
         Self {
             base_url,
             cask_base_url: "https://formulae.brew.sh/api/cask".to_string(),
-            tap_raw_base_url: "https://raw.githubusercontent.com".to_string(),
+            analytics_base_url: "https://formulae.brew.sh/api/analytics".to_string(),
+            tap_raw_base_url: DEFAULT_TAP_RAW_BASE_URL.to_string(),
             client,
             cache: None,
             formula_candidates: RwLock::new(None),
             alias_map: RwLock::new(None),
+            retry_policy: RetryPolicy::default(),
+            transcript: None,
         }
     }
 
+    /// Record every request that goes out over the network.
+    pub fn with_transcript(mut self, transcript: Arc<Transcript>) -> Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
     #[cfg(test)]
     pub fn with_tap_raw_base_url(mut self, tap_raw_base_url: String) -> Self {
         self.tap_raw_base_url = tap_raw_base_url;
@@ -139,6 +164,30 @@ impl ApiClient {
         self
     }
 
+    #[cfg(test)]
+    pub fn with_analytics_base_url(mut self, analytics_base_url: String) -> Self {
+        self.analytics_base_url = analytics_base_url;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Run `op` under the client's retry policy, logging each retry.
+    async fn with_retries<T, F, Fut>(&self, what: &str, op: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, Error>>,
+    {
+        self.retry_policy
+            .run(op, |attempt, max_attempts, err| {
+                warn!(request = what, attempt, max_attempts, error = %err, "retrying API request");
+            })
+            .await
+    }
+
     pub fn with_cache(mut self, cache: ApiCache) -> Self {
         self.cache = Some(cache);
         self
@@ -154,6 +203,20 @@ impl ApiClient {
         }
     }
 
+    /// Remove cached API responses older than `max_age`. With `dry_run` the
+    /// cache is left untouched and only the would-be count is returned.
+    pub fn prune_cache(&self, max_age: std::time::Duration, dry_run: bool) -> Result<usize, Error> {
+        let Some(cache) = &self.cache else {
+            return Ok(0);
+        };
+        let result = if dry_run {
+            cache.count_older_than(max_age)
+        } else {
+            cache.prune_older_than(max_age)
+        };
+        result.map_err(Error::store("failed to prune API cache"))
+    }
+
     pub async fn fetch_formula_rb(
         &self,
         ruby_source_path: &str,
@@ -162,7 +225,7 @@ impl ApiClient {
     ) -> Result<std::path::PathBuf, Error> {
         let locator = RubySourceLocator::parse(ruby_source_path);
         let source_id = locator.source_id(ruby_source_path);
-        let url = locator.to_url();
+        let url = locator.to_url(&self.tap_raw_base_url);
 
         self.fetch_formula_rb_from_url(source_id, &url, cache_dir, expected_sha256)
             .await
@@ -237,7 +300,10 @@ impl ApiClient {
         }
     }
 
-    async fn cached_get(&self, url: &str) -> Result<CachedGetResult, Error> {
+    /// GET `url`, revalidating a cached copy with its ETag and
+    /// Last-Modified, and cache a fresh body with the validators it came
+    /// with. `what` names the resource in errors.
+    async fn fetch_cached(&self, url: &str, what: &str) -> Result<String, Error> {
         let cached_entry = self.cache.as_ref().and_then(|c| c.get(url));
 
         let mut request = self.client.get(url);
@@ -251,34 +317,53 @@ impl ApiClient {
             }
         }
 
+        debug!(url, revalidating = cached_entry.is_some(), "API request");
+        if let Some(transcript) = &self.transcript {
+            transcript.record(TranscriptEvent::ApiRequest {
+                url: url.to_string(),
+            });
+        }
         let response = request.send().await.map_err(|e| Error::NetworkFailure {
             message: e.to_string(),
         })?;
+        debug!(url, status = response.status().as_u16(), "API response");
 
         if response.status() == reqwest::StatusCode::NOT_MODIFIED
             && let Some(entry) = cached_entry
         {
-            return Ok(CachedGetResult::Cached(entry.body));
+            return Ok(entry.body);
+        }
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus {
+                status: response.status().as_u16(),
+                message: format!("{what} fetch failed"),
+            });
         }
 
-        Ok(CachedGetResult::Fresh(response))
-    }
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string())
+        };
+        let etag = header("etag");
+        let last_modified = header("last-modified");
+
+        let body = response
+            .text()
+            .await
+            .map_err(Error::network(&format!("failed to read {what}")))?;
 
-    fn store_response_in_cache(
-        &self,
-        url: &str,
-        etag: Option<String>,
-        last_modified: Option<String>,
-        body: &str,
-    ) {
         if let Some(ref cache) = self.cache {
             let entry = CacheEntry {
                 etag,
                 last_modified,
-                body: body.to_string(),
+                body: body.clone(),
             };
             let _ = cache.put(url, &entry);
         }
+        Ok(body)
     }
 
     pub async fn get_formula(&self, name: &str) -> Result<Formula, Error> {
@@ -286,100 +371,90 @@ impl ApiClient {
             return self.get_tap_formula(&spec).await;
         }
 
-        let parse_body = |body: String| {
-            serde_json::from_str(&body).map_err(Error::network("failed to parse formula JSON"))
-        };
+        let body = self.get_formula_raw(name).await?;
+        Formula::from_json(&body)
+    }
 
+    /// The API JSON of the core formula `name`, which may be an alias.
+    pub async fn get_formula_raw(&self, name: &str) -> Result<String, Error> {
         match self.fetch_formula_json(name).await {
-            Ok(body) => parse_body(body),
             Err(Error::MissingFormula { .. }) => {
                 if let Ok(alias_map) = self.get_alias_map().await
                     && let Some(canonical) = alias_map.get(name)
                 {
-                    return self
-                        .fetch_formula_json(canonical)
-                        .await
-                        .and_then(parse_body);
+                    return self.fetch_formula_json(canonical).await;
                 }
                 Err(Error::MissingFormula {
                     name: name.to_string(),
                 })
             }
-            Err(e) => Err(e),
+            result => result,
         }
     }
 
     async fn fetch_formula_json(&self, name: &str) -> Result<String, Error> {
-        let url = format!("{}/{}.json", self.base_url, name);
+        self.with_retries(name, || self.fetch_formula_json_once(name))
+            .await
+    }
 
-        match self.cached_get(&url).await? {
-            CachedGetResult::Cached(body) => Ok(body),
-            CachedGetResult::Fresh(response) => {
-                if response.status() == reqwest::StatusCode::NOT_FOUND {
-                    return Err(Error::MissingFormula {
-                        name: name.to_string(),
-                    });
-                }
-                if !response.status().is_success() {
-                    return Err(Error::NetworkFailure {
-                        message: format!("HTTP {}", response.status()),
-                    });
-                }
+    async fn fetch_formula_json_once(&self, name: &str) -> Result<String, Error> {
+        let url = format!("{}/{}.json", self.base_url, name);
 
-                let etag = response
-                    .headers()
-                    .get("etag")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_string());
-                let last_modified = response
-                    .headers()
-                    .get("last-modified")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_string());
-
-                let body = response
-                    .text()
-                    .await
-                    .map_err(Error::network("failed to read response body"))?;
-
-                self.store_response_in_cache(&url, etag, last_modified, &body);
-                Ok(body)
-            }
+        match self.fetch_cached(&url, &format!("formula '{name}'")).await {
+            Err(Error::HttpStatus { status: 404, .. }) => Err(Error::MissingFormula {
+                name: name.to_string(),
+            }),
+            result => result,
         }
     }
 
     pub async fn get_all_formulas_raw(&self) -> Result<String, Error> {
+        self.with_retries("formula index", || self.get_all_formulas_raw_once())
+            .await
+    }
+
+    async fn get_all_formulas_raw_once(&self) -> Result<String, Error> {
         let url = format!("{}.json", self.base_url);
 
-        match self.cached_get(&url).await? {
-            CachedGetResult::Cached(body) => Ok(body),
-            CachedGetResult::Fresh(response) => {
-                if !response.status().is_success() {
-                    return Err(Error::NetworkFailure {
-                        message: format!("bulk formula fetch returned HTTP {}", response.status()),
-                    });
-                }
+        self.fetch_cached(&url, "bulk formula index").await
+    }
 
-                let etag = response
-                    .headers()
-                    .get("etag")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_string());
-                let last_modified = response
-                    .headers()
-                    .get("last-modified")
-                    .and_then(|v| v.to_str().ok())
-                    .map(|s| s.to_string());
-
-                let body = response
-                    .text()
-                    .await
-                    .map_err(Error::network("failed to read bulk formula response body"))?;
-
-                self.store_response_in_cache(&url, etag, last_modified, &body);
-                Ok(body)
-            }
-        }
+    /// Homebrew's published install counts over `period`, most installed
+    /// first.
+    pub async fn get_install_counts(
+        &self,
+        period: AnalyticsPeriod,
+    ) -> Result<Vec<InstallCount>, Error> {
+        let raw = self
+            .with_retries("install analytics", || {
+                self.get_install_counts_raw_once(period)
+            })
+            .await?;
+        parse_install_counts(&raw)
+    }
+
+    async fn get_install_counts_raw_once(&self, period: AnalyticsPeriod) -> Result<String, Error> {
+        let url = format!("{}/install/{period}.json", self.analytics_base_url);
+
+        self.fetch_cached(&url, "install analytics").await
+    }
+
+    /// The list of executables each core formula installs, as published for
+    /// Homebrew's command-not-found handler.
+    pub async fn get_executables_index_raw(&self) -> Result<String, Error> {
+        self.with_retries("executables index", || {
+            self.get_executables_index_raw_once()
+        })
+        .await
+    }
+
+    async fn get_executables_index_raw_once(&self) -> Result<String, Error> {
+        let url = format!(
+            "{}/{EXECUTABLES_INDEX_PATH}",
+            self.tap_raw_base_url.trim_end_matches('/')
+        );
+
+        self.fetch_cached(&url, "executables index").await
     }
 
     pub async fn suggest_formulas(&self, query: &str, limit: usize) -> Result<Vec<String>, Error> {
@@ -484,6 +559,10 @@ impl ApiClient {
     }
 
     pub async fn get_cask(&self, token: &str) -> Result<serde_json::Value, Error> {
+        self.with_retries(token, || self.get_cask_once(token)).await
+    }
+
+    async fn get_cask_once(&self, token: &str) -> Result<serde_json::Value, Error> {
         let url = format!("{}/{}.json", self.cask_base_url, token);
         let response = self
             .client
@@ -501,8 +580,9 @@ impl ApiClient {
         }
 
         if !response.status().is_success() {
-            return Err(Error::NetworkFailure {
-                message: format!("HTTP {}", response.status()),
+            return Err(Error::HttpStatus {
+                status: response.status().as_u16(),
+                message: format!("cask fetch for '{token}' failed"),
             });
         }
 
@@ -618,6 +698,28 @@ impl Default for ApiClient {
     }
 }
 
+/// Reject `url` unless it is http(s) without embedded credentials. `what`
+/// names it in the error.
+fn validate_base_url(url: &str, what: &str) -> Result<(), Error> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| Error::InvalidArgument {
+        message: format!("invalid {what} '{url}': {e}"),
+    })?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(Error::InvalidArgument {
+            message: format!(
+                "{what} must use http or https scheme, got: {}",
+                parsed.scheme()
+            ),
+        });
+    }
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        return Err(Error::InvalidArgument {
+            message: format!("{what} must not contain credentials"),
+        });
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -653,6 +755,35 @@ mod tests {
         assert!(ApiClient::with_base_url("http://localhost:8080/api".into()).is_ok());
     }
 
+    #[test]
+    fn endpoints_redirect_formulas_casks_and_tap_sources() {
+        let client = ApiClient::new()
+            .with_endpoints(&ApiEndpoints {
+                api_url: Some("https://mirror.example.com/api/formula/".into()),
+                tap_base_url: Some("https://raw.mirror.example.com".into()),
+            })
+            .unwrap();
+        assert_eq!(client.base_url, "https://mirror.example.com/api/formula");
+        assert_eq!(client.cask_base_url, "https://mirror.example.com/api/cask");
+        assert_eq!(
+            client.analytics_base_url,
+            "https://mirror.example.com/api/analytics"
+        );
+        assert_eq!(
+            RubySourceLocator::CoreRelativePath("Formula/f/foo.rb")
+                .to_url(&client.tap_raw_base_url),
+            "https://raw.mirror.example.com/Homebrew/homebrew-core/main/Formula/f/foo.rb"
+        );
+
+        let err = ApiClient::new()
+            .with_endpoints(&ApiEndpoints {
+                api_url: None,
+                tap_base_url: Some("file:///srv/taps".into()),
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("tap base URL"), "{err}");
+    }
+
     #[test]
     fn ruby_source_locator_parses_all_supported_kinds() {
         assert_eq!(
@@ -677,18 +808,20 @@ mod tests {
     #[test]
     fn ruby_source_locator_resolves_urls_exhaustively() {
         assert_eq!(
-            RubySourceLocator::CoreRelativePath("Formula/f/foo.rb").to_url(),
+            RubySourceLocator::CoreRelativePath("Formula/f/foo.rb")
+                .to_url(DEFAULT_TAP_RAW_BASE_URL),
             "https://raw.githubusercontent.com/Homebrew/homebrew-core/main/Formula/f/foo.rb"
         );
         assert_eq!(
-            RubySourceLocator::AbsoluteUrl("https://example.com/foo.rb").to_url(),
+            RubySourceLocator::AbsoluteUrl("https://example.com/foo.rb")
+                .to_url(DEFAULT_TAP_RAW_BASE_URL),
             "https://example.com/foo.rb"
         );
         assert_eq!(
             RubySourceLocator::TapEncodedUrl(
                 "https://raw.githubusercontent.com/org/tap/main/foo.rb"
             )
-            .to_url(),
+            .to_url("https://mirror.example.com"),
             "https://raw.githubusercontent.com/org/tap/main/foo.rb"
         );
     }
@@ -712,6 +845,29 @@ mod tests {
         assert_eq!(formula.versions.stable, "1.2.3");
     }
 
+    #[tokio::test]
+    async fn unexpected_formula_json_is_a_schema_error() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"{"name":"foo","versions":{"stable":["1.2.3"]}}"#),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri()).unwrap();
+        let err = client.get_formula("foo").await.unwrap_err();
+
+        assert!(
+            matches!(err, Error::FormulaSchema { name: Some(ref name), .. } if name == "foo"),
+            "{err:?}"
+        );
+    }
+
     #[tokio::test]
     async fn returns_missing_formula_on_404() {
         let mock_server = MockServer::start().await;
@@ -731,6 +887,64 @@ mod tests {
         ));
     }
 
+    fn fast_retries(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: std::time::Duration::from_millis(1),
+            max_backoff: std::time::Duration::from_millis(2),
+            ..RetryPolicy::default()
+        }
+        .with_max_attempts(max_attempts)
+    }
+
+    #[tokio::test]
+    async fn retries_formula_fetch_after_server_error() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri())
+            .unwrap()
+            .with_retry_policy(fast_retries(3));
+        let formula = client.get_formula("foo").await.unwrap();
+
+        assert_eq!(formula.name, "foo");
+    }
+
+    #[tokio::test]
+    async fn persistent_server_errors_exhaust_retries() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri())
+            .unwrap()
+            .with_retry_policy(fast_retries(2));
+        let err = client.get_formula("foo").await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::RetriesExhausted { attempts: 2, ref last }
+                if matches!(**last, Error::HttpStatus { status: 502, .. })
+        ));
+    }
+
     #[tokio::test]
     async fn first_request_stores_etag() {
         let mock_server = MockServer::start().await;
@@ -843,6 +1057,49 @@ mod tests {
         assert_eq!(formula.versions.stable, "1.2.3");
     }
 
+    #[tokio::test]
+    async fn executables_index_revalidates_with_last_modified() {
+        let mock_server = MockServer::start().await;
+        let index_path = format!("/{EXECUTABLES_INDEX_PATH}");
+        let modified = "Wed, 21 Oct 2026 07:28:00 GMT";
+
+        Mock::given(method("GET"))
+            .and(path(index_path.as_str()))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("jq(1.7.1):jq\n")
+                    .insert_header("last-modified", modified),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri())
+            .unwrap()
+            .with_tap_raw_base_url(mock_server.uri())
+            .with_cache(ApiCache::in_memory().unwrap());
+        client.get_executables_index_raw().await.unwrap();
+
+        mock_server.reset().await;
+        Mock::given(method("GET"))
+            .and(path(index_path.as_str()))
+            // `header` splits values on commas, which HTTP dates contain.
+            .and(move |request: &wiremock::Request| {
+                request
+                    .headers
+                    .get("If-Modified-Since")
+                    .map(|v| v.as_bytes())
+                    == Some(modified.as_bytes())
+            })
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let index = client.get_executables_index_raw().await.unwrap();
+        assert_eq!(index, "jq(1.7.1):jq\n");
+    }
+
     #[tokio::test]
     async fn fetches_formula_from_tap_ruby_source() {
         let mock_server = MockServer::start().await;
@@ -1242,6 +1499,25 @@ end
         assert_eq!(formulas[0].versions.stable, "1.2.3");
     }
 
+    #[tokio::test]
+    async fn fetches_executables_index_from_tap_raw_base() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/Homebrew/homebrew-command-not-found/HEAD/executables.txt",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string("jq(1.7.1):jq\n"))
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+            .unwrap()
+            .with_tap_raw_base_url(mock_server.uri());
+        let raw = client.get_executables_index_raw().await.unwrap();
+        assert_eq!(raw, "jq(1.7.1):jq\n");
+    }
+
     #[test]
     fn formula_suggestion_entry_defaults_optional_lists() {
         let entry: FormulaSuggestionEntry = serde_json::from_str(r#"{"name":"python"}"#).unwrap();
@@ -1,5 +1,6 @@
 use rusqlite::{Connection, params};
 use std::path::Path;
+use std::time::Duration;
 
 pub struct ApiCache {
     conn: Connection,
@@ -106,11 +107,38 @@ impl ApiCache {
         Ok(removed)
     }
 
-    pub fn put(&self, url: &str, entry: &CacheEntry) -> Result<(), rusqlite::Error> {
-        let now = std::time::SystemTime::now()
+    /// Number of entries cached longer ago than `max_age`.
+    pub fn count_older_than(&self, max_age: Duration) -> Result<usize, rusqlite::Error> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM api_cache WHERE cached_at < ?1",
+            params![Self::cutoff(max_age)],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Remove entries cached longer ago than `max_age`. Returns the number removed.
+    pub fn prune_older_than(&self, max_age: Duration) -> Result<usize, rusqlite::Error> {
+        let removed = self.conn.execute(
+            "DELETE FROM api_cache WHERE cached_at < ?1",
+            params![Self::cutoff(max_age)],
+        )?;
+        Ok(removed)
+    }
+
+    fn cutoff(max_age: Duration) -> i64 {
+        Self::now().saturating_sub(max_age.as_secs() as i64)
+    }
+
+    fn now() -> i64 {
+        std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_secs() as i64)
-            .unwrap_or(0);
+            .unwrap_or(0)
+    }
+
+    pub fn put(&self, url: &str, entry: &CacheEntry) -> Result<(), rusqlite::Error> {
+        let now = Self::now();
 
         self.conn.execute(
             "INSERT OR REPLACE INTO api_cache (url, etag, last_modified, body, cached_at)
@@ -171,6 +199,30 @@ mod tests {
         assert_eq!(cache.clear().unwrap(), 0);
     }
 
+    #[test]
+    fn prune_older_than_keeps_fresh_entries() {
+        let cache = ApiCache::in_memory().unwrap();
+        let entry = CacheEntry {
+            etag: None,
+            last_modified: None,
+            body: "{}".to_string(),
+        };
+        cache.put("https://example.com/fresh.json", &entry).unwrap();
+        cache
+            .conn
+            .execute(
+                "INSERT INTO api_cache VALUES ('https://example.com/stale.json', NULL, NULL, '{}', 123)",
+                [],
+            )
+            .unwrap();
+
+        let day = Duration::from_secs(86_400);
+        assert_eq!(cache.count_older_than(day).unwrap(), 1);
+        assert_eq!(cache.prune_older_than(day).unwrap(), 1);
+        assert!(cache.get("https://example.com/stale.json").is_none());
+        assert!(cache.get("https://example.com/fresh.json").is_some());
+    }
+
     #[test]
     fn new_database_starts_at_version_1() {
         let cache = ApiCache::in_memory().expect("failed to create cache");
@@ -52,8 +52,9 @@ pub(crate) async fn fetch_download_response_internal(
     };
 
     if !response.status().is_success() {
-        return Err(Error::NetworkFailure {
-            message: format!("HTTP {}", response.status()),
+        return Err(Error::HttpStatus {
+            status: response.status().as_u16(),
+            message: "bottle download failed".to_string(),
         });
     }
 
@@ -19,6 +19,7 @@ use super::auth::{
     fetch_range_response_internal, get_cached_token_for_url_internal,
 };
 use super::single::download_response_internal;
+use super::throttle::Throttle;
 use super::{DownloadProgressCallback, MAX_CHUNK_RETRIES, MAX_CONCURRENT_CHUNKS};
 
 const MIN_CHUNK_SIZE: u64 = 5 * 1024 * 1024;
@@ -32,6 +33,7 @@ struct ChunkDownloadContext<'a> {
     name: Option<String>,
     file_size: u64,
     total_downloaded: Arc<AtomicU64>,
+    throttle: Throttle,
 }
 
 pub(crate) struct ChunkedDownloadContext<'a> {
@@ -44,6 +46,7 @@ pub(crate) struct ChunkedDownloadContext<'a> {
     pub(crate) progress: Option<DownloadProgressCallback>,
     pub(crate) file_size: u64,
     pub(crate) global_semaphore: &'a Arc<Semaphore>,
+    pub(crate) throttle: &'a Throttle,
 }
 
 struct ChunkRange {
@@ -168,6 +171,7 @@ async fn download_chunk(
 
                 while let Some(item) = stream.next().await {
                     let bytes = item.map_err(Error::network("failed to read chunk bytes"))?;
+                    ctx.throttle.consume(bytes.len()).await;
 
                     chunk_data.extend_from_slice(&bytes);
 
@@ -223,6 +227,8 @@ pub(crate) async fn download_with_chunks(
             ctx.expected_sha256,
             ctx.name.clone(),
             ctx.progress.clone(),
+            None,
+            ctx.throttle,
         )
         .await;
     }
@@ -257,6 +263,7 @@ pub(crate) async fn download_with_chunks(
         let url = ctx.url.to_string();
         let global_semaphore = ctx.global_semaphore.clone();
         let total_downloaded = total_downloaded.clone();
+        let throttle = ctx.throttle.clone();
         let progress = ctx.progress.clone();
         let name = ctx.name.clone();
         let chunk_tx = chunk_tx.clone();
@@ -277,6 +284,7 @@ pub(crate) async fn download_with_chunks(
                 name: name.clone(),
                 file_size,
                 total_downloaded: total_downloaded.clone(),
+                throttle: throttle.clone(),
             };
 
             let chunk_data = download_chunk(&chunk_ctx, &chunk).await?;
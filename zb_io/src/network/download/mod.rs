@@ -2,9 +2,11 @@ mod auth;
 mod chunked;
 mod parallel;
 mod single;
+mod throttle;
 
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::progress::InstallProgress;
 
@@ -36,7 +38,12 @@ pub struct DownloadResult {
     pub sha256: String,
     pub blob_path: PathBuf,
     pub index: usize,
+    /// Time from getting a download slot to the blob being on disk. Near
+    /// zero for cache hits and for requests that waited on an identical
+    /// download already in flight.
+    pub elapsed: Duration,
 }
 
 pub use parallel::{DownloadRequest, ParallelDownloader};
 pub use single::Downloader;
+pub use throttle::BandwidthLimit;
@@ -1,13 +1,17 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::sync::{Mutex, Semaphore, mpsc};
 
+use crate::network::retry::RetryPolicy;
 use crate::storage::blob::BlobCache;
+use crate::storage::store::Store;
 use zb_core::Error;
 
 use super::single::Downloader;
+use super::throttle::BandwidthLimit;
 use super::{DownloadProgressCallback, DownloadResult, GLOBAL_DOWNLOAD_CONCURRENCY};
 
 pub struct DownloadRequest {
@@ -49,8 +53,36 @@ impl ParallelDownloader {
         }
     }
 
-    pub fn remove_blob(&self, sha256: &str) -> bool {
-        self.downloader.remove_blob(sha256)
+    /// Set the retry policy for bottle downloads. Must be called before the
+    /// downloader is shared, which is the case for every builder chain.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        if let Some(downloader) = Arc::get_mut(&mut self.downloader) {
+            downloader.retry_policy = retry_policy;
+        }
+        self
+    }
+
+    /// Cap download speed. Like [`Self::with_retry_policy`], must be called
+    /// before the downloader is shared.
+    pub fn with_bandwidth_limit(mut self, bandwidth: BandwidthLimit) -> Self {
+        if let Some(downloader) = Arc::get_mut(&mut self.downloader) {
+            downloader.bandwidth = bandwidth;
+        }
+        self
+    }
+
+    /// See [`Downloader::quarantine_blob`].
+    pub fn quarantine_blob(&self, sha256: &str) -> Option<PathBuf> {
+        self.downloader.quarantine_blob(sha256)
+    }
+
+    pub fn blob_cache(&self) -> &BlobCache {
+        &self.downloader.blob_cache
+    }
+
+    /// See [`Downloader::content_length`].
+    pub async fn content_length(&self, url: &str) -> Result<Option<u64>, Error> {
+        self.downloader.content_length(url).await
     }
 
     pub async fn download_single(
@@ -64,8 +96,10 @@ impl ParallelDownloader {
             self.inflight.clone(),
             request,
             progress,
+            None,
         )
         .await
+        .map(|(path, _)| path)
     }
 
     pub async fn download_all(
@@ -89,7 +123,9 @@ impl ParallelDownloader {
                 let progress = progress.clone();
 
                 tokio::spawn(async move {
-                    Self::download_with_dedup(downloader, semaphore, inflight, req, progress).await
+                    Self::download_with_dedup(downloader, semaphore, inflight, req, progress, None)
+                        .await
+                        .map(|(path, _)| path)
                 })
             })
             .collect();
@@ -107,6 +143,26 @@ impl ParallelDownloader {
         &self,
         requests: Vec<DownloadRequest>,
         progress: Option<DownloadProgressCallback>,
+    ) -> mpsc::Receiver<Result<DownloadResult, Error>> {
+        self.stream_downloads(requests, progress, None)
+    }
+
+    /// Like `download_streaming`, also unpacking each archive into `store`
+    /// while it downloads. See [`Downloader::download_unpacking`].
+    pub fn download_streaming_into(
+        &self,
+        requests: Vec<DownloadRequest>,
+        progress: Option<DownloadProgressCallback>,
+        store: &Store,
+    ) -> mpsc::Receiver<Result<DownloadResult, Error>> {
+        self.stream_downloads(requests, progress, Some(store))
+    }
+
+    fn stream_downloads(
+        &self,
+        requests: Vec<DownloadRequest>,
+        progress: Option<DownloadProgressCallback>,
+        store: Option<&Store>,
     ) -> mpsc::Receiver<Result<DownloadResult, Error>> {
         let (tx, rx) = mpsc::channel(requests.len().max(1));
 
@@ -118,16 +174,20 @@ impl ParallelDownloader {
             let tx = tx.clone();
             let name = req.name.clone();
             let sha256 = req.sha256.clone();
+            let store = store.cloned();
 
             tokio::spawn(async move {
-                let result =
-                    Self::download_with_dedup(downloader, semaphore, inflight, req, progress).await;
+                let result = Self::download_with_dedup(
+                    downloader, semaphore, inflight, req, progress, store,
+                )
+                .await;
                 let _ = tx
-                    .send(result.map(|blob_path| DownloadResult {
+                    .send(result.map(|(blob_path, elapsed)| DownloadResult {
                         name,
                         sha256,
                         blob_path,
                         index,
+                        elapsed,
                     }))
                     .await;
             });
@@ -142,7 +202,8 @@ impl ParallelDownloader {
         inflight: Arc<Mutex<InflightMap>>,
         req: DownloadRequest,
         progress: Option<DownloadProgressCallback>,
-    ) -> Result<PathBuf, Error> {
+        store: Option<Store>,
+    ) -> Result<(PathBuf, Duration), Error> {
         let mut receiver = {
             let mut map = inflight.lock().await;
 
@@ -161,7 +222,9 @@ impl ParallelDownloader {
                 .await
                 .map_err(Error::network("broadcast recv error"))?;
 
-            return result.map_err(|msg| Error::NetworkFailure { message: msg });
+            return result
+                .map(|path| (path, Duration::ZERO))
+                .map_err(|msg| Error::NetworkFailure { message: msg });
         }
 
         let _permit = semaphore
@@ -169,8 +232,15 @@ impl ParallelDownloader {
             .await
             .map_err(Error::network("semaphore error"))?;
 
+        let started = Instant::now();
         let result = downloader
-            .download_with_progress(&req.url, &req.sha256, Some(req.name), progress)
+            .download_unpacking(
+                &req.url,
+                &req.sha256,
+                Some(req.name),
+                progress,
+                store.as_ref(),
+            )
             .await;
 
         {
@@ -184,7 +254,7 @@ impl ParallelDownloader {
             }
         }
 
-        result
+        result.map(|path| (path, started.elapsed()))
     }
 }
 
@@ -7,20 +7,26 @@ use std::time::Duration;
 
 use futures_util::StreamExt;
 use futures_util::future::select_all;
-use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH};
+use reqwest::StatusCode;
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, WWW_AUTHENTICATE};
 use sha2::{Digest, Sha256};
 use tokio::sync::{Notify, RwLock, Semaphore};
-use tracing::warn;
+use tracing::{debug, warn};
 
+use crate::extraction::StreamingExtractor;
+use crate::network::retry::RetryPolicy;
 use crate::network::tls::shared_tls_config;
 use crate::progress::InstallProgress;
 use crate::storage::blob::BlobCache;
+use crate::storage::store::{StagedEntry, Store};
 use zb_core::Error;
 
 use super::auth::{
-    TokenCache, bearer_header, fetch_download_response_internal, get_cached_token_for_url_internal,
+    TokenCache, bearer_header, fetch_bearer_token_internal, fetch_download_response_internal,
+    get_cached_token_for_url_internal,
 };
 use super::chunked::{ChunkedDownloadContext, download_with_chunks, server_supports_ranges};
+use super::throttle::{BandwidthLimit, Throttle};
 use super::{
     CHUNKED_DOWNLOAD_THRESHOLD, DownloadProgressCallback, GLOBAL_DOWNLOAD_CONCURRENCY,
     RACING_CONNECTIONS, RACING_STAGGER_MS,
@@ -57,6 +63,8 @@ pub struct Downloader {
     pub(crate) token_cache: TokenCache,
     pub(crate) global_semaphore: Option<Arc<Semaphore>>,
     tls_config: Arc<rustls::ClientConfig>,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) bandwidth: BandwidthLimit,
 }
 
 impl Downloader {
@@ -87,9 +95,21 @@ impl Downloader {
             token_cache: Arc::new(RwLock::new(HashMap::new())),
             global_semaphore: semaphore,
             tls_config,
+            retry_policy: RetryPolicy::default(),
+            bandwidth: BandwidthLimit::default(),
         }
     }
 
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_bandwidth_limit(mut self, bandwidth: BandwidthLimit) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
     fn create_isolated_client(&self) -> reqwest::Client {
         reqwest::Client::builder()
             .user_agent("zerobrew/0.1")
@@ -106,8 +126,58 @@ impl Downloader {
             .expect("failed to build isolated HTTP client")
     }
 
-    pub fn remove_blob(&self, sha256: &str) -> bool {
-        self.blob_cache.remove_blob(sha256).unwrap_or(false)
+    /// Move a corrupt cached blob to the quarantine so the next download
+    /// fetches it again. Returns where it went.
+    pub fn quarantine_blob(&self, sha256: &str) -> Option<PathBuf> {
+        self.blob_cache.quarantine_blob(sha256).unwrap_or_else(|e| {
+            warn!(sha256, error = %e, "failed to quarantine blob; removing it");
+            let _ = self.blob_cache.remove_blob(sha256);
+            None
+        })
+    }
+
+    /// Size of the file at `url` as reported by a HEAD request, fetching a
+    /// registry token first if the server asks for one. `None` when the
+    /// server does not send a length.
+    pub async fn content_length(&self, url: &str) -> Result<Option<u64>, Error> {
+        let cached_token = get_cached_token_for_url_internal(&self.token_cache, url).await;
+        let mut request = self.client.head(url);
+        if let Some(token) = &cached_token {
+            request = request.header(AUTHORIZATION, bearer_header(token)?);
+        }
+        let mut response = request
+            .send()
+            .await
+            .map_err(Error::network("bottle size request failed"))?;
+
+        if response.status() == StatusCode::UNAUTHORIZED
+            && let Some(challenge) = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|v| v.to_str().ok())
+        {
+            let token =
+                fetch_bearer_token_internal(&self.client, &self.token_cache, challenge).await?;
+            response = self
+                .client
+                .head(url)
+                .header(AUTHORIZATION, bearer_header(&token)?)
+                .send()
+                .await
+                .map_err(Error::network("bottle size request failed"))?;
+        }
+
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus {
+                status: response.status().as_u16(),
+                message: "bottle size request failed".to_string(),
+            });
+        }
+        Ok(response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.parse::<u64>().ok()))
     }
 
     pub async fn download(&self, url: &str, expected_sha256: &str) -> Result<PathBuf, Error> {
@@ -121,8 +191,26 @@ impl Downloader {
         expected_sha256: &str,
         name: Option<String>,
         progress: Option<DownloadProgressCallback>,
+    ) -> Result<PathBuf, Error> {
+        self.download_unpacking(url, expected_sha256, name, progress, None)
+            .await
+    }
+
+    /// Download like `download_with_progress`, unpacking the archive into
+    /// `store` under its checksum as the bytes arrive instead of re-reading
+    /// the cached blob afterwards. Only single-connection downloads are
+    /// unpacked this way; the entry is committed once the checksum matches.
+    pub async fn download_unpacking(
+        &self,
+        url: &str,
+        expected_sha256: &str,
+        name: Option<String>,
+        progress: Option<DownloadProgressCallback>,
+        store: Option<&Store>,
     ) -> Result<PathBuf, Error> {
         if self.blob_cache.has_blob(expected_sha256) {
+            debug!(url, sha256 = expected_sha256, "bottle already cached");
+            let _ = self.blob_cache.touch_blob(expected_sha256);
             if let (Some(cb), Some(n)) = (&progress, &name) {
                 cb(InstallProgress::DownloadCompleted {
                     name: n.clone(),
@@ -133,8 +221,37 @@ impl Downloader {
         }
 
         let alternates = get_alternate_urls(url);
-
-        self.download_with_racing(url, &alternates, expected_sha256, name, progress)
+        debug!(
+            url,
+            ?alternates,
+            sha256 = expected_sha256,
+            "downloading bottle"
+        );
+
+        self.retry_policy
+            .run(
+                || {
+                    self.download_with_racing(
+                        url,
+                        &alternates,
+                        expected_sha256,
+                        name.clone(),
+                        progress.clone(),
+                        store,
+                    )
+                },
+                |attempt, max_attempts, err| {
+                    warn!(url, attempt, max_attempts, error = %err, "retrying download");
+                    if let (Some(cb), Some(n)) = (&progress, &name) {
+                        cb(InstallProgress::DownloadRetrying {
+                            name: n.clone(),
+                            attempt,
+                            max_attempts,
+                            reason: err.to_string(),
+                        });
+                    }
+                },
+            )
             .await
     }
 
@@ -145,7 +262,9 @@ impl Downloader {
         expected_sha256: &str,
         name: Option<String>,
         progress: Option<DownloadProgressCallback>,
+        store: Option<&Store>,
     ) -> Result<PathBuf, Error> {
+        let throttle = self.bandwidth.throttle();
         let (use_chunked, file_size) = {
             let cached_token =
                 get_cached_token_for_url_internal(&self.token_cache, primary_url).await;
@@ -200,6 +319,7 @@ impl Downloader {
                     progress: progress.clone(),
                     file_size: size,
                     global_semaphore: &semaphore,
+                    throttle: &throttle,
                 };
 
                 match download_with_chunks(&ctx).await {
@@ -244,6 +364,8 @@ impl Downloader {
             let done = done.clone();
             let done_notify = done_notify.clone();
             let body_download_gate = body_download_gate.clone();
+            let store = store.cloned();
+            let throttle = throttle.clone();
 
             let delay = Duration::from_millis(idx as u64 * RACING_STAGGER_MS);
 
@@ -309,6 +431,8 @@ impl Downloader {
                     &expected_sha256,
                     name,
                     progress,
+                    store.as_ref(),
+                    &throttle,
                 )
                 .await;
 
@@ -354,6 +478,8 @@ pub(crate) async fn download_response_internal(
     expected_sha256: &str,
     name: Option<String>,
     progress: Option<DownloadProgressCallback>,
+    store: Option<&Store>,
+    throttle: &Throttle,
 ) -> Result<PathBuf, Error> {
     let total_bytes = response
         .headers()
@@ -371,41 +497,60 @@ pub(crate) async fn download_response_internal(
     let mut writer = blob_cache
         .start_write(expected_sha256)
         .map_err(Error::network("failed to create blob writer"))?;
+    let mut unpacking =
+        store.and_then(|store| start_unpacking(store, expected_sha256, total_bytes));
+
+    let received = async {
+        let mut hasher = Sha256::new();
+        let mut stream = response.bytes_stream();
+        let mut downloaded: u64 = 0;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(Error::network("failed to read chunk"))?;
+            throttle.consume(chunk.len()).await;
+
+            downloaded += chunk.len() as u64;
+            hasher.update(&chunk);
+            writer
+                .write_all(&chunk)
+                .map_err(Error::network("failed to write chunk"))?;
+            if let Some((_, extractor)) = &mut unpacking {
+                // Bytes clones share the buffer, so the tee costs no copy.
+                extractor.feed(chunk.clone()).await;
+            }
 
-    let mut hasher = Sha256::new();
-    let mut stream = response.bytes_stream();
-    let mut downloaded: u64 = 0;
+            if let (Some(cb), Some(n)) = (&progress, &name) {
+                cb(InstallProgress::DownloadProgress {
+                    name: n.clone(),
+                    downloaded,
+                    total_bytes,
+                });
+            }
+        }
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(Error::network("failed to read chunk"))?;
+        let actual_hash = crate::checksum::sha256_hex(hasher);
 
-        downloaded += chunk.len() as u64;
-        hasher.update(&chunk);
-        writer
-            .write_all(&chunk)
-            .map_err(Error::network("failed to write chunk"))?;
-
-        if let (Some(cb), Some(n)) = (&progress, &name) {
-            cb(InstallProgress::DownloadProgress {
-                name: n.clone(),
-                downloaded,
-                total_bytes,
+        if actual_hash != expected_sha256 {
+            return Err(Error::ChecksumMismatch {
+                expected: expected_sha256.to_string(),
+                actual: actual_hash,
             });
         }
-    }
 
-    let actual_hash = crate::checksum::sha256_hex(hasher);
-
-    if actual_hash != expected_sha256 {
-        return Err(Error::ChecksumMismatch {
-            expected: expected_sha256.to_string(),
-            actual: actual_hash,
-        });
+        writer
+            .flush()
+            .map_err(Error::network("failed to flush download"))?;
+        Ok(downloaded)
     }
+    .await;
 
-    writer
-        .flush()
-        .map_err(Error::network("failed to flush download"))?;
+    // Always wait for the extractor so it is done writing before a failed
+    // download removes its staging directory.
+    let unpacked = match unpacking {
+        Some((staged, extractor)) => Some((staged, extractor.finish().await)),
+        None => None,
+    };
+    let downloaded = received?;
 
     if let (Some(cb), Some(n)) = (&progress, &name) {
         cb(InstallProgress::DownloadCompleted {
@@ -414,7 +559,48 @@ pub(crate) async fn download_response_internal(
         });
     }
 
-    writer.commit()
+    let blob_path = writer.commit()?;
+    match unpacked {
+        Some((staged, Ok(()))) => {
+            if let Err(error) = staged.commit() {
+                warn!(sha256 = expected_sha256, %error, "failed to commit streamed store entry");
+            }
+        }
+        Some((_, Err(error))) => {
+            debug!(sha256 = expected_sha256, %error, "streaming extraction failed; unpacking from cache");
+        }
+        None => {}
+    }
+    Ok(blob_path)
+}
+
+/// Stage the store entry for `sha256` and start unpacking into it, unless
+/// it already exists, another process is creating it, or every unpack slot
+/// is taken. `total_bytes` picks the extraction limits.
+fn start_unpacking(
+    store: &Store,
+    sha256: &str,
+    total_bytes: Option<u64>,
+) -> Option<(StagedEntry, StreamingExtractor)> {
+    let Some(slot) = store.try_unpack_slot() else {
+        debug!(sha256, "all unpack workers busy; unpacking from cache");
+        return None;
+    };
+    match store.stage_entry(sha256) {
+        Ok(Some(staged)) => {
+            let extractor = StreamingExtractor::spawn(
+                staged.path().to_path_buf(),
+                store.extract_policy(total_bytes),
+            )
+            .holding(slot);
+            Some((staged, extractor))
+        }
+        Ok(None) => None,
+        Err(error) => {
+            debug!(sha256, %error, "cannot stage store entry; unpacking from cache");
+            None
+        }
+    }
 }
 
 #[cfg(test)]
@@ -511,4 +697,48 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn unpacks_into_store_while_downloading() {
+        let mock_server = MockServer::start().await;
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_path("tool/1.0/README").unwrap();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"hello"[..]).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        let tarball = encoder.finish().unwrap();
+        let sha256 = crate::checksum::sha256_hex(Sha256::new_with_prefix(&tarball));
+
+        Mock::given(method("GET"))
+            .and(path("/tool.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(tarball))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+        let downloader = Downloader::new(BlobCache::new(tmp.path()).unwrap());
+        let url = format!("{}/tool.tar.gz", mock_server.uri());
+
+        let wrong = "0".repeat(64);
+        downloader
+            .download_unpacking(&url, &wrong, None, None, Some(&store))
+            .await
+            .unwrap_err();
+        assert!(!store.has_entry(&wrong));
+
+        let blob = downloader
+            .download_unpacking(&url, &sha256, None, None, Some(&store))
+            .await
+            .unwrap();
+        assert!(blob.exists());
+        assert_eq!(
+            std::fs::read_to_string(store.entry_path(&sha256).join("tool/1.0/README")).unwrap(),
+            "hello"
+        );
+    }
 }
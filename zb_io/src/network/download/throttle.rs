@@ -0,0 +1,122 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Caps on how fast bottles download, in bytes per second: one for each
+/// download on its own, one for all of them together.
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthLimit {
+    per_download: Option<u64>,
+    global: Option<RateLimit>,
+}
+
+impl BandwidthLimit {
+    pub fn new(per_download: Option<u64>, global: Option<u64>) -> Self {
+        Self {
+            per_download,
+            global: global.map(RateLimit::new),
+        }
+    }
+
+    /// The throttle for one download: a bucket of its own, plus the one
+    /// every download shares.
+    pub(crate) fn throttle(&self) -> Throttle {
+        Throttle {
+            limits: self
+                .per_download
+                .map(RateLimit::new)
+                .into_iter()
+                .chain(self.global.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Holds a download's stream back to its rate limits. Clones share them,
+/// so the chunks and racing connections of one download count together.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Throttle {
+    limits: Vec<RateLimit>,
+}
+
+impl Throttle {
+    /// Wait until `bytes` more may be read.
+    pub(crate) async fn consume(&self, bytes: usize) {
+        for limit in &self.limits {
+            limit.acquire(bytes).await;
+        }
+    }
+}
+
+/// A token bucket refilled at `rate` bytes per second, holding at most a
+/// second's worth. Clones share the bucket.
+#[derive(Debug, Clone)]
+struct RateLimit {
+    rate: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimit {
+    fn new(rate: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        Self {
+            rate,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: rate,
+                refilled: Instant::now(),
+            })),
+        }
+    }
+
+    /// Take `bytes` from the bucket. A read larger than what is left puts
+    /// the bucket in debt, and the caller waits for it to refill; later
+    /// callers wait behind that debt too.
+    async fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.rate;
+            bucket.tokens = (bucket.tokens + refill).min(self.rate) - bytes as f64;
+            bucket.refilled = now;
+            (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / self.rate))
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_past_the_burst_wait_for_the_rate() {
+        let throttle = BandwidthLimit::new(Some(10_000), None).throttle();
+        let start = Instant::now();
+        throttle.consume(10_000).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        throttle.consume(3_000).await;
+        assert!(start.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn downloads_share_the_global_limit_but_not_their_own() {
+        let limit = BandwidthLimit::new(Some(10_000), Some(10_000));
+        let first = limit.throttle();
+        let second = limit.throttle();
+        let start = Instant::now();
+        first.consume(10_000).await;
+        // Its own bucket is full, but the global one was just drained.
+        second.consume(3_000).await;
+        assert!(start.elapsed() >= Duration::from_millis(250));
+
+        assert!(BandwidthLimit::default().throttle().limits.is_empty());
+    }
+}
@@ -0,0 +1,555 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use zb_core::Error;
+
+use crate::checksum::sha256_hex;
+use crate::extraction::{extract_archive, is_archive};
+
+static VERSION_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|[-_/.])v?([0-9]+(?:\.[0-9]+)+(?:[-.]?(?:alpha|beta|rc)[0-9]*)?)(?:$|[-_/.])")
+        .expect("VERSION_RE must compile")
+});
+static GITHUB_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^https://github\.com/([^/]+)/([^/]+)/").expect("GITHUB_RE must compile")
+});
+
+/// Archive suffixes dropped from a download's file name before reading a
+/// name and version out of it.
+const ARCHIVE_SUFFIXES: &[&str] = &[
+    ".tar.gz", ".tgz", ".tar.xz", ".txz", ".tar.bz2", ".tbz", ".tar.zst", ".tar", ".zip",
+];
+
+/// A tap formula skeleton for one downloadable artifact, as `zb create`
+/// writes it. The rendered Ruby is what zerobrew's tap parser reads: no
+/// bottle block, and an `install` that either builds the source or only
+/// `bin.install`s prebuilt binaries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaTemplate {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub sha256: String,
+    pub homepage: Option<String>,
+    pub kind: ArtifactKind,
+}
+
+/// What the downloaded artifact holds, and so how the formula installs it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArtifactKind {
+    /// Sources built with the detected build system.
+    Source(SourceBuild),
+    /// Prebuilt executables, as `(path in the download, installed name)`.
+    Binary(Vec<(String, String)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceBuild {
+    Autotools,
+    Cmake,
+    Meson,
+    Go,
+    Cargo,
+    Make,
+    /// Nothing recognizable; the author fills in `install`.
+    Unknown,
+}
+
+impl FormulaTemplate {
+    /// Download `url` and describe it as a formula. `name` and `version`
+    /// override what the URL suggests.
+    pub async fn from_url(
+        url: &str,
+        name: Option<String>,
+        version: Option<String>,
+    ) -> Result<Self, Error> {
+        let bytes = fetch(url).await?;
+        let (inferred_name, inferred_version) = infer_name_and_version(url);
+        let name = name
+            .or(inferred_name)
+            .ok_or_else(|| Error::InvalidArgument {
+                message: format!("cannot tell the formula name from {url}; pass --name"),
+            })?;
+        let version = version
+            .or(inferred_version)
+            .ok_or_else(|| Error::InvalidArgument {
+                message: format!("cannot tell the version from {url}; pass --version"),
+            })?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(&bytes);
+        let file_name = url_file_name(url).unwrap_or(&name).to_string();
+        Ok(Self {
+            kind: inspect_artifact(&bytes, &file_name, &name)?,
+            homepage: GITHUB_RE
+                .captures(url)
+                .map(|cap| format!("https://github.com/{}/{}", &cap[1], &cap[2])),
+            sha256: sha256_hex(hasher),
+            url: url.to_string(),
+            name,
+            version,
+        })
+    }
+
+    /// The formula as Ruby source.
+    pub fn render(&self) -> String {
+        let mut rb = String::new();
+        let _ = writeln!(rb, "class {} < Formula", class_name(&self.name));
+        let _ = writeln!(rb, "  desc \"\"");
+        let _ = writeln!(
+            rb,
+            "  homepage \"{}\"",
+            self.homepage.as_deref().unwrap_or("")
+        );
+        let _ = writeln!(rb, "  url \"{}\"", self.url);
+        let _ = writeln!(rb, "  version \"{}\"", self.version);
+        let _ = writeln!(rb, "  sha256 \"{}\"", self.sha256);
+
+        let mut install = Vec::new();
+        match &self.kind {
+            ArtifactKind::Binary(binaries) => {
+                for (source, target) in binaries {
+                    install.push(if source == target {
+                        format!("bin.install \"{source}\"")
+                    } else {
+                        format!("bin.install \"{source}\" => \"{target}\"")
+                    });
+                }
+            }
+            ArtifactKind::Source(build) => {
+                let deps: &[&str] = match build {
+                    SourceBuild::Cmake => &["cmake"],
+                    SourceBuild::Meson => &["meson", "ninja"],
+                    SourceBuild::Go => &["go"],
+                    SourceBuild::Cargo => &["rust"],
+                    _ => &[],
+                };
+                if !deps.is_empty() {
+                    rb.push('\n');
+                }
+                for dep in deps {
+                    let _ = writeln!(rb, "  depends_on \"{dep}\" => :build");
+                }
+                install.extend(build.install_lines().iter().map(|line| line.to_string()));
+            }
+        }
+
+        rb.push_str("\n  def install\n");
+        for line in &install {
+            let _ = writeln!(rb, "    {line}");
+        }
+        rb.push_str("  end\n");
+
+        let program = match &self.kind {
+            ArtifactKind::Binary(binaries) => binaries[0].1.as_str(),
+            ArtifactKind::Source(_) => self.name.as_str(),
+        };
+        let _ = write!(
+            rb,
+            "\n  test do\n    system bin/\"{program}\", \"--version\"\n  end\nend\n"
+        );
+        rb
+    }
+}
+
+impl SourceBuild {
+    pub fn label(self) -> &'static str {
+        match self {
+            SourceBuild::Autotools => "autotools",
+            SourceBuild::Cmake => "CMake",
+            SourceBuild::Meson => "Meson",
+            SourceBuild::Go => "Go",
+            SourceBuild::Cargo => "Cargo",
+            SourceBuild::Make => "make",
+            SourceBuild::Unknown => "unknown",
+        }
+    }
+
+    fn install_lines(self) -> &'static [&'static str] {
+        match self {
+            SourceBuild::Autotools => &[
+                "system \"./configure\", *std_configure_args",
+                "system \"make\", \"install\"",
+            ],
+            SourceBuild::Cmake => &[
+                "system \"cmake\", \"-S\", \".\", \"-B\", \"build\", *std_cmake_args",
+                "system \"cmake\", \"--build\", \"build\"",
+                "system \"cmake\", \"--install\", \"build\"",
+            ],
+            SourceBuild::Meson => &[
+                "system \"meson\", \"setup\", \"build\", *std_meson_args",
+                "system \"meson\", \"compile\", \"-C\", \"build\"",
+                "system \"meson\", \"install\", \"-C\", \"build\"",
+            ],
+            SourceBuild::Go => &["system \"go\", *std_go_args(ldflags: \"-s -w\")"],
+            SourceBuild::Cargo => &[
+                "system \"cargo\", \"install\", \"--locked\", \"--root\", prefix, \"--path\", \".\"",
+            ],
+            SourceBuild::Make => &["system \"make\", \"install\", \"PREFIX=#{prefix}\""],
+            SourceBuild::Unknown => &["# Build and install the project into prefix here."],
+        }
+    }
+}
+
+/// The formula name and version a download URL suggests. GitHub release
+/// and tag URLs name the repository; otherwise the name is whatever
+/// precedes the version in the file name.
+pub fn infer_name_and_version(url: &str) -> (Option<String>, Option<String>) {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = url_file_name(path).unwrap_or("");
+    let stem = ARCHIVE_SUFFIXES
+        .iter()
+        .find_map(|suffix| file.strip_suffix(suffix))
+        .unwrap_or(file);
+
+    let version_in = |text: &str| {
+        VERSION_RE
+            .captures(text)
+            .and_then(|cap| cap.get(1))
+            .map(|m| (m.start(), m.as_str().to_string()))
+    };
+    let version = version_in(stem)
+        .map(|(_, version)| version)
+        .or_else(|| version_in(path).map(|(_, version)| version));
+
+    let name = match GITHUB_RE.captures(path) {
+        Some(cap) => Some(cap[2].to_string()),
+        None => {
+            let end = version_in(stem).map_or(stem.len(), |(start, _)| start);
+            let head = &stem[..end];
+            let name = head
+                .strip_suffix('v')
+                .unwrap_or(head)
+                .trim_end_matches(['-', '_', '.']);
+            (!name.is_empty()).then(|| name.to_string())
+        }
+    };
+    (name.map(|name| name.to_lowercase()), version)
+}
+
+fn url_file_name(url: &str) -> Option<&str> {
+    url.rsplit('/').find(|segment| !segment.is_empty())
+}
+
+/// The Ruby class Homebrew expects for formula `name`: `foo-bar@2` is
+/// `FooBarAT2`.
+fn class_name(name: &str) -> String {
+    name.replace('@', "AT")
+        .replace('+', "x")
+        .split(['-', '_', '.'])
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_uppercase().chain(chars).collect()
+            })
+        })
+        .collect()
+}
+
+async fn fetch(url: &str) -> Result<Vec<u8>, Error> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return std::fs::read(path).map_err(Error::file("failed to read artifact"));
+    }
+    let client = reqwest::Client::builder()
+        .user_agent("zerobrew/0.1")
+        .use_preconfigured_tls((*crate::network::tls::shared_tls_config()).clone())
+        .build()
+        .map_err(Error::network("failed to build HTTP client"))?;
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(Error::network("failed to download artifact"))?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus {
+            status: response.status().as_u16(),
+            message: format!("download of {url} failed"),
+        });
+    }
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(Error::network("failed to read artifact"))?;
+    Ok(bytes.to_vec())
+}
+
+/// Unpack the download to tell sources from prebuilt binaries. A download
+/// that is not an archive is the binary itself.
+fn inspect_artifact(bytes: &[u8], file_name: &str, name: &str) -> Result<ArtifactKind, Error> {
+    let tmp = tempfile::tempdir().map_err(Error::file("failed to create temp dir"))?;
+    let download = tmp.path().join(file_name);
+    std::fs::write(&download, bytes).map_err(Error::file("failed to write artifact"))?;
+    if !is_archive(&download)? {
+        return Ok(ArtifactKind::Binary(vec![(
+            file_name.to_string(),
+            name.to_string(),
+        )]));
+    }
+
+    let unpacked = tmp.path().join("unpacked");
+    std::fs::create_dir(&unpacked).map_err(Error::file("failed to create temp dir"))?;
+    extract_archive(&download, &unpacked)?;
+    // Homebrew steps into a lone top-level directory before `install`.
+    let mut root = unpacked;
+    if let Ok(entries) = std::fs::read_dir(&root) {
+        let entries: Vec<_> = entries.flatten().collect();
+        if let [entry] = entries.as_slice()
+            && entry.path().is_dir()
+        {
+            root = entry.path();
+        }
+    }
+
+    let has = |file: &str| root.join(file).exists();
+    let build = if has("CMakeLists.txt") {
+        Some(SourceBuild::Cmake)
+    } else if has("meson.build") {
+        Some(SourceBuild::Meson)
+    } else if has("go.mod") {
+        Some(SourceBuild::Go)
+    } else if has("Cargo.toml") {
+        Some(SourceBuild::Cargo)
+    } else if has("configure") {
+        Some(SourceBuild::Autotools)
+    } else if has("Makefile") {
+        Some(SourceBuild::Make)
+    } else {
+        None
+    };
+    if let Some(build) = build {
+        return Ok(ArtifactKind::Source(build));
+    }
+
+    let binaries = executables(&root);
+    if binaries.is_empty() {
+        return Ok(ArtifactKind::Source(SourceBuild::Unknown));
+    }
+    Ok(ArtifactKind::Binary(binaries))
+}
+
+/// Executable files at the top of `root` or in its `bin`, sorted.
+fn executables(root: &Path) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    for dir in ["", "bin"] {
+        let Ok(entries) = std::fs::read_dir(root.join(dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() || !is_executable(&metadata) {
+                continue;
+            }
+            let file = entry.file_name().to_string_lossy().into_owned();
+            let source = if dir.is_empty() {
+                file.clone()
+            } else {
+                format!("{dir}/{file}")
+            };
+            found.push((source, file));
+        }
+    }
+    found.sort();
+    found
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::tap_formula::{TapFormulaRef, parse_tap_formula_ruby};
+
+    fn tarball(files: &[(&str, &str, u32)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, body, mode) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(body.len() as u64);
+            header.set_mode(*mode);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, body.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn parse(template: &FormulaTemplate) -> zb_core::Formula {
+        let spec = TapFormulaRef {
+            owner: "acme".to_string(),
+            repo: "tools".to_string(),
+            formula: template.name.clone(),
+        };
+        parse_tap_formula_ruby(&spec, &template.render()).unwrap()
+    }
+
+    #[test]
+    fn infers_name_and_version_from_urls() {
+        let cases = [
+            (
+                "https://github.com/acme/widget/releases/download/v1.4.2/widget_1.4.2_linux_amd64.tar.gz",
+                "widget",
+                "1.4.2",
+            ),
+            (
+                "https://github.com/acme/widget/archive/refs/tags/v2.0.0.tar.gz",
+                "widget",
+                "2.0.0",
+            ),
+            (
+                "https://ftp.gnu.org/gnu/hello/hello-2.12.1.tar.gz",
+                "hello",
+                "2.12.1",
+            ),
+            (
+                "https://example.com/dl/Tool_v3.1-rc1.zip",
+                "tool",
+                "3.1-rc1",
+            ),
+        ];
+        for (url, name, version) in cases {
+            assert_eq!(
+                infer_name_and_version(url),
+                (Some(name.to_string()), Some(version.to_string())),
+                "{url}"
+            );
+        }
+        assert_eq!(
+            infer_name_and_version("https://example.com/tool"),
+            (Some("tool".to_string()), None)
+        );
+    }
+
+    #[test]
+    fn class_names_follow_homebrew() {
+        assert_eq!(class_name("widget"), "Widget");
+        assert_eq!(class_name("foo-bar_baz"), "FooBarBaz");
+        assert_eq!(class_name("python@3.12"), "PythonAT312");
+        assert_eq!(class_name("c++fmt"), "Cxxfmt");
+    }
+
+    #[test]
+    fn detects_build_systems_and_binaries() {
+        let source = tarball(&[
+            ("hello-1.0/configure", "#!/bin/sh", 0o755),
+            ("hello-1.0/main.c", "", 0o644),
+        ]);
+        assert_eq!(
+            inspect_artifact(&source, "hello-1.0.tar", "hello").unwrap(),
+            ArtifactKind::Source(SourceBuild::Autotools)
+        );
+
+        let cmake = tarball(&[("CMakeLists.txt", "", 0o644), ("configure", "", 0o755)]);
+        assert_eq!(
+            inspect_artifact(&cmake, "x.tar", "x").unwrap(),
+            ArtifactKind::Source(SourceBuild::Cmake)
+        );
+
+        let release = tarball(&[
+            ("widget-1.4.2/widget", "\x7fELF", 0o755),
+            ("widget-1.4.2/bin/widgetctl", "\x7fELF", 0o755),
+            ("widget-1.4.2/README.md", "", 0o644),
+        ]);
+        assert_eq!(
+            inspect_artifact(&release, "widget.tar", "widget").unwrap(),
+            ArtifactKind::Binary(vec![
+                ("bin/widgetctl".to_string(), "widgetctl".to_string()),
+                ("widget".to_string(), "widget".to_string()),
+            ])
+        );
+
+        assert_eq!(
+            inspect_artifact(b"\x7fELF raw", "widget-linux-amd64", "widget").unwrap(),
+            ArtifactKind::Binary(vec![(
+                "widget-linux-amd64".to_string(),
+                "widget".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn rendered_formulas_parse_back() {
+        let binary = FormulaTemplate {
+            name: "widget".to_string(),
+            version: "1.4.2".to_string(),
+            url: "https://github.com/acme/widget/releases/download/v1.4.2/widget-linux-amd64"
+                .to_string(),
+            sha256: "a".repeat(64),
+            homepage: Some("https://github.com/acme/widget".to_string()),
+            kind: ArtifactKind::Binary(vec![(
+                "widget-linux-amd64".to_string(),
+                "widget".to_string(),
+            )]),
+        };
+        let rendered = binary.render();
+        assert!(rendered.starts_with("class Widget < Formula\n"));
+        assert!(rendered.contains("    bin.install \"widget-linux-amd64\" => \"widget\"\n"));
+        let formula = parse(&binary);
+        assert_eq!(formula.versions.stable, "1.4.2");
+        assert_eq!(
+            formula.homepage.as_deref(),
+            Some("https://github.com/acme/widget")
+        );
+        assert_eq!(formula.desc, None);
+        let selected = zb_core::select_binary(&formula, std::env::consts::ARCH).unwrap();
+        assert_eq!(selected.url, binary.url);
+        assert_eq!(selected.sha256, binary.sha256);
+        assert_eq!(selected.binaries[0].target, "widget");
+
+        let source = FormulaTemplate {
+            kind: ArtifactKind::Source(SourceBuild::Cmake),
+            url: "https://example.com/widget-1.4.2.tar.gz".to_string(),
+            homepage: None,
+            ..binary
+        };
+        let formula = parse(&source);
+        assert!(formula.binary.is_none());
+        assert_eq!(formula.build_dependencies, ["cmake"]);
+        let stable = formula.source_url().unwrap();
+        assert_eq!(stable.url, source.url);
+        assert_eq!(stable.checksum.as_deref(), Some(source.sha256.as_str()));
+    }
+
+    #[tokio::test]
+    async fn from_url_hashes_the_download() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("hello-2.12.1.tar");
+        let bytes = tarball(&[("hello-2.12.1/configure", "#!/bin/sh", 0o755)]);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let template = FormulaTemplate::from_url(&format!("file://{}", path.display()), None, None)
+            .await
+            .unwrap();
+        assert_eq!(template.name, "hello");
+        assert_eq!(template.version, "2.12.1");
+        let mut hasher = Sha256::new();
+        hasher.update(&bytes);
+        assert_eq!(template.sha256, sha256_hex(hasher));
+        assert_eq!(template.kind, ArtifactKind::Source(SourceBuild::Autotools));
+
+        let named = FormulaTemplate::from_url(
+            &format!("file://{}", path.display()),
+            Some("greeter".to_string()),
+            Some("3.0".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (named.name.as_str(), named.version.as_str()),
+            ("greeter", "3.0")
+        );
+    }
+}
@@ -1,12 +1,27 @@
+pub mod analytics;
 pub mod api;
 pub mod cache;
 pub mod download;
+pub mod formula_template;
+pub mod osv;
+pub mod release;
+pub mod retry;
+pub(crate) mod ruby_expr;
 pub mod suggest;
 pub mod tap_formula;
 pub(crate) mod tls;
 
+pub use analytics::{AnalyticsPeriod, InstallCount, parse_install_counts};
 pub use api::ApiClient;
 pub use cache::{ApiCache, CacheEntry};
 pub use download::{
-    DownloadProgressCallback, DownloadRequest, DownloadResult, Downloader, ParallelDownloader,
+    BandwidthLimit, DownloadProgressCallback, DownloadRequest, DownloadResult, Downloader,
+    ParallelDownloader,
 };
+pub use formula_template::{ArtifactKind, FormulaTemplate, SourceBuild, infer_name_and_version};
+pub use osv::{OSV_API_URL, OsvClient, Vulnerability, parse_vulnerabilities};
+pub use release::{
+    RELEASE_DOWNLOAD_URL, RELEASE_REPO, ReleaseClient, StagedExecutable, is_newer_release,
+    matches_release, release_asset_name, replace_executable, stage_executable, verify_attestation,
+};
+pub use retry::{RetryOn, RetryPolicy};
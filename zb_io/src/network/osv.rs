@@ -0,0 +1,396 @@
+use std::cmp::Ordering;
+
+use serde::Deserialize;
+use zb_core::Error;
+
+/// The OSV vulnerability database that `zb audit` checks packages against.
+pub const OSV_API_URL: &str = "https://api.osv.dev";
+
+/// The OSV ecosystem packages are looked up in. OSV has no Homebrew
+/// ecosystem; Bitnami's records are keyed by upstream package names and
+/// versions, which is what formulas package, where crates.io, npm or
+/// Debian records for a same-named package would match the wrong thing.
+pub const OSV_ECOSYSTEM: &str = "Bitnami";
+
+/// Fetches vulnerability records for packages from the OSV API.
+pub struct OsvClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl OsvClient {
+    pub fn new() -> Self {
+        Self::with_base_url(OSV_API_URL.to_string())
+    }
+
+    pub fn with_base_url(base_url: String) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent("zerobrew/0.1")
+            .use_preconfigured_tls((*crate::network::tls::shared_tls_config()).clone())
+            .build()
+            .expect("failed to build HTTP client");
+        Self { client, base_url }
+    }
+
+    /// Every vulnerability recorded for the package `name` in
+    /// [`OSV_ECOSYSTEM`], as a JSON array of OSV entries, following
+    /// pagination.
+    pub async fn package_vulnerabilities(&self, name: &str) -> Result<String, Error> {
+        let url = format!("{}/v1/query", self.base_url);
+        let mut vulns = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut query =
+                serde_json::json!({ "package": { "name": name, "ecosystem": OSV_ECOSYSTEM } });
+            if let Some(token) = &page_token {
+                query["page_token"] = token.clone().into();
+            }
+            let response = self
+                .client
+                .post(&url)
+                .json(&query)
+                .send()
+                .await
+                .map_err(Error::network("failed to query vulnerability database"))?;
+            if !response.status().is_success() {
+                return Err(Error::HttpStatus {
+                    status: response.status().as_u16(),
+                    message: format!("vulnerability query for {name} failed"),
+                });
+            }
+            let page: QueryPage = response
+                .json()
+                .await
+                .map_err(Error::network("failed to read vulnerability records"))?;
+            vulns.extend(page.vulns);
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => break,
+            }
+        }
+        Ok(serde_json::Value::Array(vulns).to_string())
+    }
+}
+
+impl Default for OsvClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Deserialize)]
+struct QueryPage {
+    #[serde(default)]
+    vulns: Vec<serde_json::Value>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+/// One OSV entry, trimmed to what matching a version needs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Vulnerability {
+    pub id: String,
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Other identifiers of the same issue, usually its CVE.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    affected: Vec<Affected>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Affected {
+    #[serde(default)]
+    package: Option<AffectedPackage>,
+    #[serde(default)]
+    ranges: Vec<AffectedRange>,
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AffectedPackage {
+    name: String,
+    #[serde(default)]
+    ecosystem: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AffectedRange {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    events: Vec<RangeEvent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RangeEvent {
+    introduced: Option<String>,
+    fixed: Option<String>,
+    last_affected: Option<String>,
+}
+
+/// Parse the JSON array returned by [`OsvClient::package_vulnerabilities`].
+pub fn parse_vulnerabilities(json: &str) -> Result<Vec<Vulnerability>, Error> {
+    serde_json::from_str(json).map_err(|e| Error::NetworkFailure {
+        message: format!("failed to parse vulnerability records: {e}"),
+    })
+}
+
+impl Vulnerability {
+    /// Whether `version` of the package `name` is affected. Git commit
+    /// ranges are ignored: installed kegs only know their version.
+    pub fn affects(&self, name: &str, version: &str) -> bool {
+        self.affected_entries(name)
+            .any(|affected| affected.contains(version))
+    }
+
+    /// The earliest version after `version` that fixes this, if known.
+    pub fn fixed_after(&self, name: &str, version: &str) -> Option<String> {
+        self.affected_entries(name)
+            .flat_map(|affected| &affected.ranges)
+            .filter(|range| range.is_versioned())
+            .flat_map(|range| &range.events)
+            .filter_map(|event| event.fixed.as_deref())
+            .filter(|fixed| compare_versions(fixed, version) == Ordering::Greater)
+            .min_by(|a, b| compare_versions(a, b))
+            .map(str::to_string)
+    }
+
+    /// The entries for `name` in [`OSV_ECOSYSTEM`]; a record can also list
+    /// same-named packages of other ecosystems, whose versions mean
+    /// something else.
+    fn affected_entries<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Affected> {
+        self.affected.iter().filter(move |affected| {
+            affected.package.as_ref().is_some_and(|package| {
+                package.ecosystem == OSV_ECOSYSTEM && package.name.eq_ignore_ascii_case(name)
+            })
+        })
+    }
+}
+
+impl Affected {
+    fn contains(&self, version: &str) -> bool {
+        self.versions.iter().any(|v| v == version)
+            || self
+                .ranges
+                .iter()
+                .filter(|range| range.is_versioned())
+                .any(|range| range.contains(version))
+    }
+}
+
+impl AffectedRange {
+    fn is_versioned(&self) -> bool {
+        matches!(self.kind.as_str(), "ECOSYSTEM" | "SEMVER")
+    }
+
+    /// Walk the events in version order: each `introduced` at or below
+    /// `version` opens the range, each `fixed` at or below it (or
+    /// `last_affected` below it) closes it again.
+    fn contains(&self, version: &str) -> bool {
+        let mut events: Vec<(&str, &RangeEvent)> = self
+            .events
+            .iter()
+            .filter_map(|event| {
+                let at = event
+                    .introduced
+                    .as_deref()
+                    .or(event.fixed.as_deref())
+                    .or(event.last_affected.as_deref())?;
+                Some((at, event))
+            })
+            .collect();
+        events.sort_by(|(a, _), (b, _)| match (*a, *b) {
+            ("0", "0") => Ordering::Equal,
+            ("0", _) => Ordering::Less,
+            (_, "0") => Ordering::Greater,
+            (a, b) => compare_versions(a, b),
+        });
+
+        let mut affected = false;
+        for (at, event) in events {
+            // OSV introduces at `0` for "since the first release".
+            let order = match at {
+                "0" => Ordering::Less,
+                at => compare_versions(at, version),
+            };
+            if event.introduced.is_some() {
+                affected |= order != Ordering::Greater;
+            } else if (event.fixed.is_some() && order != Ordering::Greater)
+                || (event.last_affected.is_some() && order == Ordering::Less)
+            {
+                affected = false;
+            }
+        }
+        affected
+    }
+}
+
+/// Compare two versions piece by piece: numbers numerically, words
+/// alphabetically. A trailing word marks a pre-release, so `1.0rc1` sorts
+/// before `1.0`, which sorts before `1.0.1`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (version_pieces(a), version_pieces(b));
+    for i in 0..a.len().max(b.len()) {
+        let order = match (a.get(i), b.get(i)) {
+            (Some(Piece::Number(x)), Some(Piece::Number(y))) => x.cmp(y),
+            (Some(Piece::Word(x)), Some(Piece::Word(y))) => x.cmp(y),
+            (Some(Piece::Number(_)), Some(Piece::Word(_))) => Ordering::Greater,
+            (Some(Piece::Word(_)), Some(Piece::Number(_))) => Ordering::Less,
+            (Some(Piece::Number(_)), None) => Ordering::Greater,
+            (Some(Piece::Word(_)), None) => Ordering::Less,
+            (None, Some(Piece::Number(_))) => Ordering::Less,
+            (None, Some(Piece::Word(_))) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    Ordering::Equal
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Piece {
+    Number(u64),
+    Word(String),
+}
+
+fn version_pieces(version: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let flush = |current: &mut String, pieces: &mut Vec<Piece>| {
+        if current.is_empty() {
+            return;
+        }
+        let piece = match current.parse() {
+            Ok(n) => Piece::Number(n),
+            Err(_) => Piece::Word(current.to_lowercase()),
+        };
+        pieces.push(piece);
+        current.clear();
+    };
+    for c in version.chars() {
+        let boundary = current
+            .chars()
+            .last()
+            .is_some_and(|last| last.is_ascii_digit() != c.is_ascii_digit());
+        if !c.is_ascii_alphanumeric() || boundary {
+            flush(&mut current, &mut pieces);
+        }
+        if c.is_ascii_alphanumeric() {
+            current.push(c);
+        }
+    }
+    flush(&mut current, &mut pieces);
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    const RECORDS: &str = r#"[
+        {
+            "id": "OSV-2024-1",
+            "summary": "heap overflow in the parser",
+            "aliases": ["CVE-2024-0001"],
+            "affected": [{
+                "package": {"name": "jq", "ecosystem": "Bitnami"},
+                "ranges": [
+                    {"type": "GIT", "events": [{"introduced": "abc123"}]},
+                    {"type": "ECOSYSTEM", "events": [
+                        {"introduced": "0"}, {"fixed": "1.7"},
+                        {"introduced": "1.7.1"}, {"fixed": "1.7.2"}
+                    ]}
+                ],
+                "versions": ["1.5rc2"]
+            }, {
+                "package": {"name": "jq", "ecosystem": "npm"},
+                "ranges": [{"type": "SEMVER", "events": [{"introduced": "0"}]}]
+            }]
+        },
+        {
+            "id": "OSV-2024-2",
+            "affected": [{
+                "package": {"name": "jq", "ecosystem": "Bitnami"},
+                "ranges": [{"type": "SEMVER", "events": [
+                    {"introduced": "1.6"}, {"last_affected": "1.6.2"}
+                ]}]
+            }]
+        }
+    ]"#;
+
+    #[test]
+    fn ranges_match_installed_versions() {
+        let vulns = parse_vulnerabilities(RECORDS).unwrap();
+        let (overflow, other) = (&vulns[0], &vulns[1]);
+
+        for version in ["1.5", "1.6", "1.7.1"] {
+            assert!(overflow.affects("jq", version), "{version}");
+        }
+        // The npm package of the same name does not count.
+        for version in ["1.7", "1.7.2", "1.8"] {
+            assert!(!overflow.affects("jq", version), "{version}");
+        }
+        assert!(!overflow.affects("gojq", "1.5"));
+        assert_eq!(overflow.fixed_after("jq", "1.6").as_deref(), Some("1.7"));
+        assert_eq!(
+            overflow.fixed_after("jq", "1.7.1").as_deref(),
+            Some("1.7.2")
+        );
+
+        assert!(other.affects("jq", "1.6.2"));
+        assert!(!other.affects("jq", "1.6.3"));
+        assert!(!other.affects("jq", "1.5"));
+        assert_eq!(other.fixed_after("jq", "1.6"), None);
+    }
+
+    #[test]
+    fn versions_compare_piece_by_piece() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0rc1", "1.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("3.0.1a", "3.0.1b"), Ordering::Less);
+        assert_eq!(compare_versions("0", "0.1"), Ordering::Less);
+        assert_eq!(compare_versions("2.4.0", "2.4"), Ordering::Greater);
+    }
+
+    #[tokio::test]
+    async fn pages_are_followed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/query"))
+            .and(body_partial_json(serde_json::json!({"page_token": "next"})))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"vulns": [{"id": "OSV-2"}]})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/query"))
+            .and(body_partial_json(
+                serde_json::json!({"package": {"name": "jq", "ecosystem": "Bitnami"}}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                serde_json::json!({"vulns": [{"id": "OSV-1"}], "next_page_token": "next"}),
+            ))
+            .mount(&server)
+            .await;
+
+        let client = OsvClient::with_base_url(server.uri());
+        let json = client.package_vulnerabilities("jq").await.unwrap();
+        let ids: Vec<String> = parse_vulnerabilities(&json)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        assert_eq!(ids, ["OSV-1", "OSV-2"]);
+    }
+}
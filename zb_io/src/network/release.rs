@@ -0,0 +1,376 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zb_core::Error;
+
+use crate::checksum::verify_sha256_bytes;
+
+/// Where `install.sh` and `zb self update` fetch release binaries from.
+pub const RELEASE_DOWNLOAD_URL: &str =
+    "https://github.com/lucasgelfond/zerobrew/releases/latest/download";
+
+/// The repository releases are built in, whose build provenance
+/// attestations `zb self update` verifies.
+pub const RELEASE_REPO: &str = "lucasgelfond/zerobrew";
+
+/// Name of the release asset holding `binary` (`zb` or `zbx`) for this
+/// platform, matching the names the release workflow publishes.
+pub fn release_asset_name(binary: &str) -> Option<String> {
+    let os = match std::env::consts::OS {
+        "macos" => "darwin",
+        "linux" => "linux",
+        _ => return None,
+    };
+    let arch = match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        "x86_64" => "x64",
+        _ => return None,
+    };
+    Some(format!("{binary}-{os}-{arch}"))
+}
+
+/// Downloads release binaries, checking each against the `SHA256SUMS` file
+/// published with the release.
+pub struct ReleaseClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl ReleaseClient {
+    pub fn new() -> Self {
+        Self::with_base_url(RELEASE_DOWNLOAD_URL.to_string())
+    }
+
+    pub fn with_base_url(base_url: String) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent("zerobrew/0.1")
+            .use_preconfigured_tls((*crate::network::tls::shared_tls_config()).clone())
+            .build()
+            .expect("failed to build HTTP client");
+        Self { client, base_url }
+    }
+
+    /// The version of the latest release, from the tag `releases/latest`
+    /// redirects to, without its leading `v`.
+    pub async fn latest_version(&self) -> Result<String, Error> {
+        let url = self.base_url.trim_end_matches("/download");
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(Error::network("failed to look up the latest release"))?;
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus {
+                status: response.status().as_u16(),
+                message: "latest release lookup failed".to_string(),
+            });
+        }
+        let mut segments = response
+            .url()
+            .path_segments()
+            .map(|segments| segments.rev().take(2).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter();
+        match (segments.next(), segments.next()) {
+            (Some(tag), Some("tag")) if !tag.is_empty() => {
+                Ok(tag.strip_prefix('v').unwrap_or(tag).to_string())
+            }
+            _ => Err(Error::NetworkFailure {
+                message: format!(
+                    "latest release did not resolve to a tag: {}",
+                    response.url()
+                ),
+            }),
+        }
+    }
+
+    /// Asset name to SHA-256 for every asset in the release.
+    pub async fn checksums(&self) -> Result<HashMap<String, String>, Error> {
+        let body = self.get("SHA256SUMS").await?;
+        let body = String::from_utf8(body).map_err(|_| Error::NetworkFailure {
+            message: "SHA256SUMS is not valid UTF-8".to_string(),
+        })?;
+        Ok(parse_checksums(&body))
+    }
+
+    /// Download `asset` and verify it against `checksums`. Assets missing
+    /// from the checksum file are refused rather than installed unverified.
+    pub async fn download(
+        &self,
+        asset: &str,
+        checksums: &HashMap<String, String>,
+    ) -> Result<Vec<u8>, Error> {
+        let expected = checksums.get(asset).ok_or_else(|| Error::NetworkFailure {
+            message: format!("release has no checksum for {asset}"),
+        })?;
+        let bytes = self.get(asset).await?;
+        verify_sha256_bytes(&bytes, Some(expected))?;
+        Ok(bytes)
+    }
+
+    async fn get(&self, asset: &str) -> Result<Vec<u8>, Error> {
+        let url = format!("{}/{asset}", self.base_url);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(Error::network("failed to fetch release asset"))?;
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus {
+                status: response.status().as_u16(),
+                message: format!("release download of {asset} failed"),
+            });
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(Error::network("failed to read release asset"))?;
+        Ok(bytes.to_vec())
+    }
+}
+
+impl Default for ReleaseClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse `sha256sum` output: `<hex>  <name>` per line, where binary mode
+/// marks the name with a leading `*`.
+fn parse_checksums(body: &str) -> HashMap<String, String> {
+    body.lines()
+        .filter_map(|line| {
+            let (sha, name) = line.trim().split_once(char::is_whitespace)?;
+            let name = name.trim_start();
+            let name = name.strip_prefix('*').unwrap_or(name);
+            Some((name.to_string(), sha.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Whether the file at `target` is already the release's `asset`.
+pub fn matches_release(target: &Path, asset: &str, checksums: &HashMap<String, String>) -> bool {
+    let (Some(expected), Ok(bytes)) = (checksums.get(asset), std::fs::read(target)) else {
+        return false;
+    };
+    verify_sha256_bytes(&bytes, Some(expected)).is_ok()
+}
+
+/// Whether `release` is a newer version than `current`. Dotted numbers
+/// compare numerically, and a pre-release such as `0.4.0-dev` sorts before
+/// the release it leads up to.
+pub fn is_newer_release(release: &str, current: &str) -> bool {
+    fn key(version: &str) -> (Vec<u64>, bool) {
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let (core, pre) = match version.split_once(['-', '+']) {
+            Some((core, _)) => (core, version[core.len()..].starts_with('-')),
+            None => (version, false),
+        };
+        let numbers = core
+            .split('.')
+            .map(|piece| piece.parse().unwrap_or(0))
+            .collect();
+        (numbers, !pre)
+    }
+    let (release, current) = (key(release), key(current));
+    let length = release.0.len().max(current.0.len());
+    let padded = |numbers: &[u64]| {
+        let mut numbers = numbers.to_vec();
+        numbers.resize(length, 0);
+        numbers
+    };
+    padded(&release.0)
+        .cmp(&padded(&current.0))
+        .then(release.1.cmp(&current.1))
+        == Ordering::Greater
+}
+
+/// Check the build provenance GitHub attests for each release asset,
+/// through `gh attestation verify`, so a binary is only trusted when it was
+/// built by the release workflow of [`RELEASE_REPO`], not merely when it
+/// matches a checksum file served from the same place.
+pub fn verify_attestation(path: &Path) -> Result<(), Error> {
+    let output = Command::new("gh")
+        .args(["attestation", "verify"])
+        .arg(path)
+        .args(["--repo", RELEASE_REPO])
+        .output()
+        .map_err(|e| Error::ExecutionError {
+            message: format!(
+                "verifying build provenance needs the GitHub CLI (gh), which could not be run: {e}"
+            ),
+        })?;
+    if !output.status.success() {
+        return Err(Error::ExecutionError {
+            message: format!(
+                "build provenance of the downloaded zb could not be verified: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// A new binary written next to the one it replaces, not yet swapped in.
+pub struct StagedExecutable {
+    target: PathBuf,
+    file: tempfile::NamedTempFile,
+}
+
+impl StagedExecutable {
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Rename the new binary over the old one.
+    pub fn commit(self) -> Result<(), Error> {
+        let target = self.target;
+        self.file.persist(&target).map_err(|e| Error::FileError {
+            message: format!("failed to replace {}: {}", target.display(), e.error),
+        })?;
+        Ok(())
+    }
+}
+
+/// Replace the executable at `target` with `bytes`. The new binary is
+/// written next to it and renamed over it, so an interrupted update leaves
+/// the old binary in place, and a running process keeps its original inode.
+pub fn replace_executable(target: &Path, bytes: &[u8]) -> Result<(), Error> {
+    stage_executable(target, bytes)?.commit()
+}
+
+/// Write `bytes` as an executable next to `target`, to check before it
+/// replaces `target`. Dropping the result removes it again.
+pub fn stage_executable(target: &Path, bytes: &[u8]) -> Result<StagedExecutable, Error> {
+    let dir = target.parent().ok_or_else(|| Error::FileError {
+        message: format!("{} has no parent directory", target.display()),
+    })?;
+    let mut staged = tempfile::Builder::new()
+        .prefix(".zb-update-")
+        .tempfile_in(dir)
+        .map_err(Error::file("failed to stage new binary"))?;
+    staged
+        .write_all(bytes)
+        .and_then(|()| staged.as_file().sync_all())
+        .map_err(Error::file("failed to write new binary"))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(staged.path(), std::fs::Permissions::from_mode(0o755))
+            .map_err(Error::file("failed to make new binary executable"))?;
+    }
+
+    Ok(StagedExecutable {
+        target: target.to_path_buf(),
+        file: staged,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::checksum::sha256_hex;
+
+    #[tokio::test]
+    async fn download_verifies_against_published_checksums() {
+        let server = MockServer::start().await;
+        let binary = b"#!/bin/sh\necho new\n".to_vec();
+        let mut hasher = Sha256::new();
+        hasher.update(&binary);
+        let sha = sha256_hex(hasher);
+        Mock::given(method("GET"))
+            .and(path("/SHA256SUMS"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                "{sha}  zb-linux-x64\n{}  zb-darwin-arm64\n",
+                "0".repeat(64)
+            )))
+            .mount(&server)
+            .await;
+        for asset in ["zb-linux-x64", "zb-darwin-arm64"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/{asset}")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(binary.clone()))
+                .mount(&server)
+                .await;
+        }
+
+        let client = ReleaseClient::with_base_url(server.uri());
+        let checksums = client.checksums().await.unwrap();
+
+        assert_eq!(
+            client.download("zb-linux-x64", &checksums).await.unwrap(),
+            binary
+        );
+        assert!(matches!(
+            client.download("zb-darwin-arm64", &checksums).await,
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert!(client.download("zbx-linux-x64", &checksums).await.is_err());
+
+        let tmp = TempDir::new().unwrap();
+        let installed = tmp.path().join("zb");
+        std::fs::write(&installed, &binary).unwrap();
+        assert!(matches_release(&installed, "zb-linux-x64", &checksums));
+        assert!(!matches_release(&installed, "zb-darwin-arm64", &checksums));
+    }
+
+    #[tokio::test]
+    async fn latest_version_follows_the_redirect_to_its_tag() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/releases/latest"))
+            .respond_with(
+                ResponseTemplate::new(302).insert_header("location", "/releases/tag/v0.4.1"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/releases/tag/v0.4.1"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let client =
+            ReleaseClient::with_base_url(format!("{}/releases/latest/download", server.uri()));
+        assert_eq!(client.latest_version().await.unwrap(), "0.4.1");
+    }
+
+    #[test]
+    fn only_later_releases_are_newer() {
+        assert!(is_newer_release("0.4.0", "0.3.2"));
+        assert!(is_newer_release("v0.10.0", "0.9.9"));
+        assert!(is_newer_release("0.4.0", "0.4.0-dev"));
+        assert!(!is_newer_release("0.3.2", "0.3.2"));
+        assert!(!is_newer_release("0.3.2", "0.4.0-dev"));
+        assert!(!is_newer_release("0.3", "0.3.0"));
+    }
+
+    #[test]
+    fn replace_executable_swaps_the_file_in_place() {
+        let tmp = TempDir::new().unwrap();
+        let target = tmp.path().join("zb");
+        std::fs::write(&target, b"old").unwrap();
+
+        replace_executable(&target, b"new").unwrap();
+
+        assert_eq!(std::fs::read(&target).unwrap(), b"new");
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use zb_core::Error;
+
+/// Which failure classes a [`RetryPolicy`] retries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryOn {
+    /// Connection resets, DNS failures, timeouts and other transport errors.
+    pub transport: bool,
+    /// HTTP 5xx responses.
+    pub server_errors: bool,
+    /// HTTP 408 and 429 responses.
+    pub throttling: bool,
+}
+
+impl Default for RetryOn {
+    fn default() -> Self {
+        Self {
+            transport: true,
+            server_errors: true,
+            throttling: true,
+        }
+    }
+}
+
+/// Exponential backoff with jitter for network operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one; 1 disables retries.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub retry_on: RetryOn,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(8),
+            retry_on: RetryOn::default(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn should_retry(&self, err: &Error) -> bool {
+        if !err.is_retryable() {
+            return false;
+        }
+        match err {
+            Error::HttpStatus { status, .. } if (500..600).contains(status) => {
+                self.retry_on.server_errors
+            }
+            Error::HttpStatus { .. } => self.retry_on.throttling,
+            _ => self.retry_on.transport,
+        }
+    }
+
+    /// Delay before retry number `retry` (1-based): the capped exponential
+    /// backoff, randomized into its upper half so parallel clients spread out.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exp = retry.saturating_sub(1).min(16);
+        let base = self
+            .initial_backoff
+            .saturating_mul(1 << exp)
+            .min(self.max_backoff);
+        let half = base / 2;
+        let jitter_nanos = half.as_nanos() as u64;
+        if jitter_nanos == 0 {
+            return base;
+        }
+        half + Duration::from_nanos(random_u64() % (jitter_nanos + 1))
+    }
+
+    /// Run `op` until it succeeds, fails permanently, or attempts run out.
+    ///
+    /// `on_retry(attempt, max_attempts, err)` is called before each retry,
+    /// with `attempt` being the upcoming attempt number. When more than one
+    /// attempt was made, the final error is wrapped in
+    /// [`Error::RetriesExhausted`].
+    pub async fn run<T, F, Fut>(
+        &self,
+        mut op: F,
+        mut on_retry: impl FnMut(u32, u32, &Error),
+    ) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt < max_attempts && self.should_retry(&err) => {
+                    attempt += 1;
+                    on_retry(attempt, max_attempts, &err);
+                    tokio::time::sleep(self.backoff(attempt - 1)).await;
+                }
+                Err(err) if attempt > 1 => {
+                    return Err(Error::RetriesExhausted {
+                        attempts: attempt,
+                        last: Box::new(err),
+                    });
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn fast() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn server_error() -> Error {
+        Error::HttpStatus {
+            status: 503,
+            message: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_stays_within_bounds() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            ..RetryPolicy::default()
+        };
+        for _ in 0..50 {
+            let first = policy.backoff(1);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let third = policy.backoff(3);
+            assert!(third >= Duration::from_millis(175) && third <= Duration::from_millis(350));
+        }
+    }
+
+    #[test]
+    fn retry_on_classes_can_be_disabled() {
+        let policy = RetryPolicy {
+            retry_on: RetryOn {
+                server_errors: false,
+                ..RetryOn::default()
+            },
+            ..RetryPolicy::default()
+        };
+        assert!(!policy.should_retry(&server_error()));
+        assert!(policy.should_retry(&Error::NetworkFailure {
+            message: "reset".to_string()
+        }));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures_until_success() {
+        let calls = AtomicU32::new(0);
+        let mut retries = Vec::new();
+
+        let result = fast()
+            .run(
+                || async {
+                    if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                        Err(server_error())
+                    } else {
+                        Ok(42)
+                    }
+                },
+                |attempt, max, _| retries.push((attempt, max)),
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(retries, vec![(2, 3), (3, 3)]);
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_not_retried() {
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = fast()
+            .run(
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(Error::MissingFormula {
+                        name: "jq".to_string(),
+                    })
+                },
+                |_, _, _| {},
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::MissingFormula { .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn exhausted_retries_wrap_last_error() {
+        let result: Result<(), _> = fast()
+            .run(|| async { Err(server_error()) }, |_, _, _| {})
+            .await;
+
+        match result {
+            Err(Error::RetriesExhausted { attempts, last }) => {
+                assert_eq!(attempts, 3);
+                assert_eq!(*last, server_error());
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,522 @@
+//! Evaluation of the simple Ruby string expressions tap formulas build their
+//! URLs from: literals with `#{...}` interpolation, `+` concatenation, `||`
+//! defaults, `cond ? a : b` on the host's OS or CPU, `ENV["X"]`,
+//! `ENV.fetch("X", "default")`, `version`, `name` and earlier assignments.
+//! Anything else is not evaluated, and the expression is left as written.
+
+use std::collections::HashMap;
+
+/// What the names in an expression refer to.
+pub(crate) struct ExprContext<'a> {
+    pub name: &'a str,
+    /// `None` until the formula's version is known.
+    pub version: Option<&'a str>,
+    /// Values of earlier `NAME = "..."` assignments.
+    pub locals: &'a HashMap<String, String>,
+    pub env: &'a dyn Fn(&str) -> Option<String>,
+}
+
+/// The string `expr` evaluates to, or `None` when it is not a string or
+/// uses something this evaluator does not know.
+pub(crate) fn eval_string(expr: &str, ctx: &ExprContext) -> Option<String> {
+    match eval(expr, ctx)? {
+        Value::Str(s) => Some(s),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Bool(bool),
+    Nil,
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// Ruby's `to_s`, as used by interpolation.
+    fn into_string(self) -> String {
+        match self {
+            Value::Str(s) => s,
+            Value::Bool(b) => b.to_string(),
+            Value::Nil => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Interpolation(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(Vec<Segment>),
+    Ident(String),
+    /// `.name` after a literal or parenthesized expression.
+    Method(String),
+    Plus,
+    OrOr,
+    Question,
+    Colon,
+    Comma,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+}
+
+fn eval(expr: &str, ctx: &ExprContext) -> Option<Value> {
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        ctx,
+    };
+    let value = parser.expr()?;
+    (parser.pos == tokens.len()).then_some(value)
+}
+
+fn tokenize(expr: &str) -> Option<Vec<Token>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' => i += 1,
+            '"' => {
+                let (segments, next) = scan_double_quoted(&chars, i + 1)?;
+                tokens.push(Token::Str(segments));
+                i = next;
+            }
+            '\'' => {
+                let (text, next) = scan_single_quoted(&chars, i + 1)?;
+                tokens.push(Token::Str(vec![Segment::Text(text)]));
+                i = next;
+            }
+            '+' => {
+                tokens.push(Token::Plus);
+                i += 1;
+            }
+            '|' if chars.get(i + 1) == Some(&'|') => {
+                tokens.push(Token::OrOr);
+                i += 2;
+            }
+            '?' => {
+                tokens.push(Token::Question);
+                i += 1;
+            }
+            ':' => {
+                tokens.push(Token::Colon);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '[' => {
+                tokens.push(Token::LBracket);
+                i += 1;
+            }
+            ']' => {
+                tokens.push(Token::RBracket);
+                i += 1;
+            }
+            '.' if chars.get(i + 1).is_some_and(|c| c.is_ascii_alphabetic()) => {
+                let (method, next) = scan_ident(&chars, i + 1);
+                tokens.push(Token::Method(method));
+                i = next;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let (ident, next) = scan_ident(&chars, i);
+                tokens.push(Token::Ident(ident));
+                i = next;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(tokens)
+}
+
+/// An identifier with its `::Const` and `.method` parts, e.g.
+/// `Hardware::CPU.arm?` or `version.major_minor`.
+fn scan_ident(chars: &[char], start: usize) -> (String, usize) {
+    let word_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut i = start;
+    loop {
+        while i < chars.len() && word_char(chars[i]) {
+            i += 1;
+        }
+        if chars.get(i) == Some(&'?') {
+            i += 1;
+            break;
+        }
+        if chars.get(i) == Some(&':')
+            && chars.get(i + 1) == Some(&':')
+            && chars.get(i + 2).is_some_and(|c| c.is_ascii_alphabetic())
+        {
+            i += 2;
+        } else if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|c| word_char(*c)) {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    (chars[start..i].iter().collect(), i)
+}
+
+fn scan_single_quoted(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut text = String::new();
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if matches!(chars.get(i + 1), Some('\'' | '\\')) => {
+                text.push(chars[i + 1]);
+                i += 2;
+            }
+            '\'' => return Some((text, i + 1)),
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+fn scan_double_quoted(chars: &[char], start: usize) -> Option<(Vec<Segment>, usize)> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                let escaped = *chars.get(i + 1)?;
+                text.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    c => c,
+                });
+                i += 2;
+            }
+            '#' if chars.get(i + 1) == Some(&'{') => {
+                let end = interpolation_end(chars, i + 2)?;
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Interpolation(chars[i + 2..end].iter().collect()));
+                i = end + 1;
+            }
+            '"' => {
+                if !text.is_empty() {
+                    segments.push(Segment::Text(text));
+                }
+                return Some((segments, i + 1));
+            }
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+/// The index of the `}` closing an interpolation whose body starts at
+/// `start`, skipping braces inside nested string literals.
+fn interpolation_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            quote @ ('"' | '\'') => {
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+            }
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+struct Parser<'t, 'c> {
+    tokens: &'t [Token],
+    pos: usize,
+    ctx: &'c ExprContext<'c>,
+}
+
+impl Parser<'_, '_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Option<()> {
+        self.eat(token).then_some(())
+    }
+
+    /// `or ( '?' expr ':' expr )?`
+    fn expr(&mut self) -> Option<Value> {
+        let condition = self.or()?;
+        if !self.eat(&Token::Question) {
+            return Some(condition);
+        }
+        let if_true = self.expr()?;
+        self.expect(&Token::Colon)?;
+        let if_false = self.expr()?;
+        Some(if condition.is_truthy() {
+            if_true
+        } else {
+            if_false
+        })
+    }
+
+    /// `concat ( '||' concat )*`
+    fn or(&mut self) -> Option<Value> {
+        let mut value = self.concat()?;
+        while self.eat(&Token::OrOr) {
+            let right = self.concat()?;
+            if !value.is_truthy() {
+                value = right;
+            }
+        }
+        Some(value)
+    }
+
+    /// `primary ( '+' primary )*`, on strings only.
+    fn concat(&mut self) -> Option<Value> {
+        let mut value = self.primary()?;
+        while self.eat(&Token::Plus) {
+            match (value, self.primary()?) {
+                (Value::Str(left), Value::Str(right)) => value = Value::Str(left + &right),
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
+    /// An operand, followed by any no-op `.freeze` or `.to_s` calls.
+    fn primary(&mut self) -> Option<Value> {
+        let value = self.operand()?;
+        while let Some(Token::Method(method)) = self.peek() {
+            match method.as_str() {
+                "freeze" | "to_s" => self.pos += 1,
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
+    fn operand(&mut self) -> Option<Value> {
+        let token = self.peek()?.clone();
+        self.pos += 1;
+        match token {
+            Token::Str(segments) => {
+                let mut s = String::new();
+                for segment in segments {
+                    match segment {
+                        Segment::Text(text) => s.push_str(&text),
+                        Segment::Interpolation(inner) => {
+                            s.push_str(&eval(&inner, self.ctx)?.into_string())
+                        }
+                    }
+                }
+                Some(Value::Str(s))
+            }
+            Token::LParen => {
+                let value = self.expr()?;
+                self.expect(&Token::RParen)?;
+                Some(value)
+            }
+            Token::Ident(ident) => self.ident(&ident),
+            _ => None,
+        }
+    }
+
+    fn ident(&mut self, ident: &str) -> Option<Value> {
+        let ident = ident
+            .strip_suffix(".to_s")
+            .or_else(|| ident.strip_suffix(".freeze"))
+            .unwrap_or(ident);
+        let host = |cond: bool| Some(Value::Bool(cond));
+        match ident {
+            "nil" => Some(Value::Nil),
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            "name" => Some(Value::Str(self.ctx.name.to_string())),
+            "OS.mac?" => host(cfg!(target_os = "macos")),
+            "OS.linux?" => host(cfg!(target_os = "linux")),
+            "Hardware::CPU.arm?" => host(cfg!(target_arch = "aarch64")),
+            "Hardware::CPU.intel?" => host(cfg!(target_arch = "x86_64")),
+            "ENV" => {
+                self.expect(&Token::LBracket)?;
+                let key = self.string_arg()?;
+                self.expect(&Token::RBracket)?;
+                Some((self.ctx.env)(&key).map_or(Value::Nil, Value::Str))
+            }
+            "ENV.fetch" => {
+                self.expect(&Token::LParen)?;
+                let key = self.string_arg()?;
+                let default = if self.eat(&Token::Comma) {
+                    Some(self.expr()?)
+                } else {
+                    None
+                };
+                self.expect(&Token::RParen)?;
+                (self.ctx.env)(&key).map(Value::Str).or(default)
+            }
+            _ => {
+                if let Some(method) = ident
+                    .strip_prefix("version")
+                    .and_then(|rest| rest.strip_prefix('.').or(rest.is_empty().then_some("")))
+                {
+                    return version_method(self.ctx.version?, method).map(Value::Str);
+                }
+                self.ctx.locals.get(ident).cloned().map(Value::Str)
+            }
+        }
+    }
+
+    fn string_arg(&mut self) -> Option<String> {
+        match self.expr()? {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// `version` or one of the `Version` methods formulas use in URLs.
+fn version_method(version: &str, method: &str) -> Option<String> {
+    let part = |i: usize| version.split('.').nth(i).unwrap_or("0").to_string();
+    match method {
+        "" => Some(version.to_string()),
+        "major" => Some(part(0)),
+        "minor" => Some(part(1)),
+        "patch" => Some(part(2)),
+        "major_minor" => Some(format!("{}.{}", part(0), part(1))),
+        "major_minor_patch" => Some(format!("{}.{}.{}", part(0), part(1), part(2))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_with(expr: &str, version: Option<&str>, env: &[(&str, &str)]) -> Option<String> {
+        let locals = HashMap::from([("BASE".to_string(), "https://dl.example.com".to_string())]);
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let lookup = |key: &str| env.get(key).cloned();
+        let ctx = ExprContext {
+            name: "tool",
+            version,
+            locals: &locals,
+            env: &lookup,
+        };
+        eval_string(expr, &ctx)
+    }
+
+    #[test]
+    fn evaluates_concatenation_and_interpolation() {
+        assert_eq!(
+            eval_with(
+                r#"BASE + "/#{name}/v#{version}/" + '#{literal}'"#,
+                Some("1.2.3"),
+                &[]
+            )
+            .as_deref(),
+            Some("https://dl.example.com/tool/v1.2.3/#{literal}")
+        );
+        assert_eq!(
+            eval_with(r##""#{version.major_minor}.x""##, Some("1.2.3"), &[]).as_deref(),
+            Some("1.2.x")
+        );
+        assert_eq!(eval_with(r#""v#{version}""#, None, &[]), None);
+    }
+
+    #[test]
+    fn evaluates_host_ternaries() {
+        let arch = if cfg!(target_arch = "aarch64") {
+            "arm64"
+        } else {
+            "amd64"
+        };
+        let os = if cfg!(target_os = "macos") {
+            "darwin"
+        } else {
+            "linux"
+        };
+        assert_eq!(
+            eval_with(
+                r#""tool-#{OS.mac? ? "darwin" : "linux"}-" + (Hardware::CPU.arm? ? "arm64" : "amd64")"#,
+                None,
+                &[]
+            ),
+            Some(format!("tool-{os}-{arch}"))
+        );
+    }
+
+    #[test]
+    fn evaluates_env_defaults() {
+        let expr = r#"(ENV["TOOL_MIRROR"] || "https://a.example.com") + "/x""#;
+        assert_eq!(
+            eval_with(expr, None, &[]).as_deref(),
+            Some("https://a.example.com/x")
+        );
+        assert_eq!(
+            eval_with(expr, None, &[("TOOL_MIRROR", "https://b.example.com")]).as_deref(),
+            Some("https://b.example.com/x")
+        );
+        assert_eq!(
+            eval_with(r#"ENV.fetch("TOOL_CHANNEL", "stable")"#, None, &[]).as_deref(),
+            Some("stable")
+        );
+        assert_eq!(eval_with(r#"ENV.fetch("TOOL_CHANNEL")"#, None, &[]), None);
+    }
+
+    #[test]
+    fn leaves_unknown_expressions_alone() {
+        for expr in [
+            r#"stable.url"#,
+            r#""a" + 1"#,
+            r#""a".downcase"#,
+            r#"("a" + "b").tr("a", "c")"#,
+            r#"cellar: :any, arm64_sonoma: "abc""#,
+            r#""unterminated"#,
+        ] {
+            assert_eq!(eval_with(expr, Some("1.0"), &[]), None, "{expr}");
+        }
+    }
+}
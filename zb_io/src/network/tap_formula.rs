@@ -1,10 +1,13 @@
 use regex::Regex;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::LazyLock;
 use zb_core::formula::{
-    Bottle, BottleFile, BottleStable, FormulaUrls, KegOnly, SourceUrl, Versions,
+    BinaryArtifact, BinaryPlatform, BinaryTarget, Bottle, BottleFile, BottleStable,
+    FormulaResource, FormulaUrls, KegOnly, SourceUrl, Versions, binary_platform_key,
 };
-use zb_core::{Error, Formula};
+use zb_core::{DependencyClass, Error, Formula};
+
+use super::ruby_expr::{ExprContext, eval_string};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TapFormulaRef {
@@ -22,6 +25,15 @@ static URL_VERSION_RE: LazyLock<Regex> = LazyLock::new(|| {
     )
     .expect("URL_VERSION_RE must compile")
 });
+static DESC_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)^\s*desc\s+["']([^"']+)["']"#).expect("DESC_RE must compile")
+});
+static HOMEPAGE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)^\s*homepage\s+["']([^"']+)["']"#).expect("HOMEPAGE_RE must compile")
+});
+static LICENSE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)^\s*license\s+["']([^"']+)["']\s*$"#).expect("LICENSE_RE must compile")
+});
 static REVISION_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"(?m)^\s*revision\s+(\d+)\s*$"#).expect("REVISION_RE must compile")
 });
@@ -35,11 +47,26 @@ static SOURCE_SHA_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"(?m)^\s*sha256\s+["']([0-9a-f]{64})["']\s*$"#)
         .expect("SOURCE_SHA_RE must compile")
 });
+static HEAD_URL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*(?:head|url)\s+["']([^"']+)["'](?:\s*,.*\bbranch:\s*["']([^"']+)["'])?"#)
+        .expect("HEAD_URL_RE must compile")
+});
+static HEAD_BLOCK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^\s*head\s+do\b"#).expect("HEAD_BLOCK_RE must compile"));
+static RESOURCE_START_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*resource\s+["']([^"']+)["']\s+do\b"#).expect("RESOURCE_START_RE must compile")
+});
 static CLASS_START_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"^\s*class\s+\w+\s*<\s*Formula\b"#).expect("CLASS_START_RE must compile")
 });
 static BOTTLE_START_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"^\s*bottle\s+do\b"#).expect("BOTTLE_START_RE must compile"));
+/// A named `bottle "no-gui" do` block: a tap-specific alternative to the
+/// formula's default bottles, installed with `zb install --variant`.
+static BOTTLE_VARIANT_START_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*bottle\s+(?:["']([^"']+)["']|:(\w+))\s+do\b"#)
+        .expect("BOTTLE_VARIANT_START_RE must compile")
+});
 static END_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"^\s*end\b"#).expect("END_RE must compile"));
 static DO_RE: LazyLock<Regex> = LazyLock::new(|| {
@@ -58,6 +85,18 @@ static REBUILD_RE: LazyLock<Regex> = LazyLock::new(|| {
 static BOTTLE_SHA_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"([a-z0-9_]+):\s*"([0-9a-f]{64})""#).expect("BOTTLE_SHA_RE must compile")
 });
+static BOTTLE_CELLAR_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\bcellar:\s*(:[a-z_]+|"[^"]+"|'[^']+')"#).expect("BOTTLE_CELLAR_RE must compile")
+});
+static DEF_INSTALL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*def\s+install\s*(?:#.*)?$"#).expect("DEF_INSTALL_RE must compile")
+});
+static BIN_INSTALL_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^bin\.install\s+(.+)$"#).expect("BIN_INSTALL_RE must compile"));
+static BIN_INSTALL_ARG_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^["']([^"']+)["'](?:\s*=>\s*["']([^"']+)["'])?$"#)
+        .expect("BIN_INSTALL_ARG_RE must compile")
+});
 static ON_PLATFORM_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"^\s*on_(macos|linux|arm|intel)\s+do\b"#).expect("ON_PLATFORM_RE must compile")
 });
@@ -67,6 +106,13 @@ static HW_CPU_RE: LazyLock<Regex> = LazyLock::new(|| {
 static ELSIF_HW_CPU_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"^\s*elsif\s+Hardware::CPU\.(arm|intel)\?"#).expect("ELSIF_HW_CPU_RE must compile")
 });
+static ASSIGNMENT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*([A-Za-z_]\w*)\s*=\s*([^=~].*)$"#).expect("ASSIGNMENT_RE must compile")
+});
+static DSL_STRING_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(\s*)(url|mirror|head|version|root_url)\s+(.+)$"#)
+        .expect("DSL_STRING_RE must compile")
+});
 static ELSE_LINE_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"^\s*else\s*(?:#.*)?$"#).expect("ELSE_LINE_RE must compile"));
 
@@ -90,12 +136,14 @@ pub fn parse_tap_formula_ref(input: &str) -> Option<TapFormulaRef> {
 
 /// Pre-processes a tap formula Ruby source to resolve platform-conditional blocks
 /// (`on_macos do`, `on_linux do`, `on_arm do`, `on_intel do`), architecture
-/// conditionals (`if Hardware::CPU.arm?`, `if Hardware::CPU.intel?`), and Ruby
-/// `#{version}` string interpolation so that the downstream regex-based parser
-/// sees the relevant fields at the top level.
-fn preprocess_tap_source(source: &str) -> String {
+/// conditionals (`if Hardware::CPU.arm?`, `if Hardware::CPU.intel?`), string
+/// expressions in `url`-like lines, and Ruby `#{version}` string
+/// interpolation so that the downstream regex-based parser sees the relevant
+/// fields at the top level.
+fn preprocess_tap_source(source: &str, name: &str) -> String {
     let resolved = resolve_on_platform_blocks(source);
     let resolved = resolve_arch_conditionals(&resolved);
+    let resolved = resolve_string_expressions(&resolved, name);
     resolve_version_interpolation(&resolved)
 }
 
@@ -244,7 +292,9 @@ fn resolve_on_platform_blocks_inner(source: &str, inside_bottle: bool) -> String
 
         // Detect entering a `bottle do` block so nested platform blocks are
         // unwrapped unconditionally.
-        if !inside_bottle && BOTTLE_START_RE.is_match(trimmed) {
+        if !inside_bottle
+            && (BOTTLE_START_RE.is_match(trimmed) || BOTTLE_VARIANT_START_RE.is_match(trimmed))
+        {
             result.push(lines[i].to_string());
             let end_idx = find_matching_end(&lines, i + 1);
             let inner: String = lines[i + 1..end_idx.min(lines.len())].join("\n");
@@ -326,6 +376,103 @@ fn resolve_arch_conditionals(source: &str) -> String {
     result.join("\n")
 }
 
+/// Rewrites `url`, `mirror`, `head`, `version` and `root_url` arguments built
+/// from concatenation, interpolation, host ternaries, `ENV` lookups or
+/// earlier assignments into plain string literals. The version may itself be
+/// an expression or come from the url, so the rewrite runs again once it is
+/// known. Expressions that cannot be evaluated are left as written.
+fn resolve_string_expressions(source: &str, name: &str) -> String {
+    let env = |key: &str| std::env::var(key).ok();
+    let first = rewrite_string_expressions(source, name, None, &env);
+    match parse_version(&first) {
+        Some(version) => rewrite_string_expressions(&first, name, Some(&version), &env),
+        None => first,
+    }
+}
+
+fn rewrite_string_expressions(
+    source: &str,
+    name: &str,
+    version: Option<&str>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> String {
+    let mut locals = HashMap::new();
+    let mut lines = Vec::new();
+
+    for line in source.lines() {
+        let ctx = ExprContext {
+            name,
+            version,
+            locals: &locals,
+            env,
+        };
+        if let Some(cap) = ASSIGNMENT_RE.captures(line) {
+            if let Some(value) = eval_string(cap[2].trim(), &ctx) {
+                locals.insert(cap[1].to_string(), value);
+            }
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let rewritten = DSL_STRING_RE.captures(line).and_then(|cap| {
+            let args = &cap[3];
+            let (expr, rest) = match top_level_comma(args) {
+                Some(i) => (&args[..i], &args[i..]),
+                None => (args, ""),
+            };
+            let expr = expr.trim();
+            if is_plain_string_literal(expr) {
+                return None;
+            }
+            let value = eval_string(expr, &ctx).filter(|v| !v.contains(['"', '\\']))?;
+            Some(format!("{}{} \"{value}\"{rest}", &cap[1], &cap[2]))
+        });
+        lines.push(rewritten.unwrap_or_else(|| line.to_string()));
+    }
+
+    lines.join("\n")
+}
+
+/// The byte index of the first `,` in `args` outside strings and brackets,
+/// where the keyword arguments of a DSL call start.
+fn top_level_comma(args: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in args.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A single string literal without interpolation, already what the parser
+/// reads.
+fn is_plain_string_literal(expr: &str) -> bool {
+    let Some(quote) = expr.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+        return false;
+    };
+    expr.len() >= 2
+        && expr.ends_with(quote)
+        && !expr[1..expr.len() - 1].contains([quote, '\\'])
+        && !(quote == '"' && expr.contains("#{"))
+}
+
 /// Replaces `#{version}` in the source with the actual version string extracted
 /// from the `version "..."` directive, enabling correct URL resolution for tap
 /// formulas that use Ruby string interpolation.
@@ -338,12 +485,14 @@ fn resolve_version_interpolation(source: &str) -> String {
 }
 
 pub fn parse_tap_formula_ruby(spec: &TapFormulaRef, source: &str) -> Result<Formula, Error> {
-    let source = preprocess_tap_source(source);
+    let source = preprocess_tap_source(source, &spec.formula);
     let stable = parse_version(&source).unwrap_or_else(|| "0".to_string());
     let revision = parse_revision(&source).unwrap_or(0);
-    let dependencies = parse_runtime_dependencies(&source);
-    let build_dependencies = parse_build_dependencies(&source);
+    let dependencies = parse_dependencies(&source, DependencyClass::Required);
+    let build_dependencies = parse_dependencies(&source, DependencyClass::Build);
     let parsed_source_url = parse_source_url(&source);
+    let head = parse_head_url(&source);
+    let resources = parse_resources(&source);
     let bottle = parse_bottle(spec, &source, &stable, revision);
 
     let source_url = match parsed_source_url {
@@ -360,7 +509,14 @@ pub fn parse_tap_formula_ruby(spec: &TapFormulaRef, source: &str) -> Result<Form
         ParsedSourceUrl::NotPresent => None,
     };
 
-    if bottle.is_none() && source_url.is_none() {
+    let binary = match (&bottle, &source_url) {
+        (None, Some(source_url)) if build_dependencies.is_empty() && resources.is_empty() => {
+            parse_binary_artifact(&source, source_url)
+        }
+        _ => None,
+    };
+
+    if bottle.is_none() && source_url.is_none() && head.is_none() {
         return Err(Error::UnsupportedFormula {
             name: spec.formula.clone(),
             reason: "tap formula does not provide bottle data or source url".to_string(),
@@ -376,18 +532,37 @@ pub fn parse_tap_formula_ruby(spec: &TapFormulaRef, source: &str) -> Result<Form
         keg_only: KegOnly::default(),
         keg_only_reason: None,
         build_dependencies,
-        urls: source_url.map(|stable| FormulaUrls {
-            stable: Some(stable),
-            head: None,
+        test_dependencies: parse_dependencies(&source, DependencyClass::Test),
+        recommended_dependencies: parse_dependencies(&source, DependencyClass::Recommended),
+        optional_dependencies: parse_dependencies(&source, DependencyClass::Optional),
+        urls: (source_url.is_some() || head.is_some()).then_some(FormulaUrls {
+            stable: source_url,
+            head,
         }),
         ruby_source_path: None,
         ruby_source_checksum: None,
         uses_from_macos: Vec::new(),
         requirements: Vec::new(),
         variations: None,
+        conflicts_with: Vec::new(),
+        conflicts_with_reasons: Vec::new(),
+        service: None,
+        desc: capture(&DESC_RE, &source),
+        homepage: capture(&HOMEPAGE_RE, &source),
+        license: capture(&LICENSE_RE, &source),
+        binary,
+        resources,
     })
 }
 
+/// The first capture of `re` in `source`. Only plain string literals are
+/// read, so e.g. a `license any_of: [...]` expression yields `None`.
+fn capture(re: &Regex, source: &str) -> Option<String> {
+    re.captures(source)
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str().to_string())
+}
+
 fn parse_version(source: &str) -> Option<String> {
     if let Some(v) = VERSION_RE
         .captures(source)
@@ -421,7 +596,7 @@ fn parse_revision(source: &str) -> Option<u32> {
         .and_then(|m| m.as_str().parse::<u32>().ok())
 }
 
-fn parse_runtime_dependencies(source: &str) -> Vec<String> {
+fn parse_dependencies(source: &str, class: DependencyClass) -> Vec<String> {
     let mut deps = Vec::new();
     let body = extract_formula_class_body(source).unwrap_or(source);
     let mut depth = 0usize;
@@ -432,8 +607,7 @@ fn parse_runtime_dependencies(source: &str) -> Vec<String> {
             && let Some(cap) = DEPENDS_ON_RE.captures(trimmed)
         {
             let options = cap.get(2).map(|m| m.as_str()).unwrap_or("");
-            if !options.contains(":build")
-                && !options.contains(":test")
+            if dependency_classes(options).contains(&class)
                 && let Some(dep) = cap.get(1)
             {
                 deps.push(dep.as_str().to_string());
@@ -447,29 +621,24 @@ fn parse_runtime_dependencies(source: &str) -> Vec<String> {
     deps
 }
 
-fn parse_build_dependencies(source: &str) -> Vec<String> {
-    let mut deps = Vec::new();
-    let body = extract_formula_class_body(source).unwrap_or(source);
-    let mut depth = 0usize;
-
-    for line in body.lines() {
-        let trimmed = line.trim();
-        if depth == 0
-            && let Some(cap) = DEPENDS_ON_RE.captures(trimmed)
-        {
-            let options = cap.get(2).map(|m| m.as_str()).unwrap_or("");
-            if options.contains(":build")
-                && let Some(dep) = cap.get(1)
-            {
-                deps.push(dep.as_str().to_string());
-            }
-        }
-        update_depth(&mut depth, trimmed);
+/// The classes named by the options of a `depends_on "x" => ...` line:
+/// one symbol like `:build`, or an array like `[:build, :test]`.
+fn dependency_classes(options: &str) -> Vec<DependencyClass> {
+    let classes: Vec<DependencyClass> = [
+        (":build", DependencyClass::Build),
+        (":test", DependencyClass::Test),
+        (":optional", DependencyClass::Optional),
+        (":recommended", DependencyClass::Recommended),
+    ]
+    .into_iter()
+    .filter(|(symbol, _)| options.contains(symbol))
+    .map(|(_, class)| class)
+    .collect();
+    if classes.is_empty() {
+        vec![DependencyClass::Required]
+    } else {
+        classes
     }
-
-    deps.sort_unstable();
-    deps.dedup();
-    deps
 }
 
 enum ParsedSourceUrl {
@@ -520,6 +689,132 @@ fn parse_source_url(source: &str) -> ParsedSourceUrl {
     }
 }
 
+/// The top-level `resource "name" do ... end` blocks. Their `url` and
+/// `sha256` lines are what the source build downloads before `install`
+/// stages them.
+fn parse_resources(source: &str) -> Vec<FormulaResource> {
+    let body = extract_formula_class_body(source).unwrap_or(source);
+    let mut depth = 0usize;
+    let mut resources = Vec::new();
+    let mut current: Option<(String, Option<String>, Option<String>)> = None;
+
+    for line in body.lines() {
+        let trimmed = line.trim();
+
+        if depth == 0
+            && let Some(cap) = RESOURCE_START_RE.captures(trimmed)
+        {
+            current = Some((cap[1].to_string(), None, None));
+        } else if depth == 1
+            && let Some((_, url, sha256)) = current.as_mut()
+        {
+            if url.is_none()
+                && let Some(cap) = SOURCE_URL_RE.captures(trimmed)
+            {
+                *url = Some(cap[1].to_string());
+            }
+            if sha256.is_none()
+                && let Some(cap) = SOURCE_SHA_RE.captures(trimmed)
+            {
+                *sha256 = Some(cap[1].to_string());
+            }
+        }
+
+        update_depth(&mut depth, trimmed);
+        if depth == 0
+            && let Some((name, url, sha256)) = current.take()
+            && let Some(url) = url
+        {
+            resources.push(FormulaResource { name, url, sha256 });
+        }
+    }
+
+    resources
+}
+
+/// A formula whose `install` only copies files of its download into `bin`
+/// ships prebuilt binaries, and installs as a release binary for the host
+/// instead of through a source build.
+fn parse_binary_artifact(source: &str, source_url: &SourceUrl) -> Option<BinaryArtifact> {
+    let lines: Vec<&str> = source.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| DEF_INSTALL_RE.is_match(line))?
+        + 1;
+    let end = find_matching_end(&lines, start);
+    if end == lines.len() {
+        return None;
+    }
+
+    let mut binaries = Vec::new();
+    for line in &lines[start..end] {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let args = BIN_INSTALL_RE.captures(trimmed)?.get(1)?.as_str();
+        for arg in args.split(',') {
+            let cap = BIN_INSTALL_ARG_RE.captures(arg.trim())?;
+            let source = cap[1].to_string();
+            let target = match cap.get(2) {
+                Some(target) => target.as_str().to_string(),
+                None => source.rsplit('/').next().unwrap_or(&source).to_string(),
+            };
+            binaries.push(BinaryTarget { source, target });
+        }
+    }
+    if binaries.is_empty() {
+        return None;
+    }
+
+    // Platform blocks were resolved for the host, so the URL is its own.
+    let key = binary_platform_key(std::env::consts::OS, std::env::consts::ARCH)?;
+    Some(BinaryArtifact {
+        url: None,
+        platforms: BTreeMap::from([(
+            key,
+            BinaryPlatform {
+                url: Some(source_url.url.clone()),
+                sha256: source_url.checksum.clone()?,
+            },
+        )]),
+        strip_components: None,
+        binaries,
+    })
+}
+
+/// The `head` spec, either `head "url", branch: "x"` or a `head do` block
+/// with a `url` line, in the shape the formula API uses.
+fn parse_head_url(source: &str) -> Option<serde_json::Value> {
+    let body = extract_formula_class_body(source).unwrap_or(source);
+    let mut depth = 0usize;
+    let mut in_head_block = false;
+
+    for line in body.lines() {
+        let trimmed = line.trim();
+
+        if depth == 0 && HEAD_BLOCK_RE.is_match(trimmed) {
+            in_head_block = true;
+        } else if ((depth == 0 && trimmed.starts_with("head"))
+            || (depth == 1 && in_head_block && trimmed.starts_with("url")))
+            && let Some(cap) = HEAD_URL_RE.captures(trimmed)
+        {
+            let mut head = serde_json::json!({ "url": &cap[1] });
+            if let Some(branch) = cap.get(2) {
+                head["branch"] = branch.as_str().into();
+            }
+            return Some(head);
+        }
+
+        update_depth(&mut depth, trimmed);
+        if depth == 0 {
+            in_head_block = false;
+        }
+    }
+
+    None
+}
+
 fn update_depth(depth: &mut usize, trimmed: &str) {
     if END_RE.is_match(trimmed) {
         *depth = depth.saturating_sub(1);
@@ -561,34 +856,66 @@ fn extract_formula_class_body(source: &str) -> Option<&str> {
 }
 
 fn parse_bottle(spec: &TapFormulaRef, source: &str, stable: &str, revision: u32) -> Option<Bottle> {
-    let block = extract_bottle_block(source)?;
-
-    let root_url = parse_root_url(block)
-        .unwrap_or_else(|| format!("https://ghcr.io/v2/{}/{}", spec.owner, spec.repo));
-    let rebuild = parse_rebuild(block).unwrap_or(0);
-    let files = parse_bottle_files(spec, &root_url, stable, revision, rebuild, block);
-
-    if files.is_empty() {
+    let default = extract_bottle_block(source)
+        .map(|block| parse_bottle_stable(spec, block, stable, revision))
+        .unwrap_or_default();
+    let variants: BTreeMap<String, BottleStable> = extract_bottle_variant_blocks(source)
+        .into_iter()
+        .map(|(name, block)| (name, parse_bottle_stable(spec, block, stable, revision)))
+        .filter(|(_, bottle)| !bottle.files.is_empty())
+        .collect();
+
+    if default.files.is_empty() && variants.is_empty() {
         return None;
     }
 
     Some(Bottle {
-        stable: BottleStable { files, rebuild },
+        stable: default,
+        variants,
     })
 }
 
+fn parse_bottle_stable(
+    spec: &TapFormulaRef,
+    block: &str,
+    stable: &str,
+    revision: u32,
+) -> BottleStable {
+    let root_url = parse_root_url(block)
+        .unwrap_or_else(|| format!("https://ghcr.io/v2/{}/{}", spec.owner, spec.repo));
+    let rebuild = parse_rebuild(block).unwrap_or(0);
+    let files = parse_bottle_files(spec, &root_url, stable, revision, rebuild, block);
+    BottleStable { files, rebuild }
+}
+
 fn empty_bottle() -> Bottle {
     Bottle {
         stable: BottleStable {
             files: BTreeMap::new(),
             rebuild: 0,
         },
+        variants: BTreeMap::new(),
     }
 }
 
 fn extract_bottle_block(source: &str) -> Option<&str> {
+    block_bodies(source, &BOTTLE_START_RE)
+        .into_iter()
+        .next()
+        .map(|(_, body)| body)
+}
+
+/// The bodies of the named `bottle "<variant>" do` blocks, by name.
+fn extract_bottle_variant_blocks(source: &str) -> Vec<(String, &str)> {
+    block_bodies(source, &BOTTLE_VARIANT_START_RE)
+}
+
+/// The body of every top-level block whose opening line matches `start`,
+/// with the first group `start` captured.
+fn block_bodies<'a>(source: &'a str, start: &Regex) -> Vec<(String, &'a str)> {
+    let mut blocks = Vec::new();
     let mut offset = 0usize;
-    let mut bottle_body_start: Option<usize> = None;
+    let mut body_start: Option<(String, usize)> = None;
     let mut depth = 0usize;
 
     for line in source.split_inclusive('\n') {
@@ -596,22 +923,31 @@ fn extract_bottle_block(source: &str) -> Option<&str> {
         offset += line.len();
         let trimmed = line.trim();
 
-        if bottle_body_start.is_none() {
-            if BOTTLE_START_RE.is_match(trimmed) {
-                bottle_body_start = Some(offset);
+        let Some((_, start_offset)) = &body_start else {
+            if let Some(captures) = start.captures(trimmed) {
+                let name = captures
+                    .iter()
+                    .skip(1)
+                    .flatten()
+                    .next()
+                    .map_or_else(String::new, |m| m.as_str().to_string());
+                body_start = Some((name, offset));
                 depth = 1;
             }
             continue;
-        }
+        };
 
         let depth_before = depth;
         update_depth(&mut depth, trimmed);
         if depth_before > 0 && depth == 0 {
-            return bottle_body_start.map(|start| &source[start..line_start]);
+            let body = &source[*start_offset..line_start];
+            if let Some((name, _)) = body_start.take() {
+                blocks.push((name, body));
+            }
         }
     }
 
-    None
+    blocks
 }
 
 fn parse_root_url(block: &str) -> Option<String> {
@@ -638,24 +974,31 @@ fn parse_bottle_files(
 ) -> BTreeMap<String, BottleFile> {
     let mut files = BTreeMap::new();
 
-    for cap in BOTTLE_SHA_RE.captures_iter(block) {
-        let Some(tag) = cap.get(1).map(|m| m.as_str()) else {
-            continue;
-        };
-        let Some(sha) = cap.get(2).map(|m| m.as_str()) else {
-            continue;
-        };
-        if tag == "cellar" {
-            continue;
+    for line in block.lines() {
+        let cellar = BOTTLE_CELLAR_RE
+            .captures(line)
+            .and_then(|c| c.get(1))
+            .map(|m| m.as_str().trim_matches(['"', '\'']).to_string());
+        for cap in BOTTLE_SHA_RE.captures_iter(line) {
+            let Some(tag) = cap.get(1).map(|m| m.as_str()) else {
+                continue;
+            };
+            let Some(sha) = cap.get(2).map(|m| m.as_str()) else {
+                continue;
+            };
+            if tag == "cellar" {
+                continue;
+            }
+            let url = build_bottle_url(spec, root_url, stable, revision, rebuild, tag, sha);
+            files.insert(
+                tag.to_string(),
+                BottleFile {
+                    url,
+                    sha256: sha.to_string(),
+                    cellar: cellar.clone(),
+                },
+            );
         }
-        let url = build_bottle_url(spec, root_url, stable, revision, rebuild, tag, sha);
-        files.insert(
-            tag.to_string(),
-            BottleFile {
-                url,
-                sha256: sha.to_string(),
-            },
-        );
     }
 
     files
@@ -745,7 +1088,66 @@ end
         assert_eq!(formula.dependencies, vec!["openssl@3".to_string()]);
         assert_eq!(formula.build_dependencies, vec!["go".to_string()]);
         assert!(formula.bottle.stable.files.contains_key("arm64_sonoma"));
-        assert!(formula.bottle.stable.files.contains_key("x86_64_linux"));
+        assert_eq!(
+            formula.bottle.stable.files["x86_64_linux"]
+                .cellar
+                .as_deref(),
+            Some(":any_skip_relocation")
+        );
+    }
+
+    #[test]
+    fn parses_named_bottle_variants() {
+        let source = r#"
+class Viewer < Formula
+  version "2.0"
+
+  bottle do
+    sha256 cellar: :any, x86_64_linux: "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+  end
+
+  bottle "no-gui" do
+    root_url "https://example.com/viewer/no-gui"
+    rebuild 1
+    on_linux do
+      sha256 cellar: :any_skip_relocation, x86_64_linux: "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+    end
+  end
+
+  bottle :minimal do
+    sha256 arm64_sonoma: "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc"
+  end
+end
+"#;
+        let spec = TapFormulaRef {
+            owner: "acme".to_string(),
+            repo: "tap".to_string(),
+            formula: "viewer".to_string(),
+        };
+
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        assert_eq!(
+            formula.bottle.stable.files["x86_64_linux"].sha256,
+            "a".repeat(64)
+        );
+        let variants: Vec<&str> = formula.bottle.variants.keys().map(String::as_str).collect();
+        assert_eq!(variants, ["minimal", "no-gui"]);
+
+        let no_gui = &formula.bottle.variants["no-gui"];
+        assert_eq!(no_gui.rebuild, 1);
+        assert_eq!(
+            no_gui.files["x86_64_linux"].url,
+            "https://example.com/viewer/no-gui/viewer-2.0.1.x86_64_linux.bottle.tar.gz"
+        );
+        assert_eq!(
+            no_gui.files["x86_64_linux"].cellar.as_deref(),
+            Some(":any_skip_relocation")
+        );
+        assert!(
+            formula.bottle.variants["minimal"]
+                .files
+                .contains_key("arm64_sonoma")
+        );
     }
 
     #[test]
@@ -918,6 +1320,183 @@ end
         );
     }
 
+    #[test]
+    fn parses_dependency_classes() {
+        let source = r#"
+class Tool < Formula
+  url "https://example.com/tool-1.0.tar.gz"
+  sha256 "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+
+  depends_on "cmake" => :build
+  depends_on "pkgconf" => [:build, :test]
+  depends_on "bats" => :test
+  depends_on "readline" => :recommended
+  depends_on "x11" => :optional
+  depends_on "zstd"
+end
+"#;
+        let spec = TapFormulaRef {
+            owner: "o".to_string(),
+            repo: "tap".to_string(),
+            formula: "tool".to_string(),
+        };
+
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        assert_eq!(formula.dependencies, ["zstd"]);
+        assert_eq!(formula.build_dependencies, ["cmake", "pkgconf"]);
+        assert_eq!(formula.test_dependencies, ["bats", "pkgconf"]);
+        assert_eq!(formula.recommended_dependencies, ["readline"]);
+        assert_eq!(formula.optional_dependencies, ["x11"]);
+    }
+
+    #[test]
+    fn parses_resources_and_ignores_livecheck() {
+        let source = r#"
+class Pytool < Formula
+  include Language::Python::Virtualenv
+
+  desc "Python tool"
+  url "https://github.com/o/pytool/archive/refs/tags/v2.1.0.tar.gz"
+  sha256 "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+
+  livecheck do
+    url "https://pypi.org/project/pytool/"
+    strategy :page_match do |page, regex|
+      page.scan(regex).map { |match| match[0] }
+    end
+  end
+
+  depends_on "python@3.12"
+
+  resource "click" do
+    url "https://files.example.com/click-8.1.7.tar.gz"
+    sha256 "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+  end
+
+  resource "vendored" do
+    url "https://github.com/o/vendored.git", tag: "v1.0"
+  end
+
+  def install
+    virtualenv_install_with_resources
+  end
+end
+"#;
+        let spec = TapFormulaRef {
+            owner: "o".to_string(),
+            repo: "tap".to_string(),
+            formula: "pytool".to_string(),
+        };
+
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        assert_eq!(formula.versions.stable, "2.1.0");
+        assert_eq!(formula.dependencies, ["python@3.12"]);
+        assert_eq!(
+            formula.source_url().map(|s| s.url.as_str()),
+            Some("https://github.com/o/pytool/archive/refs/tags/v2.1.0.tar.gz")
+        );
+        assert_eq!(
+            formula.resources,
+            [
+                FormulaResource {
+                    name: "click".to_string(),
+                    url: "https://files.example.com/click-8.1.7.tar.gz".to_string(),
+                    sha256: Some("b".repeat(64)),
+                },
+                FormulaResource {
+                    name: "vendored".to_string(),
+                    url: "https://github.com/o/vendored.git".to_string(),
+                    sha256: None,
+                },
+            ]
+        );
+        assert!(formula.binary.is_none());
+    }
+
+    #[test]
+    fn evaluates_string_expressions_in_urls() {
+        let source = r##"
+class Tool < Formula
+  TOOL_VERSION = "2.4.1".freeze
+  BASE = ENV.fetch("ZEROBREW_TEST_UNSET_MIRROR", "https://dl.example.com") + "/tool"
+  ARCH = Hardware::CPU.arm? ? "arm64" : "amd64"
+
+  desc "Tool with computed urls"
+  version TOOL_VERSION
+  url BASE + "/v#{version}/#{name}-" + ARCH + ".tar.gz", using: :nounzip
+  sha256 "1111111111111111111111111111111111111111111111111111111111111111"
+  head (ENV["ZEROBREW_TEST_UNSET_HEAD"] || "https://github.com/o/tool.git"), branch: "main"
+
+  def install
+    system "make", "PREFIX=#{prefix}"
+  end
+end
+"##;
+        let spec = TapFormulaRef {
+            owner: "o".to_string(),
+            repo: "tap".to_string(),
+            formula: "tool".to_string(),
+        };
+
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        let arch = if cfg!(target_arch = "aarch64") {
+            "arm64"
+        } else {
+            "amd64"
+        };
+        assert_eq!(formula.versions.stable, "2.4.1");
+        assert_eq!(
+            formula.source_url().map(|s| s.url.clone()),
+            Some(format!(
+                "https://dl.example.com/tool/v2.4.1/tool-{arch}.tar.gz"
+            ))
+        );
+        assert_eq!(
+            formula.urls.and_then(|u| u.head),
+            Some(serde_json::json!({
+                "url": "https://github.com/o/tool.git",
+                "branch": "main",
+            }))
+        );
+    }
+
+    #[test]
+    fn parses_head_spec() {
+        let spec = TapFormulaRef {
+            owner: "someone".to_string(),
+            repo: "tap".to_string(),
+            formula: "example".to_string(),
+        };
+
+        let source = r#"
+class Example < Formula
+  head "https://github.com/someone/example.git", branch: "main"
+  depends_on "go" => :build
+end
+"#;
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        let head = formula.head_source().expect("head spec should be parsed");
+        assert_eq!(head.url, "https://github.com/someone/example.git");
+        assert_eq!(head.branch.as_deref(), Some("main"));
+        assert!(!formula.has_source_url());
+
+        let source = r#"
+class Example < Formula
+  url "https://example.com/example-1.0.0.tar.gz"
+  sha256 "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+
+  head do
+    url "https://github.com/someone/example.git"
+  end
+end
+"#;
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        assert!(formula.has_source_url());
+        let head = formula.head_source().unwrap();
+        assert_eq!(head.url, "https://github.com/someone/example.git");
+        assert_eq!(head.branch, None);
+    }
+
     #[test]
     fn source_url_without_sha256_is_unsupported() {
         let source = r#"
@@ -1226,6 +1805,15 @@ end
         let formula = parse_tap_formula_ruby(&spec, source).unwrap();
         assert_eq!(formula.name, "sag");
         assert_eq!(formula.versions.stable, "0.2.2");
+        assert_eq!(
+            formula.desc.as_deref(),
+            Some("Command-line ElevenLabs TTS with mac-style flags")
+        );
+        assert_eq!(
+            formula.homepage.as_deref(),
+            Some("https://github.com/steipete/sag")
+        );
+        assert_eq!(formula.license.as_deref(), Some("MIT"));
 
         let stable = formula
             .urls
@@ -1270,6 +1858,81 @@ end
             );
             assert_eq!(formula.build_dependencies, vec!["go".to_string()]);
         }
+
+        // `install` falls back to a build, so it is not a binary formula.
+        assert!(formula.binary.is_none());
+    }
+
+    #[test]
+    fn install_of_only_bin_install_lines_makes_a_binary_formula() {
+        let source = r#"
+class Tool < Formula
+  desc "Prebuilt tool"
+  version "1.4.0"
+
+  on_macos do
+    url "https://github.com/o/tool/releases/download/v#{version}/tool_darwin.tar.gz"
+    sha256 "1111111111111111111111111111111111111111111111111111111111111111"
+  end
+
+  on_linux do
+    url "https://github.com/o/tool/releases/download/v#{version}/tool_linux.tar.gz"
+    sha256 "2222222222222222222222222222222222222222222222222222222222222222"
+  end
+
+  def install
+    # Both binaries are prebuilt.
+    bin.install "tool"
+    bin.install "extra/toolctl" => "tctl", "helper"
+  end
+end
+"#;
+        let spec = TapFormulaRef {
+            owner: "o".to_string(),
+            repo: "tap".to_string(),
+            formula: "tool".to_string(),
+        };
+
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        let binary = zb_core::select_binary(&formula, std::env::consts::ARCH)
+            .expect("bin.install-only formulas ship release binaries");
+        let os = if cfg!(target_os = "macos") {
+            ("darwin", "1")
+        } else {
+            ("linux", "2")
+        };
+        assert_eq!(
+            binary.url,
+            format!(
+                "https://github.com/o/tool/releases/download/v1.4.0/tool_{}.tar.gz",
+                os.0
+            )
+        );
+        assert_eq!(binary.sha256, os.1.repeat(64));
+        assert_eq!(binary.strip_components, None);
+        assert_eq!(
+            binary
+                .binaries
+                .iter()
+                .map(|b| (b.source.as_str(), b.target.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("tool", "tool"),
+                ("extra/toolctl", "tctl"),
+                ("helper", "helper")
+            ]
+        );
+
+        let built = source.replace(
+            "    bin.install \"tool\"\n",
+            "    system \"make\"\n    bin.install \"tool\"\n",
+        );
+        assert!(
+            parse_tap_formula_ruby(&spec, &built)
+                .unwrap()
+                .binary
+                .is_none()
+        );
     }
 
     #[test]
@@ -1,5 +1,6 @@
 /// Progress events during installation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
 pub enum InstallProgress {
     /// Starting to download a package (with total size if known)
     DownloadStarted {
@@ -12,6 +13,13 @@ pub enum InstallProgress {
         downloaded: u64,
         total_bytes: Option<u64>,
     },
+    /// A download failed transiently and is being retried
+    DownloadRetrying {
+        name: String,
+        attempt: u32,
+        max_attempts: u32,
+        reason: String,
+    },
     /// Download completed for a package
     DownloadCompleted { name: String, total_bytes: u64 },
     /// Starting to unpack/materialize a package
@@ -30,3 +38,24 @@ pub enum InstallProgress {
 
 /// Callback type for progress reporting
 pub type ProgressCallback = Box<dyn Fn(InstallProgress) + Send + Sync>;
+
+/// Sending half of a progress event stream, see [`progress_channel`].
+pub type ProgressSender = tokio::sync::mpsc::UnboundedSender<InstallProgress>;
+
+/// Receiving half of a progress event stream. Yields `None` once the
+/// operation that owns the sender has finished.
+pub type ProgressReceiver = tokio::sync::mpsc::UnboundedReceiver<InstallProgress>;
+
+/// Create a channel for consuming progress events asynchronously instead of
+/// through a [`ProgressCallback`].
+pub fn progress_channel() -> (ProgressSender, ProgressReceiver) {
+    tokio::sync::mpsc::unbounded_channel()
+}
+
+/// Adapt a channel sender to the callback interface used by the installer.
+/// Events sent after the receiver is dropped are discarded.
+pub fn channel_callback(sender: ProgressSender) -> std::sync::Arc<ProgressCallback> {
+    std::sync::Arc::new(Box::new(move |event| {
+        let _ = sender.send(event);
+    }))
+}
@@ -0,0 +1,282 @@
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use zb_core::{CronSchedule, Error, RestartPolicy, RunType, ServiceDefinition};
+
+use super::{ServiceState, run_command, write_service_file};
+
+/// Loads services as launchd agents of the current user.
+pub struct LaunchdManager {
+    agents_dir: PathBuf,
+}
+
+impl LaunchdManager {
+    pub fn new(agents_dir: PathBuf) -> Self {
+        Self { agents_dir }
+    }
+
+    pub fn plist_path(&self, label: &str) -> PathBuf {
+        self.agents_dir.join(format!("{label}.plist"))
+    }
+
+    pub(super) fn start(
+        &self,
+        label: &str,
+        definition: &ServiceDefinition,
+    ) -> Result<PathBuf, Error> {
+        let path = self.plist_path(label);
+        write_service_file(&path, &render_plist(label, definition))?;
+        run_command(
+            "launchctl",
+            &["bootstrap", &gui_domain(), &path.to_string_lossy()],
+        )?;
+        Ok(path)
+    }
+
+    pub(super) fn stop(&self, label: &str) -> Result<(), Error> {
+        // Not being loaded is fine; the plist may be left from a reboot.
+        let _ = run_command(
+            "launchctl",
+            &["bootout", &format!("{}/{label}", gui_domain())],
+        );
+        std::fs::remove_file(self.plist_path(label))
+            .map_err(Error::service("failed to remove launchd plist"))
+    }
+
+    /// Whether launchd has a job called `label` loaded, by whoever loaded it.
+    pub(super) fn is_loaded(&self, label: &str) -> bool {
+        run_command(
+            "launchctl",
+            &["print", &format!("{}/{label}", gui_domain())],
+        )
+        .is_ok()
+    }
+
+    pub(super) fn state(&self, label: &str) -> ServiceState {
+        let Ok(info) = run_command(
+            "launchctl",
+            &["print", &format!("{}/{label}", gui_domain())],
+        ) else {
+            return ServiceState::Stopped;
+        };
+        let pid = info
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("pid = "))
+            .and_then(|pid| pid.trim().parse().ok());
+        match pid {
+            Some(pid) => ServiceState::Running { pid: Some(pid) },
+            None => ServiceState::Scheduled,
+        }
+    }
+}
+
+fn gui_domain() -> String {
+    // SAFETY: getuid has no preconditions and cannot fail.
+    format!("gui/{}", unsafe { libc::getuid() })
+}
+
+/// The launchd agent plist for `definition`.
+pub fn render_plist(label: &str, definition: &ServiceDefinition) -> String {
+    let mut plist = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n<dict>\n",
+    ));
+    let _ = writeln!(
+        plist,
+        "\t<key>Label</key>\n\t<string>{}</string>",
+        xml_escape(label)
+    );
+
+    plist.push_str("\t<key>ProgramArguments</key>\n\t<array>\n");
+    for arg in &definition.run {
+        let _ = writeln!(plist, "\t\t<string>{}</string>", xml_escape(arg));
+    }
+    plist.push_str("\t</array>\n");
+
+    match &definition.run_type {
+        RunType::Immediate => {
+            plist.push_str("\t<key>RunAtLoad</key>\n\t<true/>\n");
+            match definition.restart {
+                RestartPolicy::Always => plist.push_str("\t<key>KeepAlive</key>\n\t<true/>\n"),
+                RestartPolicy::OnFailure => plist.push_str(concat!(
+                    "\t<key>KeepAlive</key>\n\t<dict>\n",
+                    "\t\t<key>SuccessfulExit</key>\n\t\t<false/>\n",
+                    "\t</dict>\n",
+                )),
+                RestartPolicy::Never => {}
+            }
+        }
+        RunType::Interval(secs) => {
+            let _ = writeln!(
+                plist,
+                "\t<key>StartInterval</key>\n\t<integer>{secs}</integer>"
+            );
+        }
+        RunType::Cron(schedule) => {
+            plist.push_str("\t<key>StartCalendarInterval</key>\n");
+            plist.push_str(&render_calendar_intervals(schedule));
+        }
+    }
+
+    for (key, value) in [
+        ("WorkingDirectory", &definition.working_dir),
+        ("StandardOutPath", &definition.log_path),
+        ("StandardErrorPath", &definition.error_log_path),
+    ] {
+        if let Some(value) = value {
+            let _ = writeln!(
+                plist,
+                "\t<key>{key}</key>\n\t<string>{}</string>",
+                xml_escape(value)
+            );
+        }
+    }
+
+    if !definition.environment_variables.is_empty() {
+        plist.push_str("\t<key>EnvironmentVariables</key>\n\t<dict>\n");
+        for (key, value) in &definition.environment_variables {
+            let _ = writeln!(
+                plist,
+                "\t\t<key>{}</key>\n\t\t<string>{}</string>",
+                xml_escape(key),
+                xml_escape(value)
+            );
+        }
+        plist.push_str("\t</dict>\n");
+    }
+
+    plist.push_str("</dict>\n</plist>\n");
+    plist
+}
+
+/// One `StartCalendarInterval` dict per combination of restricted field
+/// values, since launchd takes a single value per key.
+fn render_calendar_intervals(schedule: &CronSchedule) -> String {
+    let fields = [
+        ("Minute", &schedule.minute),
+        ("Hour", &schedule.hour),
+        ("Day", &schedule.day),
+        ("Month", &schedule.month),
+        ("Weekday", &schedule.weekday),
+    ];
+    let mut combinations: Vec<Vec<(&str, u32)>> = vec![Vec::new()];
+    for (key, values) in fields {
+        let Some(values) = values else {
+            continue;
+        };
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combo| {
+                values.iter().map(move |value| {
+                    let mut combo = combo.clone();
+                    combo.push((key, *value));
+                    combo
+                })
+            })
+            .collect();
+    }
+
+    let render_dict = |combo: &[(&str, u32)], indent: &str| {
+        let mut dict = format!("{indent}<dict>\n");
+        for (key, value) in combo {
+            let _ = writeln!(
+                dict,
+                "{indent}\t<key>{key}</key>\n{indent}\t<integer>{value}</integer>"
+            );
+        }
+        let _ = writeln!(dict, "{indent}</dict>");
+        dict
+    };
+
+    if let [combo] = combinations.as_slice() {
+        return render_dict(combo, "\t");
+    }
+    let mut array = String::from("\t<array>\n");
+    for combo in &combinations {
+        array.push_str(&render_dict(combo, "\t\t"));
+    }
+    array.push_str("\t</array>\n");
+    array
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn definition(run_type: RunType) -> ServiceDefinition {
+        ServiceDefinition {
+            run: vec![
+                "/opt/zb/opt/redis/bin/redis-server".to_string(),
+                "/opt/zb/etc/redis.conf".to_string(),
+            ],
+            run_type,
+            restart: RestartPolicy::Always,
+            health_check: None,
+            working_dir: Some("/opt/zb/var".to_string()),
+            log_path: Some("/opt/zb/var/log/redis.log".to_string()),
+            error_log_path: None,
+            environment_variables: BTreeMap::from([("LANG".to_string(), "C & UTF-8".to_string())]),
+        }
+    }
+
+    #[test]
+    fn immediate_service_runs_at_load() {
+        let plist = render_plist("zerobrew.redis", &definition(RunType::Immediate));
+        assert!(plist.contains("<key>Label</key>\n\t<string>zerobrew.redis</string>"));
+        assert!(plist.contains("\t\t<string>/opt/zb/etc/redis.conf</string>\n"));
+        assert!(plist.contains("<key>RunAtLoad</key>\n\t<true/>"));
+        assert!(plist.contains("<key>KeepAlive</key>\n\t<true/>"));
+        assert!(plist.contains("<key>WorkingDirectory</key>\n\t<string>/opt/zb/var</string>"));
+        assert!(!plist.contains("StandardErrorPath"));
+        assert!(plist.contains("<string>C &amp; UTF-8</string>"));
+    }
+
+    #[test]
+    fn restart_on_failure_keeps_alive_after_unsuccessful_exit() {
+        let mut definition = definition(RunType::Immediate);
+        definition.restart = RestartPolicy::OnFailure;
+        let plist = render_plist("zerobrew.redis", &definition);
+        assert!(plist.contains(
+            "<key>KeepAlive</key>\n\t<dict>\n\t\t<key>SuccessfulExit</key>\n\t\t<false/>\n\t</dict>"
+        ));
+
+        definition.restart = RestartPolicy::Never;
+        assert!(!render_plist("zerobrew.redis", &definition).contains("KeepAlive"));
+    }
+
+    #[test]
+    fn scheduled_services_use_launchd_triggers() {
+        let plist = render_plist("zerobrew.redis", &definition(RunType::Interval(3600)));
+        assert!(plist.contains("<key>StartInterval</key>\n\t<integer>3600</integer>"));
+        assert!(!plist.contains("RunAtLoad"));
+
+        let nightly = CronSchedule::parse("0 3 * * *").unwrap();
+        let plist = render_plist("zerobrew.redis", &definition(RunType::Cron(nightly)));
+        assert!(plist.contains(concat!(
+            "<key>StartCalendarInterval</key>\n",
+            "\t<dict>\n",
+            "\t\t<key>Minute</key>\n\t\t<integer>0</integer>\n",
+            "\t\t<key>Hour</key>\n\t\t<integer>3</integer>\n",
+            "\t</dict>\n",
+        )));
+    }
+
+    #[test]
+    fn cron_lists_expand_to_calendar_interval_array() {
+        let schedule = CronSchedule::parse("0,30 9 * * 1-5").unwrap();
+        let rendered = render_calendar_intervals(&schedule);
+        assert!(rendered.starts_with("\t<array>\n"));
+        assert_eq!(rendered.matches("<dict>").count(), 10);
+    }
+}
@@ -0,0 +1,494 @@
+//! Running formula services under the platform's service manager: launchd
+//! agents on macOS, systemd user units elsewhere.
+
+pub mod launchd;
+pub mod systemd;
+
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+
+use zb_core::{Error, HealthCheck, RestartPolicy, RunType, ServiceDefinition, formula_token};
+
+use crate::storage::db::{Database, ServiceOverrides, ServiceSettings};
+
+pub use launchd::LaunchdManager;
+pub use systemd::SystemdManager;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The launchd label and systemd unit name of the service for `name`.
+pub fn service_label(name: &str) -> String {
+    format!("zerobrew.{}", formula_token(name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    Running {
+        pid: Option<u32>,
+    },
+    /// Has a process, but its health check fails.
+    Unhealthy {
+        pid: Option<u32>,
+    },
+    /// Loaded and waiting for its next scheduled run.
+    Scheduled,
+    Stopped,
+}
+
+impl ServiceState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ServiceState::Running { .. } => "running",
+            ServiceState::Unhealthy { .. } => "unhealthy",
+            ServiceState::Scheduled => "scheduled",
+            ServiceState::Stopped => "stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub state: ServiceState,
+    /// Schedule set with `zb services schedule`, overriding the formula's.
+    pub schedule: Option<RunType>,
+    /// The generated plist or unit, when the service has been started.
+    pub file: Option<PathBuf>,
+    /// When the service was last (re)started.
+    pub started_at: Option<SystemTime>,
+}
+
+enum Backend {
+    Launchd(LaunchdManager),
+    Systemd(SystemdManager),
+}
+
+/// Starts, stops and schedules formula services, remembering per-formula
+/// settings in the database.
+pub struct ServiceManager {
+    db: Database,
+    backend: Backend,
+}
+
+impl ServiceManager {
+    /// Manage services of the current user, with agents or units written to
+    /// the platform's per-user directory.
+    pub fn new(db: Database) -> Result<Self, Error> {
+        let home =
+            std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::ServiceError {
+                    message: "HOME is not set".to_string(),
+                })?;
+        let backend = if cfg!(target_os = "macos") {
+            Backend::Launchd(LaunchdManager::new(home.join("Library/LaunchAgents")))
+        } else {
+            let config = std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".config"));
+            Backend::Systemd(SystemdManager::new(config.join("systemd/user")))
+        };
+        Ok(Self { db, backend })
+    }
+
+    pub fn settings(&self, name: &str) -> Result<Option<ServiceSettings>, Error> {
+        self.db.service_settings(name)
+    }
+
+    /// `definition` as it will run, with the settings for `name` applied.
+    pub fn effective_definition(
+        &self,
+        name: &str,
+        definition: &ServiceDefinition,
+    ) -> Result<ServiceDefinition, Error> {
+        let mut effective = definition.clone();
+        let Some(settings) = self.settings(name)? else {
+            return Ok(effective);
+        };
+        if let Some(schedule) = settings.schedule {
+            effective.run_type = schedule;
+            effective.restart = RestartPolicy::Never;
+        }
+        settings.overrides.apply(&mut effective);
+        Ok(effective)
+    }
+
+    /// Write the service file for `name` and load it, replacing a service
+    /// that is already running. Returns the file written.
+    pub fn start(&self, name: &str, definition: &ServiceDefinition) -> Result<PathBuf, Error> {
+        let definition = self.effective_definition(name, definition)?;
+        check_definition(name, &definition)?;
+        if let Some(label) = self.homebrew_job(name) {
+            return Err(Error::ServiceLabelTaken {
+                name: name.to_string(),
+                label,
+            });
+        }
+        let label = service_label(name);
+        self.db.record_service(name)?;
+        if self.file(name).is_some() {
+            self.unload(&label)?;
+        }
+        match &self.backend {
+            Backend::Launchd(launchd) => launchd.start(&label, &definition),
+            Backend::Systemd(systemd) => systemd.start(&label, &definition),
+        }
+    }
+
+    /// The command running the service of `name` directly, with the
+    /// environment and working directory the service manager would use.
+    /// Output is left to the caller rather than the service's log files.
+    pub fn foreground_command(
+        &self,
+        name: &str,
+        definition: &ServiceDefinition,
+    ) -> Result<std::process::Command, Error> {
+        Ok(foreground_command(
+            &self.effective_definition(name, definition)?,
+        ))
+    }
+
+    /// Unload the service of `name` and remove its file. Its settings are
+    /// kept for the next start.
+    pub fn stop(&self, name: &str) -> Result<(), Error> {
+        let label = service_label(name);
+        if self.file(name).is_none() {
+            return Err(Error::ServiceError {
+                message: format!("{name} is not running"),
+            });
+        }
+        self.unload(&label)
+    }
+
+    /// Run the service of `name` on `schedule` from its next start, or as
+    /// the formula declares when `None`.
+    pub fn set_schedule(&self, name: &str, schedule: Option<&RunType>) -> Result<(), Error> {
+        self.db.set_service_schedule(name, schedule)
+    }
+
+    /// Run the service of `name` with `overrides` from its next start.
+    pub fn set_overrides(&self, name: &str, overrides: &ServiceOverrides) -> Result<(), Error> {
+        self.db.set_service_overrides(name, overrides)
+    }
+
+    /// The generated service file of `name`, if it has been started.
+    pub fn file(&self, name: &str) -> Option<PathBuf> {
+        let label = service_label(name);
+        let path = match &self.backend {
+            Backend::Launchd(launchd) => launchd.plist_path(&label),
+            Backend::Systemd(systemd) => systemd.service_path(&label),
+        };
+        path.exists().then_some(path)
+    }
+
+    /// The status of `name`, probing its health check if it has a process
+    /// so that running means ready.
+    pub fn status(&self, name: &str) -> Result<ServiceStatus, Error> {
+        let settings = self.settings(name)?;
+        let schedule = settings.as_ref().and_then(|s| s.schedule.clone());
+        let mut status = self.status_of(name.to_string(), schedule);
+        if let ServiceState::Running { pid } = status.state
+            && let Some(check) = settings.and_then(|s| s.overrides.health_check)
+            && !probe(&check)
+        {
+            status.state = ServiceState::Unhealthy { pid };
+        }
+        Ok(status)
+    }
+
+    /// Every service zerobrew has started or been given settings for.
+    pub fn list(&self) -> Result<Vec<ServiceStatus>, Error> {
+        Ok(self
+            .db
+            .list_service_settings()?
+            .into_iter()
+            .map(|settings| self.status_of(settings.name, settings.schedule))
+            .collect())
+    }
+
+    fn status_of(&self, name: String, schedule: Option<RunType>) -> ServiceStatus {
+        let file = self.file(&name);
+        let label = service_label(&name);
+        let state = match (&file, &self.backend) {
+            (None, _) => ServiceState::Stopped,
+            (Some(_), Backend::Launchd(launchd)) => launchd.state(&label),
+            (Some(_), Backend::Systemd(systemd)) => systemd.state(&label),
+        };
+        let started_at = file
+            .as_deref()
+            .and_then(|f| f.metadata().ok())
+            .and_then(|m| m.modified().ok());
+        ServiceStatus {
+            name,
+            state,
+            schedule,
+            file,
+            started_at,
+        }
+    }
+
+    /// Homebrew's label for the service of `name`, if Homebrew has it
+    /// loaded. Both running it would fight over its ports and files.
+    pub fn homebrew_job(&self, name: &str) -> Option<String> {
+        let token = formula_token(name);
+        match &self.backend {
+            Backend::Launchd(launchd) => {
+                let label = format!("homebrew.mxcl.{token}");
+                launchd.is_loaded(&label).then_some(label)
+            }
+            Backend::Systemd(systemd) => {
+                let label = format!("homebrew.{token}");
+                systemd.is_loaded(&label).then_some(label)
+            }
+        }
+    }
+
+    fn unload(&self, label: &str) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Launchd(launchd) => launchd.stop(label),
+            Backend::Systemd(systemd) => systemd.stop(label),
+        }
+    }
+}
+
+fn foreground_command(definition: &ServiceDefinition) -> std::process::Command {
+    let mut command = std::process::Command::new(&definition.run[0]);
+    command
+        .args(&definition.run[1..])
+        .envs(&definition.environment_variables);
+    if let Some(dir) = &definition.working_dir {
+        command.current_dir(dir);
+    }
+    command
+}
+
+/// Catch what would otherwise surface as a cryptic launchctl or systemctl
+/// failure: a missing or non-executable program, and values no plist or
+/// unit can carry.
+fn check_definition(name: &str, definition: &ServiceDefinition) -> Result<(), Error> {
+    let invalid = |reason: String| Error::ServiceFileInvalid {
+        name: name.to_string(),
+        reason,
+    };
+    let Some(program) = definition.run.first() else {
+        return Err(invalid("it has no command to run".to_string()));
+    };
+
+    let environment = &definition.environment_variables;
+    let mut values = definition
+        .run
+        .iter()
+        .chain(environment.iter().flat_map(|(key, value)| [key, value]))
+        .chain(&definition.working_dir)
+        .chain(&definition.log_path)
+        .chain(&definition.error_log_path);
+    if let Some(value) = values.find(|value| value.chars().any(|c| c.is_control() && c != '\t')) {
+        return Err(invalid(format!("{value:?} contains a control character")));
+    }
+    if let Some(key) = environment
+        .keys()
+        .find(|key| key.is_empty() || key.contains('='))
+    {
+        return Err(invalid(format!("{key:?} is not a valid variable name")));
+    }
+    if let Some(dir) = &definition.working_dir
+        && !Path::new(dir).is_dir()
+    {
+        return Err(invalid(format!("working directory {dir} does not exist")));
+    }
+
+    let path = resolve_program(program, environment.get("PATH").map(String::as_str));
+    let Ok(metadata) = std::fs::metadata(&path) else {
+        return Err(Error::ServiceBinaryMissing {
+            name: name.to_string(),
+            path,
+        });
+    };
+    if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+        return Err(Error::ServiceBinaryNotExecutable {
+            name: name.to_string(),
+            path,
+        });
+    }
+    Ok(())
+}
+
+/// `program` as a path, looked up in the service's `PATH` when it is a bare
+/// name.
+fn resolve_program(program: &str, path: Option<&str>) -> PathBuf {
+    if program.contains('/') {
+        return PathBuf::from(program);
+    }
+    path.into_iter()
+        .flat_map(|path| path.split(':'))
+        .map(|dir| Path::new(dir).join(program))
+        .find(|candidate| candidate.exists())
+        .unwrap_or_else(|| PathBuf::from(program))
+}
+
+/// Whether `check` passes right now.
+pub fn probe(check: &HealthCheck) -> bool {
+    match check {
+        HealthCheck::Tcp(port) => TcpStream::connect_timeout(
+            &SocketAddr::from((Ipv4Addr::LOCALHOST, *port)),
+            PROBE_TIMEOUT,
+        )
+        .is_ok(),
+        HealthCheck::Command(command) => std::process::Command::new("sh")
+            .args(["-c", command])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success()),
+    }
+}
+
+fn write_service_file(path: &Path, contents: &str) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(Error::service("failed to create service directory"))?;
+    }
+    std::fs::write(path, contents).map_err(Error::service("failed to write service file"))
+}
+
+/// Run a service manager command, failing with its stderr.
+fn run_command(program: &str, args: &[&str]) -> Result<String, Error> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| Error::ServiceError {
+            message: format!("failed to run {program}: {e}"),
+        })?;
+    if !output.status.success() {
+        return Err(Error::ServiceError {
+            message: format!(
+                "{program} {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::ffi::OsStr;
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn foreground_command_matches_the_managed_service() {
+        let definition = ServiceDefinition {
+            run: vec![
+                "/opt/zb/bin/redis-server".to_string(),
+                "--port=6380".to_string(),
+            ],
+            run_type: RunType::Immediate,
+            restart: RestartPolicy::Always,
+            health_check: None,
+            working_dir: Some("/opt/zb/var".to_string()),
+            log_path: Some("/opt/zb/var/log/redis.log".to_string()),
+            error_log_path: None,
+            environment_variables: BTreeMap::from([("LANG".to_string(), "C".to_string())]),
+        };
+        let command = foreground_command(&definition);
+        assert_eq!(command.get_program(), "/opt/zb/bin/redis-server");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["--port=6380"]);
+        assert_eq!(
+            command.get_envs().collect::<Vec<_>>(),
+            [(OsStr::new("LANG"), Some(OsStr::new("C")))]
+        );
+        assert_eq!(command.get_current_dir(), Some(Path::new("/opt/zb/var")));
+    }
+
+    fn definition_running(program: &str) -> ServiceDefinition {
+        ServiceDefinition {
+            run: vec![program.to_string()],
+            run_type: RunType::Immediate,
+            restart: RestartPolicy::Never,
+            health_check: None,
+            working_dir: None,
+            log_path: None,
+            error_log_path: None,
+            environment_variables: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn check_definition_requires_an_executable_program() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let program = tmp.path().join("redis-server");
+        let program_str = program.to_string_lossy();
+
+        assert!(matches!(
+            check_definition("redis", &definition_running(&program_str)),
+            Err(Error::ServiceBinaryMissing { path, .. }) if path == program
+        ));
+
+        std::fs::write(&program, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(matches!(
+            check_definition("redis", &definition_running(&program_str)),
+            Err(Error::ServiceBinaryNotExecutable { .. })
+        ));
+
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        check_definition("redis", &definition_running(&program_str)).unwrap();
+
+        // Bare names are looked up in the service's own PATH.
+        let mut bare = definition_running("redis-server");
+        bare.environment_variables.insert(
+            "PATH".to_string(),
+            format!("/nonexistent:{}", tmp.path().display()),
+        );
+        check_definition("redis", &bare).unwrap();
+    }
+
+    #[test]
+    fn check_definition_rejects_values_service_files_cannot_carry() {
+        let mut definition = definition_running("/bin/sh");
+        definition.working_dir = Some("/nonexistent/var/redis".to_string());
+        assert!(matches!(
+            check_definition("redis", &definition),
+            Err(Error::ServiceFileInvalid { reason, .. }) if reason.contains("working directory")
+        ));
+
+        let mut definition = definition_running("/bin/sh");
+        definition.run.push("--motd=hi\nthere".to_string());
+        assert!(matches!(
+            check_definition("redis", &definition),
+            Err(Error::ServiceFileInvalid { .. })
+        ));
+
+        let mut definition = definition_running("/bin/sh");
+        definition
+            .environment_variables
+            .insert("A=B".to_string(), "C".to_string());
+        assert!(matches!(
+            check_definition("redis", &definition),
+            Err(Error::ServiceFileInvalid { .. })
+        ));
+    }
+
+    #[test]
+    fn probes_tcp_ports() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(probe(&HealthCheck::Tcp(port)));
+
+        drop(listener);
+        assert!(!probe(&HealthCheck::Tcp(port)));
+    }
+
+    #[test]
+    fn probes_commands_by_exit_status() {
+        assert!(probe(&HealthCheck::Command("exit 0".to_string())));
+        assert!(!probe(&HealthCheck::Command("exit 1".to_string())));
+    }
+}
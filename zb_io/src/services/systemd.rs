@@ -0,0 +1,255 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use zb_core::{CronSchedule, Error, RestartPolicy, RunType, ServiceDefinition};
+
+use super::{ServiceState, run_command, write_service_file};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Runs services as systemd user units, with a timer for scheduled ones.
+pub struct SystemdManager {
+    unit_dir: PathBuf,
+}
+
+impl SystemdManager {
+    pub fn new(unit_dir: PathBuf) -> Self {
+        Self { unit_dir }
+    }
+
+    pub fn service_path(&self, label: &str) -> PathBuf {
+        self.unit_dir.join(format!("{label}.service"))
+    }
+
+    pub fn timer_path(&self, label: &str) -> PathBuf {
+        self.unit_dir.join(format!("{label}.timer"))
+    }
+
+    pub(super) fn start(
+        &self,
+        label: &str,
+        definition: &ServiceDefinition,
+    ) -> Result<PathBuf, Error> {
+        let path = self.service_path(label);
+        write_service_file(&path, &render_service(label, definition))?;
+        let unit = match render_timer(label, &definition.run_type) {
+            Some(timer) => {
+                write_service_file(&self.timer_path(label), &timer)?;
+                format!("{label}.timer")
+            }
+            None => format!("{label}.service"),
+        };
+        systemctl(&["daemon-reload"])?;
+        systemctl(&["enable", "--now", &unit])?;
+        Ok(path)
+    }
+
+    pub(super) fn stop(&self, label: &str) -> Result<(), Error> {
+        let timer = self.timer_path(label);
+        if timer.exists() {
+            let _ = systemctl(&["disable", "--now", &format!("{label}.timer")]);
+            std::fs::remove_file(&timer)
+                .map_err(Error::service("failed to remove systemd timer"))?;
+        }
+        let _ = systemctl(&["disable", "--now", &format!("{label}.service")]);
+        std::fs::remove_file(self.service_path(label))
+            .map_err(Error::service("failed to remove systemd unit"))?;
+        systemctl(&["daemon-reload"]).map(|_| ())
+    }
+
+    /// Whether the user's systemd has the service or timer `label` active,
+    /// whoever wrote it.
+    pub(super) fn is_loaded(&self, label: &str) -> bool {
+        ["service", "timer"]
+            .iter()
+            .any(|kind| systemctl(&["is-active", "--quiet", &format!("{label}.{kind}")]).is_ok())
+    }
+
+    pub(super) fn state(&self, label: &str) -> ServiceState {
+        let active = |unit: &str| systemctl(&["is-active", "--quiet", unit]).is_ok();
+        if active(&format!("{label}.service")) {
+            let pid = systemctl(&[
+                "show",
+                "--property",
+                "MainPID",
+                "--value",
+                &format!("{label}.service"),
+            ])
+            .ok()
+            .and_then(|pid| pid.trim().parse().ok())
+            .filter(|pid| *pid != 0);
+            ServiceState::Running { pid }
+        } else if self.timer_path(label).exists() && active(&format!("{label}.timer")) {
+            ServiceState::Scheduled
+        } else {
+            ServiceState::Stopped
+        }
+    }
+}
+
+fn systemctl(args: &[&str]) -> Result<String, Error> {
+    let mut full = vec!["--user"];
+    full.extend_from_slice(args);
+    run_command("systemctl", &full)
+}
+
+/// The `.service` unit for `definition`.
+pub fn render_service(label: &str, definition: &ServiceDefinition) -> String {
+    let scheduled = !matches!(definition.run_type, RunType::Immediate);
+    let mut unit = format!("[Unit]\nDescription=zerobrew service {label}\n\n[Service]\n");
+    let _ = writeln!(
+        unit,
+        "Type={}",
+        if scheduled { "oneshot" } else { "simple" }
+    );
+    let exec: Vec<String> = definition.run.iter().map(|arg| quote(arg)).collect();
+    let _ = writeln!(unit, "ExecStart={}", exec.join(" "));
+    if !scheduled && definition.restart != RestartPolicy::Never {
+        let _ = writeln!(unit, "Restart={}", definition.restart);
+    }
+    if let Some(dir) = &definition.working_dir {
+        let _ = writeln!(unit, "WorkingDirectory={}", quote(dir));
+    }
+    if let Some(path) = &definition.log_path {
+        let _ = writeln!(unit, "StandardOutput=append:{path}");
+    }
+    if let Some(path) = &definition.error_log_path {
+        let _ = writeln!(unit, "StandardError=append:{path}");
+    }
+    for (key, value) in &definition.environment_variables {
+        let _ = writeln!(unit, "Environment={}", quote(&format!("{key}={value}")));
+    }
+    if !scheduled {
+        unit.push_str("\n[Install]\nWantedBy=default.target\n");
+    }
+    unit
+}
+
+/// The `.timer` unit triggering a scheduled service, if it is scheduled.
+pub fn render_timer(label: &str, run_type: &RunType) -> Option<String> {
+    let triggers = match run_type {
+        RunType::Immediate => return None,
+        RunType::Interval(secs) => format!("OnActiveSec={secs}s\nOnUnitActiveSec={secs}s\n"),
+        RunType::Cron(schedule) => on_calendar(schedule)
+            .into_iter()
+            .map(|spec| format!("OnCalendar={spec}\n"))
+            .collect(),
+    };
+    Some(format!(
+        "[Unit]\nDescription=zerobrew timer for {label}\n\n[Timer]\nUnit={label}.service\n{triggers}\n[Install]\nWantedBy=timers.target\n"
+    ))
+}
+
+/// `OnCalendar` expressions for `schedule`. Cron fires when either the day
+/// or the weekday matches but systemd requires both, so a schedule
+/// restricting both becomes two expressions.
+fn on_calendar(schedule: &CronSchedule) -> Vec<String> {
+    let list = |values: &Option<BTreeSet<u32>>, width: usize| match values {
+        None => "*".to_string(),
+        Some(values) => values
+            .iter()
+            .map(|v| format!("{v:0width$}"))
+            .collect::<Vec<_>>()
+            .join(","),
+    };
+    let time = format!(
+        "{}:{}:00",
+        list(&schedule.hour, 2),
+        list(&schedule.minute, 2)
+    );
+    let date =
+        |day: &Option<BTreeSet<u32>>| format!("*-{}-{}", list(&schedule.month, 2), list(day, 2));
+    let weekdays = |days: &BTreeSet<u32>| {
+        days.iter()
+            .map(|d| WEEKDAYS[*d as usize])
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    match (&schedule.day, &schedule.weekday) {
+        (Some(_), Some(days)) => vec![
+            format!("{} {time}", date(&schedule.day)),
+            format!("{} {} {time}", weekdays(days), date(&None)),
+        ],
+        (_, Some(days)) => vec![format!("{} {} {time}", weekdays(days), date(&None))],
+        _ => vec![format!("{} {time}", date(&schedule.day))],
+    }
+}
+
+fn quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn definition(run_type: RunType) -> ServiceDefinition {
+        ServiceDefinition {
+            run: vec!["/opt/zb/bin/backup".to_string(), "--label=100%".to_string()],
+            run_type,
+            restart: RestartPolicy::Always,
+            health_check: None,
+            working_dir: None,
+            log_path: Some("/opt/zb/var/log/backup.log".to_string()),
+            error_log_path: None,
+            environment_variables: BTreeMap::from([("MODE".to_string(), "full".to_string())]),
+        }
+    }
+
+    #[test]
+    fn immediate_service_is_wanted_by_default_target() {
+        let unit = render_service("zerobrew.backup", &definition(RunType::Immediate));
+        assert!(unit.contains("Type=simple\n"));
+        assert!(unit.contains("ExecStart=\"/opt/zb/bin/backup\" \"--label=100%%\"\n"));
+        assert!(unit.contains("Restart=always\n"));
+        assert!(unit.contains("StandardOutput=append:/opt/zb/var/log/backup.log\n"));
+        assert!(unit.contains("Environment=\"MODE=full\"\n"));
+        assert!(unit.contains("WantedBy=default.target"));
+        assert_eq!(render_timer("zerobrew.backup", &RunType::Immediate), None);
+
+        let mut on_failure = definition(RunType::Immediate);
+        on_failure.restart = RestartPolicy::OnFailure;
+        let unit = render_service("zerobrew.backup", &on_failure);
+        assert!(unit.contains("Restart=on-failure\n"));
+    }
+
+    #[test]
+    fn scheduled_service_gets_a_timer() {
+        let run_type = RunType::Interval(3600);
+        let unit = render_service("zerobrew.backup", &definition(run_type.clone()));
+        assert!(unit.contains("Type=oneshot\n"));
+        assert!(!unit.contains("Restart="));
+        assert!(!unit.contains("[Install]"));
+
+        let timer = render_timer("zerobrew.backup", &run_type).unwrap();
+        assert!(timer.contains("Unit=zerobrew.backup.service\n"));
+        assert!(timer.contains("OnUnitActiveSec=3600s\n"));
+        assert!(timer.contains("WantedBy=timers.target"));
+    }
+
+    #[test]
+    fn cron_becomes_on_calendar() {
+        let nightly = CronSchedule::parse("0 3 * * *").unwrap();
+        assert_eq!(on_calendar(&nightly), ["*-*-* 03:00:00"]);
+
+        let weekdays = CronSchedule::parse("*/30 9 * * 1-5").unwrap();
+        assert_eq!(
+            on_calendar(&weekdays),
+            ["Mon,Tue,Wed,Thu,Fri *-*-* 09:00,30:00"]
+        );
+
+        let either = CronSchedule::parse("0 0 1 * 0").unwrap();
+        assert_eq!(
+            on_calendar(&either),
+            ["*-*-01 00:00:00", "Sun *-*-* 00:00:00"]
+        );
+    }
+}
@@ -1,14 +1,27 @@
 use std::fs;
 use std::io::{self, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use tempfile::NamedTempFile;
 use zb_core::Error;
 
+/// A cached bottle blob as seen on disk.
+#[derive(Debug, Clone)]
+pub struct BlobEntry {
+    pub sha256: String,
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time, refreshed on every cache hit so it doubles as an
+    /// LRU timestamp.
+    pub last_used: SystemTime,
+}
+
 #[derive(Clone)]
 pub struct BlobCache {
     blobs_dir: PathBuf,
     tmp_dir: PathBuf,
+    quarantine_dir: PathBuf,
 }
 
 impl BlobCache {
@@ -19,7 +32,11 @@ impl BlobCache {
         fs::create_dir_all(&blobs_dir)?;
         fs::create_dir_all(&tmp_dir)?;
 
-        Ok(Self { blobs_dir, tmp_dir })
+        Ok(Self {
+            blobs_dir,
+            tmp_dir,
+            quarantine_dir: cache_root.join("quarantine"),
+        })
     }
 
     pub fn blob_path(&self, sha256: &str) -> PathBuf {
@@ -41,6 +58,88 @@ impl BlobCache {
         }
     }
 
+    /// Move a blob that failed to verify or unpack out of the cache, keeping
+    /// it under `quarantine/` for inspection. Returns where it went, or
+    /// `None` when there was no such blob.
+    pub fn quarantine_blob(&self, sha256: &str) -> io::Result<Option<PathBuf>> {
+        let path = self.blob_path(sha256);
+        if !path.exists() {
+            return Ok(None);
+        }
+        fs::create_dir_all(&self.quarantine_dir)?;
+        let stamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let target = self.quarantine_dir.join(format!("{sha256}-{stamp}.tar.gz"));
+        fs::rename(&path, &target)?;
+        Ok(Some(target))
+    }
+
+    /// Quarantined blobs and their sizes in bytes.
+    pub fn list_quarantined(&self) -> io::Result<Vec<(PathBuf, u64)>> {
+        let entries = match fs::read_dir(&self.quarantine_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut quarantined = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                quarantined.push((entry.path(), metadata.len()));
+            }
+        }
+        quarantined.sort();
+        Ok(quarantined)
+    }
+
+    /// Delete every quarantined blob, returning how many there were.
+    pub fn clear_quarantine(&self) -> io::Result<usize> {
+        let quarantined = self.list_quarantined()?;
+        for (path, _) in &quarantined {
+            fs::remove_file(path)?;
+        }
+        Ok(quarantined.len())
+    }
+
+    /// Mark a blob as recently used so size-based eviction keeps it longer.
+    pub fn touch_blob(&self, sha256: &str) -> io::Result<()> {
+        let file = fs::File::options()
+            .append(true)
+            .open(self.blob_path(sha256))?;
+        file.set_modified(SystemTime::now())
+    }
+
+    pub fn list_blobs(&self) -> io::Result<Vec<BlobEntry>> {
+        let mut blobs = Vec::new();
+        for entry in fs::read_dir(&self.blobs_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(sha256) = file_name.to_str().and_then(|n| n.strip_suffix(".tar.gz")) else {
+                continue;
+            };
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            blobs.push(BlobEntry {
+                sha256: sha256.to_string(),
+                path: entry.path(),
+                size: metadata.len(),
+                last_used: metadata.modified()?,
+            });
+        }
+        blobs.sort_by(|a, b| a.sha256.cmp(&b.sha256));
+        Ok(blobs)
+    }
+
+    /// Total size in bytes of all cached blobs.
+    pub fn total_size(&self) -> io::Result<u64> {
+        Ok(self.list_blobs()?.iter().map(|b| b.size).sum())
+    }
+
     pub fn start_write(&self, sha256: &str) -> io::Result<BlobWriter> {
         let final_path = self.blob_path(sha256);
         let temp_file = NamedTempFile::new_in(&self.tmp_dir)?;
@@ -153,6 +252,68 @@ mod tests {
         assert!(!cache.has_blob(sha));
     }
 
+    #[test]
+    fn list_blobs_reports_sizes_and_total() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+
+        for (sha, data) in [("aaa", &b"12345"[..]), ("bbb", &b"123"[..])] {
+            let mut writer = cache.start_write(sha).unwrap();
+            writer.write_all(data).unwrap();
+            writer.commit().unwrap();
+        }
+        fs::write(tmp.path().join("blobs/stray.txt"), b"ignored").unwrap();
+
+        let blobs = cache.list_blobs().unwrap();
+        let names: Vec<_> = blobs.iter().map(|b| b.sha256.as_str()).collect();
+        assert_eq!(names, vec!["aaa", "bbb"]);
+        assert_eq!(cache.total_size().unwrap(), 8);
+    }
+
+    #[test]
+    fn touch_blob_refreshes_last_used() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+
+        let mut writer = cache.start_write("old").unwrap();
+        writer.write_all(b"data").unwrap();
+        let path = writer.commit().unwrap();
+
+        let long_ago = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::File::options()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(long_ago)
+            .unwrap();
+        assert_eq!(cache.list_blobs().unwrap()[0].last_used, long_ago);
+
+        cache.touch_blob("old").unwrap();
+        assert!(cache.list_blobs().unwrap()[0].last_used > long_ago);
+    }
+
+    #[test]
+    fn quarantine_moves_blob_aside() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+        assert!(cache.list_quarantined().unwrap().is_empty());
+
+        let mut writer = cache.start_write("bad").unwrap();
+        writer.write_all(b"corrupt data").unwrap();
+        writer.commit().unwrap();
+
+        let moved = cache.quarantine_blob("bad").unwrap().unwrap();
+        assert!(!cache.has_blob("bad"));
+        assert!(moved.starts_with(tmp.path().join("quarantine")));
+        assert_eq!(fs::read(&moved).unwrap(), b"corrupt data");
+        assert_eq!(cache.list_quarantined().unwrap(), vec![(moved, 12)]);
+        assert!(cache.list_blobs().unwrap().is_empty());
+        assert!(cache.quarantine_blob("bad").unwrap().is_none());
+
+        assert_eq!(cache.clear_quarantine().unwrap(), 1);
+        assert!(cache.list_quarantined().unwrap().is_empty());
+    }
+
     #[test]
     fn remove_blob_returns_false_for_nonexistent() {
         let tmp = TempDir::new().unwrap();
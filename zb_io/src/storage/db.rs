@@ -1,11 +1,20 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use rusqlite::{
+    Connection, ErrorCode, OptionalExtension, Transaction, TransactionBehavior, params,
+};
 
-use zb_core::Error;
+use zb_core::{CronSchedule, Error, HealthCheck, RestartPolicy, RunType, ServiceDefinition};
+
+use crate::cellar::link::LinkMode;
+
+use super::migrations;
 
 pub struct Database {
     conn: Connection,
+    schema_version: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -14,6 +23,26 @@ pub struct InstalledKeg {
     pub version: String,
     pub store_key: String,
     pub installed_at: i64,
+    /// Disk usage of the keg in bytes, computed at install time.
+    pub size_bytes: Option<u64>,
+    /// Bytes of the keg that are hardlinks or clones of its store entry,
+    /// so take no extra space. Computed at install time.
+    pub shared_bytes: Option<u64>,
+    /// CPU architecture of a keg installed for a foreign arch (e.g.
+    /// `x86_64` under Rosetta 2). `None` for native kegs.
+    pub arch: Option<String>,
+}
+
+/// A keg kept on disk after an upgrade replaced it, so it can be rolled
+/// back to. It keeps its store reference until collected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KegGeneration {
+    pub name: String,
+    pub version: String,
+    pub store_key: String,
+    pub arch: Option<String>,
+    pub installed_at: i64,
+    pub superseded_at: i64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -22,113 +51,289 @@ pub struct StoreRef {
     pub refcount: i64,
 }
 
+/// A store entry no keg or generation references any more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnreferencedStoreRef {
+    pub store_key: String,
+    /// When it lost its last reference; `None` if that was before this was
+    /// recorded.
+    pub released_at: Option<i64>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KegFileRecord {
     pub name: String,
     pub version: String,
     pub linked_path: String,
     pub target_path: String,
+    pub mode: LinkMode,
 }
 
-impl Database {
-    const SCHEMA_VERSION: u32 = 1;
+/// A file or symlink a keg contained when it was installed, relative to
+/// the keg.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size_bytes: u64,
+    /// Content hash of a regular file; `None` for symlinks.
+    pub sha256: Option<String>,
+}
 
-    pub fn open(path: &Path) -> Result<Self, Error> {
-        let conn = Connection::open(path).map_err(Error::store("failed to open database"))?;
-        Self::migrate(&conn)?;
-        Ok(Self { conn })
-    }
+/// What `zb services` remembers about a formula's service, on top of the
+/// definition the formula itself provides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSettings {
+    pub name: String,
+    /// Replaces the run type the formula declares.
+    pub schedule: Option<RunType>,
+    pub overrides: ServiceOverrides,
+}
 
-    pub fn in_memory() -> Result<Self, Error> {
-        let conn =
-            Connection::open_in_memory().map_err(Error::store("failed to open in-memory db"))?;
-        Self::migrate(&conn)?;
-        Ok(Self { conn })
-    }
+/// Changes `zb services edit` makes to how a service is run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceOverrides {
+    /// Added to the service's environment, replacing variables it sets.
+    pub environment: BTreeMap<String, String>,
+    /// Appended to the service's command line.
+    pub args: Vec<String>,
+    pub working_dir: Option<String>,
+    pub restart: Option<RestartPolicy>,
+    pub health_check: Option<HealthCheck>,
+}
 
-    fn get_schema_version(conn: &Connection) -> Result<u32, Error> {
-        let version: u32 = conn
-            .query_row("PRAGMA user_version", [], |row| row.get(0))
-            .map_err(Error::store("failed to query schema version"))?;
-        Ok(version)
+impl ServiceOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.environment.is_empty()
+            && self.args.is_empty()
+            && self.working_dir.is_none()
+            && self.restart.is_none()
+            && self.health_check.is_none()
     }
 
-    fn set_schema_version(conn: &Connection, version: u32) -> Result<(), Error> {
-        conn.execute(&format!("PRAGMA user_version = {}", version), [])
-            .map_err(Error::store("failed to set schema version"))?;
-        Ok(())
+    pub fn apply(&self, definition: &mut ServiceDefinition) {
+        definition.environment_variables.extend(
+            self.environment
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+        definition.run.extend(self.args.iter().cloned());
+        if let Some(dir) = &self.working_dir {
+            definition.working_dir = Some(dir.clone());
+        }
+        if let Some(restart) = self.restart {
+            definition.restart = restart;
+        }
+        if let Some(check) = &self.health_check {
+            definition.health_check = Some(check.clone());
+        }
     }
+}
 
-    fn migrate(conn: &Connection) -> Result<(), Error> {
-        let current_version = Self::get_schema_version(conn)?;
+/// A named link tree kept next to the prefix, see `zb profile`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileRecord {
+    pub name: String,
+    pub created_at: i64,
+    /// The kegs linked into it, as `(name, version)`, sorted by name.
+    pub kegs: Vec<(String, String)>,
+}
 
-        if current_version > Self::SCHEMA_VERSION {
-            return Err(Error::StoreCorruption {
-                message: format!(
-                    "database schema version {} is newer than supported version {}. \
-                     Please upgrade zerobrew",
-                    current_version,
-                    Self::SCHEMA_VERSION
-                ),
-            });
-        }
+/// A core formula's API JSON, kept so resolving needs no network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaRecord {
+    pub name: String,
+    pub version: String,
+    pub dependencies: Vec<String>,
+    /// Aliases and old names the formula is also known by.
+    pub aliases: Vec<String>,
+    pub json: String,
+}
 
-        if current_version == Self::SCHEMA_VERSION {
-            return Ok(());
-        }
+/// A stored formula's JSON and when it was fetched (Unix seconds).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedFormula {
+    pub json: String,
+    pub fetched_at: i64,
+}
 
-        for version in current_version..Self::SCHEMA_VERSION {
-            let next_version = version + 1;
-            Self::migrate_to_version(conn, next_version)?;
-            Self::set_schema_version(conn, next_version)?;
-        }
+/// The vulnerability records last fetched for a package, as a JSON array
+/// of OSV entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedAdvisories {
+    pub json: String,
+    pub fetched_at: i64,
+}
 
-        Ok(())
+/// A bottle URL whose data failed to verify or unpack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptDownload {
+    pub url: String,
+    pub sha256: String,
+    pub failures: u32,
+    /// When it last failed (Unix seconds).
+    pub last_failed_at: i64,
+}
+
+/// Timings of one install, recorded for `zb stats` when stats are enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallStat {
+    pub name: String,
+    /// `bottle` or `source`.
+    pub method: &'static str,
+    /// Wall time from the start of the download or build to the keg being
+    /// linked.
+    pub duration_ms: u64,
+    /// Whether the bottle was already in the download cache or store.
+    pub cached: bool,
+    /// Bytes fetched over the network, for bottles that were not cached.
+    pub download_bytes: Option<u64>,
+    pub download_ms: Option<u64>,
+    /// Time spent materializing a keg whose bottle needed relocation.
+    pub relocation_ms: Option<u64>,
+}
+
+/// Totals over every recorded install.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct StatsSummary {
+    pub installs: u64,
+    pub source_builds: u64,
+    /// Bottle installs that needed no download.
+    pub cache_hits: u64,
+    pub total_duration_ms: u64,
+    pub downloaded_bytes: u64,
+    pub download_ms: u64,
+    pub relocations: u64,
+    pub relocation_ms: u64,
+    /// When the oldest recorded install happened (Unix seconds).
+    pub since: Option<i64>,
+    /// The longest installs, slowest first, as `(name, duration_ms)`.
+    pub slowest: Vec<(String, u64)>,
+}
+
+/// A version change one recorded operation made. `None` means the package
+/// was not installed on that side.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct PackageChange {
+    pub name: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// An install, uninstall, upgrade or other change recorded in the history.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct OperationRecord {
+    pub id: i64,
+    pub kind: String,
+    /// Unix seconds.
+    pub recorded_at: i64,
+    /// Why the operation failed; its changes are what it did before that.
+    pub error: Option<String>,
+    pub detail: Option<String>,
+    /// Sorted by name.
+    pub changes: Vec<PackageChange>,
+}
+
+/// How many times a statement that found the database locked by another
+/// connection is retried, with [`busy_backoff`] between attempts. Adds up
+/// to about ten seconds.
+const MAX_BUSY_RETRIES: i32 = 110;
+
+/// Busy handler: back off from 1ms up to 100ms while another connection
+/// (a second `zb`, or the service manager in this one) holds the write
+/// lock, then give up with `SQLITE_BUSY`.
+fn busy_backoff(attempt: i32) -> bool {
+    if attempt >= MAX_BUSY_RETRIES {
+        return false;
     }
+    std::thread::sleep(Duration::from_millis((1u64 << attempt.min(7)).min(100)));
+    true
+}
 
-    fn migrate_to_version(conn: &Connection, version: u32) -> Result<(), Error> {
-        match version {
-            1 => Self::migrate_to_v1(conn),
-            _ => Err(Error::StoreCorruption {
-                message: format!("unknown migration version {}", version),
-            }),
-        }
+/// Begin a transaction that takes the write lock up front. Writers then
+/// queue on the busy handler instead of failing when a read inside a
+/// deferred transaction has to be upgraded after another connection
+/// committed, which SQLite cannot retry.
+pub(super) fn write_transaction<'c>(
+    conn: &'c mut Connection,
+    ctx: &str,
+) -> Result<Transaction<'c>, Error> {
+    conn.transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|e| {
+            if e.sqlite_error_code() == Some(ErrorCode::DatabaseBusy) {
+                Error::DatabaseLocked {
+                    context: ctx.to_string(),
+                }
+            } else {
+                Error::store(ctx)(e)
+            }
+        })
+}
+
+impl Database {
+    /// Open the database in WAL mode, so readers never block the writer,
+    /// with a busy handler so concurrent writers wait for each other.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let conn = Connection::open(path).map_err(Error::store("failed to open database"))?;
+        conn.busy_handler(Some(busy_backoff))
+            .map_err(Error::store("failed to configure database"))?;
+        // Switching the journal needs write access; a read-only database
+        // keeps its mode and can still be queried.
+        let _ = conn
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0));
+        conn.pragma_update(None, "synchronous", "NORMAL")
+            .map_err(Error::store("failed to configure database"))?;
+        Self::from_connection(conn)
     }
 
-    fn migrate_to_v1(conn: &Connection) -> Result<(), Error> {
-        conn.execute_batch(
-            "
-            CREATE TABLE IF NOT EXISTS installed_kegs (
-                name TEXT PRIMARY KEY,
-                version TEXT NOT NULL,
-                store_key TEXT NOT NULL,
-                installed_at INTEGER NOT NULL
-            );
+    pub fn in_memory() -> Result<Self, Error> {
+        let conn =
+            Connection::open_in_memory().map_err(Error::store("failed to open in-memory db"))?;
+        Self::from_connection(conn)
+    }
 
-            CREATE TABLE IF NOT EXISTS store_refs (
-                store_key TEXT PRIMARY KEY,
-                refcount INTEGER NOT NULL DEFAULT 1
-            );
+    fn from_connection(mut conn: Connection) -> Result<Self, Error> {
+        migrations::apply_pending(&mut conn)?;
+        let schema_version = migrations::current_version(&conn)?;
+        Ok(Self {
+            conn,
+            schema_version,
+        })
+    }
 
-            CREATE TABLE IF NOT EXISTS keg_files (
-                name TEXT NOT NULL,
-                version TEXT NOT NULL,
-                linked_path TEXT NOT NULL,
-                target_path TEXT NOT NULL,
-                PRIMARY KEY (name, linked_path)
-            );
-            ",
-        )
-        .map_err(Error::store("failed to create initial schema"))?;
+    /// Schema version of the open database.
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
 
-        Ok(())
+    /// Newest schema version this build of zerobrew understands.
+    pub fn supported_schema_version() -> u32 {
+        migrations::latest_version()
+    }
+
+    /// A database written by a newer zerobrew can still be read, but is not
+    /// modified.
+    pub fn is_newer_than_supported(&self) -> bool {
+        self.schema_version > Self::supported_schema_version()
+    }
+
+    /// Fail if the database [`is_newer_than_supported`](Self::is_newer_than_supported).
+    pub fn ensure_writable(&self) -> Result<(), Error> {
+        if !self.is_newer_than_supported() {
+            return Ok(());
+        }
+        Err(Error::StoreCorruption {
+            message: format!(
+                "database schema version {} is newer than supported version {}. \
+                 Please upgrade zerobrew",
+                self.schema_version,
+                Self::supported_schema_version()
+            ),
+        })
     }
 
     pub fn transaction(&mut self) -> Result<InstallTransaction<'_>, Error> {
-        let tx = self
-            .conn
-            .transaction()
-            .map_err(Error::store("failed to start transaction"))?;
+        self.ensure_writable()?;
+        let tx = write_transaction(&mut self.conn, "failed to start transaction")?;
 
         Ok(InstallTransaction { tx })
     }
@@ -136,16 +341,10 @@ impl Database {
     pub fn get_installed(&self, name: &str) -> Option<InstalledKeg> {
         self.conn
             .query_row(
-                "SELECT name, version, store_key, installed_at FROM installed_kegs WHERE name = ?1",
+                "SELECT name, version, store_key, installed_at, size_bytes, arch, shared_bytes
+                 FROM installed_kegs WHERE name = ?1",
                 params![name],
-                |row| {
-                    Ok(InstalledKeg {
-                        name: row.get(0)?,
-                        version: row.get(1)?,
-                        store_key: row.get(2)?,
-                        installed_at: row.get(3)?,
-                    })
-                },
+                Self::installed_keg_from_row,
             )
             .ok()
     }
@@ -154,24 +353,112 @@ impl Database {
         let mut stmt = self
             .conn
             .prepare(
-                "SELECT name, version, store_key, installed_at FROM installed_kegs ORDER BY name",
+                "SELECT name, version, store_key, installed_at, size_bytes, arch, shared_bytes
+                 FROM installed_kegs ORDER BY name",
             )
             .map_err(Error::store("failed to prepare statement"))?;
 
         let kegs = stmt
-            .query_map([], |row| {
-                Ok(InstalledKeg {
+            .query_map([], Self::installed_keg_from_row)
+            .map_err(Error::store("failed to query installed kegs"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        Ok(kegs)
+    }
+
+    fn installed_keg_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<InstalledKeg> {
+        Ok(InstalledKeg {
+            name: row.get(0)?,
+            version: row.get(1)?,
+            store_key: row.get(2)?,
+            installed_at: row.get(3)?,
+            size_bytes: row.get::<_, Option<i64>>(4)?.map(|s| s as u64),
+            arch: row.get(5)?,
+            shared_bytes: row.get::<_, Option<i64>>(6)?.map(|s| s as u64),
+        })
+    }
+
+    /// Cache the disk usage of an installed keg (used to backfill kegs
+    /// installed before sizes were tracked).
+    pub fn set_keg_size(&self, name: &str, size_bytes: u64) -> Result<(), Error> {
+        self.ensure_writable()?;
+        self.conn
+            .execute(
+                "UPDATE installed_kegs SET size_bytes = ?2 WHERE name = ?1",
+                params![name, size_bytes as i64],
+            )
+            .map_err(Error::store("failed to record keg size"))?;
+        Ok(())
+    }
+
+    /// Cache how many bytes of an installed keg its store entry shares.
+    pub fn set_keg_shared_size(&self, name: &str, shared_bytes: u64) -> Result<(), Error> {
+        self.ensure_writable()?;
+        self.conn
+            .execute(
+                "UPDATE installed_kegs SET shared_bytes = ?2 WHERE name = ?1",
+                params![name, shared_bytes as i64],
+            )
+            .map_err(Error::store("failed to record keg shared size"))?;
+        Ok(())
+    }
+
+    /// Direct runtime dependencies recorded when `name` was installed.
+    pub fn list_dependencies(&self, name: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT dependency FROM keg_dependencies WHERE name = ?1 ORDER BY dependency")
+            .map_err(Error::store("failed to prepare statement"))?;
+
+        let deps = stmt
+            .query_map(params![name], |row| row.get(0))
+            .map_err(Error::store("failed to query keg dependencies"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        Ok(deps)
+    }
+
+    /// Previous generations of `name`, most recently superseded first.
+    pub fn list_generations(&self, name: &str) -> Result<Vec<KegGeneration>, Error> {
+        self.query_generations("WHERE name = ?1", params![name])
+    }
+
+    pub fn list_all_generations(&self) -> Result<Vec<KegGeneration>, Error> {
+        self.query_generations("", params![])
+    }
+
+    fn query_generations(
+        &self,
+        filter: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<KegGeneration>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT name, version, store_key, arch, installed_at, superseded_at
+                 FROM keg_generations {filter}
+                 ORDER BY name, superseded_at DESC, rowid DESC"
+            ))
+            .map_err(Error::store("failed to prepare statement"))?;
+
+        let generations = stmt
+            .query_map(params, |row| {
+                Ok(KegGeneration {
                     name: row.get(0)?,
                     version: row.get(1)?,
                     store_key: row.get(2)?,
-                    installed_at: row.get(3)?,
+                    arch: row.get(3)?,
+                    installed_at: row.get(4)?,
+                    superseded_at: row.get(5)?,
                 })
             })
-            .map_err(Error::store("failed to query installed kegs"))?
+            .map_err(Error::store("failed to query keg generations"))?
             .collect::<Result<Vec<_>, _>>()
             .map_err(Error::store("failed to collect results"))?;
 
-        Ok(kegs)
+        Ok(generations)
     }
 
     pub fn get_store_refcount(&self, store_key: &str) -> i64 {
@@ -199,7 +486,28 @@ impl Database {
         Ok(keys)
     }
 
+    pub fn get_unreferenced_store_refs(&self) -> Result<Vec<UnreferencedStoreRef>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT store_key, released_at FROM store_refs WHERE refcount <= 0")
+            .map_err(Error::store("failed to prepare statement"))?;
+
+        let refs = stmt
+            .query_map([], |row| {
+                Ok(UnreferencedStoreRef {
+                    store_key: row.get(0)?,
+                    released_at: row.get(1)?,
+                })
+            })
+            .map_err(Error::store("failed to query unreferenced keys"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        Ok(refs)
+    }
+
     pub fn delete_store_ref(&self, store_key: &str) -> Result<(), Error> {
+        self.ensure_writable()?;
         self.conn
             .execute(
                 "DELETE FROM store_refs WHERE store_key = ?1",
@@ -230,22 +538,69 @@ impl Database {
     }
 
     pub fn list_keg_files(&self) -> Result<Vec<KegFileRecord>, Error> {
+        self.query_keg_files(
+            "SELECT name, version, linked_path, target_path, mode
+             FROM keg_files
+             ORDER BY name, version, linked_path",
+            [],
+        )
+    }
+
+    /// The links recorded for `name`, of any version.
+    pub fn keg_files(&self, name: &str) -> Result<Vec<KegFileRecord>, Error> {
+        self.query_keg_files(
+            "SELECT name, version, linked_path, target_path, mode
+             FROM keg_files
+             WHERE name = ?1
+             ORDER BY version, linked_path",
+            params![name],
+        )
+    }
+
+    /// The links recorded for the installed version of each keg.
+    pub fn installed_keg_files(&self) -> Result<Vec<KegFileRecord>, Error> {
+        self.query_keg_files(
+            "SELECT f.name, f.version, f.linked_path, f.target_path, f.mode
+             FROM keg_files f
+             JOIN installed_kegs k ON k.name = f.name AND k.version = f.version
+             ORDER BY f.name, f.linked_path",
+            [],
+        )
+    }
+
+    /// Up to `limit` links of installed kegs, picked at random.
+    pub fn sample_installed_keg_files(&self, limit: usize) -> Result<Vec<KegFileRecord>, Error> {
+        self.query_keg_files(
+            "SELECT f.name, f.version, f.linked_path, f.target_path, f.mode
+             FROM keg_files f
+             JOIN installed_kegs k ON k.name = f.name AND k.version = f.version
+             ORDER BY random()
+             LIMIT ?1",
+            params![limit as i64],
+        )
+    }
+
+    fn query_keg_files(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<KegFileRecord>, Error> {
         let mut stmt = self
             .conn
-            .prepare(
-                "SELECT name, version, linked_path, target_path
-                 FROM keg_files
-                 ORDER BY name, version, linked_path",
-            )
+            .prepare(sql)
             .map_err(Error::store("failed to prepare statement"))?;
 
         let records = stmt
-            .query_map([], |row| {
+            .query_map(params, |row| {
                 Ok(KegFileRecord {
                     name: row.get(0)?,
                     version: row.get(1)?,
                     linked_path: row.get(2)?,
                     target_path: row.get(3)?,
+                    mode: row
+                        .get::<_, String>(4)?
+                        .parse()
+                        .unwrap_or(LinkMode::Symlink),
                 })
             })
             .map_err(Error::store("failed to query keg files"))?
@@ -255,93 +610,941 @@ impl Database {
         Ok(records)
     }
 
-    pub fn replace_store_refs(&self, store_refs: &[StoreRef]) -> Result<(), Error> {
-        let tx = self
+    /// The files recorded for the installed keg of `name`, by path. Empty
+    /// for kegs installed before manifests were recorded.
+    pub fn keg_manifest(&self, name: &str) -> Result<Vec<ManifestEntry>, Error> {
+        let mut stmt = self
             .conn
-            .unchecked_transaction()
-            .map_err(Error::store("failed to start transaction"))?;
-
-        tx.execute("DELETE FROM store_refs", [])
-            .map_err(Error::store("failed to clear store refs"))?;
-
-        {
-            let mut stmt = tx
-                .prepare("INSERT INTO store_refs (store_key, refcount) VALUES (?1, ?2)")
-                .map_err(Error::store("failed to prepare statement"))?;
+            .prepare(
+                "SELECT path, size_bytes, sha256 FROM keg_manifests
+                 WHERE name = ?1
+                 ORDER BY path",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
 
-            for store_ref in store_refs {
-                stmt.execute(params![store_ref.store_key, store_ref.refcount])
-                    .map_err(Error::store("failed to insert store ref"))?;
-            }
-        }
+        let entries = stmt
+            .query_map(params![name], |row| {
+                Ok(ManifestEntry {
+                    path: row.get(0)?,
+                    size_bytes: row.get::<_, i64>(1)? as u64,
+                    sha256: row.get(2)?,
+                })
+            })
+            .map_err(Error::store("failed to query keg manifest"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
 
-        tx.commit()
-            .map_err(Error::store("failed to commit transaction"))
+        Ok(entries)
     }
 
-    pub fn count_stale_keg_file_records(&self) -> Result<usize, Error> {
-        let count: i64 = self
-            .conn
+    /// The installed formula whose link occupies `linked_path`, if zerobrew
+    /// created it.
+    pub fn link_owner(&self, linked_path: &Path) -> Result<Option<String>, Error> {
+        self.conn
             .query_row(
-                "SELECT COUNT(*) FROM keg_files
-                 WHERE NOT EXISTS (
-                     SELECT 1
-                     FROM installed_kegs
-                     WHERE installed_kegs.name = keg_files.name
-                       AND installed_kegs.version = keg_files.version
-                 )",
-                [],
+                "SELECT name FROM keg_files WHERE linked_path = ?1 LIMIT 1",
+                params![linked_path.to_string_lossy()],
                 |row| row.get(0),
             )
-            .map_err(Error::store("failed to count stale keg file records"))?;
-        Ok(count as usize)
+            .optional()
+            .map_err(Error::store("failed to query link owner"))
     }
 
-    pub fn prune_stale_keg_file_records(&self) -> Result<usize, Error> {
-        self.conn
-            .execute(
-                "DELETE FROM keg_files
-                 WHERE NOT EXISTS (
-                     SELECT 1
-                     FROM installed_kegs
-                     WHERE installed_kegs.name = keg_files.name
-                       AND installed_kegs.version = keg_files.version
-                 )",
-                [],
+    /// How the executables of `name` are to be linked, when set for it
+    /// alone.
+    pub fn link_mode(&self, name: &str) -> Result<Option<LinkMode>, Error> {
+        let mode: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT mode FROM link_modes WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
             )
-            .map_err(Error::store("failed to prune stale keg file records"))
+            .optional()
+            .map_err(Error::store("failed to query link mode"))?;
+        Ok(mode.and_then(|m| m.parse().ok()))
     }
-}
-
-pub struct InstallTransaction<'a> {
-    tx: Transaction<'a>,
-}
 
-impl<'a> InstallTransaction<'a> {
-    pub fn record_install(&self, name: &str, version: &str, store_key: &str) -> Result<(), Error> {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs() as i64)
-            .unwrap_or(0);
+    /// Link the executables of `name` by `mode` from now on, whatever the
+    /// global mode. `None` clears the setting.
+    pub fn set_link_mode(&self, name: &str, mode: Option<LinkMode>) -> Result<(), Error> {
+        self.ensure_writable()?;
+        match mode {
+            Some(mode) => self.conn.execute(
+                "INSERT INTO link_modes (name, mode) VALUES (?1, ?2)
+                 ON CONFLICT(name) DO UPDATE SET mode = ?2",
+                params![name, mode.as_str()],
+            ),
+            None => self
+                .conn
+                .execute("DELETE FROM link_modes WHERE name = ?1", params![name]),
+        }
+        .map_err(Error::store("failed to record link mode"))?;
+        Ok(())
+    }
 
-        let previous_store_key: Option<String> = self
-            .tx
+    /// The free-text note recorded for `name`, if any.
+    pub fn package_note(&self, name: &str) -> Result<Option<String>, Error> {
+        self.conn
             .query_row(
-                "SELECT store_key FROM installed_kegs WHERE name = ?1",
+                "SELECT note FROM package_notes WHERE name = ?1",
                 params![name],
                 |row| row.get(0),
             )
             .optional()
-            .map_err(Error::store("failed to query previous store key"))?;
+            .map_err(Error::store("failed to query package note"))
+    }
 
-        self.tx
-            .execute(
-                "INSERT INTO installed_kegs (name, version, store_key, installed_at)
-                 VALUES (?1, ?2, ?3, ?4)
-                 ON CONFLICT(name) DO UPDATE SET
+    /// Replace the note of `name`; `None` clears it.
+    pub fn set_package_note(&self, name: &str, note: Option<&str>) -> Result<(), Error> {
+        self.ensure_writable()?;
+        match note {
+            Some(note) => self.conn.execute(
+                "INSERT INTO package_notes (name, note, updated_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(name) DO UPDATE SET note = ?2, updated_at = ?3",
+                params![name, note, unix_now()],
+            ),
+            None => self
+                .conn
+                .execute("DELETE FROM package_notes WHERE name = ?1", params![name]),
+        }
+        .map_err(Error::store("failed to record package note"))?;
+        Ok(())
+    }
+
+    /// The tags of `name`, sorted.
+    pub fn package_tags(&self, name: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag FROM package_tags WHERE name = ?1 ORDER BY tag")
+            .map_err(Error::store("failed to prepare statement"))?;
+        stmt.query_map(params![name], |row| row.get(0))
+            .map_err(Error::store("failed to query package tags"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))
+    }
+
+    /// Add `add` to the tags of `name` and drop `remove` from them.
+    pub fn update_package_tags(
+        &mut self,
+        name: &str,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<(), Error> {
+        self.ensure_writable()?;
+        let tx = write_transaction(&mut self.conn, "failed to start transaction")?;
+        for tag in add {
+            tx.execute(
+                "INSERT OR IGNORE INTO package_tags (name, tag) VALUES (?1, ?2)",
+                params![name, tag],
+            )
+            .map_err(Error::store("failed to record package tag"))?;
+        }
+        for tag in remove {
+            tx.execute(
+                "DELETE FROM package_tags WHERE name = ?1 AND tag = ?2",
+                params![name, tag],
+            )
+            .map_err(Error::store("failed to remove package tag"))?;
+        }
+        tx.commit()
+            .map_err(Error::store("failed to commit transaction"))
+    }
+
+    /// Names of the packages tagged `tag`, sorted.
+    pub fn packages_tagged(&self, tag: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM package_tags WHERE tag = ?1 ORDER BY name")
+            .map_err(Error::store("failed to prepare statement"))?;
+        stmt.query_map(params![tag], |row| row.get(0))
+            .map_err(Error::store("failed to query tagged packages"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))
+    }
+
+    /// Record profile `name` holding `kegs`, as `(name, version)`.
+    pub fn create_profile(&self, name: &str, kegs: &[(String, String)]) -> Result<(), Error> {
+        self.ensure_writable()?;
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(Error::store("failed to start transaction"))?;
+        tx.execute(
+            "INSERT INTO profiles (name, created_at) VALUES (?1, ?2)",
+            params![name, unix_now()],
+        )
+        .map_err(Error::store("failed to record profile"))?;
+        for (keg, version) in kegs {
+            tx.execute(
+                "INSERT INTO profile_kegs (profile, name, version) VALUES (?1, ?2, ?3)",
+                params![name, keg, version],
+            )
+            .map_err(Error::store("failed to record profile keg"))?;
+        }
+        tx.commit()
+            .map_err(Error::store("failed to commit transaction"))
+    }
+
+    /// Every profile, sorted by name.
+    pub fn list_profiles(&self) -> Result<Vec<ProfileRecord>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT p.name, p.created_at, k.name, k.version
+                 FROM profiles p LEFT JOIN profile_kegs k ON k.profile = p.name
+                 ORDER BY p.name, k.name",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })
+            .map_err(Error::store("failed to query profiles"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        let mut profiles: Vec<ProfileRecord> = Vec::new();
+        for (name, created_at, keg, version) in rows {
+            if profiles.last().is_none_or(|p| p.name != name) {
+                profiles.push(ProfileRecord {
+                    name,
+                    created_at,
+                    kegs: Vec::new(),
+                });
+            }
+            if let (Some(keg), Some(version), Some(profile)) = (keg, version, profiles.last_mut()) {
+                profile.kegs.push((keg, version));
+            }
+        }
+        Ok(profiles)
+    }
+
+    /// Directories kept mirroring Homebrew's Cellar and opt layout, sorted.
+    pub fn list_compat_roots(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path FROM compat_roots ORDER BY path")
+            .map_err(Error::store("failed to prepare statement"))?;
+        let roots = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(Error::store("failed to query compat roots"))?
+            .map(|row| row.map(PathBuf::from))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+        Ok(roots)
+    }
+
+    /// Keep `path` mirroring Homebrew's layout. Returns false when it
+    /// already did.
+    pub fn add_compat_root(&self, path: &Path) -> Result<bool, Error> {
+        self.ensure_writable()?;
+        let added = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO compat_roots (path) VALUES (?1)",
+                params![path.to_string_lossy()],
+            )
+            .map_err(Error::store("failed to record compat root"))?;
+        Ok(added > 0)
+    }
+
+    /// Stop keeping `path` up to date. Returns false when it was not.
+    pub fn remove_compat_root(&self, path: &Path) -> Result<bool, Error> {
+        self.ensure_writable()?;
+        let removed = self
+            .conn
+            .execute(
+                "DELETE FROM compat_roots WHERE path = ?1",
+                params![path.to_string_lossy()],
+            )
+            .map_err(Error::store("failed to remove compat root"))?;
+        Ok(removed > 0)
+    }
+
+    /// Drop keg `name` `version` from every profile holding it. Returns the
+    /// profiles it was dropped from.
+    pub fn forget_profile_keg(&self, name: &str, version: &str) -> Result<Vec<String>, Error> {
+        self.ensure_writable()?;
+        let mut stmt = self
+            .conn
+            .prepare(
+                "DELETE FROM profile_kegs WHERE name = ?1 AND version = ?2
+                 RETURNING profile",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+        let mut profiles = stmt
+            .query_map(params![name, version], |row| row.get(0))
+            .map_err(Error::store("failed to remove profile keg"))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+        profiles.sort();
+        Ok(profiles)
+    }
+
+    pub fn service_settings(&self, name: &str) -> Result<Option<ServiceSettings>, Error> {
+        Ok(self
+            .query_service_settings("WHERE name = ?1", params![name])?
+            .into_iter()
+            .next())
+    }
+
+    pub fn list_service_settings(&self) -> Result<Vec<ServiceSettings>, Error> {
+        self.query_service_settings("", params![])
+    }
+
+    fn query_service_settings(
+        &self,
+        filter: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<ServiceSettings>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT name, interval_secs, cron, environment, args, working_dir,
+                        restart, health_port, health_command
+                 FROM services {filter} ORDER BY name"
+            ))
+            .map_err(Error::store("failed to prepare statement"))?;
+
+        let settings = stmt
+            .query_map(params, |row| {
+                let interval: Option<i64> = row.get(1)?;
+                let cron: Option<String> = row.get(2)?;
+                let schedule = match (interval, cron) {
+                    (Some(secs), _) => Some(RunType::Interval(secs as u64)),
+                    (None, Some(cron)) => CronSchedule::parse(&cron).ok().map(RunType::Cron),
+                    (None, None) => None,
+                };
+                let environment: Option<String> = row.get(3)?;
+                let args: Option<String> = row.get(4)?;
+                let restart: Option<String> = row.get(6)?;
+                let health_port: Option<u16> = row.get(7)?;
+                let health_command: Option<String> = row.get(8)?;
+                let overrides = ServiceOverrides {
+                    environment: environment
+                        .and_then(|json| serde_json::from_str(&json).ok())
+                        .unwrap_or_default(),
+                    args: args
+                        .and_then(|json| serde_json::from_str(&json).ok())
+                        .unwrap_or_default(),
+                    working_dir: row.get(5)?,
+                    restart: restart.and_then(|r| RestartPolicy::parse(&r).ok()),
+                    health_check: health_port
+                        .map(HealthCheck::Tcp)
+                        .or(health_command.map(HealthCheck::Command)),
+                };
+                Ok(ServiceSettings {
+                    name: row.get(0)?,
+                    schedule,
+                    overrides,
+                })
+            })
+            .map_err(Error::store("failed to query service settings"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        Ok(settings)
+    }
+
+    /// Remember that the service of `name` has been started, so it is
+    /// listed even without settings of its own.
+    pub fn record_service(&self, name: &str) -> Result<(), Error> {
+        self.ensure_writable()?;
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO services (name) VALUES (?1)",
+                params![name],
+            )
+            .map_err(Error::store("failed to record service"))?;
+        Ok(())
+    }
+
+    /// Run the service of `name` on `schedule` instead of as the formula
+    /// declares. `None` or [`RunType::Immediate`] clears the override.
+    pub fn set_service_schedule(
+        &self,
+        name: &str,
+        schedule: Option<&RunType>,
+    ) -> Result<(), Error> {
+        self.ensure_writable()?;
+        let (interval, cron) = match schedule {
+            Some(RunType::Interval(secs)) => (Some(*secs as i64), None),
+            Some(RunType::Cron(cron)) => (None, Some(cron.to_string())),
+            Some(RunType::Immediate) | None => (None, None),
+        };
+        self.conn
+            .execute(
+                "INSERT INTO services (name, interval_secs, cron) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(name) DO UPDATE SET interval_secs = ?2, cron = ?3",
+                params![name, interval, cron],
+            )
+            .map_err(Error::store("failed to record service schedule"))?;
+        Ok(())
+    }
+
+    /// Replace the overrides of the service of `name`.
+    pub fn set_service_overrides(
+        &self,
+        name: &str,
+        overrides: &ServiceOverrides,
+    ) -> Result<(), Error> {
+        self.ensure_writable()?;
+        let environment = (!overrides.environment.is_empty())
+            .then(|| serde_json::to_string(&overrides.environment))
+            .transpose()
+            .map_err(Error::store("failed to encode service environment"))?;
+        let args = (!overrides.args.is_empty())
+            .then(|| serde_json::to_string(&overrides.args))
+            .transpose()
+            .map_err(Error::store("failed to encode service arguments"))?;
+        let (health_port, health_command) = match &overrides.health_check {
+            Some(HealthCheck::Tcp(port)) => (Some(*port), None),
+            Some(HealthCheck::Command(command)) => (None, Some(command.as_str())),
+            None => (None, None),
+        };
+        self.conn
+            .execute(
+                "INSERT INTO services
+                    (name, environment, args, working_dir, restart, health_port, health_command)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT(name) DO UPDATE SET
+                    environment = ?2, args = ?3, working_dir = ?4,
+                    restart = ?5, health_port = ?6, health_command = ?7",
+                params![
+                    name,
+                    environment,
+                    args,
+                    overrides.working_dir,
+                    overrides.restart.map(RestartPolicy::as_str),
+                    health_port,
+                    health_command
+                ],
+            )
+            .map_err(Error::store("failed to record service overrides"))?;
+        Ok(())
+    }
+
+    /// The stored JSON of `name`, which may be an alias.
+    pub fn cached_formula(&self, name: &str) -> Result<Option<CachedFormula>, Error> {
+        self.conn
+            .query_row(
+                "SELECT json, fetched_at FROM formulas
+                 WHERE name = ?1
+                    OR name = (SELECT name FROM formula_aliases WHERE alias = ?1)
+                 ORDER BY name = ?1 DESC LIMIT 1",
+                params![name],
+                |row| {
+                    Ok(CachedFormula {
+                        json: row.get(0)?,
+                        fetched_at: row.get(1)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(Error::store("failed to query formula metadata"))
+    }
+
+    /// The stored JSON of every formula, sorted by name.
+    pub fn stored_formulas(&self) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT json FROM formulas ORDER BY name")
+            .map_err(Error::store("failed to prepare statement"))?;
+        let formulas = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(Error::store("failed to query formula metadata"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+        Ok(formulas)
+    }
+
+    /// Store one freshly fetched formula.
+    pub fn store_formula(&self, record: &FormulaRecord) -> Result<(), Error> {
+        self.ensure_writable()?;
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(Error::store("failed to start transaction"))?;
+        upsert_formula(&tx, record, unix_now())?;
+        tx.commit()
+            .map_err(Error::store("failed to commit transaction"))
+    }
+
+    /// Replace all stored formulas with a full index, rewriting only those
+    /// whose JSON changed and dropping those no longer in it. Returns the
+    /// number added or changed.
+    pub fn replace_formulas(&self, records: &[FormulaRecord]) -> Result<usize, Error> {
+        self.ensure_writable()?;
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(Error::store("failed to start transaction"))?;
+        tx.execute("DELETE FROM formula_aliases", [])
+            .map_err(Error::store("failed to clear formula aliases"))?;
+        // Every formula in the index gets a real timestamp again below;
+        // whatever is left marked was dropped upstream.
+        tx.execute("UPDATE formulas SET fetched_at = -1", [])
+            .map_err(Error::store("failed to mark stored formulas"))?;
+        let now = unix_now();
+        let mut changed = 0;
+        for record in records {
+            if upsert_formula(&tx, record, now)? {
+                changed += 1;
+            }
+        }
+        tx.execute("DELETE FROM formulas WHERE fetched_at = -1", [])
+            .map_err(Error::store("failed to remove dropped formulas"))?;
+        tx.commit()
+            .map_err(Error::store("failed to commit transaction"))?;
+        Ok(changed)
+    }
+
+    /// Record the vulnerability records just fetched for `name`.
+    pub fn store_advisories(&self, name: &str, json: &str) -> Result<(), Error> {
+        self.ensure_writable()?;
+        self.conn
+            .execute(
+                "INSERT INTO advisories (name, json, fetched_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(name) DO UPDATE SET json = ?2, fetched_at = ?3",
+                params![name, json, unix_now()],
+            )
+            .map_err(Error::store("failed to record vulnerability records"))?;
+        Ok(())
+    }
+
+    /// The vulnerability records last fetched for `name`, if any.
+    pub fn cached_advisories(&self, name: &str) -> Result<Option<CachedAdvisories>, Error> {
+        self.conn
+            .query_row(
+                "SELECT json, fetched_at FROM advisories WHERE name = ?1",
+                params![name],
+                |row| {
+                    Ok(CachedAdvisories {
+                        json: row.get(0)?,
+                        fetched_at: row.get(1)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(Error::store("failed to query vulnerability records"))
+    }
+
+    pub fn replace_store_refs(&self, store_refs: &[StoreRef]) -> Result<(), Error> {
+        self.ensure_writable()?;
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(Error::store("failed to start transaction"))?;
+
+        tx.execute("DELETE FROM store_refs", [])
+            .map_err(Error::store("failed to clear store refs"))?;
+
+        {
+            let mut stmt = tx
+                .prepare("INSERT INTO store_refs (store_key, refcount) VALUES (?1, ?2)")
+                .map_err(Error::store("failed to prepare statement"))?;
+
+            for store_ref in store_refs {
+                stmt.execute(params![store_ref.store_key, store_ref.refcount])
+                    .map_err(Error::store("failed to insert store ref"))?;
+            }
+        }
+
+        tx.commit()
+            .map_err(Error::store("failed to commit transaction"))
+    }
+
+    /// Replace the executables index with `entries` of a formula and the
+    /// executables it installs. Returns the number of executables stored.
+    pub fn replace_executables(&self, entries: &[(String, Vec<String>)]) -> Result<usize, Error> {
+        self.ensure_writable()?;
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(Error::store("failed to start transaction"))?;
+        tx.execute("DELETE FROM executables", [])
+            .map_err(Error::store("failed to clear executables index"))?;
+        let mut stored = 0;
+        {
+            let mut stmt = tx
+                .prepare("INSERT OR IGNORE INTO executables (executable, formula) VALUES (?1, ?2)")
+                .map_err(Error::store("failed to prepare statement"))?;
+            for (formula, executables) in entries {
+                for executable in executables {
+                    stored += stmt
+                        .execute(params![executable, formula])
+                        .map_err(Error::store("failed to store executable"))?;
+                }
+            }
+        }
+        tx.commit()
+            .map_err(Error::store("failed to commit transaction"))?;
+        Ok(stored)
+    }
+
+    /// Formulas in the executables index that install `executable`.
+    pub fn formulas_providing(&self, executable: &str) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT formula FROM executables WHERE executable = ?1 ORDER BY formula")
+            .map_err(Error::store("failed to prepare statement"))?;
+
+        let formulas = stmt
+            .query_map(params![executable], |row| row.get(0))
+            .map_err(Error::store("failed to query executables index"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        Ok(formulas)
+    }
+
+    /// Count another corrupt download from `url`, returning how many there
+    /// have been.
+    pub fn record_corrupt_download(&self, url: &str, sha256: &str) -> Result<u32, Error> {
+        self.ensure_writable()?;
+        self.conn
+            .query_row(
+                "INSERT INTO corrupt_downloads (url, sha256, failures, last_failed_at)
+                 VALUES (?1, ?2, 1, ?3)
+                 ON CONFLICT(url) DO UPDATE SET
+                    sha256 = ?2, failures = failures + 1, last_failed_at = ?3
+                 RETURNING failures",
+                params![url, sha256, unix_now()],
+                |row| row.get(0),
+            )
+            .map_err(Error::store("failed to record corrupt download"))
+    }
+
+    pub fn list_corrupt_downloads(&self) -> Result<Vec<CorruptDownload>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT url, sha256, failures, last_failed_at FROM corrupt_downloads
+                 ORDER BY url",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+
+        let downloads = stmt
+            .query_map([], |row| {
+                Ok(CorruptDownload {
+                    url: row.get(0)?,
+                    sha256: row.get(1)?,
+                    failures: row.get(2)?,
+                    last_failed_at: row.get(3)?,
+                })
+            })
+            .map_err(Error::store("failed to query corrupt downloads"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        Ok(downloads)
+    }
+
+    /// Forget every corrupt download, returning how many were recorded.
+    pub fn clear_corrupt_downloads(&self) -> Result<usize, Error> {
+        self.ensure_writable()?;
+        self.conn
+            .execute("DELETE FROM corrupt_downloads", [])
+            .map_err(Error::store("failed to clear corrupt downloads"))
+    }
+
+    pub fn record_install_stat(&self, stat: &InstallStat) -> Result<(), Error> {
+        self.ensure_writable()?;
+        self.conn
+            .execute(
+                "INSERT INTO install_stats
+                    (name, method, recorded_at, duration_ms, cached,
+                     download_bytes, download_ms, relocation_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    stat.name,
+                    stat.method,
+                    unix_now(),
+                    stat.duration_ms as i64,
+                    stat.cached,
+                    stat.download_bytes.map(|b| b as i64),
+                    stat.download_ms.map(|ms| ms as i64),
+                    stat.relocation_ms.map(|ms| ms as i64),
+                ],
+            )
+            .map_err(Error::store("failed to record install stats"))?;
+        Ok(())
+    }
+
+    /// Aggregate the recorded installs, listing up to `slowest` of the
+    /// longest ones.
+    pub fn install_stats(&self, slowest: usize) -> Result<StatsSummary, Error> {
+        let mut summary = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*),
+                        COALESCE(SUM(method = 'source'), 0),
+                        COALESCE(SUM(method = 'bottle' AND cached), 0),
+                        COALESCE(SUM(duration_ms), 0),
+                        COALESCE(SUM(download_bytes), 0),
+                        COALESCE(SUM(download_ms), 0),
+                        COUNT(relocation_ms),
+                        COALESCE(SUM(relocation_ms), 0),
+                        MIN(recorded_at)
+                 FROM install_stats",
+                [],
+                |row| {
+                    Ok(StatsSummary {
+                        installs: row.get::<_, i64>(0)? as u64,
+                        source_builds: row.get::<_, i64>(1)? as u64,
+                        cache_hits: row.get::<_, i64>(2)? as u64,
+                        total_duration_ms: row.get::<_, i64>(3)? as u64,
+                        downloaded_bytes: row.get::<_, i64>(4)? as u64,
+                        download_ms: row.get::<_, i64>(5)? as u64,
+                        relocations: row.get::<_, i64>(6)? as u64,
+                        relocation_ms: row.get::<_, i64>(7)? as u64,
+                        since: row.get(8)?,
+                        slowest: Vec::new(),
+                    })
+                },
+            )
+            .map_err(Error::store("failed to query install stats"))?;
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, duration_ms FROM install_stats ORDER BY duration_ms DESC LIMIT ?1",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+        summary.slowest = stmt
+            .query_map(params![slowest as i64], |row| {
+                Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+            })
+            .map_err(Error::store("failed to query install stats"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        Ok(summary)
+    }
+
+    /// Append an operation to the history, returning its id.
+    pub fn record_operation(
+        &mut self,
+        kind: &str,
+        error: Option<&str>,
+        detail: Option<&str>,
+        changes: &[PackageChange],
+    ) -> Result<i64, Error> {
+        self.ensure_writable()?;
+        let tx = write_transaction(&mut self.conn, "failed to start transaction")?;
+        tx.execute(
+            "INSERT INTO operations (kind, recorded_at, error, detail) VALUES (?1, ?2, ?3, ?4)",
+            params![kind, unix_now(), error, detail],
+        )
+        .map_err(Error::store("failed to record operation"))?;
+        let id = tx.last_insert_rowid();
+        for change in changes {
+            tx.execute(
+                "INSERT INTO operation_changes (operation_id, name, from_version, to_version)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![id, change.name, change.from, change.to],
+            )
+            .map_err(Error::store("failed to record operation"))?;
+        }
+        tx.commit()
+            .map_err(Error::store("failed to commit transaction"))?;
+        Ok(id)
+    }
+
+    /// The most recent `limit` operations, newest first.
+    pub fn operations(&self, limit: usize) -> Result<Vec<OperationRecord>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, kind, recorded_at, error, detail FROM operations
+                 ORDER BY id DESC LIMIT ?1",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+        let mut operations = stmt
+            .query_map(params![limit as i64], Self::operation_from_row)
+            .map_err(Error::store("failed to query operations"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+        for operation in &mut operations {
+            operation.changes = self.operation_changes(operation.id)?;
+        }
+        Ok(operations)
+    }
+
+    pub fn operation(&self, id: i64) -> Result<Option<OperationRecord>, Error> {
+        let operation = self
+            .conn
+            .query_row(
+                "SELECT id, kind, recorded_at, error, detail FROM operations WHERE id = ?1",
+                params![id],
+                Self::operation_from_row,
+            )
+            .optional()
+            .map_err(Error::store("failed to query operation"))?;
+        match operation {
+            Some(mut operation) => {
+                operation.changes = self.operation_changes(id)?;
+                Ok(Some(operation))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn operation_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<OperationRecord> {
+        Ok(OperationRecord {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            recorded_at: row.get(2)?,
+            error: row.get(3)?,
+            detail: row.get(4)?,
+            changes: Vec::new(),
+        })
+    }
+
+    fn operation_changes(&self, id: i64) -> Result<Vec<PackageChange>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, from_version, to_version FROM operation_changes
+                 WHERE operation_id = ?1 ORDER BY name",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+        stmt.query_map(params![id], |row| {
+            Ok(PackageChange {
+                name: row.get(0)?,
+                from: row.get(1)?,
+                to: row.get(2)?,
+            })
+        })
+        .map_err(Error::store("failed to query operation changes"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::store("failed to collect results"))
+    }
+
+    /// Forget every recorded install, returning how many there were.
+    pub fn clear_install_stats(&self) -> Result<usize, Error> {
+        self.ensure_writable()?;
+        self.conn
+            .execute("DELETE FROM install_stats", [])
+            .map_err(Error::store("failed to clear install stats"))
+    }
+
+    pub fn count_stale_keg_file_records(&self) -> Result<usize, Error> {
+        let count: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM keg_files
+                 WHERE NOT EXISTS (
+                     SELECT 1
+                     FROM installed_kegs
+                     WHERE installed_kegs.name = keg_files.name
+                       AND installed_kegs.version = keg_files.version
+                 )",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(Error::store("failed to count stale keg file records"))?;
+        Ok(count as usize)
+    }
+
+    pub fn prune_stale_keg_file_records(&self) -> Result<usize, Error> {
+        self.ensure_writable()?;
+        self.conn
+            .execute(
+                "DELETE FROM keg_files
+                 WHERE NOT EXISTS (
+                     SELECT 1
+                     FROM installed_kegs
+                     WHERE installed_kegs.name = keg_files.name
+                       AND installed_kegs.version = keg_files.version
+                 )",
+                [],
+            )
+            .map_err(Error::store("failed to prune stale keg file records"))
+    }
+}
+
+/// Insert or refresh `record`, returning whether its JSON changed.
+fn upsert_formula(conn: &Connection, record: &FormulaRecord, now: i64) -> Result<bool, Error> {
+    let dependencies = serde_json::to_string(&record.dependencies)
+        .map_err(Error::store("failed to encode formula dependencies"))?;
+    let changed = conn
+        .execute(
+            "INSERT INTO formulas (name, version, dependencies, json, fetched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(name) DO UPDATE SET
+                version = excluded.version,
+                dependencies = excluded.dependencies,
+                json = excluded.json,
+                fetched_at = excluded.fetched_at
+             WHERE formulas.json != excluded.json",
+            params![record.name, record.version, dependencies, record.json, now],
+        )
+        .map_err(Error::store("failed to store formula metadata"))?
+        > 0;
+    if !changed {
+        conn.execute(
+            "UPDATE formulas SET fetched_at = ?2 WHERE name = ?1",
+            params![record.name, now],
+        )
+        .map_err(Error::store("failed to store formula metadata"))?;
+    }
+    for alias in &record.aliases {
+        conn.execute(
+            "INSERT OR REPLACE INTO formula_aliases (alias, name) VALUES (?1, ?2)",
+            params![alias, record.name],
+        )
+        .map_err(Error::store("failed to store formula alias"))?;
+    }
+    Ok(changed)
+}
+
+/// Drops a reference to store entry `?1`, noting the time `?2` when it
+/// was the last one so `gc` can keep recently released entries around.
+const RELEASE_STORE_REF: &str = "UPDATE store_refs SET refcount = refcount - 1,
+         released_at = CASE WHEN refcount <= 1 THEN ?2 ELSE released_at END
+     WHERE store_key = ?1";
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+pub struct InstallTransaction<'a> {
+    tx: Transaction<'a>,
+}
+
+impl<'a> InstallTransaction<'a> {
+    pub fn record_install(&self, name: &str, version: &str, store_key: &str) -> Result<(), Error> {
+        let now = unix_now();
+
+        let previous_store_key: Option<String> = self
+            .tx
+            .query_row(
+                "SELECT store_key FROM installed_kegs WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(Error::store("failed to query previous store key"))?;
+
+        self.tx
+            .execute(
+                "INSERT INTO installed_kegs (name, version, store_key, installed_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(name) DO UPDATE SET
                      version = excluded.version,
                      store_key = excluded.store_key,
-                     installed_at = excluded.installed_at",
+                     installed_at = excluded.installed_at,
+                     shared_bytes = NULL",
                 params![name, version, store_key, now],
             )
             .map_err(Error::store("failed to record install"))?;
@@ -351,17 +1554,16 @@ impl<'a> InstallTransaction<'a> {
             other => {
                 if let Some(previous) = other {
                     self.tx
-                        .execute(
-                            "UPDATE store_refs SET refcount = refcount - 1 WHERE store_key = ?1",
-                            params![previous],
-                        )
+                        .execute(RELEASE_STORE_REF, params![previous, now])
                         .map_err(Error::store("failed to decrement previous store ref"))?;
                 }
 
                 self.tx
                     .execute(
                         "INSERT INTO store_refs (store_key, refcount) VALUES (?1, 1)
-                         ON CONFLICT(store_key) DO UPDATE SET refcount = refcount + 1",
+                         ON CONFLICT(store_key) DO UPDATE SET
+                             refcount = refcount + 1,
+                             released_at = NULL",
                         params![store_key],
                     )
                     .map_err(Error::store("failed to increment store ref"))?;
@@ -371,63 +1573,259 @@ impl<'a> InstallTransaction<'a> {
         Ok(())
     }
 
-    pub fn record_linked_file(
-        &self,
-        name: &str,
-        version: &str,
-        linked_path: &str,
-        target_path: &str,
-    ) -> Result<(), Error> {
+    /// Record the keg size and direct runtime dependencies of `name`,
+    /// replacing anything recorded by a previous install.
+    pub fn record_keg_metadata(
+        &self,
+        name: &str,
+        size_bytes: Option<u64>,
+        dependencies: &[String],
+    ) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "UPDATE installed_kegs SET size_bytes = ?2 WHERE name = ?1",
+                params![name, size_bytes.map(|s| s as i64)],
+            )
+            .map_err(Error::store("failed to record keg size"))?;
+
+        self.tx
+            .execute(
+                "DELETE FROM keg_dependencies WHERE name = ?1",
+                params![name],
+            )
+            .map_err(Error::store("failed to clear keg dependencies"))?;
+
+        for dependency in dependencies {
+            self.tx
+                .execute(
+                    "INSERT OR IGNORE INTO keg_dependencies (name, dependency) VALUES (?1, ?2)",
+                    params![name, dependency],
+                )
+                .map_err(Error::store("failed to record keg dependency"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Record the foreign CPU architecture `name` was installed for, or clear
+    /// it for a native install.
+    pub fn record_keg_arch(&self, name: &str, arch: Option<&str>) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "UPDATE installed_kegs SET arch = ?2 WHERE name = ?1",
+                params![name, arch],
+            )
+            .map_err(Error::store("failed to record keg arch"))?;
+        Ok(())
+    }
+
+    /// Record how many bytes of the keg of `name` its store entry shares.
+    pub fn record_keg_shared_size(
+        &self,
+        name: &str,
+        shared_bytes: Option<u64>,
+    ) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "UPDATE installed_kegs SET shared_bytes = ?2 WHERE name = ?1",
+                params![name, shared_bytes.map(|s| s as i64)],
+            )
+            .map_err(Error::store("failed to record keg shared size"))?;
+        Ok(())
+    }
+
+    pub fn record_linked_file(
+        &self,
+        name: &str,
+        version: &str,
+        linked_path: &str,
+        target_path: &str,
+        mode: LinkMode,
+    ) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "INSERT OR REPLACE INTO keg_files (name, version, linked_path, target_path, mode)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![name, version, linked_path, target_path, mode.as_str()],
+            )
+            .map_err(Error::store("failed to record linked file"))?;
+
+        Ok(())
+    }
+
+    /// Replace the file manifest recorded for `name`.
+    pub fn record_keg_manifest(
+        &self,
+        name: &str,
+        version: &str,
+        entries: &[ManifestEntry],
+    ) -> Result<(), Error> {
+        self.clear_keg_manifest(name)?;
+        let mut stmt = self
+            .tx
+            .prepare(
+                "INSERT INTO keg_manifests (name, version, path, size_bytes, sha256)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+        for entry in entries {
+            stmt.execute(params![
+                name,
+                version,
+                entry.path,
+                entry.size_bytes as i64,
+                entry.sha256
+            ])
+            .map_err(Error::store("failed to record keg manifest"))?;
+        }
+        Ok(())
+    }
+
+    pub fn record_uninstall(&self, name: &str) -> Result<Option<String>, Error> {
+        // Get the store_key before removing
+        let store_key: Option<String> = self
+            .tx
+            .query_row(
+                "SELECT store_key FROM installed_kegs WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .ok();
+
+        // Remove installed keg record
+        self.tx
+            .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to remove install record"))?;
+
+        self.tx
+            .execute("DELETE FROM keg_files WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to remove keg files records"))?;
+        self.clear_keg_manifest(name)?;
+
+        self.tx
+            .execute(
+                "DELETE FROM keg_dependencies WHERE name = ?1",
+                params![name],
+            )
+            .map_err(Error::store("failed to remove keg dependency records"))?;
+
+        self.tx
+            .execute("DELETE FROM package_notes WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to remove package note"))?;
+        self.tx
+            .execute("DELETE FROM package_tags WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to remove package tags"))?;
+
+        // Decrement store ref if we had one
+        if let Some(ref key) = store_key {
+            self.tx
+                .execute(RELEASE_STORE_REF, params![key, unix_now()])
+                .map_err(Error::store("failed to decrement store ref"))?;
+        }
+
+        Ok(store_key)
+    }
+
+    pub fn delete_installed_record(&self, name: &str) -> Result<(), Error> {
+        self.tx
+            .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to remove install record"))?;
+
+        self.tx
+            .execute(
+                "DELETE FROM keg_dependencies WHERE name = ?1",
+                params![name],
+            )
+            .map_err(Error::store("failed to remove keg dependency records"))?;
+
+        self.clear_keg_manifest(name)?;
+        self.clear_keg_file_records(name)
+    }
+
+    /// Move the installed keg of `name` into its generations, keeping its
+    /// store reference. Link records are dropped since the caller unlinks
+    /// it; dependency records stay for the keg that replaces it.
+    pub fn retire_to_generation(&self, name: &str) -> Result<(), Error> {
+        let retired = self
+            .tx
+            .execute(
+                "INSERT OR REPLACE INTO keg_generations
+                     (name, version, store_key, arch, installed_at, superseded_at)
+                 SELECT name, version, store_key, arch, installed_at, ?2
+                 FROM installed_kegs WHERE name = ?1",
+                params![name, unix_now()],
+            )
+            .map_err(Error::store("failed to record keg generation"))?;
+        if retired == 0 {
+            return Err(Error::NotInstalled {
+                name: name.to_string(),
+            });
+        }
+
+        self.tx
+            .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to remove install record"))?;
+        self.clear_keg_manifest(name)?;
+        self.clear_keg_file_records(name)
+    }
+
+    /// Make generation `version` of `name` the installed keg again. The
+    /// currently installed keg must have been retired first.
+    pub fn restore_generation(&self, name: &str, version: &str) -> Result<(), Error> {
         self.tx
             .execute(
-                "INSERT OR REPLACE INTO keg_files (name, version, linked_path, target_path)
-                 VALUES (?1, ?2, ?3, ?4)",
-                params![name, version, linked_path, target_path],
+                "INSERT INTO installed_kegs (name, version, store_key, installed_at, arch)
+                 SELECT name, version, store_key, installed_at, arch
+                 FROM keg_generations WHERE name = ?1 AND version = ?2",
+                params![name, version],
             )
-            .map_err(Error::store("failed to record linked file"))?;
-
+            .map_err(Error::store("failed to restore keg generation"))?;
+        self.tx
+            .execute(
+                "DELETE FROM keg_generations WHERE name = ?1 AND version = ?2",
+                params![name, version],
+            )
+            .map_err(Error::store("failed to remove keg generation"))?;
         Ok(())
     }
 
-    pub fn record_uninstall(&self, name: &str) -> Result<Option<String>, Error> {
-        // Get the store_key before removing
+    /// Forget a generation and release its store reference.
+    pub fn delete_generation(&self, name: &str, version: &str) -> Result<(), Error> {
         let store_key: Option<String> = self
             .tx
             .query_row(
-                "SELECT store_key FROM installed_kegs WHERE name = ?1",
-                params![name],
+                "SELECT store_key FROM keg_generations WHERE name = ?1 AND version = ?2",
+                params![name, version],
                 |row| row.get(0),
             )
-            .ok();
-
-        // Remove installed keg record
-        self.tx
-            .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
-            .map_err(Error::store("failed to remove install record"))?;
+            .optional()
+            .map_err(Error::store("failed to query keg generation"))?;
 
         self.tx
-            .execute("DELETE FROM keg_files WHERE name = ?1", params![name])
-            .map_err(Error::store("failed to remove keg files records"))?;
+            .execute(
+                "DELETE FROM keg_generations WHERE name = ?1 AND version = ?2",
+                params![name, version],
+            )
+            .map_err(Error::store("failed to remove keg generation"))?;
 
-        // Decrement store ref if we had one
-        if let Some(ref key) = store_key {
+        if let Some(key) = store_key {
             self.tx
-                .execute(
-                    "UPDATE store_refs SET refcount = refcount - 1 WHERE store_key = ?1",
-                    params![key],
-                )
+                .execute(RELEASE_STORE_REF, params![key, unix_now()])
                 .map_err(Error::store("failed to decrement store ref"))?;
         }
-
-        Ok(store_key)
+        Ok(())
     }
 
-    pub fn delete_installed_record(&self, name: &str) -> Result<(), Error> {
+    /// Drop the record of whichever keg owned the link at `linked_path`.
+    pub fn forget_linked_file(&self, linked_path: &Path) -> Result<(), Error> {
         self.tx
-            .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
-            .map_err(Error::store("failed to remove install record"))?;
+            .execute(
+                "DELETE FROM keg_files WHERE linked_path = ?1",
+                params![linked_path.to_string_lossy()],
+            )
+            .map_err(Error::store("failed to remove linked file record"))?;
 
-        self.clear_keg_file_records(name)
+        Ok(())
     }
 
     pub fn clear_keg_file_records(&self, name: &str) -> Result<(), Error> {
@@ -438,6 +1836,14 @@ impl<'a> InstallTransaction<'a> {
         Ok(())
     }
 
+    fn clear_keg_manifest(&self, name: &str) -> Result<(), Error> {
+        self.tx
+            .execute("DELETE FROM keg_manifests WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to clear keg manifest"))?;
+
+        Ok(())
+    }
+
     pub fn commit(self) -> Result<(), Error> {
         self.tx
             .commit()
@@ -451,6 +1857,27 @@ impl<'a> InstallTransaction<'a> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn a_held_write_lock_is_reported_as_locked_not_corrupt() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("zb.sqlite3");
+        let mut holder = Database::open(&path).unwrap();
+        let _held = write_transaction(&mut holder.conn, "hold").unwrap();
+
+        // Without a busy timeout the second writer gives up at once.
+        let mut waiter = Connection::open(&path).unwrap();
+        waiter.busy_timeout(Duration::ZERO).unwrap();
+        let err = write_transaction(&mut waiter, "failed to start transaction")
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::DatabaseLocked {
+                context: "failed to start transaction".to_string()
+            }
+        );
+    }
+
     #[test]
     fn install_and_list() {
         let mut db = Database::in_memory().unwrap();
@@ -546,6 +1973,7 @@ mod tests {
                 "1.0.0",
                 "/opt/homebrew/bin/foo",
                 "/opt/zerobrew/cellar/foo/1.0.0/bin/foo",
+                LinkMode::Symlink,
             )
             .unwrap();
             tx.commit().unwrap();
@@ -561,6 +1989,40 @@ mod tests {
         assert!(db.get_installed("foo").is_none());
     }
 
+    #[test]
+    fn keg_manifest_is_replaced_and_cleared_on_uninstall() {
+        let mut db = Database::in_memory().unwrap();
+        let entry = |path: &str| ManifestEntry {
+            path: path.to_string(),
+            size_bytes: 4,
+            sha256: Some("abc".to_string()),
+        };
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc123").unwrap();
+            tx.record_keg_manifest("foo", "1.0.0", &[entry("bin/foo"), entry("bin/old")])
+                .unwrap();
+            tx.record_keg_manifest("foo", "1.0.0", &[entry("lib/libfoo.a"), entry("bin/foo")])
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        let paths: Vec<_> = db
+            .keg_manifest("foo")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(paths, ["bin/foo", "lib/libfoo.a"]);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("foo").unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(db.keg_manifest("foo").unwrap().is_empty());
+    }
+
     #[test]
     fn reinstall_with_same_store_key_does_not_leak_refcount() {
         let mut db = Database::in_memory().unwrap();
@@ -608,6 +2070,34 @@ mod tests {
         assert_eq!(installed.store_key, "newkey");
     }
 
+    #[test]
+    fn store_refs_record_when_they_were_released() {
+        let mut db = Database::in_memory().unwrap();
+        let released = |db: &Database| -> Vec<(String, bool)> {
+            db.get_unreferenced_store_refs()
+                .unwrap()
+                .into_iter()
+                .map(|r| (r.store_key, r.released_at.is_some()))
+                .collect()
+        };
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "key").unwrap();
+            tx.record_install("bar", "1.0.0", "key").unwrap();
+            tx.record_uninstall("foo").unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(released(&db).is_empty());
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("bar").unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(released(&db), [("key".to_string(), true)]);
+    }
+
     #[test]
     fn delete_store_ref_removes_unreferenced_entry() {
         let mut db = Database::in_memory().unwrap();
@@ -653,26 +2143,36 @@ mod tests {
     }
 
     #[test]
-    fn new_database_starts_at_version_1() {
+    fn new_database_starts_at_current_version() {
         let db = Database::in_memory().expect("failed to create database");
-        let version = Database::get_schema_version(&db.conn).expect("failed to get version");
-        assert_eq!(version, 1);
+        assert_eq!(db.schema_version(), Database::supported_schema_version());
+        assert!(!db.is_newer_than_supported());
     }
 
     #[test]
     fn migration_is_idempotent() {
-        let db = Database::in_memory().expect("failed to create database");
-        Database::migrate(&db.conn).expect("first migration failed");
-        Database::migrate(&db.conn).expect("second migration failed");
-        let version = Database::get_schema_version(&db.conn).expect("failed to get version");
-        assert_eq!(version, 1);
+        let mut db = Database::in_memory().expect("failed to create database");
+        migrations::apply_pending(&mut db.conn).expect("first migration failed");
+        migrations::apply_pending(&mut db.conn).expect("second migration failed");
+        let version = migrations::current_version(&db.conn).expect("failed to get version");
+        assert_eq!(version, Database::supported_schema_version());
     }
 
     #[test]
-    fn rejects_future_schema_version() {
+    fn future_schema_version_is_read_only() {
         let conn = Connection::open_in_memory().expect("failed to open connection");
-        Database::set_schema_version(&conn, 999).expect("failed to set version");
-        let err = Database::migrate(&conn).unwrap_err();
+        migrations::MIGRATIONS
+            .iter()
+            .for_each(|m| conn.execute_batch(m.sql).unwrap());
+        conn.execute_batch("PRAGMA user_version = 999")
+            .expect("failed to set version");
+
+        let mut db = Database::from_connection(conn).expect("failed to open newer database");
+        assert_eq!(db.schema_version(), 999);
+        assert!(db.is_newer_than_supported());
+        assert!(db.list_installed().unwrap().is_empty());
+
+        let err = db.transaction().err().unwrap();
         assert!(matches!(err, Error::StoreCorruption { .. }));
         assert!(err.to_string().contains("newer than supported version"));
     }
@@ -692,16 +2192,504 @@ mod tests {
         )
         .expect("failed to create old schema");
 
-        Database::migrate(&conn).expect("migration failed");
+        let db = Database::from_connection(conn).expect("migration failed");
 
-        let count: i64 = conn
+        let count: i64 = db
+            .conn
             .query_row("SELECT COUNT(*) FROM installed_kegs", [], |row| row.get(0))
             .expect("failed to count rows");
         assert_eq!(count, 1);
 
-        let name: String = conn
+        let name: String = db
+            .conn
             .query_row("SELECT name FROM installed_kegs", [], |row| row.get(0))
             .expect("failed to query data");
         assert_eq!(name, "test");
     }
+
+    #[test]
+    fn v1_database_migrates_with_unknown_sizes() {
+        let conn = Connection::open_in_memory().expect("failed to open connection");
+        conn.execute_batch(migrations::MIGRATIONS[0].sql).unwrap();
+        conn.execute_batch("PRAGMA user_version = 1").unwrap();
+        conn.execute(
+            "INSERT INTO installed_kegs VALUES ('old', '1.0.0', 'key', 1)",
+            [],
+        )
+        .unwrap();
+
+        let db = Database::from_connection(conn).expect("migration failed");
+        let keg = db.get_installed("old").unwrap();
+        assert_eq!(keg.size_bytes, None);
+
+        db.set_keg_size("old", 4096).unwrap();
+        assert_eq!(db.get_installed("old").unwrap().size_bytes, Some(4096));
+    }
+
+    #[test]
+    fn keg_metadata_is_replaced_and_cleared_on_uninstall() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc").unwrap();
+            tx.record_keg_metadata("foo", Some(10), &["bar".into(), "baz".into()])
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.list_dependencies("foo").unwrap(), vec!["bar", "baz"]);
+        assert_eq!(db.get_installed("foo").unwrap().size_bytes, Some(10));
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_keg_metadata("foo", Some(20), &["qux".into()])
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.list_dependencies("foo").unwrap(), vec!["qux"]);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("foo").unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(db.list_dependencies("foo").unwrap().is_empty());
+    }
+
+    #[test]
+    fn keg_arch_is_recorded_and_cleared() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc").unwrap();
+            tx.record_keg_arch("foo", Some("x86_64")).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(
+            db.get_installed("foo").unwrap().arch.as_deref(),
+            Some("x86_64")
+        );
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.1", "def").unwrap();
+            tx.record_keg_arch("foo", None).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.get_installed("foo").unwrap().arch, None);
+    }
+
+    #[test]
+    fn link_modes_are_recorded_per_formula_and_per_link() {
+        let mut db = Database::in_memory().unwrap();
+        assert_eq!(db.link_mode("jq").unwrap(), None);
+        db.set_link_mode("jq", Some(LinkMode::Wrapper)).unwrap();
+        db.set_link_mode("jq", Some(LinkMode::Hardlink)).unwrap();
+        assert_eq!(db.link_mode("jq").unwrap(), Some(LinkMode::Hardlink));
+        db.set_link_mode("jq", None).unwrap();
+        assert_eq!(db.link_mode("jq").unwrap(), None);
+
+        let tx = db.transaction().unwrap();
+        tx.record_linked_file(
+            "jq",
+            "1.7",
+            "/p/bin/jq",
+            "/c/jq/1.7/bin/jq",
+            LinkMode::Wrapper,
+        )
+        .unwrap();
+        tx.commit().unwrap();
+        assert_eq!(db.list_keg_files().unwrap()[0].mode, LinkMode::Wrapper);
+    }
+
+    #[test]
+    fn package_notes_and_tags_are_kept_per_name() {
+        let mut db = Database::in_memory().unwrap();
+        assert_eq!(db.package_note("jq").unwrap(), None);
+        db.set_package_note("jq", Some("needed for project X"))
+            .unwrap();
+        db.set_package_note("jq", Some("needed for CI")).unwrap();
+        assert_eq!(
+            db.package_note("jq").unwrap().as_deref(),
+            Some("needed for CI")
+        );
+        db.set_package_note("jq", None).unwrap();
+        assert_eq!(db.package_note("jq").unwrap(), None);
+
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        db.update_package_tags("jq", &tags(&["work", "ci", "work"]), &[])
+            .unwrap();
+        db.update_package_tags("wget", &tags(&["work"]), &[])
+            .unwrap();
+        assert_eq!(db.package_tags("jq").unwrap(), ["ci", "work"]);
+        assert_eq!(db.packages_tagged("work").unwrap(), ["jq", "wget"]);
+        db.update_package_tags("jq", &[], &tags(&["work"])).unwrap();
+        assert_eq!(db.packages_tagged("work").unwrap(), ["wget"]);
+    }
+
+    #[test]
+    fn profiles_record_their_kegs() {
+        let db = Database::in_memory().unwrap();
+        let keg = |name: &str, version: &str| (name.to_string(), version.to_string());
+        db.create_profile("work", &[keg("jq", "1.7"), keg("git", "2.44")])
+            .unwrap();
+        db.create_profile("empty", &[]).unwrap();
+        assert!(db.create_profile("work", &[]).is_err());
+
+        let profiles = db.list_profiles().unwrap();
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["empty", "work"]);
+        assert!(profiles[0].kegs.is_empty());
+        assert_eq!(profiles[1].kegs, [keg("git", "2.44"), keg("jq", "1.7")]);
+
+        assert_eq!(
+            db.forget_profile_keg("jq", "1.6").unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(db.forget_profile_keg("jq", "1.7").unwrap(), ["work"]);
+        assert_eq!(db.list_profiles().unwrap()[1].kegs, [keg("git", "2.44")]);
+    }
+
+    #[test]
+    fn compat_roots_are_a_set() {
+        let db = Database::in_memory().unwrap();
+        assert!(db.add_compat_root(Path::new("/opt/homebrew")).unwrap());
+        assert!(!db.add_compat_root(Path::new("/opt/homebrew")).unwrap());
+        assert!(
+            db.add_compat_root(Path::new("/home/linuxbrew/.linuxbrew"))
+                .unwrap()
+        );
+        assert_eq!(
+            db.list_compat_roots().unwrap(),
+            [
+                PathBuf::from("/home/linuxbrew/.linuxbrew"),
+                PathBuf::from("/opt/homebrew")
+            ]
+        );
+        assert!(db.remove_compat_root(Path::new("/opt/homebrew")).unwrap());
+        assert!(!db.remove_compat_root(Path::new("/opt/homebrew")).unwrap());
+        assert_eq!(db.list_compat_roots().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn service_schedule_round_trips() {
+        let db = Database::in_memory().unwrap();
+        assert_eq!(db.service_settings("redis").unwrap(), None);
+
+        let nightly = RunType::Cron(CronSchedule::parse("0 3 * * *").unwrap());
+        db.set_service_schedule("redis", Some(&nightly)).unwrap();
+        db.set_service_schedule("backup", Some(&RunType::Interval(3600)))
+            .unwrap();
+        assert_eq!(
+            db.service_settings("redis").unwrap().unwrap().schedule,
+            Some(nightly)
+        );
+
+        db.set_service_schedule("redis", None).unwrap();
+        let all = db.list_service_settings().unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].schedule, Some(RunType::Interval(3600)));
+        assert_eq!(all[1].schedule, None);
+    }
+
+    #[test]
+    fn replacing_formulas_rewrites_only_changes() {
+        let db = Database::in_memory().unwrap();
+        let record = |name: &str, version: &str, aliases: &[&str]| FormulaRecord {
+            name: name.to_string(),
+            version: version.to_string(),
+            dependencies: Vec::new(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            json: format!(r#"{{"name":"{name}","version":"{version}"}}"#),
+        };
+
+        let first = [
+            record("python@3.13", "3.13.1", &["python3"]),
+            record("jq", "1.7", &[]),
+        ];
+        assert_eq!(db.replace_formulas(&first).unwrap(), 2);
+        assert_eq!(
+            db.cached_formula("python3").unwrap().unwrap().json,
+            first[0].json
+        );
+
+        let second = [
+            record("python@3.13", "3.13.2", &["python3"]),
+            record("jq", "1.7", &[]),
+        ];
+        assert_eq!(db.replace_formulas(&second).unwrap(), 1);
+        assert!(
+            db.cached_formula("python3")
+                .unwrap()
+                .unwrap()
+                .json
+                .contains("3.13.2")
+        );
+
+        assert_eq!(db.replace_formulas(&second[1..]).unwrap(), 0);
+        assert_eq!(db.cached_formula("python@3.13").unwrap(), None);
+        assert_eq!(db.cached_formula("python3").unwrap(), None);
+        assert!(db.cached_formula("jq").unwrap().is_some());
+    }
+
+    #[test]
+    fn service_overrides_round_trip_alongside_schedule() {
+        let db = Database::in_memory().unwrap();
+        let overrides = ServiceOverrides {
+            environment: BTreeMap::from([("PORT".to_string(), "6380".to_string())]),
+            args: vec!["--port=6380".to_string()],
+            working_dir: Some("/srv/redis".to_string()),
+            restart: Some(RestartPolicy::OnFailure),
+            health_check: Some(HealthCheck::Tcp(6380)),
+        };
+        db.set_service_schedule("redis", Some(&RunType::Interval(60)))
+            .unwrap();
+        db.set_service_overrides("redis", &overrides).unwrap();
+
+        let settings = db.service_settings("redis").unwrap().unwrap();
+        assert_eq!(settings.overrides, overrides);
+        assert_eq!(settings.schedule, Some(RunType::Interval(60)));
+
+        db.set_service_overrides("redis", &ServiceOverrides::default())
+            .unwrap();
+        let settings = db.service_settings("redis").unwrap().unwrap();
+        assert!(settings.overrides.is_empty());
+    }
+
+    #[test]
+    fn link_owner_follows_keg_files() {
+        let mut db = Database::in_memory().unwrap();
+        let link = Path::new("/opt/zerobrew/prefix/bin/python3");
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("python@3.12", "3.12.1", "abc").unwrap();
+            tx.record_linked_file(
+                "python@3.12",
+                "3.12.1",
+                &link.to_string_lossy(),
+                "/opt/zerobrew/Cellar/python@3.12/3.12.1/bin/python3",
+                LinkMode::Symlink,
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.link_owner(link).unwrap().as_deref(), Some("python@3.12"));
+        assert_eq!(db.link_owner(Path::new("/usr/bin/python3")).unwrap(), None);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.forget_linked_file(link).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.link_owner(link).unwrap(), None);
+    }
+
+    #[test]
+    fn sampled_keg_files_are_limited_to_installed_versions() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("jq", "1.7.1", "abc").unwrap();
+            for (version, bin) in [("1.7.1", "jq"), ("1.7.1", "jq-1"), ("1.6", "jq-old")] {
+                tx.record_linked_file(
+                    "jq",
+                    version,
+                    &format!("/opt/zerobrew/prefix/bin/{bin}"),
+                    &format!("/opt/zerobrew/Cellar/jq/{version}/bin/{bin}"),
+                    LinkMode::Symlink,
+                )
+                .unwrap();
+            }
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(db.keg_files("jq").unwrap().len(), 3);
+        assert_eq!(db.installed_keg_files().unwrap().len(), 2);
+        assert_eq!(db.sample_installed_keg_files(1).unwrap().len(), 1);
+        let sampled = db.sample_installed_keg_files(8).unwrap();
+        assert_eq!(sampled.len(), 2);
+        assert!(sampled.iter().all(|record| record.version == "1.7.1"));
+    }
+
+    #[test]
+    fn generations_hold_store_refs_until_deleted() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "old").unwrap();
+            tx.commit().unwrap();
+        }
+        {
+            let tx = db.transaction().unwrap();
+            tx.retire_to_generation("foo").unwrap();
+            tx.record_install("foo", "2.0.0", "new").unwrap();
+            tx.commit().unwrap();
+        }
+
+        let generations = db.list_generations("foo").unwrap();
+        assert_eq!(generations.len(), 1);
+        assert_eq!(generations[0].version, "1.0.0");
+        assert_eq!(db.get_installed("foo").unwrap().version, "2.0.0");
+        assert_eq!(db.get_store_refcount("old"), 1);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.retire_to_generation("foo").unwrap();
+            tx.restore_generation("foo", "1.0.0").unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.get_installed("foo").unwrap().version, "1.0.0");
+        let generations = db.list_generations("foo").unwrap();
+        assert_eq!(generations.len(), 1);
+        assert_eq!(generations[0].version, "2.0.0");
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.delete_generation("foo", "2.0.0").unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(db.list_all_generations().unwrap().is_empty());
+        assert_eq!(db.get_store_refcount("new"), 0);
+        assert_eq!(db.get_store_refcount("old"), 1);
+    }
+
+    #[test]
+    fn corrupt_downloads_count_per_url() {
+        let db = Database::in_memory().unwrap();
+        let url = "https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc";
+
+        assert_eq!(db.record_corrupt_download(url, "abc").unwrap(), 1);
+        assert_eq!(db.record_corrupt_download(url, "abc").unwrap(), 2);
+        assert_eq!(
+            db.record_corrupt_download("https://other", "def").unwrap(),
+            1
+        );
+
+        let downloads = db.list_corrupt_downloads().unwrap();
+        assert_eq!(downloads.len(), 2);
+        assert_eq!(downloads[0].url, url);
+        assert_eq!(downloads[0].failures, 2);
+
+        assert_eq!(db.clear_corrupt_downloads().unwrap(), 2);
+        assert!(db.list_corrupt_downloads().unwrap().is_empty());
+    }
+
+    #[test]
+    fn install_stats_aggregate_recorded_installs() {
+        let db = Database::in_memory().unwrap();
+        assert_eq!(db.install_stats(5).unwrap(), StatsSummary::default());
+
+        let bottle = InstallStat {
+            name: "jq".to_string(),
+            method: "bottle",
+            duration_ms: 1_000,
+            cached: false,
+            download_bytes: Some(4_000),
+            download_ms: Some(500),
+            relocation_ms: Some(200),
+        };
+        db.record_install_stat(&bottle).unwrap();
+        db.record_install_stat(&InstallStat {
+            name: "oniguruma".to_string(),
+            duration_ms: 300,
+            cached: true,
+            download_bytes: None,
+            download_ms: None,
+            relocation_ms: None,
+            ..bottle.clone()
+        })
+        .unwrap();
+        db.record_install_stat(&InstallStat {
+            name: "tool".to_string(),
+            method: "source",
+            duration_ms: 9_000,
+            relocation_ms: None,
+            download_bytes: None,
+            download_ms: None,
+            ..bottle
+        })
+        .unwrap();
+
+        let summary = db.install_stats(2).unwrap();
+        assert_eq!(summary.installs, 3);
+        assert_eq!(summary.source_builds, 1);
+        assert_eq!(summary.cache_hits, 1);
+        assert_eq!(summary.total_duration_ms, 10_300);
+        assert_eq!(summary.downloaded_bytes, 4_000);
+        assert_eq!(summary.download_ms, 500);
+        assert_eq!(summary.relocations, 1);
+        assert_eq!(summary.relocation_ms, 200);
+        assert!(summary.since.is_some());
+        assert_eq!(
+            summary.slowest,
+            vec![("tool".to_string(), 9_000), ("jq".to_string(), 1_000)]
+        );
+
+        assert_eq!(db.clear_install_stats().unwrap(), 3);
+        assert_eq!(db.install_stats(2).unwrap().installs, 0);
+    }
+
+    #[test]
+    fn executables_index_maps_commands_to_formulas() {
+        let db = Database::in_memory().unwrap();
+        let entries = vec![
+            (
+                "vim".to_string(),
+                vec!["vim".to_string(), "vimdiff".to_string()],
+            ),
+            ("macvim".to_string(), vec!["vim".to_string()]),
+        ];
+        assert_eq!(db.replace_executables(&entries).unwrap(), 3);
+        assert_eq!(db.formulas_providing("vim").unwrap(), ["macvim", "vim"]);
+        assert_eq!(db.formulas_providing("vimdiff").unwrap(), ["vim"]);
+
+        db.replace_executables(&entries[1..]).unwrap();
+        assert!(db.formulas_providing("vimdiff").unwrap().is_empty());
+    }
+
+    #[test]
+    fn concurrent_connections_share_the_database() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("zb.sqlite3");
+        let mut db = Database::open(&path).unwrap();
+        let mode: String = db
+            .conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        // A second connection holds the write lock for a while; this one
+        // waits for it instead of failing with SQLITE_BUSY.
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let writer = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let mut other = Database::open(&path).unwrap();
+                let tx = other.transaction().unwrap();
+                tx.record_install("jq", "1.7.1", "jq-key").unwrap();
+                locked_tx.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(200));
+                tx.commit().unwrap();
+            })
+        };
+        locked_rx.recv().unwrap();
+        // Readers are not blocked by the writer.
+        assert!(db.get_installed("jq").is_none());
+        let tx = db.transaction().unwrap();
+        tx.record_install("wget", "1.24.5", "wget-key").unwrap();
+        tx.commit().unwrap();
+        writer.join().unwrap();
+
+        let names: Vec<String> = db
+            .list_installed()
+            .unwrap()
+            .into_iter()
+            .map(|keg| keg.name)
+            .collect();
+        assert_eq!(names, ["jq", "wget"]);
+    }
 }
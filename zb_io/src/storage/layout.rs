@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use zb_core::Error;
+
+/// Where zerobrew keeps its data. Everything lives under the root by
+/// default, and the cache, store, cellar and database can each be moved on
+/// their own, e.g. the download cache onto a scratch disk and the store onto
+/// a persistent volume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageLayout {
+    pub root: PathBuf,
+    pub prefix: PathBuf,
+    /// Downloaded bottles, API responses and cached relocations.
+    pub cache: PathBuf,
+    /// Unpacked bottles, keyed by their checksum.
+    pub store: PathBuf,
+    /// Installed kegs. Bottles expect them at `prefix/Cellar`, so a cellar
+    /// elsewhere is reached through a link there.
+    pub cellar: PathBuf,
+    /// The SQLite database file.
+    pub db: PathBuf,
+}
+
+impl StorageLayout {
+    pub fn new(root: &Path, prefix: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            prefix: prefix.to_path_buf(),
+            cache: root.join("cache"),
+            store: root.join("store"),
+            cellar: prefix.join("Cellar"),
+            db: root.join("db/zb.sqlite3"),
+        }
+    }
+
+    pub fn with_cache_dir(mut self, dir: PathBuf) -> Self {
+        self.cache = dir;
+        self
+    }
+
+    pub fn with_store_dir(mut self, dir: PathBuf) -> Self {
+        self.store = dir;
+        self
+    }
+
+    pub fn with_cellar_dir(mut self, dir: PathBuf) -> Self {
+        self.cellar = dir;
+        self
+    }
+
+    pub fn with_db_path(mut self, path: PathBuf) -> Self {
+        self.db = path;
+        self
+    }
+
+    /// The cellar path kegs are installed and relocated to.
+    pub fn prefix_cellar(&self) -> PathBuf {
+        self.prefix.join("Cellar")
+    }
+
+    /// Make `prefix/Cellar` a link to a cellar configured elsewhere. An
+    /// existing cellar directory is only replaced while it is empty, so
+    /// installed kegs are never hidden.
+    pub fn link_cellar(&self) -> Result<(), Error> {
+        let link = self.prefix_cellar();
+        if self.cellar == link {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.cellar).map_err(Error::store("failed to create cellar"))?;
+        match fs::read_link(&link) {
+            Ok(target) if target == self.cellar => return Ok(()),
+            Ok(_) => {
+                fs::remove_file(&link).map_err(Error::store("failed to replace cellar link"))?
+            }
+            Err(_) if link.is_dir() => {
+                fs::remove_dir(&link).map_err(|_| Error::InvalidArgument {
+                    message: format!(
+                        "{} already holds kegs; move them to {} before relocating the cellar",
+                        link.display(),
+                        self.cellar.display()
+                    ),
+                })?;
+            }
+            Err(_) => {
+                fs::create_dir_all(&self.prefix)
+                    .map_err(Error::store("failed to create prefix directory"))?;
+            }
+        }
+        std::os::unix::fs::symlink(&self.cellar, &link)
+            .map_err(Error::store("failed to link cellar into prefix"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn defaults_keep_everything_under_root_and_prefix() {
+        let layout = StorageLayout::new(Path::new("/zb"), Path::new("/zb/prefix"));
+        assert_eq!(layout.cache, Path::new("/zb/cache"));
+        assert_eq!(layout.store, Path::new("/zb/store"));
+        assert_eq!(layout.cellar, Path::new("/zb/prefix/Cellar"));
+        assert_eq!(layout.db, Path::new("/zb/db/zb.sqlite3"));
+    }
+
+    #[test]
+    fn moved_cellar_is_linked_into_the_prefix() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        let cellar = tmp.path().join("volume/Cellar");
+        let layout =
+            StorageLayout::new(&tmp.path().join("root"), &prefix).with_cellar_dir(cellar.clone());
+
+        layout.link_cellar().unwrap();
+        layout.link_cellar().unwrap();
+        assert_eq!(fs::read_link(prefix.join("Cellar")).unwrap(), cellar);
+
+        // A cellar with kegs in it is left alone.
+        let other = StorageLayout::new(&tmp.path().join("root"), &tmp.path().join("other"))
+            .with_cellar_dir(cellar);
+        fs::create_dir_all(tmp.path().join("other/Cellar/jq/1.7")).unwrap();
+        assert!(matches!(
+            other.link_cellar(),
+            Err(Error::InvalidArgument { .. })
+        ));
+    }
+}
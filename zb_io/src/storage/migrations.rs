@@ -0,0 +1,452 @@
+use rusqlite::{Connection, OptionalExtension, params};
+
+use zb_core::Error;
+
+use super::db::write_transaction;
+
+/// One step of the database schema. Migrations are applied in order, each in
+/// its own transaction together with its `schema_version` row, so a failed
+/// step leaves the database at the previous version.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Every schema change, oldest first. Append new steps; never edit or
+/// reorder released ones.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: "
+            CREATE TABLE IF NOT EXISTS installed_kegs (
+                name TEXT PRIMARY KEY,
+                version TEXT NOT NULL,
+                store_key TEXT NOT NULL,
+                installed_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS store_refs (
+                store_key TEXT PRIMARY KEY,
+                refcount INTEGER NOT NULL DEFAULT 1
+            );
+
+            CREATE TABLE IF NOT EXISTS keg_files (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                linked_path TEXT NOT NULL,
+                target_path TEXT NOT NULL,
+                PRIMARY KEY (name, linked_path)
+            );
+        ",
+    },
+    Migration {
+        version: 2,
+        description: "keg sizes and dependencies",
+        sql: "
+            ALTER TABLE installed_kegs ADD COLUMN size_bytes INTEGER;
+
+            CREATE TABLE IF NOT EXISTS keg_dependencies (
+                name TEXT NOT NULL,
+                dependency TEXT NOT NULL,
+                PRIMARY KEY (name, dependency)
+            );
+        ",
+    },
+    Migration {
+        version: 3,
+        description: "keg architecture",
+        sql: "ALTER TABLE installed_kegs ADD COLUMN arch TEXT;",
+    },
+    Migration {
+        version: 4,
+        description: "keg generations",
+        sql: "
+            CREATE TABLE IF NOT EXISTS keg_generations (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                store_key TEXT NOT NULL,
+                arch TEXT,
+                installed_at INTEGER NOT NULL,
+                superseded_at INTEGER NOT NULL,
+                PRIMARY KEY (name, version)
+            );
+        ",
+    },
+    Migration {
+        version: 5,
+        description: "link ownership index",
+        sql: "CREATE INDEX IF NOT EXISTS keg_files_linked_path ON keg_files (linked_path);",
+    },
+    Migration {
+        version: 6,
+        description: "service settings",
+        sql: "
+            CREATE TABLE IF NOT EXISTS services (
+                name TEXT PRIMARY KEY,
+                interval_secs INTEGER,
+                cron TEXT
+            );
+        ",
+    },
+    Migration {
+        version: 7,
+        description: "service overrides",
+        sql: "
+            ALTER TABLE services ADD COLUMN environment TEXT;
+            ALTER TABLE services ADD COLUMN args TEXT;
+            ALTER TABLE services ADD COLUMN working_dir TEXT;
+        ",
+    },
+    Migration {
+        version: 8,
+        description: "service health checks",
+        sql: "
+            ALTER TABLE services ADD COLUMN restart TEXT;
+            ALTER TABLE services ADD COLUMN health_port INTEGER;
+            ALTER TABLE services ADD COLUMN health_command TEXT;
+        ",
+    },
+    Migration {
+        version: 9,
+        description: "formula metadata",
+        sql: "
+            CREATE TABLE IF NOT EXISTS formulas (
+                name TEXT PRIMARY KEY,
+                version TEXT NOT NULL,
+                dependencies TEXT NOT NULL,
+                json TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS formula_aliases (
+                alias TEXT PRIMARY KEY,
+                name TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS formula_aliases_name ON formula_aliases (name);
+        ",
+    },
+    Migration {
+        version: 10,
+        description: "corrupt downloads",
+        sql: "
+            CREATE TABLE IF NOT EXISTS corrupt_downloads (
+                url TEXT PRIMARY KEY,
+                sha256 TEXT NOT NULL,
+                failures INTEGER NOT NULL,
+                last_failed_at INTEGER NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 11,
+        description: "executables index",
+        sql: "
+            CREATE TABLE IF NOT EXISTS executables (
+                executable TEXT NOT NULL,
+                formula TEXT NOT NULL,
+                PRIMARY KEY (executable, formula)
+            );
+        ",
+    },
+    Migration {
+        version: 12,
+        description: "keg file manifests",
+        sql: "
+            CREATE TABLE IF NOT EXISTS keg_manifests (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                path TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                sha256 TEXT,
+                PRIMARY KEY (name, path)
+            );
+        ",
+    },
+    Migration {
+        version: 13,
+        description: "install stats",
+        sql: "
+            CREATE TABLE IF NOT EXISTS install_stats (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                method TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                cached INTEGER NOT NULL,
+                download_bytes INTEGER,
+                download_ms INTEGER,
+                relocation_ms INTEGER
+            );
+        ",
+    },
+    Migration {
+        version: 14,
+        description: "vulnerability feed snapshot",
+        sql: "
+            CREATE TABLE IF NOT EXISTS advisories (
+                name TEXT PRIMARY KEY,
+                json TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 15,
+        description: "link modes",
+        sql: "
+            ALTER TABLE keg_files ADD COLUMN mode TEXT NOT NULL DEFAULT 'symlink';
+            CREATE TABLE IF NOT EXISTS link_modes (
+                name TEXT PRIMARY KEY,
+                mode TEXT NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 16,
+        description: "link profiles",
+        sql: "
+            CREATE TABLE IF NOT EXISTS profiles (
+                name TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS profile_kegs (
+                profile TEXT NOT NULL,
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                PRIMARY KEY (profile, name)
+            );
+        ",
+    },
+    Migration {
+        version: 17,
+        description: "Homebrew layout compatibility roots",
+        sql: "
+            CREATE TABLE IF NOT EXISTS compat_roots (
+                path TEXT PRIMARY KEY
+            );
+        ",
+    },
+    Migration {
+        version: 18,
+        description: "package notes and tags",
+        sql: "
+            CREATE TABLE IF NOT EXISTS package_notes (
+                name TEXT PRIMARY KEY,
+                note TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS package_tags (
+                name TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (name, tag)
+            );
+            CREATE INDEX IF NOT EXISTS idx_package_tags_tag ON package_tags(tag);
+        ",
+    },
+    Migration {
+        version: 19,
+        description: "keg bytes shared with the store",
+        sql: "ALTER TABLE installed_kegs ADD COLUMN shared_bytes INTEGER;",
+    },
+    Migration {
+        version: 20,
+        description: "store ref release times",
+        sql: "ALTER TABLE store_refs ADD COLUMN released_at INTEGER;",
+    },
+    Migration {
+        version: 21,
+        description: "operation history",
+        sql: "
+            CREATE TABLE IF NOT EXISTS operations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL,
+                error TEXT,
+                detail TEXT
+            );
+            CREATE TABLE IF NOT EXISTS operation_changes (
+                operation_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                from_version TEXT,
+                to_version TEXT,
+                PRIMARY KEY (operation_id, name)
+            );
+        ",
+    },
+];
+
+/// The schema version this build of zerobrew writes.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// The version the database at `conn` is at.
+///
+/// Databases created before the `schema_version` table existed only carry
+/// `PRAGMA user_version`, which is still kept in sync so older binaries
+/// can tell that a database is newer than they are.
+pub fn current_version(conn: &Connection) -> Result<u32, Error> {
+    let user_version: u32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(Error::store("failed to query schema version"))?;
+
+    let has_table: bool = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+            [],
+            |_| Ok(true),
+        )
+        .optional()
+        .map_err(Error::store("failed to look up schema_version table"))?
+        .unwrap_or(false);
+    if !has_table {
+        return Ok(user_version);
+    }
+
+    let recorded: Option<u32> = conn
+        .query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })
+        .map_err(Error::store("failed to query schema_version"))?;
+    Ok(recorded.unwrap_or(0).max(user_version))
+}
+
+/// Bring the database up to [`latest_version`]. A database that is already
+/// newer is left untouched; the caller decides how to treat it.
+pub fn apply_pending(conn: &mut Connection) -> Result<(), Error> {
+    let current = current_version(conn)?;
+    if current >= latest_version() {
+        return Ok(());
+    }
+
+    let tx = write_transaction(conn, "failed to start migration")?;
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        );",
+    )
+    .map_err(Error::store("failed to create schema_version table"))?;
+    // Steps applied before the table existed are recorded without a date.
+    for migration in MIGRATIONS.iter().filter(|m| m.version <= current) {
+        tx.execute(
+            "INSERT OR IGNORE INTO schema_version (version, description, applied_at)
+             VALUES (?1, ?2, 0)",
+            params![migration.version, migration.description],
+        )
+        .map_err(Error::store("failed to backfill schema_version"))?;
+    }
+    tx.commit()
+        .map_err(Error::store("failed to commit schema_version table"))?;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        apply(conn, migration)?;
+    }
+    Ok(())
+}
+
+fn apply(conn: &mut Connection, migration: &Migration) -> Result<(), Error> {
+    let context = format!(
+        "failed to apply migration {} ({})",
+        migration.version, migration.description
+    );
+    let tx = write_transaction(conn, "failed to start migration")?;
+    // Another process opening the database at the same time may have
+    // applied it while this one waited for the write lock.
+    if current_version(&tx)? >= migration.version {
+        return Ok(());
+    }
+
+    tx.execute_batch(migration.sql)
+        .map_err(Error::store(&context))?;
+    tx.execute(
+        "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+        params![migration.version, migration.description, unix_now()],
+    )
+    .map_err(Error::store(&context))?;
+    tx.execute_batch(&format!("PRAGMA user_version = {}", migration.version))
+        .map_err(Error::store(&context))?;
+
+    tx.commit().map_err(Error::store(&context))
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_sequential() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, index + 1);
+        }
+    }
+
+    #[test]
+    fn records_each_applied_migration() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply_pending(&mut conn).unwrap();
+
+        let rows: Vec<(u32, String)> = conn
+            .prepare("SELECT version, description FROM schema_version ORDER BY version")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), MIGRATIONS.len());
+        assert_eq!(rows[0], (1, "initial schema".to_string()));
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn legacy_user_version_is_backfilled() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute_batch("PRAGMA user_version = 1").unwrap();
+
+        apply_pending(&mut conn).unwrap();
+
+        let applied_at: i64 = conn
+            .query_row(
+                "SELECT applied_at FROM schema_version WHERE version = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(applied_at, 0);
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply_pending(&mut conn).unwrap();
+        let broken = Migration {
+            version: latest_version() + 1,
+            description: "broken",
+            sql: "CREATE TABLE half_done (id INTEGER); SELECT * FROM missing_table;",
+        };
+
+        let err = apply(&mut conn, &broken).unwrap_err();
+        assert!(err.to_string().contains("broken"));
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        let half_done: Option<i64> = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE name = 'half_done'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap();
+        assert_eq!(half_done, None);
+    }
+}
@@ -1,7 +1,16 @@
 pub mod blob;
 pub mod db;
+pub mod layout;
+pub mod migrations;
 pub mod store;
+pub mod verify;
 
-pub use blob::{BlobCache, BlobWriter};
-pub use db::{Database, InstallTransaction, InstalledKeg, KegFileRecord, StoreRef};
-pub use store::Store;
+pub use blob::{BlobCache, BlobEntry, BlobWriter};
+pub use db::{
+    CachedAdvisories, CachedFormula, CorruptDownload, Database, FormulaRecord, InstallStat,
+    InstallTransaction, InstalledKeg, KegFileRecord, KegGeneration, ManifestEntry, OperationRecord,
+    PackageChange, ProfileRecord, ServiceOverrides, ServiceSettings, StatsSummary, StoreRef,
+};
+pub use layout::StorageLayout;
+pub use store::{DedupeSummary, StagedEntry, Store};
+pub use verify::StoreVerification;
@@ -1,29 +1,137 @@
+use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io;
+use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::extraction::extract::extract_archive;
+use sha2::{Digest, Sha256};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+
+use super::verify::{EntryManifest, StoreVerification};
+use crate::cellar::materialize::disk_usage;
+use crate::checksum::sha256_hex;
+use crate::extraction::extract::{ExtractPolicy, extract_archive_with_policy};
 use zb_core::Error;
 
+/// Text files carrying this marker are rewritten in place when a keg is
+/// materialized, so they must never share an inode with another entry.
+const PLACEHOLDER_MARKER: &[u8] = b"@@HOMEBREW_";
+
+/// Marks the temporary directory an entry is unpacked into, named
+/// `.{store_key}.zb-incomplete-XXXXXX`, so one left behind by an
+/// interrupted install can be traced back to its lock.
+const INCOMPLETE_MARKER: &str = ".zb-incomplete-";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DedupeSummary {
+    pub files_scanned: usize,
+    pub files_linked: usize,
+    pub bytes_saved: u64,
+}
+
+struct StoreFile {
+    path: PathBuf,
+    inode: (u64, u64),
+    nlink: u64,
+}
+
+#[derive(Clone)]
 pub struct Store {
     store_dir: PathBuf,
     locks_dir: PathBuf,
+    /// Set by [`Store::with_extract_policy`]; otherwise each archive is
+    /// unpacked with [`ExtractPolicy::for_bottle`] its size.
+    extract_policy: Option<ExtractPolicy>,
+    verification: StoreVerification,
+    unpack_slots: Arc<Semaphore>,
 }
 
 impl Store {
     pub fn new(root: &Path) -> io::Result<Self> {
-        let store_dir = root.join("store");
-        let locks_dir = root.join("locks");
+        Self::new_at(root.join("store"), root.join("locks"))
+    }
 
+    pub fn new_at(store_dir: PathBuf, locks_dir: PathBuf) -> io::Result<Self> {
         fs::create_dir_all(&store_dir)?;
         fs::create_dir_all(&locks_dir)?;
 
         Ok(Self {
             store_dir,
             locks_dir,
+            extract_policy: None,
+            verification: StoreVerification::Off,
+            unpack_slots: Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)),
         })
     }
 
+    pub fn with_extract_policy(mut self, extract_policy: ExtractPolicy) -> Self {
+        self.extract_policy = Some(extract_policy);
+        self
+    }
+
+    /// Record a content manifest for each new entry, and check entries
+    /// against theirs in [`Store::verify_entry`].
+    pub fn with_verification(mut self, verification: StoreVerification) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// Unpack at most `workers` downloads into the store while they are
+    /// still arriving. Unlimited by default.
+    pub fn with_unpack_workers(mut self, workers: usize) -> Self {
+        self.unpack_slots = Arc::new(Semaphore::new(workers.max(1)));
+        self
+    }
+
+    /// A slot for unpacking a download as it arrives, if one is free. The
+    /// download is unpacked from the cache otherwise.
+    pub(crate) fn try_unpack_slot(&self) -> Option<OwnedSemaphorePermit> {
+        self.unpack_slots.clone().try_acquire_owned().ok()
+    }
+
+    /// Where the content manifest of `store_key` is kept, next to its entry.
+    fn manifest_path(&self, store_key: &str) -> PathBuf {
+        self.store_dir.join(format!("{store_key}.manifest.json"))
+    }
+
+    /// Check the entry for `store_key` against the manifest recorded when it
+    /// was unpacked, failing with `StoreCorruption` naming the first file
+    /// that was added, removed or changed since. An entry unpacked before
+    /// verification was turned on has its manifest recorded now instead.
+    pub fn verify_entry(&self, store_key: &str) -> Result<(), Error> {
+        if self.verification == StoreVerification::Off {
+            return Ok(());
+        }
+        let entry_path = self.entry_path(store_key);
+        let manifest_path = self.manifest_path(store_key);
+        match EntryManifest::read(&manifest_path)? {
+            Some(manifest) => manifest.verify(&entry_path, self.verification),
+            None => {
+                debug!(
+                    store_key,
+                    "recording manifest for an unverified store entry"
+                );
+                EntryManifest::of(&entry_path)?.write(&manifest_path)
+            }
+        }
+    }
+
+    /// Move a freshly unpacked entry into place, recording its manifest
+    /// first when verification is on.
+    fn persist(&self, tmp_dir: tempfile::TempDir, store_key: &str) -> Result<PathBuf, Error> {
+        let manifest = (self.verification != StoreVerification::Off)
+            .then(|| EntryManifest::of(tmp_dir.path()))
+            .transpose()?;
+        let entry_path = persist_entry(tmp_dir, self.entry_path(store_key))?;
+        if let Some(manifest) = manifest {
+            manifest.write(&self.manifest_path(store_key))?;
+        }
+        Ok(entry_path)
+    }
+
     pub fn entry_path(&self, store_key: &str) -> PathBuf {
         self.store_dir.join(store_key)
     }
@@ -32,6 +140,26 @@ impl Store {
         self.entry_path(store_key).exists()
     }
 
+    /// The formula an entry was unpacked from, read from the
+    /// `{name}/{version}` layout of bottles; `None` for other layouts.
+    pub fn entry_formula(&self, store_key: &str) -> Option<String> {
+        let mut dirs = fs::read_dir(self.entry_path(store_key))
+            .ok()?
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()));
+        let formula = dirs.next()?;
+        if dirs.next().is_some() {
+            return None;
+        }
+        formula.file_name().into_string().ok()
+    }
+
+    /// Bytes the store takes on disk, counting files that entries share
+    /// once.
+    pub fn total_size(&self) -> io::Result<u64> {
+        disk_usage(&self.store_dir)
+    }
+
     pub fn list_entries(&self) -> Result<Vec<String>, Error> {
         let mut entries = Vec::new();
         for entry in
@@ -73,23 +201,213 @@ impl Store {
             return Ok(entry_path);
         }
 
-        let tmp_dir = tempfile::tempdir_in(&self.store_dir)
-            .map_err(Error::store("failed to create temp directory"))?;
+        let tmp_dir = self.staging_dir(store_key)?;
+
+        let archive_bytes = fs::metadata(blob_path)
+            .map_err(Error::store("failed to read blob metadata"))?
+            .len();
+        let policy = self.extract_policy(Some(archive_bytes));
+        extract_archive_with_policy(blob_path, tmp_dir.path(), &policy)?;
 
-        extract_archive(blob_path, tmp_dir.path())?;
+        // Lock will be released when lock_file is dropped
+        self.persist(tmp_dir, store_key)
+    }
 
-        // Persist the temp dir by converting it into a permanent path.
-        // into_path() prevents auto-cleanup so rename failure still needs manual handling.
-        let tmp_path = tmp_dir.keep();
-        if let Err(e) = fs::rename(&tmp_path, &entry_path) {
-            let _ = fs::remove_dir_all(&tmp_path);
-            return Err(Error::StoreCorruption {
-                message: format!("failed to rename store entry: {e}"),
-            });
+    /// Start building the entry for `store_key` in a temporary directory,
+    /// holding its lock until the entry is committed or dropped. `None` when
+    /// the entry exists or another process is creating it.
+    pub fn stage_entry(&self, store_key: &str) -> Result<Option<StagedEntry>, Error> {
+        let entry_path = self.entry_path(store_key);
+        if entry_path.exists() {
+            return Ok(None);
         }
 
-        // Lock will be released when lock_file is dropped
-        Ok(entry_path)
+        let lock_path = self.locks_dir.join(format!("{store_key}.lock"));
+        let lock = File::create(&lock_path).map_err(Error::store("failed to create lock file"))?;
+        if lock.try_lock().is_err() || entry_path.exists() {
+            return Ok(None);
+        }
+
+        let dir = self.staging_dir(store_key)?;
+        let manifest_path =
+            (self.verification != StoreVerification::Off).then(|| self.manifest_path(store_key));
+        Ok(Some(StagedEntry {
+            dir,
+            entry_path,
+            manifest_path,
+            _lock: lock,
+        }))
+    }
+
+    fn staging_dir(&self, store_key: &str) -> Result<tempfile::TempDir, Error> {
+        tempfile::Builder::new()
+            .prefix(&format!(".{store_key}{INCOMPLETE_MARKER}"))
+            .tempdir_in(&self.store_dir)
+            .map_err(Error::store("failed to create temp directory"))
+    }
+
+    /// Remove directories left by unpacking that was interrupted before the
+    /// entry was committed, e.g. by Ctrl-C. Ones whose entry another
+    /// process is still unpacking are left alone. Returns how many were
+    /// removed.
+    pub fn remove_stale_staging(&self) -> Result<usize, Error> {
+        let mut removed = 0;
+        for name in self.list_entries()? {
+            let Some(store_key) = name
+                .strip_prefix('.')
+                .and_then(|rest| rest.split_once(INCOMPLETE_MARKER))
+                .map(|(store_key, _)| store_key)
+            else {
+                continue;
+            };
+            let lock_path = self.locks_dir.join(format!("{store_key}.lock"));
+            let lock =
+                File::create(&lock_path).map_err(Error::store("failed to create lock file"))?;
+            if lock.try_lock().is_err() {
+                continue;
+            }
+            fs::remove_dir_all(self.store_dir.join(&name))
+                .map_err(Error::store("failed to remove stale temp directory"))?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Limits applied when unpacking an archive of `archive_bytes`, when
+    /// known, into the store.
+    pub fn extract_policy(&self, archive_bytes: Option<u64>) -> ExtractPolicy {
+        match &self.extract_policy {
+            Some(policy) => policy.clone(),
+            None => ExtractPolicy::for_bottle(archive_bytes.unwrap_or(0)),
+        }
+    }
+
+    /// Replace byte-identical regular files across store entries with
+    /// hardlinks to a single copy.
+    ///
+    /// Files are only merged when size and mode match, since hardlinks share
+    /// permissions. Files containing Homebrew placeholders are skipped.
+    pub fn dedupe(&self) -> Result<DedupeSummary, Error> {
+        let mut summary = DedupeSummary::default();
+        let mut by_shape: HashMap<(u64, u32), Vec<StoreFile>> = HashMap::new();
+
+        for store_key in self.list_entries()? {
+            // Skip in-flight extraction temp dirs
+            if store_key.starts_with('.') {
+                continue;
+            }
+            for entry in walkdir::WalkDir::new(self.entry_path(&store_key)) {
+                let entry = entry.map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to walk store entry '{store_key}': {e}"),
+                })?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let metadata = entry.metadata().map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to read metadata: {e}"),
+                })?;
+                summary.files_scanned += 1;
+                if metadata.len() == 0 {
+                    continue;
+                }
+                by_shape
+                    .entry((metadata.len(), metadata.mode()))
+                    .or_default()
+                    .push(StoreFile {
+                        path: entry.into_path(),
+                        inode: (metadata.dev(), metadata.ino()),
+                        nlink: metadata.nlink(),
+                    });
+            }
+        }
+
+        for ((size, _), files) in by_shape {
+            let mut by_inode: HashMap<(u64, u64), Vec<StoreFile>> = HashMap::new();
+            for file in files {
+                by_inode.entry(file.inode).or_default().push(file);
+            }
+            if by_inode.len() < 2 {
+                continue;
+            }
+
+            let mut canonical: HashMap<String, PathBuf> = HashMap::new();
+            let mut inodes: Vec<_> = by_inode.into_values().collect();
+            inodes.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+
+            for paths in inodes {
+                let Some(digest) = hash_dedupe_candidate(&paths[0].path)? else {
+                    continue;
+                };
+                let Some(target) = canonical.get(&digest) else {
+                    canonical.insert(digest, paths[0].path.clone());
+                    continue;
+                };
+
+                // Space is only reclaimed if nothing outside the store (e.g. a
+                // hardlinked keg) still holds the old inode.
+                let fully_replaced = paths[0].nlink == paths.len() as u64;
+                for file in &paths {
+                    replace_with_hardlink(target, &file.path)?;
+                    summary.files_linked += 1;
+                }
+                if fully_replaced {
+                    summary.bytes_saved += size;
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Hardlink files in `new_key` that are unchanged since `base_key`, a
+    /// previous version of the same bottle, to the base entry's copies.
+    ///
+    /// Bottles nest their contents under `{name}/{version}/`, so files are
+    /// paired by their path below those two components. Pairs must match in
+    /// size, mode and content; placeholder files are never shared. Once the
+    /// base entry is garbage collected, the shared inodes live on in the new
+    /// entry, so an upgrade only adds disk usage for files that changed.
+    pub fn share_unchanged_files(
+        &self,
+        new_key: &str,
+        base_key: &str,
+    ) -> Result<DedupeSummary, Error> {
+        let mut summary = DedupeSummary::default();
+        let base_files = versioned_files(&self.entry_path(base_key))?;
+        if base_files.is_empty() {
+            return Ok(summary);
+        }
+
+        for (relative, new_file) in versioned_files(&self.entry_path(new_key))? {
+            summary.files_scanned += 1;
+            let Some(base_file) = base_files.get(&relative) else {
+                continue;
+            };
+            let (new_meta, base_meta) = match (fs::metadata(&new_file), fs::metadata(base_file)) {
+                (Ok(n), Ok(b)) => (n, b),
+                _ => continue,
+            };
+            if new_meta.len() == 0
+                || new_meta.len() != base_meta.len()
+                || new_meta.mode() != base_meta.mode()
+                || (new_meta.dev(), new_meta.ino()) == (base_meta.dev(), base_meta.ino())
+            {
+                continue;
+            }
+
+            let Some(new_digest) = hash_dedupe_candidate(&new_file)? else {
+                continue;
+            };
+            if hash_dedupe_candidate(base_file)?.as_ref() != Some(&new_digest) {
+                continue;
+            }
+
+            replace_with_hardlink(base_file, &new_file)?;
+            summary.files_linked += 1;
+            summary.bytes_saved += new_meta.len();
+        }
+
+        Ok(summary)
     }
 
     /// Remove a store entry. This should only be called when the refcount is 0.
@@ -113,6 +431,7 @@ impl Store {
             fs::remove_dir_all(&entry_path)
                 .map_err(Error::store("failed to remove store entry"))?;
         }
+        let _ = fs::remove_file(self.manifest_path(store_key));
 
         // Clean up the lock file
         let _ = fs::remove_file(&lock_path);
@@ -121,6 +440,114 @@ impl Store {
     }
 }
 
+/// A store entry being unpacked; removed again unless committed.
+pub struct StagedEntry {
+    dir: tempfile::TempDir,
+    entry_path: PathBuf,
+    /// Where to record the entry's manifest, when verification is on.
+    manifest_path: Option<PathBuf>,
+    _lock: File,
+}
+
+impl StagedEntry {
+    /// Where the entry's contents are unpacked until it is committed.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Move the unpacked contents into place as the store entry.
+    pub fn commit(self) -> Result<PathBuf, Error> {
+        let Some(manifest_path) = self.manifest_path else {
+            return persist_entry(self.dir, self.entry_path);
+        };
+        let manifest = EntryManifest::of(self.dir.path())?;
+        let entry_path = persist_entry(self.dir, self.entry_path)?;
+        manifest.write(&manifest_path)?;
+        Ok(entry_path)
+    }
+}
+
+fn persist_entry(tmp_dir: tempfile::TempDir, entry_path: PathBuf) -> Result<PathBuf, Error> {
+    // Persist the temp dir by converting it into a permanent path.
+    // keep() prevents auto-cleanup so rename failure still needs manual handling.
+    let tmp_path = tmp_dir.keep();
+    if let Err(e) = fs::rename(&tmp_path, &entry_path) {
+        let _ = fs::remove_dir_all(&tmp_path);
+        return Err(Error::StoreCorruption {
+            message: format!("failed to rename store entry: {e}"),
+        });
+    }
+    Ok(entry_path)
+}
+
+/// Regular files under `entry`, keyed by their path below the bottle's
+/// `{name}/{version}/` prefix.
+fn versioned_files(entry: &Path) -> Result<HashMap<PathBuf, PathBuf>, Error> {
+    let mut files = HashMap::new();
+    if !entry.exists() {
+        return Ok(files);
+    }
+    for item in walkdir::WalkDir::new(entry).min_depth(3) {
+        let item = item.map_err(|e| Error::StoreCorruption {
+            message: format!("failed to walk store entry {}: {e}", entry.display()),
+        })?;
+        if !item.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative) = item.path().strip_prefix(entry) else {
+            continue;
+        };
+        let below_version: PathBuf = relative.components().skip(2).collect();
+        files.insert(below_version, item.into_path());
+    }
+    Ok(files)
+}
+
+/// SHA-256 of `path`, or `None` if the file contains placeholders.
+fn hash_dedupe_candidate(path: &Path) -> Result<Option<String>, Error> {
+    let mut file = File::open(path).map_err(Error::store("failed to open store file"))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    // Keep the tail of the previous chunk so markers split across reads match
+    let overlap = PLACEHOLDER_MARKER.len() - 1;
+    let mut window = Vec::with_capacity(buf.len() + overlap);
+
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(Error::store("failed to read store file"))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        window.extend_from_slice(&buf[..n]);
+        if window
+            .windows(PLACEHOLDER_MARKER.len())
+            .any(|w| w == PLACEHOLDER_MARKER)
+        {
+            return Ok(None);
+        }
+        let keep_from = window.len().saturating_sub(overlap);
+        window.drain(..keep_from);
+    }
+
+    Ok(Some(sha256_hex(hasher)))
+}
+
+fn replace_with_hardlink(target: &Path, path: &Path) -> Result<(), Error> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{file_name}.zb-dedupe"));
+    let _ = fs::remove_file(&tmp_path);
+    fs::hard_link(target, &tmp_path).map_err(Error::store("failed to create dedupe hardlink"))?;
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(Error::StoreCorruption {
+            message: format!("failed to replace {} with hardlink: {e}", path.display()),
+        });
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -228,6 +655,68 @@ mod tests {
         assert_eq!(content, "concurrent test");
     }
 
+    #[test]
+    fn staged_entry_appears_only_when_committed() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let staged = store.stage_entry("staged").unwrap().unwrap();
+        fs::write(staged.path().join("test.txt"), "staged").unwrap();
+        // Someone else is already unpacking this entry.
+        assert!(store.stage_entry("staged").unwrap().is_none());
+        drop(staged);
+        assert!(!store.has_entry("staged"));
+
+        let staged = store.stage_entry("staged").unwrap().unwrap();
+        fs::write(staged.path().join("test.txt"), "staged").unwrap();
+        let entry = staged.commit().unwrap();
+        assert_eq!(
+            fs::read_to_string(entry.join("test.txt")).unwrap(),
+            "staged"
+        );
+        assert!(store.stage_entry("staged").unwrap().is_none());
+    }
+
+    #[test]
+    fn stale_staging_is_removed_unless_still_locked() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let live = store.stage_entry("live").unwrap().unwrap();
+        let interrupted = store.stage_entry("gone").unwrap().unwrap().dir.keep();
+
+        assert_eq!(store.remove_stale_staging().unwrap(), 1);
+        assert!(!interrupted.exists());
+        assert!(live.path().exists());
+        assert!(!store.has_entry("gone"));
+    }
+
+    #[test]
+    fn verification_catches_edits_to_an_entry() {
+        let tmp = TempDir::new().unwrap();
+        let blob_path = tmp.path().join("blob.tar.gz");
+        fs::write(&blob_path, create_test_tarball(b"original")).unwrap();
+        let store = Store::new(tmp.path())
+            .unwrap()
+            .with_verification(StoreVerification::Full);
+
+        let entry = store.ensure_entry("verified", &blob_path).unwrap();
+        store.verify_entry("verified").unwrap();
+
+        fs::write(entry.join("test.txt"), b"tampered").unwrap();
+        let err = store.verify_entry("verified").unwrap_err();
+        assert!(matches!(err, Error::StoreCorruption { .. }));
+        assert!(err.to_string().contains("test.txt"), "{err}");
+
+        // Entries unpacked without verification get a manifest on first use.
+        let unverified = Store::new(tmp.path()).unwrap();
+        unverified.ensure_entry("legacy", &blob_path).unwrap();
+        store.verify_entry("legacy").unwrap();
+        fs::remove_file(store.entry_path("legacy").join("test.txt")).unwrap();
+        assert!(store.verify_entry("legacy").is_err());
+        assert!(unverified.verify_entry("legacy").is_ok());
+    }
+
     #[test]
     fn has_entry_returns_correct_state() {
         let tmp = TempDir::new().unwrap();
@@ -245,4 +734,96 @@ mod tests {
 
         assert!(store.has_entry(store_key));
     }
+
+    #[test]
+    fn dedupe_hardlinks_identical_files_across_entries() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        for key in ["one", "two"] {
+            let dir = store.entry_path(key).join("share");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("LICENSE"), b"same license text").unwrap();
+            fs::write(dir.join("unique"), key.as_bytes()).unwrap();
+        }
+
+        let summary = store.dedupe().unwrap();
+        assert_eq!(summary.files_scanned, 4);
+        assert_eq!(summary.files_linked, 1);
+        assert_eq!(summary.bytes_saved, b"same license text".len() as u64);
+
+        let a = fs::metadata(store.entry_path("one").join("share/LICENSE")).unwrap();
+        let b = fs::metadata(store.entry_path("two").join("share/LICENSE")).unwrap();
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(
+            fs::read(store.entry_path("two").join("share/LICENSE")).unwrap(),
+            b"same license text"
+        );
+
+        // Already-linked files are not counted again
+        assert_eq!(store.dedupe().unwrap().files_linked, 0);
+    }
+
+    #[test]
+    fn share_unchanged_files_links_across_versions() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let write = |key: &str, version: &str, file: &str, content: &[u8]| {
+            let path = store.entry_path(key).join("foo").join(version).join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write("old", "1.0", "share/doc.txt", b"unchanged docs");
+        write("old", "1.0", "bin/foo", b"old binary");
+        write("old", "1.0", "lib/foo.pc", b"prefix=@@HOMEBREW_PREFIX@@");
+        write("new", "2.0", "share/doc.txt", b"unchanged docs");
+        write("new", "2.0", "bin/foo", b"new binary");
+        write("new", "2.0", "lib/foo.pc", b"prefix=@@HOMEBREW_PREFIX@@");
+
+        let summary = store.share_unchanged_files("new", "old").unwrap();
+        assert_eq!(summary.files_scanned, 3);
+        assert_eq!(summary.files_linked, 1);
+        assert_eq!(summary.bytes_saved, b"unchanged docs".len() as u64);
+
+        let ino = |key: &str, version: &str, file: &str| {
+            fs::metadata(store.entry_path(key).join("foo").join(version).join(file))
+                .unwrap()
+                .ino()
+        };
+        assert_eq!(
+            ino("old", "1.0", "share/doc.txt"),
+            ino("new", "2.0", "share/doc.txt")
+        );
+        assert_ne!(ino("old", "1.0", "bin/foo"), ino("new", "2.0", "bin/foo"));
+        assert_ne!(
+            ino("old", "1.0", "lib/foo.pc"),
+            ino("new", "2.0", "lib/foo.pc")
+        );
+
+        // Removing the old entry leaves the shared file intact
+        store.remove_entry("old").unwrap();
+        assert_eq!(
+            fs::read(store.entry_path("new").join("foo/2.0/share/doc.txt")).unwrap(),
+            b"unchanged docs"
+        );
+    }
+
+    #[test]
+    fn dedupe_skips_files_with_placeholders() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        for key in ["one", "two"] {
+            let dir = store.entry_path(key);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("foo.pc"), b"prefix=@@HOMEBREW_PREFIX@@").unwrap();
+        }
+
+        let summary = store.dedupe().unwrap();
+        assert_eq!(summary.files_linked, 0);
+        let a = fs::metadata(store.entry_path("one").join("foo.pc")).unwrap();
+        let b = fs::metadata(store.entry_path("two").join("foo.pc")).unwrap();
+        assert_ne!(a.ino(), b.ino());
+    }
 }
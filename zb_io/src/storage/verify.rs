@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::checksum::sha256_hex;
+use zb_core::Error;
+
+/// Files re-hashed per entry by [`StoreVerification::Sampled`].
+const SAMPLED_FILES: usize = 32;
+
+/// How closely a store entry is checked against the manifest recorded when
+/// it was unpacked, before it is materialized into the cellar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StoreVerification {
+    /// Trust the entry as it is on disk.
+    #[default]
+    Off,
+    /// Check every file's presence, size and symlink target, and re-hash a
+    /// rotating sample of files.
+    Sampled,
+    /// Re-hash every file.
+    Full,
+}
+
+impl StoreVerification {
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        match s {
+            "off" => Ok(Self::Off),
+            "sampled" => Ok(Self::Sampled),
+            "full" => Ok(Self::Full),
+            other => Err(Error::InvalidArgument {
+                message: format!(
+                    "unknown store verification '{other}' (expected off, sampled or full)"
+                ),
+            }),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Sampled => "sampled",
+            Self::Full => "full",
+        }
+    }
+}
+
+/// What a store entry held when it was unpacked, keyed by path below it.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct EntryManifest {
+    files: BTreeMap<String, RecordedFile>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum RecordedFile {
+    File { size: u64, sha256: String },
+    Symlink { target: String },
+}
+
+impl EntryManifest {
+    /// Hash every file under `dir`.
+    pub(super) fn of(dir: &Path) -> Result<Self, Error> {
+        let mut files = BTreeMap::new();
+        for (relative, path) in walk(dir)? {
+            let metadata =
+                fs::symlink_metadata(&path).map_err(Error::store("failed to read store file"))?;
+            let recorded = if metadata.is_symlink() {
+                let target =
+                    fs::read_link(&path).map_err(Error::store("failed to read symlink"))?;
+                RecordedFile::Symlink {
+                    target: target.to_string_lossy().into_owned(),
+                }
+            } else {
+                RecordedFile::File {
+                    size: metadata.len(),
+                    sha256: hash_file(&path)?,
+                }
+            };
+            files.insert(relative, recorded);
+        }
+        Ok(Self { files })
+    }
+
+    pub(super) fn read(path: &Path) -> Result<Option<Self>, Error> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::StoreCorruption {
+                    message: format!("failed to read {}: {e}", path.display()),
+                });
+            }
+        };
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("store manifest {} is unreadable: {e}", path.display()),
+            })
+    }
+
+    pub(super) fn write(&self, path: &Path) -> Result<(), Error> {
+        let contents = serde_json::to_vec(self).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to serialize store manifest: {e}"),
+        })?;
+        fs::write(path, contents).map_err(Error::store("failed to write store manifest"))
+    }
+
+    /// Check `entry` against the manifest, failing with the first path
+    /// that was added, removed or changed.
+    pub(super) fn verify(&self, entry: &Path, mode: StoreVerification) -> Result<(), Error> {
+        if mode == StoreVerification::Off {
+            return Ok(());
+        }
+        let modified = |relative: &str, what: &str| Error::StoreCorruption {
+            message: format!(
+                "store entry was modified: {} {what}",
+                entry.join(relative).display()
+            ),
+        };
+
+        let on_disk = walk(entry)?;
+        if let Some((relative, _)) = on_disk
+            .iter()
+            .find(|(relative, _)| !self.files.contains_key(relative))
+        {
+            return Err(modified(relative, "was not in the bottle"));
+        }
+
+        let mut hashed = Vec::new();
+        for (relative, recorded) in &self.files {
+            let path = entry.join(relative);
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                return Err(modified(relative, "is missing"));
+            };
+            match recorded {
+                RecordedFile::Symlink { target } => {
+                    let actual = fs::read_link(&path).ok();
+                    if actual.is_none_or(|actual| actual.to_string_lossy() != *target) {
+                        return Err(modified(relative, "no longer points where it did"));
+                    }
+                }
+                RecordedFile::File { size, sha256 } => {
+                    if !metadata.is_file() || metadata.len() != *size {
+                        return Err(modified(relative, "changed size"));
+                    }
+                    hashed.push((relative, sha256));
+                }
+            }
+        }
+
+        if mode == StoreVerification::Sampled && hashed.len() > SAMPLED_FILES {
+            // Start somewhere different each time, so repeated installs end
+            // up covering the whole entry.
+            let stride = hashed.len() / SAMPLED_FILES;
+            let offset = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.subsec_nanos() as usize)
+                % stride;
+            hashed = hashed
+                .into_iter()
+                .skip(offset)
+                .step_by(stride)
+                .take(SAMPLED_FILES)
+                .collect();
+        }
+        for (relative, sha256) in hashed {
+            if hash_file(&entry.join(relative))? != *sha256 {
+                return Err(modified(relative, "does not match its recorded checksum"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every file and symlink under `dir`, as (path below `dir`, full path).
+fn walk(dir: &Path) -> Result<Vec<(String, std::path::PathBuf)>, Error> {
+    let mut files = Vec::new();
+    for item in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let item = item.map_err(|e| Error::StoreCorruption {
+            message: format!("failed to walk store entry {}: {e}", dir.display()),
+        })?;
+        if item.file_type().is_dir() {
+            continue;
+        }
+        let Ok(relative) = item.path().strip_prefix(dir) else {
+            continue;
+        };
+        files.push((relative.to_string_lossy().into_owned(), item.into_path()));
+    }
+    Ok(files)
+}
+
+fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut file = File::open(path).map_err(Error::store("failed to open store file"))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(Error::store("failed to read store file"))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(sha256_hex(hasher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(tmp: &TempDir) -> std::path::PathBuf {
+        let entry = tmp.path().join("abc123");
+        fs::create_dir_all(entry.join("foo/1.0/bin")).unwrap();
+        for i in 0..40 {
+            fs::write(entry.join(format!("foo/1.0/bin/tool{i}")), format!("{i}")).unwrap();
+        }
+        std::os::unix::fs::symlink("tool0", entry.join("foo/1.0/bin/tool")).unwrap();
+        entry
+    }
+
+    #[test]
+    fn verification_reports_the_modified_path() {
+        let tmp = TempDir::new().unwrap();
+        let entry = entry(&tmp);
+        let manifest = EntryManifest::of(&entry).unwrap();
+        let path = tmp.path().join("abc123.manifest.json");
+        manifest.write(&path).unwrap();
+        let manifest = EntryManifest::read(&path).unwrap().unwrap();
+        for mode in [
+            StoreVerification::Off,
+            StoreVerification::Sampled,
+            StoreVerification::Full,
+        ] {
+            manifest.verify(&entry, mode).unwrap();
+        }
+
+        // Same size, different content: only a full check re-hashes it all.
+        fs::write(entry.join("foo/1.0/bin/tool7"), "x").unwrap();
+        let err = manifest
+            .verify(&entry, StoreVerification::Full)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("foo/1.0/bin/tool7"), "{err}");
+        assert!(err.contains("checksum"), "{err}");
+
+        fs::write(entry.join("foo/1.0/bin/tool8"), "longer").unwrap();
+        let err = manifest
+            .verify(&entry, StoreVerification::Sampled)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("foo/1.0/bin/tool8"), "{err}");
+
+        fs::write(entry.join("foo/1.0/bin/extra"), "").unwrap();
+        assert!(manifest.verify(&entry, StoreVerification::Sampled).is_err());
+        assert!(manifest.verify(&entry, StoreVerification::Off).is_ok());
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        for mode in [
+            StoreVerification::Off,
+            StoreVerification::Sampled,
+            StoreVerification::Full,
+        ] {
+            assert_eq!(StoreVerification::parse(mode.as_str()).unwrap(), mode);
+        }
+        assert!(StoreVerification::parse("some").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use zb_core::Error;
+
+/// Something an install did that matters when reproducing it elsewhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TranscriptEvent {
+    /// An API request that went out over the network.
+    ApiRequest { url: String },
+    /// The bottle chosen for a formula.
+    Bottle {
+        formula: String,
+        url: String,
+        sha256: String,
+    },
+    /// Whether a keg's Homebrew placeholders were rewritten for this prefix.
+    Relocation {
+        formula: String,
+        keg: PathBuf,
+        relocated: bool,
+    },
+    /// A symlink created in the prefix.
+    Link {
+        formula: String,
+        link: PathBuf,
+        target: PathBuf,
+    },
+    /// A formula that finished installing.
+    Installed { formula: String, version: String },
+}
+
+/// Where an install records its events: each is appended as one JSON line
+/// as it happens, so the transcript survives an install that dies midway.
+pub struct Transcript {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Transcript {
+    /// Record into a new file at `path`, replacing any existing one.
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let file = File::create(path).map_err(|e| Error::FileError {
+            message: format!("failed to create transcript {}: {e}", path.display()),
+        })?;
+        Ok(Self::to_writer(file))
+    }
+
+    pub fn to_writer(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Mutex::new(Box::new(out)),
+        }
+    }
+
+    /// Best effort: a transcript that cannot be written never fails the
+    /// install it describes.
+    pub fn record(&self, event: TranscriptEvent) {
+        let Ok(line) = serde_json::to_string(&event) else {
+            return;
+        };
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(out, "{line}").and_then(|()| out.flush()) {
+            warn!(error = %e, "failed to write install transcript");
+        }
+    }
+}
+
+impl fmt::Debug for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transcript").finish_non_exhaustive()
+    }
+}
+
+/// Read a transcript written by [`Transcript`]. Blank lines are skipped.
+pub fn read_transcript(path: &Path) -> Result<Vec<TranscriptEvent>, Error> {
+    let read_error = |e: std::io::Error| Error::FileError {
+        message: format!("failed to read transcript {}: {e}", path.display()),
+    };
+    let file = File::open(path).map_err(read_error)?;
+    let mut events = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(read_error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line).map_err(|e| Error::InvalidArgument {
+            message: format!(
+                "{} line {} is not a transcript event: {e}",
+                path.display(),
+                number + 1
+            ),
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_round_trip_through_a_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("install.jsonl");
+        let events = [
+            TranscriptEvent::ApiRequest {
+                url: "https://formulae.brew.sh/api/formula/jq.json".into(),
+            },
+            TranscriptEvent::Relocation {
+                formula: "jq".into(),
+                keg: "/opt/zerobrew/prefix/Cellar/jq/1.7.1".into(),
+                relocated: true,
+            },
+        ];
+
+        let transcript = Transcript::create(&path).unwrap();
+        for event in &events {
+            transcript.record(event.clone());
+        }
+        drop(transcript);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(r#"{"event":"api_request","#), "{text}");
+        assert_eq!(read_transcript(&path).unwrap(), events);
+
+        std::fs::write(&path, "{\"event\":\"unknown\"}\n").unwrap();
+        let err = read_transcript(&path).unwrap_err();
+        assert!(err.to_string().contains("line 1"), "{err}");
+    }
+}
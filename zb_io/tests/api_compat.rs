@@ -0,0 +1,87 @@
+//! Compatibility tests that parse recorded Homebrew API responses, so a
+//! schema change shows up as a failing fixture rather than a failed install.
+//!
+//! `fixtures/api/formula` holds one `<name>.json` per formula. Refresh the
+//! recordings of the most installed formulas with
+//! `ZEROBREW_RECORD_FIXTURES=1 cargo test -p zb_io --test api_compat -- --ignored`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use zb_core::{Formula, Platform};
+
+const RECORDED_FORMULAS: usize = 100;
+
+fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/api/formula")
+}
+
+fn recorded_fixtures() -> Vec<(String, String)> {
+    let mut fixtures: Vec<(String, String)> = fs::read_dir(fixtures_dir())
+        .expect("missing API fixtures directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, fs::read_to_string(&path).unwrap())
+        })
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+#[test]
+fn recorded_formulas_parse() {
+    let fixtures = recorded_fixtures();
+    assert!(!fixtures.is_empty(), "no recorded API fixtures");
+
+    for (name, raw) in fixtures {
+        let formula = Formula::from_json(&raw)
+            .unwrap_or_else(|e| panic!("recorded formula {name} no longer parses: {e}"));
+        assert_eq!(formula.name, name);
+        assert!(
+            !formula.versions.stable.is_empty(),
+            "{name} has no stable version"
+        );
+        for platform in [Platform::Macos, Platform::Linux { arch: "x86_64" }] {
+            let _ = formula.runtime_dependencies_for(platform);
+            let _ = formula.build_dependencies_for(platform);
+        }
+        for (tag, file) in &formula.bottle.stable.files {
+            assert!(!file.url.is_empty(), "{name} bottle {tag} has no url");
+            assert_eq!(file.sha256.len(), 64, "{name} bottle {tag} sha256");
+        }
+    }
+}
+
+/// Re-record the most installed formulas. Writes into the source tree, so
+/// it only runs with `ZEROBREW_RECORD_FIXTURES=1`.
+#[tokio::test(flavor = "current_thread")]
+#[ignore = "network: records Homebrew API fixtures"]
+async fn record_most_installed_formulas() {
+    use zb_io::{AnalyticsPeriod, ApiClient};
+
+    if std::env::var_os("ZEROBREW_RECORD_FIXTURES").is_none() {
+        eprintln!("set ZEROBREW_RECORD_FIXTURES=1 to record fixtures");
+        return;
+    }
+
+    let client = ApiClient::new();
+    let counts = client
+        .get_install_counts(AnalyticsPeriod::Days30)
+        .await
+        .expect("failed to fetch install analytics");
+    for count in counts.iter().take(RECORDED_FORMULAS) {
+        let raw = client
+            .get_formula_raw(&count.formula)
+            .await
+            .unwrap_or_else(|e| panic!("failed to fetch {}: {e}", count.formula));
+        let json: serde_json::Value = serde_json::from_str(&raw).unwrap();
+        let name = json["name"].as_str().unwrap_or(&count.formula);
+        fs::write(
+            fixtures_dir().join(format!("{name}.json")),
+            serde_json::to_string_pretty(&json).unwrap() + "\n",
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+//! Cold-cache downloads against a mock registry with simulated network
+//! conditions: per-request latency, a bandwidth cap and a deterministic share
+//! of failing requests. They run offline and always behave the same, so
+//! changes to retries, resumption or concurrency can be compared without
+//! hitting ghcr.io.
+//!
+//! The defaults keep the tests fast. Set `ZB_SIM_LATENCY_MS`, `ZB_SIM_MBPS`
+//! and `ZB_SIM_ERROR_RATE` to shape the network, and run with
+//! `cargo test -p zb_io --test network_simulation -- --nocapture` to see
+//! the timings.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+use zb_io::{BlobCache, DownloadRequest, ParallelDownloader, RetryPolicy};
+
+const BOTTLES: usize = 8;
+const BOTTLE_SIZE: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct NetworkProfile {
+    latency: Duration,
+    /// Bandwidth cap per response; `None` is unlimited.
+    bytes_per_sec: Option<u64>,
+    /// Share of requests answered with a 503, from 0.0 to 1.0.
+    error_rate: f64,
+}
+
+impl NetworkProfile {
+    /// `self`, with any of its settings overridden from the environment.
+    fn or_env(self) -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok()?.parse().ok()
+        }
+        Self {
+            latency: var("ZB_SIM_LATENCY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(self.latency),
+            bytes_per_sec: var::<f64>("ZB_SIM_MBPS")
+                .map(|mbps| (mbps * 1_000_000.0) as u64)
+                .or(self.bytes_per_sec),
+            error_rate: var("ZB_SIM_ERROR_RATE").unwrap_or(self.error_rate),
+        }
+    }
+
+    /// How long a response of `len` bytes takes to arrive in full. wiremock
+    /// sends bodies in one piece, so the transfer time is added up front.
+    fn delay(&self, len: usize) -> Duration {
+        let transfer = self.bytes_per_sec.map_or(Duration::ZERO, |rate| {
+            Duration::from_secs_f64(len as f64 / rate as f64)
+        });
+        self.latency + transfer
+    }
+
+    /// Whether the `n`th request fails: spread evenly rather than at
+    /// random, so every run sees the same failures.
+    fn fails(&self, n: usize) -> bool {
+        ((n + 1) as f64 * self.error_rate).floor() > (n as f64 * self.error_rate).floor()
+    }
+}
+
+struct ShapedResponder {
+    body: Vec<u8>,
+    profile: NetworkProfile,
+    requests: Arc<AtomicUsize>,
+}
+
+impl Respond for ShapedResponder {
+    fn respond(&self, _request: &Request) -> ResponseTemplate {
+        let n = self.requests.fetch_add(1, Ordering::SeqCst);
+        if self.profile.fails(n) {
+            return ResponseTemplate::new(503).set_delay(self.profile.latency);
+        }
+        ResponseTemplate::new(200)
+            .set_body_bytes(self.body.clone())
+            .set_delay(self.profile.delay(self.body.len()))
+    }
+}
+
+struct Registry {
+    /// Kept alive for as long as the bottles are downloaded.
+    _server: MockServer,
+    requests: Arc<AtomicUsize>,
+    /// `(url, sha256)` of each bottle.
+    bottles: Vec<(String, String)>,
+}
+
+/// A registry serving `BOTTLES` distinct bottles through `profile`.
+async fn registry(profile: NetworkProfile) -> Registry {
+    let server = MockServer::start().await;
+    let requests = Arc::new(AtomicUsize::new(0));
+    let mut bottles = Vec::new();
+    for i in 0..BOTTLES {
+        let body: Vec<u8> = (0..BOTTLE_SIZE).map(|b| (b + i) as u8).collect();
+        let sha256 = Sha256::digest(&body)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        let blob_path = format!("/v2/bottles/blobs/{i}");
+        Mock::given(method("GET"))
+            .and(path(blob_path.as_str()))
+            .respond_with(ShapedResponder {
+                body,
+                profile,
+                requests: requests.clone(),
+            })
+            .mount(&server)
+            .await;
+        bottles.push((format!("{}{blob_path}", server.uri()), sha256));
+    }
+    Registry {
+        _server: server,
+        requests,
+        bottles,
+    }
+}
+
+fn requests(registry: &Registry) -> Vec<DownloadRequest> {
+    registry
+        .bottles
+        .iter()
+        .enumerate()
+        .map(|(i, (url, sha256))| DownloadRequest {
+            url: url.clone(),
+            sha256: sha256.clone(),
+            name: format!("bottle{i}"),
+        })
+        .collect()
+}
+
+fn fast_retries(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+        ..RetryPolicy::default().with_max_attempts(max_attempts)
+    }
+}
+
+#[tokio::test]
+async fn cold_cache_downloads_respect_latency_and_bandwidth() {
+    let profile = NetworkProfile {
+        latency: Duration::from_millis(50),
+        bytes_per_sec: Some(16_000_000),
+        error_rate: 0.0,
+    }
+    .or_env();
+    let registry = registry(profile).await;
+    let tmp = TempDir::new().unwrap();
+    let downloader = ParallelDownloader::with_concurrency(BlobCache::new(tmp.path()).unwrap(), 4)
+        .with_retry_policy(fast_retries(5));
+
+    let started = Instant::now();
+    let blobs = downloader.download_all(requests(&registry)).await.unwrap();
+    let elapsed = started.elapsed();
+
+    assert_eq!(blobs.len(), BOTTLES);
+    assert!(blobs.iter().all(|blob| blob.exists()));
+    assert!(elapsed >= profile.delay(BOTTLE_SIZE));
+    eprintln!(
+        "{BOTTLES} x {BOTTLE_SIZE} bytes over {profile:?}: {:.3}s, {} requests",
+        elapsed.as_secs_f64(),
+        registry.requests.load(Ordering::SeqCst)
+    );
+}
+
+#[tokio::test]
+async fn retries_absorb_a_failing_share_of_requests() {
+    let profile = NetworkProfile {
+        latency: Duration::from_millis(5),
+        bytes_per_sec: None,
+        error_rate: 0.25,
+    }
+    .or_env();
+    let registry = registry(profile).await;
+    let tmp = TempDir::new().unwrap();
+    let downloader = ParallelDownloader::with_concurrency(BlobCache::new(tmp.path()).unwrap(), 4)
+        .with_retry_policy(fast_retries(5));
+
+    let started = Instant::now();
+    let blobs = downloader.download_all(requests(&registry)).await.unwrap();
+    let sent = registry.requests.load(Ordering::SeqCst);
+
+    assert_eq!(blobs.len(), BOTTLES);
+    if profile.error_rate > 0.0 {
+        assert!(sent > BOTTLES, "expected retried requests, saw {sent}");
+    }
+    eprintln!(
+        "{BOTTLES} bottles over {profile:?}: {:.3}s, {sent} requests",
+        started.elapsed().as_secs_f64()
+    );
+}
+
+#[test]
+fn failures_are_spread_evenly() {
+    let profile = NetworkProfile {
+        latency: Duration::ZERO,
+        bytes_per_sec: None,
+        error_rate: 0.25,
+    };
+    let failures: Vec<usize> = (0..12).filter(|&n| profile.fails(n)).collect();
+    assert_eq!(failures, [3, 7, 11]);
+    assert_eq!(
+        profile.delay(1000).as_millis(),
+        0,
+        "unlimited bandwidth adds no delay"
+    );
+}